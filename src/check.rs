@@ -0,0 +1,486 @@
+//! One-shot diagnostics for CI (`lsmcp check`)
+//!
+//! Opens each file on its servers, waits for the diagnostics they publish
+//! for it, and reports them as text, JSON, SARIF or GitHub workflow
+//! annotations.
+//! The command exits non-zero when any diagnostic is at least as severe as
+//! `--fail-on`, so lsmcp can gate a pipeline without an MCP client.
+
+use crate::lsp::prepare::PREPARE_BATCH;
+use crate::lsp::problems::SeverityCounts;
+use crate::mcp::tools::code_string;
+use crate::tools::files::find_files;
+use crate::utils::position::PositionEncoding;
+use crate::utils::sarif;
+use crate::LspManager;
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Columns count characters, as editors and annotations do
+pub const CHECK_ENCODING: PositionEncoding = PositionEncoding::Utf32;
+
+/// How `lsmcp check` prints its findings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckFormat {
+    /// `path:line:column: severity[source code]: message`
+    Text,
+    /// One JSON document
+    Json,
+    /// A SARIF 2.1.0 log, for code scanning
+    Sarif,
+    /// GitHub Actions workflow commands (`::error file=...::message`)
+    Github,
+}
+
+impl FromStr for CheckFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            "github" => Ok(Self::Github),
+            _ => Err(format!(
+                "unknown format '{}' (text, json, sarif, github)",
+                s
+            )),
+        }
+    }
+}
+
+/// The least severe diagnostic that fails a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailOn {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    /// Report, but never fail
+    Never,
+}
+
+impl FailOn {
+    /// Whether diagnostics counted as `counts` fail the check
+    pub fn fails(self, counts: &SeverityCounts) -> bool {
+        match self {
+            Self::Error => counts.error > 0,
+            Self::Warning => counts.error + counts.warning > 0,
+            Self::Information => counts.error + counts.warning + counts.information > 0,
+            Self::Hint => counts.total() > 0,
+            Self::Never => false,
+        }
+    }
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warning" => Ok(Self::Warning),
+            "info" | "information" => Ok(Self::Information),
+            "hint" => Ok(Self::Hint),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown severity '{}' (error, warning, info, hint, never)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FailOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Information => "info",
+            Self::Hint => "hint",
+            Self::Never => "never",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One file's diagnostics
+#[derive(Debug, Clone)]
+pub struct CheckedFile {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// What a check found
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Paths are shown relative to this
+    pub root: PathBuf,
+    /// Every file diagnosed, with or without diagnostics
+    pub files: Vec<CheckedFile>,
+    /// Files no configured server handles
+    pub unsupported: usize,
+    /// Files that couldn't be diagnosed, with why
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// The files to check: each of `paths` that is a file, the files under each
+/// that is a directory, and the workspace files matching the others as
+/// `lsp_find_files` globs; the whole workspace when `paths` is empty
+pub fn files_to_check(root: &Path, paths: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if paths.is_empty() {
+        files = find_files(root, "*", usize::MAX).files;
+    }
+    for path in paths {
+        let as_path = std::path::absolute(path).unwrap_or_else(|_| PathBuf::from(path));
+        if as_path.is_file() {
+            files.push(as_path);
+        } else if as_path.is_dir() {
+            files.extend(find_files(&as_path, "*", usize::MAX).files);
+        } else {
+            files.extend(find_files(root, path, usize::MAX).files);
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Diagnose `files`, waiting up to `wait` for each file's servers to
+/// publish their diagnostics for it
+pub async fn check(manager: &LspManager, files: Vec<PathBuf>, wait: Duration) -> CheckReport {
+    let mut report = CheckReport {
+        root: manager.workspace_root().to_path_buf(),
+        ..CheckReport::default()
+    };
+
+    let mut supported = Vec::new();
+    for file in files {
+        if manager.file_language(&file).is_none() {
+            report.unsupported += 1;
+        } else if let Err(e) = manager.check_path(&file) {
+            report.failed.push((file, e.to_string()));
+        } else {
+            supported.push(file);
+        }
+    }
+
+    for batch in supported.chunks(PREPARE_BATCH) {
+        let results = futures::future::join_all(
+            batch
+                .iter()
+                .map(|file| manager.published_diagnostics(file, wait, CHECK_ENCODING)),
+        )
+        .await;
+        for (file, result) in batch.iter().zip(results) {
+            match result {
+                Ok(diagnostics) => report.files.push(CheckedFile {
+                    path: file.clone(),
+                    diagnostics,
+                }),
+                Err(e) => report.failed.push((file.clone(), e.to_string())),
+            }
+        }
+    }
+    report
+}
+
+impl CheckReport {
+    /// Diagnostics by severity across every file
+    pub fn counts(&self) -> SeverityCounts {
+        let mut counts = SeverityCounts::default();
+        for diagnostic in self.diagnostics().map(|(_, d)| d) {
+            counts.add(diagnostic.severity);
+        }
+        counts
+    }
+
+    /// Every diagnostic with its file, in file order
+    fn diagnostics(&self) -> impl Iterator<Item = (&CheckedFile, &Diagnostic)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.diagnostics.iter().map(move |d| (file, d)))
+    }
+
+    /// `path` relative to the root, as it is shown
+    fn display_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// `Checked 12 file(s): 2 error(s), 1 warning(s)`, and what was skipped
+    /// or failed
+    pub fn summary(&self) -> String {
+        let counts = self.counts();
+        let found: Vec<String> = [
+            (counts.error, "error"),
+            (counts.warning, "warning"),
+            (counts.information, "info"),
+            (counts.hint, "hint"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}(s)", count, label))
+        .collect();
+        let mut text = format!(
+            "Checked {} file(s): {}",
+            self.files.len(),
+            if found.is_empty() {
+                "no problems".to_string()
+            } else {
+                found.join(", ")
+            }
+        );
+        if self.unsupported > 0 {
+            text.push_str(&format!(
+                "\nSkipped {} file(s) no language server handles",
+                self.unsupported
+            ));
+        }
+        for (path, error) in &self.failed {
+            text.push_str(&format!(
+                "\nFailed to check {}: {}",
+                self.display_path(path),
+                error
+            ));
+        }
+        text
+    }
+
+    /// The findings in `format`; text ends with the summary
+    pub fn render(&self, format: CheckFormat) -> String {
+        match format {
+            CheckFormat::Text => self.render_text(),
+            CheckFormat::Json => {
+                serde_json::to_string_pretty(&self.to_json()).expect("report serializes to JSON")
+            }
+            CheckFormat::Sarif => {
+                let files = self
+                    .files
+                    .iter()
+                    .map(|file| (file.path.as_path(), file.diagnostics.as_slice()));
+                let log = sarif::log(&self.root, files, CHECK_ENCODING);
+                serde_json::to_string_pretty(&log).expect("SARIF log serializes to JSON")
+            }
+            CheckFormat::Github => self.render_github(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut text = String::new();
+        for (file, diagnostic) in self.diagnostics() {
+            let label = match (&diagnostic.source, &diagnostic.code) {
+                (Some(source), Some(code)) => format!("[{} {}]", source, code_string(code)),
+                (Some(source), None) => format!("[{}]", source),
+                (None, Some(code)) => format!("[{}]", code_string(code)),
+                (None, None) => String::new(),
+            };
+            text.push_str(&format!(
+                "{}:{}:{}: {}{}: {}\n",
+                self.display_path(&file.path),
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                severity_name(diagnostic.severity),
+                label,
+                diagnostic.message.replace('\n', "\n  ")
+            ));
+        }
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&self.summary());
+        text.push('\n');
+        text
+    }
+
+    fn to_json(&self) -> Value {
+        let diagnostics: Vec<Value> = self
+            .diagnostics()
+            .map(|(file, diagnostic)| {
+                json!({
+                    "path": self.display_path(&file.path),
+                    "severity": severity_name(diagnostic.severity),
+                    "line": diagnostic.range.start.line + 1,
+                    "column": diagnostic.range.start.character + 1,
+                    "end_line": diagnostic.range.end.line + 1,
+                    "end_column": diagnostic.range.end.character + 1,
+                    "source": diagnostic.source,
+                    "code": diagnostic.code.as_ref().map(code_string),
+                    "message": diagnostic.message,
+                })
+            })
+            .collect();
+        let failed: Vec<Value> = self
+            .failed
+            .iter()
+            .map(|(path, error)| json!({"path": self.display_path(path), "error": error}))
+            .collect();
+        json!({
+            "root": self.root,
+            "files_checked": self.files.len(),
+            "counts": self.counts(),
+            "diagnostics": diagnostics,
+            "unsupported_files": self.unsupported,
+            "failed": failed,
+        })
+    }
+
+    fn render_github(&self) -> String {
+        let mut text = String::new();
+        for (file, diagnostic) in self.diagnostics() {
+            let command = match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => "error",
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                _ => "notice",
+            };
+            let title: Vec<String> = diagnostic
+                .source
+                .iter()
+                .cloned()
+                .chain(diagnostic.code.as_ref().map(code_string))
+                .collect();
+            let start = diagnostic.range.start;
+            let end = diagnostic.range.end;
+            let mut properties = vec![
+                format!("file={}", escape_property(&self.display_path(&file.path))),
+                format!("line={}", start.line + 1),
+                format!("col={}", start.character + 1),
+                format!("endLine={}", end.line + 1),
+            ];
+            // Annotations take an end column only on the start line
+            if end.line == start.line {
+                properties.push(format!("endColumn={}", end.character + 1));
+            }
+            if !title.is_empty() {
+                properties.push(format!("title={}", escape_property(&title.join(" "))));
+            }
+            text.push_str(&format!(
+                "::{} {}::{}\n",
+                command,
+                properties.join(","),
+                escape_data(&diagnostic.message)
+            ));
+        }
+        text
+    }
+}
+
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        None | Some(_) => "unknown",
+    }
+}
+
+/// A workflow command's message, with the characters GitHub reads escaped
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// A workflow command's property value
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{NumberOrString, Position, Range};
+
+    fn diagnostic(severity: DiagnosticSeverity, line: u32, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 4), Position::new(line, 9)),
+            severity: Some(severity),
+            code: Some(NumberOrString::String("E0308".to_string())),
+            source: Some("rustc".to_string()),
+            message: message.to_string(),
+            ..Diagnostic::default()
+        }
+    }
+
+    #[test]
+    fn test_check_report() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("src")).unwrap();
+        for name in ["src/main.rs", "src/lib.rs", "README.md"] {
+            std::fs::write(root.path().join(name), "").unwrap();
+        }
+        let files = files_to_check(root.path(), &["src/*.rs".to_string()]);
+        assert_eq!(
+            files,
+            [
+                root.path().join("src/lib.rs"),
+                root.path().join("src/main.rs")
+            ]
+        );
+        assert_eq!(files_to_check(root.path(), &[]).len(), 3);
+        let dir = root.path().join("src").to_string_lossy().into_owned();
+        assert_eq!(files_to_check(root.path(), &[dir]).len(), 2);
+
+        let report = CheckReport {
+            root: root.path().to_path_buf(),
+            files: vec![
+                CheckedFile {
+                    path: root.path().join("src/lib.rs"),
+                    diagnostics: vec![],
+                },
+                CheckedFile {
+                    path: root.path().join("src/main.rs"),
+                    diagnostics: vec![
+                        diagnostic(
+                            DiagnosticSeverity::ERROR,
+                            2,
+                            "mismatched types\nexpected `u32`",
+                        ),
+                        diagnostic(DiagnosticSeverity::WARNING, 7, "unused: x, 100%"),
+                    ],
+                },
+            ],
+            unsupported: 1,
+            failed: vec![],
+        };
+
+        let text = report.render(CheckFormat::Text);
+        assert!(text.starts_with(
+            "src/main.rs:3:5: error[rustc E0308]: mismatched types\n  expected `u32`\n"
+        ));
+        assert!(text.contains("Checked 2 file(s): 1 error(s), 1 warning(s)"));
+        assert!(text.contains("Skipped 1 file(s)"));
+
+        let json: Value = serde_json::from_str(&report.render(CheckFormat::Json)).unwrap();
+        assert_eq!(json["counts"]["error"], 1);
+        assert_eq!(json["diagnostics"][1]["line"], 8);
+        assert_eq!(json["diagnostics"][1]["code"], "E0308");
+
+        let github = report.render(CheckFormat::Github);
+        assert_eq!(
+            github.lines().nth(1),
+            Some("::warning file=src/main.rs,line=8,col=5,endLine=8,endColumn=10,title=rustc E0308::unused: x, 100%25")
+        );
+        assert!(github.contains("mismatched types%0Aexpected"));
+
+        let sarif: Value = serde_json::from_str(&report.render(CheckFormat::Sarif)).unwrap();
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["level"], "warning");
+
+        let counts = report.counts();
+        assert!(FailOn::Error.fails(&counts));
+        assert!(!FailOn::Never.fails(&counts));
+        assert!(!FailOn::Error.fails(&CheckReport::default().counts()));
+        assert_eq!("info".parse::<FailOn>(), Ok(FailOn::Information));
+        assert!("loud".parse::<CheckFormat>().is_err());
+    }
+}
@@ -0,0 +1,640 @@
+//! CLI subcommands for managing language servers outside an MCP session
+
+use anyhow::{bail, Result};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use lsmcp::check::{self, CheckFormat, FailOn};
+use lsmcp::config::neovim::{self, NeovimSources};
+use lsmcp::config::validate::{self, Severity};
+use lsmcp::config::{InstallSource, UserConfig};
+use lsmcp::installer::discovery::SearchRoots;
+use lsmcp::installer::{InstallEvent, InstallOptions};
+use lsmcp::lsp::{orphans, warm_cache};
+use lsmcp::mcp::tools::get_tool_definitions;
+use lsmcp::tools::edits::{self, EditJournal};
+use lsmcp::usage;
+use lsmcp::utils::git;
+use lsmcp::{ConfigLoader, LspManager, ServerInstaller};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Installer using the network and cache settings from the config
+fn installer(config: &ConfigLoader) -> Result<ServerInstaller> {
+    Ok(ServerInstaller::new()?.with_options(InstallOptions::from_settings(&config.settings())))
+}
+
+/// Short description of where a package is installed from
+fn source_label(source: &InstallSource) -> String {
+    match source {
+        InstallSource::Npm { package, .. } => format!("npm:{}", package),
+        InstallSource::Cargo { crate_name, .. } => format!("cargo:{}", crate_name),
+        InstallSource::Pip { package, .. } => format!("pip:{}", package),
+        InstallSource::Go { package, .. } => format!("go:{}", package),
+        InstallSource::GithubRelease { repo, .. } => format!("github:{}", repo),
+        InstallSource::System { .. } => "system".to_string(),
+        InstallSource::External { command } => format!("external:{}", command),
+    }
+}
+
+/// `lsmcp install <server>`
+pub async fn install(config: &ConfigLoader, server: &str) -> Result<()> {
+    let package = config.find_lsp(server)?;
+    let mut installer = installer(config)?;
+
+    if let Some(path) = installer.find_lsp_binary(&package.name, &package.bin.primary) {
+        println!(
+            "{} is already installed at {}",
+            package.name,
+            path.display()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Installing {} ({})...",
+        package.name,
+        source_label(&package.source)
+    );
+    let path = installer.install_lsp(&package).await?;
+    println!("Installed {} to {}", package.name, path.display());
+
+    Ok(())
+}
+
+/// `lsmcp install --all-detected`
+///
+/// Installs servers for every language with files in the workspace, several
+/// at a time, with a progress line per server.
+pub async fn install_detected(config: &ConfigLoader, workspace_root: &Path) -> Result<()> {
+    let packages = config.detect_lsps(workspace_root);
+    if packages.is_empty() {
+        println!(
+            "No languages with a known language server found in {}",
+            workspace_root.display()
+        );
+        return Ok(());
+    }
+
+    let names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+    println!("Detected servers: {}", names.join(", "));
+
+    let mut installer = installer(config)?;
+    let progress = MultiProgress::new();
+    let bars: Mutex<HashMap<String, ProgressBar>> = Mutex::default();
+    let running = ProgressStyle::with_template("{spinner} {prefix:.bold} {msg}")?;
+    let done = ProgressStyle::with_template("{prefix:.bold} {msg}")?;
+
+    let on_event = |event: InstallEvent| {
+        let Ok(mut bars) = bars.lock() else {
+            return;
+        };
+        match event {
+            InstallEvent::Started { name } => {
+                let bar = progress.add(ProgressBar::new_spinner());
+                bar.set_style(running.clone());
+                bar.set_prefix(name.clone());
+                bar.set_message("installing...");
+                bar.enable_steady_tick(Duration::from_millis(100));
+                bars.insert(name, bar);
+            }
+            InstallEvent::Finished { name, binary_path } => {
+                if let Some(bar) = bars.remove(&name) {
+                    bar.set_style(done.clone());
+                    bar.finish_with_message(format!("installed ({})", binary_path.display()));
+                }
+            }
+            InstallEvent::Failed { name, error } => {
+                if let Some(bar) = bars.remove(&name) {
+                    bar.set_style(done.clone());
+                    let first_line = error.lines().next().unwrap_or_default();
+                    bar.finish_with_message(format!("failed: {}", first_line));
+                }
+            }
+        }
+    };
+
+    let summary = installer.install_missing(&packages, &on_event).await;
+
+    for name in &summary.present {
+        println!("{} already installed", name);
+    }
+    for name in &summary.manual {
+        println!("{} must be installed manually", name);
+    }
+
+    let failed = summary.failed();
+    if failed > 0 {
+        for (name, result) in &summary.results {
+            if let Err(e) = result {
+                eprintln!("\n{}: {}", name, e);
+            }
+        }
+        bail!("{} server(s) failed to install", failed);
+    }
+
+    Ok(())
+}
+
+/// `lsmcp uninstall <server>`
+pub fn uninstall(config: &ConfigLoader, server: &str) -> Result<()> {
+    let name = config
+        .find_lsp(server)
+        .map(|p| p.name)
+        .unwrap_or_else(|_| server.to_string());
+    let mut installer = installer(config)?;
+
+    if installer.uninstall(&name)? {
+        println!("Uninstalled {}", name);
+        Ok(())
+    } else {
+        bail!("{} is not installed by lsmcp", name)
+    }
+}
+
+/// `lsmcp list [--installed] [--available]`
+pub fn list(config: &ConfigLoader, installed: bool, available: bool) -> Result<()> {
+    // Show both sections when no filter is given
+    let (installed, available) = if installed || available {
+        (installed, available)
+    } else {
+        (true, true)
+    };
+
+    let installer = installer(config)?;
+
+    if installed {
+        let mut servers = installer.list_installed();
+        servers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("Installed by lsmcp ({}):", servers.len());
+        for server in servers {
+            println!(
+                "  {:<32} {:<12} {}",
+                server.name,
+                server.version.as_deref().unwrap_or("-"),
+                server.binary_path.display()
+            );
+        }
+        println!();
+    }
+
+    if available {
+        let mut packages = config.list_available_lsps();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        println!("Available ({}):", packages.len());
+        for package in packages {
+            let status = match installer.find_lsp_binary(&package.name, &package.bin.primary) {
+                Some(_) => "found",
+                None => "missing",
+            };
+            println!(
+                "  {:<32} {:<8} {:<40} {}",
+                package.name,
+                status,
+                source_label(&package.source),
+                package.languages.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// `lsmcp update [server] [--check]`
+///
+/// Upgrades the given server, or every server installed by lsmcp that has a
+/// newer (or differently pinned) version available. With `check`, only
+/// reports what would be updated.
+pub async fn update(config: &ConfigLoader, server: Option<&str>, check: bool) -> Result<()> {
+    let mut installer = installer(config)?;
+    let server = server
+        .map(|server| config.find_lsp(server).map(|p| p.name))
+        .transpose()?;
+
+    let checks: Vec<_> = installer
+        .check_updates()
+        .await
+        .into_iter()
+        .filter(|c| server.as_ref().is_none_or(|name| &c.name == name))
+        .collect();
+
+    if check {
+        if checks.is_empty() {
+            println!("No servers installed by lsmcp");
+        }
+        for c in &checks {
+            let status = match &c.error {
+                Some(e) => format!("check failed: {}", e),
+                None if c.has_update() => "update available".to_string(),
+                None => "up to date".to_string(),
+            };
+            println!(
+                "  {:<32} {:<12} -> {:<12} {}{}",
+                c.name,
+                c.current.as_deref().unwrap_or("-"),
+                c.latest.as_deref().unwrap_or("-"),
+                status,
+                if c.pinned { " (pinned)" } else { "" }
+            );
+        }
+        return Ok(());
+    }
+
+    // An explicitly named server is always reinstalled, even if not yet installed
+    let names: Vec<String> = match server {
+        Some(name) => vec![name],
+        None => checks
+            .iter()
+            .filter(|c| c.error.is_some() || c.has_update())
+            .map(|c| c.name.clone())
+            .collect(),
+    };
+
+    if names.is_empty() {
+        println!("All servers installed by lsmcp are up to date");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for name in names {
+        let package = match config.get_lsp_by_name(&name) {
+            Ok(package) => package,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        println!("Updating {}...", package.name);
+        match installer.update_lsp(&package).await {
+            Ok(path) => println!("Updated {} ({})", package.name, path.display()),
+            Err(e) => {
+                eprintln!(
+                    "Failed to update {} (previous version kept): {}",
+                    package.name, e
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} server(s) failed to update", failed);
+    }
+
+    Ok(())
+}
+
+/// `lsmcp prune [--dry-run]`
+pub fn prune(dry_run: bool) -> Result<()> {
+    let mut installer = ServerInstaller::new()?;
+    let report = installer.prune(dry_run)?;
+
+    if report.is_empty() {
+        println!("Nothing to prune");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for name in &report.stale_entries {
+        println!("{} manifest entry {} (binary missing)", verb, name);
+    }
+    for path in &report.orphaned_paths {
+        println!("{} orphaned {}", verb, path.display());
+    }
+
+    Ok(())
+}
+
+/// `lsmcp cache [SERVER] [--clear]`
+pub fn cache(server: Option<&str>, clear: bool) -> Result<()> {
+    let installer = ServerInstaller::new()?;
+    let dirs = if clear {
+        warm_cache::clear(installer.data_dir(), server)?
+    } else {
+        warm_cache::list(installer.data_dir(), server)
+    };
+    if dirs.is_empty() {
+        println!("No server caches");
+        return Ok(());
+    }
+
+    let total: u64 = dirs.iter().map(|dir| dir.bytes).sum();
+    for dir in &dirs {
+        println!(
+            "  {:<24} {:>10}  {}",
+            dir.server,
+            format_size(dir.bytes),
+            dir.path.display()
+        );
+    }
+    let verb = if clear { "Cleared" } else { "Total" };
+    println!(
+        "{} {} in {} director(ies)",
+        verb,
+        format_size(total),
+        dirs.len()
+    );
+    Ok(())
+}
+
+/// `1.5 GB`, `20.0 MB`, `512 B`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// `lsmcp check`
+///
+/// The findings go to stdout; for JSON and annotations the summary goes to
+/// stderr, keeping stdout machine-readable.
+pub async fn check(
+    config: Arc<ConfigLoader>,
+    workspace_root: PathBuf,
+    paths: &[String],
+    changed: Option<Option<String>>,
+    format: CheckFormat,
+    fail_on: FailOn,
+    wait: u64,
+) -> Result<()> {
+    let files = match &changed {
+        None => check::files_to_check(&workspace_root, paths),
+        Some(base) => {
+            let changed = git::changed_files(&workspace_root, base.as_deref()).await?;
+            eprintln!(
+                "{} file(s) changed since {} (merge base {})",
+                changed.files.len(),
+                changed.base,
+                changed.short_merge_base()
+            );
+            if paths.is_empty() {
+                changed.files
+            } else {
+                let mut files = check::files_to_check(&workspace_root, paths);
+                files.retain(|file| changed.files.contains(file));
+                files
+            }
+        }
+    };
+    if files.is_empty() {
+        if changed.is_some() {
+            eprintln!("No changed files to check");
+            return Ok(());
+        }
+        bail!("No files match {}", paths.join(" "));
+    }
+
+    let manager = LspManager::new(workspace_root, config)?;
+    let report = check::check(&manager, files, Duration::from_secs(wait)).await;
+    manager.shutdown().await;
+
+    print!("{}", report.render(format));
+    if format != CheckFormat::Text {
+        eprintln!("{}", report.summary());
+    }
+
+    let counts = report.counts();
+    if fail_on.fails(&counts) {
+        bail!("Diagnostics at or above {} severity", fail_on);
+    }
+    if report.files.is_empty() {
+        bail!("No files could be checked");
+    }
+    Ok(())
+}
+
+/// `lsmcp stats`
+pub fn stats(config: &ConfigLoader, reset: bool) -> Result<()> {
+    let path = usage::usage_path(ServerInstaller::new()?.data_dir());
+    if reset {
+        match std::fs::remove_file(&path) {
+            Ok(()) => println!("Forgot the tool usage recorded in {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                println!("No tool usage recorded")
+            }
+            Err(e) => bail!("Failed to remove {}: {}", path.display(), e),
+        }
+        return Ok(());
+    }
+
+    let report = usage::load(&path);
+    if report.tools.is_empty() {
+        println!("No tool usage recorded");
+        if config.settings().usage_stats == Some(false) {
+            println!("`usage_stats` is off, so lsmcp doesn't record it");
+        }
+        return Ok(());
+    }
+    let tools_config = config.tools();
+    let offered: Vec<String> = get_tool_definitions()
+        .into_iter()
+        .map(|tool| tool.name)
+        .filter(|name| tools_config.is_enabled(name))
+        .collect();
+    print!("{}", report.render("Tool usage recorded", &offered));
+    Ok(())
+}
+
+/// `lsmcp ps`
+pub async fn ps() -> Result<()> {
+    let servers = orphans::list().await;
+    if servers.is_empty() {
+        println!("No language servers running");
+        return Ok(());
+    }
+
+    println!(
+        "  {:<8} {:<24} {:<12} {:<8} {:<9} WORKSPACE",
+        "PID", "SERVER", "LANGUAGE", "OWNER", "STATUS"
+    );
+    for server in &servers {
+        println!(
+            "  {:<8} {:<24} {:<12} {:<8} {:<9} {}",
+            server.process.pid,
+            server.process.server,
+            server.process.language,
+            server.owner_pid,
+            if server.orphaned {
+                "orphaned"
+            } else {
+                "running"
+            },
+            server.process.workspace_root.display()
+        );
+    }
+    Ok(())
+}
+
+/// `lsmcp kill-orphans`
+pub async fn kill_orphans() -> Result<()> {
+    let stopped = orphans::kill_orphans().await;
+    if stopped.is_empty() {
+        println!("No orphaned language servers");
+    }
+    for server in &stopped {
+        println!(
+            "Stopped {} (pid {}) for {}",
+            server.server,
+            server.pid,
+            server.workspace_root.display()
+        );
+    }
+    Ok(())
+}
+
+/// `lsmcp edits list`
+pub fn edits_list(workspace_root: &Path) -> Result<()> {
+    let entries = EditJournal::for_workspace(workspace_root)?.list()?;
+    if entries.is_empty() {
+        println!("No journaled edits for {}", workspace_root.display());
+        return Ok(());
+    }
+
+    let now = lsmcp::lsp::session::now();
+    for entry in &entries {
+        let files: Vec<String> = entry
+            .changes
+            .iter()
+            .map(|change| {
+                change
+                    .path
+                    .strip_prefix(workspace_root)
+                    .unwrap_or(&change.path)
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        println!(
+            "{:>4}  {:<40} {} ago  {}",
+            entry.id,
+            entry.description,
+            format_age(now.saturating_sub(entry.applied_at)),
+            files.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// `lsmcp edits rollback [id] [--force] [--dry-run]`
+pub fn edits_rollback(
+    workspace_root: &Path,
+    id: Option<u64>,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let journal = EditJournal::for_workspace(workspace_root)?;
+    let undo = journal.get(id)?.undo(force)?;
+    print!("{}", edits::unified_diff(&undo.changes, workspace_root));
+    if dry_run {
+        println!("Would apply: {}", undo.description);
+        return Ok(());
+    }
+    undo.apply(Some(&journal))?;
+    println!("Applied: {}", undo.description);
+    Ok(())
+}
+
+/// `seconds` as a rough age, like "5m" or "3d"
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// `lsmcp registry sync`
+pub async fn registry_sync(config: &ConfigLoader) -> Result<()> {
+    println!("Syncing the Mason registry...");
+    let report = lsmcp::registry::sync(&InstallOptions::from_settings(&config.settings())).await?;
+    println!(
+        "Cached {} language servers ({} Mason packages) from registry {} in {}",
+        report.servers,
+        report.total,
+        report.version,
+        report.path.display()
+    );
+    Ok(())
+}
+
+/// `lsmcp config validate [path]`
+pub fn config_validate(path: Option<PathBuf>) -> Result<()> {
+    let Some(path) = path.or_else(ConfigLoader::user_config_path) else {
+        println!(
+            "No config file found (.lsmcp.toml, $LSMCP_CONFIG or ~/.config/lsmcp/config.toml)"
+        );
+        return Ok(());
+    };
+
+    let report = validate::validate_file(&path)?;
+    println!("Checked {}", report.path.display());
+    for issue in &report.issues {
+        println!("{}: {}", issue.severity, issue.message);
+    }
+
+    let errors = report.count(Severity::Error);
+    let warnings = report.count(Severity::Warning);
+    if errors > 0 {
+        bail!("{} error(s), {} warning(s)", errors, warnings);
+    }
+
+    if warnings > 0 {
+        println!("{} warning(s)", warnings);
+    } else {
+        println!("No problems found");
+    }
+    Ok(())
+}
+
+/// `lsmcp config schema`
+pub fn config_schema() -> Result<()> {
+    println!("{}", UserConfig::json_schema());
+    Ok(())
+}
+
+/// `lsmcp config import-neovim`
+///
+/// The config goes to stdout, to review and add to a config file; what was
+/// imported and skipped goes to stderr.
+pub fn config_import_neovim(lspconfig: Option<PathBuf>, mason: Option<PathBuf>) -> Result<()> {
+    let mason = match mason {
+        Some(dir) => vec![dir],
+        None => SearchRoots::from_env().mason,
+    };
+    let import = neovim::import(&NeovimSources { lspconfig, mason })?;
+
+    for (name, neovim_name) in &import.servers {
+        if name == neovim_name {
+            eprintln!("Imported {}", name);
+        } else {
+            eprintln!("Imported {} ({} in Neovim)", name, neovim_name);
+        }
+    }
+    for (name, reason) in &import.skipped {
+        eprintln!("Skipped {}: {}", name, reason);
+    }
+    if import.servers.is_empty() {
+        bail!("No language servers found to import");
+    }
+
+    let toml = import.to_toml()?;
+    if toml.is_empty() {
+        eprintln!("lsmcp already runs these servers as Neovim does; nothing to add");
+    } else {
+        print!("{}", toml);
+    }
+    Ok(())
+}
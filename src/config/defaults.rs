@@ -2,7 +2,7 @@
 //!
 //! These provide zero-config support for the most popular languages
 
-use crate::config::registry::{BinaryConfig, InstallSource, LspPackage};
+use crate::config::registry::{BinaryConfig, Connection, InstallSource, LspPackage};
 use std::collections::HashMap;
 
 pub fn get_default_configs() -> HashMap<String, LspPackage> {
@@ -39,6 +39,9 @@ fn typescript_config() -> LspPackage {
             "mjs".to_string(),
             "cjs".to_string(),
         ],
+        filenames: vec![],
+        filename_patterns: vec![],
+        shebangs: vec!["node".to_string()],
         source: InstallSource::Npm {
             package: "typescript-language-server".to_string(),
             version: None,
@@ -47,8 +50,18 @@ fn typescript_config() -> LspPackage {
             primary: "typescript-language-server".to_string(),
             additional: vec![],
             lsp_args: vec!["--stdio".to_string()],
+            env: HashMap::new(),
+            cwd: None,
+            connection: Connection::Stdio,
+            spawn_timeout_secs: None,
+            initialize_timeout_secs: None,
+            limits: Default::default(),
+            setup: Vec::new(),
         },
         initialization_options: None,
+        settings: None,
+        remote: None,
+        extensions: vec![],
     }
 }
 
@@ -60,6 +73,9 @@ fn python_config() -> LspPackage {
         licenses: vec!["MIT".to_string()],
         languages: vec!["python".to_string()],
         file_extensions: vec!["py".to_string(), "pyi".to_string()],
+        filenames: vec![],
+        filename_patterns: vec![],
+        shebangs: vec!["python".to_string(), "python3".to_string()],
         source: InstallSource::Npm {
             package: "pyright".to_string(),
             version: None,
@@ -68,8 +84,18 @@ fn python_config() -> LspPackage {
             primary: "pyright-langserver".to_string(),
             additional: vec!["pyright".to_string()],
             lsp_args: vec!["--stdio".to_string()],
+            env: HashMap::new(),
+            cwd: None,
+            connection: Connection::Stdio,
+            spawn_timeout_secs: None,
+            initialize_timeout_secs: None,
+            limits: Default::default(),
+            setup: Vec::new(),
         },
         initialization_options: None,
+        settings: None,
+        remote: None,
+        extensions: vec![],
     }
 }
 
@@ -81,15 +107,31 @@ fn rust_config() -> LspPackage {
         licenses: vec!["MIT".to_string(), "Apache-2.0".to_string()],
         languages: vec!["rust".to_string()],
         file_extensions: vec!["rs".to_string()],
-        source: InstallSource::External {
-            command: "rust-analyzer".to_string(),
+        filenames: vec![],
+        filename_patterns: vec![],
+        shebangs: vec![],
+        source: InstallSource::GithubRelease {
+            repo: "rust-lang/rust-analyzer".to_string(),
+            tag: None,
+            asset: None,
         },
         bin: BinaryConfig {
             primary: "rust-analyzer".to_string(),
             additional: vec![],
             lsp_args: vec![],
+            env: HashMap::new(),
+            cwd: None,
+            connection: Connection::Stdio,
+            spawn_timeout_secs: None,
+            // Loading a large Cargo workspace can take minutes
+            initialize_timeout_secs: Some(120),
+            limits: Default::default(),
+            setup: Vec::new(),
         },
         initialization_options: None,
+        settings: None,
+        remote: None,
+        extensions: vec![],
     }
 }
 
@@ -101,6 +143,9 @@ fn go_config() -> LspPackage {
         licenses: vec!["BSD-3-Clause".to_string()],
         languages: vec!["go".to_string()],
         file_extensions: vec!["go".to_string()],
+        filenames: vec![],
+        filename_patterns: vec![],
+        shebangs: vec![],
         source: InstallSource::External {
             command: "gopls".to_string(),
         },
@@ -108,8 +153,19 @@ fn go_config() -> LspPackage {
             primary: "gopls".to_string(),
             additional: vec![],
             lsp_args: vec![],
+            env: HashMap::new(),
+            cwd: None,
+            connection: Connection::Stdio,
+            spawn_timeout_secs: None,
+            initialize_timeout_secs: None,
+            limits: Default::default(),
+            setup: Vec::new(),
         },
-        initialization_options: None,
+        // Test code lenses carry the tests `lsp_list_runnables` reports
+        initialization_options: Some(serde_json::json!({ "codelenses": { "test": true } })),
+        settings: None,
+        remote: None,
+        extensions: vec![],
     }
 }
 
@@ -1,44 +1,265 @@
-//! Configuration loader with 3-tier precedence
+//! Configuration loader with 5-tier precedence
 //!
 //! Priority order (highest to lowest):
 //! 1. User config (~/.config/lsmcp/config.toml or .lsmcp.toml)
-//! 2. Mason registry (embedded TOML files)
-//! 3. Built-in defaults (hardcoded for TS/Python/Rust/Go)
+//! 2. Local registry (~/.config/lsmcp/registry/*.toml)
+//! 3. Mason registry (embedded TOML files)
+//! 4. Built-in defaults (hardcoded for TS/Python/Rust/Go)
+//! 5. Synced Mason registry cache (`lsmcp registry sync`)
 
-use crate::config::{get_default_configs, LspPackage, UserConfig};
+use crate::config::project_config::ProjectConfigs;
+use crate::config::{
+    get_default_configs, BinaryConfig, CommandToolConfig, Connection, InstallSource, LspOverride,
+    LspPackage, Settings, ToolsConfig, UserConfig,
+};
 use crate::types::LspError;
+use crate::utils::glob::glob_match;
+use crate::utils::uri::PathMap;
 use include_dir::{include_dir, Dir};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use tracing::{debug, info, warn};
 
 // Embed the registry directory at compile time
 static REGISTRY_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/registry");
 
+/// Profile chosen with `--profile`, over `$LSMCP_PROFILE`
+static PROFILE: OnceLock<String> = OnceLock::new();
+
 pub struct ConfigLoader {
     defaults: HashMap<String, LspPackage>,
     registry: HashMap<String, LspPackage>,
+    /// User-provided package files from the local registry, keyed by name
+    local: HashMap<String, LspPackage>,
+    /// Servers from the synced Mason registry cache, keyed by name
+    mason: HashMap<String, LspPackage>,
+    /// User-defined servers from `[lsp.<name>]`, keyed by name
+    custom: HashMap<String, LspPackage>,
     user_config: Option<UserConfig>,
+    /// Command-line overrides layered over the file's settings
+    cli_settings: Settings,
+    /// `.lsmcp.toml` files of projects below the workspace root
+    projects: ProjectConfigs,
 }
 
 impl ConfigLoader {
     pub fn new() -> Result<Self, LspError> {
+        let user_config = Self::load_user_config()?;
+        if user_config.is_some() {
+            info!("Loaded user configuration");
+        }
+
+        Self::with_user_config(user_config)
+    }
+
+    pub(crate) fn with_user_config(user_config: Option<UserConfig>) -> Result<Self, LspError> {
         let defaults = get_default_configs();
         info!("Loaded {} default LSP configurations", defaults.len());
 
         let registry = Self::load_registry()?;
         info!("Loaded {} LSP configurations from registry", registry.len());
 
-        let user_config = Self::load_user_config()?;
-        if user_config.is_some() {
-            info!("Loaded user configuration");
+        let local = match Self::local_registry_dir() {
+            Some(dir) if dir.is_dir() => {
+                let local = Self::load_local_registry(&dir);
+                info!(
+                    "Loaded {} LSP configurations from {}",
+                    local.len(),
+                    dir.display()
+                );
+                local
+            }
+            _ => HashMap::new(),
+        };
+
+        let mason: HashMap<String, LspPackage> = crate::registry::load_cached()
+            .map(|cache| {
+                info!(
+                    "Loaded {} LSP configurations from Mason registry {}",
+                    cache.packages.len(),
+                    cache.version
+                );
+                cache
+                    .packages
+                    .into_iter()
+                    .map(|pkg| (pkg.name.clone(), pkg))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self::from_sources(
+            defaults,
+            registry,
+            local,
+            mason,
+            user_config,
+        ))
+    }
+
+    /// Combine the tiers, merging user overrides in up front
+    fn from_sources(
+        mut defaults: HashMap<String, LspPackage>,
+        registry: HashMap<String, LspPackage>,
+        local: HashMap<String, LspPackage>,
+        mason: HashMap<String, LspPackage>,
+        user_config: Option<UserConfig>,
+    ) -> Self {
+        // Extension requests are declared in the registry files only; the
+        // built-in defaults for the same servers take them from there
+        for pkg in defaults
+            .values_mut()
+            .filter(|pkg| pkg.extensions.is_empty())
+        {
+            if let Some(declared) = registry.values().find(|r| r.name == pkg.name) {
+                pkg.extensions = declared.extensions.clone();
+            }
         }
 
-        Ok(Self {
+        let overrides = user_config.as_ref().map(|c| &c.lsp);
+        let defaults = Self::apply_overrides(defaults, overrides);
+        let registry = Self::apply_overrides(registry, overrides);
+        let local = Self::apply_overrides(local, overrides);
+        let mason = Self::apply_overrides(mason, overrides);
+
+        let mut loader = Self {
             defaults,
             registry,
+            local,
+            mason,
+            custom: HashMap::new(),
             user_config,
-        })
+            cli_settings: Settings::default(),
+            projects: ProjectConfigs::default(),
+        };
+
+        loader.custom = loader.load_custom_lsps();
+        if !loader.custom.is_empty() {
+            info!("Loaded {} custom LSP configurations", loader.custom.len());
+        }
+
+        loader
+    }
+
+    /// Merge `[lsp.<name>]` entries onto a tier, dropping disabled servers
+    fn apply_overrides(
+        packages: HashMap<String, LspPackage>,
+        overrides: Option<&HashMap<String, LspOverride>>,
+    ) -> HashMap<String, LspPackage> {
+        packages
+            .into_iter()
+            .filter_map(|(key, pkg)| {
+                let entry = overrides.and_then(|o| o.get(&pkg.name));
+                Self::apply_override(pkg, entry).map(|pkg| (key, pkg))
+            })
+            .collect()
+    }
+
+    /// Build packages for user `[lsp.<name>]` entries that aren't known servers
+    fn load_custom_lsps(&self) -> HashMap<String, LspPackage> {
+        let mut custom = HashMap::new();
+        let Some(user_cfg) = &self.user_config else {
+            return custom;
+        };
+
+        for (name, entry) in &user_cfg.lsp {
+            let known = [&self.local, &self.defaults, &self.registry, &self.mason]
+                .iter()
+                .any(|source| source.values().any(|pkg| &pkg.name == name));
+            if known || entry.enabled == Some(false) {
+                continue;
+            }
+
+            match Self::custom_package(name, entry) {
+                Ok(mut package) => {
+                    // A server for a known language handles that language's files
+                    if package.file_extensions.is_empty() {
+                        package.file_extensions = package
+                            .languages
+                            .iter()
+                            .filter_map(|language| self.get_lsp_for_language(language).ok())
+                            .flat_map(|pkg| pkg.file_extensions)
+                            .collect();
+                    }
+                    debug!("Loaded custom LSP: {}", name);
+                    custom.insert(name.clone(), package);
+                }
+                Err(e) => warn!("Ignoring [lsp.{}]: {}", name, e),
+            }
+        }
+
+        custom
+    }
+
+    pub(super) fn custom_package(name: &str, entry: &LspOverride) -> Result<LspPackage, LspError> {
+        // Servers reached over TCP may already be running elsewhere
+        let connects = matches!(entry.connection, Some(Connection::Tcp { .. }));
+        let command = match &entry.command {
+            Some(command) => command.clone(),
+            None if connects => String::new(),
+            None => {
+                return Err(LspError::ConfigError(
+                    "custom servers need a `command`, or a TCP `connection`".to_string(),
+                ))
+            }
+        };
+
+        let has_matcher = [
+            &entry.languages,
+            &entry.file_extensions,
+            &entry.filenames,
+            &entry.filename_patterns,
+            &entry.shebangs,
+        ]
+        .iter()
+        .any(|field| field.as_ref().is_some_and(|values| !values.is_empty()));
+
+        if !has_matcher {
+            return Err(LspError::ConfigError(
+                "custom servers need `languages`, `file_extensions`, `filenames`, \
+                 `filename_patterns` or `shebangs`"
+                    .to_string(),
+            ));
+        }
+
+        let base = LspPackage {
+            name: name.to_string(),
+            description: "User-defined language server".to_string(),
+            homepage: None,
+            licenses: Vec::new(),
+            // Language is the client key, so fall back to the server name
+            languages: vec![name.to_string()],
+            file_extensions: Vec::new(),
+            filenames: Vec::new(),
+            filename_patterns: Vec::new(),
+            shebangs: Vec::new(),
+            source: InstallSource::External {
+                command: command.clone(),
+            },
+            bin: BinaryConfig {
+                primary: command,
+                additional: Vec::new(),
+                lsp_args: Vec::new(),
+                env: HashMap::new(),
+                cwd: None,
+                connection: Connection::Stdio,
+                spawn_timeout_secs: None,
+                initialize_timeout_secs: None,
+                limits: Default::default(),
+                setup: Vec::new(),
+            },
+            initialization_options: None,
+            settings: None,
+            remote: None,
+            extensions: Vec::new(),
+        };
+
+        let mut package = Self::apply_override(base, Some(entry))
+            .ok_or_else(|| LspError::ConfigError("custom server is disabled".to_string()))?;
+        if package.languages.is_empty() {
+            package.languages = vec![name.to_string()];
+        }
+        Ok(package)
     }
 
     fn load_registry() -> Result<HashMap<String, LspPackage>, LspError> {
@@ -79,19 +300,78 @@ impl ConfigLoader {
         Ok(registry)
     }
 
-    fn load_user_config() -> Result<Option<UserConfig>, LspError> {
-        // Try multiple locations in priority order:
-        // 1. ./.lsmcp.toml (project-specific)
-        // 2. $LSMCP_CONFIG (environment variable)
-        // 3. ~/.config/lsmcp/config.toml (user-global)
+    /// Directory of user-provided registry files (`~/.config/lsmcp/registry`)
+    pub fn local_registry_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("lsmcp").join("registry"))
+    }
 
-        let mut candidates = Vec::new();
+    /// Packages from the `*.toml` files in a local registry directory
+    ///
+    /// Files that fail to parse are skipped with a warning, so one broken
+    /// file doesn't take the others down. When two files define the same
+    /// server, the one whose file name sorts last wins.
+    fn load_local_registry(dir: &Path) -> HashMap<String, LspPackage> {
+        let mut packages = HashMap::new();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return packages;
+        };
 
-        // Project-specific config
-        if let Ok(cwd) = std::env::current_dir() {
-            candidates.push(cwd.join(".lsmcp.toml"));
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+
+        for path in files {
+            let package = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| {
+                    toml::from_str::<LspPackage>(&content).map_err(|e| e.to_string())
+                });
+
+            match package {
+                Ok(package) => {
+                    debug!(
+                        "Loaded local registry entry {} from {}",
+                        package.name,
+                        path.display()
+                    );
+                    if let Some(previous) = packages.insert(package.name.clone(), package) {
+                        warn!(
+                            "{} redefines {} from an earlier local registry file",
+                            path.display(),
+                            previous.name
+                        );
+                    }
+                }
+                Err(e) => warn!("Failed to parse registry file {}: {}", path.display(), e),
+            }
         }
 
+        packages
+    }
+
+    /// The user config file in use, if any
+    ///
+    /// Checked in priority order:
+    /// 1. ./.lsmcp.toml (project-specific)
+    /// 2. $LSMCP_CONFIG (environment variable)
+    /// 3. ~/.config/lsmcp/config.toml (user-global)
+    pub fn user_config_path() -> Option<PathBuf> {
+        // Project-specific config
+        std::env::current_dir()
+            .ok()
+            .map(|cwd| cwd.join(".lsmcp.toml"))
+            .filter(|path| path.exists())
+            .or_else(Self::global_config_path)
+    }
+
+    /// The config file the user set up rather than one that came with the
+    /// project: `$LSMCP_CONFIG`, else ~/.config/lsmcp/config.toml
+    fn global_config_path() -> Option<PathBuf> {
+        let mut candidates = Vec::new();
+
         // Environment variable
         if let Ok(config_path) = std::env::var("LSMCP_CONFIG") {
             candidates.push(PathBuf::from(config_path));
@@ -102,62 +382,226 @@ impl ConfigLoader {
             candidates.push(config_dir.join("lsmcp").join("config.toml"));
         }
 
-        for path in &candidates {
-            if path.exists() {
-                debug!("Loading user config from: {}", path.display());
-                let content = std::fs::read_to_string(path)
-                    .map_err(|e| LspError::ConfigError(format!("Failed to read config: {}", e)))?;
+        candidates.into_iter().find(|path| path.exists())
+    }
+
+    /// A project's own `config` (at `path`) with its sandbox settings and
+    /// `[command_tools]` replaced by the global config's, since a cloned
+    /// repository mustn't loosen them
+    fn with_global_sandbox(config: UserConfig, path: &Path) -> UserConfig {
+        let global = Self::global_config_path().and_then(|global| {
+            let content = std::fs::read_to_string(&global).ok()?;
+            let (config, _) = UserConfig::parse(&global, &content).ok()?;
+            match Self::profile() {
+                Some(name) => Some(config.clone().with_profile(&name).unwrap_or(config)),
+                None => Some(config),
+            }
+        });
+
+        let (config, replaced) = config.with_sandbox_from(global.as_ref());
+        for key in replaced {
+            warn!(
+                "Ignoring `{}` in {}: it is only read from the user-global config",
+                key,
+                path.display()
+            );
+        }
+        config
+    }
+
+    /// Use the config file's `[profile.<name>]` from now on (first call wins)
+    pub fn select_profile(name: &str) {
+        let _ = PROFILE.set(name.to_string());
+    }
+
+    /// The profile in use: from `--profile`, else `$LSMCP_PROFILE`
+    pub fn profile() -> Option<String> {
+        PROFILE.get().cloned().or_else(|| {
+            std::env::var("LSMCP_PROFILE")
+                .ok()
+                .filter(|name| !name.is_empty())
+        })
+    }
+
+    fn load_user_config() -> Result<Option<UserConfig>, LspError> {
+        let profile = Self::profile();
+        let Some(path) = Self::user_config_path() else {
+            if let Some(name) = profile {
+                return Err(LspError::ConfigError(format!(
+                    "Profile '{}' selected, but there is no config file",
+                    name
+                )));
+            }
+            debug!("No user config file found");
+            return Ok(None);
+        };
+
+        debug!("Loading user config from: {}", path.display());
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| LspError::ConfigError(format!("Failed to read config: {}", e)))?;
 
-                let config: UserConfig = toml::from_str(&content)
-                    .map_err(|e| LspError::ConfigError(format!("Failed to parse config: {}", e)))?;
+        let (config, unknown_keys) = UserConfig::parse(&path, &content)?;
+        for key in unknown_keys {
+            warn!("Ignoring unknown key `{}` in {}", key, path.display());
+        }
 
-                return Ok(Some(config));
+        let config = match profile {
+            Some(name) => {
+                info!("Using profile {}", name);
+                config.with_profile(&name).map_err(|e| match e {
+                    LspError::ConfigError(message) => {
+                        LspError::ConfigError(format!("{}: {}", path.display(), message))
+                    }
+                    e => e,
+                })?
             }
+            None => config,
+        };
+
+        if Self::global_config_path().as_deref() == Some(path.as_path()) {
+            Ok(Some(config))
+        } else {
+            Ok(Some(Self::with_global_sandbox(config, &path)))
         }
+    }
 
-        debug!("No user config file found");
-        Ok(None)
+    /// Re-read the config file, keeping command-line overrides
+    pub fn reload(&self) -> Result<Self, LspError> {
+        let mut fresh = Self::new()?;
+        fresh.cli_settings = self.cli_settings.clone();
+        Ok(fresh)
     }
 
-    /// Get LSP configuration for a file based on its extension
-    pub fn get_lsp_for_file(&self, file: &Path) -> Result<LspPackage, LspError> {
-        let ext = file
-            .extension()
-            .and_then(|e| e.to_str())
-            .ok_or_else(|| LspError::InvalidPath(file.to_path_buf()))?;
+    /// Global settings: the user config's, with command-line overrides applied
+    pub fn settings(&self) -> Settings {
+        self.user_config
+            .as_ref()
+            .and_then(|c| c.settings.clone())
+            .unwrap_or_default()
+            .overlaid(&self.cli_settings)
+    }
 
-        self.get_lsp_for_extension(ext)
+    /// The user config file's settings, read without loading the registry
+    ///
+    /// For setting up logging before the full config loads; a missing or
+    /// broken file gives the defaults (the full load reports it).
+    pub fn user_settings() -> Settings {
+        Self::user_config_path()
+            .and_then(|path| {
+                let content = std::fs::read_to_string(&path).ok()?;
+                UserConfig::parse(&path, &content).ok()
+            })
+            .and_then(|(config, _)| match Self::profile() {
+                Some(name) => config.with_profile(&name).ok(),
+                None => Some(config),
+            })
+            .and_then(|config| config.settings)
+            .unwrap_or_default()
     }
 
-    /// Get LSP configuration for a specific file extension
-    pub fn get_lsp_for_extension(&self, ext: &str) -> Result<LspPackage, LspError> {
-        debug!("Looking up LSP for extension: .{}", ext);
+    /// The user's `[command_tools]`, by tool name
+    pub fn command_tools(&self) -> BTreeMap<String, CommandToolConfig> {
+        self.user_config
+            .as_ref()
+            .map(|config| config.command_tools.clone())
+            .unwrap_or_default()
+    }
 
-        // Check user config first
-        if let Some(user_cfg) = &self.user_config {
-            // Check if user has custom LSP for this extension
-            for (name, _) in &user_cfg.lsp {
-                // TODO: Match against file extensions in custom configs
-                debug!("Found user config for LSP: {}", name);
+    /// The user's `[tools]`
+    pub fn tools(&self) -> ToolsConfig {
+        self.user_config
+            .as_ref()
+            .map(|config| config.tools.clone())
+            .unwrap_or_default()
+    }
+
+    /// The user's `[path_map]`: paths servers report to the MCP client's
+    pub fn path_map(&self) -> Option<PathMap> {
+        let config = self.user_config.as_ref()?;
+        PathMap::new(config.path_map.clone())
+    }
+
+    /// Command-line overrides for the global settings (kept across reloads)
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.cli_settings
+    }
+
+    /// Get LSP configuration for a file
+    ///
+    /// Tries, in order: exact file names (`Dockerfile`), file name patterns
+    /// (`Dockerfile.*`), the extension, and finally the interpreter in the
+    /// file's `#!` line for extensionless or unrecognised scripts.
+    pub fn get_lsp_for_file(&self, file: &Path) -> Result<LspPackage, LspError> {
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| LspError::InvalidPath(file.to_path_buf()))?;
+
+        if let Some(pkg) = self.get_lsp_for_filename(name) {
+            return Ok(pkg);
+        }
+
+        if let Some(ext) = file.extension().and_then(|e| e.to_str()) {
+            let pkg = self
+                .get_lsp_for_extension(ext)
+                .or_else(|_| self.get_lsp_for_extension(&ext.to_lowercase()));
+            if let Ok(pkg) = pkg {
+                return Ok(pkg);
             }
         }
 
-        // Search in all sources: defaults, registry
-        for (source_name, source) in [("defaults", &self.defaults), ("registry", &self.registry)] {
-            for (lang, pkg) in source {
-                if pkg.file_extensions.iter().any(|e| e == ext) {
-                    debug!("Found LSP '{}' for .{} in {}", pkg.name, ext, source_name);
-                    return Ok(pkg.clone());
-                }
+        if let Some(interpreter) = read_shebang(file) {
+            if let Some((source_name, pkg)) = self.find_package(|pkg| {
+                pkg.shebangs
+                    .iter()
+                    .any(|s| interpreter_matches(&interpreter, s))
+            }) {
+                debug!(
+                    "Found LSP '{}' for #!{} in {}",
+                    pkg.name, interpreter, source_name
+                );
+                return Ok(pkg.clone());
             }
         }
 
         Err(LspError::UnsupportedLanguage(format!(
-            "No LSP found for file extension '.{}'",
-            ext
+            "No LSP found for file '{}'",
+            name
         )))
     }
 
+    /// Get LSP configuration by exact file name or file name pattern
+    fn get_lsp_for_filename(&self, name: &str) -> Option<LspPackage> {
+        let (source_name, pkg) = self
+            .find_package(|pkg| pkg.filenames.iter().any(|f| f == name))
+            .or_else(|| {
+                self.find_package(|pkg| {
+                    pkg.filename_patterns
+                        .iter()
+                        .any(|pattern| glob_match(pattern, name))
+                })
+            })?;
+
+        debug!("Found LSP '{}' for {} in {}", pkg.name, name, source_name);
+        Some(pkg.clone())
+    }
+
+    /// Get LSP configuration for a specific file extension
+    pub fn get_lsp_for_extension(&self, ext: &str) -> Result<LspPackage, LspError> {
+        debug!("Looking up LSP for extension: .{}", ext);
+
+        match self.find_package(|pkg| pkg.file_extensions.iter().any(|e| e == ext)) {
+            Some((source_name, pkg)) => {
+                debug!("Found LSP '{}' for .{} in {}", pkg.name, ext, source_name);
+                Ok(pkg.clone())
+            }
+            None => Err(LspError::UnsupportedLanguage(format!(
+                "No LSP found for file extension '.{}'",
+                ext
+            ))),
+        }
+    }
+
     /// Get LSP configuration by language name
     pub fn get_lsp_for_language(&self, language: &str) -> Result<LspPackage, LspError> {
         debug!("Looking up LSP for language: {}", language);
@@ -170,6 +614,27 @@ impl ConfigLoader {
             }
         }
 
+        // User-defined servers take precedence over everything else
+        if let Some(pkg) = self
+            .custom
+            .values()
+            .find(|pkg| pkg.languages.iter().any(|l| l == language))
+        {
+            debug!("Found LSP for {} in user config", language);
+            return Ok(pkg.clone());
+        }
+
+        // Then the user's own registry files
+        if let Some(pkg) = self
+            .local
+            .values()
+            .filter(|pkg| pkg.languages.iter().any(|l| l == language))
+            .min_by_key(|pkg| &pkg.name)
+        {
+            debug!("Found LSP for {} in local registry", language);
+            return Ok(pkg.clone());
+        }
+
         // Try defaults first (highest priority for built-in langs)
         if let Some(pkg) = self.defaults.get(language) {
             debug!("Found LSP for {} in defaults", language);
@@ -182,60 +647,405 @@ impl ConfigLoader {
             return Ok(pkg.clone());
         }
 
+        // Fall back to the synced Mason registry
+        if let Some(pkg) = self
+            .mason
+            .values()
+            .filter(|pkg| pkg.languages.iter().any(|l| l == language))
+            .min_by_key(|pkg| &pkg.name)
+        {
+            debug!("Found LSP for {} in Mason registry cache", language);
+            return Ok(pkg.clone());
+        }
+
         Err(LspError::UnsupportedLanguage(format!(
             "No LSP found for language '{}'",
             language
         )))
     }
 
+    /// All servers for a language, highest priority first
+    ///
+    /// Uses the `[language_servers]` list when the language has one, and the
+    /// single server from `get_lsp_for_language` otherwise. Listed servers that
+    /// can't be resolved are skipped with a warning.
+    pub fn get_lsps_for_language(&self, language: &str) -> Result<Vec<LspPackage>, LspError> {
+        let names = self
+            .user_config
+            .as_ref()
+            .and_then(|c| c.language_servers.get(language))
+            .filter(|names| !names.is_empty());
+
+        let Some(names) = names else {
+            return self.get_lsp_for_language(language).map(|pkg| vec![pkg]);
+        };
+
+        let mut packages: Vec<LspPackage> = Vec::new();
+        for name in names {
+            match self.get_lsp_by_name(name) {
+                Ok(pkg) if !packages.iter().any(|p| p.name == pkg.name) => packages.push(pkg),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping server '{}' for {}: {}", name, language, e),
+            }
+        }
+
+        if packages.is_empty() {
+            return Err(LspError::ConfigError(format!(
+                "None of the servers listed for '{}' in [language_servers] are available",
+                language
+            )));
+        }
+
+        Ok(packages)
+    }
+
+    /// All servers for a file, highest priority first
+    ///
+    /// Returns the file's language along with its servers.
+    pub fn get_lsps_for_file(&self, file: &Path) -> Result<(String, Vec<LspPackage>), LspError> {
+        let pkg = self.get_lsp_for_file(file)?;
+        let language = pkg.languages[0].clone();
+
+        let listed = self
+            .user_config
+            .as_ref()
+            .is_some_and(|c| c.language_servers.contains_key(&language));
+        if listed {
+            let packages = self.get_lsps_for_language(&language)?;
+            return Ok((language, packages));
+        }
+
+        Ok((language, vec![pkg]))
+    }
+
+    /// All servers for a file in `project_root`, highest priority first
+    ///
+    /// Like `get_lsps_for_file` (or `get_lsps_for_language` when the file's
+    /// `language` is given), with the `[lsp.<name>]` entries of the
+    /// `.lsmcp.toml` files between `workspace_root` and `project_root`
+    /// merged on, outermost first. Servers a project disables are left out.
+    pub fn get_lsps_for_project(
+        &self,
+        file: &Path,
+        language: Option<&str>,
+        project_root: &Path,
+        workspace_root: &Path,
+    ) -> Result<(String, Vec<LspPackage>), LspError> {
+        let (language, packages) = match language {
+            Some(language) => (language.to_string(), self.get_lsps_for_language(language)?),
+            None => self.get_lsps_for_file(file)?,
+        };
+        let overrides = self.projects.overrides_for(project_root, workspace_root);
+        if overrides.is_empty() {
+            return Ok((language, packages));
+        }
+
+        let packages: Vec<LspPackage> = packages
+            .into_iter()
+            .filter_map(|pkg| Self::apply_project_overrides(pkg, &overrides))
+            .collect();
+        if packages.is_empty() {
+            return Err(LspError::ConfigError(format!(
+                "The servers for '{}' are disabled in {}",
+                language,
+                project_root.join(super::PROJECT_CONFIG_FILE).display()
+            )));
+        }
+        Ok((language, packages))
+    }
+
+    /// A server's definition as used in `project_root`, `None` when it's
+    /// unknown or disabled there
+    pub fn get_lsp_for_project(
+        &self,
+        name: &str,
+        project_root: &Path,
+        workspace_root: &Path,
+    ) -> Option<LspPackage> {
+        let pkg = self.get_lsp_by_name(name).ok()?;
+        let overrides = self.projects.overrides_for(project_root, workspace_root);
+        Self::apply_project_overrides(pkg, &overrides)
+    }
+
+    fn apply_project_overrides(
+        pkg: LspPackage,
+        overrides: &[Arc<HashMap<String, LspOverride>>],
+    ) -> Option<LspPackage> {
+        overrides.iter().try_fold(pkg, |pkg, lsp| {
+            let entry = lsp.get(&pkg.name);
+            Self::apply_override(pkg, entry)
+        })
+    }
+
+    /// Whether a project `.lsmcp.toml` consulted so far has been created,
+    /// edited or removed, so the config should be reloaded
+    pub fn project_configs_changed(&self) -> bool {
+        self.projects.changed()
+    }
+
     /// Get LSP configuration by exact name
     pub fn get_lsp_by_name(&self, name: &str) -> Result<LspPackage, LspError> {
-        // Check user config
-        if let Some(user_cfg) = &self.user_config {
-            if let Some(_lsp_override) = user_cfg.lsp.get(name) {
-                // TODO: Merge user override with base config
-                debug!("Found user override for LSP: {}", name);
-            }
+        if let Some((_, pkg)) = self.find_package(|pkg| pkg.name == name) {
+            return Ok(pkg.clone());
         }
 
-        // Search all sources
-        for source in [&self.defaults, &self.registry] {
-            for pkg in source.values() {
-                if pkg.name == name {
-                    return Ok(pkg.clone());
+        let disabled = self
+            .user_config
+            .as_ref()
+            .and_then(|c| c.lsp.get(name))
+            .is_some_and(|entry| entry.enabled == Some(false));
+        if disabled {
+            return Err(LspError::ConfigError(format!(
+                "LSP '{}' is disabled in user config",
+                name
+            )));
+        }
+
+        Err(LspError::ConfigError(format!("LSP '{}' not found", name)))
+    }
+
+    /// Get LSP configuration by name (e.g. "pyright") or language (e.g. "python")
+    pub fn find_lsp(&self, server: &str) -> Result<LspPackage, LspError> {
+        self.get_lsp_by_name(server)
+            .or_else(|_| self.get_lsp_for_language(server))
+    }
+
+    /// LSPs for every language with files in the workspace, sorted by name
+    pub fn detect_lsps(&self, workspace_root: &Path) -> Vec<LspPackage> {
+        let mut lsps: Vec<LspPackage> = Vec::new();
+        let mut seen_extensions = HashSet::new();
+
+        for file in crate::utils::workspace::source_files(workspace_root) {
+            // One file per extension is enough unless the name itself is special
+            if let Some(ext) = file.extension() {
+                let special = file
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|name| self.get_lsp_for_filename(name).is_some());
+                if !seen_extensions.insert(ext.to_ascii_lowercase()) && !special {
+                    continue;
+                }
+            }
+
+            if let Ok(pkg) = self.get_lsp_for_file(&file) {
+                if !lsps.iter().any(|p| p.name == pkg.name) {
+                    lsps.push(pkg);
                 }
             }
         }
 
-        Err(LspError::ConfigError(format!("LSP '{}' not found", name)))
+        lsps.sort_by(|a, b| a.name.cmp(&b.name));
+        lsps
     }
 
-    /// List all available LSPs
+    /// List all available (enabled) LSPs with user overrides applied
     pub fn list_available_lsps(&self) -> Vec<&LspPackage> {
         let mut lsps: Vec<&LspPackage> = Vec::new();
 
-        // Collect from all sources (defaults take priority for duplicates)
-        let mut seen = std::collections::HashSet::new();
+        // Collect from all sources (earlier tiers take priority for duplicates)
+        let mut seen = HashSet::new();
 
-        for pkg in self.defaults.values() {
-            if seen.insert(&pkg.name) {
-                lsps.push(pkg);
+        for (_, source) in self.sources() {
+            for pkg in source.values() {
+                if seen.insert(&pkg.name) {
+                    lsps.push(pkg);
+                }
             }
         }
 
-        for pkg in self.registry.values() {
-            if seen.insert(&pkg.name) {
-                lsps.push(pkg);
-            }
+        lsps
+    }
+
+    /// All package sources, highest precedence first
+    fn sources(&self) -> [(&'static str, &HashMap<String, LspPackage>); 5] {
+        [
+            ("user config", &self.custom),
+            ("local registry", &self.local),
+            ("defaults", &self.defaults),
+            ("registry", &self.registry),
+            ("Mason registry cache", &self.mason),
+        ]
+    }
+
+    /// First package accepted by `matches`, searching tiers in precedence order
+    ///
+    /// Ties within a tier go to the alphabetically first server name.
+    fn find_package(
+        &self,
+        matches: impl Fn(&LspPackage) -> bool,
+    ) -> Option<(&'static str, &LspPackage)> {
+        self.sources()
+            .into_iter()
+            .find_map(|(source_name, source)| {
+                source
+                    .values()
+                    .filter(|pkg| matches(pkg))
+                    .min_by_key(|pkg| &pkg.name)
+                    .map(|pkg| (source_name, pkg))
+            })
+    }
+
+    /// Merge a user `[lsp.<name>]` entry onto a package
+    ///
+    /// Returns `None` when the entry disables the server. `command` makes the
+    /// server user-managed (it is never auto-installed); `args`, `cwd`,
+    /// `connection`, `remote`, `setup` and the file matching fields replace the base
+    /// values; `env` is merged over the base environment and `initialization_options` and
+    /// `settings` are deep-merged over the base ones.
+    fn apply_override(mut pkg: LspPackage, entry: Option<&LspOverride>) -> Option<LspPackage> {
+        let Some(entry) = entry else {
+            return Some(pkg);
+        };
+
+        if entry.enabled == Some(false) {
+            debug!("LSP {} is disabled in user config", pkg.name);
+            return None;
         }
 
-        lsps
+        debug!("Applying user override for LSP: {}", pkg.name);
+
+        if let Some(command) = &entry.command {
+            pkg.source = InstallSource::External {
+                command: command.clone(),
+            };
+            pkg.bin.primary = command.clone();
+        }
+        if let Some(args) = &entry.args {
+            pkg.bin.lsp_args = args.clone();
+        }
+        if let Some(env) = &entry.env {
+            pkg.bin.env.extend(env.clone());
+        }
+        if let Some(cwd) = &entry.cwd {
+            pkg.bin.cwd = Some(cwd.clone());
+        }
+        if let Some(connection) = &entry.connection {
+            pkg.bin.connection = connection.clone();
+        }
+        if let Some(remote) = &entry.remote {
+            pkg.remote = Some(remote.clone());
+        }
+        if let Some(secs) = entry.spawn_timeout_secs {
+            pkg.bin.spawn_timeout_secs = Some(secs);
+        }
+        if let Some(secs) = entry.initialize_timeout_secs {
+            pkg.bin.initialize_timeout_secs = Some(secs);
+        }
+        if let Some(limits) = &entry.limits {
+            pkg.bin.limits = pkg.bin.limits.overlaid(limits);
+        }
+        if let Some(setup) = &entry.setup {
+            pkg.bin.setup = setup.clone();
+        }
+        if let Some(languages) = &entry.languages {
+            pkg.languages = languages.clone();
+        }
+        if let Some(extensions) = &entry.file_extensions {
+            // Accept ".ml" as well as "ml"
+            pkg.file_extensions = extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_string())
+                .collect();
+        }
+        if let Some(filenames) = &entry.filenames {
+            pkg.filenames = filenames.clone();
+        }
+        if let Some(patterns) = &entry.filename_patterns {
+            pkg.filename_patterns = patterns.clone();
+        }
+        if let Some(shebangs) = &entry.shebangs {
+            pkg.shebangs = shebangs.clone();
+        }
+        if let Some(options) = &entry.initialization_options {
+            let merged = match pkg.initialization_options.take() {
+                Some(mut base) => {
+                    merge_json(&mut base, options);
+                    base
+                }
+                None => options.clone(),
+            };
+            pkg.initialization_options = Some(merged);
+        }
+        if let Some(settings) = &entry.settings {
+            let merged = match pkg.settings.take() {
+                Some(mut base) => {
+                    merge_json(&mut base, settings);
+                    base
+                }
+                None => settings.clone(),
+            };
+            pkg.settings = Some(merged);
+        }
+
+        Some(pkg)
+    }
+}
+
+/// Interpreter named by a file's `#!` line, if it has one
+fn read_shebang(file: &Path) -> Option<String> {
+    use std::io::Read;
+
+    let mut head = [0u8; 256];
+    let len = std::fs::File::open(file).ok()?.read(&mut head).ok()?;
+    let first_line = String::from_utf8_lossy(&head[..len])
+        .lines()
+        .next()?
+        .to_string();
+    parse_shebang(&first_line)
+}
+
+/// Extract the interpreter from a `#!` line
+///
+/// `#!/bin/bash` gives `bash`; `#!/usr/bin/env -S python3 -u` gives `python3`.
+fn parse_shebang(line: &str) -> Option<String> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+
+    if program != "env" {
+        return Some(program.to_string());
+    }
+
+    // Skip env's flags and VAR=value assignments
+    words
+        .find(|word| !word.starts_with('-') && !word.contains('='))
+        .and_then(|word| word.rsplit('/').next())
+        .map(String::from)
+}
+
+/// Whether an interpreter matches a configured name, ignoring a version
+/// suffix (`python3.12` matches `python3` and `python`)
+fn interpreter_matches(interpreter: &str, name: &str) -> bool {
+    interpreter == name
+        || interpreter
+            .strip_prefix(name)
+            .is_some_and(|suffix| suffix.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
+/// Recursively merge `overlay` into `base`
+///
+/// Objects are merged key by key; any other value in `overlay` replaces the
+/// one in `base`.
+fn merge_json(base: &mut serde_json::Value, overlay: &serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ExtensionParams;
+    use std::time::Duration;
 
     #[test]
     fn test_config_loader_new() {
@@ -288,6 +1098,15 @@ mod tests {
         assert_eq!(lua_lsp.unwrap().name, "lua-language-server");
     }
 
+    #[test]
+    fn test_defaults_take_registry_extensions() {
+        let loader = ConfigLoader::new().unwrap();
+        let rust = loader.get_lsp_for_language("rust").unwrap();
+        let tools: Vec<&str> = rust.extensions.iter().map(|e| e.tool.as_str()).collect();
+        assert_eq!(tools, ["lsp_rust_expand_macro", "lsp_rust_syntax_tree"]);
+        assert_eq!(rust.extensions[1].params, ExtensionParams::Document);
+    }
+
     #[test]
     fn test_list_available_lsps() {
         let loader = ConfigLoader::new().unwrap();
@@ -307,4 +1126,445 @@ mod tests {
             "Should have TypeScript LSP"
         );
     }
+
+    #[test]
+    fn test_custom_lsp_resolution() {
+        let user_config: UserConfig = toml::from_str(
+            r#"
+            [lsp.my-lsp]
+            languages = ["mylang"]
+            file_extensions = [".ml", "mli"]
+            command = "my-lsp-server"
+            args = ["--stdio"]
+
+            [lsp.my-python]
+            languages = ["python"]
+            command = "my-python-server"
+
+            [lsp.incomplete]
+            file_extensions = ["inc"]
+            "#,
+        )
+        .unwrap();
+        let loader = ConfigLoader::with_user_config(Some(user_config)).unwrap();
+
+        let by_ext = loader.get_lsp_for_extension("ml").unwrap();
+        assert_eq!(by_ext.name, "my-lsp");
+        assert_eq!(by_ext.bin.primary, "my-lsp-server");
+        assert_eq!(by_ext.bin.lsp_args, vec!["--stdio"]);
+        assert_eq!(loader.get_lsp_for_extension("mli").unwrap().name, "my-lsp");
+        assert_eq!(
+            loader.get_lsp_for_language("mylang").unwrap().name,
+            "my-lsp"
+        );
+        assert_eq!(loader.get_lsp_by_name("my-lsp").unwrap().name, "my-lsp");
+
+        // User servers win over built-in defaults, and inherit their extensions
+        assert_eq!(
+            loader.get_lsp_for_language("python").unwrap().name,
+            "my-python"
+        );
+        assert_eq!(
+            loader.get_lsp_for_extension("py").unwrap().name,
+            "my-python"
+        );
+
+        // Entries without a command are ignored
+        assert!(loader.get_lsp_for_extension("inc").is_err());
+        assert!(loader
+            .list_available_lsps()
+            .iter()
+            .any(|p| p.name == "my-lsp"));
+    }
+
+    #[test]
+    fn test_override_merges_across_tiers() {
+        let user_config: UserConfig = toml::from_str(
+            r#"
+            [lsp.rust-analyzer]
+            command = "/opt/rust-analyzer"
+            env = { RA_LOG = "info" }
+            cwd = "crates"
+            initialization_options = { cargo = { features = "all" }, checkOnSave = false }
+
+            [lsp.pyright]
+            enabled = false
+
+            [lsp.lua-language-server]
+            args = ["--loglevel=trace"]
+            file_extensions = ["lua", "luau"]
+            initialize_timeout_secs = 90
+            "#,
+        )
+        .unwrap();
+        // Give the built-in default base options to merge into
+        let mut defaults = get_default_configs();
+        let rust = defaults.get_mut("rust").unwrap();
+        rust.initialization_options = Some(serde_json::json!({
+            "cargo": { "buildScripts": { "enable": true }, "features": [] },
+            "checkOnSave": true
+        }));
+        rust.bin.env = HashMap::from([
+            ("RA_LOG".to_string(), "error".to_string()),
+            ("CARGO_TARGET_DIR".to_string(), "target/ra".to_string()),
+        ]);
+        let registry = ConfigLoader::load_registry().unwrap();
+        let loader = ConfigLoader::from_sources(
+            defaults,
+            registry,
+            HashMap::new(),
+            HashMap::new(),
+            Some(user_config),
+        );
+
+        // Defaults tier: command and options come from the user config
+        let rust = loader.get_lsp_for_extension("rs").unwrap();
+        assert_eq!(rust.bin.primary, "/opt/rust-analyzer");
+        assert!(matches!(rust.source, InstallSource::External { .. }));
+        assert_eq!(rust.bin.env["RA_LOG"], "info");
+        assert_eq!(rust.bin.env["CARGO_TARGET_DIR"], "target/ra");
+        assert_eq!(rust.bin.cwd.as_deref(), Some(Path::new("crates")));
+        assert_eq!(
+            rust.initialization_options.unwrap(),
+            serde_json::json!({
+                "cargo": { "buildScripts": { "enable": true }, "features": "all" },
+                "checkOnSave": false
+            })
+        );
+
+        // Registry tier: args replaced, base command kept, extensions replaced
+        let lua = loader.get_lsp_for_extension("luau").unwrap();
+        assert_eq!(lua.name, "lua-language-server");
+        assert_eq!(lua.bin.primary, "lua-language-server");
+        assert_eq!(lua.bin.lsp_args, vec!["--loglevel=trace"]);
+        assert_eq!(lua.bin.initialize_timeout(), Duration::from_secs(90));
+        assert_eq!(lua.bin.spawn_timeout(), Duration::from_secs(20));
+
+        // Disabled servers are not resolved by any lookup
+        assert!(loader.get_lsp_for_extension("py").is_err());
+        assert!(loader.get_lsp_for_language("python").is_err());
+        assert!(loader.get_lsp_by_name("pyright").is_err());
+        assert!(!loader
+            .list_available_lsps()
+            .iter()
+            .any(|p| p.name == "pyright"));
+    }
+
+    #[test]
+    fn test_local_registry_overrides_embedded() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("nil.toml"),
+            r#"
+            name = "nil"
+            description = "Nix language server"
+            licenses = ["MIT"]
+            languages = ["nix"]
+            file_extensions = ["nix"]
+
+            [source]
+            type = "Cargo"
+            crate_name = "nil"
+
+            [bin]
+            primary = "nil"
+            additional = []
+            lsp_args = []
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("zls.toml"),
+            r#"
+            name = "zls"
+            description = "Zig language server, built locally"
+            licenses = ["MIT"]
+            languages = ["zig"]
+            file_extensions = ["zig", "zon"]
+
+            [source]
+            type = "External"
+            command = "/opt/zls/bin/zls"
+
+            [bin]
+            primary = "/opt/zls/bin/zls"
+            additional = []
+            lsp_args = []
+            "#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.toml"), "name = \"broken\"\n").unwrap();
+        std::fs::write(dir.path().join("README.md"), "# notes\n").unwrap();
+
+        let local = ConfigLoader::load_local_registry(dir.path());
+        assert_eq!(local.len(), 2);
+
+        let loader = ConfigLoader::from_sources(
+            get_default_configs(),
+            ConfigLoader::load_registry().unwrap(),
+            local,
+            HashMap::new(),
+            None,
+        );
+
+        assert_eq!(loader.get_lsp_for_extension("nix").unwrap().name, "nil");
+        assert_eq!(loader.get_lsp_for_language("nix").unwrap().name, "nil");
+
+        // A local file with an embedded server's name replaces it
+        let zls = loader.get_lsp_for_extension("zon").unwrap();
+        assert_eq!(zls.bin.primary, "/opt/zls/bin/zls");
+        assert_eq!(
+            loader.get_lsp_by_name("zls").unwrap().bin.primary,
+            "/opt/zls/bin/zls"
+        );
+        assert_eq!(
+            loader
+                .list_available_lsps()
+                .iter()
+                .filter(|p| p.name == "zls")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_mason_cache_is_lowest_tier() {
+        let package = |name: &str, language: &str, ext: &str| LspPackage {
+            name: name.to_string(),
+            description: String::new(),
+            homepage: None,
+            licenses: Vec::new(),
+            languages: vec![language.to_string()],
+            file_extensions: vec![ext.to_string()],
+            filenames: Vec::new(),
+            filename_patterns: Vec::new(),
+            shebangs: Vec::new(),
+            source: InstallSource::External {
+                command: name.to_string(),
+            },
+            bin: BinaryConfig {
+                primary: name.to_string(),
+                additional: Vec::new(),
+                lsp_args: Vec::new(),
+                env: HashMap::new(),
+                cwd: None,
+                connection: Connection::Stdio,
+                spawn_timeout_secs: None,
+                initialize_timeout_secs: None,
+                limits: Default::default(),
+                setup: Vec::new(),
+            },
+            initialization_options: None,
+            settings: None,
+            remote: None,
+            extensions: Vec::new(),
+        };
+        let mason = [
+            package("rnix-lsp", "nix", "nix"),
+            package("nil", "nix", "nix"),
+            package("zls-fork", "zig", "zig"),
+        ]
+        .into_iter()
+        .map(|pkg| (pkg.name.clone(), pkg))
+        .collect();
+
+        let loader = ConfigLoader::from_sources(
+            get_default_configs(),
+            ConfigLoader::load_registry().unwrap(),
+            HashMap::new(),
+            mason,
+            None,
+        );
+
+        // Embedded entries win; ties within the cache go by name
+        assert_eq!(loader.get_lsp_for_extension("zig").unwrap().name, "zls");
+        assert_eq!(loader.get_lsp_for_extension("nix").unwrap().name, "nil");
+        assert_eq!(loader.get_lsp_for_language("nix").unwrap().name, "nil");
+        assert!(loader.get_lsp_by_name("rnix-lsp").is_ok());
+    }
+
+    #[test]
+    fn test_merge_json() {
+        let mut base = serde_json::json!({ "a": { "b": 1, "c": [1, 2] }, "d": "x" });
+        merge_json(
+            &mut base,
+            &serde_json::json!({ "a": { "c": [3], "e": null }, "f": true }),
+        );
+        assert_eq!(
+            base,
+            serde_json::json!({ "a": { "b": 1, "c": [3], "e": null }, "d": "x", "f": true })
+        );
+    }
+
+    #[test]
+    fn test_parse_shebang() {
+        assert_eq!(parse_shebang("#!/bin/bash").as_deref(), Some("bash"));
+        assert_eq!(parse_shebang("#! /bin/sh -e").as_deref(), Some("sh"));
+        assert_eq!(
+            parse_shebang("#!/usr/bin/env python3").as_deref(),
+            Some("python3")
+        );
+        assert_eq!(
+            parse_shebang("#!/usr/bin/env -S NODE_ENV=dev node --inspect").as_deref(),
+            Some("node")
+        );
+        assert_eq!(parse_shebang("# just a comment"), None);
+        assert_eq!(parse_shebang("#!/usr/bin/env"), None);
+
+        assert!(interpreter_matches("python3.12", "python3"));
+        assert!(interpreter_matches("python3", "python"));
+        assert!(!interpreter_matches("pythonw", "python"));
+    }
+
+    #[test]
+    fn test_get_lsp_for_file_by_name_and_shebang() {
+        let loader = ConfigLoader::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        // Exact file names and patterns take precedence over the extension
+        assert_eq!(
+            loader
+                .get_lsp_for_file(&dir.path().join("Dockerfile"))
+                .unwrap()
+                .name,
+            "dockerfile-language-server"
+        );
+        assert_eq!(
+            loader
+                .get_lsp_for_file(&dir.path().join("Dockerfile.dev"))
+                .unwrap()
+                .name,
+            "dockerfile-language-server"
+        );
+        assert_eq!(
+            loader
+                .get_lsp_for_file(&dir.path().join("main.rs"))
+                .unwrap()
+                .name,
+            "rust-analyzer"
+        );
+
+        // Extensionless scripts fall back to the #! line
+        let script = dir.path().join("deploy");
+        std::fs::write(
+            &script,
+            "#!/usr/bin/env python3
+print('hi')
+",
+        )
+        .unwrap();
+        assert_eq!(loader.get_lsp_for_file(&script).unwrap().name, "pyright");
+
+        let plain = dir.path().join("NOTES");
+        std::fs::write(&plain, "nothing to see\n").unwrap();
+        assert!(loader.get_lsp_for_file(&plain).is_err());
+    }
+
+    #[test]
+    fn test_custom_lsp_by_filename() {
+        let user_config: UserConfig = toml::from_str(
+            r#"
+            [lsp.just-lsp]
+            command = "just-lsp"
+            filenames = ["justfile", "Justfile"]
+            filename_patterns = ["*.just"]
+            "#,
+        )
+        .unwrap();
+        let loader = ConfigLoader::with_user_config(Some(user_config)).unwrap();
+
+        let just = loader.get_lsp_for_file(Path::new("/tmp/Justfile")).unwrap();
+        assert_eq!(just.name, "just-lsp");
+        assert_eq!(just.languages, vec!["just-lsp"]);
+        assert_eq!(
+            loader
+                .get_lsp_for_file(Path::new("/tmp/build.just"))
+                .unwrap()
+                .name,
+            "just-lsp"
+        );
+    }
+
+    #[test]
+    fn test_tcp_connection() {
+        let user_config: UserConfig = toml::from_str(
+            r#"
+            [lsp.godot]
+            file_extensions = ["gd"]
+            connection = { type = "tcp", port = 6005 }
+
+            [lsp.rust-analyzer]
+            connection = { type = "tcp", host = "localhost", port = 9257 }
+
+            [lsp.broken]
+            file_extensions = ["broken"]
+            "#,
+        )
+        .unwrap();
+        let loader = ConfigLoader::with_user_config(Some(user_config)).unwrap();
+
+        let godot = loader.get_lsp_by_name("godot").unwrap();
+        assert!(godot.bin.primary.is_empty());
+        assert_eq!(
+            godot.bin.connection,
+            Connection::Tcp {
+                host: "127.0.0.1".to_string(),
+                port: 6005
+            }
+        );
+
+        // Registry servers keep their command and connect once it's running
+        let rust = loader.get_lsp_by_name("rust-analyzer").unwrap();
+        assert_eq!(rust.bin.primary, "rust-analyzer");
+        assert!(!rust.bin.connection.is_stdio());
+
+        // Neither a command nor a connection: nothing to talk to
+        assert!(loader.get_lsp_by_name("broken").is_err());
+    }
+
+    #[test]
+    fn test_multiple_servers_per_language() {
+        let user_config: UserConfig = toml::from_str(
+            r#"
+            [lsp.ruff-lsp]
+            command = "ruff-lsp"
+            languages = ["ruff"]
+
+            [language_servers]
+            python = ["pyright", "ruff-lsp", "no-such-server"]
+            "#,
+        )
+        .unwrap();
+        let loader = ConfigLoader::with_user_config(Some(user_config)).unwrap();
+
+        let names = |packages: Vec<LspPackage>| -> Vec<String> {
+            packages.into_iter().map(|p| p.name).collect()
+        };
+
+        assert_eq!(
+            names(loader.get_lsps_for_language("python").unwrap()),
+            vec!["pyright", "ruff-lsp"]
+        );
+
+        let (language, packages) = loader.get_lsps_for_file(Path::new("/src/app.py")).unwrap();
+        assert_eq!(language, "python");
+        assert_eq!(names(packages), vec!["pyright", "ruff-lsp"]);
+
+        // Languages without a list keep their single server
+        assert_eq!(
+            names(loader.get_lsps_for_language("rust").unwrap()),
+            vec!["rust-analyzer"]
+        );
+
+        // A language given for a file wins over its name
+        let root = Path::new("/src");
+        let (language, packages) = loader
+            .get_lsps_for_project(Path::new("/src/build"), Some("python"), root, root)
+            .unwrap();
+        assert_eq!(language, "python");
+        assert_eq!(names(packages), vec!["pyright", "ruff-lsp"]);
+        assert!(loader
+            .get_lsps_for_project(Path::new("/src/build"), None, root, root)
+            .is_err());
+    }
 }
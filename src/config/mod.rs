@@ -1,16 +1,28 @@
 //! Configuration system for LSMCP
 //!
-//! Provides a 3-tier configuration hierarchy:
+//! Provides a 5-tier configuration hierarchy:
 //! 1. User config (highest priority)
-//! 2. Mason registry (medium priority)
-//! 3. Built-in defaults (lowest priority)
+//! 2. Local registry files (~/.config/lsmcp/registry)
+//! 3. Mason registry (medium priority)
+//! 4. Built-in defaults
+//! 5. Synced Mason registry cache (lowest priority)
 
 mod defaults;
 mod loader;
+pub mod neovim;
+mod project_config;
 mod registry;
 mod user_config;
+pub mod validate;
 
 pub use defaults::get_default_configs;
 pub use loader::ConfigLoader;
-pub use registry::{LspPackage, InstallSource, BinaryConfig};
-pub use user_config::UserConfig;
+pub use project_config::PROJECT_CONFIG_FILE;
+pub use registry::{
+    LspPackage, InstallSource, BinaryConfig, Connection, ExtensionParams, ExtensionRequest, Remote,
+    ResourceLimits,
+};
+pub use user_config::{
+    BinarySource, CommandToolConfig, EditPolicy, LocationFormat, LogRotation, LspOverride,
+    NpmRunner, Profile, Settings, ToolsConfig, UserConfig,
+};
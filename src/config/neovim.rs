@@ -0,0 +1,774 @@
+//! Importing a Neovim setup (`lsmcp config import-neovim`)
+//!
+//! Neovim users already chose their servers, and often tuned their flags
+//! and settings. Two places record that:
+//!
+//! - Mason's install receipts (`<mason>/packages/<name>/mason-receipt.json`),
+//!   which say what is installed;
+//! - a dump of the lspconfig configurations, as JSON (`vim.json.encode`) or
+//!   as a Lua table (`vim.inspect`), keyed by server name or as a list of
+//!   configs with a `name`, which says what is used and how.
+//!
+//! With a dump, its servers are imported along with their `cmd`,
+//! `settings` and `init_options`; otherwise every installed Mason package
+//! lsmcp knows as a language server is. Servers lsmcp doesn't know become
+//! custom servers when the dump gives their `cmd` and `filetypes`. The
+//! result is a `[language_servers]` table where the choice differs from
+//! lsmcp's own, and `[lsp.<name>]` entries for whatever else differs.
+
+use crate::config::{ConfigLoader, LspOverride, LspPackage};
+use crate::types::LspError;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// lspconfig names to the names lsmcp may know the server by, in order of
+/// preference; the built-in registry and Mason differ for some
+const LSPCONFIG_ALIASES: &[(&str, &[&str])] = &[
+    ("ansiblels", &["ansible-language-server"]),
+    ("astro", &["astro-language-server"]),
+    ("bashls", &["bash-language-server"]),
+    ("clojure_lsp", &["clojure-lsp"]),
+    ("cmake", &["cmake-language-server"]),
+    ("csharp_ls", &["csharp-language-server"]),
+    ("cssls", &["vscode-css-language-server", "css-lsp"]),
+    ("denols", &["deno"]),
+    ("dockerls", &["dockerfile-language-server"]),
+    ("elixirls", &["elixir-ls"]),
+    ("elmls", &["elm-language-server"]),
+    ("emmet_ls", &["emmet-ls"]),
+    ("erlangls", &["erlang-ls"]),
+    ("eslint", &["eslint-lsp"]),
+    ("graphql", &["graphql-language-service-cli"]),
+    ("hls", &["haskell-language-server"]),
+    ("html", &["vscode-html-language-server", "html-lsp"]),
+    ("jsonls", &["vscode-json-language-server", "json-lsp"]),
+    ("julials", &["julia-lsp"]),
+    ("lua_ls", &["lua-language-server"]),
+    ("nil_ls", &["nil"]),
+    ("ocamllsp", &["ocaml-lsp"]),
+    ("prismals", &["prisma-language-server"]),
+    ("pylsp", &["python-lsp-server"]),
+    ("ruby_lsp", &["ruby-lsp"]),
+    ("svelte", &["svelte-language-server"]),
+    ("tailwindcss", &["tailwindcss-language-server"]),
+    ("terraformls", &["terraform-ls"]),
+    ("ts_ls", &["typescript-language-server"]),
+    ("tsserver", &["typescript-language-server"]),
+    ("vimls", &["vim-language-server"]),
+    ("volar", &["vue-language-server"]),
+    ("vue_ls", &["vue-language-server"]),
+    ("yamlls", &["yaml-language-server"]),
+];
+
+/// Mason package names lsmcp's built-in registry knows by another name
+const MASON_ALIASES: &[(&str, &str)] = &[
+    ("css-lsp", "vscode-css-language-server"),
+    ("html-lsp", "vscode-html-language-server"),
+    ("json-lsp", "vscode-json-language-server"),
+];
+
+/// Neovim filetypes that lsmcp calls otherwise
+const FILETYPE_ALIASES: &[(&str, &str)] = &[
+    ("cs", "csharp"),
+    ("javascriptreact", "javascript"),
+    ("jsonc", "json"),
+    ("sh", "bash"),
+    ("tex", "latex"),
+    ("typescriptreact", "typescript"),
+];
+
+/// Where to read the Neovim setup from
+#[derive(Debug, Clone, Default)]
+pub struct NeovimSources {
+    /// Dump of the lspconfig configurations
+    pub lspconfig: Option<PathBuf>,
+    /// Mason install roots to read receipts from
+    pub mason: Vec<PathBuf>,
+}
+
+/// The part of a user config an import produces
+#[derive(Debug, Default, Serialize)]
+pub struct ImportedConfig {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub language_servers: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub lsp: BTreeMap<String, LspOverride>,
+}
+
+/// What an import found
+#[derive(Debug, Default)]
+pub struct NeovimImport {
+    pub config: ImportedConfig,
+    /// Servers imported, by lsmcp name, with the name Neovim used
+    pub servers: Vec<(String, String)>,
+    /// Servers left out, with why
+    pub skipped: Vec<(String, String)>,
+}
+
+impl NeovimImport {
+    /// The config as TOML to add to a config file
+    pub fn to_toml(&self) -> Result<String, LspError> {
+        toml::to_string(&self.config)
+            .map_err(|e| LspError::ConfigError(format!("Failed to write the config: {}", e)))
+    }
+}
+
+/// One server as Neovim configures it
+#[derive(Debug, Default)]
+struct NeovimServer {
+    name: String,
+    cmd: Option<Vec<String>>,
+    settings: Option<Value>,
+    init_options: Option<Value>,
+    filetypes: Vec<String>,
+}
+
+/// Import the servers of the Neovim setup in `sources`
+pub fn import(sources: &NeovimSources) -> Result<NeovimImport, LspError> {
+    let loader = ConfigLoader::with_user_config(None)?;
+    let servers = match &sources.lspconfig {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| {
+                LspError::ConfigError(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            parse_lspconfig(&text)?
+        }
+        None => mason_receipts(&sources.mason)
+            .into_iter()
+            .map(|name| NeovimServer {
+                name,
+                ..Default::default()
+            })
+            .collect(),
+    };
+    Ok(convert(&loader, servers, &sources.mason))
+}
+
+/// Names of the packages Mason installed under `roots`
+fn mason_receipts(roots: &[PathBuf]) -> Vec<String> {
+    let mut names = Vec::new();
+    for root in roots {
+        let Ok(entries) = std::fs::read_dir(root.join("packages")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let receipt = entry.path().join("mason-receipt.json");
+            let Ok(text) = std::fs::read_to_string(&receipt) else {
+                continue;
+            };
+            let name = serde_json::from_str::<Value>(&text)
+                .ok()
+                .and_then(|receipt| receipt["name"].as_str().map(str::to_string))
+                .unwrap_or_else(|| entry.file_name().to_string_lossy().into_owned());
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+/// Read a dump of lspconfig configurations, JSON or a Lua table
+fn parse_lspconfig(text: &str) -> Result<Vec<NeovimServer>, LspError> {
+    let dump = match serde_json::from_str::<Value>(text) {
+        Ok(dump) => dump,
+        Err(_) => parse_lua_table(text)?,
+    };
+
+    let configs: Vec<(Option<String>, Value)> = match dump {
+        Value::Array(configs) => configs.into_iter().map(|c| (None, c)).collect(),
+        Value::Object(configs) => configs.into_iter().map(|(k, c)| (Some(k), c)).collect(),
+        _ => {
+            return Err(LspError::ConfigError(
+                "Expected a table of lspconfig configurations".to_string(),
+            ))
+        }
+    };
+
+    let mut servers: Vec<NeovimServer> = Vec::new();
+    for (key, config) in configs {
+        let Value::Object(mut config) = config else {
+            continue;
+        };
+        let Some(name) = config
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or(key)
+        else {
+            continue;
+        };
+        let strings = |value: Option<Value>| -> Option<Vec<String>> {
+            value?
+                .as_array()?
+                .iter()
+                .map(|v| v.as_str().map(str::to_string))
+                .collect()
+        };
+        let server = NeovimServer {
+            name,
+            cmd: strings(config.remove("cmd")).filter(|cmd| !cmd.is_empty()),
+            settings: config.remove("settings").and_then(without_nulls),
+            init_options: config.remove("init_options").and_then(without_nulls),
+            filetypes: strings(config.remove("filetypes")).unwrap_or_default(),
+        };
+        // Several clients of one server, one per project root
+        if !servers.iter().any(|s| s.name == server.name) {
+            servers.push(server);
+        }
+    }
+    Ok(servers)
+}
+
+/// `value` without nulls, which TOML can't hold; `None` if nothing is left
+fn without_nulls(value: Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Array(items) => Some(Value::Array(
+            items.into_iter().filter_map(without_nulls).collect(),
+        )),
+        Value::Object(map) => {
+            let map: Map<String, Value> = map
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, without_nulls(v)?)))
+                .collect();
+            (!map.is_empty()).then_some(Value::Object(map))
+        }
+        value => Some(value),
+    }
+}
+
+/// The names lsmcp may know a server Neovim calls `name` by
+fn candidates(name: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if let Some((_, aliases)) = LSPCONFIG_ALIASES.iter().find(|(n, _)| *n == name) {
+        names.extend(aliases.iter().map(|a| a.to_string()));
+    }
+    if let Some((_, alias)) = MASON_ALIASES.iter().find(|(n, _)| *n == name) {
+        names.push(alias.to_string());
+    }
+    names.push(name.to_string());
+    names.push(name.replace('_', "-"));
+    names.dedup();
+    names
+}
+
+fn convert(loader: &ConfigLoader, servers: Vec<NeovimServer>, mason: &[PathBuf]) -> NeovimImport {
+    let mut import = NeovimImport::default();
+    // Languages of each imported server
+    let mut languages: Vec<(String, Vec<String>)> = Vec::new();
+
+    for server in servers {
+        let known = candidates(&server.name)
+            .iter()
+            .find_map(|name| loader.get_lsp_by_name(name).ok());
+
+        let (name, entry, server_languages) = match known {
+            Some(pkg) => {
+                let entry = override_for(&pkg, &server, mason);
+                (pkg.name.clone(), entry, pkg.languages.clone())
+            }
+            None => match custom_server(&server) {
+                Some((entry, server_languages)) => (server.name.clone(), entry, server_languages),
+                None => {
+                    let reason = if server.cmd.is_none() {
+                        "not a language server lsmcp knows"
+                    } else {
+                        "not a language server lsmcp knows, and no filetypes to use it for"
+                    };
+                    import.skipped.push((server.name, reason.to_string()));
+                    continue;
+                }
+            },
+        };
+
+        if import.servers.iter().any(|(n, _)| *n == name) {
+            continue;
+        }
+        if !is_empty(&entry) {
+            import.config.lsp.insert(name.clone(), entry);
+        }
+        import.servers.push((name.clone(), server.name));
+        languages.push((name, server_languages));
+    }
+
+    // Each language's servers, where they aren't lsmcp's choice already. A
+    // server alone on its language is: custom servers come first, and files
+    // no default claims go to whichever server takes their extension.
+    let mut by_language: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, server_languages) in &languages {
+        for language in server_languages {
+            by_language
+                .entry(language.clone())
+                .or_default()
+                .push(name.clone());
+        }
+    }
+    for (language, mut names) in by_language {
+        let default = loader
+            .get_lsp_for_language(&language)
+            .ok()
+            .map(|pkg| pkg.name);
+        let custom = import
+            .config
+            .lsp
+            .get(&names[0])
+            .is_some_and(|entry| entry.languages.is_some());
+        if names.len() == 1 && (default.is_none() || default.as_ref() == names.first() || custom) {
+            continue;
+        }
+        names.sort_by_key(|name| (Some(name) != default.as_ref(), name.clone()));
+        import.config.language_servers.insert(language, names);
+    }
+    import
+}
+
+/// What to override in `pkg` for it to run as Neovim runs it
+fn override_for(pkg: &LspPackage, server: &NeovimServer, mason: &[PathBuf]) -> LspOverride {
+    let mut entry = LspOverride::default();
+    if let Some((program, args)) = server.cmd.as_ref().and_then(|cmd| cmd.split_first()) {
+        // Mason's own copy of the binary is one lsmcp finds anyway
+        let in_mason = mason
+            .iter()
+            .any(|root| Path::new(program).starts_with(root))
+            && Path::new(program).file_name() == Some(pkg.bin.primary.as_ref());
+        if *program != pkg.bin.primary && !in_mason {
+            entry.command = Some(program.clone());
+        }
+        if *args != pkg.bin.lsp_args[..] {
+            entry.args = Some(args.to_vec());
+        }
+    }
+    entry.settings = server.settings.clone();
+    entry.initialization_options = server.init_options.clone();
+    entry
+}
+
+/// A custom server for one lsmcp doesn't know, with its languages
+fn custom_server(server: &NeovimServer) -> Option<(LspOverride, Vec<String>)> {
+    let (program, args) = server.cmd.as_ref()?.split_first()?;
+    let mut languages: Vec<String> = Vec::new();
+    for filetype in &server.filetypes {
+        let language = FILETYPE_ALIASES
+            .iter()
+            .find(|(ft, _)| ft == filetype)
+            .map_or(filetype.as_str(), |(_, language)| language)
+            .to_string();
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    if languages.is_empty() {
+        return None;
+    }
+
+    let entry = LspOverride {
+        languages: Some(languages.clone()),
+        command: Some(program.clone()),
+        args: (!args.is_empty()).then(|| args.to_vec()),
+        settings: server.settings.clone(),
+        initialization_options: server.init_options.clone(),
+        ..Default::default()
+    };
+    Some((entry, languages))
+}
+
+fn is_empty(entry: &LspOverride) -> bool {
+    entry.command.is_none()
+        && entry.args.is_none()
+        && entry.languages.is_none()
+        && entry.settings.is_none()
+        && entry.initialization_options.is_none()
+}
+
+/// Parse a Lua table as `vim.inspect` prints it, optionally after `return`
+///
+/// Tables with only positional entries become arrays, others objects;
+/// functions, userdata and references to tables printed elsewhere
+/// (`<function 1>`, `<table 2>`) become null.
+fn parse_lua_table(text: &str) -> Result<Value, LspError> {
+    let mut parser = LuaParser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    parser.skip_space();
+    if parser.text[parser.pos..].starts_with(b"return") {
+        parser.pos += "return".len();
+    }
+    let value = parser.value()?;
+    parser.skip_space();
+    if parser.pos < parser.text.len() {
+        return Err(parser.error("unexpected text after the table"));
+    }
+    Ok(value)
+}
+
+struct LuaParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl LuaParser<'_> {
+    fn error(&self, message: &str) -> LspError {
+        let line = self.text[..self.pos.min(self.text.len())]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1;
+        LspError::ConfigError(format!(
+            "Not JSON, nor a Lua table: line {}: {}",
+            line, message
+        ))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), LspError> {
+        self.skip_space();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Skip whitespace and comments
+    fn skip_space(&mut self) {
+        loop {
+            while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if !self.text[self.pos..].starts_with(b"--") {
+                return;
+            }
+            let rest = &self.text[self.pos + 2..];
+            self.pos += 2 + if rest.starts_with(b"[[") {
+                find(rest, b"]]").map_or(rest.len(), |end| end + 2)
+            } else {
+                find(rest, b"\n").unwrap_or(rest.len())
+            };
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, LspError> {
+        self.skip_space();
+        match self.peek() {
+            Some(b'{') => self.table(),
+            Some(b'"') | Some(b'\'') => self.string().map(Value::String),
+            Some(b'<') => {
+                self.tag();
+                self.skip_space();
+                // `<1>{...}` labels a table referred to elsewhere
+                if self.peek() == Some(b'{') {
+                    self.table()
+                } else {
+                    Ok(Value::Null)
+                }
+            }
+            Some(_) => {
+                let word = self.word();
+                match word.as_str() {
+                    "" => Err(self.error("expected a value")),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Ok(number(&word).unwrap_or(Value::Null)),
+                }
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// A `<...>` tag
+    fn tag(&mut self) -> String {
+        let start = self.pos;
+        let end = find(&self.text[start..], b">").map_or(self.text.len(), |end| start + end + 1);
+        self.pos = end;
+        String::from_utf8_lossy(&self.text[start..end]).into_owned()
+    }
+
+    /// An identifier, keyword or number
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|b| b.is_ascii_alphanumeric() || b"_.+-".contains(&b))
+        {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.text[start..self.pos]).into_owned()
+    }
+
+    fn string(&mut self) -> Result<String, LspError> {
+        let quote = self.text[self.pos];
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(byte) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            if byte == quote {
+                break;
+            }
+            if byte != b'\\' {
+                bytes.push(byte);
+                continue;
+            }
+            let Some(escaped) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match escaped {
+                b'n' => bytes.push(b'\n'),
+                b'r' => bytes.push(b'\r'),
+                b't' => bytes.push(b'\t'),
+                b'0'..=b'9' => {
+                    // `\ddd`, a decimal byte
+                    let start = self.pos - 1;
+                    while self.pos < start + 3 && self.peek().is_some_and(|b| b.is_ascii_digit()) {
+                        self.pos += 1;
+                    }
+                    let code = std::str::from_utf8(&self.text[start..self.pos])
+                        .ok()
+                        .and_then(|digits| digits.parse::<u8>().ok())
+                        .ok_or_else(|| self.error("invalid escape"))?;
+                    bytes.push(code);
+                }
+                other => bytes.push(other),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn table(&mut self) -> Result<Value, LspError> {
+        self.expect(b'{')?;
+        let mut positional: Vec<Value> = Vec::new();
+        let mut named = Map::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some(b'}') => {
+                    self.pos += 1;
+                    break;
+                }
+                None => return Err(self.error("unterminated table")),
+                _ => {}
+            }
+
+            match self.key()? {
+                Some(key) => {
+                    let value = self.value()?;
+                    // `<metatable> = {...}` isn't data
+                    if !value.is_null() && !key.starts_with('<') {
+                        named.insert(key, value);
+                    }
+                }
+                None => positional.push(self.value()?),
+            }
+
+            self.skip_space();
+            match self.peek() {
+                Some(b',') | Some(b';') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+
+        if named.is_empty() {
+            return Ok(Value::Array(positional));
+        }
+        for (i, value) in positional.into_iter().enumerate() {
+            named.insert((i + 1).to_string(), value);
+        }
+        Ok(Value::Object(named))
+    }
+
+    /// The key of a `key = value` entry, consuming the `=`; `None`, consuming
+    /// nothing, for a positional entry
+    fn key(&mut self) -> Result<Option<String>, LspError> {
+        let start = self.pos;
+        let key = match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                let key = match self.value()? {
+                    Value::String(key) => key,
+                    other => other.to_string(),
+                };
+                self.expect(b']')?;
+                key
+            }
+            Some(b'<') => self.tag(),
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' => self.word(),
+            _ => return Ok(None),
+        };
+        self.skip_space();
+        if self.peek() == Some(b'=') && self.text.get(self.pos + 1) != Some(&b'=') {
+            self.pos += 1;
+            Ok(Some(key))
+        } else {
+            self.pos = start;
+            Ok(None)
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// A Lua number as JSON; `None` for what JSON can't hold (`inf`, `nan`)
+fn number(word: &str) -> Option<Value> {
+    if let Ok(n) = word.parse::<i64>() {
+        return Some(Value::from(n));
+    }
+    let n = word.parse::<f64>().ok()?;
+    serde_json::Number::from_f64(n).map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_lua_table() {
+        let dump = r#"{ {
+            cmd = { "/home/me/.local/share/nvim/mason/bin/rust-analyzer" },
+            filetypes = { "rust" },
+            handlers = <1>{
+              ["textDocument/hover"] = <function 1>
+            },
+            name = "rust_analyzer",
+            root_dir = "/home/me/app",
+            settings = {
+              ["rust-analyzer"] = {
+                cargo = { features = "all" }, -- every feature
+                checkOnSave = true,
+                procMacro = { ignored = { leptos_macro = { "server" } } }
+              }
+            },
+            <metatable> = <table 1>
+          }, {
+            cmd = { "pyright-langserver", "--stdio" },
+            name = "pyright",
+            settings = { python = { analysis = { typeCheckingMode = "strict", maxDepth = 2.5 } } }
+          } }"#;
+        let servers = parse_lspconfig(dump).unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].name, "rust_analyzer");
+        assert_eq!(servers[0].filetypes, ["rust"]);
+        assert_eq!(
+            servers[0].settings,
+            Some(json!({"rust-analyzer": {
+                "cargo": {"features": "all"},
+                "checkOnSave": true,
+                "procMacro": {"ignored": {"leptos_macro": ["server"]}},
+            }}))
+        );
+        assert_eq!(
+            servers[1].settings.as_ref().unwrap()["python"]["analysis"]["maxDepth"],
+            json!(2.5)
+        );
+
+        // The same from JSON, keyed by name
+        let dump =
+            json!({"pyright": {"cmd": ["pyright-langserver", "--stdio"], "init_options": null}});
+        let servers = parse_lspconfig(&dump.to_string()).unwrap();
+        assert_eq!(servers[0].name, "pyright");
+        assert_eq!(servers[0].init_options, None);
+
+        assert!(parse_lua_table("{ cmd = { \"a\" ").is_err());
+        assert_eq!(
+            parse_lua_table("return { 'it\\'s', \"\\65\\t\", -1, nil }").unwrap(),
+            json!(["it's", "A\t", -1, null])
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let mason = dir.path().join("mason");
+        let bin = mason.join("bin/rust-analyzer");
+        let dump = dir.path().join("lspconfig.json");
+        std::fs::write(
+            &dump,
+            json!([
+                {"name": "rust_analyzer", "cmd": [bin], "settings": {"rust-analyzer": {"checkOnSave": false}}},
+                {"name": "pyright", "cmd": ["basedpyright-langserver", "--stdio"]},
+                {"name": "bashls", "cmd": ["bash-language-server", "start"]},
+                {"name": "my_ls", "cmd": ["my-ls", "--stdio"], "filetypes": ["typescriptreact", "ocaml"]},
+                {"name": "ts_ls", "cmd": ["typescript-language-server", "--stdio"]},
+                {"name": "copilot", "cmd": ["copilot-language-server"]},
+            ])
+            .to_string(),
+        )
+        .unwrap();
+
+        let imported = import(&NeovimSources {
+            lspconfig: Some(dump),
+            mason: vec![mason.clone()],
+        })
+        .unwrap();
+        let config = &imported.config;
+
+        // Mason's binary is found anyway; only the settings differ
+        let rust = &config.lsp["rust-analyzer"];
+        assert!(rust.command.is_none() && rust.args.is_none());
+        assert_eq!(
+            rust.settings,
+            Some(json!({"rust-analyzer": {"checkOnSave": false}}))
+        );
+        assert_eq!(
+            config.lsp["pyright"].command.as_deref(),
+            Some("basedpyright-langserver")
+        );
+        // Run as lsmcp runs it already
+        assert!(!config.lsp.contains_key("bash-language-server"));
+        assert!(imported
+            .servers
+            .contains(&("bash-language-server".to_string(), "bashls".to_string())));
+
+        let custom = &config.lsp["my_ls"];
+        assert_eq!(
+            custom.languages.as_deref(),
+            Some(&["typescript".to_string(), "ocaml".to_string()][..])
+        );
+        // Side by side with lsmcp's own choice, which comes first
+        assert_eq!(
+            config.language_servers["typescript"],
+            ["typescript-language-server", "my_ls"]
+        );
+        // lsmcp's choice already, or the only server
+        assert!(!config.language_servers.contains_key("rust"));
+        assert!(!config.language_servers.contains_key("ocaml"));
+        assert_eq!(imported.skipped[0].0, "copilot");
+
+        let toml = imported.to_toml().unwrap();
+        let parsed: crate::config::UserConfig = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.lsp["pyright"].args, None);
+        assert_eq!(parsed.language_servers["typescript"].len(), 2);
+
+        // Without a dump, what Mason installed
+        let receipt = mason.join("packages/gopls/mason-receipt.json");
+        std::fs::create_dir_all(receipt.parent().unwrap()).unwrap();
+        std::fs::write(&receipt, r#"{"name": "gopls"}"#).unwrap();
+        std::fs::create_dir_all(mason.join("packages/stylua")).unwrap();
+        let imported = import(&NeovimSources {
+            lspconfig: None,
+            mason: vec![mason],
+        })
+        .unwrap();
+        assert_eq!(
+            imported.servers,
+            [("gopls".to_string(), "gopls".to_string())]
+        );
+        assert!(imported.config.lsp.is_empty() && imported.config.language_servers.is_empty());
+    }
+}
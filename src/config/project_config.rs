@@ -0,0 +1,183 @@
+//! Per-project `.lsmcp.toml` files in monorepos
+//!
+//! A `.lsmcp.toml` in a project below the workspace root (a Cargo workspace,
+//! a pnpm package, a Python project...) may adjust the servers for that
+//! project's files: its `[lsp.<name>]` entries are merged onto the
+//! workspace's definition of each server, outermost file first. Other
+//! sections only apply in the workspace's own config and are ignored here.
+
+use crate::config::{LspOverride, UserConfig};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// File name of project configs
+pub const PROJECT_CONFIG_FILE: &str = ".lsmcp.toml";
+
+/// A file's modification time and length, to notice edits
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
+
+/// A project config as last read (empty when missing or invalid)
+struct ProjectConfig {
+    stamp: Stamp,
+    lsp: Arc<HashMap<String, LspOverride>>,
+}
+
+/// Project configs read so far, by path
+///
+/// Each file is read once and kept as first read, so a loader answers
+/// consistently until it is replaced by a reload; `changed` tells when
+/// that is due.
+#[derive(Default)]
+pub struct ProjectConfigs {
+    files: Mutex<HashMap<PathBuf, ProjectConfig>>,
+}
+
+impl ProjectConfigs {
+    /// The `[lsp]` entries that apply to `project_root`, outermost first
+    ///
+    /// Only directories below `workspace_root` are looked at, since the
+    /// workspace's own `.lsmcp.toml` is the main config.
+    pub fn overrides_for(
+        &self,
+        project_root: &Path,
+        workspace_root: &Path,
+    ) -> Vec<Arc<HashMap<String, LspOverride>>> {
+        let Ok(relative) = project_root.strip_prefix(workspace_root) else {
+            return Vec::new();
+        };
+
+        let mut dir = workspace_root.to_path_buf();
+        let mut overrides = Vec::new();
+        for component in relative.components() {
+            dir.push(component);
+            let lsp = self.read(&dir.join(PROJECT_CONFIG_FILE));
+            if !lsp.is_empty() {
+                overrides.push(lsp);
+            }
+        }
+        overrides
+    }
+
+    /// A file's `[lsp]` entries, read on first use
+    fn read(&self, path: &Path) -> Arc<HashMap<String, LspOverride>> {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(cached) = files.get(path) {
+            return Arc::clone(&cached.lsp);
+        }
+
+        let current = stamp(path);
+        let lsp = Arc::new(match current {
+            Some(_) => parse(path),
+            None => HashMap::new(),
+        });
+        files.insert(
+            path.to_path_buf(),
+            ProjectConfig {
+                stamp: current,
+                lsp: Arc::clone(&lsp),
+            },
+        );
+        lsp
+    }
+
+    /// Whether a project config read so far has been created, edited or
+    /// removed since
+    pub fn changed(&self) -> bool {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files
+            .iter()
+            .any(|(path, cached)| stamp(path) != cached.stamp)
+    }
+}
+
+fn parse(path: &Path) -> HashMap<String, LspOverride> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            return HashMap::new();
+        }
+    };
+    match UserConfig::parse(path, &content) {
+        Ok((config, unknown_keys)) => {
+            for key in unknown_keys {
+                warn!("Ignoring unknown key `{}` in {}", key, path.display());
+            }
+            if config.settings.is_some()
+                || !config.language_overrides.is_empty()
+                || !config.language_servers.is_empty()
+                || !config.path_map.is_empty()
+            {
+                warn!(
+                    "Only [lsp.<name>] entries apply in project config {}",
+                    path.display()
+                );
+            }
+            debug!("Loaded project config {}", path.display());
+            config.lsp
+        }
+        Err(e) => {
+            warn!("Ignoring project config: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_overrides_for_nested_projects() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("services/api")).unwrap();
+        fs::write(
+            root.join(PROJECT_CONFIG_FILE),
+            "[lsp.gopls]\nargs = [\"root\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("services").join(PROJECT_CONFIG_FILE),
+            "[lsp.gopls]\nargs = [\"services\"]\n",
+        )
+        .unwrap();
+        let api_config = root.join("services/api").join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &api_config,
+            "[lsp.gopls.initialization_options]\nbuildFlags = [\"-tags=api\"]\n",
+        )
+        .unwrap();
+
+        let configs = ProjectConfigs::default();
+        let overrides = configs.overrides_for(&root.join("services/api"), root);
+        // The workspace root's own file is the main config
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(
+            overrides[0]["gopls"].args.as_deref(),
+            Some(["services".to_string()].as_slice())
+        );
+        assert!(overrides[1]["gopls"].initialization_options.is_some());
+        assert!(configs.overrides_for(root, root).is_empty());
+        assert!(!configs.changed());
+
+        fs::remove_file(&api_config).unwrap();
+        assert!(configs.changed());
+        // Until a reload replaces them
+        assert_eq!(
+            configs
+                .overrides_for(&root.join("services/api"), root)
+                .len(),
+            2
+        );
+    }
+}
@@ -1,9 +1,12 @@
 //! LSP package registry types
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LspPackage {
     pub name: String,
     pub description: String,
@@ -11,12 +14,39 @@ pub struct LspPackage {
     pub licenses: Vec<String>,
     pub languages: Vec<String>,
     pub file_extensions: Vec<String>,
+    /// Exact file names handled by the server (e.g. `Dockerfile`)
+    #[serde(default)]
+    pub filenames: Vec<String>,
+    /// Glob patterns matched against file names (e.g. `Dockerfile.*`)
+    #[serde(default)]
+    pub filename_patterns: Vec<String>,
+    /// Interpreters named in the `#!` line of scripts (e.g. `bash`)
+    #[serde(default)]
+    pub shebangs: Vec<String>,
     pub source: InstallSource,
     pub bin: BinaryConfig,
     pub initialization_options: Option<serde_json::Value>,
+    /// Answers to the server's `workspace/configuration` requests, by
+    /// section, also sent with `workspace/didChangeConfiguration`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings: Option<serde_json::Value>,
+    /// Run the server on another machine or in a container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<Remote>,
+    /// Server-specific requests exposed as extra MCP tools
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<ExtensionRequest>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl LspPackage {
+    /// Whether the server's binary must exist locally, i.e. whether lsmcp
+    /// looks for it and installs it
+    pub fn needs_local_binary(&self) -> bool {
+        !self.bin.primary.is_empty() && self.remote.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum InstallSource {
     Npm {
@@ -38,6 +68,10 @@ pub enum InstallSource {
     GithubRelease {
         repo: String,
         tag: Option<String>,
+        /// Asset name pattern (`*`, `{os}`, `{arch}`, `{version}`); picked
+        /// heuristically from the platform when absent
+        #[serde(default)]
+        asset: Option<String>,
     },
     System {
         packages: HashMap<String, String>,
@@ -47,9 +81,192 @@ pub enum InstallSource {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BinaryConfig {
     pub primary: String,
     pub additional: Vec<String>,
     pub lsp_args: Vec<String>,
+    /// Extra environment variables for the server process
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Working directory for the server process; relative paths are resolved
+    /// against the project root (defaults to the project root)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// How to talk to the server (its stdio unless configured otherwise)
+    #[serde(default, skip_serializing_if = "Connection::is_stdio")]
+    pub connection: Connection,
+    /// How long a server started for a TCP connection has to start listening
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn_timeout_secs: Option<u64>,
+    /// How long the server has to answer `initialize`, counted from its
+    /// last progress report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initialize_timeout_secs: Option<u64>,
+    /// Memory and CPU limits for the server process
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_unlimited")]
+    pub limits: ResourceLimits,
+    /// Commands run in the project root before each launch, e.g. to
+    /// prepare a per-project data directory (see `lsp::launch`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<Vec<String>>,
+}
+
+/// Guardrails for a server's memory and CPU use, e.g.
+/// `{ max_memory_mb = 4096, nice = 10 }`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// Restart the server once it and its child processes use more resident
+    /// memory than this
+    pub max_memory_mb: Option<u64>,
+    /// Niceness to run the server at (up to 19), leaving CPU to other
+    /// processes when busy (Unix)
+    pub nice: Option<i32>,
+    /// Also enforce the limits in a systemd scope (`MemoryMax`,
+    /// `CPUQuota`), so the kernel stops a runaway server between checks
+    /// (Linux with a systemd user session)
+    pub cgroup: Option<bool>,
+    /// CPU the server may use in its systemd scope, in percent of one core
+    pub max_cpu_percent: Option<u32>,
+    /// Requests sent to the server at once; the rest wait their turn (0
+    /// for no limit; defaults to the `max_concurrent_requests` setting)
+    pub max_concurrent_requests: Option<usize>,
+}
+
+impl ResourceLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// These limits with every value set in `overlay` replacing ours
+    pub fn overlaid(self, overlay: &ResourceLimits) -> ResourceLimits {
+        let overlay = overlay.clone();
+        ResourceLimits {
+            max_memory_mb: overlay.max_memory_mb.or(self.max_memory_mb),
+            nice: overlay.nice.or(self.nice),
+            cgroup: overlay.cgroup.or(self.cgroup),
+            max_cpu_percent: overlay.max_cpu_percent.or(self.max_cpu_percent),
+            max_concurrent_requests: overlay
+                .max_concurrent_requests
+                .or(self.max_concurrent_requests),
+        }
+    }
+}
+
+/// Spawn timeout for servers that don't set one
+const DEFAULT_SPAWN_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Initialize timeout for servers that don't set one
+const DEFAULT_INITIALIZE_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl BinaryConfig {
+    pub fn spawn_timeout(&self) -> Duration {
+        self.spawn_timeout_secs
+            .map_or(DEFAULT_SPAWN_TIMEOUT, Duration::from_secs)
+    }
+
+    pub fn initialize_timeout(&self) -> Duration {
+        self.initialize_timeout_secs
+            .map_or(DEFAULT_INITIALIZE_TIMEOUT, Duration::from_secs)
+    }
+}
+
+/// Transport an LSP server is spoken to over
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Connection {
+    /// The stdin and stdout of the server process
+    #[default]
+    Stdio,
+    /// A TCP socket. The server command, if any, is started first and is
+    /// expected to listen on `port`; without a command, lsmcp connects to a
+    /// server that is already running (e.g. an editor's built-in server).
+    Tcp {
+        #[serde(default = "default_host")]
+        host: String,
+        port: u16,
+    },
+}
+
+impl Connection {
+    pub fn is_stdio(&self) -> bool {
+        matches!(self, Self::Stdio)
+    }
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+/// Where a server runs when not on this machine
+///
+/// The server command is run through `ssh` or `docker exec`; `path_map`
+/// maps local directories to where they are mounted remotely, and every
+/// path and `file://` URI exchanged with the server is translated with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Remote {
+    /// `ssh <options> <host> <command>`
+    Ssh {
+        host: String,
+        #[serde(default)]
+        options: Vec<String>,
+        #[serde(default)]
+        path_map: BTreeMap<PathBuf, PathBuf>,
+    },
+    /// `docker exec -i <options> <container> <command>`
+    Docker {
+        container: String,
+        #[serde(default)]
+        options: Vec<String>,
+        #[serde(default)]
+        path_map: BTreeMap<PathBuf, PathBuf>,
+    },
+}
+
+impl Remote {
+    /// Local directories and where they are mounted remotely
+    pub fn path_map(&self) -> &BTreeMap<PathBuf, PathBuf> {
+        match self {
+            Self::Ssh { path_map, .. } | Self::Docker { path_map, .. } => path_map,
+        }
+    }
+}
+
+impl std::fmt::Display for Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ssh { host, .. } => write!(f, "ssh {}", host),
+            Self::Docker { container, .. } => write!(f, "docker {}", container),
+        }
+    }
+}
+
+/// A request outside the LSP spec that a server understands, exposed as an
+/// MCP tool (e.g. rust-analyzer's `rust-analyzer/expandMacro`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtensionRequest {
+    /// MCP tool name, e.g. `lsp_rust_expand_macro`
+    pub tool: String,
+    /// JSON-RPC method sent to the server
+    pub method: String,
+    /// Tool description shown to MCP clients
+    pub description: String,
+    #[serde(default)]
+    pub params: ExtensionParams,
+}
+
+/// The params an extension request takes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtensionParams {
+    /// `{ textDocument, position }`
+    #[default]
+    Position,
+    /// `{ textDocument }`
+    Document,
+    /// A bare `{ uri }` text document identifier
+    Uri,
 }
@@ -1,26 +1,696 @@
 //! User configuration file parsing
 
+use crate::config::{Connection, Remote, ResourceLimits};
+use crate::types::LspError;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// lsmcp configuration (`.lsmcp.toml` or `~/.config/lsmcp/config.toml`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct UserConfig {
     pub settings: Option<Settings>,
+    /// Overrides for known servers and definitions of custom servers
+    #[serde(default)]
     pub lsp: HashMap<String, LspOverride>,
+    /// Server to use for a language instead of the default
+    #[serde(default)]
     pub language_overrides: HashMap<String, String>,
+    /// Servers to run side by side per language, highest priority first
+    #[serde(default)]
+    pub language_servers: HashMap<String, Vec<String>>,
+    /// Directories as servers report them, mapped to where the MCP client
+    /// sees them (e.g. a container mount or a symlink-resolved workspace)
+    #[serde(default)]
+    pub path_map: BTreeMap<PathBuf, PathBuf>,
+    /// Extra tools that run a command, by tool name
+    #[serde(default)]
+    pub command_tools: BTreeMap<String, CommandToolConfig>,
+    /// Which tools are offered, and under what names
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// Named presets over the rest of the file, selected with `--profile`
+    /// or `$LSMCP_PROFILE`, e.g. `[profile.ci]`
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A `[profile.<name>]`: what changes when the profile is selected
+///
+/// `settings` replace the `[settings]` values they set, `tools` adds to
+/// `[tools]` (its `enabled`, `prefix` and renames win), and each language in
+/// `language_servers` or `language_overrides` replaces that language's entry.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct Profile {
+    pub settings: Option<Settings>,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    #[serde(default)]
+    pub language_overrides: HashMap<String, String>,
+    #[serde(default)]
+    pub language_servers: HashMap<String, Vec<String>>,
+}
+
+/// Global `[settings]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Settings {
     pub workspace_root: Option<String>,
     pub log_level: Option<String>,
+    /// Per-module log levels, e.g. `lsp::client=debug,mcp=info`
+    pub log_filter: Option<String>,
+    /// When to start a new log file (default `size`)
+    pub log_rotation: Option<LogRotation>,
+    /// Size in megabytes at which `size` rotation starts a new log file
+    /// (default 10)
+    pub log_max_size_mb: Option<u64>,
+    /// Rotated log files kept (default 5)
+    pub log_max_files: Option<usize>,
     pub auto_install: Option<bool>,
+    /// Maximum number of concurrently running language servers
+    pub max_servers: Option<usize>,
+    /// Documents each server keeps open, closing the least recently used
+    /// beyond that (default 200, 0 for no limit)
+    pub max_open_documents: Option<usize>,
+    /// Requests each server is sent at once, the rest queueing (default 8,
+    /// 0 for no limit); a server's `limits.max_concurrent_requests` wins
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests in a row a server may leave unanswered (timed out or
+    /// crashed) before its requests fail at once for a cooldown (default 3,
+    /// 0 to never)
+    pub circuit_breaker_failures: Option<u32>,
+    /// Seconds requests to such a server fail at once before one tries it
+    /// again (default 30)
+    pub circuit_breaker_cooldown_secs: Option<u64>,
+    /// Shut down language servers unused for this many minutes
+    pub idle_timeout_minutes: Option<u64>,
+    /// Exit when the MCP client sends nothing over stdio for this many
+    /// minutes (0 or unset never exits)
+    pub exit_after_idle_minutes: Option<u64>,
+    /// Check every message sent to the MCP client is a JSON-RPC frame,
+    /// logging anything else (and writing it to stderr on stdio) instead of
+    /// sending it (default true)
+    pub strict_stdio: Option<bool>,
+    /// Languages whose servers are started eagerly at launch
+    pub preload: Option<Vec<String>>,
+    /// Globs of files opened at launch so servers that only diagnose open
+    /// files report on them, as `lsp_prepare_workspace` does
+    pub prepare_workspace: Option<Vec<String>>,
+    /// Keep rust-analyzer's, gopls' and clangd's caches under lsmcp's data
+    /// directory so later sessions start warm (default true)
+    pub warm_caches: Option<bool>,
+    /// Run gopls with `-remote=auto`, so every session and project shares
+    /// one gopls daemon (default true)
+    pub share_gopls_daemon: Option<bool>,
+    /// Save tool usage to the data directory for `lsmcp stats` and
+    /// `lsp_usage_stats` (default true)
+    pub usage_stats: Option<bool>,
+    /// Never download during installs; only cached artifacts are used
+    pub offline: Option<bool>,
+    /// HTTP(S) proxy used for downloads and package managers
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass the proxy
+    pub no_proxy: Option<String>,
+    /// Directory for downloaded artifacts (defaults to the data directory)
+    pub cache_dir: Option<PathBuf>,
+    /// Refresh a synced Mason registry cache older than this many hours
+    /// in the background while serving (0 disables)
+    pub registry_refresh_hours: Option<u64>,
+    /// Whether tool positions count lines and columns from 0 (default) or 1
+    pub index_base: Option<u32>,
+    /// How tool output shows locations: `editor` (absolute
+    /// `path:line:column`, the default), `relative` to the workspace root,
+    /// or `uri`; tools' `location_format` argument overrides it per call
+    pub location_format: Option<LocationFormat>,
+    /// Characters a tool's answer may take before it is cut down to fit,
+    /// with a note on what was left out (default 50,000, 0 for no limit);
+    /// tools' `max_response_chars` argument overrides it per call
+    pub max_response_chars: Option<usize>,
+    /// The same budget in tokens, counted as four characters each; when
+    /// both are set the smaller one applies
+    pub max_response_tokens: Option<usize>,
+    /// Wait up to this many seconds for a newly started server to finish
+    /// indexing before querying it (default 30, 0 disables)
+    pub indexing_timeout_secs: Option<u64>,
+    /// Keep hover, definition and document symbol answers for this many
+    /// seconds while the document is unchanged (default 30, 0 disables)
+    pub response_cache_ttl_secs: Option<u64>,
+    /// Show the author, date and commit of the last change to each line in
+    /// reference and definition results, from `git blame` (default false,
+    /// as it runs git on every file in the answer)
+    pub git_blame: Option<bool>,
+    /// Let `lsp_run_runnable` run the test and binary commands servers
+    /// report (default false)
+    pub allow_run: Option<bool>,
+    /// Offer `lsp_raw_request`, which sends any method to a server
+    /// (default false)
+    pub allow_raw_requests: Option<bool>,
+    /// Save the open documents and active languages, and restore them on
+    /// the next start (default true)
+    pub persist_session: Option<bool>,
+    /// Serve Prometheus metrics at `http://<address>/metrics`, e.g.
+    /// `127.0.0.1:9464` (off by default)
+    pub metrics_address: Option<String>,
+    /// Reject tool calls on files outside the workspace and `allowed_paths`
+    /// (default true)
+    pub restrict_paths: Option<bool>,
+    /// Directories outside the workspace tools may use (`~/` is expanded)
+    pub allowed_paths: Option<Vec<String>>,
+    /// Let tools use dependency sources outside the workspace, such as the
+    /// cargo registry or `node_modules` (default true)
+    pub allow_dependency_paths: Option<bool>,
+    /// Whether tools that change files show a diff to confirm with
+    /// `lsp_confirm_edit` (`preview`, the default) or write at once
+    /// (`apply`); tools' `dry_run` argument overrides it per call
+    pub edit_policy: Option<EditPolicy>,
+    /// Where to look for servers without a configured `command`, in order
+    /// (default: lsmcp, mason, path, homebrew, volta, nvm, vscode); sources
+    /// left out aren't searched
+    pub binary_search_order: Option<Vec<BinarySource>>,
+    /// Whether npm-sourced servers that aren't installed run through `npx`
+    /// or `bunx`: `auto` (the default) when installing them fails, `npx` or
+    /// `bunx` instead of installing them, `off` never
+    pub npm_runner: Option<NpmRunner>,
+}
+
+/// When the log file is rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    /// Once it reaches `log_max_size_mb`, and at startup
+    #[default]
+    Size,
+    /// Every hour
+    Hourly,
+    /// Every day
+    Daily,
+    /// Never; each start replaces it
+    Never,
+}
+
+/// What tools that change files do with the change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EditPolicy {
+    /// Return a diff and a token for `lsp_confirm_edit`
+    #[default]
+    Preview,
+    /// Write the change immediately
+    Apply,
+}
+
+/// How tool output shows locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationFormat {
+    /// Absolute `path:line:column`, as editors and compilers print it
+    #[default]
+    Editor,
+    /// `path:line:column` relative to the workspace root, for files inside it
+    Relative,
+    /// `file://` URIs, with positions as `#L<line>C<column>`
+    Uri,
+}
+
+/// Where a server binary can be found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BinarySource {
+    /// Servers installed by lsmcp
+    Lsmcp,
+    /// Neovim's Mason (`$MASON`, or `nvim/mason` in the data directory)
+    Mason,
+    /// Directories on `PATH`
+    Path,
+    /// Homebrew's `bin` (`$HOMEBREW_PREFIX`, `/opt/homebrew`, `/usr/local`)
+    Homebrew,
+    /// Volta's shims and packages (`$VOLTA_HOME` or `~/.volta`)
+    Volta,
+    /// npm globals of nvm's Node versions, newest first
+    Nvm,
+    /// Servers bundled with VS Code (and Cursor) extensions
+    Vscode,
+}
+
+/// How npm-sourced servers run without being installed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NpmRunner {
+    /// Install them; if that fails, run them with whichever of `npx` and
+    /// `bunx` is on `PATH`
+    #[default]
+    Auto,
+    /// Always run them with `npx --yes` instead of installing them
+    Npx,
+    /// Always run them with `bunx` instead of installing them
+    Bunx,
+    /// Only ever install them
+    Off,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Settings {
+    /// These settings with every value set in `overlay` replacing ours
+    pub fn overlaid(self, overlay: &Settings) -> Settings {
+        let overlay = overlay.clone();
+        Settings {
+            workspace_root: overlay.workspace_root.or(self.workspace_root),
+            log_level: overlay.log_level.or(self.log_level),
+            log_filter: overlay.log_filter.or(self.log_filter),
+            log_rotation: overlay.log_rotation.or(self.log_rotation),
+            log_max_size_mb: overlay.log_max_size_mb.or(self.log_max_size_mb),
+            log_max_files: overlay.log_max_files.or(self.log_max_files),
+            auto_install: overlay.auto_install.or(self.auto_install),
+            max_servers: overlay.max_servers.or(self.max_servers),
+            max_open_documents: overlay.max_open_documents.or(self.max_open_documents),
+            max_concurrent_requests: overlay
+                .max_concurrent_requests
+                .or(self.max_concurrent_requests),
+            circuit_breaker_failures: overlay
+                .circuit_breaker_failures
+                .or(self.circuit_breaker_failures),
+            circuit_breaker_cooldown_secs: overlay
+                .circuit_breaker_cooldown_secs
+                .or(self.circuit_breaker_cooldown_secs),
+            idle_timeout_minutes: overlay.idle_timeout_minutes.or(self.idle_timeout_minutes),
+            exit_after_idle_minutes: overlay
+                .exit_after_idle_minutes
+                .or(self.exit_after_idle_minutes),
+            strict_stdio: overlay.strict_stdio.or(self.strict_stdio),
+            preload: overlay.preload.or(self.preload),
+            prepare_workspace: overlay.prepare_workspace.or(self.prepare_workspace),
+            warm_caches: overlay.warm_caches.or(self.warm_caches),
+            share_gopls_daemon: overlay.share_gopls_daemon.or(self.share_gopls_daemon),
+            usage_stats: overlay.usage_stats.or(self.usage_stats),
+            offline: overlay.offline.or(self.offline),
+            proxy: overlay.proxy.or(self.proxy),
+            no_proxy: overlay.no_proxy.or(self.no_proxy),
+            cache_dir: overlay.cache_dir.or(self.cache_dir),
+            registry_refresh_hours: overlay
+                .registry_refresh_hours
+                .or(self.registry_refresh_hours),
+            index_base: overlay.index_base.or(self.index_base),
+            location_format: overlay.location_format.or(self.location_format),
+            max_response_chars: overlay.max_response_chars.or(self.max_response_chars),
+            max_response_tokens: overlay.max_response_tokens.or(self.max_response_tokens),
+            indexing_timeout_secs: overlay.indexing_timeout_secs.or(self.indexing_timeout_secs),
+            response_cache_ttl_secs: overlay
+                .response_cache_ttl_secs
+                .or(self.response_cache_ttl_secs),
+            git_blame: overlay.git_blame.or(self.git_blame),
+            allow_run: overlay.allow_run.or(self.allow_run),
+            allow_raw_requests: overlay.allow_raw_requests.or(self.allow_raw_requests),
+            persist_session: overlay.persist_session.or(self.persist_session),
+            metrics_address: overlay.metrics_address.or(self.metrics_address),
+            restrict_paths: overlay.restrict_paths.or(self.restrict_paths),
+            allowed_paths: overlay.allowed_paths.or(self.allowed_paths),
+            allow_dependency_paths: overlay
+                .allow_dependency_paths
+                .or(self.allow_dependency_paths),
+            edit_policy: overlay.edit_policy.or(self.edit_policy),
+            binary_search_order: overlay.binary_search_order.or(self.binary_search_order),
+            npm_runner: overlay.npm_runner.or(self.npm_runner),
+        }
+    }
+}
+
+/// A `[lsp.<name>]` entry
+///
+/// Entries whose name matches a known server override it; any other entry
+/// defines a custom server, which needs a `command` and something to match
+/// files by: `languages`, `file_extensions`, `filenames`, `filename_patterns`
+/// or `shebangs`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct LspOverride {
     pub enabled: Option<bool>,
+    pub languages: Option<Vec<String>>,
+    pub file_extensions: Option<Vec<String>>,
+    pub filenames: Option<Vec<String>>,
+    pub filename_patterns: Option<Vec<String>>,
+    pub shebangs: Option<Vec<String>>,
     pub command: Option<String>,
     pub args: Option<Vec<String>>,
+    /// Environment variables, merged over the server's own
+    pub env: Option<HashMap<String, String>>,
+    /// Working directory, relative to the project root unless absolute
+    pub cwd: Option<PathBuf>,
+    /// Talk to the server over TCP instead of its stdio, e.g.
+    /// `{ type = "tcp", port = 6005 }`
+    pub connection: Option<Connection>,
+    /// Run the server over SSH or in a container, e.g.
+    /// `{ type = "docker", container = "dev", path_map = { "/home/me/app" = "/workspaces/app" } }`
+    pub remote: Option<Remote>,
+    /// Seconds a server started for a TCP `connection` has to start
+    /// listening (default 20)
+    pub spawn_timeout_secs: Option<u64>,
+    /// Seconds the server has to answer `initialize` (default 30), counted
+    /// from its last `$/progress` report
+    pub initialize_timeout_secs: Option<u64>,
+    /// Memory and CPU guardrails, e.g. `{ max_memory_mb = 4096, nice = 10 }`
+    pub limits: Option<ResourceLimits>,
+    /// Commands run in the project root before each launch, with the same
+    /// placeholders as `args`
+    pub setup: Option<Vec<Vec<String>>>,
+    /// Sent with `initialize`, deep-merged over the server's own; strings
+    /// may use the same placeholders as `args`, and `${userHome}` and
+    /// `${env:NAME}`
     pub initialization_options: Option<serde_json::Value>,
+    /// Answers to the server's `workspace/configuration` requests, e.g.
+    /// `{ python = { analysis = { extraPaths = ["${workspaceRoot}/lib"] } } }`;
+    /// deep-merged and templated like `initialization_options`
+    pub settings: Option<serde_json::Value>,
+}
+
+/// A `[command_tools.<name>]` entry: a tool that runs a command in the
+/// workspace and returns its output, e.g. a linter or code generator
+///
+/// `${workspace}` in the command is replaced by the workspace root and
+/// `${file}` by the call's `file` argument; the call's `args` are appended.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CommandToolConfig {
+    /// What the tool does, as agents are told
+    pub description: String,
+    /// Program and arguments, e.g. `["cargo", "deny", "check"]`
+    pub command: Vec<String>,
+    /// Working directory, relative to the workspace root unless absolute
+    pub cwd: Option<PathBuf>,
+    /// Environment variables for the command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Seconds before the command is killed (default 300)
+    pub timeout_secs: Option<u64>,
+    /// Whether the command only reads, so clients may call it without
+    /// asking (default false)
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// `[tools]`: trimming and renaming the tools offered
+///
+/// Names in `enabled`, `disabled` and `rename` are the tools' own
+/// (`lsp_hover`, a command tool's key), whatever they are offered as.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct ToolsConfig {
+    /// Offer only these tools (default: all of them)
+    pub enabled: Option<Vec<String>>,
+    /// Tools not to offer
+    #[serde(default)]
+    pub disabled: Vec<String>,
+    /// Put before every tool name, e.g. `code_` for `code_lsp_hover`, to
+    /// avoid clashes with other MCP servers' tools
+    pub prefix: Option<String>,
+    /// Names to offer tools as instead, e.g. `{ lsp_hover = "hover" }`;
+    /// `prefix` isn't added to these
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+}
+
+impl ToolsConfig {
+    /// Whether the tool called `name` is offered
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled
+            .as_ref()
+            .is_none_or(|enabled| enabled.iter().any(|tool| tool == name))
+            && !self.disabled.iter().any(|tool| tool == name)
+    }
+
+    /// This config with `overlay`'s on top: its `enabled`, `prefix` and
+    /// renames replace ours, and its `disabled` tools are added to ours
+    pub fn overlaid(mut self, overlay: &ToolsConfig) -> ToolsConfig {
+        let overlay = overlay.clone();
+        self.disabled.extend(overlay.disabled);
+        self.rename.extend(overlay.rename);
+        ToolsConfig {
+            enabled: overlay.enabled.or(self.enabled),
+            disabled: self.disabled,
+            prefix: overlay.prefix.or(self.prefix),
+            rename: self.rename,
+        }
+    }
+
+    /// The name the tool called `name` is offered as
+    pub fn exposed_name(&self, name: &str) -> String {
+        match (self.rename.get(name), &self.prefix) {
+            (Some(renamed), _) => renamed.clone(),
+            (None, Some(prefix)) => format!("{}{}", prefix, name),
+            (None, None) => name.to_string(),
+        }
+    }
+}
+
+impl UserConfig {
+    /// Parse a config file's contents
+    ///
+    /// Returns the config along with the dotted paths of any keys lsmcp
+    /// doesn't know (e.g. `settings.max_server`), which are otherwise ignored.
+    /// Parse errors name the file, line and column of the problem.
+    pub fn parse(path: &Path, content: &str) -> Result<(Self, Vec<String>), LspError> {
+        let mut unknown_keys = Vec::new();
+        let config = serde_ignored::deserialize(toml::Deserializer::new(content), |key| {
+            unknown_keys.push(dotted_key(&key))
+        })
+        .map_err(|e| LspError::ConfigError(describe_toml_error(path, content, &e)))?;
+
+        Ok((config, unknown_keys))
+    }
+
+    /// This config as the profile called `name` changes it
+    pub fn with_profile(mut self, name: &str) -> Result<Self, LspError> {
+        let Some(profile) = self.profile.get(name).cloned() else {
+            let known: Vec<&str> = self.profile.keys().map(String::as_str).collect();
+            return Err(LspError::ConfigError(if known.is_empty() {
+                format!(
+                    "Profile '{}' not found; the config has no [profile.<name>] tables",
+                    name
+                )
+            } else {
+                format!(
+                    "Profile '{}' not found; the config has {}",
+                    name,
+                    known.join(", ")
+                )
+            }));
+        };
+
+        if let Some(settings) = &profile.settings {
+            self.settings = Some(self.settings.unwrap_or_default().overlaid(settings));
+        }
+        self.tools = self.tools.overlaid(&profile.tools);
+        self.language_overrides.extend(profile.language_overrides);
+        self.language_servers.extend(profile.language_servers);
+        Ok(self)
+    }
+
+    /// This config with the sandbox settings (`restrict_paths`,
+    /// `allowed_paths`, `allow_dependency_paths`, `allow_run`,
+    /// `allow_raw_requests`) and `[command_tools]` taken from `trusted`
+    ///
+    /// For a project's own `.lsmcp.toml`: a cloned repository may tune its
+    /// servers, but not loosen what tools may touch or run. Also returns the
+    /// keys this config set that were replaced.
+    pub fn with_sandbox_from(mut self, trusted: Option<&UserConfig>) -> (Self, Vec<&'static str>) {
+        let trusted_settings = trusted
+            .and_then(|config| config.settings.clone())
+            .unwrap_or_default();
+        let mut replaced = Vec::new();
+
+        let settings = self.settings.get_or_insert_with(Settings::default);
+        let replaced_settings = [
+            ("settings.restrict_paths", settings.restrict_paths.is_some()),
+            ("settings.allowed_paths", settings.allowed_paths.is_some()),
+            (
+                "settings.allow_dependency_paths",
+                settings.allow_dependency_paths.is_some(),
+            ),
+            ("settings.allow_run", settings.allow_run.is_some()),
+            (
+                "settings.allow_raw_requests",
+                settings.allow_raw_requests.is_some(),
+            ),
+        ];
+        replaced.extend(
+            replaced_settings
+                .into_iter()
+                .filter_map(|(key, set)| set.then_some(key)),
+        );
+        settings.restrict_paths = trusted_settings.restrict_paths;
+        settings.allowed_paths = trusted_settings.allowed_paths;
+        settings.allow_dependency_paths = trusted_settings.allow_dependency_paths;
+        settings.allow_run = trusted_settings.allow_run;
+        settings.allow_raw_requests = trusted_settings.allow_raw_requests;
+
+        if !self.command_tools.is_empty() {
+            replaced.push("command_tools");
+        }
+        self.command_tools = trusted
+            .map(|config| config.command_tools.clone())
+            .unwrap_or_default();
+        (self, replaced)
+    }
+
+    /// JSON Schema for the config file, for editor validation
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(UserConfig);
+        serde_json::to_string_pretty(&schema).expect("schema serializes to JSON")
+    }
+}
+
+/// `settings.max_server` style path for an ignored key
+fn dotted_key(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", dotted_key(parent), index),
+        Path::Map { parent, key } => match dotted_key(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => dotted_key(parent),
+    }
+}
+
+/// Render a TOML error as `path:line:column: message` plus the offending line
+fn describe_toml_error(path: &Path, content: &str, error: &toml::de::Error) -> String {
+    let Some(span) = error.span() else {
+        return format!("{}: {}", path.display(), error.message());
+    };
+
+    let before = &content[..span.start.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    let source_line = content[line_start..].lines().next().unwrap_or_default();
+
+    format!(
+        "{}:{}:{}: {}\n  | {}\n  | {}^",
+        path.display(),
+        line,
+        column,
+        error.message().trim(),
+        source_line,
+        " ".repeat(column - 1)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reports_unknown_keys_and_locations() {
+        let path = Path::new(".lsmcp.toml");
+        let (config, unknown) = UserConfig::parse(
+            path,
+            "[settings]\nmax_server = 3\nlog_level = \"debug\"\n\n[lsp.pyright]\nenable = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.settings.unwrap().log_level.as_deref(), Some("debug"));
+        assert_eq!(unknown, vec!["settings.max_server", "lsp.pyright.enable"]);
+
+        let err = UserConfig::parse(path, "[settings]\nmax_servers = \"four\"\n").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains(".lsmcp.toml:2:15:"), "{}", message);
+        assert!(message.contains("max_servers = \"four\""), "{}", message);
+    }
+
+    #[test]
+    fn test_with_sandbox_from() {
+        let path = Path::new(".lsmcp.toml");
+        let (project, _) = UserConfig::parse(
+            path,
+            "[settings]\nrestrict_paths = false\nallow_run = true\nlog_level = \"debug\"\n\n[command_tools.pwn]\ncommand = [\"sh\"]\ndescription = \"x\"\n",
+        )
+        .unwrap();
+        let (global, _) =
+            UserConfig::parse(path, "[settings]\nallow_raw_requests = true\n").unwrap();
+
+        let (config, replaced) = project.with_sandbox_from(Some(&global));
+        assert_eq!(
+            replaced,
+            vec![
+                "settings.restrict_paths",
+                "settings.allow_run",
+                "command_tools"
+            ]
+        );
+        let settings = config.settings.unwrap();
+        assert_eq!(settings.restrict_paths, None);
+        assert_eq!(settings.allow_run, None);
+        assert_eq!(settings.allow_raw_requests, Some(true));
+        assert_eq!(settings.log_level.as_deref(), Some("debug"));
+        assert!(config.command_tools.is_empty());
+    }
+
+    #[test]
+    fn test_tools_config() {
+        let (config, _) = UserConfig::parse(
+            Path::new(".lsmcp.toml"),
+            "[tools]\nenabled = [\"lsp_hover\", \"lsp_rename\"]\ndisabled = [\"lsp_rename\"]\nprefix = \"code_\"\nrename = { lsp_hover = \"hover\" }\n",
+        )
+        .unwrap();
+        let tools = config.tools;
+        assert!(tools.is_enabled("lsp_hover"));
+        assert!(!tools.is_enabled("lsp_rename"));
+        assert!(!tools.is_enabled("lsp_diagnostics"));
+        assert_eq!(tools.exposed_name("lsp_hover"), "hover");
+        assert_eq!(tools.exposed_name("lsp_rename"), "code_lsp_rename");
+    }
+
+    #[test]
+    fn test_with_profile() {
+        let (config, unknown) = UserConfig::parse(
+            Path::new(".lsmcp.toml"),
+            r#"
+            [settings]
+            log_level = "debug"
+            max_response_tokens = 8000
+
+            [tools]
+            disabled = ["lsp_rename"]
+
+            [language_servers]
+            python = ["pyright", "ruff"]
+            rust = ["rust-analyzer"]
+
+            [profile.ci.settings]
+            max_response_tokens = 2000
+            indexing_timeout_secs = 600
+
+            [profile.ci.tools]
+            enabled = ["lsp_diagnostics", "lsp_hover"]
+            disabled = ["lsp_hover"]
+
+            [profile.ci.language_servers]
+            python = ["ruff"]
+            "#,
+        )
+        .unwrap();
+        assert!(unknown.is_empty(), "{:?}", unknown);
+
+        let ci = config.clone().with_profile("ci").unwrap();
+        let settings = ci.settings.unwrap();
+        assert_eq!(settings.max_response_tokens, Some(2000));
+        assert_eq!(settings.indexing_timeout_secs, Some(600));
+        assert_eq!(settings.log_level.as_deref(), Some("debug"));
+        assert!(ci.tools.is_enabled("lsp_diagnostics"));
+        assert!(!ci.tools.is_enabled("lsp_hover"));
+        assert!(!ci.tools.is_enabled("lsp_find_references"));
+        assert_eq!(ci.language_servers["python"], ["ruff"]);
+        assert_eq!(ci.language_servers["rust"], ["rust-analyzer"]);
+
+        let message = config.with_profile("nightly").unwrap_err().to_string();
+        assert!(
+            message.contains("'nightly' not found; the config has ci"),
+            "{}",
+            message
+        );
+    }
 }
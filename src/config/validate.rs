@@ -0,0 +1,550 @@
+//! Static checks for a user config file (`lsmcp config validate`)
+
+use crate::config::{ConfigLoader, InstallSource, LspPackage, UserConfig};
+use crate::installer::ServerInstaller;
+use crate::types::LspError;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Problems found in one config file
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub path: PathBuf,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .count()
+    }
+
+    fn error(&mut self, message: String) {
+        self.issues.push(Issue {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.issues.push(Issue {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// Check a config file for syntax errors, unknown keys, broken or
+/// conflicting server references and missing binaries
+///
+/// Only failing to read the file is an `Err`; everything else is reported.
+pub fn validate_file(path: &Path) -> Result<ValidationReport, LspError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| LspError::ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let mut report = ValidationReport {
+        path: path.to_path_buf(),
+        issues: Vec::new(),
+    };
+
+    let (config, unknown_keys) = match UserConfig::parse(path, &content) {
+        Ok(parsed) => parsed,
+        Err(LspError::ConfigError(message)) => {
+            report.error(message);
+            return Ok(report);
+        }
+        Err(e) => return Err(e),
+    };
+
+    for key in unknown_keys {
+        report.warning(format!("unknown key `{}` is ignored", key));
+    }
+
+    let loader = ConfigLoader::with_user_config(Some(config.clone()))?;
+    check_settings(&config, &mut report);
+    check_path_map(&config, &mut report);
+    check_command_tools(&config, &mut report);
+    check_tools(&config, &mut report);
+    check_servers(&config, &loader, &mut report);
+    let referenced = check_languages(&config, &loader, &mut report);
+    check_binaries(&referenced, &mut report);
+    for name in config.profile.keys() {
+        check_profile(&config, name, &mut report)?;
+    }
+
+    Ok(report)
+}
+
+/// The config as a profile changes it; only what the profile brings in is
+/// reported, under its name
+fn check_profile(
+    config: &UserConfig,
+    name: &str,
+    report: &mut ValidationReport,
+) -> Result<(), LspError> {
+    let config = config.clone().with_profile(name)?;
+    let loader = ConfigLoader::with_user_config(Some(config.clone()))?;
+    let mut profiled = ValidationReport {
+        path: report.path.clone(),
+        issues: Vec::new(),
+    };
+    check_settings(&config, &mut profiled);
+    check_tools(&config, &mut profiled);
+    let referenced = check_languages(&config, &loader, &mut profiled);
+    check_binaries(&referenced, &mut profiled);
+
+    for issue in profiled.issues {
+        if !report.issues.iter().any(|i| i.message == issue.message) {
+            report.issues.push(Issue {
+                severity: issue.severity,
+                message: format!("profile.{}: {}", name, issue.message),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_settings(config: &UserConfig, report: &mut ValidationReport) {
+    let Some(settings) = &config.settings else {
+        return;
+    };
+
+    if let Some(level) = &settings.log_level {
+        if !LOG_LEVELS.contains(&level.to_lowercase().as_str()) {
+            report.warning(format!(
+                "settings.log_level `{}` is not one of {}",
+                level,
+                LOG_LEVELS.join(", ")
+            ));
+        }
+    }
+
+    if let Some(filter) = &settings.log_filter {
+        for directive in crate::utils::logging::module_directives(filter) {
+            if directive
+                .parse::<tracing_subscriber::filter::Directive>()
+                .is_err()
+            {
+                report.warning(format!(
+                    "settings.log_filter directive `{}` is invalid",
+                    directive
+                ));
+            }
+        }
+    }
+
+    if settings.max_servers == Some(0) {
+        report.warning("settings.max_servers = 0 is treated as 1".to_string());
+    }
+
+    if let Some(base) = settings.index_base.filter(|base| *base > 1) {
+        report.error(format!("settings.index_base must be 0 or 1, not {}", base));
+    }
+
+    if let Some(root) = &settings.workspace_root {
+        if !Path::new(root).is_dir() {
+            report.warning(format!(
+                "settings.workspace_root `{}` is not a directory",
+                root
+            ));
+        }
+    }
+}
+
+/// `[path_map]` entries, which must be absolute to be matched
+fn check_path_map(config: &UserConfig, report: &mut ValidationReport) {
+    for (from, to) in &config.path_map {
+        if !from.is_absolute() || !to.is_absolute() {
+            report.error(format!(
+                "path_map: `{}` = `{}` is ignored; both paths must be absolute",
+                from.display(),
+                to.display()
+            ));
+        }
+    }
+}
+
+/// `[command_tools.<name>]` entries, which need a command and a name
+/// clients accept
+fn check_command_tools(config: &UserConfig, report: &mut ValidationReport) {
+    for (name, tool) in &config.command_tools {
+        if !valid_tool_name(name) {
+            report.error(format!(
+                "command_tools.{}: tool names may only use letters, digits, `_` and `-`",
+                name
+            ));
+        }
+        if tool
+            .command
+            .first()
+            .is_none_or(|program| program.is_empty())
+        {
+            report.error(format!("command_tools.{}: `command` is empty", name));
+        }
+    }
+}
+
+/// `[tools]`: names clients accept, and no two tools offered as one
+fn check_tools(config: &UserConfig, report: &mut ValidationReport) {
+    let tools = &config.tools;
+    if let Some(prefix) = &tools.prefix {
+        if !prefix.is_empty() && !valid_tool_name(prefix) {
+            report.error(format!(
+                "tools.prefix `{}` may only use letters, digits, `_` and `-`",
+                prefix
+            ));
+        }
+    }
+
+    let mut offered_as: BTreeMap<&str, &str> = BTreeMap::new();
+    for (name, renamed) in &tools.rename {
+        if !valid_tool_name(renamed) {
+            report.error(format!(
+                "tools.rename.{}: `{}` may only use letters, digits, `_` and `-`",
+                name, renamed
+            ));
+        }
+        if let Some(other) = offered_as.insert(renamed, name) {
+            report.error(format!(
+                "tools.rename: {} and {} are both renamed to `{}`",
+                other, name, renamed
+            ));
+        }
+    }
+
+    if let Some(enabled) = &tools.enabled {
+        for name in tools.disabled.iter().filter(|name| enabled.contains(name)) {
+            report.warning(format!(
+                "tools: {} is both enabled and disabled, so it is disabled",
+                name
+            ));
+        }
+    }
+}
+
+fn valid_tool_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `[lsp.<name>]` entries: broken custom servers and overlapping matchers
+fn check_servers(config: &UserConfig, loader: &ConfigLoader, report: &mut ValidationReport) {
+    let entries: BTreeMap<_, _> = config
+        .lsp
+        .iter()
+        .filter(|(_, entry)| entry.enabled != Some(false))
+        .collect();
+
+    // file type -> entries claiming it
+    let mut claims: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+
+    for (name, entry) in &entries {
+        if loader.get_lsp_by_name(name).is_err() {
+            let reason = match ConfigLoader::custom_package(name, entry) {
+                Err(LspError::ConfigError(reason)) => reason,
+                Err(e) => e.to_string(),
+                Ok(_) => "could not be loaded".to_string(),
+            };
+            report.error(format!("[lsp.{}]: {}", name, reason));
+            continue;
+        }
+
+        if let Some(cwd) = &entry.cwd {
+            if cwd.is_absolute() && !cwd.is_dir() {
+                report.warning(format!(
+                    "[lsp.{}]: cwd `{}` does not exist",
+                    name,
+                    cwd.display()
+                ));
+            }
+        }
+
+        let extensions = entry
+            .file_extensions
+            .iter()
+            .flatten()
+            .map(|ext| format!("`.{}` files", ext.trim_start_matches('.')));
+        let filenames = entry
+            .filenames
+            .iter()
+            .flatten()
+            .map(|f| format!("`{}` files", f));
+        for file_type in extensions.chain(filenames) {
+            claims.entry(file_type).or_default().push(name.as_str());
+        }
+    }
+
+    for (file_type, names) in claims {
+        if names.len() > 1 {
+            report.warning(format!(
+                "{} are claimed by {}; only one of them will be used",
+                file_type,
+                names
+                    .iter()
+                    .map(|n| format!("[lsp.{}]", n))
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            ));
+        }
+    }
+}
+
+/// Language tables and preload list; returns every server they reference
+fn check_languages(
+    config: &UserConfig,
+    loader: &ConfigLoader,
+    report: &mut ValidationReport,
+) -> Vec<LspPackage> {
+    let mut referenced: HashMap<String, LspPackage> = HashMap::new();
+    let mut reference = |pkg: LspPackage| {
+        referenced.entry(pkg.name.clone()).or_insert(pkg);
+    };
+
+    for name in config.lsp.keys() {
+        if let Ok(pkg) = loader.get_lsp_by_name(name) {
+            reference(pkg);
+        }
+    }
+
+    let overrides: BTreeMap<_, _> = config.language_overrides.iter().collect();
+    for (language, server) in overrides {
+        match loader.get_lsp_by_name(server) {
+            Ok(pkg) => reference(pkg),
+            Err(e) => report.error(format!("language_overrides.{}: {}", language, message(e))),
+        }
+
+        if config.language_servers.contains_key(language) {
+            report.warning(format!(
+                "`{}` is set in both language_overrides and language_servers; \
+                 language_servers takes precedence for queries",
+                language
+            ));
+        }
+    }
+
+    let language_servers: BTreeMap<_, _> = config.language_servers.iter().collect();
+    for (language, servers) in language_servers {
+        if servers.is_empty() {
+            report.warning(format!("language_servers.{} is empty", language));
+        }
+
+        for (i, server) in servers.iter().enumerate() {
+            if servers[..i].contains(server) {
+                report.warning(format!(
+                    "language_servers.{} lists `{}` more than once",
+                    language, server
+                ));
+                continue;
+            }
+
+            match loader.get_lsp_by_name(server) {
+                Ok(pkg) => reference(pkg),
+                Err(e) => report.error(format!("language_servers.{}: {}", language, message(e))),
+            }
+        }
+    }
+
+    let preload = config
+        .settings
+        .as_ref()
+        .and_then(|s| s.preload.as_ref())
+        .into_iter()
+        .flatten();
+    for language in preload {
+        match loader.get_lsps_for_language(language) {
+            Ok(packages) => packages.into_iter().for_each(&mut reference),
+            Err(e) => report.warning(format!(
+                "settings.preload: `{}` has no server: {}",
+                language,
+                message(e)
+            )),
+        }
+    }
+
+    let mut referenced: Vec<LspPackage> = referenced.into_values().collect();
+    referenced.sort_by(|a, b| a.name.cmp(&b.name));
+    referenced
+}
+
+/// Servers the config relies on whose binaries can't be found
+fn check_binaries(packages: &[LspPackage], report: &mut ValidationReport) {
+    let installer = match ServerInstaller::new() {
+        Ok(installer) => installer,
+        Err(e) => {
+            report.warning(format!("skipped binary checks: {}", e));
+            return;
+        }
+    };
+
+    for pkg in packages {
+        // Remote servers and TCP servers run by someone else have no local
+        // binary
+        if !pkg.needs_local_binary()
+            || installer
+                .find_lsp_binary(&pkg.name, &pkg.bin.primary)
+                .is_some()
+        {
+            continue;
+        }
+
+        match &pkg.source {
+            InstallSource::External { .. } | InstallSource::System { .. } => report.error(format!(
+                "{}: command `{}` not found",
+                pkg.name, pkg.bin.primary
+            )),
+            _ => report.warning(format!(
+                "{} is not installed; it will be installed on first use or with `lsmcp install {}`",
+                pkg.name, pkg.name
+            )),
+        }
+    }
+}
+
+/// Error text without the `Configuration error:` prefix
+fn message(error: LspError) -> String {
+    match error {
+        LspError::ConfigError(message) => message,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(report: &ValidationReport, severity: Severity) -> Vec<&str> {
+        report
+            .issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .map(|i| i.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".lsmcp.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [settings]
+            log_level = "loud"
+            log_filter = "lsp::client=debug,mcp=noisy"
+            idle_timeout = 5
+
+            [lsp.my-lsp]
+            command = "/nonexistent/my-lsp"
+            file_extensions = ["ml"]
+
+            [lsp.other-lsp]
+            command = "/nonexistent/other-lsp"
+            file_extensions = [".ml"]
+
+            [lsp.broken]
+            languages = ["broken"]
+
+            [language_overrides]
+            python = "no-such-server"
+
+            [language_servers]
+            python = ["pyright"]
+
+            [path_map]
+            "app" = "/srv/app"
+
+            [tools]
+            rename = { lsp_hover = "hover", lsp_find_references = "hover" }
+
+            [profile.ci.settings]
+            index_base = 2
+
+            [profile.ci.language_servers]
+            go = ["no-such-go"]
+            "#,
+        )
+        .unwrap();
+
+        let report = validate_file(&path).unwrap();
+        let errors = messages(&report, Severity::Error);
+        let warnings = messages(&report, Severity::Warning);
+
+        assert!(errors
+            .contains(&"[lsp.broken]: custom servers need a `command`, or a TCP `connection`"));
+        assert!(errors.contains(&"language_overrides.python: LSP 'no-such-server' not found"));
+        assert!(errors.contains(&"my-lsp: command `/nonexistent/my-lsp` not found"));
+        assert!(errors
+            .contains(&"path_map: `app` = `/srv/app` is ignored; both paths must be absolute"));
+        assert!(errors.contains(
+            &"tools.rename: lsp_find_references and lsp_hover are both renamed to `hover`"
+        ));
+        assert!(warnings.contains(&"unknown key `settings.idle_timeout` is ignored"));
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("settings.log_level `loud`")));
+        assert!(warnings.contains(&"settings.log_filter directive `lsmcp::mcp=noisy` is invalid"));
+        assert!(!warnings.iter().any(|w| w.contains("lsp::client")));
+        assert!(warnings.contains(
+            &"`.ml` files are claimed by [lsp.my-lsp] and [lsp.other-lsp]; only one of them will be used"
+        ));
+        assert!(warnings
+            .iter()
+            .any(|w| w.starts_with("`python` is set in both")));
+
+        // Only what a profile brings in is reported for it
+        assert!(errors.contains(&"profile.ci: settings.index_base must be 0 or 1, not 2"));
+        assert!(errors.contains(&"profile.ci: language_servers.go: LSP 'no-such-go' not found"));
+        assert!(!errors
+            .iter()
+            .any(|e| e.starts_with("profile.ci: language_overrides")));
+    }
+
+    #[test]
+    fn test_validate_reports_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[settings\nmax_servers = 2\n").unwrap();
+
+        let report = validate_file(&path).unwrap();
+        assert_eq!(report.count(Severity::Error), 1);
+        assert!(report.issues[0].message.contains("config.toml:1:10:"));
+    }
+
+    #[test]
+    fn test_schema_is_up_to_date() {
+        let committed = include_str!("../../schema/lsmcp.schema.json");
+        assert_eq!(
+            committed.trim_end(),
+            UserConfig::json_schema(),
+            "schema/lsmcp.schema.json is stale; regenerate it with `lsmcp config schema > schema/lsmcp.schema.json`"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Finding servers installed by editors and package managers
+//!
+//! Many users already have a server from Neovim's Mason, a VS Code
+//! extension, Homebrew or a Node version manager, so those installs are
+//! searched before lsmcp downloads a second copy. `binary_search_order`
+//! picks which sources are searched and in what order.
+
+use crate::config::BinarySource;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Sources searched when `binary_search_order` isn't set
+pub const DEFAULT_SEARCH_ORDER: [BinarySource; 7] = [
+    BinarySource::Lsmcp,
+    BinarySource::Mason,
+    BinarySource::Path,
+    BinarySource::Homebrew,
+    BinarySource::Volta,
+    BinarySource::Nvm,
+    BinarySource::Vscode,
+];
+
+/// VS Code-like editors' extension directories, relative to the home
+/// directory
+const VSCODE_EXTENSION_DIRS: [&str; 5] = [
+    ".vscode/extensions",
+    ".vscode-insiders/extensions",
+    ".vscode-server/extensions",
+    ".vscode-oss/extensions",
+    ".cursor/extensions",
+];
+
+/// Directories below an extension searched for its bundled server
+const VSCODE_MAX_DEPTH: usize = 3;
+
+/// Where the other tools keep their installs, from the environment
+#[derive(Debug, Clone, Default)]
+pub struct SearchRoots {
+    pub home: Option<PathBuf>,
+    /// Mason's install root (`$MASON`, else Neovim's data directory)
+    pub mason: Vec<PathBuf>,
+    pub homebrew: Vec<PathBuf>,
+    pub volta: Option<PathBuf>,
+    pub nvm: Option<PathBuf>,
+}
+
+impl SearchRoots {
+    pub fn from_env() -> Self {
+        let var = |name: &str| {
+            std::env::var_os(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        };
+        let home = var("HOME");
+        let data_home =
+            var("XDG_DATA_HOME").or_else(|| home.as_ref().map(|h| h.join(".local/share")));
+
+        let mut mason: Vec<PathBuf> = var("MASON").into_iter().collect();
+        mason.extend(data_home.map(|dir| dir.join("nvim/mason")));
+        mason.extend(home.as_ref().map(|h| h.join(".local/share/nvim/mason")));
+        mason.dedup();
+
+        let mut homebrew: Vec<PathBuf> = var("HOMEBREW_PREFIX").into_iter().collect();
+        homebrew.extend(
+            ["/opt/homebrew", "/usr/local", "/home/linuxbrew/.linuxbrew"].map(PathBuf::from),
+        );
+        homebrew.dedup();
+
+        Self {
+            volta: var("VOLTA_HOME").or_else(|| home.as_ref().map(|h| h.join(".volta"))),
+            nvm: var("NVM_DIR").or_else(|| home.as_ref().map(|h| h.join(".nvm"))),
+            home,
+            mason,
+            homebrew,
+        }
+    }
+
+    /// `binary` of the server `package` as installed by `source`
+    ///
+    /// lsmcp's own installs and `PATH` are the installer's to search, so
+    /// they're never found here.
+    pub fn find(&self, source: BinarySource, package: &str, binary: &str) -> Option<PathBuf> {
+        let found = match source {
+            BinarySource::Lsmcp | BinarySource::Path => None,
+            BinarySource::Mason => self.mason.iter().find_map(|root| {
+                // Mason links every package's binaries into `bin`, but
+                // users may have removed the link or disabled linking
+                let package_dir = root.join("packages").join(package);
+                first_file([
+                    root.join("bin").join(binary),
+                    package_dir.join(binary),
+                    package_dir.join("bin").join(binary),
+                    package_dir.join("node_modules/.bin").join(binary),
+                    package_dir.join("venv/bin").join(binary),
+                ])
+            }),
+            BinarySource::Homebrew => self
+                .homebrew
+                .iter()
+                .find_map(|prefix| first_file([prefix.join("bin").join(binary)])),
+            BinarySource::Volta => self.volta.as_ref().and_then(|volta| {
+                first_file([
+                    volta.join("bin").join(binary),
+                    volta
+                        .join("tools/image/packages")
+                        .join(package)
+                        .join("bin")
+                        .join(binary),
+                ])
+            }),
+            BinarySource::Nvm => self.nvm.as_ref().and_then(|nvm| {
+                // The newest Node version with the server installed globally
+                let mut versions: Vec<PathBuf> = std::fs::read_dir(nvm.join("versions/node"))
+                    .ok()?
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .collect();
+                versions.sort_by_key(|dir| std::cmp::Reverse(node_version(dir)));
+                first_file(versions.iter().map(|dir| dir.join("bin").join(binary)))
+            }),
+            BinarySource::Vscode => {
+                let home = self.home.as_ref()?;
+                VSCODE_EXTENSION_DIRS
+                    .iter()
+                    .find_map(|dir| find_in_extensions(&home.join(dir), binary))
+            }
+        };
+        if let Some(path) = &found {
+            debug!("Found {} from {:?} at {}", binary, source, path.display());
+        }
+        found
+    }
+
+    /// The directories `find` searches for `package` from `source`, for
+    /// error messages
+    pub fn locations(&self, source: BinarySource, package: &str) -> Vec<PathBuf> {
+        match source {
+            BinarySource::Lsmcp | BinarySource::Path => Vec::new(),
+            BinarySource::Mason => self
+                .mason
+                .iter()
+                .flat_map(|root| [root.join("bin"), root.join("packages").join(package)])
+                .collect(),
+            BinarySource::Homebrew => self.homebrew.iter().map(|p| p.join("bin")).collect(),
+            BinarySource::Volta => self.volta.iter().map(|v| v.join("bin")).collect(),
+            BinarySource::Nvm => self
+                .nvm
+                .iter()
+                .map(|nvm| nvm.join("versions/node/*/bin"))
+                .collect(),
+            BinarySource::Vscode => self
+                .home
+                .iter()
+                .flat_map(|home| VSCODE_EXTENSION_DIRS.map(|dir| home.join(dir)))
+                .collect(),
+        }
+    }
+}
+
+/// The first of `paths` that is a file
+fn first_file(paths: impl IntoIterator<Item = PathBuf>) -> Option<PathBuf> {
+    paths.into_iter().find(|path| path.is_file())
+}
+
+/// `(major, minor, patch)` of a directory named like `v20.11.0`
+fn node_version(dir: &Path) -> (u64, u64, u64) {
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned());
+    let mut parts = name
+        .as_deref()
+        .unwrap_or_default()
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0));
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+/// An executable named `binary` bundled with an extension in `extensions`,
+/// from the most recently updated extension first
+fn find_in_extensions(extensions: &Path, binary: &str) -> Option<PathBuf> {
+    let mut dirs: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(extensions)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| {
+            let modified = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::UNIX_EPOCH);
+            (modified, entry.path())
+        })
+        .collect();
+    dirs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    dirs.iter()
+        .find_map(|(_, dir)| find_executable(dir, binary, VSCODE_MAX_DEPTH))
+}
+
+/// An executable named `binary` at most `depth` directories below `dir`,
+/// skipping `node_modules` except for its `.bin`
+fn find_executable(dir: &Path, binary: &str, depth: usize) -> Option<PathBuf> {
+    let candidate = dir.join(binary);
+    if is_executable(&candidate) {
+        return Some(candidate);
+    }
+    if depth == 0 {
+        return None;
+    }
+    let mut subdirs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    subdirs.sort();
+    subdirs.iter().find_map(|subdir| {
+        if subdir
+            .file_name()
+            .is_some_and(|name| name == "node_modules")
+        {
+            find_executable(&subdir.join(".bin"), binary, 0)
+        } else {
+            find_executable(subdir, binary, depth - 1)
+        }
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_find_installs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = tmp.path().join("home");
+        let roots = SearchRoots {
+            home: Some(home.clone()),
+            mason: vec![tmp.path().join("mason")],
+            homebrew: vec![tmp.path().join("brew")],
+            volta: Some(home.join(".volta")),
+            nvm: Some(home.join(".nvm")),
+        };
+
+        let mason = tmp
+            .path()
+            .join("mason/packages/pyright/node_modules/.bin/pyright");
+        let brew = tmp.path().join("brew/bin/gopls");
+        let nvm_old = home.join(".nvm/versions/node/v9.11.2/bin/tsserver");
+        let nvm_new = home.join(".nvm/versions/node/v20.1.0/bin/tsserver");
+        let vscode =
+            home.join(".vscode/extensions/rust-lang.rust-analyzer-0.3.1/server/rust-analyzer");
+        for path in [&mason, &brew, &nvm_old, &nvm_new, &vscode] {
+            touch(path);
+        }
+
+        let find = |source, package, binary| roots.find(source, package, binary);
+        assert_eq!(find(BinarySource::Mason, "pyright", "pyright"), Some(mason));
+        assert_eq!(find(BinarySource::Homebrew, "gopls", "gopls"), Some(brew));
+        assert_eq!(
+            find(BinarySource::Nvm, "typescript", "tsserver"),
+            Some(nvm_new)
+        );
+        assert_eq!(
+            find(BinarySource::Vscode, "rust-analyzer", "rust-analyzer"),
+            Some(vscode)
+        );
+        assert_eq!(find(BinarySource::Volta, "pyright", "pyright"), None);
+        assert_eq!(find(BinarySource::Path, "gopls", "gopls"), None);
+    }
+}
@@ -0,0 +1,913 @@
+//! GitHub release installation
+//!
+//! Picks the release asset matching the current platform, verifies its
+//! SHA-256 checksum when one is published, and unpacks it. Downloads and
+//! extraction shell out to `curl`, `tar`, `unzip` and `gzip`, like the other
+//! install sources shell out to their package managers.
+
+use crate::installer::InstallOptions;
+use crate::types::LspError;
+use crate::utils::glob::glob_match;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+const GITHUB_API: &str = "https://api.github.com";
+
+/// Archive formats we know how to unpack
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".tar.xz", ".zip", ".gz"];
+
+/// Assets that are never the server itself
+const IGNORED_EXTENSIONS: &[&str] = &[
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".sig",
+    ".asc",
+    ".minisig",
+    ".vsix",
+    ".txt",
+    ".json",
+    ".sbom",
+    ".pem",
+    ".deb",
+    ".rpm",
+    ".msi",
+    ".dmg",
+    ".pkg",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    /// `sha256:<hex>` digest published by GitHub for newer releases
+    pub digest: Option<String>,
+}
+
+/// Result of a successful release install
+#[derive(Debug)]
+pub struct ReleaseInstall {
+    pub binary_path: PathBuf,
+    pub tag: String,
+}
+
+/// Download and unpack a GitHub release into `server_dir`
+///
+/// Release metadata and assets are cached, so offline installs can reuse
+/// anything downloaded before.
+pub async fn install_release(
+    repo: &str,
+    tag: Option<&str>,
+    asset_pattern: Option<&str>,
+    binary: &str,
+    server_dir: &Path,
+    options: &InstallOptions,
+) -> Result<ReleaseInstall, LspError> {
+    let cache = options
+        .cache_dir
+        .as_deref()
+        .map(|dir| ReleaseCache::new(dir, repo));
+
+    let release = if options.offline {
+        cache
+            .as_ref()
+            .and_then(|cache| cache.release(tag))
+            .ok_or_else(|| {
+                LspError::Offline(format!("release {} of {}", tag.unwrap_or("latest"), repo))
+            })?
+    } else {
+        let release = fetch_release(repo, tag, options).await?;
+        if let Some(cache) = &cache {
+            if let Err(e) = cache.store_release(&release, tag.is_none()) {
+                warn!("Failed to cache release metadata for {}: {}", repo, e);
+            }
+        }
+        release
+    };
+    let version = release.tag_name.trim_start_matches('v');
+
+    let platform = Platform::current();
+    let asset = match asset_pattern {
+        Some(pattern) => select_asset_by_pattern(&release.assets, pattern, &platform, version),
+        None => select_asset(&release.assets, &platform),
+    }
+    .ok_or_else(|| {
+        install_error(
+            repo,
+            format!(
+                "No release asset of {} matches this platform ({}-{})",
+                release.tag_name, platform.os, platform.arch
+            ),
+        )
+    })?
+    .clone();
+
+    info!(
+        "Installing {} {} from asset {}",
+        repo, release.tag_name, asset.name
+    );
+
+    // Unpack next to the installed server and swap it in only once the new
+    // one is complete, so a failed download or extract leaves the old one
+    let staging = aside_dir(server_dir, "installing");
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(LspError::Io)?;
+    }
+    fs::create_dir_all(&staging).map_err(LspError::Io)?;
+
+    let staged = stage_asset(
+        repo,
+        &release,
+        &asset,
+        binary,
+        &staging,
+        cache.as_ref(),
+        options,
+    )
+    .await;
+    let relative = match staged {
+        Ok(relative) => relative,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+    replace_dir(&staging, server_dir).map_err(LspError::Io)?;
+
+    Ok(ReleaseInstall {
+        binary_path: server_dir.join(relative),
+        tag: release.tag_name,
+    })
+}
+
+/// Download, verify and unpack `asset` into `staging`, returning the path of
+/// the server binary relative to it
+async fn stage_asset(
+    repo: &str,
+    release: &Release,
+    asset: &Asset,
+    binary: &str,
+    staging: &Path,
+    cache: Option<&ReleaseCache>,
+    options: &InstallOptions,
+) -> Result<PathBuf, LspError> {
+    let archive_path = staging.join(&asset.name);
+    let cached = cache
+        .map(|cache| cache.asset_path(&release.tag_name, &asset.name))
+        .filter(|path| path.exists());
+
+    let checksum = if let Some(cached) = cached {
+        debug!("Using cached {}", cached.display());
+        fs::copy(&cached, &archive_path).map_err(LspError::Io)?;
+        asset_digest(asset).or_else(|| cache?.checksum(&release.tag_name, &asset.name))
+    } else if options.offline {
+        return Err(LspError::Offline(format!(
+            "asset {} of {}",
+            asset.name, release.tag_name
+        )));
+    } else {
+        download(&asset.browser_download_url, &archive_path, repo, options).await?;
+        find_checksum(&release.assets, asset, options).await
+    };
+
+    match &checksum {
+        Some(expected) => verify_sha256(&archive_path, expected, repo)?,
+        None => warn!(
+            "No checksum published for {}, skipping verification",
+            asset.name
+        ),
+    }
+
+    if let Some(cache) = cache {
+        if let Err(e) = cache.store_asset(&release.tag_name, &archive_path, checksum.as_deref()) {
+            warn!("Failed to cache {}: {}", asset.name, e);
+        }
+    }
+
+    extract(&archive_path, staging, binary, repo).await?;
+    fs::remove_file(&archive_path).map_err(LspError::Io)?;
+
+    let binary_path = find_binary(staging, binary).ok_or_else(|| {
+        install_error(
+            repo,
+            format!(
+                "Binary {} not found in release asset {}",
+                binary, asset.name
+            ),
+        )
+    })?;
+    make_executable(&binary_path)?;
+
+    Ok(binary_path
+        .strip_prefix(staging)
+        .map(Path::to_path_buf)
+        .unwrap_or(binary_path))
+}
+
+/// `<parent>/.<purpose>/<name>` for `dir`: next to it, so renames stay on
+/// one filesystem, but hidden, so what an interrupted install leaves there
+/// isn't taken for an installed server
+fn aside_dir(dir: &Path, purpose: &str) -> PathBuf {
+    let parent = dir.parent().unwrap_or(Path::new(""));
+    parent
+        .join(format!(".{}", purpose))
+        .join(dir.file_name().unwrap_or_default())
+}
+
+/// Move `staging` to `target`, replacing what was there
+///
+/// The old directory is moved aside first and put back if the rename fails.
+fn replace_dir(staging: &Path, target: &Path) -> std::io::Result<()> {
+    if !target.exists() {
+        return fs::rename(staging, target);
+    }
+    let previous = aside_dir(target, "previous");
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    if let Some(parent) = previous.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(target, &previous)?;
+    if let Err(e) = fs::rename(staging, target) {
+        let _ = fs::rename(&previous, target);
+        return Err(e);
+    }
+    if let Err(e) = fs::remove_dir_all(&previous) {
+        warn!("Failed to remove {}: {}", previous.display(), e);
+    }
+    Ok(())
+}
+
+/// Release metadata and assets cached under `<cache_dir>/github/<owner>/<repo>`
+struct ReleaseCache {
+    dir: PathBuf,
+}
+
+impl ReleaseCache {
+    fn new(cache_dir: &Path, repo: &str) -> Self {
+        Self {
+            dir: cache_dir.join("github").join(repo),
+        }
+    }
+
+    /// Cached metadata for a tag, or for the last release fetched as latest
+    fn release(&self, tag: Option<&str>) -> Option<Release> {
+        let tag = match tag {
+            Some(tag) => tag.to_string(),
+            None => fs::read_to_string(self.dir.join("latest"))
+                .ok()?
+                .trim()
+                .to_string(),
+        };
+        let content = fs::read(self.dir.join(tag).join("release.json")).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    fn store_release(&self, release: &Release, latest: bool) -> std::io::Result<()> {
+        let dir = self.dir.join(&release.tag_name);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("release.json"), serde_json::to_vec(release)?)?;
+        if latest {
+            fs::write(self.dir.join("latest"), &release.tag_name)?;
+        }
+        Ok(())
+    }
+
+    fn asset_path(&self, tag: &str, name: &str) -> PathBuf {
+        self.dir.join(tag).join(name)
+    }
+
+    fn checksum(&self, tag: &str, name: &str) -> Option<String> {
+        let content =
+            fs::read_to_string(self.dir.join(tag).join(format!("{}.sha256", name))).ok()?;
+        parse_checksum(&content, None)
+    }
+
+    fn store_asset(
+        &self,
+        tag: &str,
+        archive: &Path,
+        checksum: Option<&str>,
+    ) -> std::io::Result<()> {
+        let Some(name) = archive.file_name() else {
+            return Ok(());
+        };
+        let dest = self.dir.join(tag).join(name);
+        if dest.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(self.dir.join(tag))?;
+        fs::copy(archive, &dest)?;
+        if let Some(checksum) = checksum {
+            let mut sum_name = name.to_os_string();
+            sum_name.push(".sha256");
+            fs::write(self.dir.join(tag).join(sum_name), checksum)?;
+        }
+        Ok(())
+    }
+}
+
+fn install_error(repo: &str, message: String) -> LspError {
+    LspError::ServerNotFound(repo.to_string(), message)
+}
+
+/// Fetch release metadata for a tag, or the latest release
+pub async fn fetch_release(
+    repo: &str,
+    tag: Option<&str>,
+    options: &InstallOptions,
+) -> Result<Release, LspError> {
+    let url = match tag {
+        Some(tag) => format!("{}/repos/{}/releases/tags/{}", GITHUB_API, repo, tag),
+        None => format!("{}/repos/{}/releases/latest", GITHUB_API, repo),
+    };
+
+    let body = curl_api(&url, repo, options).await?;
+    serde_json::from_slice(&body)
+        .map_err(|e| install_error(repo, format!("Invalid GitHub release response: {}", e)))
+}
+
+async fn curl_api(url: &str, repo: &str, options: &InstallOptions) -> Result<Vec<u8>, LspError> {
+    let mut command = options.command("curl");
+    command.args(["-fsSL", "-H", "Accept: application/vnd.github+json", url]);
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        command.args(["-H", &format!("Authorization: Bearer {}", token)]);
+    }
+
+    run_curl(command, repo).await
+}
+
+pub(crate) async fn download(
+    url: &str,
+    dest: &Path,
+    repo: &str,
+    options: &InstallOptions,
+) -> Result<(), LspError> {
+    debug!("Downloading {} to {}", url, dest.display());
+    let mut command = options.command("curl");
+    command.args(["-fsSL", "-o"]).arg(dest).arg(url);
+    run_curl(command, repo).await.map(|_| ())
+}
+
+async fn run_curl(mut command: AsyncCommand, repo: &str) -> Result<Vec<u8>, LspError> {
+    let output = command
+        .output()
+        .await
+        .map_err(|e| install_error(repo, format!("curl not found or failed: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(install_error(
+            repo,
+            format!(
+                "Download failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Operating system and architecture aliases used in asset names
+#[derive(Debug, Clone)]
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        Self {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+
+    /// Name fragments identifying this OS (matched against name tokens)
+    fn os_aliases(&self) -> &'static [&'static str] {
+        match self.os {
+            "macos" => &["darwin", "macos", "mac", "osx", "apple"],
+            "windows" => &["windows", "win", "win32", "win64"],
+            "linux" => &["linux"],
+            _ => &[],
+        }
+    }
+
+    /// Name fragments identifying this architecture (matched as substrings)
+    fn arch_aliases(&self) -> &'static [&'static str] {
+        aliases_for_arch(self.arch)
+    }
+}
+
+fn aliases_for_arch(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" => &["x86_64", "amd64", "x64"],
+        "aarch64" => &["aarch64", "arm64"],
+        "x86" => &["i686", "i386", "x86-32"],
+        "arm" => &["armv7", "armhf"],
+        "riscv64" => &["riscv64"],
+        _ => &[],
+    }
+}
+
+const KNOWN_ARCHES: &[&str] = &["x86_64", "aarch64", "x86", "arm", "riscv64"];
+
+fn tokens(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(['-', '_', '.'])
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_candidate(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    !IGNORED_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+/// Pick the best asset for the platform using naming heuristics
+///
+/// An asset must name the current OS and must not name a different
+/// architecture. Assets naming our architecture, packed in a known archive
+/// format, and (on Linux) built against glibc rather than musl are preferred.
+pub fn select_asset<'a>(assets: &'a [Asset], platform: &Platform) -> Option<&'a Asset> {
+    let os_aliases = platform.os_aliases();
+    let arch_aliases = platform.arch_aliases();
+
+    assets
+        .iter()
+        .filter(|asset| is_candidate(&asset.name))
+        .filter_map(|asset| {
+            let lower = asset.name.to_lowercase();
+            let name_tokens = tokens(&asset.name);
+
+            if !name_tokens.iter().any(|t| os_aliases.contains(&t.as_str())) {
+                return None;
+            }
+
+            let ours = arch_aliases.iter().any(|a| lower.contains(a));
+            let other = KNOWN_ARCHES
+                .iter()
+                .filter(|arch| **arch != platform.arch)
+                .flat_map(|arch| aliases_for_arch(arch).iter())
+                .any(|a| lower.contains(a));
+            if other && !ours {
+                return None;
+            }
+
+            let mut score = 0;
+            if ours {
+                score += 4;
+            }
+            if ARCHIVE_EXTENSIONS.iter().any(|ext| lower.ends_with(ext)) {
+                score += 2;
+            }
+            if !name_tokens.iter().any(|t| t == "musl") {
+                score += 1;
+            }
+
+            Some((score, asset))
+        })
+        // Keep the first asset among equal scores
+        .fold(
+            None,
+            |best: Option<(i32, &Asset)>, (score, asset)| match best {
+                Some((best_score, _)) if best_score >= score => best,
+                _ => Some((score, asset)),
+            },
+        )
+        .map(|(_, asset)| asset)
+}
+
+/// Pick an asset using a registry-provided name pattern
+///
+/// Patterns support `*` wildcards and `{os}`, `{arch}` and `{version}`
+/// placeholders, where `{os}`/`{arch}` match any alias of the current
+/// platform (e.g. `clangd-{os}-*.zip` matches `clangd-mac-19.1.2.zip`).
+pub fn select_asset_by_pattern<'a>(
+    assets: &'a [Asset],
+    pattern: &str,
+    platform: &Platform,
+    version: &str,
+) -> Option<&'a Asset> {
+    let pattern = pattern.replace("{version}", version);
+    let os_options: Vec<&str> = if pattern.contains("{os}") {
+        platform.os_aliases().to_vec()
+    } else {
+        vec![""]
+    };
+    let arch_options: Vec<&str> = if pattern.contains("{arch}") {
+        platform.arch_aliases().to_vec()
+    } else {
+        vec![""]
+    };
+
+    let candidates: Vec<String> = os_options
+        .iter()
+        .flat_map(|os| {
+            let pattern = pattern.replace("{os}", os);
+            arch_options
+                .iter()
+                .map(move |arch| pattern.replace("{arch}", arch))
+        })
+        .collect();
+
+    assets.iter().find(|asset| {
+        candidates
+            .iter()
+            .any(|candidate| glob_match(candidate, &asset.name))
+    })
+}
+
+/// Find the expected SHA-256 of an asset
+///
+/// Checks, in order: the digest GitHub publishes for the asset, a sibling
+/// `<asset>.sha256` file, and a checksum list such as `SHA256SUMS`.
+async fn find_checksum(
+    assets: &[Asset],
+    asset: &Asset,
+    options: &InstallOptions,
+) -> Option<String> {
+    if let Some(digest) = asset_digest(asset) {
+        return Some(digest);
+    }
+
+    let sibling = assets.iter().find(|a| {
+        a.name == format!("{}.sha256", asset.name) || a.name == format!("{}.sha256sum", asset.name)
+    });
+    if let Some(sibling) = sibling {
+        let content = fetch_text(&sibling.browser_download_url, options).await?;
+        return parse_checksum(&content, None);
+    }
+
+    for list in assets.iter().filter(|a| {
+        let lower = a.name.to_lowercase();
+        lower.contains("sha256") || lower.contains("checksums")
+    }) {
+        if let Some(content) = fetch_text(&list.browser_download_url, options).await {
+            if let Some(checksum) = parse_checksum(&content, Some(&asset.name)) {
+                return Some(checksum);
+            }
+        }
+    }
+
+    None
+}
+
+/// The `sha256:<hex>` digest GitHub publishes for an asset
+pub(crate) fn asset_digest(asset: &Asset) -> Option<String> {
+    asset
+        .digest
+        .as_deref()
+        .and_then(|d| d.strip_prefix("sha256:"))
+        .map(|d| d.to_lowercase())
+}
+
+async fn fetch_text(url: &str, options: &InstallOptions) -> Option<String> {
+    let output = options
+        .command("curl")
+        .args(["-fsSL", url])
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Extract a SHA-256 hex digest from a checksum file
+///
+/// With `file_name`, only the line mentioning that file is considered
+/// (`sha256sum` output format); otherwise the first digest is used.
+pub fn parse_checksum(content: &str, file_name: Option<&str>) -> Option<String> {
+    content
+        .lines()
+        .filter(|line| match file_name {
+            Some(name) => line
+                .split_whitespace()
+                .any(|field| field.trim_start_matches('*') == name),
+            None => true,
+        })
+        .flat_map(|line| line.split_whitespace())
+        .find(|field| field.len() == 64 && field.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase)
+}
+
+pub(crate) fn verify_sha256(path: &Path, expected: &str, repo: &str) -> Result<(), LspError> {
+    let data = fs::read(path).map_err(LspError::Io)?;
+    let actual: String = Sha256::digest(&data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    if actual != expected {
+        let _ = fs::remove_file(path);
+        return Err(install_error(
+            repo,
+            format!(
+                "Checksum mismatch for {} (expected {}, got {})",
+                path.display(),
+                expected,
+                actual
+            ),
+        ));
+    }
+
+    debug!("Verified sha256 of {}", path.display());
+    Ok(())
+}
+
+async fn extract(archive: &Path, dest: &Path, binary: &str, repo: &str) -> Result<(), LspError> {
+    let name = archive
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let mut command = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut command = AsyncCommand::new("tar");
+        command.arg("-xzf").arg(archive).arg("-C").arg(dest);
+        command
+    } else if name.ends_with(".tar.xz") {
+        let mut command = AsyncCommand::new("tar");
+        command.arg("-xJf").arg(archive).arg("-C").arg(dest);
+        command
+    } else if name.ends_with(".zip") {
+        let mut command = AsyncCommand::new("unzip");
+        command.args(["-o", "-q"]).arg(archive).arg("-d").arg(dest);
+        command
+    } else if name.ends_with(".gz") {
+        // A single compressed binary
+        let output = AsyncCommand::new("gzip")
+            .arg("-dc")
+            .arg(archive)
+            .output()
+            .await
+            .map_err(|e| install_error(repo, format!("gzip not found or failed: {}", e)))?;
+        if !output.status.success() {
+            return Err(install_error(
+                repo,
+                format!(
+                    "Failed to decompress {}: {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+        fs::write(dest.join(binary_file_name(binary)), output.stdout).map_err(LspError::Io)?;
+        return Ok(());
+    } else {
+        // An uncompressed binary
+        fs::copy(archive, dest.join(binary_file_name(binary))).map_err(LspError::Io)?;
+        return Ok(());
+    };
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| install_error(repo, format!("Failed to run extractor: {}", e)))?;
+    if !output.status.success() {
+        return Err(install_error(
+            repo,
+            format!(
+                "Failed to extract {}: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn binary_file_name(binary: &str) -> String {
+    if cfg!(windows) && !binary.ends_with(".exe") {
+        format!("{}.exe", binary)
+    } else {
+        binary.to_string()
+    }
+}
+
+/// Locate the server binary in an unpacked release, preferring `bin/` dirs
+pub fn find_binary(dir: &Path, binary: &str) -> Option<PathBuf> {
+    let file_name = binary_file_name(binary);
+    let mut matches = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+
+    while let Some(current) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if entry.file_name().to_string_lossy() == file_name {
+                matches.push(path);
+            }
+        }
+    }
+
+    matches.sort_by_key(|path| {
+        let in_bin = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n == "bin")
+            .unwrap_or(false);
+        (!in_bin, path.components().count())
+    });
+    matches.into_iter().next()
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), LspError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path).map_err(LspError::Io)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions).map_err(LspError::Io)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), LspError> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assets(names: &[&str]) -> Vec<Asset> {
+        names
+            .iter()
+            .map(|name| Asset {
+                name: name.to_string(),
+                browser_download_url: format!("https://example.com/{}", name),
+                digest: None,
+            })
+            .collect()
+    }
+
+    const LINUX_X64: Platform = Platform {
+        os: "linux",
+        arch: "x86_64",
+    };
+    const MAC_ARM: Platform = Platform {
+        os: "macos",
+        arch: "aarch64",
+    };
+
+    #[test]
+    fn test_select_rust_analyzer_asset() {
+        let assets = assets(&[
+            "rust-analyzer-aarch64-apple-darwin.gz",
+            "rust-analyzer-x86_64-unknown-linux-musl.gz",
+            "rust-analyzer-x86_64-unknown-linux-gnu.gz",
+            "rust-analyzer-x86_64-unknown-linux-gnu.gz.sha256",
+            "rust-analyzer-linux-x64.vsix",
+            "rust-analyzer-x86_64-pc-windows-msvc.zip",
+        ]);
+
+        let linux = select_asset(&assets, &LINUX_X64).unwrap();
+        assert_eq!(linux.name, "rust-analyzer-x86_64-unknown-linux-gnu.gz");
+
+        let mac = select_asset(&assets, &MAC_ARM).unwrap();
+        assert_eq!(mac.name, "rust-analyzer-aarch64-apple-darwin.gz");
+    }
+
+    #[test]
+    fn test_select_lua_and_zls_assets() {
+        let lua = assets(&[
+            "lua-language-server-3.9.0-darwin-arm64.tar.gz",
+            "lua-language-server-3.9.0-linux-arm64.tar.gz",
+            "lua-language-server-3.9.0-linux-x64.tar.gz",
+            "lua-language-server-3.9.0-win32-x64.zip",
+        ]);
+        assert_eq!(
+            select_asset(&lua, &LINUX_X64).unwrap().name,
+            "lua-language-server-3.9.0-linux-x64.tar.gz"
+        );
+
+        let zls = assets(&[
+            "zls-aarch64-macos.tar.xz",
+            "zls-x86_64-linux.tar.xz",
+            "zls-x86_64-linux.tar.xz.minisig",
+        ]);
+        assert_eq!(
+            select_asset(&zls, &LINUX_X64).unwrap().name,
+            "zls-x86_64-linux.tar.xz"
+        );
+        assert_eq!(
+            select_asset(&zls, &MAC_ARM).unwrap().name,
+            "zls-aarch64-macos.tar.xz"
+        );
+    }
+
+    #[test]
+    fn test_select_asset_by_pattern() {
+        let clangd = assets(&[
+            "clangd_indexing_tools-linux-19.1.2.zip",
+            "clangd-linux-19.1.2.zip",
+            "clangd-mac-19.1.2.zip",
+        ]);
+
+        let linux =
+            select_asset_by_pattern(&clangd, "clangd-{os}-{version}.zip", &LINUX_X64, "19.1.2");
+        assert_eq!(linux.unwrap().name, "clangd-linux-19.1.2.zip");
+
+        let mac = select_asset_by_pattern(&clangd, "clangd-{os}-*.zip", &MAC_ARM, "19.1.2");
+        assert_eq!(mac.unwrap().name, "clangd-mac-19.1.2.zip");
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let digest = "a".repeat(64);
+        assert_eq!(
+            parse_checksum(&format!("{}\n", digest), None),
+            Some(digest.clone())
+        );
+
+        let list = format!(
+            "{}  other.tar.gz\n{} *zls-x86_64-linux.tar.xz\n",
+            "b".repeat(64),
+            digest
+        );
+        assert_eq!(
+            parse_checksum(&list, Some("zls-x86_64-linux.tar.xz")),
+            Some(digest)
+        );
+        assert_eq!(parse_checksum(&list, Some("missing.zip")), None);
+    }
+
+    #[test]
+    fn test_find_binary_prefers_bin_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(tmp.path().join("pkg/bin")).unwrap();
+        fs::create_dir_all(tmp.path().join("pkg/script")).unwrap();
+        fs::write(tmp.path().join("pkg/bin/lua-language-server"), "").unwrap();
+        fs::write(tmp.path().join("pkg/script/lua-language-server"), "").unwrap();
+
+        let found = find_binary(tmp.path(), "lua-language-server").unwrap();
+        assert!(found.ends_with("pkg/bin/lua-language-server"));
+    }
+
+    #[test]
+    fn test_release_cache_round_trip() {
+        const DIGEST: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let tmp = tempfile::tempdir().unwrap();
+        let cache = ReleaseCache::new(tmp.path(), "zigtools/zls");
+        assert!(cache.release(None).is_none());
+
+        let release = Release {
+            tag_name: "0.13.0".to_string(),
+            assets: assets(&["zls-x86_64-linux.tar.xz"]),
+        };
+        cache.store_release(&release, true).unwrap();
+        assert_eq!(cache.release(None).unwrap().tag_name, "0.13.0");
+        assert_eq!(cache.release(Some("0.13.0")).unwrap().assets.len(), 1);
+        assert!(cache.release(Some("0.12.0")).is_none());
+
+        let archive = tmp.path().join("zls-x86_64-linux.tar.xz");
+        fs::write(&archive, "archive").unwrap();
+        cache.store_asset("0.13.0", &archive, Some(DIGEST)).unwrap();
+        assert!(cache
+            .asset_path("0.13.0", "zls-x86_64-linux.tar.xz")
+            .exists());
+        assert_eq!(
+            cache
+                .checksum("0.13.0", "zls-x86_64-linux.tar.xz")
+                .as_deref(),
+            Some(DIGEST)
+        );
+    }
+
+    #[test]
+    fn test_replace_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let target = tmp.path().join("zls");
+        let staging = aside_dir(&target, "installing");
+        assert_eq!(staging, tmp.path().join(".installing/zls"));
+
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("old"), "old").unwrap();
+        fs::create_dir_all(&staging).unwrap();
+        fs::write(staging.join("new"), "new").unwrap();
+
+        replace_dir(&staging, &target).unwrap();
+        assert!(target.join("new").exists());
+        assert!(!target.join("old").exists());
+        assert!(!staging.exists());
+        assert!(!tmp.path().join(".previous/zls").exists());
+    }
+}
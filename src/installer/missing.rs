@@ -0,0 +1,227 @@
+//! Errors for servers that can't be found or started
+//!
+//! The manager, the installer and the process spawner all end up telling
+//! the user a server is missing. [`MissingServer`] gathers what they know
+//! (the binary tried, where it was looked for, how the server is installed
+//! and whether lsmcp would install it) into one actionable error.
+
+use super::ServerInstaller;
+use crate::config::{InstallSource, LspPackage};
+use crate::types::LspError;
+
+/// What is known about a server that couldn't be run
+#[derive(Debug, Clone)]
+pub struct MissingServer {
+    server: String,
+    binary: String,
+    source: InstallSource,
+    searched: Vec<String>,
+    /// `Some(false)` when `auto_install` is off
+    auto_install: Option<bool>,
+    offline: bool,
+    /// Why the binary couldn't be used, when it was found
+    spawn_error: Option<String>,
+    /// Why installing it failed, when lsmcp tried
+    install_error: Option<String>,
+}
+
+impl MissingServer {
+    pub fn new(package: &LspPackage) -> Self {
+        Self {
+            server: package.name.clone(),
+            binary: package.bin.primary.clone(),
+            source: package.source.clone(),
+            searched: Vec::new(),
+            auto_install: None,
+            offline: false,
+            spawn_error: None,
+            install_error: None,
+        }
+    }
+
+    /// Locations searched for the binary, in order
+    pub fn searched(mut self, locations: Vec<String>) -> Self {
+        self.searched = locations;
+        self
+    }
+
+    /// The `auto_install` and `offline` settings in effect
+    pub fn install_settings(mut self, auto_install: Option<bool>, offline: bool) -> Self {
+        self.auto_install = auto_install;
+        self.offline = offline;
+        self
+    }
+
+    /// `program` was run but couldn't be started
+    pub fn spawn_failed(mut self, program: &str, error: impl std::fmt::Display) -> Self {
+        self.binary = program.to_string();
+        self.spawn_error = Some(error.to_string());
+        self
+    }
+
+    /// Installing the server was tried and failed
+    pub fn install_failed(mut self, error: impl std::fmt::Display) -> Self {
+        self.install_error = Some(error.to_string());
+        self
+    }
+
+    /// Whether lsmcp knows how to install the server
+    fn installable(&self) -> bool {
+        !matches!(
+            self.source,
+            InstallSource::External { .. } | InstallSource::System { .. }
+        )
+    }
+
+    pub fn into_error(self) -> LspError {
+        let problem = match (&self.spawn_error, self.searched.as_slice()) {
+            (Some(error), _) => format!("could not be started ({})", error),
+            (None, []) => "was not found".to_string(),
+            (None, searched) => format!("was not found in {}", searched.join(", ")),
+        };
+
+        let mut hint = Vec::new();
+        if let Some(error) = &self.install_error {
+            hint.push(format!("Installing it failed: {}.", error));
+        }
+        let commands = install_commands(&self.server, &self.source);
+        hint.push(match commands.as_slice() {
+            [] => format!(
+                "Install `{}` with your system's package manager.",
+                self.binary
+            ),
+            commands => format!("Install it with {}.", or_list(commands)),
+        });
+        if self.installable() && self.install_error.is_none() {
+            if self.auto_install == Some(false) {
+                hint.push("Auto-install is off (`auto_install` in [settings]).".to_string());
+            } else if self.offline {
+                hint.push("Auto-install can't download it in offline mode.".to_string());
+            }
+        } else if !self.installable() {
+            hint.push("lsmcp can't install it automatically.".to_string());
+        }
+        hint.push(format!(
+            "If it's installed elsewhere, set `command` under [lsp.{}].",
+            self.server
+        ));
+
+        LspError::BinaryNotFound {
+            server: self.server,
+            binary: self.binary,
+            problem,
+            hint: hint.join(" "),
+        }
+    }
+}
+
+/// Commands that install `server` from `source`, lsmcp's own first
+///
+/// System packages are limited to the package managers on `PATH` when any
+/// of them is.
+fn install_commands(server: &str, source: &InstallSource) -> Vec<String> {
+    let mut commands = Vec::new();
+    if !matches!(
+        source,
+        InstallSource::External { .. } | InstallSource::System { .. }
+    ) {
+        commands.push(format!("lsmcp install {}", server));
+    }
+
+    match source {
+        InstallSource::Npm { package, version } => commands.push(format!(
+            "npm install -g {}{}",
+            package,
+            version
+                .as_ref()
+                .map(|v| format!("@{}", v))
+                .unwrap_or_default()
+        )),
+        InstallSource::Cargo {
+            crate_name,
+            version,
+        } => commands.push(format!(
+            "cargo install {}{}",
+            crate_name,
+            version
+                .as_ref()
+                .map(|v| format!(" --version {}", v))
+                .unwrap_or_default()
+        )),
+        InstallSource::Pip { package, version } => commands.push(format!(
+            "pip install {}{}",
+            package,
+            version
+                .as_ref()
+                .map(|v| format!("=={}", v))
+                .unwrap_or_default()
+        )),
+        InstallSource::Go { package, version } => commands.push(format!(
+            "go install {}@{}",
+            package,
+            version.as_deref().unwrap_or("latest")
+        )),
+        InstallSource::System { packages } => {
+            let mut managers: Vec<(&String, &String)> = packages.iter().collect();
+            managers.sort();
+            let available: Vec<_> = managers
+                .iter()
+                .filter(|(manager, _)| ServerInstaller::find_on_path(manager, manager).is_some())
+                .copied()
+                .collect();
+            if !available.is_empty() {
+                managers = available;
+            }
+            commands.extend(
+                managers
+                    .into_iter()
+                    .map(|(manager, package)| format!("{} install {}", manager, package)),
+            );
+        }
+        InstallSource::GithubRelease { .. } | InstallSource::External { .. } => {}
+    }
+    commands
+}
+
+/// "`a`, `b` or `c`"
+fn or_list(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|item| format!("`{}`", item)).collect();
+    match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => quoted.concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_server_error() {
+        let package = crate::config::get_default_configs()["python"].clone();
+        let error = MissingServer::new(&package)
+            .searched(vec!["PATH".to_string(), "/data/servers".to_string()])
+            .install_settings(Some(false), false)
+            .into_error()
+            .to_string();
+        assert!(error.contains("was not found in PATH, /data/servers"));
+        assert!(error.contains(&format!(
+            "Install it with `lsmcp install {}` or `npm install -g",
+            package.name
+        )));
+        assert!(error.contains("Auto-install is off"));
+
+        let package = LspPackage {
+            source: InstallSource::External {
+                command: "jdtls".to_string(),
+            },
+            ..package
+        };
+        let error = MissingServer::new(&package)
+            .spawn_failed("/opt/jdtls/bin/jdtls", "Permission denied")
+            .into_error()
+            .to_string();
+        assert!(error.contains("`/opt/jdtls/bin/jdtls` could not be started (Permission denied)"));
+        assert!(error.contains("lsmcp can't install it automatically"));
+    }
+}
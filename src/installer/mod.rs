@@ -0,0 +1,1208 @@
+//! LSP server installer
+//!
+//! Automatically downloads and manages LSP server installations
+
+pub mod discovery;
+pub(crate) mod github;
+mod missing;
+mod versions;
+
+pub use missing::MissingServer;
+
+use crate::config::{BinaryConfig, BinarySource, InstallSource, LspPackage, NpmRunner, Settings};
+use crate::types::LspError;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tokio::process::Command as AsyncCommand;
+use tracing::{debug, info, warn};
+
+/// Manifest tracking installed LSP servers
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct InstallManifest {
+    pub servers: HashMap<String, InstalledServer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InstalledServer {
+    pub name: String,
+    pub version: Option<String>,
+    pub install_date: String,
+    pub binary_path: PathBuf,
+    pub install_method: String,
+    /// Source the server was installed from, used for update checks
+    #[serde(default)]
+    pub source: Option<InstallSource>,
+}
+
+/// Result of comparing an installed server against its upstream version
+#[derive(Debug, Clone)]
+pub struct UpdateCheck {
+    pub name: String,
+    pub current: Option<String>,
+    /// Latest upstream version, or the pinned version for pinned sources
+    pub latest: Option<String>,
+    pub pinned: bool,
+    pub error: Option<String>,
+}
+
+impl UpdateCheck {
+    /// Whether installing the server again would change its version
+    pub fn has_update(&self) -> bool {
+        match (&self.current, &self.latest) {
+            (Some(current), Some(latest)) => !versions::versions_match(current, latest),
+            // Unknown installed version: reinstalling records it
+            (None, Some(_)) => true,
+            (_, None) => false,
+        }
+    }
+}
+
+/// Maximum number of installs [`ServerInstaller::install_many`] runs at once
+pub const MAX_CONCURRENT_INSTALLS: usize = 4;
+
+/// Progress of one server in [`ServerInstaller::install_many`]
+#[derive(Debug, Clone)]
+pub enum InstallEvent {
+    Started { name: String },
+    Finished { name: String, binary_path: PathBuf },
+    Failed { name: String, error: String },
+}
+
+/// Outcome of [`ServerInstaller::install_missing`]
+#[derive(Debug, Default)]
+pub struct InstallSummary {
+    /// Servers already available on this machine
+    pub present: Vec<String>,
+    /// Servers that can only be installed by hand (external or system packages)
+    pub manual: Vec<String>,
+    /// Result of every install that was attempted
+    pub results: Vec<(String, Result<PathBuf, LspError>)>,
+}
+
+impl InstallSummary {
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|(_, r)| r.is_err()).count()
+    }
+}
+
+/// Network and cache settings for installs
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// Fail instead of downloading; only cached artifacts are installed
+    pub offline: bool,
+    /// HTTP(S) proxy passed to curl and the package managers
+    pub proxy: Option<String>,
+    /// Comma-separated hosts that bypass the proxy
+    pub no_proxy: Option<String>,
+    /// Directory for downloaded artifacts
+    pub cache_dir: Option<PathBuf>,
+    /// Where to look for installed servers, in order (empty for
+    /// [`discovery::DEFAULT_SEARCH_ORDER`])
+    pub search_order: Vec<BinarySource>,
+    /// Whether npm-sourced servers may run through npx or bunx
+    pub npm_runner: NpmRunner,
+    /// `Some(false)` when servers aren't installed on first use
+    pub auto_install: Option<bool>,
+}
+
+impl InstallOptions {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            offline: settings.offline.unwrap_or(false),
+            proxy: settings.proxy.clone(),
+            no_proxy: settings.no_proxy.clone(),
+            cache_dir: settings.cache_dir.clone(),
+            search_order: settings.binary_search_order.clone().unwrap_or_default(),
+            npm_runner: settings.npm_runner.unwrap_or_default(),
+            auto_install: settings.auto_install,
+        }
+    }
+
+    /// A command with the proxy configured through the environment
+    ///
+    /// curl, npm, cargo and go all honour the conventional proxy variables;
+    /// npm and cargo also get their own so their config files can't shadow ours.
+    pub(crate) fn command(&self, program: &str) -> AsyncCommand {
+        let mut command = AsyncCommand::new(program);
+        command.envs(self.proxy_env());
+        command
+    }
+
+    /// The proxy variables [`Self::command`] sets
+    fn proxy_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = Vec::new();
+        if let Some(proxy) = &self.proxy {
+            for var in [
+                "http_proxy",
+                "https_proxy",
+                "HTTP_PROXY",
+                "HTTPS_PROXY",
+                "npm_config_proxy",
+                "npm_config_https_proxy",
+                "CARGO_HTTP_PROXY",
+            ] {
+                env.push((var, proxy.clone()));
+            }
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            env.push(("no_proxy", no_proxy.clone()));
+            env.push(("NO_PROXY", no_proxy.clone()));
+        }
+        env
+    }
+}
+
+/// Arguments running `binary` from the npm package `package` with `runner`
+fn npm_runner_args(
+    runner: NpmRunner,
+    package: &str,
+    version: Option<&str>,
+    binary: &str,
+    lsp_args: &[String],
+) -> Vec<String> {
+    let spec = match version {
+        Some(version) => format!("{}@{}", package, version),
+        None => package.to_string(),
+    };
+    // The package's binary may not share its name (pyright-langserver)
+    let mut args = match runner {
+        NpmRunner::Bunx => vec!["--package".to_string(), spec, binary.to_string()],
+        _ => vec![
+            "--yes".to_string(),
+            "--package".to_string(),
+            spec,
+            "--".to_string(),
+            binary.to_string(),
+        ],
+    };
+    args.extend(lsp_args.iter().cloned());
+    args
+}
+
+/// Result of [`ServerInstaller::prune`]
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    /// Manifest entries whose binary no longer exists
+    pub stale_entries: Vec<String>,
+    /// Server directories not owned by any manifest entry
+    pub orphaned_paths: Vec<PathBuf>,
+}
+
+impl PruneReport {
+    pub fn is_empty(&self) -> bool {
+        self.stale_entries.is_empty() && self.orphaned_paths.is_empty()
+    }
+}
+
+/// LSP Server installer
+pub struct ServerInstaller {
+    /// LSMCP data directory (~/.local/share/lsmcp)
+    data_dir: PathBuf,
+
+    /// Servers directory (~/.local/share/lsmcp/servers)
+    servers_dir: PathBuf,
+
+    /// Manifest file path
+    manifest_path: PathBuf,
+
+    /// Loaded manifest
+    manifest: InstallManifest,
+
+    /// Network and cache settings
+    options: InstallOptions,
+}
+
+impl ServerInstaller {
+    /// Create a new server installer
+    pub fn new() -> Result<Self, LspError> {
+        Self::with_data_dir(Self::get_data_dir()?)
+    }
+
+    /// Create an installer rooted at `data_dir`
+    fn with_data_dir(data_dir: PathBuf) -> Result<Self, LspError> {
+        let servers_dir = data_dir.join("servers");
+        let manifest_path = data_dir.join("manifest.json");
+
+        // Ensure directories exist
+        fs::create_dir_all(&servers_dir).map_err(LspError::Io)?;
+
+        // Load or create manifest
+        let manifest = if manifest_path.exists() {
+            let content = fs::read_to_string(&manifest_path).map_err(LspError::Io)?;
+            serde_json::from_str(&content).unwrap_or_default()
+        } else {
+            InstallManifest::default()
+        };
+
+        Ok(Self {
+            data_dir,
+            servers_dir,
+            manifest_path,
+            manifest,
+            options: InstallOptions::default(),
+        })
+    }
+
+    /// Apply network and cache settings
+    ///
+    /// The cache defaults to `<data_dir>/cache` when not configured.
+    pub fn with_options(mut self, options: InstallOptions) -> Self {
+        self.set_options(options);
+        self
+    }
+
+    /// Replace the network and cache options (e.g. after a config reload)
+    pub fn set_options(&mut self, mut options: InstallOptions) {
+        if options.cache_dir.is_none() {
+            options.cache_dir = Some(self.data_dir.join("cache"));
+        }
+        self.options = options;
+    }
+
+    /// LSMCP data directory used for manifests, caches and state files
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    /// Get LSMCP data directory
+    pub(crate) fn get_data_dir() -> Result<PathBuf, LspError> {
+        if let Ok(xdg_data) = std::env::var("XDG_DATA_HOME") {
+            Ok(PathBuf::from(xdg_data).join("lsmcp"))
+        } else if let Ok(home) = std::env::var("HOME") {
+            Ok(PathBuf::from(home).join(".local/share/lsmcp"))
+        } else {
+            Err(LspError::ConfigError(
+                "Cannot determine data directory (no $HOME or $XDG_DATA_HOME)".to_string(),
+            ))
+        }
+    }
+
+    /// Find LSP binary in multiple locations
+    ///
+    /// Sources are searched in the `binary_search_order` setting's order.
+    pub fn find_lsp_binary(&self, lsp_name: &str, binary_name: &str) -> Option<PathBuf> {
+        let roots = discovery::SearchRoots::from_env();
+        self.search_order().iter().find_map(|source| match source {
+            BinarySource::Lsmcp => self.find_installed(lsp_name),
+            BinarySource::Path => Self::find_on_path(lsp_name, binary_name),
+            source => roots.find(*source, lsp_name, binary_name),
+        })
+    }
+
+    /// The binary to run for `package`
+    ///
+    /// Commands given as paths are run as they are; bare names are searched
+    /// for as in [`Self::find_lsp_binary`]. The error lists every location
+    /// searched.
+    pub fn resolve_binary(&self, package: &LspPackage) -> Result<PathBuf, LspError> {
+        let binary = &package.bin.primary;
+        let is_path = Path::new(binary).components().count() > 1;
+        let found = if is_path {
+            Some(PathBuf::from(binary)).filter(|path| path.exists())
+        } else {
+            self.find_lsp_binary(&package.name, binary)
+        };
+
+        found.ok_or_else(|| {
+            let missing = self.missing_server(package);
+            match is_path {
+                true => missing.searched(vec!["the configured path".to_string()]),
+                false => missing,
+            }
+            .into_error()
+        })
+    }
+
+    /// Context for an error about `package` missing: where it was looked
+    /// for and whether it would be installed
+    pub fn missing_server(&self, package: &LspPackage) -> MissingServer {
+        MissingServer::new(package)
+            .searched(self.search_locations(&package.name))
+            .install_settings(self.options.auto_install, self.options.offline)
+    }
+
+    /// Whether `package` should run through npx or bunx rather than be
+    /// installed (`npm_runner` is `npx` or `bunx`)
+    pub fn prefers_npm_runner(&self, package: &LspPackage) -> bool {
+        matches!(package.source, InstallSource::Npm { .. })
+            && matches!(self.options.npm_runner, NpmRunner::Npx | NpmRunner::Bunx)
+    }
+
+    /// `package`'s binary config changed to run it through npx or bunx
+    /// without installing it
+    ///
+    /// `None` unless it's sourced from npm, `npm_runner` allows it, lsmcp
+    /// isn't offline and the runner is on `PATH`.
+    pub fn npm_runner_config(&self, package: &LspPackage) -> Option<BinaryConfig> {
+        let InstallSource::Npm {
+            package: npm_package,
+            version,
+        } = &package.source
+        else {
+            return None;
+        };
+        if self.options.offline {
+            return None;
+        }
+        let runners: &[NpmRunner] = match self.options.npm_runner {
+            NpmRunner::Auto => &[NpmRunner::Npx, NpmRunner::Bunx],
+            NpmRunner::Off => &[],
+            ref runner => std::slice::from_ref(runner),
+        };
+        let (runner, program) = runners.iter().find_map(|runner| {
+            let name = match runner {
+                NpmRunner::Bunx => "bunx",
+                _ => "npx",
+            };
+            Self::find_on_path(name, name).map(|path| (*runner, path))
+        })?;
+
+        let mut bin = package.bin.clone();
+        bin.primary = program.to_string_lossy().to_string();
+        bin.lsp_args = npm_runner_args(
+            runner,
+            npm_package,
+            version.as_deref(),
+            &package.bin.primary,
+            &package.bin.lsp_args,
+        );
+        bin.env.extend(
+            self.options
+                .proxy_env()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v)),
+        );
+        Some(bin)
+    }
+
+    /// Where [`Self::find_lsp_binary`] looks, in order
+    pub fn search_locations(&self, lsp_name: &str) -> Vec<String> {
+        let roots = discovery::SearchRoots::from_env();
+        self.search_order()
+            .iter()
+            .flat_map(|source| match source {
+                BinarySource::Lsmcp => vec![self.servers_dir.display().to_string()],
+                BinarySource::Path => vec!["PATH".to_string()],
+                source => roots
+                    .locations(*source, lsp_name)
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn search_order(&self) -> &[BinarySource] {
+        match self.options.search_order.as_slice() {
+            [] => &discovery::DEFAULT_SEARCH_ORDER[..],
+            order => order,
+        }
+    }
+
+    /// `lsp_name`'s binary if lsmcp installed it
+    fn find_installed(&self, lsp_name: &str) -> Option<PathBuf> {
+        let installed = self.manifest.servers.get(lsp_name)?;
+        if installed.binary_path.exists() {
+            debug!("Found {} in LSMCP directory", lsp_name);
+            return Some(installed.binary_path.clone());
+        }
+        None
+    }
+
+    fn find_on_path(lsp_name: &str, binary_name: &str) -> Option<PathBuf> {
+        let output = Command::new("which").arg(binary_name).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let path_buf = PathBuf::from(path);
+        if !path_buf.as_os_str().is_empty() && path_buf.exists() {
+            debug!("Found {} in system PATH", lsp_name);
+            return Some(path_buf);
+        }
+        None
+    }
+
+    /// Install an LSP server
+    pub async fn install_lsp(&mut self, package: &LspPackage) -> Result<PathBuf, LspError> {
+        let (binary_path, version) = self.install_files(package).await?;
+        self.record_install(package, binary_path.clone(), version)?;
+        Ok(binary_path)
+    }
+
+    /// Install several LSP servers concurrently
+    ///
+    /// At most [`MAX_CONCURRENT_INSTALLS`] installs run at once. Packages
+    /// installed from the same npm package (e.g. the vscode-langservers)
+    /// are installed one after another since they share a directory.
+    /// `on_event` is called as each install starts and finishes.
+    pub async fn install_many(
+        &mut self,
+        packages: &[LspPackage],
+        on_event: &(dyn Fn(InstallEvent) + Sync),
+    ) -> Vec<(String, Result<PathBuf, LspError>)> {
+        let mut groups: Vec<Vec<&LspPackage>> = Vec::new();
+        for package in packages {
+            let key = Self::install_group(package);
+            match groups
+                .iter_mut()
+                .find(|group| Self::install_group(group[0]) == key)
+            {
+                Some(group) => group.push(package),
+                None => groups.push(vec![package]),
+            }
+        }
+
+        let this = &*self;
+        // Futures are built up front rather than in `StreamExt::map`, which
+        // would leave the stream not provably `Send` for spawned callers
+        let installs: Vec<_> = groups
+            .into_iter()
+            .map(|group| async move {
+                let mut results = Vec::new();
+                for package in group {
+                    on_event(InstallEvent::Started {
+                        name: package.name.clone(),
+                    });
+                    let result = this.install_files(package).await;
+                    on_event(match &result {
+                        Ok((path, _)) => InstallEvent::Finished {
+                            name: package.name.clone(),
+                            binary_path: path.clone(),
+                        },
+                        Err(e) => InstallEvent::Failed {
+                            name: package.name.clone(),
+                            error: e.to_string(),
+                        },
+                    });
+                    results.push((package, result));
+                }
+                results
+            })
+            .collect();
+        let installed: Vec<_> = futures::stream::iter(installs)
+            .buffer_unordered(MAX_CONCURRENT_INSTALLS)
+            .collect()
+            .await;
+
+        let mut results = Vec::new();
+        for (package, result) in installed.into_iter().flatten() {
+            let result = match result {
+                Ok((path, version)) => self
+                    .record_install(package, path.clone(), version)
+                    .map(|_| path),
+                Err(e) => Err(e),
+            };
+            results.push((package.name.clone(), result));
+        }
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+
+    /// Install every package not already available, concurrently
+    pub async fn install_missing(
+        &mut self,
+        packages: &[LspPackage],
+        on_event: &(dyn Fn(InstallEvent) + Sync),
+    ) -> InstallSummary {
+        let mut summary = InstallSummary::default();
+        let mut missing = Vec::new();
+
+        for package in packages {
+            // Remote servers and TCP servers run by someone else have
+            // nothing to install here
+            if !package.needs_local_binary()
+                || self
+                    .find_lsp_binary(&package.name, &package.bin.primary)
+                    .is_some()
+            {
+                summary.present.push(package.name.clone());
+            } else if matches!(
+                package.source,
+                InstallSource::External { .. } | InstallSource::System { .. }
+            ) {
+                summary.manual.push(package.name.clone());
+            } else {
+                missing.push(package.clone());
+            }
+        }
+
+        summary.results = self.install_many(&missing, on_event).await;
+        summary
+    }
+
+    /// Packages with the same key must not be installed concurrently
+    fn install_group(package: &LspPackage) -> String {
+        match &package.source {
+            InstallSource::Npm { package, .. } => format!("npm:{}", package),
+            _ => package.name.clone(),
+        }
+    }
+
+    /// Download and install a server's files without touching the manifest
+    async fn install_files(
+        &self,
+        package: &LspPackage,
+    ) -> Result<(PathBuf, Option<String>), LspError> {
+        info!("Installing LSP server: {}", package.name);
+
+        let installed = match &package.source {
+            InstallSource::Npm {
+                package: npm_pkg,
+                version,
+            } => {
+                self.install_npm(npm_pkg, version.as_deref(), &package.bin.primary)
+                    .await?
+            }
+            InstallSource::Cargo {
+                crate_name,
+                version,
+            } => {
+                self.install_cargo(crate_name, version.as_deref(), &package.bin.primary)
+                    .await?
+            }
+            InstallSource::Go {
+                package: go_pkg,
+                version,
+            } => {
+                self.install_go(go_pkg, version.as_deref(), &package.bin.primary)
+                    .await?
+            }
+            InstallSource::GithubRelease { repo, tag, asset } => {
+                let server_dir = self.servers_dir.join(&package.name);
+                let release = github::install_release(
+                    repo,
+                    tag.as_deref(),
+                    asset.as_deref(),
+                    &package.bin.primary,
+                    &server_dir,
+                    &self.options,
+                )
+                .await?;
+                (release.binary_path, Some(release.tag))
+            }
+            InstallSource::External { command } => {
+                return Err(LspError::ServerNotFound(
+                    package.name.clone(),
+                    format!(
+                        "Cannot auto-install external command: {}. Please install manually.",
+                        command
+                    ),
+                ));
+            }
+            _ => {
+                return Err(LspError::ServerNotFound(
+                    package.name.clone(),
+                    "Auto-installation not yet supported for this install source type.".to_string(),
+                ));
+            }
+        };
+
+        Ok(installed)
+    }
+
+    /// Record a completed installation in the manifest
+    fn record_install(
+        &mut self,
+        package: &LspPackage,
+        binary_path: PathBuf,
+        version: Option<String>,
+    ) -> Result<(), LspError> {
+        self.manifest.servers.insert(
+            package.name.clone(),
+            InstalledServer {
+                name: package.name.clone(),
+                version,
+                install_date: chrono::Utc::now().to_rfc3339(),
+                binary_path,
+                install_method: format!("{:?}", package.source),
+                source: Some(package.source.clone()),
+            },
+        );
+
+        self.save_manifest()?;
+
+        info!("Successfully installed {}", package.name);
+        Ok(())
+    }
+
+    /// Install from npm
+    async fn install_npm(
+        &self,
+        package: &str,
+        version: Option<&str>,
+        binary: &str,
+    ) -> Result<(PathBuf, Option<String>), LspError> {
+        info!("Installing {} via npm", package);
+
+        let spec = match version {
+            Some(version) => format!("{}@{}", package, version),
+            None => package.to_string(),
+        };
+
+        let server_dir = self.servers_dir.join(package);
+        fs::create_dir_all(&server_dir).map_err(LspError::Io)?;
+
+        // Install locally to server directory
+        let mut command = self.options.command("npm");
+        command.args(["install", "--prefix", server_dir.to_str().unwrap(), &spec]);
+        if let Some(cache_dir) = &self.options.cache_dir {
+            command.arg("--cache").arg(cache_dir.join("npm"));
+        }
+        if self.options.offline {
+            command.arg("--offline");
+        }
+
+        let output = command.output().await.map_err(|e| {
+            LspError::ServerNotFound(
+                package.to_string(),
+                format!("npm not found or failed: {}", e),
+            )
+        })?;
+
+        if !output.status.success() {
+            if self.options.offline {
+                return Err(LspError::Offline(format!("npm package {}", spec)));
+            }
+            return Err(LspError::ServerNotFound(
+                package.to_string(),
+                format!(
+                    "npm install failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+
+        // Find the binary in node_modules/.bin/
+        let binary_path = server_dir.join("node_modules/.bin").join(binary);
+
+        if !binary_path.exists() {
+            return Err(LspError::ServerNotFound(
+                package.to_string(),
+                format!("Binary {} not found after npm install", binary),
+            ));
+        }
+
+        let installed = versions::npm_installed_version(&server_dir, package);
+        Ok((binary_path, installed))
+    }
+
+    /// Install from cargo
+    async fn install_cargo(
+        &self,
+        crate_name: &str,
+        version: Option<&str>,
+        binary: &str,
+    ) -> Result<(PathBuf, Option<String>), LspError> {
+        info!("Installing {} via cargo", crate_name);
+
+        let root = self.servers_dir.to_str().unwrap();
+        // --force so updates replace the previously installed version
+        let mut args = vec!["install", crate_name, "--root", root, "--force"];
+        if let Some(version) = version {
+            args.extend(["--version", version]);
+        }
+        if self.options.offline {
+            args.push("--offline");
+        }
+
+        let output = self
+            .options
+            .command("cargo")
+            .args(&args)
+            .output()
+            .await
+            .map_err(|e| {
+                LspError::ServerNotFound(
+                    crate_name.to_string(),
+                    format!("cargo not found or failed: {}", e),
+                )
+            })?;
+
+        if !output.status.success() {
+            if self.options.offline {
+                return Err(LspError::Offline(format!("crate {}", crate_name)));
+            }
+            return Err(LspError::ServerNotFound(
+                crate_name.to_string(),
+                format!(
+                    "cargo install failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+
+        let binary_path = self.servers_dir.join("bin").join(binary);
+
+        if !binary_path.exists() {
+            return Err(LspError::ServerNotFound(
+                crate_name.to_string(),
+                format!("Binary {} not found after cargo install", binary),
+            ));
+        }
+
+        let installed = AsyncCommand::new("cargo")
+            .args(["install", "--list", "--root", root])
+            .output()
+            .await
+            .ok()
+            .and_then(|output| {
+                versions::parse_cargo_install_list(
+                    &String::from_utf8_lossy(&output.stdout),
+                    crate_name,
+                )
+            });
+
+        Ok((binary_path, installed))
+    }
+
+    /// Install from go
+    async fn install_go(
+        &self,
+        package: &str,
+        version: Option<&str>,
+        binary: &str,
+    ) -> Result<(PathBuf, Option<String>), LspError> {
+        info!("Installing {} via go install", package);
+
+        let gobin = self.servers_dir.join("go-bin");
+        fs::create_dir_all(&gobin).map_err(LspError::Io)?;
+
+        let mut command = self.options.command("go");
+        if self.options.offline {
+            // Resolve only from the module cache
+            command.env("GOPROXY", "off").env("GOFLAGS", "-mod=mod");
+        }
+
+        let output = command
+            .args([
+                "install",
+                &format!("{}@{}", package, version.unwrap_or("latest")),
+            ])
+            .env("GOBIN", gobin.to_str().unwrap())
+            .output()
+            .await
+            .map_err(|e| {
+                LspError::ServerNotFound(
+                    package.to_string(),
+                    format!("go not found or failed: {}", e),
+                )
+            })?;
+
+        if !output.status.success() {
+            if self.options.offline {
+                return Err(LspError::Offline(format!("Go module {}", package)));
+            }
+            return Err(LspError::ServerNotFound(
+                package.to_string(),
+                format!(
+                    "go install failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            ));
+        }
+
+        let binary_path = gobin.join(binary);
+
+        if !binary_path.exists() {
+            return Err(LspError::ServerNotFound(
+                package.to_string(),
+                format!("Binary {} not found after go install", binary),
+            ));
+        }
+
+        let installed = versions::go_binary_version(&binary_path).await;
+        Ok((binary_path, installed))
+    }
+
+    /// Compare installed servers against their latest upstream versions
+    ///
+    /// Servers installed with a pinned version are compared against the pin
+    /// instead, so they are only reported when the pin has changed.
+    pub async fn check_updates(&self) -> Vec<UpdateCheck> {
+        let options = &self.options;
+        let checks = self.manifest.servers.values().map(|installed| async move {
+            let (latest, pinned) = match &installed.source {
+                Some(source) => match Self::pinned_version(source) {
+                    Some(pin) => (Ok(Some(pin.to_string())), true),
+                    None if options.offline => (
+                        Err(LspError::Offline("the latest version".to_string())),
+                        false,
+                    ),
+                    None => (Self::latest_version(source, options).await, false),
+                },
+                None => (Ok(None), false),
+            };
+
+            let (latest, error) = match latest {
+                Ok(latest) => (latest, None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+
+            UpdateCheck {
+                name: installed.name.clone(),
+                current: installed.version.clone(),
+                latest,
+                pinned,
+                error,
+            }
+        });
+
+        let mut results = futures::future::join_all(checks).await;
+        results.sort_by(|a, b| a.name.cmp(&b.name));
+        results
+    }
+
+    /// Version a source is pinned to, if any
+    fn pinned_version(source: &InstallSource) -> Option<&str> {
+        match source {
+            InstallSource::Npm { version, .. }
+            | InstallSource::Cargo { version, .. }
+            | InstallSource::Pip { version, .. }
+            | InstallSource::Go { version, .. } => version.as_deref(),
+            InstallSource::GithubRelease { tag, .. } => tag.as_deref(),
+            InstallSource::System { .. } | InstallSource::External { .. } => None,
+        }
+    }
+
+    /// Latest upstream version of a source, `None` if it can't be checked
+    async fn latest_version(
+        source: &InstallSource,
+        options: &InstallOptions,
+    ) -> Result<Option<String>, LspError> {
+        let latest = match source {
+            InstallSource::Npm { package, .. } => versions::latest_npm(package, options).await?,
+            InstallSource::Cargo { crate_name, .. } => {
+                versions::latest_crate(crate_name, options).await?
+            }
+            InstallSource::Go { package, .. } => versions::latest_go(package, options).await?,
+            InstallSource::GithubRelease { repo, .. } => {
+                github::fetch_release(repo, None, options).await?.tag_name
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(latest))
+    }
+
+    /// Upgrade an installed server in place
+    ///
+    /// The previous installation is moved aside first and restored if the
+    /// new install fails, so a failed update never leaves the server missing.
+    pub async fn update_lsp(&mut self, package: &LspPackage) -> Result<PathBuf, LspError> {
+        let Some(previous) = self.manifest.servers.get(&package.name).cloned() else {
+            return self.install_lsp(package).await;
+        };
+
+        let backup = match self.install_path(&previous.binary_path) {
+            Some(path) if path.exists() => {
+                let backup_dir = self.data_dir.join("backup");
+                fs::create_dir_all(&backup_dir).map_err(LspError::Io)?;
+
+                let backup_path = backup_dir.join(&package.name);
+                remove_path(&backup_path)?;
+                fs::rename(&path, &backup_path).map_err(LspError::Io)?;
+                debug!("Moved {} to {}", path.display(), backup_path.display());
+                Some((path, backup_path))
+            }
+            _ => None,
+        };
+
+        match self.install_lsp(package).await {
+            Ok(binary_path) => {
+                if let Some((_, backup_path)) = backup {
+                    remove_path(&backup_path)?;
+                }
+                Ok(binary_path)
+            }
+            Err(e) => {
+                warn!("Update of {} failed, rolling back: {}", package.name, e);
+                if let Some((path, backup_path)) = backup {
+                    remove_path(&path)?;
+                    fs::rename(&backup_path, &path).map_err(LspError::Io)?;
+                }
+                self.manifest.servers.insert(package.name.clone(), previous);
+                self.save_manifest()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Save manifest to disk
+    fn save_manifest(&self) -> Result<(), LspError> {
+        let content = serde_json::to_string_pretty(&self.manifest)
+            .map_err(|e| LspError::ConfigError(format!("Failed to serialize manifest: {}", e)))?;
+
+        fs::write(&self.manifest_path, content).map_err(LspError::Io)?;
+
+        Ok(())
+    }
+
+    /// List all installed servers
+    pub fn list_installed(&self) -> Vec<&InstalledServer> {
+        self.manifest.servers.values().collect()
+    }
+
+    /// Uninstall a server installed by LSMCP
+    ///
+    /// Returns `false` if the server is not in the manifest.
+    pub fn uninstall(&mut self, name: &str) -> Result<bool, LspError> {
+        let Some(installed) = self.manifest.servers.remove(name) else {
+            return Ok(false);
+        };
+
+        info!("Uninstalling {}", name);
+
+        if let Some(path) = self.install_path(&installed.binary_path) {
+            remove_path(&path)?;
+            debug!("Removed {}", path.display());
+        }
+
+        self.save_manifest()?;
+        Ok(true)
+    }
+
+    /// Remove manifest entries whose binaries are gone and server
+    /// directories that no manifest entry owns
+    ///
+    /// With `dry_run`, only reports what would be removed.
+    pub fn prune(&mut self, dry_run: bool) -> Result<PruneReport, LspError> {
+        let mut report = PruneReport::default();
+
+        let mut stale: Vec<String> = self
+            .manifest
+            .servers
+            .values()
+            .filter(|s| !s.binary_path.exists())
+            .map(|s| s.name.clone())
+            .collect();
+        stale.sort();
+
+        let owned: std::collections::HashSet<PathBuf> = self
+            .manifest
+            .servers
+            .values()
+            .filter(|s| !stale.contains(&s.name))
+            .filter_map(|s| self.install_path(&s.binary_path))
+            .collect();
+
+        for path in self.server_dirs()? {
+            if !owned.contains(&path) {
+                report.orphaned_paths.push(path);
+            }
+        }
+        report.orphaned_paths.sort();
+
+        if !dry_run {
+            for name in &stale {
+                info!("Pruning stale manifest entry {}", name);
+                self.manifest.servers.remove(name);
+            }
+            for path in &report.orphaned_paths {
+                info!("Pruning orphaned {}", path.display());
+                remove_path(path)?;
+            }
+            if !stale.is_empty() {
+                self.save_manifest()?;
+            }
+        }
+
+        report.stale_entries = stale;
+        Ok(report)
+    }
+
+    /// Per-server install directories under `servers/`
+    ///
+    /// The shared cargo and go bin directories are skipped since their
+    /// contents can't be attributed reliably, and so are hidden ones.
+    fn server_dirs(&self) -> Result<Vec<PathBuf>, LspError> {
+        let mut dirs = Vec::new();
+
+        for entry in fs::read_dir(&self.servers_dir).map_err(LspError::Io)? {
+            let path = entry.map_err(LspError::Io)?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+
+            // Hidden directories hold unfinished and replaced installs
+            if !path.is_dir() || name == "bin" || name == "go-bin" || name.starts_with('.') {
+                continue;
+            }
+
+            if name.starts_with('@') {
+                for scoped in fs::read_dir(&path).map_err(LspError::Io)? {
+                    dirs.push(scoped.map_err(LspError::Io)?.path());
+                }
+            } else {
+                dirs.push(path);
+            }
+        }
+
+        Ok(dirs)
+    }
+
+    /// The file or directory owned by an installed binary
+    ///
+    /// npm installs get their own directory under `servers/`, while cargo and
+    /// go installs share `servers/bin` and `servers/go-bin`, so only the
+    /// binary itself is owned. Paths outside `servers/` are never owned.
+    fn install_path(&self, binary_path: &Path) -> Option<PathBuf> {
+        let relative = binary_path.strip_prefix(&self.servers_dir).ok()?;
+        let mut components = relative.components().map(|c| c.as_os_str());
+        let first = components.next()?;
+
+        if first == "bin" || first == "go-bin" {
+            Some(binary_path.to_path_buf())
+        } else if first.to_string_lossy().starts_with('@') {
+            // Scoped npm packages live two levels deep (servers/@scope/name)
+            Some(self.servers_dir.join(first).join(components.next()?))
+        } else {
+            Some(self.servers_dir.join(first))
+        }
+    }
+}
+
+/// Remove a file or directory if it exists
+fn remove_path(path: &Path) -> Result<(), LspError> {
+    if path.is_dir() {
+        fs::remove_dir_all(path).map_err(LspError::Io)
+    } else if path.exists() {
+        fs::remove_file(path).map_err(LspError::Io)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(installer: &mut ServerInstaller, name: &str, binary_path: PathBuf) {
+        installer.manifest.servers.insert(
+            name.to_string(),
+            InstalledServer {
+                name: name.to_string(),
+                version: None,
+                install_date: String::new(),
+                binary_path,
+                install_method: String::new(),
+                source: None,
+            },
+        );
+    }
+
+    #[test]
+    fn test_prune_and_uninstall() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut installer = ServerInstaller::with_data_dir(tmp.path().to_path_buf()).unwrap();
+        let servers = installer.servers_dir.clone();
+
+        // Installed npm server, scoped npm server and cargo binary
+        let pyright = servers.join("pyright/node_modules/.bin/pyright");
+        let vue = servers.join("@vue/language-server/node_modules/.bin/vue-language-server");
+        let taplo = servers.join("bin/taplo");
+        for binary in [&pyright, &vue, &taplo] {
+            fs::create_dir_all(binary.parent().unwrap()).unwrap();
+            fs::write(binary, "").unwrap();
+        }
+        record(&mut installer, "pyright", pyright);
+        record(&mut installer, "vue-language-server", vue);
+        record(&mut installer, "taplo", taplo.clone());
+
+        // Entry whose binary was deleted, and a directory nobody owns
+        record(&mut installer, "gone", servers.join("gone/bin/gone"));
+        fs::create_dir_all(servers.join("leftover")).unwrap();
+
+        let report = installer.prune(true).unwrap();
+        assert_eq!(report.stale_entries, vec!["gone".to_string()]);
+        assert_eq!(report.orphaned_paths, vec![servers.join("leftover")]);
+        assert!(servers.join("leftover").exists());
+
+        installer.prune(false).unwrap();
+        assert!(!servers.join("leftover").exists());
+        assert!(!installer.manifest.servers.contains_key("gone"));
+        assert!(installer.prune(true).unwrap().is_empty());
+
+        assert!(installer.uninstall("vue-language-server").unwrap());
+        assert!(!servers.join("@vue/language-server").exists());
+        assert!(installer.uninstall("taplo").unwrap());
+        assert!(!taplo.exists() && servers.join("bin").exists());
+        assert!(!installer.uninstall("taplo").unwrap());
+    }
+
+    #[test]
+    fn test_npm_runner_args() {
+        let lsp_args = ["--stdio".to_string()];
+        assert_eq!(
+            npm_runner_args(
+                NpmRunner::Npx,
+                "pyright",
+                Some("1.1.400"),
+                "pyright-langserver",
+                &lsp_args
+            ),
+            [
+                "--yes",
+                "--package",
+                "pyright@1.1.400",
+                "--",
+                "pyright-langserver",
+                "--stdio"
+            ]
+        );
+        assert_eq!(
+            npm_runner_args(
+                NpmRunner::Bunx,
+                "typescript-language-server",
+                None,
+                "typescript-language-server",
+                &lsp_args
+            ),
+            [
+                "--package",
+                "typescript-language-server",
+                "typescript-language-server",
+                "--stdio"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_binary() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut installer = ServerInstaller::with_data_dir(tmp.path().to_path_buf())
+            .unwrap()
+            .with_options(InstallOptions {
+                search_order: vec![BinarySource::Lsmcp],
+                ..InstallOptions::default()
+            });
+        let mut package = crate::config::get_default_configs()["rust"].clone();
+
+        let missing = installer.resolve_binary(&package).unwrap_err().to_string();
+        assert!(missing.contains(&tmp.path().join("servers").display().to_string()));
+        assert!(missing.contains("lsmcp install rust-analyzer"));
+
+        let managed = installer.servers_dir.join("rust-analyzer/rust-analyzer");
+        fs::create_dir_all(managed.parent().unwrap()).unwrap();
+        fs::write(&managed, "").unwrap();
+        record(&mut installer, "rust-analyzer", managed.clone());
+        assert_eq!(installer.resolve_binary(&package).unwrap(), managed);
+
+        // A command given as a path is used as is, never a managed install
+        let command = tmp.path().join("my-rust-analyzer");
+        package.source = InstallSource::External {
+            command: command.display().to_string(),
+        };
+        package.bin.primary = command.display().to_string();
+        assert!(installer
+            .resolve_binary(&package)
+            .unwrap_err()
+            .to_string()
+            .contains("the configured path"));
+        fs::write(&command, "").unwrap();
+        assert_eq!(installer.resolve_binary(&package).unwrap(), command);
+    }
+}
@@ -0,0 +1,176 @@
+//! Installed and upstream version lookups
+//!
+//! Package managers are queried through their own CLIs where possible so
+//! that registry mirrors and credentials configured for them keep working.
+
+use crate::installer::InstallOptions;
+use crate::types::LspError;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Version of an npm package installed under `prefix`
+pub fn npm_installed_version(prefix: &Path, package: &str) -> Option<String> {
+    let manifest = prefix
+        .join("node_modules")
+        .join(package)
+        .join("package.json");
+    let content = std::fs::read_to_string(manifest).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+    value["version"].as_str().map(String::from)
+}
+
+/// Version of a crate from `cargo install --list` output
+pub fn parse_cargo_install_list(output: &str, crate_name: &str) -> Option<String> {
+    // Lines look like `taplo-cli v0.9.3:` followed by indented binary names
+    output.lines().find_map(|line| {
+        let (name, rest) = line.split_once(' ')?;
+        if name != crate_name {
+            return None;
+        }
+        let version = rest.trim_end_matches(':').split_whitespace().next()?;
+        Some(version.trim_start_matches('v').to_string())
+    })
+}
+
+/// Module version from `go version -m <binary>` output
+pub fn parse_go_version_m(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some("mod"), Some(_), Some(version)) => Some(version.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Compare versions ignoring a leading `v` (`v1.2.0` == `1.2.0`)
+pub fn versions_match(a: &str, b: &str) -> bool {
+    a.trim_start_matches('v') == b.trim_start_matches('v')
+}
+
+async fn run(
+    program: &str,
+    args: &[&str],
+    name: &str,
+    options: &InstallOptions,
+) -> Result<String, LspError> {
+    let output = options
+        .command(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| {
+            LspError::ServerNotFound(
+                name.to_string(),
+                format!("{} not found or failed: {}", program, e),
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(LspError::ServerNotFound(
+            name.to_string(),
+            format!(
+                "{} {} failed: {}",
+                program,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Latest published version of an npm package
+pub async fn latest_npm(package: &str, options: &InstallOptions) -> Result<String, LspError> {
+    run("npm", &["view", package, "version"], package, options).await
+}
+
+/// Latest stable version of a crate on crates.io
+pub async fn latest_crate(crate_name: &str, options: &InstallOptions) -> Result<String, LspError> {
+    #[derive(Deserialize)]
+    struct CrateResponse {
+        #[serde(rename = "crate")]
+        krate: CrateInfo,
+    }
+
+    #[derive(Deserialize)]
+    struct CrateInfo {
+        max_stable_version: Option<String>,
+        max_version: String,
+    }
+
+    // crates.io rejects requests without a descriptive user agent
+    let user_agent = format!("lsmcp/{}", env!("CARGO_PKG_VERSION"));
+    let url = format!("https://crates.io/api/v1/crates/{}", crate_name);
+    let body = run(
+        "curl",
+        &["-fsSL", "-A", &user_agent, &url],
+        crate_name,
+        options,
+    )
+    .await?;
+
+    let response: CrateResponse = serde_json::from_str(&body).map_err(|e| {
+        LspError::ServerNotFound(
+            crate_name.to_string(),
+            format!("Invalid crates.io response: {}", e),
+        )
+    })?;
+
+    Ok(response
+        .krate
+        .max_stable_version
+        .unwrap_or(response.krate.max_version))
+}
+
+/// Latest version of a Go module
+pub async fn latest_go(package: &str, options: &InstallOptions) -> Result<String, LspError> {
+    run(
+        "go",
+        &[
+            "list",
+            "-m",
+            "-f",
+            "{{.Version}}",
+            &format!("{}@latest", package),
+        ],
+        package,
+        options,
+    )
+    .await
+}
+
+/// Version recorded in a Go binary's build info
+pub async fn go_binary_version(binary: &Path) -> Option<String> {
+    let output = run(
+        "go",
+        &["version", "-m", binary.to_str()?],
+        "go",
+        &InstallOptions::default(),
+    )
+    .await
+    .ok()?;
+    parse_go_version_m(&output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_output() {
+        let cargo = "taplo-cli v0.9.3:\n    taplo\ntexlab v5.19.0:\n    texlab\n";
+        assert_eq!(
+            parse_cargo_install_list(cargo, "texlab").as_deref(),
+            Some("5.19.0")
+        );
+        assert_eq!(parse_cargo_install_list(cargo, "taplo"), None);
+
+        let go = "gopls: go1.22.1\n\tpath\tgolang.org/x/tools/gopls\n\tmod\tgolang.org/x/tools/gopls\tv0.15.2\th1:abc=\n";
+        assert_eq!(parse_go_version_m(go).as_deref(), Some("v0.15.2"));
+
+        assert!(versions_match("v1.2.0", "1.2.0"));
+        assert!(!versions_match("1.2.0", "1.3.0"));
+    }
+}
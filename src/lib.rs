@@ -4,16 +4,21 @@
 //! Language Server Protocol (LSP) servers, enabling rich code intelligence
 //! for CLI-based LLM tools.
 
+pub mod check;
 pub mod config;
 pub mod installer;
 pub mod lsp;
 pub mod mcp;
+pub mod metrics;
+pub mod registry;
 pub mod tools;
+pub mod trace;
 pub mod types;
+pub mod usage;
 pub mod utils;
 
 pub use config::ConfigLoader;
 pub use installer::ServerInstaller;
 pub use lsp::{LspClient, LspManager};
-pub use mcp::McpServer;
+pub use mcp::{McpServer, McpService};
 pub use types::LspError;
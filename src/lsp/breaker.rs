@@ -0,0 +1,223 @@
+//! Circuit breaker on a server's requests
+//!
+//! A wedged server answers nothing, and each agent call would wait out the
+//! full request timeout against it. After `circuit_breaker_failures`
+//! requests in a row time out or lose the server, the breaker opens: calls
+//! fail at once with `LspError::CircuitOpen` for `circuit_breaker_cooldown_secs`.
+//! Then one request is let through to try the server again (half-open); an
+//! answer, even an error response, closes the breaker, and another failure
+//! opens it for a further cooldown.
+
+use crate::types::LspError;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Failures in a row that open the breaker unless configured otherwise
+pub const DEFAULT_BREAKER_FAILURES: u32 = 3;
+
+/// How long the breaker stays open unless configured otherwise
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Whether `error` means the server didn't answer, as opposed to answering
+/// with an error
+pub fn is_failure(error: &LspError) -> bool {
+    matches!(
+        error,
+        LspError::Timeout(_) | LspError::ServerCrashed(_) | LspError::ConnectionFailed(..)
+    )
+}
+
+/// Where a server's breaker stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Requests go through; `failures` in a row so far
+    Closed { failures: u32 },
+    /// Requests fail at once until `retry_in` has passed
+    Open { failures: u32, retry_in: Duration },
+    /// A request is trying the server again
+    HalfOpen { failures: u32 },
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// 0 when turned off
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    /// Set while open or half-open
+    open_until: Option<Instant>,
+    /// When the half-open trial request was let through; a trial that never
+    /// reports back (its call was dropped) is replaced after a cooldown
+    trial: Option<Instant>,
+    /// Times the breaker opened since the server started
+    trips: u64,
+}
+
+/// Fails a server's requests fast while it doesn't answer
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    server: String,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            inner: Mutex::new(Inner {
+                threshold: DEFAULT_BREAKER_FAILURES,
+                cooldown: DEFAULT_BREAKER_COOLDOWN,
+                failures: 0,
+                open_until: None,
+                trial: None,
+                trips: 0,
+            }),
+        }
+    }
+
+    /// Open after `threshold` failures in a row (0 to never open), for
+    /// `cooldown` at a time
+    pub fn configure(&self, threshold: u32, cooldown: Duration) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.threshold = threshold;
+        inner.cooldown = cooldown;
+        if threshold == 0 {
+            inner.open_until = None;
+            inner.trial = None;
+        }
+    }
+
+    /// Let a request through, or fail it while the breaker is open
+    pub fn admit(&self) -> Result<(), LspError> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(open_until) = inner.open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let trial_running = inner
+            .trial
+            .is_some_and(|started| now < started + inner.cooldown);
+        let retry_in = if now < open_until {
+            open_until - now
+        } else if trial_running {
+            // Answered by the trial; until then, as good as open
+            Duration::ZERO
+        } else {
+            inner.trial = Some(now);
+            info!("Trying {} again after its cooldown", self.server);
+            return Ok(());
+        };
+        Err(LspError::CircuitOpen {
+            server: self.server.clone(),
+            failures: inner.failures,
+            retry_in_secs: retry_in.as_secs_f64().ceil() as u64,
+        })
+    }
+
+    /// Record how a request let through went: `failed` if the server didn't
+    /// answer
+    pub fn record(&self, failed: bool) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if !failed {
+            if inner.open_until.is_some() {
+                info!("{} is answering again", self.server);
+            }
+            inner.failures = 0;
+            inner.open_until = None;
+            inner.trial = None;
+            return;
+        }
+
+        inner.failures += 1;
+        let now = Instant::now();
+        let trip = match inner.open_until {
+            // Requests sent before it opened, failing in turn
+            Some(until) if now < until => false,
+            // The trial
+            Some(_) => true,
+            None => inner.failures >= inner.threshold,
+        };
+        if inner.threshold > 0 && trip {
+            inner.open_until = Some(now + inner.cooldown);
+            inner.trial = None;
+            inner.trips += 1;
+            warn!(
+                "{} failed {} request(s) in a row; failing its requests for {}s",
+                self.server,
+                inner.failures,
+                inner.cooldown.as_secs()
+            );
+        }
+    }
+
+    /// Where the breaker stands now
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let failures = inner.failures;
+        match inner.open_until {
+            None => BreakerState::Closed { failures },
+            Some(until) => match until.checked_duration_since(Instant::now()) {
+                Some(retry_in) => BreakerState::Open { failures, retry_in },
+                None => BreakerState::HalfOpen { failures },
+            },
+        }
+    }
+
+    /// Times the breaker opened since the server started
+    pub fn trips(&self) -> u64 {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).trips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new("pyright");
+        breaker.configure(2, Duration::from_millis(50));
+
+        breaker.record(true);
+        assert!(breaker.admit().is_ok());
+        // An answer resets the count
+        breaker.record(false);
+        breaker.record(true);
+        assert_eq!(breaker.state(), BreakerState::Closed { failures: 1 });
+
+        breaker.record(true);
+        let error = breaker.admit().unwrap_err();
+        assert!(matches!(error, LspError::CircuitOpen { failures: 2, .. }));
+        assert_eq!(error.kind(), "server_unresponsive");
+        // Requests already in flight failing don't extend the cooldown
+        breaker.record(true);
+        assert_eq!(breaker.trips(), 1);
+
+        // After the cooldown one request tries again; the rest still fail
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen { failures: 3 });
+        assert!(breaker.admit().is_ok());
+        assert!(breaker.admit().is_err());
+
+        // The trial failing opens the breaker again at once
+        breaker.record(true);
+        assert!(matches!(
+            breaker.state(),
+            BreakerState::Open { failures: 4, .. }
+        ));
+        assert_eq!(breaker.trips(), 2);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.admit().is_ok());
+        breaker.record(false);
+        assert_eq!(breaker.state(), BreakerState::Closed { failures: 0 });
+
+        // Turned off, it never opens
+        breaker.configure(0, Duration::from_millis(50));
+        for _ in 0..5 {
+            breaker.record(true);
+        }
+        assert!(breaker.admit().is_ok());
+    }
+}
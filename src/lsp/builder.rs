@@ -0,0 +1,145 @@
+//! Builders for embedding lsmcp as an LSP client library
+//!
+//! The binary sets its manager up from the user's config and command line;
+//! other Rust tools get the same through `LspManager::builder`, and can
+//! drive a single server directly with `LspClient::builder`, handing it
+//! streams of their own instead of letting it start the server.
+
+use crate::config::{ConfigLoader, LspPackage, Settings};
+use crate::lsp::process::Transport;
+use crate::lsp::{orphans, LspClient, LspManager};
+use crate::types::LspError;
+use crate::utils::uri::PathMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Sets up an `LspManager` for a workspace
+pub struct LspManagerBuilder {
+    workspace_root: PathBuf,
+    config: Option<ConfigLoader>,
+    settings: Settings,
+    background_tasks: bool,
+}
+
+impl LspManager {
+    /// A builder for a manager serving `workspace_root`, configured from
+    /// the user's config file unless given a config
+    pub fn builder(workspace_root: impl Into<PathBuf>) -> LspManagerBuilder {
+        LspManagerBuilder {
+            workspace_root: workspace_root.into(),
+            config: None,
+            settings: Settings::default(),
+            background_tasks: false,
+        }
+    }
+}
+
+impl LspManagerBuilder {
+    /// Use `config` instead of loading the user's
+    pub fn with_config(mut self, config: ConfigLoader) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Settings layered over the config's, as command-line flags are; they
+    /// survive config reloads
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = self.settings.overlaid(&settings);
+        self
+    }
+
+    /// Run what `lsmcp serve` runs in the background: idle shutdown,
+    /// orphan reaping, session persistence, file watching and the
+    /// workspace scan
+    pub fn with_background_tasks(mut self) -> Self {
+        self.background_tasks = true;
+        self
+    }
+
+    /// Create the manager; background tasks need a Tokio runtime
+    pub fn build(self) -> Result<Arc<LspManager>, LspError> {
+        let mut config = match self.config {
+            Some(config) => config,
+            None => ConfigLoader::new()?,
+        };
+        let cli_settings = config.settings_mut();
+        *cli_settings = std::mem::take(cli_settings).overlaid(&self.settings);
+
+        let manager = Arc::new(LspManager::new(self.workspace_root, Arc::new(config))?);
+        if self.background_tasks {
+            manager.spawn_idle_reaper();
+            orphans::spawn_orphan_reaper();
+            manager.spawn_session_persistence();
+            manager.spawn_file_watcher();
+            manager.spawn_workspace_scan();
+        }
+        Ok(manager)
+    }
+}
+
+/// Starts an `LspClient` for one server
+pub struct LspClientBuilder {
+    language: String,
+    package: LspPackage,
+    workspace_root: PathBuf,
+    path_map: Option<PathMap>,
+    transport: Option<Transport>,
+}
+
+impl LspClient {
+    /// A builder for a client of `package`'s server, serving `language`
+    /// files under `workspace_root`
+    pub fn builder(
+        language: impl Into<String>,
+        package: LspPackage,
+        workspace_root: impl Into<PathBuf>,
+    ) -> LspClientBuilder {
+        LspClientBuilder {
+            language: language.into(),
+            package,
+            workspace_root: workspace_root.into(),
+            path_map: None,
+            transport: None,
+        }
+    }
+}
+
+impl LspClientBuilder {
+    /// Rewrite the paths the server reports (see `[path_map]`)
+    pub fn with_path_map(mut self, path_map: PathMap) -> Self {
+        self.path_map = Some(path_map);
+        self
+    }
+
+    /// Talk to a server over `transport` instead of starting the package's
+    /// command
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Start the server, unless given a transport, and initialize it
+    pub async fn start(self) -> Result<LspClient, LspError> {
+        match self.transport {
+            Some(transport) => {
+                LspClient::connect(
+                    self.language,
+                    self.package,
+                    self.workspace_root,
+                    self.path_map,
+                    transport,
+                )
+                .await
+            }
+            None => {
+                LspClient::spawn(
+                    self.language,
+                    self.package,
+                    self.workspace_root,
+                    self.path_map,
+                )
+                .await
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Caching of idempotent query responses
+//!
+//! Hover, definition and document symbol answers depend only on the
+//! documents the servers have seen, so they are kept until a document
+//! changes. Each entry is stamped with the content generation of every
+//! client that answered it (see `LspClient::content_generation`); a
+//! `didChange` to any of those clients, or a client restart, gives a new
+//! stamp and the entry is no longer used. Entries also expire after a TTL,
+//! since files the servers haven't opened can change on disk unseen.
+
+use crate::utils::position::PositionEncoding;
+use lsp_types::Position;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Most entries kept; the oldest are dropped beyond this
+const MAX_ENTRIES: usize = 1000;
+
+/// What a response was computed for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    method: &'static str,
+    file: PathBuf,
+    position: Option<(u32, u32)>,
+    encoding: PositionEncoding,
+}
+
+impl CacheKey {
+    pub fn new(
+        method: &'static str,
+        file: &Path,
+        position: Option<Position>,
+        encoding: PositionEncoding,
+    ) -> Self {
+        Self {
+            method,
+            file: file.to_path_buf(),
+            position: position.map(|p| (p.line, p.character)),
+            encoding,
+        }
+    }
+}
+
+struct Entry {
+    stamp: Vec<u64>,
+    inserted_at: Instant,
+    value: Value,
+}
+
+/// Cache counters, for `lsp_server_status`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<CacheKey, Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Responses by key, valid while their stamp matches
+pub struct ResponseCache {
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached response for `key`, if it was stored under `stamp` and
+    /// hasn't expired
+    pub fn get(&self, key: &CacheKey, stamp: &[u64]) -> Option<Value> {
+        let mut inner = self.lock();
+        let fresh = inner
+            .entries
+            .get(key)
+            .filter(|entry| entry.stamp == stamp && entry.inserted_at.elapsed() < self.ttl)
+            .map(|entry| entry.value.clone());
+
+        match fresh {
+            Some(value) => {
+                inner.hits += 1;
+                Some(value)
+            }
+            None => {
+                inner.misses += 1;
+                inner.entries.remove(key);
+                None
+            }
+        }
+    }
+
+    /// Store a response computed under `stamp`
+    pub fn insert(&self, key: CacheKey, stamp: Vec<u64>, value: Value) {
+        let mut inner = self.lock();
+        if inner.entries.len() >= MAX_ENTRIES && !inner.entries.contains_key(&key) {
+            let ttl = self.ttl;
+            inner
+                .entries
+                .retain(|_, entry| entry.inserted_at.elapsed() < ttl);
+            if inner.entries.len() >= MAX_ENTRIES {
+                let oldest = inner
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.inserted_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    inner.entries.remove(&oldest);
+                }
+            }
+        }
+        inner.entries.insert(
+            key,
+            Entry {
+                stamp,
+                inserted_at: Instant::now(),
+                value,
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        CacheStats {
+            entries: inner.entries.len(),
+            hits: inner.hits,
+            misses: inner.misses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(line: u32) -> CacheKey {
+        CacheKey::new(
+            "textDocument/hover",
+            Path::new("/src/main.rs"),
+            Some(Position::new(line, 0)),
+            PositionEncoding::Utf16,
+        )
+    }
+
+    #[test]
+    fn test_entries_follow_stamp() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        cache.insert(key(1), vec![7], json!("foo"));
+
+        assert_eq!(cache.get(&key(1), &[7]), Some(json!("foo")));
+        assert_eq!(cache.get(&key(2), &[7]), None);
+
+        // A document changed since: the entry is dropped
+        assert_eq!(cache.get(&key(1), &[8]), None);
+        assert_eq!(cache.get(&key(1), &[7]), None);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                entries: 0,
+                hits: 1,
+                misses: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = ResponseCache::new(Duration::ZERO);
+        cache.insert(key(1), vec![7], json!("foo"));
+        assert_eq!(cache.get(&key(1), &[7]), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! The capabilities lsmcp advertises in `initialize`
+//!
+//! Servers tailor their answers to these, so leaving a feature out costs
+//! more than its own requests: without markdown, hover comes back as plain
+//! text, and without hierarchical symbol support `documentSymbol` comes
+//! back flat. Features lsmcp can't handle stay out (`workspace/applyEdit`,
+//! file operations in edits), since servers would rely on them.
+
+use crate::utils::position::PositionEncoding;
+use lsp_types::*;
+
+/// Every symbol kind of LSP 3.17, which servers fall back from otherwise
+const SYMBOL_KINDS: [SymbolKind; 26] = [
+    SymbolKind::FILE,
+    SymbolKind::MODULE,
+    SymbolKind::NAMESPACE,
+    SymbolKind::PACKAGE,
+    SymbolKind::CLASS,
+    SymbolKind::METHOD,
+    SymbolKind::PROPERTY,
+    SymbolKind::FIELD,
+    SymbolKind::CONSTRUCTOR,
+    SymbolKind::ENUM,
+    SymbolKind::INTERFACE,
+    SymbolKind::FUNCTION,
+    SymbolKind::VARIABLE,
+    SymbolKind::CONSTANT,
+    SymbolKind::STRING,
+    SymbolKind::NUMBER,
+    SymbolKind::BOOLEAN,
+    SymbolKind::ARRAY,
+    SymbolKind::OBJECT,
+    SymbolKind::KEY,
+    SymbolKind::NULL,
+    SymbolKind::ENUM_MEMBER,
+    SymbolKind::STRUCT,
+    SymbolKind::EVENT,
+    SymbolKind::OPERATOR,
+    SymbolKind::TYPE_PARAMETER,
+];
+
+/// Markdown first: it's what callers get back either way
+fn content_formats() -> Vec<MarkupKind> {
+    vec![MarkupKind::Markdown, MarkupKind::PlainText]
+}
+
+fn symbol_kinds() -> Option<SymbolKindCapability> {
+    Some(SymbolKindCapability {
+        value_set: Some(SYMBOL_KINDS.to_vec()),
+    })
+}
+
+/// What lsmcp supports, sent with every `initialize`
+pub fn client_capabilities() -> ClientCapabilities {
+    let goto = Some(GotoCapability {
+        dynamic_registration: None,
+        link_support: Some(true),
+    });
+
+    ClientCapabilities {
+        general: Some(GeneralClientCapabilities {
+            // Columns are translated for callers anyway, so let the server
+            // pick whatever it counts natively
+            position_encodings: Some(
+                [
+                    PositionEncoding::Utf8,
+                    PositionEncoding::Utf32,
+                    PositionEncoding::Utf16,
+                ]
+                .map(PositionEncoding::to_lsp)
+                .to_vec(),
+            ),
+            markdown: Some(MarkdownClientCapabilities {
+                parser: "marked".to_string(),
+                version: None,
+                allowed_tags: None,
+            }),
+            ..Default::default()
+        }),
+        workspace: Some(WorkspaceClientCapabilities {
+            workspace_folders: Some(true),
+            // Answered with nulls, so servers use their defaults instead
+            // of waiting for `didChangeConfiguration`
+            configuration: Some(true),
+            did_change_watched_files: Some(DidChangeWatchedFilesClientCapabilities {
+                dynamic_registration: Some(true),
+                relative_pattern_support: Some(true),
+            }),
+            workspace_edit: Some(WorkspaceEditClientCapabilities {
+                document_changes: Some(true),
+                ..Default::default()
+            }),
+            symbol: Some(WorkspaceSymbolClientCapabilities {
+                symbol_kind: symbol_kinds(),
+                resolve_support: Some(WorkspaceSymbolResolveSupportCapability {
+                    properties: vec!["location.range".to_string()],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        text_document: Some(TextDocumentClientCapabilities {
+            completion: Some(CompletionClientCapabilities {
+                completion_item: Some(CompletionItemCapability {
+                    snippet_support: Some(true),
+                    documentation_format: Some(content_formats()),
+                    deprecated_support: Some(true),
+                    label_details_support: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            hover: Some(HoverClientCapabilities {
+                dynamic_registration: None,
+                content_format: Some(content_formats()),
+            }),
+            signature_help: Some(SignatureHelpClientCapabilities {
+                signature_information: Some(SignatureInformationSettings {
+                    documentation_format: Some(content_formats()),
+                    parameter_information: Some(ParameterInformationSettings {
+                        label_offset_support: Some(true),
+                    }),
+                    active_parameter_support: Some(true),
+                }),
+                ..Default::default()
+            }),
+            definition: goto,
+            type_definition: goto,
+            implementation: goto,
+            declaration: goto,
+            document_symbol: Some(DocumentSymbolClientCapabilities {
+                symbol_kind: symbol_kinds(),
+                hierarchical_document_symbol_support: Some(true),
+                ..Default::default()
+            }),
+            rename: Some(RenameClientCapabilities {
+                prepare_support: Some(true),
+                ..Default::default()
+            }),
+            publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+                related_information: Some(true),
+                tag_support: Some(TagSupport {
+                    value_set: vec![DiagnosticTag::UNNECESSARY, DiagnosticTag::DEPRECATED],
+                }),
+                version_support: Some(true),
+                code_description_support: Some(true),
+                data_support: Some(true),
+            }),
+            // Literal actions carry their edits, which is what callers
+            // need to apply a fix
+            code_action: Some(CodeActionClientCapabilities {
+                code_action_literal_support: Some(CodeActionLiteralSupport {
+                    code_action_kind: CodeActionKindLiteralSupport {
+                        value_set: [
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR,
+                            CodeActionKind::SOURCE,
+                        ]
+                        .map(|kind| kind.as_str().to_string())
+                        .to_vec(),
+                    },
+                }),
+                is_preferred_support: Some(true),
+                data_support: Some(true),
+                resolve_support: Some(CodeActionCapabilityResolveSupport {
+                    properties: vec!["edit".to_string()],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        window: Some(WindowClientCapabilities {
+            work_done_progress: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Whether a server with `capabilities` handles `method`
+///
+/// Capabilities a server registers later are checked separately. Methods
+/// without a capability (extensions, `workspaceSymbol/resolve`...) are
+/// assumed to be handled.
+pub fn server_supports(capabilities: &ServerCapabilities, method: &str) -> bool {
+    fn provided<T>(capability: &Option<OneOf<bool, T>>) -> bool {
+        !matches!(capability, None | Some(OneOf::Left(false)))
+    }
+
+    let c = capabilities;
+    match method {
+        "textDocument/definition" => provided(&c.definition_provider),
+        "textDocument/typeDefinition" => !matches!(
+            c.type_definition_provider,
+            None | Some(TypeDefinitionProviderCapability::Simple(false))
+        ),
+        "textDocument/implementation" => !matches!(
+            c.implementation_provider,
+            None | Some(ImplementationProviderCapability::Simple(false))
+        ),
+        "textDocument/references" => provided(&c.references_provider),
+        "textDocument/hover" => !matches!(
+            c.hover_provider,
+            None | Some(HoverProviderCapability::Simple(false))
+        ),
+        "textDocument/rename" => provided(&c.rename_provider),
+        "textDocument/documentSymbol" => provided(&c.document_symbol_provider),
+        "textDocument/codeAction" => !matches!(
+            c.code_action_provider,
+            None | Some(CodeActionProviderCapability::Simple(false))
+        ),
+        "textDocument/codeLens" => c.code_lens_provider.is_some(),
+        "textDocument/formatting" => provided(&c.document_formatting_provider),
+        "textDocument/signatureHelp" => c.signature_help_provider.is_some(),
+        "textDocument/completion" => c.completion_provider.is_some(),
+        "workspace/symbol" => provided(&c.workspace_symbol_provider),
+        _ => true,
+    }
+}
+
+/// What `method` does, for messages about servers lacking it
+pub fn feature_name(method: &str) -> &str {
+    match method {
+        "textDocument/definition" => "go to definition",
+        "textDocument/typeDefinition" => "go to type definition",
+        "textDocument/implementation" => "go to implementation",
+        "textDocument/references" => "find references",
+        "textDocument/hover" => "hover",
+        "textDocument/rename" => "rename",
+        "textDocument/documentSymbol" => "document symbols",
+        "textDocument/codeAction" => "code actions",
+        "textDocument/codeLens" => "code lenses",
+        "textDocument/formatting" => "formatting",
+        "textDocument/signatureHelp" => "signature help",
+        "textDocument/completion" => "completion",
+        "workspace/symbol" => "workspace symbol search",
+        _ => method,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn at<'a>(value: &'a Value, pointer: &str) -> &'a Value {
+        value
+            .pointer(pointer)
+            .unwrap_or_else(|| panic!("missing {}", pointer))
+    }
+
+    #[test]
+    fn test_advertised_capabilities() {
+        let caps = serde_json::to_value(client_capabilities()).unwrap();
+
+        assert_eq!(
+            at(
+                &caps,
+                "/textDocument/documentSymbol/hierarchicalDocumentSymbolSupport"
+            ),
+            &json!(true)
+        );
+        assert_eq!(
+            at(&caps, "/textDocument/hover/contentFormat"),
+            &json!(["markdown", "plaintext"])
+        );
+        assert_eq!(
+            at(
+                &caps,
+                "/textDocument/publishDiagnostics/tagSupport/valueSet"
+            ),
+            &json!([1, 2])
+        );
+        assert_eq!(
+            at(
+                &caps,
+                "/textDocument/completion/completionItem/snippetSupport"
+            ),
+            &json!(true)
+        );
+        assert_eq!(at(&caps, "/workspace/workspaceFolders"), &json!(true));
+        assert_eq!(at(&caps, "/workspace/configuration"), &json!(true));
+        assert_eq!(
+            at(
+                &caps,
+                "/workspace/didChangeWatchedFiles/dynamicRegistration"
+            ),
+            &json!(true)
+        );
+        assert_eq!(
+            at(&caps, "/general/positionEncodings"),
+            &json!(["utf-8", "utf-32", "utf-16"])
+        );
+
+        let kinds = at(&caps, "/textDocument/documentSymbol/symbolKind/valueSet");
+        assert_eq!(kinds, &json!((1..=26).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn test_server_supports() {
+        let capabilities: ServerCapabilities = serde_json::from_value(json!({
+            "hoverProvider": true,
+            "renameProvider": { "prepareProvider": true },
+            "referencesProvider": false,
+        }))
+        .unwrap();
+
+        assert!(server_supports(&capabilities, "textDocument/hover"));
+        assert!(server_supports(&capabilities, "textDocument/rename"));
+        assert!(!server_supports(&capabilities, "textDocument/references"));
+        assert!(!server_supports(&capabilities, "textDocument/definition"));
+        assert!(server_supports(&capabilities, "rust-analyzer/expandMacro"));
+        assert_eq!(feature_name("textDocument/references"), "find references");
+    }
+
+    #[test]
+    fn test_features_without_support_are_not_advertised() {
+        let caps = serde_json::to_value(client_capabilities()).unwrap();
+
+        // Nothing answers `workspace/applyEdit`, and file operations in
+        // edits are refused
+        assert_eq!(caps.pointer("/workspace/applyEdit"), None);
+        assert_eq!(
+            caps.pointer("/workspace/workspaceEdit/resourceOperations"),
+            None
+        );
+        assert_eq!(caps.pointer("/window/showDocument"), None);
+    }
+}
@@ -2,20 +2,41 @@
 //!
 //! Handles communication with a single LSP server via JSON-RPC over stdin/stdout
 
-use crate::config::LspPackage;
+use crate::config::{Connection, ExtensionParams, ExtensionRequest, LspPackage, ResourceLimits};
+use crate::lsp::breaker::{self, CircuitBreaker};
+use crate::lsp::capabilities::{client_capabilities, server_supports};
+use crate::lsp::documents::{self, DocumentLease, Leases, OpenDocument};
+use crate::lsp::file_watcher::{self, Snapshot};
+use crate::lsp::health::{LastError, ServerHealth, ServerState};
+use crate::lsp::launch;
+use crate::lsp::merge;
+use crate::lsp::orphans;
+use crate::lsp::partial::{self, PartialBatch};
+use crate::lsp::problems;
+use crate::lsp::process::{open_transport, resolve_command, Transport};
+use crate::lsp::progress::{ActiveProgress, ProgressTracker};
+use crate::lsp::registrations::{Registrations, WATCHED_FILES_METHOD};
+use crate::lsp::remote;
+use crate::lsp::runnables::{self, Runnable};
+use crate::lsp::shared_daemon;
+use crate::lsp::throttle::{RequestLimiter, DEFAULT_MAX_CONCURRENT_REQUESTS};
+use crate::lsp::virtual_docs;
+use crate::trace::{self, Direction};
 use crate::types::LspError;
+use crate::utils::position::{PositionEncoding, PositionMapper};
+use crate::utils::uri::PathMap;
 use lsp_types::*;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{ChildStdin, ChildStdout, Command};
-use tokio::sync::{mpsc, oneshot, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::Child;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 use url::Url;
@@ -29,16 +50,6 @@ struct JsonRpcRequest {
     params: Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    id: u64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    result: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<JsonRpcError>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct JsonRpcError {
     code: i32,
@@ -54,6 +65,316 @@ struct JsonRpcNotification {
     params: Value,
 }
 
+/// Channel a request's response is delivered on
+type ResponseSender = oneshot::Sender<Result<Value, LspError>>;
+
+/// In-flight requests, shared with the reader task
+type PendingRequests = Arc<Mutex<PendingTable>>;
+
+/// Where the partial results of in-flight requests go, by their token
+type PartialResults = Arc<std::sync::Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>;
+
+/// Read-only requests that identical concurrent calls share, rather than
+/// each sending their own (retrying agents often repeat a query at once)
+const COALESCED_METHODS: &[&str] = &[
+    "textDocument/hover",
+    "textDocument/definition",
+    "textDocument/typeDefinition",
+    "textDocument/implementation",
+    "textDocument/references",
+    "textDocument/documentSymbol",
+    "workspace/symbol",
+];
+
+/// An in-flight request and everyone waiting for its response
+struct PendingRequest {
+    /// Method, content generation and params, for coalesced methods
+    key: Option<String>,
+    waiters: Vec<ResponseSender>,
+}
+
+impl PendingRequest {
+    /// Deliver a response to every waiter, each with its own copy of an
+    /// error so its kind (a timeout, a crash...) is kept
+    fn respond(self, result: Result<Value, LspError>) {
+        let mut waiters = self.waiters.into_iter();
+        let first = waiters.next();
+        for waiter in waiters {
+            let _ = waiter.send(result.clone());
+        }
+        if let Some(first) = first {
+            let _ = first.send(result);
+        }
+    }
+}
+
+/// In-flight requests by id, plus an index of coalesced ones by key
+#[derive(Default)]
+struct PendingTable {
+    by_id: HashMap<u64, PendingRequest>,
+    by_key: HashMap<String, u64>,
+}
+
+impl PendingTable {
+    /// Wait on an identical in-flight request, returning its id
+    fn join(&mut self, key: &str, waiter: ResponseSender) -> Result<u64, ResponseSender> {
+        match self
+            .by_key
+            .get(key)
+            .and_then(|id| Some((*id, self.by_id.get_mut(id)?)))
+        {
+            Some((id, request)) => {
+                request.waiters.push(waiter);
+                Ok(id)
+            }
+            None => Err(waiter),
+        }
+    }
+
+    fn insert(&mut self, id: u64, key: Option<String>, waiter: ResponseSender) {
+        if let Some(key) = &key {
+            self.by_key.insert(key.clone(), id);
+        }
+        self.by_id.insert(
+            id,
+            PendingRequest {
+                key,
+                waiters: vec![waiter],
+            },
+        );
+    }
+
+    /// Drop the waiters that stopped waiting for request `id`, and the
+    /// request itself once nobody waits for it
+    fn abandon(&mut self, id: u64) {
+        let Some(request) = self.by_id.get_mut(&id) else {
+            return;
+        };
+        request.waiters.retain(|waiter| !waiter.is_closed());
+        if request.waiters.is_empty() {
+            self.remove(id);
+        }
+    }
+
+    /// Take a request out of the table, e.g. once its response arrived
+    fn remove(&mut self, id: u64) -> Option<PendingRequest> {
+        let request = self.by_id.remove(&id)?;
+        if let Some(key) = &request.key {
+            self.by_key.remove(key);
+        }
+        Some(request)
+    }
+
+    fn len(&self) -> usize {
+        self.by_id.len()
+    }
+}
+
+/// How long a request may go unanswered
+#[derive(Debug, Clone, Copy)]
+enum ResponseWait {
+    /// Give up after a fixed time
+    Fixed(Duration),
+    /// Give up once the server has gone this long without reporting
+    /// progress, for requests that can legitimately take minutes
+    WhileProgressing(Duration),
+}
+
+impl ResponseWait {
+    /// The wait for ordinary requests
+    const DEFAULT: Self = Self::Fixed(Duration::from_secs(30));
+}
+
+/// Token servers report `initialize` progress with
+const INITIALIZE_PROGRESS_TOKEN: &str = "lsmcp/initialize";
+
+/// How long to wait for a server to start reporting progress before
+/// assuming it has nothing to index
+const PROGRESS_GRACE: Duration = Duration::from_secs(2);
+
+/// How long progress must stay idle before indexing is considered finished
+const PROGRESS_SETTLE: Duration = Duration::from_millis(500);
+
+/// Source of content generations, shared by all clients so that a restarted
+/// client never reuses an earlier client's generation
+static GENERATIONS: AtomicU64 = AtomicU64::new(1);
+
+fn next_generation() -> u64 {
+    GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Diagnostics publications for a file
+#[derive(Debug, Clone, Copy, Default)]
+struct Publications {
+    /// How many times they were published
+    count: u64,
+    /// The document version last diagnosed, when the server says
+    version: Option<i32>,
+}
+
+/// Source of client instance numbers, which key each client's diagnostics
+/// in the workspace problems
+static INSTANCES: AtomicU64 = AtomicU64::new(1);
+
+/// State shared between the client and its background reader task
+#[derive(Clone)]
+struct ReaderContext {
+    pending: PendingRequests,
+    diagnostics: Arc<Mutex<HashMap<PathBuf, Vec<Diagnostic>>>>,
+    /// Diagnostics publications for each file
+    published: Arc<watch::Sender<HashMap<PathBuf, Publications>>>,
+    progress: Arc<Mutex<ProgressTracker>>,
+    partial_results: PartialResults,
+    outgoing: mpsc::UnboundedSender<String>,
+    /// Server name, for the trace file
+    server: String,
+    /// This client's key in `problems::global()`
+    instance: u64,
+    /// Answer to `workspace/workspaceFolders`
+    workspace_folders: Vec<WorkspaceFolder>,
+    /// The server's `settings`, answering `workspace/configuration`
+    settings: Option<Arc<Value>>,
+    registrations: Arc<std::sync::Mutex<Registrations>>,
+}
+
+/// The part of `settings` under a dotted `section` (all of it without one),
+/// or null when the settings don't have it
+fn configuration_section(settings: Option<&Value>, section: Option<&str>) -> Value {
+    let mut value = settings;
+    for key in section.into_iter().flat_map(|s| s.split('.')) {
+        value = value.and_then(|v| v.get(key));
+    }
+    value.cloned().unwrap_or(Value::Null)
+}
+
+/// A JSON-RPC invalid params error for a server request
+fn invalid_params(error: serde_json::Error) -> JsonRpcError {
+    JsonRpcError {
+        code: -32602,
+        message: format!("Invalid params: {}", error),
+        data: None,
+    }
+}
+
+/// The folder a server is started for, named after its directory
+fn workspace_folder(root: &Path, uri: Url) -> WorkspaceFolder {
+    WorkspaceFolder {
+        uri,
+        name: root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "workspace".to_string()),
+    }
+}
+
+/// Read one message's content from a server's stdout, `None` once it closes
+///
+/// Servers share stdout with whatever their wrapper scripts print: a line
+/// before a message's headers that isn't one (a banner, a stray
+/// `console.log`) is logged and skipped, and a header printed right after
+/// such output without a newline is still found.
+async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    server: &str,
+) -> std::io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut content_length = None;
+        let mut in_headers = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() && in_headers {
+                break;
+            }
+
+            let header = match line.find("Content-Length:") {
+                Some(0) => line,
+                Some(start) => {
+                    warn!(
+                        "{} wrote to stdout outside the protocol: {}",
+                        server,
+                        &line[..start]
+                    );
+                    &line[start..]
+                }
+                None if (in_headers || line.starts_with("Content-Type:"))
+                    && line.contains(": ") =>
+                {
+                    line
+                }
+                None => {
+                    if !line.trim().is_empty() {
+                        warn!("{} wrote to stdout outside the protocol: {}", server, line);
+                    }
+                    continue;
+                }
+            };
+            in_headers = true;
+            if let Some((key, value)) = header.split_once(':') {
+                if key.eq_ignore_ascii_case("Content-Length") {
+                    content_length = Some(value.trim().to_string());
+                }
+            }
+        }
+
+        let length: usize = match content_length.as_deref().map(str::parse) {
+            Some(Ok(length)) => length,
+            Some(Err(e)) => {
+                error!("Invalid Content-Length from {}: {}", server, e);
+                continue;
+            }
+            None => {
+                error!("Missing Content-Length header from {}", server);
+                continue;
+            }
+        };
+        let mut content = vec![0u8; length];
+        tokio::io::AsyncReadExt::read_exact(reader, &mut content).await?;
+        return Ok(Some(content));
+    }
+}
+
+/// Path rewrites for messages to and from a server
+#[derive(Clone, Default)]
+struct PathRewrites {
+    /// Applied in order to messages to the server
+    outbound: Vec<PathMap>,
+    /// Applied in order to messages from the server
+    inbound: Vec<PathMap>,
+}
+
+impl PathRewrites {
+    /// `reported` rewrites server paths to the MCP client's, and is undone on
+    /// the way out; `remote` translates local paths to a remote server's
+    fn new(reported: Option<PathMap>, remote: Option<PathMap>) -> Self {
+        let mut rewrites = Self::default();
+        if let Some(map) = &reported {
+            rewrites.outbound.push(map.reversed());
+        }
+        if let Some(map) = remote {
+            rewrites.outbound.push(map.clone());
+            rewrites.inbound.push(map.reversed());
+        }
+        rewrites.inbound.extend(reported);
+        rewrites
+    }
+
+    fn outbound(&self, message: String) -> String {
+        self.outbound
+            .iter()
+            .fold(message, |message, map| map.rewrite_message(&message))
+    }
+
+    fn inbound(&self, message: String) -> String {
+        self.inbound
+            .iter()
+            .fold(message, |message, map| map.rewrite_message(&message))
+    }
+}
+
 /// LSP client for a single language server
 pub struct LspClient {
     /// Language ID (e.g., "rust", "typescript")
@@ -69,7 +390,7 @@ pub struct LspClient {
     next_id: Arc<AtomicU64>,
 
     /// Pending requests
-    pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, LspError>>>>>,
+    pending: PendingRequests,
 
     /// Channel to send requests to the LSP server
     request_tx: mpsc::UnboundedSender<String>,
@@ -77,72 +398,174 @@ pub struct LspClient {
     /// Server capabilities after initialization
     capabilities: Arc<Mutex<Option<ServerCapabilities>>>,
 
-    /// Opened documents
-    opened_documents: Arc<Mutex<HashMap<PathBuf, String>>>,
+    /// Opened documents, as last sent to the server
+    opened_documents: Arc<Mutex<HashMap<PathBuf, OpenDocument>>>,
+
+    /// Documents in use by in-flight requests, which stay open
+    leases: Leases,
+
+    /// Most documents kept open before the least recently used are closed
+    /// (0 for no limit)
+    max_open_documents: AtomicUsize,
+
+    /// Turns for requests, at most `max_concurrent_requests` in flight
+    limiter: RequestLimiter,
+
+    /// Fails requests fast while the server doesn't answer
+    breaker: CircuitBreaker,
+
+    /// Changes to this server's view of the documents; bumped on every
+    /// `didChange` (see `content_generation`)
+    generation: AtomicU64,
 
     /// Diagnostics per file
     diagnostics: Arc<Mutex<HashMap<PathBuf, Vec<Diagnostic>>>>,
+
+    /// Diagnostics publications for each file
+    published: Arc<watch::Sender<HashMap<PathBuf, Publications>>>,
+
+    /// Work-done progress reported by the server
+    progress: Arc<Mutex<ProgressTracker>>,
+
+    /// Requests streaming their results through `$/progress`
+    partial_results: PartialResults,
+
+    /// Server process (`None` for a TCP server run by someone else)
+    child: Mutex<Option<Child>>,
+
+    /// Set once the server closed its end of the connection
+    closed: Arc<AtomicBool>,
+
+    /// When the server was spawned
+    started_at: Instant,
+
+    /// Set once initial indexing has finished, so later progress (e.g. a
+    /// check run after a save) doesn't hold up queries
+    indexed: AtomicBool,
+
+    /// Most recent request failure
+    last_error: std::sync::Mutex<Option<LastError>>,
+
+    /// Capabilities the server registered after `initialize`
+    registrations: Arc<std::sync::Mutex<Registrations>>,
+
+    /// The watched files as of the last poll, once the server registered
+    /// file watchers
+    watched_files: Mutex<Option<Snapshot>>,
 }
 
 impl LspClient {
     /// Spawn a new LSP server and create a client
+    ///
+    /// `path_map` rewrites the paths the server reports to the ones the MCP
+    /// client sees (the user's `[path_map]`). A bare command name is
+    /// resolved like the installer finds servers, so managed and Mason
+    /// installs start without being on `PATH`. Launch placeholders are
+    /// filled in and `setup` commands run first (see `lsp::launch`).
     pub async fn spawn(
         language: String,
-        config: LspPackage,
+        mut config: LspPackage,
         workspace_root: PathBuf,
+        path_map: Option<PathMap>,
     ) -> Result<Self, LspError> {
         info!("Spawning LSP server for {}: {}", language, config.name);
+        resolve_command(&mut config)?;
 
-        // Spawn the LSP server process
-        let command = config.bin.primary.as_str();
-        let args = config.bin.lsp_args.clone();
-
-        let mut child = Command::new(command)
-            .args(&args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null()) // TODO: Consider logging stderr
-            .kill_on_drop(true)
-            .spawn()
-            .map_err(|e| {
-                LspError::ServerNotFound(
-                    config.name.clone(),
-                    format!("Failed to spawn {}: {}. Install it first.", command, e),
-                )
-            })?;
-
-        let stdin = child
-            .stdin
-            .take()
-            .ok_or_else(|| LspError::ProtocolError("Failed to get stdin".to_string()))?;
+        // Start the server and connect to it
+        let cwd = match &config.bin.cwd {
+            Some(cwd) => workspace_root.join(cwd),
+            None => workspace_root.clone(),
+        };
+        if !cwd.is_dir() {
+            return Err(LspError::ConfigError(format!(
+                "Working directory for {} does not exist: {}",
+                config.name,
+                cwd.display()
+            )));
+        }
+        launch::prepare(&mut config, &workspace_root).await?;
+        let transport = open_transport(&config, &cwd).await?;
+        if let Some(pid) = transport.child.as_ref().and_then(Child::id) {
+            orphans::record(pid, &config.name, &language, &workspace_root).await;
+        }
 
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| LspError::ProtocolError("Failed to get stdout".to_string()))?;
+        Self::connect(language, config, workspace_root, path_map, transport).await
+    }
 
+    /// Create a client for a server already started, talking to it over
+    /// `transport`, and initialize it
+    ///
+    /// For embedders running servers their own way (in-process, over a
+    /// socket they opened); `config` names the server and supplies its
+    /// settings and initialization options, but its command isn't run.
+    pub async fn connect(
+        language: String,
+        config: LspPackage,
+        workspace_root: PathBuf,
+        path_map: Option<PathMap>,
+        transport: Transport,
+    ) -> Result<Self, LspError> {
         // Create channels for communication
         let (request_tx, request_rx) = mpsc::unbounded_channel();
-        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let ctx = ReaderContext {
+            pending: Arc::new(Mutex::new(PendingTable::default())),
+            diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            published: Arc::new(watch::Sender::new(HashMap::new())),
+            progress: Arc::new(Mutex::new(ProgressTracker::default())),
+            partial_results: PartialResults::default(),
+            outgoing: request_tx.clone(),
+            server: config.name.clone(),
+            instance: INSTANCES.fetch_add(1, Ordering::Relaxed),
+            workspace_folders: Url::from_file_path(&workspace_root)
+                .map(|uri| vec![workspace_folder(&workspace_root, uri)])
+                .unwrap_or_default(),
+            settings: config.settings.clone().map(Arc::new),
+            registrations: Arc::new(std::sync::Mutex::new(Registrations::default())),
+        };
 
         // Spawn background tasks
-        let pending_clone = Arc::clone(&pending);
-        let diagnostics = Arc::new(Mutex::new(HashMap::new()));
-        let diagnostics_clone = Arc::clone(&diagnostics);
-
-        tokio::spawn(Self::write_loop(stdin, request_rx));
-        tokio::spawn(Self::read_loop(stdout, pending_clone, diagnostics_clone));
+        let rewrites =
+            PathRewrites::new(path_map, config.remote.as_ref().and_then(remote::path_map));
+        tokio::spawn(Self::write_loop(
+            transport.writer,
+            request_rx,
+            rewrites.clone(),
+        ));
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader_ctx = ctx.clone();
+        let reader_closed = Arc::clone(&closed);
+        tokio::spawn(async move {
+            let instance = reader_ctx.instance;
+            Self::read_loop(transport.reader, reader_ctx, rewrites).await;
+            reader_closed.store(true, Ordering::Relaxed);
+            problems::global().forget(instance);
+        });
 
         let client = Self {
             language: language.clone(),
             config,
             workspace_root,
             next_id: Arc::new(AtomicU64::new(1)),
-            pending,
+            pending: ctx.pending,
             request_tx,
             capabilities: Arc::new(Mutex::new(None)),
             opened_documents: Arc::new(Mutex::new(HashMap::new())),
-            diagnostics,
+            leases: Leases::default(),
+            max_open_documents: AtomicUsize::new(documents::DEFAULT_MAX_OPEN_DOCUMENTS),
+            limiter: RequestLimiter::new(DEFAULT_MAX_CONCURRENT_REQUESTS),
+            breaker: CircuitBreaker::new(&ctx.server),
+            generation: AtomicU64::new(next_generation()),
+            diagnostics: ctx.diagnostics,
+            published: ctx.published,
+            progress: ctx.progress,
+            partial_results: ctx.partial_results,
+            child: Mutex::new(transport.child),
+            closed,
+            started_at: Instant::now(),
+            indexed: AtomicBool::new(false),
+            last_error: std::sync::Mutex::new(None),
+            registrations: ctx.registrations,
+            watched_files: Mutex::new(None),
         };
 
         // Initialize the LSP server
@@ -154,8 +577,13 @@ impl LspClient {
     }
 
     /// Background task to write messages to LSP server
-    async fn write_loop(mut stdin: ChildStdin, mut request_rx: mpsc::UnboundedReceiver<String>) {
+    async fn write_loop(
+        mut stdin: Box<dyn AsyncWrite + Unpin + Send>,
+        mut request_rx: mpsc::UnboundedReceiver<String>,
+        rewrites: PathRewrites,
+    ) {
         while let Some(message) = request_rx.recv().await {
+            let message = rewrites.outbound(message);
             let content_length = message.len();
             let header = format!("Content-Length: {}\r\n\r\n", content_length);
 
@@ -178,68 +606,27 @@ impl LspClient {
 
     /// Background task to read messages from LSP server
     async fn read_loop(
-        stdout: ChildStdout,
-        pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, LspError>>>>>,
-        diagnostics: Arc<Mutex<HashMap<PathBuf, Vec<Diagnostic>>>>,
+        stdout: Box<dyn AsyncRead + Unpin + Send>,
+        ctx: ReaderContext,
+        rewrites: PathRewrites,
     ) {
         let mut reader = BufReader::new(stdout);
-        let mut headers = HashMap::new();
 
         loop {
-            headers.clear();
-
-            // Read headers
-            loop {
-                let mut line = String::new();
-                match reader.read_line(&mut line).await {
-                    Ok(0) => {
-                        warn!("LSP server closed stdout");
-                        return;
-                    }
-                    Ok(_) => {
-                        let line = line.trim();
-                        if line.is_empty() {
-                            break;
-                        }
-
-                        if let Some((key, value)) = line.split_once(": ") {
-                            headers.insert(key.to_string(), value.to_string());
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to read header: {}", e);
-                        return;
-                    }
-                }
-            }
-
-            // Get content length
-            let content_length: usize = match headers.get("Content-Length") {
-                Some(len) => match len.parse() {
-                    Ok(len) => len,
-                    Err(e) => {
-                        error!("Invalid Content-Length: {}", e);
-                        continue;
-                    }
-                },
-                None => {
-                    error!("Missing Content-Length header");
-                    continue;
+            let content = match read_frame(&mut reader, &ctx.server).await {
+                Ok(Some(content)) => content,
+                Ok(None) => {
+                    warn!("LSP server closed stdout");
+                    return;
                 }
-            };
-
-            // Read content
-            let mut content = vec![0u8; content_length];
-            match tokio::io::AsyncReadExt::read_exact(&mut reader, &mut content).await {
-                Ok(_) => {}
                 Err(e) => {
-                    error!("Failed to read content: {}", e);
+                    error!("Failed to read message: {}", e);
                     return;
                 }
-            }
+            };
 
             let content_str = match String::from_utf8(content) {
-                Ok(s) => s,
+                Ok(s) => rewrites.inbound(s),
                 Err(e) => {
                     error!("Invalid UTF-8 in message: {}", e);
                     continue;
@@ -247,56 +634,177 @@ impl LspClient {
             };
 
             debug!("Received message: {}", content_str);
+            trace::lsp(&ctx.server, Direction::In, &content_str);
 
             // Parse and dispatch message
-            Self::handle_message(&content_str, &pending, &diagnostics).await;
+            Self::handle_message(&content_str, &ctx).await;
         }
     }
 
-    async fn handle_message(
-        content: &str,
-        pending: &Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, LspError>>>>>,
-        diagnostics: &Arc<Mutex<HashMap<PathBuf, Vec<Diagnostic>>>>,
-    ) {
-        // Try to parse as response first
-        if let Ok(response) = serde_json::from_str::<JsonRpcResponse>(content) {
-            let mut pending_guard = pending.lock().await;
-            if let Some(sender) = pending_guard.remove(&response.id) {
-                let result = if let Some(result) = response.result {
-                    Ok(result)
-                } else if let Some(error) = response.error {
-                    Err(LspError::ProtocolError(format!(
-                        "LSP error: {}",
-                        error.message
-                    )))
+    async fn handle_message(content: &str, ctx: &ReaderContext) {
+        let message: Value = match serde_json::from_str(content) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to parse LSP message: {}", e);
+                return;
+            }
+        };
+
+        let method = message.get("method").and_then(Value::as_str);
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        match (method, message.get("id")) {
+            (Some(method), Some(id)) => {
+                Self::handle_server_request(method, id.clone(), params, ctx).await
+            }
+            (Some(method), None) => Self::handle_notification(method, params, ctx).await,
+            (None, Some(id)) => {
+                let Some(id) = id.as_u64() else {
+                    warn!("Response with unexpected id: {}", id);
+                    return;
+                };
+
+                let result = if let Some(error) = message.get("error") {
+                    let message = serde_json::from_value::<JsonRpcError>(error.clone())
+                        .map(|e| e.message)
+                        .unwrap_or_else(|_| error.to_string());
+                    Err(LspError::ProtocolError(format!("LSP error: {}", message)))
+                } else if let Some(result) = message.get("result") {
+                    Ok(result.clone())
                 } else {
                     Err(LspError::ProtocolError("No result or error".to_string()))
                 };
 
-                let _ = sender.send(result);
+                if let Some(request) = ctx.pending.lock().await.remove(id) {
+                    request.respond(result);
+                }
             }
-            return;
+            (None, None) => warn!("Unknown message type: {}", content),
         }
+    }
 
-        // Try to parse as notification
-        if let Ok(notification) = serde_json::from_str::<JsonRpcNotification>(content) {
-            // Handle publishDiagnostics notification
-            if notification.method == "textDocument/publishDiagnostics" {
-                if let Ok(params) =
-                    serde_json::from_value::<PublishDiagnosticsParams>(notification.params)
-                {
+    /// Handle a notification sent by the server
+    async fn handle_notification(method: &str, params: Value, ctx: &ReaderContext) {
+        match method {
+            "textDocument/publishDiagnostics" => {
+                if let Ok(params) = serde_json::from_value::<PublishDiagnosticsParams>(params) {
                     // Convert URI to PathBuf
                     if let Ok(path) = params.uri.to_file_path() {
-                        let mut diagnostics_guard = diagnostics.lock().await;
-                        diagnostics_guard.insert(path, params.diagnostics);
+                        problems::global().publish(
+                            ctx.instance,
+                            &ctx.server,
+                            &path,
+                            &params.diagnostics,
+                        );
+                        let mut diagnostics_guard = ctx.diagnostics.lock().await;
+                        diagnostics_guard.insert(path.clone(), params.diagnostics);
+                        ctx.published.send_modify(|published| {
+                            let publications = published.entry(path).or_default();
+                            publications.count += 1;
+                            publications.version = params.version;
+                        });
                         debug!("Updated diagnostics for file");
                     }
                 }
             }
-            return;
+            "$/progress" => {
+                // A batch of a streaming request's results, else work-done
+                // progress
+                let results = params
+                    .get("token")
+                    .and_then(Value::as_str)
+                    .and_then(|token| {
+                        let senders = ctx
+                            .partial_results
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner());
+                        senders.get(token).cloned()
+                    });
+                if let Some(results) = results {
+                    let _ = results.send(params.get("value").cloned().unwrap_or(Value::Null));
+                } else if let Ok(params) = serde_json::from_value::<ProgressParams>(params) {
+                    ctx.progress.lock().await.update(params);
+                }
+            }
+            "window/logMessage" | "window/showMessage" => {
+                if let Some(message) = params.get("message").and_then(Value::as_str) {
+                    debug!("Server message: {}", message);
+                }
+            }
+            _ => debug!("Ignoring notification: {}", method),
         }
+    }
+
+    /// Answer a request sent by the server
+    ///
+    /// Servers block on some of these (e.g. progress token creation), so
+    /// every request gets a response, even if only an error.
+    async fn handle_server_request(method: &str, id: Value, params: Value, ctx: &ReaderContext) {
+        debug!("Received server request: {}", method);
+
+        let result = match method {
+            "window/workDoneProgress/create" | "window/showMessageRequest" => Ok(Value::Null),
+            "client/registerCapability" => serde_json::from_value(params)
+                .map(|params| {
+                    ctx.registrations
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .register(params)
+                })
+                .map(|()| Value::Null)
+                .map_err(invalid_params),
+            "client/unregisterCapability" => serde_json::from_value(params)
+                .map(|params| {
+                    ctx.registrations
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .unregister(params)
+                })
+                .map(|()| Value::Null)
+                .map_err(invalid_params),
+            "workspace/workspaceFolders" => {
+                serde_json::to_value(&ctx.workspace_folders).map_err(|e| JsonRpcError {
+                    code: -32603,
+                    message: e.to_string(),
+                    data: None,
+                })
+            }
+            "workspace/configuration" => {
+                let items = params
+                    .get("items")
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let settings = ctx.settings.as_deref();
+                Ok(Value::Array(
+                    items
+                        .iter()
+                        .map(|item| {
+                            configuration_section(
+                                settings,
+                                item.get("section").and_then(Value::as_str),
+                            )
+                        })
+                        .collect(),
+                ))
+            }
+            _ => Err(JsonRpcError {
+                code: -32601,
+                message: format!("Method not supported by lsmcp: {}", method),
+                data: None,
+            }),
+        };
+
+        let response = match result {
+            Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        };
 
-        warn!("Unknown message type: {}", content);
+        let response = response.to_string();
+        trace::lsp(&ctx.server, Direction::Out, &response);
+        if ctx.outgoing.send(response).is_err() {
+            warn!("Failed to answer server request {}: writer closed", method);
+        }
     }
 
     /// Send a request and wait for response
@@ -305,33 +813,240 @@ impl LspClient {
         method: &str,
         params: P,
     ) -> Result<R, LspError> {
-        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.send_request_with(method, params, ResponseWait::DEFAULT)
+            .await
+    }
 
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id,
-            method: method.to_string(),
-            params: serde_json::to_value(params)?,
+    /// Send a request and wait for response as long as `wait` allows
+    async fn send_request_with<P: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        params: P,
+        wait: ResponseWait,
+    ) -> Result<R, LspError> {
+        let result = self
+            .send_request_value(method, serde_json::to_value(params)?, wait)
+            .await?;
+
+        serde_json::from_value(result).map_err(|e| {
+            let error = LspError::ProtocolError(format!("Failed to parse response: {}", e));
+            self.record_error(method, &error);
+            error
+        })
+    }
+
+    /// Send a request, passing the batches of results the server streams
+    /// for it to `on_batch` while the tool call takes partial results (see
+    /// `lsp::partial`)
+    ///
+    /// A server streaming its results answers with only what it didn't
+    /// stream, so `on_batch` has to keep the batches. Servers without
+    /// streaming ignore the token and answer as usual.
+    async fn send_request_streaming<P: Serialize, B: DeserializeOwned, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: P,
+        mut on_batch: impl FnMut(B),
+    ) -> Result<R, LspError> {
+        if !partial::is_streaming() {
+            return self.send_request(method, params).await;
+        }
+
+        let token = format!(
+            "lsmcp-partial-{}",
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        );
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        self.partial_results
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token.clone(), tx);
+
+        let mut params = serde_json::to_value(params)?;
+        params["partialResultToken"] = Value::String(token.clone());
+        let mut batch = |value: Value| match serde_json::from_value(value) {
+            Ok(batch) => on_batch(batch),
+            Err(e) => debug!("Ignoring malformed partial result for {}: {}", method, e),
+        };
+
+        let request = self.send_request_value(method, params, ResponseWait::DEFAULT);
+        tokio::pin!(request);
+        let result = loop {
+            tokio::select! {
+                result = &mut request => break result,
+                Some(value) = rx.recv() => batch(value),
+            }
         };
+        self.partial_results
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&token);
+        // Batches are sent before the response, but may not be taken yet
+        while let Ok(value) = rx.try_recv() {
+            batch(value);
+        }
 
-        let message = serde_json::to_string(&request)?;
-        debug!("Sending request {}: {}", id, method);
+        serde_json::from_value(result?).map_err(|e| {
+            let error = LspError::ProtocolError(format!("Failed to parse response: {}", e));
+            self.record_error(method, &error);
+            error
+        })
+    }
+
+    /// Send a request with untyped params and return the raw result
+    async fn send_request_value(
+        &self,
+        method: &str,
+        params: Value,
+        wait: ResponseWait,
+    ) -> Result<Value, LspError> {
+        // Fail fast, without taking a turn, while the server doesn't answer
+        self.breaker.admit()?;
+        let slot = self.limiter.acquire().await;
+        if let Some(waited) = slot.waited {
+            crate::metrics::global().record_queue_wait(&self.config.name, waited);
+        }
+        let started = Instant::now();
+        let (result, sent) = self.dispatch_request(method, params, wait).await;
+        if let Err(e) = &result {
+            self.record_error(method, e);
+        }
+        // Once per request sent, however many calls shared it
+        if sent {
+            self.breaker
+                .record(result.as_ref().is_err_and(breaker::is_failure));
+        }
+        crate::metrics::global().record_request(
+            &self.config.name,
+            method,
+            started.elapsed(),
+            result.as_ref().err(),
+        );
+        result
+    }
 
+    /// Send a request, or share an identical one in flight, and wait for
+    /// its response
+    ///
+    /// Also returns whether this call sent the request rather than joining
+    /// one.
+    async fn dispatch_request(
+        &self,
+        method: &str,
+        params: Value,
+        wait: ResponseWait,
+    ) -> (Result<Value, LspError>, bool) {
+        // Only calls on the same content share: a call on other content
+        // (another generation) must not get an answer computed before its
+        // change reached the server
+        let key = COALESCED_METHODS
+            .contains(&method)
+            .then(|| format!("{} {} {}", method, self.content_generation(), params));
         let (tx, rx) = oneshot::channel();
-        self.pending.lock().await.insert(id, tx);
 
-        self.request_tx
-            .send(message)
-            .map_err(|_| LspError::ProtocolError("Failed to send request".to_string()))?;
+        let (id, sent) = {
+            let mut pending = self.pending.lock().await;
+            let joined = match &key {
+                Some(key) => pending.join(key, tx),
+                None => Err(tx),
+            };
+            match joined {
+                Ok(id) => {
+                    debug!("Sharing in-flight request {}: {}", id, method);
+                    (id, false)
+                }
+                Err(tx) => {
+                    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                    let request = JsonRpcRequest {
+                        jsonrpc: "2.0".to_string(),
+                        id,
+                        method: method.to_string(),
+                        params,
+                    };
+                    let message = match serde_json::to_string(&request) {
+                        Ok(message) => message,
+                        Err(e) => return (Err(e.into()), true),
+                    };
+                    debug!("Sending request {}: {}", id, method);
+                    trace::lsp(&self.config.name, Direction::Out, &message);
+
+                    if self.request_tx.send(message).is_err() {
+                        return (
+                            Err(LspError::ProtocolError(
+                                "Failed to send request".to_string(),
+                            )),
+                            true,
+                        );
+                    }
+                    pending.insert(id, key, tx);
+                    (id, true)
+                }
+            }
+        };
+        (self.wait_for_response(id, rx, method, wait).await, sent)
+    }
 
-        // Wait for response with timeout
-        let result = timeout(Duration::from_secs(30), rx)
-            .await
-            .map_err(|_| LspError::Timeout(30))?
-            .map_err(|_| LspError::ProtocolError("Response channel closed".to_string()))??;
+    /// Wait for the response to request `id` on `rx`, until this call's own
+    /// deadline
+    ///
+    /// A call that gives up leaves the request to any others sharing it,
+    /// which each wait out their own deadline.
+    async fn wait_for_response(
+        &self,
+        id: u64,
+        mut rx: oneshot::Receiver<Result<Value, LspError>>,
+        method: &str,
+        wait: ResponseWait,
+    ) -> Result<Value, LspError> {
+        let started = Instant::now();
+        let (limit, progressing) = match wait {
+            ResponseWait::Fixed(limit) => (limit, false),
+            ResponseWait::WhileProgressing(limit) => (limit, true),
+        };
+        let mut deadline = started + limit;
+        loop {
+            match tokio::time::timeout_at(deadline.into(), &mut rx).await {
+                Ok(Ok(result)) => return result,
+                Ok(Err(_)) => {
+                    return Err(LspError::ProtocolError(
+                        "Response channel closed".to_string(),
+                    ))
+                }
+                Err(_) => {}
+            }
+
+            let last_progress = self.progress.lock().await.last_activity();
+            match last_progress.map(|last| last + limit) {
+                Some(extended) if progressing && extended > deadline => {
+                    debug!(
+                        "{} is reporting progress, still waiting for {}",
+                        self.config.name, method
+                    );
+                    deadline = extended;
+                }
+                _ => break,
+            }
+        }
 
-        serde_json::from_value(result)
-            .map_err(|e| LspError::ProtocolError(format!("Failed to parse response: {}", e)))
+        // Stop waiting ourselves; the request stays in flight while others
+        // still wait for it
+        drop(rx);
+        self.pending.lock().await.abandon(id);
+        Err(LspError::Timeout(started.elapsed().as_secs()))
+    }
+
+    /// Remember the most recent request failure for health reporting
+    fn record_error(&self, method: &str, error: &LspError) {
+        if let Ok(mut last_error) = self.last_error.lock() {
+            let message = match trace::correlation_id() {
+                Some(id) => format!("{}: {} (correlation id: {})", method, error, id),
+                None => format!("{}: {}", method, error),
+            };
+            *last_error = Some(LastError {
+                message,
+                at: Instant::now(),
+            });
+        }
     }
 
     /// Send a notification (no response expected)
@@ -348,6 +1063,7 @@ impl LspClient {
 
         let message = serde_json::to_string(&notification)?;
         debug!("Sending notification: {}", method);
+        trace::lsp(&self.config.name, Direction::Out, &message);
 
         self.request_tx
             .send(message)
@@ -358,15 +1074,39 @@ impl LspClient {
 
     /// Initialize the LSP server
     async fn initialize(&self) -> Result<(), LspError> {
+        let root_uri = Url::from_file_path(&self.workspace_root)
+            .map_err(|_| LspError::InvalidPath(self.workspace_root.clone()))?;
+
+        // `root_uri` is deprecated in favour of workspace folders, but older
+        // servers still rely on it, so we send both.
+        #[allow(deprecated)]
         let params = InitializeParams {
             process_id: Some(std::process::id()),
-            root_uri: Some(Url::from_file_path(&self.workspace_root).unwrap()),
-            capabilities: ClientCapabilities::default(),
+            root_uri: Some(root_uri.clone()),
+            workspace_folders: Some(vec![workspace_folder(&self.workspace_root, root_uri)]),
+            capabilities: client_capabilities(),
             initialization_options: self.config.initialization_options.clone(),
+            work_done_progress_params: WorkDoneProgressParams {
+                work_done_token: Some(NumberOrString::String(
+                    INITIALIZE_PROGRESS_TOKEN.to_string(),
+                )),
+            },
             ..Default::default()
         };
 
-        let result: InitializeResult = self.send_request("initialize", params).await?;
+        // Servers loading a large workspace may take minutes to answer, so
+        // keep waiting while they report progress
+        let limit = self.config.bin.initialize_timeout();
+        let result: InitializeResult = self
+            .send_request_with("initialize", params, ResponseWait::WhileProgressing(limit))
+            .await
+            .map_err(|e| match e {
+                LspError::Timeout(secs) => LspError::InitializeTimeout {
+                    server: self.config.name.clone(),
+                    secs,
+                },
+                e => e,
+            })?;
 
         // Store capabilities
         *self.capabilities.lock().await = Some(result.capabilities);
@@ -375,18 +1115,190 @@ impl LspClient {
         self.send_notification("initialized", InitializedParams {})
             .await?;
 
+        // Servers that don't ask for their settings take them pushed
+        if let Some(settings) = &self.config.settings {
+            self.send_notification(
+                "workspace/didChangeConfiguration",
+                DidChangeConfigurationParams {
+                    settings: settings.clone(),
+                },
+            )
+            .await?;
+        }
+
         Ok(())
     }
 
+    /// Shut down the server with the `shutdown`/`exit` handshake
+    ///
+    /// Best effort: errors are logged, and the process is killed on drop
+    /// if it does not exit on its own.
+    pub async fn shutdown(&self) {
+        let result = timeout(
+            Duration::from_secs(5),
+            self.send_request::<_, Value>("shutdown", Value::Null),
+        )
+        .await;
+
+        match result {
+            Ok(Ok(_)) => {
+                if let Err(e) = self.send_notification("exit", Value::Null).await {
+                    debug!("Failed to send exit to {}: {}", self.config.name, e);
+                }
+            }
+            Ok(Err(e)) => warn!("{} shutdown request failed: {}", self.config.name, e),
+            Err(_) => warn!("{} did not answer shutdown in time", self.config.name),
+        }
+
+        if let Some(child) = self.child.lock().await.as_mut() {
+            let pid = child.id();
+            if timeout(Duration::from_secs(2), child.wait()).await.is_err() {
+                debug!("{} did not exit, killing it", self.config.name);
+                let _ = child.kill().await;
+            }
+            if let Some(pid) = pid {
+                orphans::forget(pid);
+            }
+        }
+    }
+
+    /// Wait until the server has finished its initial indexing
+    ///
+    /// Returns `true` once no progress operation has been active for a short
+    /// settle period (or the server never reported progress), and `false` if
+    /// it was still busy when `max_wait` elapsed.
+    pub async fn wait_for_indexing(&self, max_wait: Duration) -> bool {
+        let deadline = Instant::now() + max_wait;
+
+        loop {
+            {
+                let progress = self.progress.lock().await;
+                if !progress.is_busy() {
+                    let settled = match progress.last_activity() {
+                        Some(last) => last.elapsed() >= PROGRESS_SETTLE,
+                        None => self.started_at.elapsed() >= PROGRESS_GRACE,
+                    };
+                    if settled {
+                        self.indexed.store(true, Ordering::Relaxed);
+                        return true;
+                    }
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return false;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Wait for initial indexing unless it has already finished once
+    ///
+    /// Queries call this so that a freshly started server answers from a
+    /// loaded project instead of returning empty results.
+    pub async fn wait_for_initial_indexing(&self, max_wait: Duration) -> bool {
+        self.indexed.load(Ordering::Relaxed) || self.wait_for_indexing(max_wait).await
+    }
+
+    /// Progress operations the server is currently running
+    pub async fn active_progress(&self) -> Vec<ActiveProgress> {
+        self.progress.lock().await.active()
+    }
+
+    /// Language this client serves
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Name of the server package (e.g. "pyright")
+    pub fn server_name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Process id of the server, when lsmcp runs it on this machine
+    pub async fn local_pid(&self) -> Option<u32> {
+        if self.config.remote.is_some() {
+            return None;
+        }
+        self.child.lock().await.as_ref().and_then(Child::id)
+    }
+
+    /// Memory, CPU and request limits the server runs with
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.config.bin.limits
+    }
+
+    /// Whether requests are in flight or waiting for a turn
+    pub fn is_busy(&self) -> bool {
+        let queue = self.limiter.stats();
+        queue.in_flight + queue.queued > 0
+    }
+
+    /// Whether the server process has exited or closed the connection
+    pub async fn has_exited(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+            || matches!(
+                self.child.lock().await.as_mut().map(Child::try_wait),
+                Some(Ok(Some(_)))
+            )
+    }
+
+    /// Take a health snapshot of the server
+    pub async fn health(&self) -> ServerHealth {
+        let (pid, exit_status) = match self.child.lock().await.as_mut() {
+            Some(child) => (child.id(), child.try_wait().ok().flatten()),
+            None => (None, None),
+        };
+
+        let progress = self.active_progress().await;
+        let state = match exit_status {
+            Some(status) => ServerState::Exited(status.code()),
+            None if self.closed.load(Ordering::Relaxed) => ServerState::Exited(None),
+            None if !progress.is_empty() => ServerState::Indexing,
+            None => ServerState::Ready,
+        };
+
+        ServerHealth {
+            language: self.language.clone(),
+            server_name: self.config.name.clone(),
+            project_root: self.workspace_root.clone(),
+            binary: match &self.config.bin.connection {
+                Connection::Tcp { host, port } if self.config.bin.primary.is_empty() => {
+                    format!("tcp://{}:{}", host, port)
+                }
+                _ => match &self.config.remote {
+                    Some(remote) => format!("{} ({})", self.config.bin.primary, remote),
+                    None if shared_daemon::is_shared(&self.config) => {
+                        format!(
+                            "{} (forwarding to the shared daemon)",
+                            self.config.bin.primary
+                        )
+                    }
+                    None => self.config.bin.primary.clone(),
+                },
+            },
+            pid,
+            uptime: self.started_at.elapsed(),
+            pending_requests: self.pending.lock().await.len(),
+            queue: self.limiter.stats(),
+            breaker: self.breaker.state(),
+            breaker_trips: self.breaker.trips(),
+            last_error: self.last_error.lock().ok().and_then(|e| e.clone()),
+            progress,
+            state,
+        }
+    }
+
     /// Open a document
     pub async fn did_open(&self, file_path: &Path) -> Result<(), LspError> {
         let uri = Url::from_file_path(file_path)
             .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
 
-        // Read file content
-        let text = tokio::fs::read_to_string(file_path)
+        // Read file content (or extract a virtual document's)
+        let text = virtual_docs::read_document(file_path)
             .await
-            .map_err(|e| LspError::Io(e))?;
+            .map_err(LspError::Io)?;
 
         let params = DidOpenTextDocumentParams {
             text_document: TextDocumentItem {
@@ -404,11 +1316,170 @@ impl LspClient {
         self.opened_documents
             .lock()
             .await
-            .insert(file_path.to_path_buf(), text);
+            .insert(file_path.to_path_buf(), OpenDocument::new(text, 1));
+        self.close_idle_documents().await;
 
         Ok(())
     }
 
+    /// Close the least recently used documents beyond `max_open_documents`
+    /// that no request is using
+    async fn close_idle_documents(&self) {
+        let max = self.max_open_documents.load(Ordering::Relaxed);
+        let mut documents = self.opened_documents.lock().await;
+        let evicted = documents::eviction_candidates(&documents, &self.leases, max);
+        if evicted.is_empty() {
+            return;
+        }
+        for path in &evicted {
+            let Ok(uri) = Url::from_file_path(path) else {
+                continue;
+            };
+            let params = DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri },
+            };
+            if let Err(e) = self
+                .send_notification("textDocument/didClose", params)
+                .await
+            {
+                debug!("Failed to close {}: {}", path.display(), e);
+                continue;
+            }
+            documents.remove(path);
+        }
+        self.generation.store(next_generation(), Ordering::Relaxed);
+        debug!(
+            "Closed {} idle document(s) on {} ({} open)",
+            evicted.len(),
+            self.config.name,
+            documents.len()
+        );
+    }
+
+    /// Keep at most `max` documents open (0 for no limit)
+    pub fn limit_open_documents(&self, max: usize) {
+        self.max_open_documents.store(max, Ordering::Relaxed);
+    }
+
+    /// Send at most `max` requests at once, queueing the rest (0 for no
+    /// limit)
+    pub fn limit_concurrent_requests(&self, max: usize) {
+        self.limiter.set_limit(max);
+    }
+
+    /// Fail requests fast for `cooldown` after `failures` in a row went
+    /// unanswered (0 to never)
+    pub fn configure_breaker(&self, failures: u32, cooldown: Duration) {
+        self.breaker.configure(failures, cooldown);
+    }
+
+    /// Sync a document for a request, keeping it open and its content
+    /// unchanged until the returned lease is dropped
+    pub async fn lease_document(&self, file_path: &Path) -> Result<DocumentLease, LspError> {
+        let mut lease = self.leases.lease(file_path);
+        lease.share().await;
+        if !self.is_synced(file_path).await? {
+            // Wait for the requests on the server's content before changing it
+            lease.change().await;
+            lease.changed = self.send_document(file_path).await?;
+            lease.changed();
+        }
+        Ok(lease)
+    }
+
+    /// Open a document, or send its new content if it changed on disk
+    ///
+    /// Returns whether the server's copy changed (opened or updated).
+    pub async fn sync_document(&self, file_path: &Path) -> Result<bool, LspError> {
+        Ok(self.lease_document(file_path).await?.changed)
+    }
+
+    /// Whether the server has a document's current content
+    async fn is_synced(&self, file_path: &Path) -> Result<bool, LspError> {
+        if !self.has_document(file_path).await {
+            return Ok(false);
+        }
+        let text = virtual_docs::read_document(file_path)
+            .await
+            .map_err(LspError::Io)?;
+        let synced = match self.opened_documents.lock().await.get_mut(file_path) {
+            Some(document) => {
+                document.last_used = Instant::now();
+                document.text == text
+            }
+            None => false,
+        };
+        Ok(synced)
+    }
+
+    /// Open a document, or send its new content; only while no request is
+    /// using it (see `lease_document`)
+    async fn send_document(&self, file_path: &Path) -> Result<bool, LspError> {
+        let version = self
+            .opened_documents
+            .lock()
+            .await
+            .get(file_path)
+            .map(|document| document.version);
+        let version = match version {
+            Some(version) => version,
+            None => {
+                self.did_open(file_path).await?;
+                return Ok(true);
+            }
+        };
+
+        let text = virtual_docs::read_document(file_path)
+            .await
+            .map_err(LspError::Io)?;
+        let unchanged = match self.opened_documents.lock().await.get_mut(file_path) {
+            Some(document) => {
+                document.last_used = Instant::now();
+                document.text == text
+            }
+            None => false,
+        };
+        if unchanged {
+            return Ok(false);
+        }
+
+        let uri = Url::from_file_path(file_path)
+            .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri,
+                version: version + 1,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.clone(),
+            }],
+        };
+        self.send_notification("textDocument/didChange", params)
+            .await?;
+
+        self.opened_documents.lock().await.insert(
+            file_path.to_path_buf(),
+            OpenDocument::new(text, version + 1),
+        );
+        self.generation.store(next_generation(), Ordering::Relaxed);
+        debug!(
+            "Sent changed {} to {}",
+            file_path.display(),
+            self.config.name
+        );
+        Ok(true)
+    }
+
+    /// Identifies the server's current view of the documents
+    ///
+    /// Changes whenever a document changes, and differs between clients, so
+    /// answers computed under one generation can be reused while it lasts.
+    pub fn content_generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
     /// Close a document
     pub async fn did_close(&self, file_path: &Path) -> Result<(), LspError> {
         let uri = Url::from_file_path(file_path)
@@ -421,28 +1492,179 @@ impl LspClient {
         self.send_notification("textDocument/didClose", params)
             .await?;
 
-        // Remove from tracking
+        // Remove from tracking; it may have changed by the time it's reopened
         self.opened_documents.lock().await.remove(file_path);
+        self.generation.store(next_generation(), Ordering::Relaxed);
 
         Ok(())
     }
 
+    /// Whether a document is open on the server
+    pub async fn has_document(&self, file_path: &Path) -> bool {
+        self.opened_documents.lock().await.contains_key(file_path)
+    }
+
+    /// The documents open in the server, sorted
+    pub async fn open_documents(&self) -> Vec<PathBuf> {
+        let mut documents: Vec<PathBuf> =
+            self.opened_documents.lock().await.keys().cloned().collect();
+        documents.sort();
+        documents
+    }
+
+    /// The diagnostics last published for `file_path`, as the server sent
+    /// them
+    pub async fn published_diagnostics(&self, file_path: &Path) -> Option<Vec<Diagnostic>> {
+        self.diagnostics.lock().await.get(file_path).cloned()
+    }
+
+    /// Use `diagnostics` for `file_path` until the server publishes its own
+    pub async fn seed_diagnostics(&self, file_path: &Path, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics
+            .lock()
+            .await
+            .entry(file_path.to_path_buf())
+            .or_insert(diagnostics);
+    }
+
     /// Get server capabilities
     pub async fn capabilities(&self) -> Option<ServerCapabilities> {
         self.capabilities.lock().await.clone()
     }
 
+    /// Whether the server handles `method`, per its capabilities or a
+    /// later registration (assumed before it's initialized)
+    pub async fn supports(&self, method: &str) -> bool {
+        let registered = self
+            .registrations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .has(method);
+        registered
+            || self
+                .capabilities
+                .lock()
+                .await
+                .as_ref()
+                .is_none_or(|c| server_supports(c, method))
+    }
+
+    /// Methods the server registered dynamically
+    pub fn registered_methods(&self) -> Vec<String> {
+        self.registrations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .methods()
+    }
+
+    /// Tell the server about changes to the files its registered watchers
+    /// match since the last poll
+    ///
+    /// The first poll after the server registers watchers only records the
+    /// files, and nothing is scanned while it has none.
+    pub async fn poll_watched_files(&self) -> Result<(), LspError> {
+        let watchers = {
+            let registrations = self.registrations.lock().unwrap_or_else(|e| e.into_inner());
+            registrations
+                .has(WATCHED_FILES_METHOD)
+                .then(|| registrations.file_watchers(&self.workspace_root))
+        };
+        let Some(watchers) = watchers else {
+            *self.watched_files.lock().await = None;
+            return Ok(());
+        };
+
+        let root = self.workspace_root.clone();
+        let scan_watchers = watchers.clone();
+        let snapshot =
+            tokio::task::spawn_blocking(move || file_watcher::scan(&root, &scan_watchers))
+                .await
+                .map_err(|e| LspError::ProtocolError(format!("File scan failed: {}", e)))?;
+
+        let previous = self.watched_files.lock().await.replace(snapshot.clone());
+        let Some(previous) = previous else {
+            return Ok(());
+        };
+        let changes = file_watcher::changes(&previous, &snapshot, &watchers);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "{} watched files changed for {}",
+            changes.len(),
+            self.config.name
+        );
+        self.send_notification(
+            WATCHED_FILES_METHOD,
+            DidChangeWatchedFilesParams { changes },
+        )
+        .await
+    }
+
+    /// Encoding the server counts columns in (UTF-16 unless negotiated)
+    pub async fn position_encoding(&self) -> PositionEncoding {
+        self.capabilities
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|c| c.position_encoding.as_ref())
+            .and_then(PositionEncoding::from_lsp)
+            .unwrap_or_default()
+    }
+
+    /// Mapper between two encodings, seeded with the tracked text of
+    /// `file_path` so it matches what the server has seen
+    async fn position_mapper(
+        &self,
+        file_path: Option<&Path>,
+        from: PositionEncoding,
+        to: PositionEncoding,
+    ) -> PositionMapper {
+        let mut mapper = PositionMapper::new(from, to);
+        if let (false, Some(path)) = (mapper.is_identity(), file_path) {
+            if let Some(document) = self.opened_documents.lock().await.get(path) {
+                mapper.add_text(path, document.text.clone());
+            }
+        }
+        mapper
+    }
+
+    /// A caller's position in an open document, in the server's encoding
+    async fn server_position(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        encoding: PositionEncoding,
+    ) -> Position {
+        let server = self.position_encoding().await;
+        self.position_mapper(Some(file_path), encoding, server)
+            .await
+            .position(file_path, Position { line, character })
+    }
+
+    /// Mapper for translating the server's responses into `encoding`
+    async fn response_mapper(
+        &self,
+        file_path: Option<&Path>,
+        encoding: PositionEncoding,
+    ) -> PositionMapper {
+        let server = self.position_encoding().await;
+        self.position_mapper(file_path, server, encoding).await
+    }
+
     /// Go to definition
+    ///
+    /// `line`/`character` and the returned positions are in `encoding`.
     pub async fn goto_definition(
         &self,
         file_path: &Path,
         line: u32,
         character: u32,
+        encoding: PositionEncoding,
     ) -> Result<Option<GotoDefinitionResponse>, LspError> {
-        // Ensure document is opened
-        if !self.opened_documents.lock().await.contains_key(file_path) {
-            self.did_open(file_path).await?;
-        }
+        // Ensure the server has the current document
+        let _document = self.lease_document(file_path).await?;
 
         let uri = Url::from_file_path(file_path)
             .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
@@ -450,13 +1672,18 @@ impl LspClient {
         let params = GotoDefinitionParams {
             text_document_position_params: TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier { uri },
-                position: Position { line, character },
+                position: self
+                    .server_position(file_path, line, character, encoding)
+                    .await,
             },
             work_done_progress_params: WorkDoneProgressParams::default(),
             partial_result_params: PartialResultParams::default(),
         };
 
-        self.send_request("textDocument/definition", params).await
+        let response: Option<GotoDefinitionResponse> =
+            self.send_request("textDocument/definition", params).await?;
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(response.map(|r| mapper.definition(r)))
     }
 
     /// Find references
@@ -466,11 +1693,10 @@ impl LspClient {
         line: u32,
         character: u32,
         include_declaration: bool,
+        encoding: PositionEncoding,
     ) -> Result<Option<Vec<Location>>, LspError> {
-        // Ensure document is opened
-        if !self.opened_documents.lock().await.contains_key(file_path) {
-            self.did_open(file_path).await?;
-        }
+        // Ensure the server has the current document
+        let _document = self.lease_document(file_path).await?;
 
         let uri = Url::from_file_path(file_path)
             .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
@@ -478,7 +1704,9 @@ impl LspClient {
         let params = ReferenceParams {
             text_document_position: TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier { uri },
-                position: Position { line, character },
+                position: self
+                    .server_position(file_path, line, character, encoding)
+                    .await,
             },
             context: ReferenceContext {
                 include_declaration,
@@ -487,7 +1715,20 @@ impl LspClient {
             partial_result_params: PartialResultParams::default(),
         };
 
-        self.send_request("textDocument/references", params).await
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        let mut streamed = Vec::new();
+        let locations: Option<Vec<Location>> = self
+            .send_request_streaming("textDocument/references", params, |batch: Vec<Location>| {
+                let batch: Vec<Location> = batch.into_iter().map(|l| mapper.location(l)).collect();
+                partial::report(PartialBatch::Locations(&batch));
+                streamed.extend(batch);
+            })
+            .await?;
+        if locations.is_none() && streamed.is_empty() {
+            return Ok(None);
+        }
+        streamed.extend(locations.into_iter().flatten().map(|l| mapper.location(l)));
+        Ok(Some(streamed))
     }
 
     /// Hover information
@@ -496,11 +1737,10 @@ impl LspClient {
         file_path: &Path,
         line: u32,
         character: u32,
+        encoding: PositionEncoding,
     ) -> Result<Option<Hover>, LspError> {
-        // Ensure document is opened
-        if !self.opened_documents.lock().await.contains_key(file_path) {
-            self.did_open(file_path).await?;
-        }
+        // Ensure the server has the current document
+        let _document = self.lease_document(file_path).await?;
 
         let uri = Url::from_file_path(file_path)
             .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
@@ -508,23 +1748,64 @@ impl LspClient {
         let params = HoverParams {
             text_document_position_params: TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier { uri },
-                position: Position { line, character },
+                position: self
+                    .server_position(file_path, line, character, encoding)
+                    .await,
             },
             work_done_progress_params: WorkDoneProgressParams::default(),
         };
 
-        self.send_request("textDocument/hover", params).await
+        let hover: Option<Hover> = self.send_request("textDocument/hover", params).await?;
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(hover.map(|h| mapper.hover(file_path, h)))
+    }
+
+    /// The edit renaming the symbol at a position to `new_name`
+    ///
+    /// `line`/`character` and the returned edit are in `encoding`. `None` if
+    /// the server can't rename or finds nothing to rename there.
+    pub async fn rename(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        new_name: &str,
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceEdit>, LspError> {
+        match self.capabilities().await.and_then(|c| c.rename_provider) {
+            None | Some(OneOf::Left(false)) => return Ok(None),
+            Some(_) => {}
+        }
+
+        let _document = self.lease_document(file_path).await?;
+
+        let uri = Url::from_file_path(file_path)
+            .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+
+        let params = RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: self
+                    .server_position(file_path, line, character, encoding)
+                    .await,
+            },
+            new_name: new_name.to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        let edit: Option<WorkspaceEdit> = self.send_request("textDocument/rename", params).await?;
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(edit.map(|edit| mapper.workspace_edit(edit)))
     }
 
     /// Document symbols
     pub async fn document_symbols(
         &self,
         file_path: &Path,
+        encoding: PositionEncoding,
     ) -> Result<Option<DocumentSymbolResponse>, LspError> {
-        // Ensure document is opened
-        if !self.opened_documents.lock().await.contains_key(file_path) {
-            self.did_open(file_path).await?;
-        }
+        // Ensure the server has the current document
+        let _document = self.lease_document(file_path).await?;
 
         let uri = Url::from_file_path(file_path)
             .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
@@ -535,38 +1816,430 @@ impl LspClient {
             partial_result_params: PartialResultParams::default(),
         };
 
-        self.send_request("textDocument/documentSymbol", params)
-            .await
+        let response: Option<DocumentSymbolResponse> = self
+            .send_request("textDocument/documentSymbol", params)
+            .await?;
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(response.map(|r| mapper.document_symbols(file_path, r)))
     }
 
     /// Get diagnostics for a file
-    pub async fn get_diagnostics(&self, file_path: &Path) -> Result<Vec<Diagnostic>, LspError> {
-        // Ensure document is opened to receive diagnostics
-        if !self.opened_documents.lock().await.contains_key(file_path) {
-            self.did_open(file_path).await?;
-
+    pub async fn get_diagnostics(
+        &self,
+        file_path: &Path,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Diagnostic>, LspError> {
+        // Ensure the server has the current document to diagnose
+        let document = self.lease_document(file_path).await?;
+        if document.changed {
             // Wait a bit for diagnostics to be published
             tokio::time::sleep(Duration::from_millis(500)).await;
         }
 
-        let diagnostics_guard = self.diagnostics.lock().await;
-        Ok(diagnostics_guard
+        let diagnostics = self
+            .diagnostics
+            .lock()
+            .await
             .get(file_path)
             .cloned()
-            .unwrap_or_default())
+            .unwrap_or_default();
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(mapper.diagnostics(file_path, diagnostics))
+    }
+
+    /// Diagnostics for the current content of a document, waiting up to
+    /// `wait` for the server to publish them when the content just changed
+    pub async fn fresh_diagnostics(
+        &self,
+        file_path: &Path,
+        wait: Duration,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Diagnostic>, LspError> {
+        let mut published = self.published.subscribe();
+        let before = published
+            .borrow_and_update()
+            .get(file_path)
+            .map(|publications| publications.count);
+        let document = self.lease_document(file_path).await?;
+        if document.changed {
+            let version = self
+                .opened_documents
+                .lock()
+                .await
+                .get(file_path)
+                .map(|document| document.version);
+            // A server that says which version it diagnosed may still be
+            // publishing for the previous content; wait for this one's
+            let fresh = published.wait_for(|published| {
+                published.get(file_path).is_some_and(|publications| {
+                    match (publications.version, version) {
+                        (Some(diagnosed), Some(version)) => diagnosed == version,
+                        _ => Some(publications.count) > before,
+                    }
+                })
+            });
+            if tokio::time::timeout(wait, fresh).await.is_err() {
+                debug!(
+                    "{} published no diagnostics for {} within {}s",
+                    self.config.name,
+                    file_path.display(),
+                    wait.as_secs()
+                );
+            }
+        }
+
+        let diagnostics = self
+            .diagnostics
+            .lock()
+            .await
+            .get(file_path)
+            .cloned()
+            .unwrap_or_default();
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(mapper.diagnostics(file_path, diagnostics))
+    }
+
+    /// Code actions for `range`, given the diagnostics there
+    ///
+    /// `range`, `diagnostics` and the returned edits are in `encoding`.
+    /// Actions without an edit are resolved when the server supports it.
+    pub async fn code_actions(
+        &self,
+        file_path: &Path,
+        range: Range,
+        diagnostics: Vec<Diagnostic>,
+        only: Option<Vec<CodeActionKind>>,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<CodeActionOrCommand>, LspError> {
+        let resolve = match self
+            .capabilities()
+            .await
+            .and_then(|c| c.code_action_provider)
+        {
+            None | Some(CodeActionProviderCapability::Simple(false)) => return Ok(Vec::new()),
+            Some(CodeActionProviderCapability::Simple(true)) => false,
+            Some(CodeActionProviderCapability::Options(options)) => {
+                options.resolve_provider.unwrap_or(false)
+            }
+        };
+
+        let _document = self.lease_document(file_path).await?;
+
+        let uri = Url::from_file_path(file_path)
+            .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+        let server = self.position_encoding().await;
+        let mut request_mapper = self
+            .position_mapper(Some(file_path), encoding, server)
+            .await;
+
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier { uri },
+            range: request_mapper.range(file_path, range),
+            context: CodeActionContext {
+                diagnostics: request_mapper.diagnostics(file_path, diagnostics),
+                only,
+                trigger_kind: Some(CodeActionTriggerKind::INVOKED),
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let actions: Option<Vec<CodeActionOrCommand>> =
+            self.send_request("textDocument/codeAction", params).await?;
+
+        let mut resolved = Vec::new();
+        for action in actions.unwrap_or_default() {
+            let action = match action {
+                CodeActionOrCommand::CodeAction(action)
+                    if resolve && action.edit.is_none() && action.data.is_some() =>
+                {
+                    match self
+                        .send_request::<_, CodeAction>("codeAction/resolve", action.clone())
+                        .await
+                    {
+                        Ok(action) => CodeActionOrCommand::CodeAction(action),
+                        Err(e) => {
+                            debug!("Failed to resolve code action {}: {}", action.title, e);
+                            CodeActionOrCommand::CodeAction(action)
+                        }
+                    }
+                }
+                other => other,
+            };
+            resolved.push(action);
+        }
+
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(resolved
+            .into_iter()
+            .map(|action| mapper.code_action(file_path, action))
+            .collect())
     }
 
     /// Search for symbols across the workspace
+    ///
+    /// Servers may answer with either flat `SymbolInformation` or LSP 3.17
+    /// `WorkspaceSymbol` entries, which can omit ranges until resolved.
     pub async fn workspace_symbols(
         &self,
         query: String,
-    ) -> Result<Option<Vec<SymbolInformation>>, LspError> {
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceSymbolResponse>, LspError> {
         let params = WorkspaceSymbolParams {
             query,
             work_done_progress_params: WorkDoneProgressParams::default(),
             partial_result_params: PartialResultParams::default(),
         };
 
-        self.send_request("workspace/symbol", params).await
+        let mut mapper = self.response_mapper(None, encoding).await;
+        let mut streamed = Vec::new();
+        let response: Option<WorkspaceSymbolResponse> = self
+            .send_request_streaming("workspace/symbol", params, |batch| {
+                let batch = mapper.workspace_symbols(batch);
+                partial::report(PartialBatch::Symbols(&batch));
+                streamed.push(batch);
+            })
+            .await?;
+        streamed.extend(response.map(|r| mapper.workspace_symbols(r)));
+        Ok(merge::merge_workspace_symbols(streamed))
+    }
+
+    /// Whether the server supports `workspaceSymbol/resolve`
+    pub async fn supports_workspace_symbol_resolve(&self) -> bool {
+        match self
+            .capabilities()
+            .await
+            .and_then(|c| c.workspace_symbol_provider)
+        {
+            Some(OneOf::Right(options)) => options.resolve_provider.unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Resolve a workspace symbol's missing location range
+    ///
+    /// Only pass symbols without a range: the request is sent as-is, so any
+    /// positions in it would be in the wrong encoding.
+    pub async fn resolve_workspace_symbol(
+        &self,
+        symbol: WorkspaceSymbol,
+        encoding: PositionEncoding,
+    ) -> Result<WorkspaceSymbol, LspError> {
+        let symbol: WorkspaceSymbol = self.send_request("workspaceSymbol/resolve", symbol).await?;
+        let mut mapper = self.response_mapper(None, encoding).await;
+        Ok(mapper.workspace_symbol(symbol))
+    }
+
+    /// Tests, benchmarks and binaries the server can run in a file, those
+    /// at `position` if given
+    ///
+    /// Uses rust-analyzer's `experimental/runnables` when the server
+    /// advertises it and code lenses otherwise; servers with neither have
+    /// none.
+    pub async fn runnables(
+        &self,
+        file_path: &Path,
+        position: Option<Position>,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Runnable>, LspError> {
+        let Some(capabilities) = self.capabilities().await else {
+            return Ok(Vec::new());
+        };
+        let experimental = capabilities
+            .experimental
+            .as_ref()
+            .is_some_and(|e| e.get("runnables").is_some());
+        if !experimental && capabilities.code_lens_provider.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let _document = self.lease_document(file_path).await?;
+        let uri = Url::from_file_path(file_path)
+            .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+        let server_position = match position {
+            Some(p) => Some(
+                self.server_position(file_path, p.line, p.character, encoding)
+                    .await,
+            ),
+            None => None,
+        };
+
+        let runnables = if experimental {
+            let params = serde_json::json!({
+                "textDocument": TextDocumentIdentifier { uri },
+                "position": server_position,
+            });
+            runnables::from_rust_analyzer(
+                self.send_request_value("experimental/runnables", params, ResponseWait::DEFAULT)
+                    .await?,
+            )
+        } else {
+            let params = CodeLensParams {
+                text_document: TextDocumentIdentifier { uri },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                partial_result_params: PartialResultParams::default(),
+            };
+            let lenses: Option<Vec<CodeLens>> =
+                self.send_request("textDocument/codeLens", params).await?;
+            let mut runnables = runnables::from_code_lenses(lenses.unwrap_or_default(), file_path);
+            // Lenses sit on a function's first line, so the ones for a
+            // position are the closest at or above it
+            if let Some(p) = server_position {
+                let line = |r: &Runnable| r.location.as_ref().map(|l| l.range.start.line);
+                runnables.retain(|r| line(r).is_some_and(|line| line <= p.line));
+                let closest = runnables.iter().filter_map(line).max();
+                runnables.retain(|r| line(r) == closest);
+            }
+            runnables
+        };
+
+        let mut mapper = self.response_mapper(Some(file_path), encoding).await;
+        Ok(runnables
+            .into_iter()
+            .map(|mut runnable| {
+                runnable.location = runnable.location.map(|l| mapper.location(l));
+                runnable
+            })
+            .collect())
+    }
+
+    /// Send any request and return the raw result, positions and all in
+    /// the server's terms (paths are still mapped)
+    pub async fn raw_request(&self, method: &str, params: Value) -> Result<Value, LspError> {
+        self.send_request_value(method, params, ResponseWait::DEFAULT)
+            .await
+    }
+
+    /// The extension request this server exposes as `tool`, if any
+    pub fn extension(&self, tool: &str) -> Option<&ExtensionRequest> {
+        self.config.extensions.iter().find(|e| e.tool == tool)
+    }
+
+    /// Send a server-specific request about a file, or a position in it
+    ///
+    /// `position` is in `encoding` and required for `ExtensionParams::Position`
+    /// requests. The raw result is returned: the client can't know its
+    /// shape, so positions in it are left in the server's encoding.
+    pub async fn extension_request(
+        &self,
+        extension: &ExtensionRequest,
+        file_path: &Path,
+        position: Option<Position>,
+        encoding: PositionEncoding,
+    ) -> Result<Value, LspError> {
+        let _document = self.lease_document(file_path).await?;
+
+        let uri = Url::from_file_path(file_path)
+            .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+        let text_document = TextDocumentIdentifier { uri };
+
+        let params = match (extension.params, position) {
+            (ExtensionParams::Document, _) => serde_json::json!({ "textDocument": text_document }),
+            (ExtensionParams::Uri, _) => serde_json::to_value(text_document)?,
+            (ExtensionParams::Position, Some(position)) => {
+                let position = self
+                    .server_position(file_path, position.line, position.character, encoding)
+                    .await;
+                serde_json::to_value(TextDocumentPositionParams {
+                    text_document,
+                    position,
+                })?
+            }
+            (ExtensionParams::Position, None) => {
+                return Err(LspError::ProtocolError(format!(
+                    "{} needs a position",
+                    extension.method
+                )))
+            }
+        };
+        self.send_request_value(&extension.method, params, ResponseWait::DEFAULT)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pending_requests_coalesce() {
+        let mut table = PendingTable::default();
+        let (first, first_rx) = oneshot::channel();
+        table.insert(1, Some("textDocument/hover {}".to_string()), first);
+
+        let (second, second_rx) = oneshot::channel();
+        assert!(matches!(table.join("textDocument/hover {}", second), Ok(1)));
+        let (other, _) = oneshot::channel();
+        assert!(table.join("textDocument/definition {}", other).is_err());
+
+        table.remove(1).unwrap().respond(Ok(Value::from("foo")));
+        assert_eq!(first_rx.await.unwrap().unwrap(), Value::from("foo"));
+        assert_eq!(second_rx.await.unwrap().unwrap(), Value::from("foo"));
+
+        // A waiter that gives up leaves the request to the others
+        let (first, first_rx) = oneshot::channel();
+        table.insert(2, Some("textDocument/hover {}".to_string()), first);
+        let (second, second_rx) = oneshot::channel();
+        assert!(table.join("textDocument/hover {}", second).is_ok());
+        drop(first_rx);
+        table.abandon(2);
+        assert_eq!(table.len(), 1);
+
+        // Errors reach every waiter with their kind
+        let (third, third_rx) = oneshot::channel();
+        assert!(table.join("textDocument/hover {}", third).is_ok());
+        let crashed = LspError::ServerCrashed("exited".to_string());
+        table.remove(2).unwrap().respond(Err(crashed));
+        let kinds = [second_rx.await.unwrap(), third_rx.await.unwrap()]
+            .map(|result| result.unwrap_err().kind());
+        assert_eq!(kinds, ["server_crashed", "server_crashed"]);
+
+        // Nobody left waiting: the request is dropped
+        let (last, last_rx) = oneshot::channel();
+        table.insert(3, None, last);
+        drop(last_rx);
+        table.abandon(3);
+        assert_eq!(table.len(), 0);
+
+        // Finished requests are no longer joined
+        let (late, _) = oneshot::channel();
+        assert!(table.join("textDocument/hover {}", late).is_err());
+        assert_eq!(table.len(), 0);
+    }
+
+    #[test]
+    fn test_configuration_section() {
+        let settings = serde_json::json!({ "python": { "analysis": { "extraPaths": ["lib"] } } });
+        assert_eq!(
+            configuration_section(Some(&settings), Some("python.analysis")),
+            serde_json::json!({ "extraPaths": ["lib"] })
+        );
+        assert_eq!(configuration_section(Some(&settings), None), settings);
+        assert_eq!(
+            configuration_section(Some(&settings), Some("python.linting")),
+            Value::Null
+        );
+        assert_eq!(configuration_section(None, Some("python")), Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_skips_stray_output() {
+        let (mut server, client) = tokio::io::duplex(1024);
+        let message = r#"{"jsonrpc":"2.0","id":1,"result":null}"#;
+        let output = format!(
+            "Starting server v1.2...\n\nContent-Length: {0}\r\n\r\n{1}\
+             warning: no config foundContent-Length: {0}\r\nContent-Type: x\r\n\r\n{1}",
+            message.len(),
+            message
+        );
+        server.write_all(output.as_bytes()).await.unwrap();
+        drop(server);
+
+        let mut reader = BufReader::new(client);
+        for _ in 0..2 {
+            let frame = read_frame(&mut reader, "mock").await.unwrap().unwrap();
+            assert_eq!(frame, message.as_bytes());
+        }
+        assert!(read_frame(&mut reader, "mock").await.unwrap().is_none());
     }
 }
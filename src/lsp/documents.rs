@@ -0,0 +1,222 @@
+//! Lifecycle of the documents open on a server
+//!
+//! Queries open their file on the server implicitly, and nothing else
+//! closes it, so a long session would leave hundreds of documents open and
+//! some servers (pyright, tsserver) grow with every one. Each client keeps
+//! at most `max_open_documents` open and closes the least recently used
+//! beyond that. A request leases its document while it runs, so a document
+//! is never closed under a request that is still using it.
+//!
+//! Nor is it changed under one: requests on the content the server has
+//! share the document, and a request sending other content (a file changed
+//! on disk, or a tool call's unsaved content) waits until they are answered.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+/// Documents each server keeps open unless configured otherwise
+pub const DEFAULT_MAX_OPEN_DOCUMENTS: usize = 200;
+
+/// A document opened on the server
+pub struct OpenDocument {
+    pub text: String,
+    pub version: i32,
+    /// When a request last used it
+    pub last_used: Instant,
+}
+
+impl OpenDocument {
+    pub fn new(text: String, version: i32) -> Self {
+        Self {
+            text,
+            version,
+            last_used: Instant::now(),
+        }
+    }
+}
+
+/// A document in use: how many requests use it, and the lock keeping its
+/// content from changing under them
+#[derive(Default)]
+struct Leased {
+    count: usize,
+    content: Arc<RwLock<()>>,
+}
+
+/// The documents in use by in-flight requests
+#[derive(Clone, Default)]
+pub struct Leases(Arc<Mutex<HashMap<PathBuf, Leased>>>);
+
+impl Leases {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Leased>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Mark `path` in use until the lease is dropped
+    ///
+    /// The lease doesn't hold the document's content yet; see
+    /// `DocumentLease::share` and `DocumentLease::change`.
+    pub fn lease(&self, path: &Path) -> DocumentLease {
+        let mut leases = self.lock();
+        let leased = leases.entry(path.to_path_buf()).or_default();
+        leased.count += 1;
+        DocumentLease {
+            leases: self.clone(),
+            path: path.to_path_buf(),
+            content: Arc::clone(&leased.content),
+            _shared: None,
+            exclusive: None,
+            changed: false,
+        }
+    }
+
+    pub fn is_leased(&self, path: &Path) -> bool {
+        self.lock().contains_key(path)
+    }
+}
+
+/// A document in use by a request
+pub struct DocumentLease {
+    leases: Leases,
+    path: PathBuf,
+    content: Arc<RwLock<()>>,
+    /// Held while the request uses the content the server has
+    _shared: Option<OwnedRwLockReadGuard<()>>,
+    /// Held while the request changes it
+    exclusive: Option<OwnedRwLockWriteGuard<()>>,
+    /// Whether the server's copy changed when the request synced it
+    pub changed: bool,
+}
+
+impl DocumentLease {
+    /// Wait until no request is changing the document, then keep it from
+    /// changing until the lease is dropped
+    pub async fn share(&mut self) {
+        self.release();
+        self._shared = Some(Arc::clone(&self.content).read_owned().await);
+    }
+
+    /// Wait until no other request is using the document, so its content
+    /// can be changed, and keep it until `share` or the lease is dropped
+    pub async fn change(&mut self) {
+        self.release();
+        self.exclusive = Some(Arc::clone(&self.content).write_owned().await);
+    }
+
+    /// Let other requests on the (changed) content in, without letting
+    /// anyone change it in between
+    pub fn changed(&mut self) {
+        if let Some(exclusive) = self.exclusive.take() {
+            self._shared = Some(exclusive.downgrade());
+        }
+    }
+
+    fn release(&mut self) {
+        self._shared = None;
+        self.exclusive = None;
+    }
+}
+
+impl Drop for DocumentLease {
+    fn drop(&mut self) {
+        let mut leases = self.leases.lock();
+        if let Some(leased) = leases.get_mut(&self.path) {
+            leased.count -= 1;
+            if leased.count == 0 {
+                leases.remove(&self.path);
+            }
+        }
+    }
+}
+
+/// The documents to close to get back to `max` open (0 for no limit),
+/// least recently used first, skipping leased ones
+pub fn eviction_candidates(
+    documents: &HashMap<PathBuf, OpenDocument>,
+    leases: &Leases,
+    max: usize,
+) -> Vec<PathBuf> {
+    if max == 0 || documents.len() <= max {
+        return Vec::new();
+    }
+    let mut idle: Vec<(&PathBuf, Instant)> = documents
+        .iter()
+        .filter(|(path, _)| !leases.is_leased(path))
+        .map(|(path, document)| (path, document.last_used))
+        .collect();
+    idle.sort_by_key(|(_, last_used)| *last_used);
+    idle.into_iter()
+        .take(documents.len() - max)
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_eviction_candidates() {
+        let start = Instant::now();
+        let documents: HashMap<PathBuf, OpenDocument> = ["a", "b", "c", "d"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let mut document = OpenDocument::new(String::new(), 1);
+                document.last_used = start + Duration::from_secs(i as u64);
+                (PathBuf::from(name), document)
+            })
+            .collect();
+        let leases = Leases::default();
+
+        assert!(eviction_candidates(&documents, &leases, 4).is_empty());
+        assert!(eviction_candidates(&documents, &leases, 0).is_empty());
+        assert_eq!(
+            eviction_candidates(&documents, &leases, 2),
+            [PathBuf::from("a"), PathBuf::from("b")]
+        );
+
+        // Documents in use are kept, even when least recently used
+        let lease = leases.lease(Path::new("a"));
+        let second = leases.lease(Path::new("a"));
+        assert_eq!(
+            eviction_candidates(&documents, &leases, 2),
+            [PathBuf::from("b"), PathBuf::from("c")]
+        );
+        drop(lease);
+        assert!(leases.is_leased(Path::new("a")));
+        drop(second);
+        assert!(!leases.is_leased(Path::new("a")));
+    }
+
+    #[tokio::test]
+    async fn test_lease_content() {
+        let leases = Leases::default();
+        let mut first = leases.lease(Path::new("a"));
+        let mut second = leases.lease(Path::new("a"));
+        first.share().await;
+        second.share().await;
+
+        // A change waits for every request on the current content
+        let mut change = leases.lease(Path::new("a"));
+        let changing = tokio::time::timeout(Duration::from_millis(50), change.change());
+        assert!(changing.await.is_err());
+        drop(first);
+        drop(second);
+        change.change().await;
+
+        // ...and requests wait for the change, then share the new content
+        let mut next = leases.lease(Path::new("a"));
+        let sharing = tokio::time::timeout(Duration::from_millis(50), next.share());
+        assert!(sharing.await.is_err());
+        change.changed();
+        next.share().await;
+
+        // Other documents are unaffected
+        leases.lease(Path::new("b")).change().await;
+    }
+}
@@ -0,0 +1,125 @@
+//! Polling for the file changes servers registered watchers for
+//!
+//! lsmcp has no editor telling servers about files changed outside open
+//! documents (a `git checkout`, a regenerated `go.sum`), so the manager
+//! rescans the files matching each server's registered watchers and sends
+//! `workspace/didChangeWatchedFiles` for what changed between scans.
+
+use crate::lsp::registrations::FileWatcher;
+use crate::tools::files::walk_files;
+use lsp_types::{FileChangeType, FileEvent, WatchKind};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use url::Url;
+
+/// Modification times of the watched files under a root
+pub type Snapshot = HashMap<PathBuf, SystemTime>;
+
+/// The files under `root` any of `watchers` matches, skipping ignored ones
+pub fn scan(root: &Path, watchers: &[FileWatcher]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    walk_files(root, |path| {
+        if watchers.iter().any(|watcher| watcher.matches(&path)) {
+            let modified = path
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            snapshot.insert(path, modified);
+        }
+        true
+    });
+    snapshot
+}
+
+/// Events for the differences between two scans that some watcher wants,
+/// sorted by path
+pub fn changes(old: &Snapshot, new: &Snapshot, watchers: &[FileWatcher]) -> Vec<FileEvent> {
+    let mut changed: Vec<(&PathBuf, FileChangeType, WatchKind)> = new
+        .iter()
+        .filter_map(|(path, modified)| match old.get(path) {
+            None => Some((path, FileChangeType::CREATED, WatchKind::Create)),
+            Some(before) if before != modified => {
+                Some((path, FileChangeType::CHANGED, WatchKind::Change))
+            }
+            Some(_) => None,
+        })
+        .chain(
+            old.keys()
+                .filter(|path| !new.contains_key(*path))
+                .map(|path| (path, FileChangeType::DELETED, WatchKind::Delete)),
+        )
+        .filter(|(path, _, kind)| watchers.iter().any(|w| w.wants(path, *kind)))
+        .collect();
+    changed.sort_by(|a, b| a.0.cmp(b.0));
+
+    changed
+        .into_iter()
+        .filter_map(|(path, typ, _)| {
+            Some(FileEvent {
+                uri: Url::from_file_path(path).ok()?,
+                typ,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::registrations::{Registrations, WATCHED_FILES_METHOD};
+    use lsp_types::{Registration, RegistrationParams};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_changes_between_scans() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let mut registrations = Registrations::default();
+        registrations.register(RegistrationParams {
+            registrations: vec![Registration {
+                id: "watch".to_string(),
+                method: WATCHED_FILES_METHOD.to_string(),
+                register_options: Some(json!({ "watchers": [{ "globPattern": "**/*.go" }] })),
+            }],
+        });
+        let watchers = registrations.file_watchers(root);
+
+        std::fs::write(root.join("main.go"), "package main").unwrap();
+        std::fs::write(root.join("old.go"), "package main").unwrap();
+        std::fs::write(root.join("README.md"), "").unwrap();
+        let before = scan(root, &watchers);
+        assert_eq!(before.len(), 2);
+
+        std::fs::remove_file(root.join("old.go")).unwrap();
+        std::fs::write(root.join("new.go"), "package main").unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(root.join("main.go"))
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let after = scan(root, &watchers);
+
+        let events: Vec<(String, FileChangeType)> = changes(&before, &after, &watchers)
+            .into_iter()
+            .map(|event| {
+                let path = event.uri.to_file_path().unwrap();
+                (
+                    path.file_name().unwrap().to_string_lossy().into(),
+                    event.typ,
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("main.go".to_string(), FileChangeType::CHANGED),
+                ("new.go".to_string(), FileChangeType::CREATED),
+                ("old.go".to_string(), FileChangeType::DELETED),
+            ]
+        );
+        assert!(changes(&after, &after, &watchers).is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+//! Language server health tracking
+//!
+//! Snapshots of a running server's state, used by the manager to detect dead
+//! servers and by the `lsp_server_status` tool to report on them.
+
+use crate::lsp::breaker::BreakerState;
+use crate::lsp::progress::ActiveProgress;
+use crate::lsp::throttle::QueueStats;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Overall state of a server process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerState {
+    /// Running and idle
+    Ready,
+    /// Running with progress operations (usually indexing) in flight
+    Indexing,
+    /// The process has exited
+    Exited(Option<i32>),
+}
+
+/// The most recent request failure reported by a server
+#[derive(Debug, Clone)]
+pub struct LastError {
+    pub message: String,
+    pub at: Instant,
+}
+
+/// Point-in-time health report for one server
+#[derive(Debug, Clone)]
+pub struct ServerHealth {
+    pub language: String,
+    pub server_name: String,
+    pub project_root: PathBuf,
+    pub binary: String,
+    pub pid: Option<u32>,
+    pub uptime: Duration,
+    pub pending_requests: usize,
+    /// Requests in flight and waiting their turn
+    pub queue: QueueStats,
+    pub breaker: BreakerState,
+    /// Times the circuit breaker opened
+    pub breaker_trips: u64,
+    pub last_error: Option<LastError>,
+    pub progress: Vec<ActiveProgress>,
+    pub state: ServerState,
+}
+
+impl ServerHealth {
+    /// Whether the server can still answer requests
+    pub fn is_alive(&self) -> bool {
+        !matches!(self.state, ServerState::Exited(_))
+    }
+
+    /// Human-readable multi-line report
+    pub fn render(&self) -> String {
+        let state = match &self.state {
+            ServerState::Ready => "ready".to_string(),
+            ServerState::Indexing => "indexing".to_string(),
+            ServerState::Exited(Some(code)) => format!("exited (code {})", code),
+            ServerState::Exited(None) => "exited".to_string(),
+        };
+
+        let mut output = format!(
+            "- {} ({}) [{}]\n  root: {}\n  binary: {}\n  pid: {}\n  uptime: {}\n  pending requests: {}\n",
+            self.language,
+            self.server_name,
+            state,
+            self.project_root.display(),
+            self.binary,
+            self.pid
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format_duration(self.uptime),
+            self.pending_requests,
+        );
+
+        if self.queue.limit > 0 {
+            output.push_str(&format!(
+                "  request slots: {}/{} in use",
+                self.queue.in_flight, self.queue.limit
+            ));
+            if self.queue.queued > 0 {
+                output.push_str(&format!(", {} queued", self.queue.queued));
+            }
+            if self.queue.waited > 0 {
+                output.push_str(&format!(
+                    " ({} waited, {}ms on average, at most {} queued at once)",
+                    self.queue.waited,
+                    self.queue.average_wait.as_millis(),
+                    self.queue.peak_queued
+                ));
+            }
+            output.push('\n');
+        }
+
+        match &self.breaker {
+            BreakerState::Closed { .. } if self.breaker_trips == 0 => {}
+            BreakerState::Closed { .. } => output.push_str(&format!(
+                "  circuit breaker: closed (opened {} time(s))\n",
+                self.breaker_trips
+            )),
+            BreakerState::Open { failures, retry_in } => output.push_str(&format!(
+                "  circuit breaker: open after {} failed request(s) in a row; requests fail at once for {}\n",
+                failures,
+                format_duration(*retry_in)
+            )),
+            BreakerState::HalfOpen { failures } => output.push_str(&format!(
+                "  circuit breaker: trying the server again after {} failed request(s) in a row\n",
+                failures
+            )),
+        }
+
+        for progress in &self.progress {
+            let detail = match (&progress.message, progress.percentage) {
+                (Some(message), Some(pct)) => format!(" {} ({}%)", message, pct),
+                (Some(message), None) => format!(" {}", message),
+                (None, Some(pct)) => format!(" ({}%)", pct),
+                (None, None) => String::new(),
+            };
+            output.push_str(&format!(
+                "  progress: {}{} for {}\n",
+                progress.title,
+                detail,
+                format_duration(progress.started.elapsed())
+            ));
+        }
+
+        if let Some(error) = &self.last_error {
+            output.push_str(&format!(
+                "  last error: {} ({} ago)\n",
+                error.message,
+                format_duration(error.at.elapsed())
+            ));
+        }
+
+        output
+    }
+}
+
+/// Format a duration as e.g. `1h 02m 03s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(5)), "5s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m 05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+    }
+}
@@ -0,0 +1,312 @@
+//! Per-project launch preparation
+//!
+//! Some servers can't be started with a fixed command line: jdtls needs a
+//! data directory of its own for every project, others a launcher jar from
+//! their install directory, elixir-ls and ltex absolute paths in their
+//! options. A server's `lsp_args`, `env` values, `setup` commands and the
+//! strings in its `initialization_options` and `settings` may use these
+//! placeholders, filled in before each launch:
+//!
+//! - `{workspace}` or `${workspaceRoot}`: the project root the server is
+//!   started for
+//! - `{workspace_hash}`: a short, stable name for that root
+//! - `{data_dir}`: a directory kept for this server and project
+//!   (`<data dir>/workspaces/<server>/<workspace_hash>`), created on use
+//! - `{cache_dir}`: a directory kept for this server across projects
+//!   (`<data dir>/server-caches/<server>`), created on use
+//! - `{server_dir}`: where lsmcp installs the server
+//! - `${userHome}`: the user's home directory
+//! - `${env:NAME}`: lsmcp's environment variable `NAME`
+//!
+//! Other text in braces, and variables that aren't set, are left alone. `setup` commands then run in the
+//! project root, in order; a failing one stops the server from starting.
+//! Remote servers get the placeholders but not `setup`, as the paths are
+//! this machine's.
+
+use crate::config::LspPackage;
+use crate::installer::ServerInstaller;
+use crate::lsp::session::workspace_key;
+use crate::types::LspError;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+/// Values of the launch placeholders for one server and project
+#[derive(Debug, Clone)]
+pub struct LaunchContext {
+    workspace: PathBuf,
+    workspace_hash: String,
+    data_dir: PathBuf,
+    cache_dir: PathBuf,
+    server_dir: PathBuf,
+    home: Option<PathBuf>,
+}
+
+impl LaunchContext {
+    /// Placeholders for `server` started in `project_root`, with lsmcp's
+    /// data under `lsmcp_data_dir`
+    pub fn new(server: &str, project_root: &Path, lsmcp_data_dir: &Path) -> Self {
+        let workspace_hash = workspace_key(project_root);
+        Self {
+            workspace: project_root.to_path_buf(),
+            data_dir: lsmcp_data_dir
+                .join("workspaces")
+                .join(server)
+                .join(&workspace_hash),
+            cache_dir: lsmcp_data_dir.join("server-caches").join(server),
+            server_dir: lsmcp_data_dir.join("servers").join(server),
+            home: dirs::home_dir(),
+            workspace_hash,
+        }
+    }
+
+    /// `text` with its placeholders filled in
+    pub fn expand(&self, text: &str) -> String {
+        if !text.contains('{') {
+            return text.to_string();
+        }
+        let mut text =
+            expand_env(text).replace("${workspaceRoot}", &self.workspace.to_string_lossy());
+        if let Some(home) = &self.home {
+            text = text.replace("${userHome}", &home.to_string_lossy());
+        }
+        text.replace("{workspace_hash}", &self.workspace_hash)
+            .replace("{workspace}", &self.workspace.to_string_lossy())
+            .replace("{data_dir}", &self.data_dir.to_string_lossy())
+            .replace("{cache_dir}", &self.cache_dir.to_string_lossy())
+            .replace("{server_dir}", &self.server_dir.to_string_lossy())
+    }
+
+    /// `value` with the placeholders in its strings filled in
+    pub fn expand_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.expand(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.expand_value(item)),
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|field| self.expand_value(field)),
+            _ => {}
+        }
+    }
+}
+
+/// `text` with each `${env:NAME}` of a set variable replaced by its value
+fn expand_env(text: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${env:") {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + "${env:".len()..start + length];
+        expanded.push_str(&rest[..start]);
+        match std::env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => expanded.push_str(&rest[start..=start + length]),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The strings in `value`, at any depth
+fn json_strings<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+    match value {
+        Value::String(text) => strings.push(text),
+        Value::Array(items) => items.iter().for_each(|item| json_strings(item, strings)),
+        Value::Object(fields) => fields
+            .values()
+            .for_each(|field| json_strings(field, strings)),
+        _ => {}
+    }
+}
+
+/// Every text of `config` that placeholders are filled in: arguments,
+/// environment, setup commands, and the strings of its options and settings
+fn templated_texts(config: &LspPackage) -> Vec<&str> {
+    let bin = &config.bin;
+    let mut texts: Vec<&str> = bin
+        .lsp_args
+        .iter()
+        .chain(bin.env.values())
+        .chain(bin.setup.iter().flatten())
+        .map(String::as_str)
+        .collect();
+    for value in [&config.initialization_options, &config.settings]
+        .into_iter()
+        .flatten()
+    {
+        json_strings(value, &mut texts);
+    }
+    texts
+}
+
+/// Whether any of `config`'s templated texts use a placeholder
+fn uses_placeholders(config: &LspPackage) -> bool {
+    templated_texts(config)
+        .iter()
+        .any(|text| text.contains('{'))
+}
+
+/// Fill in `config`'s placeholders for `project_root` and run its `setup`
+/// commands
+pub async fn prepare(config: &mut LspPackage, project_root: &Path) -> Result<(), LspError> {
+    if uses_placeholders(config) {
+        let context = LaunchContext::new(
+            &config.name,
+            project_root,
+            &ServerInstaller::get_data_dir()?,
+        );
+        let texts = templated_texts(config);
+        let mentions = |placeholder: &str| texts.iter().any(|text| text.contains(placeholder));
+        let dirs: Vec<PathBuf> = [
+            ("{data_dir}", &context.data_dir),
+            ("{cache_dir}", &context.cache_dir),
+        ]
+        .into_iter()
+        .filter(|(placeholder, _)| mentions(placeholder))
+        .map(|(_, dir)| dir.clone())
+        .collect();
+        let bin = &mut config.bin;
+        for arg in &mut bin.lsp_args {
+            *arg = context.expand(arg);
+        }
+        for value in bin.env.values_mut() {
+            *value = context.expand(value);
+        }
+        for arg in bin.setup.iter_mut().flatten() {
+            *arg = context.expand(arg);
+        }
+        for value in [&mut config.initialization_options, &mut config.settings]
+            .into_iter()
+            .flatten()
+        {
+            context.expand_value(value);
+        }
+
+        if config.remote.is_none() {
+            for dir in &dirs {
+                std::fs::create_dir_all(dir).map_err(|e| {
+                    LspError::StartupFailed(
+                        config.name.clone(),
+                        format!("can't create {}: {}", dir.display(), e),
+                    )
+                })?;
+            }
+        }
+    }
+
+    if config.remote.is_some() {
+        if !config.bin.setup.is_empty() {
+            warn!(
+                "Ignoring `setup` for {}: it only runs for servers started on this machine",
+                config.name
+            );
+        }
+        return Ok(());
+    }
+    for command in &config.bin.setup {
+        run_setup(config, command, project_root).await?;
+    }
+    Ok(())
+}
+
+/// Run one `setup` command, failing with its output when it fails
+async fn run_setup(
+    config: &LspPackage,
+    command: &[String],
+    project_root: &Path,
+) -> Result<(), LspError> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+    info!("Running setup for {}: {}", config.name, command.join(" "));
+    let failed = |reason: String| {
+        LspError::StartupFailed(
+            config.name.clone(),
+            format!("setup command `{}` {}", command.join(" "), reason),
+        )
+    };
+
+    let output = Command::new(program)
+        .args(args)
+        .envs(&config.bin.env)
+        .current_dir(project_root)
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(config.bin.spawn_timeout(), output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(failed(format!("could not be run: {}", e))),
+        Err(_) => {
+            return Err(failed(format!(
+                "took longer than {}s",
+                config.bin.spawn_timeout().as_secs()
+            )))
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(failed(format!(
+            "failed ({}): {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    debug!("Setup for {} done", config.name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let context = LaunchContext::new("jdtls", Path::new("/work/app"), Path::new("/data/lsmcp"));
+        let hash = workspace_key(Path::new("/work/app"));
+        assert_eq!(
+            context.expand("-data={data_dir}"),
+            format!("-data=/data/lsmcp/workspaces/jdtls/{}", hash)
+        );
+        assert_eq!(
+            context.expand("{cache_dir}"),
+            "/data/lsmcp/server-caches/jdtls"
+        );
+        assert_eq!(
+            context.expand("{server_dir}/plugins"),
+            "/data/lsmcp/servers/jdtls/plugins"
+        );
+        assert_eq!(
+            context.expand("{workspace}:{workspace_hash}"),
+            format!("/work/app:{}", hash)
+        );
+        assert_eq!(context.expand(r#"{"json": true}"#), r#"{"json": true}"#);
+
+        assert_eq!(context.expand("${workspaceRoot}/lib"), "/work/app/lib");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(
+                context.expand("${userHome}/.dict"),
+                format!("{}/.dict", home.display())
+            );
+        }
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(
+            context.expand("${env:PATH}:${env:LSMCP_TEST_UNSET}"),
+            format!("{}:${{env:LSMCP_TEST_UNSET}}", path)
+        );
+
+        let mut options = serde_json::json!({
+            "dictionary": { "en-US": [":{data_dir}/words.txt"] },
+            "elixirLS": { "projectDir": "${workspaceRoot}", "fetchDeps": false }
+        });
+        context.expand_value(&mut options);
+        assert_eq!(
+            options["dictionary"]["en-US"][0],
+            format!(":/data/lsmcp/workspaces/jdtls/{}/words.txt", hash)
+        );
+        assert_eq!(options["elixirLS"]["projectDir"], "/work/app");
+        assert_eq!(options["elixirLS"]["fetchDeps"], false);
+    }
+}
@@ -1,31 +1,221 @@
 //! LSP manager for lifecycle management
 //!
-//! Manages a pool of LSP clients, one per language, with lazy initialization
+//! Manages a pool of LSP clients, one per (language, server, project root),
+//! with lazy initialization. Languages configured with several servers fan
+//! queries out to all of them and merge the answers (see `lsp::merge`).
 
 use crate::config::ConfigLoader;
-use crate::installer::ServerInstaller;
+use crate::config::{CommandToolConfig, ExtensionRequest, InstallSource, LspPackage, ToolsConfig};
+use crate::installer::{
+    InstallEvent, InstallOptions, InstallSummary, InstalledServer, PruneReport, ServerInstaller,
+    UpdateCheck,
+};
+use crate::lsp::breaker;
+use crate::lsp::cache::{CacheKey, CacheStats, ResponseCache};
+use crate::lsp::capabilities;
+use crate::lsp::documents;
+use crate::lsp::health::ServerHealth;
+use crate::lsp::merge;
+use crate::lsp::overlay;
+use crate::lsp::prepare::{self, PreparedWorkspace};
+use crate::lsp::project::find_project_root;
+use crate::lsp::resources;
+use crate::lsp::runnables::Runnable;
+use crate::lsp::session::{self, SessionState};
+use crate::lsp::shared_daemon;
+use crate::lsp::symbols::{self, SymbolMatch};
+use crate::lsp::throttle;
+use crate::lsp::virtual_docs::{self, EmbeddedDocument};
+use crate::lsp::warm_cache;
 use crate::lsp::LspClient;
+use crate::tools::workspace::{self, ServerAvailability, WorkspaceScan};
 use crate::types::LspError;
+use crate::utils::position::PositionEncoding;
+use crate::utils::sandbox::PathPolicy;
+use crate::utils::uri::PathMap;
 use lsp_types::*;
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, info, warn};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{watch, Mutex};
+use tracing::{debug, info, warn, Instrument};
+
+/// Maximum number of workspace symbol hits resolved via `workspaceSymbol/resolve`
+const MAX_SYMBOL_RESOLVES: usize = 20;
+
+/// Default wait for a new server's initial indexing before querying it
+const DEFAULT_INDEXING_TIMEOUT_SECS: u64 = 30;
+
+/// Default lifetime of cached responses
+const DEFAULT_RESPONSE_CACHE_TTL_SECS: u64 = 30;
+
+/// Default cap on concurrently running language servers
+const DEFAULT_MAX_SERVERS: usize = 8;
+
+/// How often the idle reaper checks for unused servers
+const REAPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the open documents are saved for the next start
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the config watcher checks the config file for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often files matching servers' registered watchers are rescanned
+const WATCHED_FILES_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often servers with a memory limit have their memory use checked
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Clients are keyed by language, server name and the project root they serve
+type ClientKey = (String, String, PathBuf);
+
+/// Most requests that may wait for one server to finish starting
+const MAX_STARTUP_WAITERS: usize = 64;
+
+/// How long requests fail fast after a server failed to start, before
+/// another start is attempted
+const STARTUP_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    /// Daemon session the current tool call belongs to
+    static SESSION: u64;
+    /// Language the current tool call's file is treated as, instead of the
+    /// one its name suggests
+    static FILE_LANGUAGE: String;
+}
+
+/// A running client plus bookkeeping for idle shutdown and LRU eviction
+struct ManagedClient {
+    client: Arc<LspClient>,
+    last_used: Instant,
+}
+
+/// Where a client is in its lifecycle: `Starting` until the server is
+/// installed, spawned and initialized, then `Ready` or `Failed`
+enum ClientState {
+    Starting(Arc<StartingClient>),
+    Ready(ManagedClient),
+    Failed { error: String, since: Instant },
+}
+
+impl ClientState {
+    fn ready(&self) -> Option<&ManagedClient> {
+        match self {
+            Self::Ready(managed) => Some(managed),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a startup, as seen by the requests waiting for it
+type StartupOutcome = Option<Result<Arc<LspClient>, String>>;
+
+/// A client being started and the requests queued behind it
+struct StartingClient {
+    outcome: watch::Receiver<StartupOutcome>,
+    waiters: AtomicUsize,
+}
+
+impl StartingClient {
+    /// Wait for the startup to finish
+    ///
+    /// At most `MAX_STARTUP_WAITERS` requests wait at once; beyond that,
+    /// requests are turned away rather than piling up behind a slow install.
+    async fn wait(&self, server: &str) -> Result<Arc<LspClient>, LspError> {
+        if self.waiters.fetch_add(1, Ordering::SeqCst) >= MAX_STARTUP_WAITERS {
+            self.waiters.fetch_sub(1, Ordering::SeqCst);
+            return Err(LspError::StartupQueueFull(server.to_string()));
+        }
+
+        let mut outcome = self.outcome.clone();
+        let result = outcome
+            .wait_for(Option::is_some)
+            .await
+            .map(|outcome| outcome.clone());
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+
+        match result {
+            Ok(Some(Ok(client))) => Ok(client),
+            Ok(Some(Err(error))) => Err(LspError::StartupFailed(server.to_string(), error)),
+            Ok(None) | Err(_) => Err(LspError::StartupFailed(
+                server.to_string(),
+                "startup was abandoned".to_string(),
+            )),
+        }
+    }
+}
+
+/// Outcome of reloading the user configuration
+#[derive(Debug, Clone)]
+pub struct ConfigReload {
+    /// Config file now in use (`None` when there is none)
+    pub path: Option<PathBuf>,
+    /// Clients stopped because their server definition changed, as
+    /// `(language, server)`; they restart with the new definition on next use
+    pub restarted: Vec<(String, String)>,
+}
+
+/// A server stopped by the watchdog for using more memory than its limit;
+/// it restarts on next use
+#[derive(Debug, Clone)]
+pub struct MemoryLimitExceeded {
+    pub language: String,
+    pub server: String,
+    pub root: PathBuf,
+    /// Resident memory of the server and its child processes
+    pub memory_mb: u64,
+    pub limit_mb: u64,
+}
 
 /// LSP Manager handles lifecycle of all LSP clients
 pub struct LspManager {
     /// Workspace root directory
     workspace_root: PathBuf,
 
-    /// Configuration loader
-    config: Arc<ConfigLoader>,
+    /// Configuration loader (swapped out when the config file changes)
+    config: std::sync::RwLock<Arc<ConfigLoader>>,
 
     /// Server installer for auto-downloading LSPs
     installer: Arc<Mutex<ServerInstaller>>,
 
-    /// Active LSP clients (language -> client)
-    clients: Arc<Mutex<HashMap<String, Arc<LspClient>>>>,
+    /// LSP clients by (language, server, project root), in any state
+    clients: Arc<Mutex<HashMap<ClientKey, ClientState>>>,
+
+    /// Maximum number of concurrently running servers
+    max_servers: AtomicUsize,
+
+    /// Shut down servers unused for this long (disabled when `None`)
+    idle_timeout: Option<Duration>,
+
+    /// Cached hover, definition and symbol responses (disabled when `None`)
+    cache: Option<ResponseCache>,
+
+    /// Files each daemon session has queried, so a session's documents can
+    /// be closed when it ends while the servers stay up for other sessions
+    sessions: std::sync::Mutex<HashMap<u64, HashSet<PathBuf>>>,
+
+    /// Next daemon session id
+    next_session: AtomicU64,
+
+    /// Where the open documents are saved for the next start (`None` when
+    /// `persist_session` is off)
+    session_file: Option<PathBuf>,
+
+    /// The session last saved, so unchanged state isn't rewritten
+    saved_session: std::sync::Mutex<Option<SessionState>>,
+
+    /// Languages and projects found in the workspace, once scanned
+    workspace_scan: std::sync::Mutex<Option<Arc<WorkspaceScan>>>,
+
+    /// Marked whenever servers start or stop or the configuration is
+    /// reloaded (see `watch_servers`)
+    server_changes: Arc<watch::Sender<()>>,
 }
 
 impl LspManager {
@@ -33,159 +223,2090 @@ impl LspManager {
     pub fn new(workspace_root: PathBuf, config: Arc<ConfigLoader>) -> Result<Self, LspError> {
         info!("Creating LSP manager for workspace: {}", workspace_root.display());
 
-        let installer = ServerInstaller::new()?;
+        let settings = config.settings();
+        let installer =
+            ServerInstaller::new()?.with_options(InstallOptions::from_settings(&settings));
+        let max_servers = max_servers(&settings);
+        let idle_timeout = settings
+            .idle_timeout_minutes
+            .filter(|m| *m > 0)
+            .map(|m| Duration::from_secs(m * 60));
+        let cache = match settings
+            .response_cache_ttl_secs
+            .unwrap_or(DEFAULT_RESPONSE_CACHE_TTL_SECS)
+        {
+            0 => None,
+            secs => Some(ResponseCache::new(Duration::from_secs(secs))),
+        };
+        let session_file = match settings.persist_session {
+            Some(false) => None,
+            _ => ServerInstaller::get_data_dir()
+                .ok()
+                .map(|dir| session::session_path(&dir, &workspace_root)),
+        };
 
         Ok(Self {
             workspace_root,
-            config,
+            config: std::sync::RwLock::new(config),
             installer: Arc::new(Mutex::new(installer)),
             clients: Arc::new(Mutex::new(HashMap::new())),
+            max_servers: AtomicUsize::new(max_servers),
+            idle_timeout,
+            cache,
+            sessions: std::sync::Mutex::new(HashMap::new()),
+            next_session: AtomicU64::new(1),
+            session_file,
+            saved_session: std::sync::Mutex::new(None),
+            workspace_scan: std::sync::Mutex::new(None),
+            server_changes: Arc::new(watch::Sender::new(())),
         })
     }
 
-    /// Get or create an LSP client for a language
-    async fn get_or_create_client(&self, language: &str) -> Result<Arc<LspClient>, LspError> {
-        let mut clients = self.clients.lock().await;
+    /// Changes to the running servers or the configuration, which can
+    /// change the tools on offer
+    pub fn watch_servers(&self) -> watch::Receiver<()> {
+        self.server_changes.subscribe()
+    }
+
+    /// Start a daemon session
+    ///
+    /// Sessions share servers, but each keeps track of the documents it
+    /// queried (see `in_session`) so they can be closed when it ends.
+    pub fn open_session(&self) -> u64 {
+        let session = self.next_session.fetch_add(1, Ordering::Relaxed);
+        self.lock_sessions().insert(session, HashSet::new());
+        session
+    }
+
+    /// Run `f` (a tool call) on behalf of `session`
+    pub async fn in_session<F: Future>(&self, session: u64, f: F) -> F::Output {
+        SESSION.scope(session, f).await
+    }
+
+    /// Run `f` (a tool call) treating its file as `language` when given,
+    /// for extensionless files, templates, or a file more than one
+    /// language's servers claim
+    pub async fn with_file_language<F: Future>(&self, language: Option<String>, f: F) -> F::Output {
+        match language {
+            Some(language) => FILE_LANGUAGE.scope(language, f).await,
+            None => f.await,
+        }
+    }
+
+    /// Run `f` (a tool call) with unsaved `content` for its file when given,
+    /// queried in place of the file on disk
+    pub async fn with_file_content<F: Future>(
+        &self,
+        content: Option<(PathBuf, String)>,
+        f: F,
+    ) -> F::Output {
+        overlay::scope(content, f).await
+    }
+
+    /// End a daemon session, closing the documents no other session queried
+    pub async fn close_session(&self, session: u64) {
+        let orphaned: Vec<PathBuf> = {
+            let mut sessions = self.lock_sessions();
+            let Some(files) = sessions.remove(&session) else {
+                return;
+            };
+            files
+                .into_iter()
+                .filter(|file| !sessions.values().any(|other| other.contains(file)))
+                .collect()
+        };
+        if orphaned.is_empty() {
+            return;
+        }
+
+        let clients: Vec<Arc<LspClient>> = self
+            .clients
+            .lock()
+            .await
+            .values()
+            .filter_map(|state| state.ready().map(|m| Arc::clone(&m.client)))
+            .collect();
+        for client in &clients {
+            for file in &orphaned {
+                if client.has_document(file).await {
+                    if let Err(e) = client.did_close(file).await {
+                        debug!("Failed to close {}: {}", file.display(), e);
+                    }
+                }
+            }
+        }
+        debug!(
+            "Session {} ended; closed {} document(s)",
+            session,
+            orphaned.len()
+        );
+    }
+
+    fn lock_sessions(&self) -> std::sync::MutexGuard<'_, HashMap<u64, HashSet<PathBuf>>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record that the current session (if any) queried `file_path`
+    fn note_session_document(&self, file_path: &Path) {
+        if let Ok(session) = SESSION.try_with(|session| *session) {
+            if let Some(files) = self.lock_sessions().get_mut(&session) {
+                files.insert(file_path.to_path_buf());
+            }
+        }
+    }
+
+    /// Restore the previous run's session, then save the session
+    /// periodically in the background
+    ///
+    /// Does nothing when `persist_session` is off. The task holds only a
+    /// weak reference and exits once the manager is dropped.
+    pub fn spawn_session_persistence(self: &Arc<Self>) {
+        if self.session_file.is_none() {
+            return;
+        }
+
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            if let Some(manager) = manager.upgrade() {
+                manager.restore_session().await;
+            }
+            let mut interval = tokio::time::interval(SESSION_SAVE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match manager.upgrade() {
+                    Some(manager) => manager.save_session().await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Start the servers of the saved session and reopen its documents
+    ///
+    /// Documents that no longer exist or have no server are skipped; saved
+    /// diagnostics are shown for unchanged files until the servers publish
+    /// fresh ones. Languages without a restored document get their server
+    /// started at the workspace root.
+    async fn restore_session(&self) {
+        let Some(path) = &self.session_file else {
+            return;
+        };
+        let Some(state) = session::load(path, &self.workspace_root) else {
+            return;
+        };
+        *self.saved_session.lock().unwrap_or_else(|e| e.into_inner()) = Some(state.clone());
+
+        let mut languages: HashSet<String> = HashSet::new();
+        let mut reopened = 0;
+        let documents = state
+            .documents
+            .iter()
+            .filter(|document| document.is_file())
+            .take(session::MAX_RESTORED_DOCUMENTS);
+        for document in documents {
+            let Some(language) = self.file_language(document) else {
+                continue;
+            };
+            let clients = match self.get_clients_for_file(document).await {
+                Ok(clients) => clients,
+                Err(e) => {
+                    debug!("Not reopening {}: {}", document.display(), e);
+                    continue;
+                }
+            };
+            languages.insert(language);
+            for client in clients {
+                if let Some(diagnostics) = state.diagnostics_for(client.server_name(), document) {
+                    client.seed_diagnostics(document, diagnostics.clone()).await;
+                }
+                if let Err(e) = client.sync_document(document).await {
+                    debug!("Failed to reopen {}: {}", document.display(), e);
+                }
+            }
+            reopened += 1;
+        }
+
+        for language in &state.languages {
+            if languages.contains(language) {
+                continue;
+            }
+            if let Err(e) = self.get_clients_for_language(language).await {
+                debug!("Not restarting {} server: {}", language, e);
+            }
+        }
+        info!(
+            "Restored previous session: {} language(s), {} document(s)",
+            state.languages.len(),
+            reopened
+        );
+    }
+
+    /// What the running servers have open, for the next start
+    pub async fn session_state(&self) -> SessionState {
+        let clients: Vec<Arc<LspClient>> = self
+            .clients
+            .lock()
+            .await
+            .values()
+            .filter_map(|state| state.ready().map(|m| Arc::clone(&m.client)))
+            .collect();
+
+        let mut state = SessionState {
+            workspace_root: self.workspace_root.clone(),
+            ..Default::default()
+        };
+        for client in &clients {
+            let language = client.language().to_string();
+            if !state.languages.contains(&language) {
+                state.languages.push(language);
+            }
+            for document in client.open_documents().await {
+                if let Some(diagnostics) = client.published_diagnostics(&document).await {
+                    state
+                        .diagnostics
+                        .entry(client.server_name().to_string())
+                        .or_default()
+                        .insert(document.clone(), diagnostics);
+                }
+                if !state.documents.contains(&document) {
+                    state.documents.push(document);
+                }
+            }
+        }
+        state.languages.sort();
+        state.documents.sort();
+        state
+    }
+
+    /// Save the session for the next start, if it changed
+    ///
+    /// An empty session (nothing running, e.g. after the idle reaper) leaves
+    /// the previous save in place.
+    pub async fn save_session(&self) {
+        let Some(path) = &self.session_file else {
+            return;
+        };
+        let state = self.session_state().await;
+        if state.is_empty() {
+            return;
+        }
+        {
+            let saved = self.saved_session.lock().unwrap_or_else(|e| e.into_inner());
+            let unchanged = saved.as_ref().is_some_and(|saved| {
+                saved.languages == state.languages
+                    && saved.documents == state.documents
+                    && saved.diagnostics == state.diagnostics
+            });
+            if unchanged {
+                return;
+            }
+        }
+
+        let state = SessionState {
+            saved_at: session::now(),
+            ..state
+        };
+        match session::save(path, &state) {
+            Ok(()) => {
+                debug!("Saved session to {}", path.display());
+                *self.saved_session.lock().unwrap_or_else(|e| e.into_inner()) = Some(state);
+            }
+            Err(e) => warn!("Failed to save session to {}: {}", path.display(), e),
+        }
+    }
+
+    /// Start the background task that shuts down idle servers
+    ///
+    /// Does nothing when no idle timeout is configured. The task holds only a
+    /// weak reference and exits once the manager is dropped.
+    pub fn spawn_idle_reaper(self: &Arc<Self>) {
+        let Some(idle_timeout) = self.idle_timeout else {
+            return;
+        };
+
+        info!(
+            "Shutting down language servers idle for more than {}s",
+            idle_timeout.as_secs()
+        );
+
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REAPER_INTERVAL.min(idle_timeout));
+            loop {
+                interval.tick().await;
+                match manager.upgrade() {
+                    Some(manager) => manager.reap_idle_clients(idle_timeout).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Start the background task that tells servers about changes to the
+    /// files they registered watchers for
+    ///
+    /// The task holds only a weak reference and exits once the manager is
+    /// dropped.
+    pub fn spawn_file_watcher(self: &Arc<Self>) {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCHED_FILES_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                let clients = manager.running_clients().await;
+                drop(manager);
+
+                for client in clients {
+                    if let Err(e) = client.poll_watched_files().await {
+                        debug!("Failed to poll watched files: {}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Start the watchdog that restarts servers using more memory than their
+    /// `max_memory_mb`, telling `on_exceeded` about each
+    ///
+    /// The task holds only a weak reference and exits once the manager is
+    /// dropped.
+    pub fn spawn_memory_watchdog<F>(self: &Arc<Self>, on_exceeded: F)
+    where
+        F: Fn(MemoryLimitExceeded) + Send + Sync + 'static,
+    {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(MEMORY_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                for exceeded in manager.enforce_memory_limits().await {
+                    on_exceeded(exceeded);
+                }
+            }
+        });
+    }
+
+    /// Stop the clients whose servers use more memory than their limit
+    async fn enforce_memory_limits(&self) -> Vec<MemoryLimitExceeded> {
+        let mut limited = Vec::new();
+        for (key, state) in self.clients.lock().await.iter() {
+            let Some(managed) = state.ready() else {
+                continue;
+            };
+            let client = &managed.client;
+            if let (Some(limit_mb), Some(pid)) =
+                (client.limits().max_memory_mb, client.local_pid().await)
+            {
+                limited.push((key.clone(), Arc::clone(client), pid, limit_mb));
+            }
+        }
+        if limited.is_empty() {
+            return Vec::new();
+        }
+        let Some(processes) = resources::ProcessTable::sample().await else {
+            return Vec::new();
+        };
+
+        let mut exceeded = Vec::new();
+        for (key, client, pid, limit_mb) in limited {
+            let memory_mb = processes.tree_rss(pid) / (1024 * 1024);
+            if memory_mb <= limit_mb {
+                continue;
+            }
+
+            // Leave a client that was replaced meanwhile alone
+            {
+                let mut clients = self.clients.lock().await;
+                let current = clients.get(&key).and_then(ClientState::ready);
+                if !current.is_some_and(|managed| Arc::ptr_eq(&managed.client, &client)) {
+                    continue;
+                }
+                clients.remove(&key);
+            }
+            self.server_changes.send_replace(());
+
+            let (language, server, root) = key;
+            warn!(
+                "{} for {} at {} uses {} MB, over its {} MB limit; stopping it until next use",
+                server,
+                language,
+                root.display(),
+                memory_mb,
+                limit_mb
+            );
+            client.shutdown().await;
+            exceeded.push(MemoryLimitExceeded {
+                language,
+                server,
+                root,
+                memory_mb,
+                limit_mb,
+            });
+        }
+        exceeded
+    }
+
+    /// Every started client
+    async fn running_clients(&self) -> Vec<Arc<LspClient>> {
+        self.clients
+            .lock()
+            .await
+            .values()
+            .filter_map(|state| state.ready().map(|m| Arc::clone(&m.client)))
+            .collect()
+    }
+
+    /// Names of the running servers that don't handle `method`, sorted
+    pub async fn servers_without(&self, method: &str) -> Vec<String> {
+        let mut servers = Vec::new();
+        for client in self.running_clients().await {
+            if !client.supports(method).await {
+                servers.push(client.server_name().to_string());
+            }
+        }
+        servers.sort();
+        servers.dedup();
+        servers
+    }
+
+    /// Scan the workspace for languages and projects in the background,
+    /// so `lsp_workspace_info` answers at once
+    pub fn spawn_workspace_scan(self: &Arc<Self>) {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = manager.workspace_scan(true).await {
+                warn!("Failed to scan the workspace: {}", e);
+            }
+        });
+    }
+
+    /// The languages and projects in the workspace, from the startup scan
+    /// unless `refresh` asks for a new one
+    pub async fn workspace_scan(&self, refresh: bool) -> Result<Arc<WorkspaceScan>, LspError> {
+        if !refresh {
+            if let Some(scan) = self.lock_workspace_scan().clone() {
+                return Ok(scan);
+            }
+        }
+
+        let (root, config) = (self.workspace_root.clone(), self.config());
+        let scan = tokio::task::spawn_blocking(move || workspace::scan(&root, &config))
+            .await
+            .map_err(|e| LspError::Io(std::io::Error::other(e)))?;
+        info!(
+            "Scanned {} workspace files: {}",
+            scan.files,
+            scan.languages
+                .iter()
+                .map(|l| format!("{} ({})", l.language, l.files))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let scan = Arc::new(scan);
+        *self.lock_workspace_scan() = Some(Arc::clone(&scan));
+        Ok(scan)
+    }
+
+    fn lock_workspace_scan(&self) -> std::sync::MutexGuard<'_, Option<Arc<WorkspaceScan>>> {
+        self.workspace_scan
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether `server` is running, or could start without installing
+    pub async fn server_availability(&self, server: &str) -> ServerAvailability {
+        let running = self
+            .running_clients()
+            .await
+            .iter()
+            .any(|client| client.server_name() == server);
+        if running {
+            return ServerAvailability::Running;
+        }
+        let Ok(package) = self.config().get_lsp_by_name(server) else {
+            return ServerAvailability::NotInstalled;
+        };
+        if !package.needs_local_binary() {
+            return ServerAvailability::Remote;
+        }
+        match self.installer.lock().await.resolve_binary(&package) {
+            Ok(_) => ServerAvailability::Installed,
+            Err(_) => ServerAvailability::NotInstalled,
+        }
+    }
+
+    /// Current configuration
+    fn config(&self) -> Arc<ConfigLoader> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Start the background task that reloads the config when the config file,
+    /// a local registry file or a project's `.lsmcp.toml` changes
+    ///
+    /// `on_reload` is told about every reload attempt. The task holds only a
+    /// weak reference and exits once the manager is dropped.
+    pub fn spawn_config_watcher<F>(self: &Arc<Self>, on_reload: F)
+    where
+        F: Fn(Result<ConfigReload, LspError>) + Send + Sync + 'static,
+    {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let mut last_seen = config_fingerprint();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+
+                let fingerprint = config_fingerprint();
+                if fingerprint != last_seen || manager.config().project_configs_changed() {
+                    last_seen = fingerprint;
+                    on_reload(manager.reload_config().await);
+                }
+            }
+        });
+    }
+
+    /// Refresh a stale Mason registry cache in the background
+    ///
+    /// Only an existing cache is refreshed (the first sync is an explicit
+    /// `lsmcp registry sync`), and never in offline mode or when
+    /// `registry_refresh_hours` is 0. The configuration is reloaded afterwards
+    /// so new entries take effect without a restart.
+    pub fn spawn_registry_refresh<F>(self: &Arc<Self>, on_reload: F)
+    where
+        F: FnOnce(Result<ConfigReload, LspError>) + Send + 'static,
+    {
+        let settings = self.config().settings();
+        let refresh_hours = settings
+            .registry_refresh_hours
+            .unwrap_or(crate::registry::DEFAULT_REFRESH_HOURS);
+        if refresh_hours == 0 || settings.offline == Some(true) {
+            return;
+        }
+
+        let Some(cache) = crate::registry::load_cached() else {
+            return;
+        };
+        if !cache.is_stale(Duration::from_secs(refresh_hours * 3600)) {
+            return;
+        }
+
+        info!(
+            "Mason registry cache {} is older than {}h, refreshing",
+            cache.version, refresh_hours
+        );
+
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let options = InstallOptions::from_settings(&settings);
+        tokio::spawn(async move {
+            match crate::registry::sync(&options).await {
+                Ok(report) if report.version == cache.version => {
+                    debug!("Mason registry {} is up to date", report.version)
+                }
+                Ok(_) => {
+                    if let Some(manager) = manager.upgrade() {
+                        on_reload(manager.reload_config().await);
+                    }
+                }
+                Err(e) => warn!("Failed to refresh the Mason registry: {}", e),
+            }
+        });
+    }
+
+    /// Reload the user configuration
+    ///
+    /// Only clients whose server definition changed, or which their language
+    /// no longer uses, are stopped. Settings read at startup
+    /// (`workspace_root`, `idle_timeout_minutes`) keep their old values; on a
+    /// parse error the previous configuration stays in effect.
+    pub async fn reload_config(&self) -> Result<ConfigReload, LspError> {
+        let old = self.config();
+        let new = Arc::new(old.reload()?);
+        let path = ConfigLoader::user_config_path();
+        info!(
+            "Reloaded configuration from {}",
+            path.as_deref()
+                .map_or("built-in defaults".into(), |p| p.display().to_string())
+        );
+
+        let settings = new.settings();
+        self.max_servers
+            .store(max_servers(&settings), Ordering::Relaxed);
+        self.installer
+            .lock()
+            .await
+            .set_options(InstallOptions::from_settings(&settings));
+
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::clone(&new);
+
+        let max_open_documents = max_open_documents(&settings);
+        for state in self.clients.lock().await.values() {
+            if let ClientState::Ready(managed) = state {
+                managed.client.limit_open_documents(max_open_documents);
+                managed
+                    .client
+                    .limit_concurrent_requests(max_concurrent_requests(
+                        &settings,
+                        managed.client.limits(),
+                    ));
+                let (failures, cooldown) = circuit_breaker(&settings);
+                managed.client.configure_breaker(failures, cooldown);
+            }
+        }
+
+        // Clients still starting finish with the definition they started with
+        let stale: Vec<(ClientKey, ManagedClient)> = {
+            let mut clients = self.clients.lock().await;
+            let keys: Vec<ClientKey> = clients
+                .iter()
+                .filter(|(_, state)| !matches!(state, ClientState::Starting(_)))
+                .map(|(key, _)| key)
+                .filter(|(language, server, root)| {
+                    is_stale(&old, &new, language, server, root, &self.workspace_root)
+                })
+                .cloned()
+                .collect();
+            keys.into_iter()
+                .filter_map(|key| match clients.remove(&key) {
+                    Some(ClientState::Ready(managed)) => Some((key, managed)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut restarted = Vec::new();
+        for ((language, server, root), managed) in stale {
+            info!(
+                "{} definition changed, stopping its client for {} at {}",
+                server,
+                language,
+                root.display()
+            );
+            managed.client.shutdown().await;
+            if !restarted.contains(&(language.clone(), server.clone())) {
+                restarted.push((language, server));
+            }
+        }
+
+        self.server_changes.send_replace(());
+        Ok(ConfigReload { path, restarted })
+    }
+
+    /// Shut down all clients that have been idle for longer than `idle_timeout`
+    ///
+    /// A client still answering a request counts as used now, so a request
+    /// that outlasts `idle_timeout` doesn't lose its server mid-flight.
+    async fn reap_idle_clients(&self, idle_timeout: Duration) {
+        let idle: Vec<(ClientKey, Arc<LspClient>)> = {
+            let mut clients = self.clients.lock().await;
+            let mut keys = Vec::new();
+            for (key, state) in clients.iter_mut() {
+                let ClientState::Ready(managed) = state else {
+                    continue;
+                };
+                if managed.client.is_busy() {
+                    managed.last_used = Instant::now();
+                } else if managed.last_used.elapsed() >= idle_timeout {
+                    keys.push(key.clone());
+                }
+            }
+
+            keys.into_iter()
+                .filter_map(|key| match clients.remove(&key) {
+                    Some(ClientState::Ready(managed)) => Some((key, managed.client)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        if !idle.is_empty() {
+            self.server_changes.send_replace(());
+        }
+        for ((language, server, root), client) in idle {
+            info!(
+                "Shutting down idle {} client for {} at {}",
+                server,
+                language,
+                root.display()
+            );
+            client.shutdown().await;
+        }
+    }
+
+    /// Get or create a client running `lsp_config` for a language rooted at
+    /// `project_root`
+    ///
+    /// Starting a server (installing it if needed, spawning and initializing
+    /// it) runs in a background task while the client is `Starting`;
+    /// concurrent requests for the same client queue behind it instead of
+    /// racing a half-initialized server. A failed start is remembered for
+    /// `STARTUP_RETRY_AFTER` so requests fail fast with its error.
+    async fn get_or_create_client(
+        &self,
+        language: &str,
+        lsp_config: LspPackage,
+        project_root: &Path,
+    ) -> Result<Arc<LspClient>, LspError> {
+        let key = (
+            language.to_string(),
+            lsp_config.name.clone(),
+            project_root.to_path_buf(),
+        );
+
+        let startup = {
+            let mut clients = self.clients.lock().await;
+
+            match clients.get_mut(&key) {
+                Some(ClientState::Ready(managed)) => {
+                    if managed.client.has_exited().await {
+                        warn!(
+                            "{} for {} at {} has exited, restarting it",
+                            lsp_config.name,
+                            language,
+                            project_root.display()
+                        );
+                        clients.remove(&key);
+                    } else {
+                        debug!(
+                            "Reusing existing {} client for {} at {}",
+                            lsp_config.name,
+                            language,
+                            project_root.display()
+                        );
+                        managed.last_used = Instant::now();
+                        return Ok(Arc::clone(&managed.client));
+                    }
+                }
+                Some(ClientState::Starting(starting)) => {
+                    let starting = Arc::clone(starting);
+                    drop(clients);
+                    debug!("Waiting for {} to finish starting", lsp_config.name);
+                    return starting.wait(&lsp_config.name).await;
+                }
+                Some(ClientState::Failed { error, since }) => {
+                    if since.elapsed() < STARTUP_RETRY_AFTER {
+                        return Err(LspError::StartupFailed(
+                            lsp_config.name.clone(),
+                            error.clone(),
+                        ));
+                    }
+                    clients.remove(&key);
+                }
+                None => {}
+            }
+
+            self.evict_for_new_client(&mut clients);
+
+            let (outcome_tx, outcome_rx) = watch::channel(None);
+            clients.insert(
+                key.clone(),
+                ClientState::Starting(Arc::new(StartingClient {
+                    outcome: outcome_rx,
+                    waiters: AtomicUsize::new(0),
+                })),
+            );
+
+            // The task owns the transition out of `Starting`, so it happens
+            // even if this request is cancelled
+            let installer = Arc::clone(&self.installer);
+            let clients = Arc::clone(&self.clients);
+            let path_map = self.config().path_map();
+            let auto_install = self.settings().auto_install != Some(false);
+            let max_open_documents = max_open_documents(&self.settings());
+            let max_concurrent_requests =
+                max_concurrent_requests(&self.settings(), &lsp_config.bin.limits);
+            let (breaker_failures, breaker_cooldown) = circuit_breaker(&self.settings());
+            let mut lsp_config = lsp_config;
+            if self.settings().warm_caches != Some(false) {
+                warm_cache::apply(&mut lsp_config);
+            }
+            if self.settings().share_gopls_daemon != Some(false) {
+                shared_daemon::apply(&mut lsp_config);
+            }
+            let server_changes = Arc::clone(&self.server_changes);
+            // Startup belongs to the request that needed the server
+            let start = async move {
+                let (language, server, project_root) = key.clone();
+                let result = Self::start_client(
+                    installer,
+                    language,
+                    lsp_config,
+                    project_root,
+                    path_map,
+                    auto_install,
+                )
+                .await;
 
-        // Check if client already exists
-        if let Some(client) = clients.get(language) {
-            debug!("Reusing existing LSP client for {}", language);
-            return Ok(Arc::clone(client));
+                if let Ok(client) = &result {
+                    client.limit_open_documents(max_open_documents);
+                    client.limit_concurrent_requests(max_concurrent_requests);
+                    client.configure_breaker(breaker_failures, breaker_cooldown);
+                }
+                let (state, outcome) = match &result {
+                    Ok(client) => (
+                        ClientState::Ready(ManagedClient {
+                            client: Arc::clone(client),
+                            last_used: Instant::now(),
+                        }),
+                        Ok(Arc::clone(client)),
+                    ),
+                    Err(e) => {
+                        warn!("{} failed to start: {}", server, e);
+                        // Waiters get it back as `StartupFailed`
+                        let reason = match e {
+                            LspError::StartupFailed(_, reason) => reason.clone(),
+                            e => e.to_string(),
+                        };
+                        (
+                            ClientState::Failed {
+                                error: reason.clone(),
+                                since: Instant::now(),
+                            },
+                            Err(reason),
+                        )
+                    }
+                };
+                clients.lock().await.insert(key, state);
+                let _ = outcome_tx.send(Some(outcome));
+                server_changes.send_replace(());
+                result
+            };
+            tokio::spawn(crate::trace::inherit(start).in_current_span())
+        };
+
+        startup.await.unwrap_or_else(|e| {
+            Err(LspError::ServerCrashed(format!(
+                "startup task failed: {}",
+                e
+            )))
+        })
+    }
+
+    /// Make room for one more client by evicting least recently used ones
+    ///
+    /// Clients that are starting or answering requests count towards the
+    /// limit but aren't evicted.
+    fn evict_for_new_client(&self, clients: &mut HashMap<ClientKey, ClientState>) {
+        let max_servers = self.max_servers.load(Ordering::Relaxed);
+        while clients
+            .values()
+            .filter(|state| !matches!(state, ClientState::Failed { .. }))
+            .count()
+            >= max_servers
+        {
+            let Some(lru) = clients
+                .iter()
+                .filter_map(|(key, state)| state.ready().map(|managed| (key, managed)))
+                .filter(|(_, managed)| !managed.client.is_busy())
+                .min_by_key(|(_, managed)| managed.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(ClientState::Ready(evicted)) = clients.remove(&lru) {
+                info!(
+                    "Server limit ({}) reached, evicting {} client for {} at {}",
+                    max_servers,
+                    lru.1,
+                    lru.0,
+                    lru.2.display()
+                );
+                tokio::spawn(async move { evicted.client.shutdown().await });
+                self.server_changes.send_replace(());
+            }
+        }
+    }
+
+    /// Install (if needed), spawn and initialize a server
+    ///
+    /// Servers not found anywhere are installed unless `auto_install` is
+    /// off or they can't be, in which case the error lists where lsmcp
+    /// looked. npm servers run through npx or bunx instead when
+    /// `npm_runner` asks for it or installing fails.
+    async fn start_client(
+        installer: Arc<Mutex<ServerInstaller>>,
+        language: String,
+        mut lsp_config: LspPackage,
+        project_root: PathBuf,
+        path_map: Option<PathMap>,
+        auto_install: bool,
+    ) -> Result<Arc<LspClient>, LspError> {
+        info!(
+            "Initializing new LSP client for {} at {}: {}",
+            language,
+            project_root.display(),
+            lsp_config.name
+        );
+        // Remote servers and TCP servers run by someone else have no local
+        // binary to find
+        if !lsp_config.needs_local_binary() {
+            let client = LspClient::spawn(language, lsp_config, project_root, path_map).await?;
+            return Ok(Arc::new(client));
+        }
+
+        // Try to find or install the LSP binary, or run an npm server
+        // through npx/bunx
+        {
+            let mut installer = installer.lock().await;
+            let installable = !matches!(
+                lsp_config.source,
+                InstallSource::External { .. } | InstallSource::System { .. }
+            );
+            match installer.resolve_binary(&lsp_config) {
+                Ok(path) => {
+                    info!(
+                        "Found existing LSP binary for {}: {}",
+                        lsp_config.name,
+                        path.display()
+                    );
+                    lsp_config.bin.primary = path.to_string_lossy().to_string();
+                }
+                Err(not_found) if !(auto_install && installable) => return Err(not_found),
+                Err(not_found) if installer.prefers_npm_runner(&lsp_config) => {
+                    let Some(bin) = installer.npm_runner_config(&lsp_config) else {
+                        return Err(not_found);
+                    };
+                    info!(
+                        "LSP server {} not found, running it with {}",
+                        lsp_config.name, bin.primary
+                    );
+                    lsp_config.bin = bin;
+                }
+                Err(_) => {
+                    info!(
+                        "LSP server {} not found, attempting auto-install...",
+                        lsp_config.name
+                    );
+                    match installer.install_lsp(&lsp_config).await {
+                        Ok(path) => {
+                            info!(
+                                "Successfully auto-installed {} to {}",
+                                lsp_config.name,
+                                path.display()
+                            );
+                            lsp_config.bin.primary = path.to_string_lossy().to_string();
+                        }
+                        Err(e) => match installer.npm_runner_config(&lsp_config) {
+                            Some(bin) => {
+                                warn!(
+                                    "Failed to auto-install {} ({}), running it with {}",
+                                    lsp_config.name, e, bin.primary
+                                );
+                                lsp_config.bin = bin;
+                            }
+                            None => {
+                                warn!("Failed to auto-install {}: {}", lsp_config.name, e);
+                                return Err(match e {
+                                    LspError::Offline(_) => e,
+                                    e => installer
+                                        .missing_server(&lsp_config)
+                                        .install_failed(e)
+                                        .into_error(),
+                                });
+                            }
+                        },
+                    }
+                }
+            }
+        }
+
+        let client = LspClient::spawn(language, lsp_config, project_root, path_map).await?;
+        Ok(Arc::new(client))
+    }
+
+    /// Get the clients for a file (by file type and nearest project root),
+    /// highest priority first
+    async fn get_clients_for_file(
+        &self,
+        file_path: &Path,
+    ) -> Result<Vec<Arc<LspClient>>, LspError> {
+        let project_root = find_project_root(file_path, &self.workspace_root);
+        let language = match virtual_docs::source_of(file_path) {
+            Some((_, language)) => Some(language.to_string()),
+            None => file_language_override(),
+        };
+        let (language, packages) = self.config().get_lsps_for_project(
+            file_path,
+            language.as_deref(),
+            &project_root,
+            &self.workspace_root,
+        )?;
+        self.note_session_document(file_path);
+
+        let mut results = Vec::with_capacity(packages.len());
+        for package in packages {
+            results.push(
+                self.get_or_create_client(&language, package, &project_root)
+                    .await,
+            );
+        }
+        successes(results)
+    }
+
+    /// Get the clients for a language without a specific file, highest
+    /// priority first
+    ///
+    /// Reuses any running client for each server, otherwise starts one at
+    /// the workspace root.
+    async fn get_clients_for_language(
+        &self,
+        language: &str,
+    ) -> Result<Vec<Arc<LspClient>>, LspError> {
+        let mut results = Vec::new();
+
+        for package in self.config().get_lsps_for_language(language)? {
+            let running = {
+                let mut clients = self.clients.lock().await;
+                let mut running = None;
+                for ((lang, server, _), state) in clients.iter_mut() {
+                    let ClientState::Ready(managed) = state else {
+                        continue;
+                    };
+                    if lang == language
+                        && server == &package.name
+                        && !managed.client.has_exited().await
+                    {
+                        managed.last_used = Instant::now();
+                        running = Some(Arc::clone(&managed.client));
+                        break;
+                    }
+                }
+                running
+            };
+
+            let result = match running {
+                Some(client) => Ok(client),
+                None => {
+                    self.get_or_create_client(language, package, &self.workspace_root)
+                        .await
+                }
+            };
+            results.push(result);
+        }
+
+        successes(results)
+    }
+
+    /// `get_clients_for_file`, after waiting for initial indexing
+    async fn ready_clients_for_file(
+        &self,
+        file_path: &Path,
+    ) -> Result<Vec<Arc<LspClient>>, LspError> {
+        let clients = self.get_clients_for_file(file_path).await?;
+        self.await_indexing(&clients).await;
+        Ok(clients)
+    }
+
+    /// `get_clients_for_language`, after waiting for initial indexing
+    async fn ready_clients_for_language(
+        &self,
+        language: &str,
+    ) -> Result<Vec<Arc<LspClient>>, LspError> {
+        let clients = self.get_clients_for_language(language).await?;
+        self.await_indexing(&clients).await;
+        Ok(clients)
+    }
+
+    /// Wait (up to `indexing_timeout_secs`) for clients still doing their
+    /// initial indexing, so queries don't come back empty while a server is
+    /// loading the project. Servers that are still busy are queried anyway.
+    async fn await_indexing(&self, clients: &[Arc<LspClient>]) {
+        let secs = self
+            .settings()
+            .indexing_timeout_secs
+            .unwrap_or(DEFAULT_INDEXING_TIMEOUT_SECS);
+        if secs == 0 {
+            return;
+        }
+
+        let max_wait = Duration::from_secs(secs);
+        let ready = futures::future::join_all(
+            clients
+                .iter()
+                .map(|c| c.wait_for_initial_indexing(max_wait)),
+        )
+        .await;
+        for (client, ready) in clients.iter().zip(ready) {
+            if !ready {
+                warn!(
+                    "{} is still indexing after {}s; results may be incomplete",
+                    client.server_name(),
+                    secs
+                );
+            }
+        }
+    }
+
+    /// Answer a query from the response cache, or run `fetch` and cache it
+    ///
+    /// The clients' documents are synced first, so the stamp reflects the
+    /// file as it is on disk now. Empty answers aren't cached, as a server
+    /// may still be loading the project.
+    async fn cached<T, F, Fut>(
+        &self,
+        key: CacheKey,
+        clients: &[Arc<LspClient>],
+        file_path: &Path,
+        fetch: F,
+    ) -> Result<T, LspError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, LspError>>,
+    {
+        let Some(cache) = &self.cache else {
+            return fetch().await;
+        };
+
+        for client in clients {
+            if let Err(e) = client.sync_document(file_path).await {
+                debug!("Not caching {}: {}", file_path.display(), e);
+                return fetch().await;
+            }
+        }
+        let stamp = content_stamp(clients);
+        if let Some(value) = cache.get(&key, &stamp) {
+            if let Ok(result) = serde_json::from_value(value) {
+                return Ok(result);
+            }
+        }
+
+        let result = fetch().await?;
+        // Skip answers computed while a document changed under them
+        if content_stamp(clients) == stamp {
+            match serde_json::to_value(&result) {
+                Ok(serde_json::Value::Null) | Err(_) => {}
+                Ok(value) => cache.insert(key, stamp, value),
+            }
         }
+        Ok(result)
+    }
 
-        // Get LSP configuration for this language
-        let mut lsp_config = self.config.get_lsp_for_language(language)?;
+    /// Response cache counters and TTL (`None` when caching is disabled)
+    pub fn cache_stats(&self) -> Option<(CacheStats, Duration)> {
+        self.cache
+            .as_ref()
+            .map(|cache| (cache.stats(), cache.ttl()))
+    }
 
-        info!("Initializing new LSP client for {}: {}", language, lsp_config.name);
+    /// Start servers for the given languages and wait for initial indexing
+    ///
+    /// Returns, per language, whether indexing finished within `max_wait`.
+    /// Servers are warmed concurrently.
+    pub async fn warmup(
+        &self,
+        languages: &[String],
+        max_wait: Duration,
+    ) -> Vec<(String, Result<bool, LspError>)> {
+        let warmups = languages.iter().map(|language| async move {
+            let result = match self.get_clients_for_language(language).await {
+                Ok(clients) => {
+                    let indexed = futures::future::join_all(
+                        clients.iter().map(|c| c.wait_for_indexing(max_wait)),
+                    )
+                    .await;
+                    Ok(indexed.into_iter().all(|done| done))
+                }
+                Err(e) => Err(e),
+            };
+            (language.clone(), result)
+        });
 
-        // Try to find or install the LSP binary
-        let binary_path = {
-            let mut installer = self.installer.lock().await;
+        futures::future::join_all(warmups).await
+    }
 
-            // First, try to find existing installation
-            if let Some(path) = installer.find_lsp_binary(&lsp_config.name, &lsp_config.bin.primary) {
-                info!("Found existing LSP binary for {}: {}", lsp_config.name, path.display());
-                path
-            } else {
-                // Auto-install if not found
-                info!("LSP server {} not found, attempting auto-install...", lsp_config.name);
-                match installer.install_lsp(&lsp_config).await {
-                    Ok(path) => {
-                        info!("Successfully auto-installed {} to {}", lsp_config.name, path.display());
-                        path
-                    }
-                    Err(e) => {
-                        warn!("Failed to auto-install {}: {}", lsp_config.name, e);
-                        return Err(e);
+    /// Open the workspace files matching `patterns` (of `language`, when
+    /// given) on their servers, so servers that only diagnose open files
+    /// report on them; see `lsp::prepare`
+    pub async fn prepare_workspace(
+        &self,
+        patterns: &[String],
+        language: Option<&str>,
+        limit: usize,
+    ) -> PreparedWorkspace {
+        let settings = self.settings();
+        let (files, matched) = prepare::files_to_prepare(
+            &self.workspace_root,
+            patterns,
+            limit,
+            max_open_documents(&settings),
+        );
+        let mut prepared = PreparedWorkspace {
+            matched,
+            limit: files.len(),
+            ..PreparedWorkspace::default()
+        };
+
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|file| self.check_path(file).is_ok())
+            .filter(|file| match self.file_language(file) {
+                Some(found) => language.is_none_or(|language| found == language),
+                None => {
+                    prepared.unsupported += 1;
+                    false
+                }
+            })
+            .collect();
+        for batch in files.chunks(prepare::PREPARE_BATCH) {
+            let opened = futures::future::join_all(batch.iter().map(|file| async move {
+                let clients = self.get_clients_for_file(file).await?;
+                let mut servers = Vec::new();
+                for client in clients {
+                    client.sync_document(file).await?;
+                    servers.push(client.server_name().to_string());
+                }
+                Ok::<_, LspError>(servers)
+            }))
+            .await;
+            for (file, result) in batch.iter().zip(opened) {
+                match result {
+                    Ok(servers) => {
+                        prepared.files += 1;
+                        for server in servers {
+                            *prepared.opened.entry(server).or_default() += 1;
+                        }
                     }
+                    Err(e) => prepared.failed.push((file.clone(), e.to_string())),
                 }
             }
-        };
+        }
+        prepared
+    }
 
-        // Update the config with the resolved binary path
-        lsp_config.bin.primary = binary_path.to_string_lossy().to_string();
+    /// Languages listed in the `preload` setting
+    pub fn preload_languages(&self) -> Vec<String> {
+        self.config().settings().preload.unwrap_or_default()
+    }
 
-        // Spawn new LSP client
-        let client = LspClient::spawn(
-            language.to_string(),
-            lsp_config,
-            self.workspace_root.clone(),
-        ).await?;
+    /// Current global settings
+    pub fn settings(&self) -> crate::config::Settings {
+        self.config().settings()
+    }
+
+    /// Whether tools may use `path` (see `restrict_paths`)
+    pub fn check_path(&self, path: &Path) -> Result<(), LspError> {
+        match PathPolicy::new(&self.workspace_root, &self.settings()) {
+            Some(policy) => policy.check(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Root of the workspace being served
+    pub fn workspace_root(&self) -> &Path {
+        &self.workspace_root
+    }
 
-        let client = Arc::new(client);
-        clients.insert(language.to_string(), Arc::clone(&client));
+    /// The language of `file_path`, if some configured language server
+    /// handles it
+    pub fn file_language(&self, file_path: &Path) -> Option<String> {
+        if let Some(language) = file_language_override() {
+            return Some(language);
+        }
+        match self.config().get_lsps_for_file(file_path) {
+            Ok((language, packages)) if !packages.is_empty() => Some(language),
+            _ => None,
+        }
+    }
 
-        Ok(client)
+    /// Whether some configured language server handles `language`
+    pub fn language_supported(&self, language: &str) -> bool {
+        self.config()
+            .get_lsps_for_language(language)
+            .is_ok_and(|packages| !packages.is_empty())
     }
 
-    /// Get LSP client for a file (by extension)
-    async fn get_client_for_file(&self, file_path: &Path) -> Result<Arc<LspClient>, LspError> {
-        // Detect language from file extension
-        let lsp_config = self.config.get_lsp_for_file(file_path)?;
-        let language = &lsp_config.languages[0];
+    /// The embedded document at a position of a mixed file (a `<script>`
+    /// block, a fenced code block), when its language has servers and the
+    /// caller didn't pick the file's language
+    async fn embedded_document_at(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+    ) -> Option<EmbeddedDocument> {
+        if file_language_override().is_some() || !virtual_docs::is_mixed(file_path) {
+            return None;
+        }
+        let text = overlay::read_file(file_path).await.ok()?;
+        let document = EmbeddedDocument::at(file_path, &text, line, character)?;
+        self.config()
+            .get_lsps_for_language(document.language())
+            .ok()?;
+        Some(document)
+    }
 
-        self.get_or_create_client(language).await
+    /// Run `query` against the embedded document at a position of a mixed
+    /// file, falling back to the file's own servers when that finds nothing
+    async fn route_embedded<T, F, Fut>(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        query: F,
+    ) -> Result<Option<T>, LspError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Fn(PathBuf) -> Fut,
+        Fut: Future<Output = Result<Option<T>, LspError>>,
+    {
+        let Some(document) = self.embedded_document_at(file_path, line, character).await else {
+            return query(file_path.to_path_buf()).await;
+        };
+        debug!(
+            "Routing {}:{} to its embedded {}",
+            file_path.display(),
+            line,
+            document.language()
+        );
+        let embedded_error = match query(document.path().to_path_buf()).await {
+            Ok(Some(result)) => return Ok(Some(document.restore(result))),
+            Ok(None) => None,
+            Err(e) => {
+                debug!("Embedded {} query failed: {}", document.language(), e);
+                Some(e)
+            }
+        };
+        match query(file_path.to_path_buf()).await {
+            Err(_) if embedded_error.is_none() => Ok(None),
+            Err(e) => Err(embedded_error.unwrap_or(e)),
+            result => result,
+        }
     }
 
-    /// Go to definition
+    /// Go to definition (first server with a result wins)
+    ///
+    /// Positions, here and in the other queries, count columns in `encoding`.
+    /// Positions inside embedded code of a mixed file go to that code's
+    /// language's servers first.
     pub async fn goto_definition(
         &self,
         file_path: &Path,
         line: u32,
         character: u32,
+        encoding: PositionEncoding,
+    ) -> Result<Option<GotoDefinitionResponse>, LspError> {
+        self.route_embedded(file_path, line, character, |path| async move {
+            self.definition_in(&path, line, character, encoding).await
+        })
+        .await
+    }
+
+    async fn definition_in(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        encoding: PositionEncoding,
     ) -> Result<Option<GotoDefinitionResponse>, LspError> {
-        let client = self.get_client_for_file(file_path).await?;
-        client.goto_definition(file_path, line, character).await
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let clients = supporting(clients, "textDocument/definition").await?;
+        let key = CacheKey::new(
+            "textDocument/definition",
+            file_path,
+            Some(Position::new(line, character)),
+            encoding,
+        );
+        self.cached(key, &clients, file_path, || async {
+            let responses = successes(
+                futures::future::join_all(
+                    clients
+                        .iter()
+                        .map(|c| c.goto_definition(file_path, line, character, encoding)),
+                )
+                .await,
+            )?;
+            Ok(merge::first_non_empty(
+                responses,
+                merge::definition_is_empty,
+            ))
+        })
+        .await
     }
 
-    /// Find references
+    /// Find references (union across servers)
     pub async fn find_references(
         &self,
         file_path: &Path,
         line: u32,
         character: u32,
         include_declaration: bool,
+        encoding: PositionEncoding,
+    ) -> Result<Option<Vec<Location>>, LspError> {
+        self.route_embedded(file_path, line, character, |path| async move {
+            self.references_in(&path, line, character, include_declaration, encoding)
+                .await
+        })
+        .await
+    }
+
+    async fn references_in(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        include_declaration: bool,
+        encoding: PositionEncoding,
     ) -> Result<Option<Vec<Location>>, LspError> {
-        let client = self.get_client_for_file(file_path).await?;
-        client.find_references(file_path, line, character, include_declaration).await
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let clients = supporting(clients, "textDocument/references").await?;
+        let responses = successes(
+            futures::future::join_all(clients.iter().map(|c| {
+                c.find_references(file_path, line, character, include_declaration, encoding)
+            }))
+            .await,
+        )?;
+
+        let lists: Vec<Vec<Location>> = responses.into_iter().flatten().collect();
+        if lists.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(merge::union(lists)))
     }
 
-    /// Get hover information
+    /// Get hover information (first server with a result wins)
     pub async fn hover(
         &self,
         file_path: &Path,
         line: u32,
         character: u32,
+        encoding: PositionEncoding,
+    ) -> Result<Option<Hover>, LspError> {
+        self.route_embedded(file_path, line, character, |path| async move {
+            self.hover_in(&path, line, character, encoding).await
+        })
+        .await
+    }
+
+    async fn hover_in(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        encoding: PositionEncoding,
     ) -> Result<Option<Hover>, LspError> {
-        let client = self.get_client_for_file(file_path).await?;
-        client.hover(file_path, line, character).await
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let clients = supporting(clients, "textDocument/hover").await?;
+        let key = CacheKey::new(
+            "textDocument/hover",
+            file_path,
+            Some(Position::new(line, character)),
+            encoding,
+        );
+        self.cached(key, &clients, file_path, || async {
+            let responses = successes(
+                futures::future::join_all(
+                    clients
+                        .iter()
+                        .map(|c| c.hover(file_path, line, character, encoding)),
+                )
+                .await,
+            )?;
+            Ok(merge::first_non_empty(responses, merge::hover_is_empty))
+        })
+        .await
+    }
+
+    /// The edit renaming the symbol at a position, from the highest
+    /// priority server that can rename it
+    pub async fn rename(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        new_name: &str,
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceEdit>, LspError> {
+        self.route_embedded(file_path, line, character, |path| async move {
+            self.rename_in(&path, line, character, new_name, encoding)
+                .await
+        })
+        .await
+    }
+
+    async fn rename_in(
+        &self,
+        file_path: &Path,
+        line: u32,
+        character: u32,
+        new_name: &str,
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceEdit>, LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        for client in supporting(clients, "textDocument/rename").await? {
+            if let Some(edit) = client
+                .rename(file_path, line, character, new_name, encoding)
+                .await?
+            {
+                return Ok(Some(edit));
+            }
+        }
+        Ok(None)
     }
 
-    /// Get document symbols
+    /// Get document symbols (union across servers)
     pub async fn document_symbols(
         &self,
         file_path: &Path,
+        encoding: PositionEncoding,
     ) -> Result<Option<DocumentSymbolResponse>, LspError> {
-        let client = self.get_client_for_file(file_path).await?;
-        client.document_symbols(file_path).await
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let clients = supporting(clients, "textDocument/documentSymbol").await?;
+        let key = CacheKey::new("textDocument/documentSymbol", file_path, None, encoding);
+        self.cached(key, &clients, file_path, || async {
+            let responses = successes(
+                futures::future::join_all(
+                    clients
+                        .iter()
+                        .map(|c| c.document_symbols(file_path, encoding)),
+                )
+                .await,
+            )?;
+
+            let uri = Url::from_file_path(file_path)
+                .map_err(|_| LspError::InvalidPath(file_path.to_path_buf()))?;
+            Ok(merge::merge_document_symbols(
+                responses.into_iter().flatten().collect(),
+                &uri,
+            ))
+        })
+        .await
     }
 
-    /// Get diagnostics for a file
+    /// Get diagnostics for a file (union across servers)
+    ///
+    /// A mixed file also gets its embedded code's diagnostics, from each
+    /// embedded language's servers.
     pub async fn get_diagnostics(
         &self,
         file_path: &Path,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Diagnostic>, LspError> {
+        let documents = self.embedded_documents(file_path).await;
+        if documents.is_empty() {
+            return self.diagnostics_in(file_path, encoding).await;
+        }
+
+        let mut diagnostics = Vec::new();
+        let mut error = None;
+        match self.diagnostics_in(file_path, encoding).await {
+            Ok(found) => diagnostics.push(found),
+            Err(e) => error = Some(e),
+        }
+        for document in documents {
+            match self.diagnostics_in(document.path(), encoding).await {
+                Ok(found) => diagnostics.push(document.restore(found)),
+                Err(e) => {
+                    debug!("Embedded {} diagnostics failed: {}", document.language(), e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if diagnostics.is_empty() => Err(e),
+            _ => Ok(merge::union(diagnostics)),
+        }
+    }
+
+    /// The embedded documents of a mixed file whose languages have servers
+    async fn embedded_documents(&self, file_path: &Path) -> Vec<EmbeddedDocument> {
+        if file_language_override().is_some() || !virtual_docs::is_mixed(file_path) {
+            return Vec::new();
+        }
+        let Ok(text) = overlay::read_file(file_path).await else {
+            return Vec::new();
+        };
+        EmbeddedDocument::all(file_path, &text)
+            .into_iter()
+            .filter(|document| {
+                self.config()
+                    .get_lsps_for_language(document.language())
+                    .is_ok()
+            })
+            .collect()
+    }
+
+    async fn diagnostics_in(
+        &self,
+        file_path: &Path,
+        encoding: PositionEncoding,
     ) -> Result<Vec<Diagnostic>, LspError> {
-        let client = self.get_client_for_file(file_path).await?;
-        client.get_diagnostics(file_path).await
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let results = futures::future::join_all(clients.iter().map(|c| async move {
+            c.get_diagnostics(file_path, encoding)
+                .await
+                .map(|diagnostics| (c.server_name().to_string(), diagnostics))
+        }))
+        .await;
+
+        let mut lists = successes(results)?;
+        if lists.len() == 1 {
+            return Ok(lists.remove(0).1);
+        }
+        Ok(merge::merge_diagnostics(lists))
+    }
+
+    /// Diagnostics for a file once its servers published them for the
+    /// content they were sent, waiting up to `wait`; for `lsmcp check`
+    pub async fn published_diagnostics(
+        &self,
+        file_path: &Path,
+        wait: Duration,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Diagnostic>, LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        fresh_diagnostics(&clients, file_path, wait, encoding).await
+    }
+
+    /// Diagnostics for a file as it is, and with `edited` as its content
+    ///
+    /// Each waits up to `wait` for servers to publish diagnostics for the
+    /// content they were sent. Servers see the edited content only for the
+    /// check: the file's own content is sent back afterwards, whether or not
+    /// the check succeeded.
+    pub async fn diagnostics_with_edit(
+        &self,
+        file_path: &Path,
+        edited: String,
+        wait: Duration,
+        encoding: PositionEncoding,
+    ) -> Result<(Vec<Diagnostic>, Vec<Diagnostic>), LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let before = fresh_diagnostics(&clients, file_path, wait, encoding).await?;
+        let after = overlay::scope(
+            Some((file_path.to_path_buf(), edited)),
+            fresh_diagnostics(&clients, file_path, wait, encoding),
+        )
+        .await;
+        // Give the servers the file's own content back; nobody is waiting
+        // for its diagnostics
+        for client in &clients {
+            if let Err(e) = client.sync_document(file_path).await {
+                warn!(
+                    "Couldn't restore {} on {} after checking an edit: {}",
+                    file_path.display(),
+                    client.server_name(),
+                    e
+                );
+            }
+        }
+        Ok((before, after?))
+    }
+
+    /// Get code actions for a range of a file (union across servers)
+    pub async fn code_actions(
+        &self,
+        file_path: &Path,
+        range: Range,
+        diagnostics: Vec<Diagnostic>,
+        only: Option<Vec<CodeActionKind>>,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<CodeActionOrCommand>, LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let clients = supporting(clients, "textDocument/codeAction").await?;
+        let responses = successes(
+            futures::future::join_all(clients.iter().map(|c| {
+                c.code_actions(
+                    file_path,
+                    range,
+                    diagnostics.clone(),
+                    only.clone(),
+                    encoding,
+                )
+            }))
+            .await,
+        )?;
+        Ok(merge::union(responses))
     }
 
-    /// Search for symbols across the workspace
+    /// Search for symbols across the workspace (union across servers)
     pub async fn workspace_symbols(
         &self,
         query: String,
         language: &str,
-    ) -> Result<Option<Vec<SymbolInformation>>, LspError> {
-        let client = self.get_or_create_client(language).await?;
-        client.workspace_symbols(query).await
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceSymbolResponse>, LspError> {
+        let clients = self.ready_clients_for_language(language).await?;
+        let clients = supporting(clients, "workspace/symbol").await?;
+        let responses = successes(
+            futures::future::join_all(
+                clients
+                    .iter()
+                    .map(|c| Self::workspace_symbols_from(c, query.clone(), encoding)),
+            )
+            .await,
+        )?;
+
+        Ok(merge::merge_workspace_symbols(
+            responses.into_iter().flatten().collect(),
+        ))
     }
 
-    /// Get status of all active LSP clients
-    pub async fn status(&self) -> Vec<(String, bool)> {
-        let clients = self.clients.lock().await;
-        clients.iter()
-            .map(|(lang, _client)| (lang.clone(), true))
-            .collect()
+    /// Find symbols named `name`, optionally inside `container`
+    ///
+    /// Looks in `file`'s document symbols first, then searches the
+    /// workspace of `language` (or of `file`'s language). Ranges in the
+    /// results count UTF-8 columns; use `SymbolMatch::position` to query at
+    /// one of them.
+    pub async fn find_symbols(
+        &self,
+        name: &str,
+        container: Option<&str>,
+        file: Option<&Path>,
+        language: Option<&str>,
+    ) -> Result<Vec<SymbolMatch>, LspError> {
+        if let Some(file) = file {
+            let outline = match self.document_symbols(file, PositionEncoding::Utf8).await {
+                Err(LspError::Unsupported { .. }) => None,
+                outline => outline?,
+            };
+            if let Some(response) = outline {
+                let matches =
+                    symbols::document_matches(response, file.to_path_buf(), name, container);
+                if !matches.is_empty() {
+                    return Ok(matches);
+                }
+            }
+        }
+
+        let language = match (language, file) {
+            (Some(language), _) => language.to_string(),
+            (None, Some(file)) => match file_language_override() {
+                Some(language) => language,
+                None => self.config().get_lsps_for_file(file)?.0,
+            },
+            (None, None) => {
+                return Err(LspError::UnsupportedLanguage(
+                    "a file or language is needed to search for symbols".to_string(),
+                ))
+            }
+        };
+
+        Ok(self
+            .workspace_symbols(name.to_string(), &language, PositionEncoding::Utf8)
+            .await?
+            .map(|response| symbols::workspace_matches(response, name, container))
+            .unwrap_or_default())
+    }
+
+    /// Send any request to a server and return its raw result
+    ///
+    /// The server is `file`'s (synced first) or `language`'s; `server` picks
+    /// one by name when several handle it, otherwise the first is used.
+    /// Lifecycle methods are refused, since they would break the client.
+    pub async fn raw_request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        file: Option<&Path>,
+        language: Option<&str>,
+        server: Option<&str>,
+    ) -> Result<serde_json::Value, LspError> {
+        if matches!(method, "initialize" | "shutdown" | "exit") {
+            return Err(LspError::ProtocolError(format!(
+                "{} can't be sent directly; lsmcp manages the server's lifecycle",
+                method
+            )));
+        }
+
+        let clients = match (file, language) {
+            (Some(file), _) => self.ready_clients_for_file(file).await?,
+            (None, Some(language)) => self.ready_clients_for_language(language).await?,
+            (None, None) => {
+                return Err(LspError::UnsupportedLanguage(
+                    "a file or language is needed to pick a server".to_string(),
+                ))
+            }
+        };
+        let client = match server {
+            Some(name) => clients.iter().find(|c| c.server_name() == name),
+            None => clients.first(),
+        };
+        let Some(client) = client else {
+            let names: Vec<&str> = clients.iter().map(|c| c.server_name()).collect();
+            return Err(LspError::ConfigError(format!(
+                "no server named '{}' here; available: {}",
+                server.unwrap_or_default(),
+                names.join(", ")
+            )));
+        };
+
+        let _document = match file {
+            Some(file) => Some(client.lease_document(file).await?),
+            None => None,
+        };
+        client.raw_request(method, params).await
+    }
+
+    /// Runnable tests, benchmarks and binaries in a file (union across
+    /// servers), those at `position` if given
+    pub async fn runnables(
+        &self,
+        file_path: &Path,
+        position: Option<Position>,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<Runnable>, LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let lists = successes(
+            futures::future::join_all(
+                clients
+                    .iter()
+                    .map(|c| c.runnables(file_path, position, encoding)),
+            )
+            .await,
+        )?;
+        Ok(lists.into_iter().flatten().collect())
+    }
+
+    /// Tools the config defines to run commands (`[command_tools]`)
+    pub fn command_tools(&self) -> BTreeMap<String, CommandToolConfig> {
+        self.config().command_tools()
+    }
+
+    /// Which tools are offered and under what names (`[tools]`)
+    pub fn tools_config(&self) -> ToolsConfig {
+        self.config().tools()
+    }
+
+    /// Extension requests offered as tools, by servers that are in use for
+    /// one of their languages (so `lsp_rust_expand_macro` disappears when
+    /// Rust is configured to use another server)
+    pub fn extension_tools(&self) -> Vec<ExtensionRequest> {
+        let config = self.config();
+        let mut tools: Vec<ExtensionRequest> = Vec::new();
+        for pkg in config.list_available_lsps() {
+            if pkg.extensions.is_empty() {
+                continue;
+            }
+            let active = pkg.languages.iter().any(|language| {
+                config
+                    .get_lsps_for_language(language)
+                    .is_ok_and(|servers| servers.iter().any(|s| s.name == pkg.name))
+            });
+            if !active {
+                continue;
+            }
+            for extension in &pkg.extensions {
+                if !tools.iter().any(|t| t.tool == extension.tool) {
+                    tools.push(extension.clone());
+                }
+            }
+        }
+        tools.sort_by(|a, b| a.tool.cmp(&b.tool));
+        tools
+    }
+
+    /// Send the extension request exposed as `tool` to whichever of
+    /// `file_path`'s servers declares it
+    pub async fn extension_request(
+        &self,
+        tool: &str,
+        file_path: &Path,
+        position: Option<Position>,
+        encoding: PositionEncoding,
+    ) -> Result<serde_json::Value, LspError> {
+        let clients = self.ready_clients_for_file(file_path).await?;
+        let Some((client, extension)) = clients
+            .iter()
+            .find_map(|c| c.extension(tool).map(|e| (c, e)))
+        else {
+            let servers: Vec<&str> = clients.iter().map(|c| c.server_name()).collect();
+            return Err(LspError::ExtensionUnavailable(
+                tool.to_string(),
+                servers.join(", "),
+            ));
+        };
+        client
+            .extension_request(extension, file_path, position, encoding)
+            .await
+    }
+
+    /// Search one server for workspace symbols
+    ///
+    /// `WorkspaceSymbol` hits without a range are resolved (up to
+    /// `MAX_SYMBOL_RESOLVES`) when the server supports it.
+    async fn workspace_symbols_from(
+        client: &LspClient,
+        query: String,
+        encoding: PositionEncoding,
+    ) -> Result<Option<WorkspaceSymbolResponse>, LspError> {
+        let symbols = match client.workspace_symbols(query, encoding).await? {
+            Some(WorkspaceSymbolResponse::Nested(symbols)) => symbols,
+            other => return Ok(other),
+        };
+
+        if !client.supports_workspace_symbol_resolve().await {
+            return Ok(Some(WorkspaceSymbolResponse::Nested(symbols)));
+        }
+
+        let mut resolved = Vec::with_capacity(symbols.len());
+        let mut resolves = 0;
+        for symbol in symbols {
+            if matches!(symbol.location, OneOf::Right(_)) && resolves < MAX_SYMBOL_RESOLVES {
+                resolves += 1;
+                match client
+                    .resolve_workspace_symbol(symbol.clone(), encoding)
+                    .await
+                {
+                    Ok(symbol) => resolved.push(symbol),
+                    Err(e) => {
+                        debug!("Failed to resolve workspace symbol {}: {}", symbol.name, e);
+                        resolved.push(symbol);
+                    }
+                }
+            } else {
+                resolved.push(symbol);
+            }
+        }
+
+        Ok(Some(WorkspaceSymbolResponse::Nested(resolved)))
+    }
+
+    /// Health snapshots of all active LSP clients
+    pub async fn server_status(&self) -> Vec<ServerHealth> {
+        let clients: Vec<Arc<LspClient>> = {
+            let clients = self.clients.lock().await;
+            clients
+                .values()
+                .filter_map(|state| state.ready().map(|m| Arc::clone(&m.client)))
+                .collect()
+        };
+
+        let mut status = futures::future::join_all(clients.iter().map(|c| c.health())).await;
+        status.sort_by(|a, b| {
+            (&a.language, &a.project_root, &a.server_name).cmp(&(
+                &b.language,
+                &b.project_root,
+                &b.server_name,
+            ))
+        });
+        status
+    }
+
+    /// Servers installed by lsmcp, sorted by name
+    pub async fn installed_servers(&self) -> Vec<InstalledServer> {
+        let installer = self.installer.lock().await;
+        let mut servers: Vec<InstalledServer> =
+            installer.list_installed().into_iter().cloned().collect();
+        servers.sort_by(|a, b| a.name.cmp(&b.name));
+        servers
+    }
+
+    /// Install a server by name or language, returning its name and binary path
+    pub async fn install_server(&self, server: &str) -> Result<(String, PathBuf), LspError> {
+        let package = self.config().find_lsp(server)?;
+        let path = self.installer.lock().await.install_lsp(&package).await?;
+        Ok((package.name, path))
+    }
+
+    /// Install servers for every language with files in the workspace
+    pub async fn install_detected(
+        &self,
+        on_event: &(dyn Fn(InstallEvent) + Sync),
+    ) -> InstallSummary {
+        let packages = self.config().detect_lsps(&self.workspace_root);
+        self.installer
+            .lock()
+            .await
+            .install_missing(&packages, on_event)
+            .await
+    }
+
+    /// Upgrade a server in place and restart its running clients
+    pub async fn update_server(&self, server: &str) -> Result<(String, PathBuf), LspError> {
+        let package = self.config().find_lsp(server)?;
+        let path = self.installer.lock().await.update_lsp(&package).await?;
+        self.stop_clients_for_server(&package.name).await;
+        Ok((package.name, path))
+    }
+
+    /// Stop a server's clients and uninstall it
+    ///
+    /// Returns the server name and whether it was installed by lsmcp.
+    pub async fn uninstall_server(&self, server: &str) -> Result<(String, bool), LspError> {
+        let name = self
+            .config()
+            .find_lsp(server)
+            .map(|p| p.name)
+            .unwrap_or_else(|_| server.to_string());
+
+        self.stop_clients_for_server(&name).await;
+        let removed = self.installer.lock().await.uninstall(&name)?;
+        Ok((name, removed))
+    }
+
+    /// Compare installed servers against their upstream versions
+    pub async fn check_updates(&self) -> Vec<UpdateCheck> {
+        self.installer.lock().await.check_updates().await
+    }
+
+    /// Remove stale manifest entries and orphaned server directories
+    pub async fn prune_servers(&self, dry_run: bool) -> Result<PruneReport, LspError> {
+        self.installer.lock().await.prune(dry_run)
+    }
+
+    /// Shut down every client running the given server package
+    async fn stop_clients_for_server(&self, server_name: &str) {
+        let stopped: Vec<ManagedClient> = {
+            let mut clients = self.clients.lock().await;
+            // Forget failed starts too, so the next request retries at once
+            let keys: Vec<ClientKey> = clients
+                .iter()
+                .filter(|((_, server, _), state)| {
+                    server == server_name && !matches!(state, ClientState::Starting(_))
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            keys.iter()
+                .filter_map(|key| match clients.remove(key) {
+                    Some(ClientState::Ready(managed)) => Some(managed),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        if !stopped.is_empty() {
+            self.server_changes.send_replace(());
+        }
+        for managed in stopped {
+            info!(
+                "Stopping {} client for {}",
+                server_name,
+                managed.client.language()
+            );
+            managed.client.shutdown().await;
+        }
     }
 
     /// Shutdown all LSP clients gracefully
     pub async fn shutdown(&self) {
+        self.save_session().await;
         info!("Shutting down all LSP clients");
         let mut clients = self.clients.lock().await;
 
-        for (language, client) in clients.drain() {
-            info!("Shutting down LSP client for {}", language);
-            // Clients will be dropped here, triggering process cleanup via kill_on_drop
-            drop(client);
+        for ((language, server, root), state) in clients.drain() {
+            let ClientState::Ready(managed) = state else {
+                continue;
+            };
+            info!(
+                "Shutting down {} client for {} at {}",
+                server,
+                language,
+                root.display()
+            );
+            // Any process that ignores the shutdown request is killed on drop
+            managed.client.shutdown().await;
         }
 
         info!("All LSP clients shut down");
@@ -199,3 +2320,255 @@ impl Drop for LspManager {
         debug!("LspManager dropped");
     }
 }
+
+/// Content generations of `clients`, identifying what they've been told
+fn content_stamp(clients: &[Arc<LspClient>]) -> Vec<u64> {
+    clients.iter().map(|c| c.content_generation()).collect()
+}
+
+/// Server cap from the settings
+fn max_open_documents(settings: &crate::config::Settings) -> usize {
+    settings
+        .max_open_documents
+        .unwrap_or(documents::DEFAULT_MAX_OPEN_DOCUMENTS)
+}
+
+/// Failures in a row that open a server's circuit breaker, and for how long
+fn circuit_breaker(settings: &crate::config::Settings) -> (u32, Duration) {
+    let failures = settings
+        .circuit_breaker_failures
+        .unwrap_or(breaker::DEFAULT_BREAKER_FAILURES);
+    let cooldown = settings
+        .circuit_breaker_cooldown_secs
+        .map_or(breaker::DEFAULT_BREAKER_COOLDOWN, Duration::from_secs);
+    (failures, cooldown)
+}
+
+/// Request cap for a server: its own `limits`, else the settings'
+fn max_concurrent_requests(
+    settings: &crate::config::Settings,
+    limits: &crate::config::ResourceLimits,
+) -> usize {
+    limits
+        .max_concurrent_requests
+        .or(settings.max_concurrent_requests)
+        .unwrap_or(throttle::DEFAULT_MAX_CONCURRENT_REQUESTS)
+}
+
+fn max_servers(settings: &crate::config::Settings) -> usize {
+    settings.max_servers.unwrap_or(DEFAULT_MAX_SERVERS).max(1)
+}
+
+/// Which config file and local registry files are in use, and when they
+/// were last written
+fn config_fingerprint() -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let mut paths: Vec<PathBuf> = ConfigLoader::user_config_path().into_iter().collect();
+    if let Some(entries) =
+        ConfigLoader::local_registry_dir().and_then(|d| std::fs::read_dir(d).ok())
+    {
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        files.sort();
+        paths.extend(files);
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(&path).ok();
+            let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+            let len = metadata.map_or(0, |m| m.len());
+            (path, modified, len)
+        })
+        .collect()
+}
+
+/// Whether a running `server` client for `language` at `root` is out of date
+/// after a config change: its definition there changed, or the language
+/// stopped using it
+fn is_stale(
+    old: &ConfigLoader,
+    new: &ConfigLoader,
+    language: &str,
+    server: &str,
+    root: &Path,
+    workspace_root: &Path,
+) -> bool {
+    let definition =
+        |config: &ConfigLoader| config.get_lsp_for_project(server, root, workspace_root);
+    if definition(old) != definition(new) || old.path_map() != new.path_map() {
+        return true;
+    }
+
+    let serves = |config: &ConfigLoader| {
+        config
+            .get_lsps_for_language(language)
+            .is_ok_and(|packages| packages.iter().any(|p| p.name == server))
+    };
+    serves(old) && !serves(new)
+}
+
+/// The clients whose servers handle `method`, or an error naming the
+/// servers when none does
+async fn supporting(
+    clients: Vec<Arc<LspClient>>,
+    method: &str,
+) -> Result<Vec<Arc<LspClient>>, LspError> {
+    let support = futures::future::join_all(clients.iter().map(|c| c.supports(method))).await;
+    if clients.is_empty() || support.iter().any(|supported| *supported) {
+        return Ok(clients
+            .into_iter()
+            .zip(support)
+            .filter_map(|(client, supported)| supported.then_some(client))
+            .collect());
+    }
+    let servers: Vec<&str> = clients.iter().map(|c| c.server_name()).collect();
+    Err(LspError::Unsupported {
+        server: servers.join(" or "),
+        feature: capabilities::feature_name(method).to_string(),
+    })
+}
+
+/// The language the current tool call asked its file to be treated as
+fn file_language_override() -> Option<String> {
+    FILE_LANGUAGE.try_with(Clone::clone).ok()
+}
+
+/// Diagnostics for a document from each of `clients`, merged, once they
+/// published them for its current content (see `LspClient::fresh_diagnostics`)
+async fn fresh_diagnostics(
+    clients: &[Arc<LspClient>],
+    file_path: &Path,
+    wait: Duration,
+    encoding: PositionEncoding,
+) -> Result<Vec<Diagnostic>, LspError> {
+    let results = futures::future::join_all(clients.iter().map(|c| async move {
+        c.fresh_diagnostics(file_path, wait, encoding)
+            .await
+            .map(|diagnostics| (c.server_name().to_string(), diagnostics))
+    }))
+    .await;
+    Ok(merge::merge_diagnostics(successes(results)?))
+}
+
+/// Successful results, or the first error when every server failed
+///
+/// Lets a language with several servers keep working when one of them is
+/// missing or failing.
+fn successes<T>(results: Vec<Result<T, LspError>>) -> Result<Vec<T>, LspError> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+
+    for result in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if values.is_empty() && !errors.is_empty() {
+        return Err(errors.remove(0));
+    }
+
+    for e in errors {
+        warn!("Ignoring failed language server: {}", e);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UserConfig;
+
+    fn loader(toml: &str) -> ConfigLoader {
+        let user_config: UserConfig = toml::from_str(toml).unwrap();
+        ConfigLoader::with_user_config(Some(user_config)).unwrap()
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let old = loader(
+            r#"
+            [lsp.rust-analyzer]
+            args = ["--log"]
+            "#,
+        );
+        let new = loader(
+            r#"
+            [lsp.rust-analyzer]
+            args = ["--log"]
+
+            [lsp.pyright]
+            initialization_options = { python = { analysis = { typeCheckingMode = "strict" } } }
+
+            [language_overrides]
+            typescript = "vscode-html-language-server"
+            "#,
+        );
+
+        let ws = Path::new("/ws");
+        let stale = |language, server| is_stale(&old, &new, language, server, ws, ws);
+        assert!(!stale("rust", "rust-analyzer"));
+        assert!(stale("python", "pyright"));
+        assert!(stale("typescript", "typescript-language-server"));
+        assert!(!stale("go", "gopls"));
+    }
+
+    #[test]
+    fn test_is_stale_per_project() {
+        let tmp = tempfile::tempdir().unwrap();
+        let ws = tmp.path();
+        let api = ws.join("services/api");
+        std::fs::create_dir_all(&api).unwrap();
+
+        let old = loader("");
+        assert!(old.get_lsp_for_project("gopls", &api, ws).is_some());
+        std::fs::write(
+            api.join(crate::config::PROJECT_CONFIG_FILE),
+            "[lsp.gopls]\nenv = { GOFLAGS = \"-tags=api\" }\n",
+        )
+        .unwrap();
+        assert!(old.project_configs_changed());
+
+        let new = loader("");
+        assert!(is_stale(&old, &new, "go", "gopls", &api, ws));
+        assert!(!is_stale(&old, &new, "go", "gopls", ws, ws));
+        let (_, packages) = new
+            .get_lsps_for_project(&api.join("main.go"), None, &api, ws)
+            .unwrap();
+        assert_eq!(packages[0].bin.env["GOFLAGS"], "-tags=api");
+    }
+
+    #[tokio::test]
+    async fn test_starting_client_queue() {
+        let (tx, rx) = watch::channel(None);
+        let starting = Arc::new(StartingClient {
+            outcome: rx,
+            waiters: AtomicUsize::new(MAX_STARTUP_WAITERS),
+        });
+        assert!(matches!(
+            starting.wait("pyright").await,
+            Err(LspError::StartupQueueFull(_))
+        ));
+
+        starting.waiters.store(0, Ordering::SeqCst);
+        let waiter = {
+            let starting = Arc::clone(&starting);
+            tokio::spawn(async move { starting.wait("pyright").await })
+        };
+        tx.send(Some(Err("binary not found".to_string()))).unwrap();
+
+        match waiter.await.unwrap() {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "pyright failed to start: binary not found"
+            ),
+            Ok(_) => panic!("expected the startup error"),
+        }
+        assert_eq!(starting.waiters.load(Ordering::SeqCst), 0);
+    }
+}
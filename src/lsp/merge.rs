@@ -0,0 +1,269 @@
+//! Merging responses from several servers for the same file
+//!
+//! When a language has more than one server, point queries (hover,
+//! definition) take the first non-empty answer in priority order, while
+//! list queries (references, diagnostics, symbols) return the union.
+
+use lsp_types::*;
+
+/// First non-empty response, in server priority order
+pub fn first_non_empty<T>(responses: Vec<Option<T>>, is_empty: impl Fn(&T) -> bool) -> Option<T> {
+    responses.into_iter().flatten().find(|r| !is_empty(r))
+}
+
+/// Whether a definition response has no locations
+pub fn definition_is_empty(response: &GotoDefinitionResponse) -> bool {
+    match response {
+        GotoDefinitionResponse::Scalar(_) => false,
+        GotoDefinitionResponse::Array(locations) => locations.is_empty(),
+        GotoDefinitionResponse::Link(links) => links.is_empty(),
+    }
+}
+
+/// Whether a hover carries no text
+pub fn hover_is_empty(hover: &Hover) -> bool {
+    let marked_is_empty = |marked: &MarkedString| match marked {
+        MarkedString::String(s) => s.trim().is_empty(),
+        MarkedString::LanguageString(ls) => ls.value.trim().is_empty(),
+    };
+
+    match &hover.contents {
+        HoverContents::Scalar(marked) => marked_is_empty(marked),
+        HoverContents::Array(items) => items.iter().all(marked_is_empty),
+        HoverContents::Markup(markup) => markup.value.trim().is_empty(),
+    }
+}
+
+/// Union of items, dropping exact duplicates and keeping first-seen order
+pub fn union<T: PartialEq>(lists: Vec<Vec<T>>) -> Vec<T> {
+    let mut merged: Vec<T> = Vec::new();
+    for item in lists.into_iter().flatten() {
+        if !merged.contains(&item) {
+            merged.push(item);
+        }
+    }
+    merged
+}
+
+/// Union of diagnostics, labelling unlabelled ones with their server
+///
+/// `lists` pairs each server name with the diagnostics it published.
+pub fn merge_diagnostics(lists: Vec<(String, Vec<Diagnostic>)>) -> Vec<Diagnostic> {
+    union(
+        lists
+            .into_iter()
+            .map(|(server, diagnostics)| {
+                diagnostics
+                    .into_iter()
+                    .map(|mut d| {
+                        d.source.get_or_insert_with(|| server.clone());
+                        d
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Union of document symbol responses
+///
+/// Stays hierarchical when every server answers hierarchically; otherwise
+/// nested symbols are flattened into `SymbolInformation` for `uri`.
+pub fn merge_document_symbols(
+    responses: Vec<DocumentSymbolResponse>,
+    uri: &Url,
+) -> Option<DocumentSymbolResponse> {
+    if responses.len() <= 1 {
+        return responses.into_iter().next();
+    }
+
+    if responses
+        .iter()
+        .all(|r| matches!(r, DocumentSymbolResponse::Nested(_)))
+    {
+        let lists = responses
+            .into_iter()
+            .map(|r| match r {
+                DocumentSymbolResponse::Nested(symbols) => symbols,
+                DocumentSymbolResponse::Flat(_) => Vec::new(),
+            })
+            .collect();
+        return Some(DocumentSymbolResponse::Nested(union(lists)));
+    }
+
+    let lists = responses
+        .into_iter()
+        .map(|r| match r {
+            DocumentSymbolResponse::Flat(symbols) => symbols,
+            DocumentSymbolResponse::Nested(symbols) => {
+                let mut flat = Vec::new();
+                flatten_document_symbols(&symbols, None, uri, &mut flat);
+                flat
+            }
+        })
+        .collect();
+    Some(DocumentSymbolResponse::Flat(union(lists)))
+}
+
+#[allow(deprecated)]
+fn flatten_document_symbols(
+    symbols: &[DocumentSymbol],
+    container: Option<&str>,
+    uri: &Url,
+    out: &mut Vec<SymbolInformation>,
+) {
+    for symbol in symbols {
+        out.push(SymbolInformation {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            tags: symbol.tags.clone(),
+            deprecated: None,
+            location: Location::new(uri.clone(), symbol.range),
+            container_name: container.map(String::from),
+        });
+        if let Some(children) = &symbol.children {
+            flatten_document_symbols(children, Some(&symbol.name), uri, out);
+        }
+    }
+}
+
+/// Union of workspace symbol responses
+///
+/// Flat responses are returned as-is when every server answers flat;
+/// otherwise everything is converted to `WorkspaceSymbol`.
+pub fn merge_workspace_symbols(
+    responses: Vec<WorkspaceSymbolResponse>,
+) -> Option<WorkspaceSymbolResponse> {
+    if responses.len() <= 1 {
+        return responses.into_iter().next();
+    }
+
+    if responses
+        .iter()
+        .all(|r| matches!(r, WorkspaceSymbolResponse::Flat(_)))
+    {
+        let lists = responses
+            .into_iter()
+            .map(|r| match r {
+                WorkspaceSymbolResponse::Flat(symbols) => symbols,
+                WorkspaceSymbolResponse::Nested(_) => Vec::new(),
+            })
+            .collect();
+        return Some(WorkspaceSymbolResponse::Flat(union(lists)));
+    }
+
+    let lists = responses
+        .into_iter()
+        .map(|r| match r {
+            WorkspaceSymbolResponse::Nested(symbols) => symbols,
+            WorkspaceSymbolResponse::Flat(symbols) => symbols
+                .into_iter()
+                .map(|s| WorkspaceSymbol {
+                    name: s.name,
+                    kind: s.kind,
+                    tags: s.tags,
+                    container_name: s.container_name,
+                    location: OneOf::Left(s.location),
+                    data: None,
+                })
+                .collect(),
+        })
+        .collect();
+    Some(WorkspaceSymbolResponse::Nested(union(lists)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(line: u32) -> Location {
+        Location::new(
+            Url::parse("file:///src/app.py").unwrap(),
+            Range::new(Position::new(line, 0), Position::new(line, 4)),
+        )
+    }
+
+    #[test]
+    fn test_first_non_empty_and_union() {
+        let responses = vec![
+            None,
+            Some(GotoDefinitionResponse::Array(vec![])),
+            Some(GotoDefinitionResponse::Scalar(location(3))),
+            Some(GotoDefinitionResponse::Scalar(location(7))),
+        ];
+        assert_eq!(
+            first_non_empty(responses, definition_is_empty),
+            Some(GotoDefinitionResponse::Scalar(location(3)))
+        );
+
+        let merged = union(vec![
+            vec![location(1), location(2)],
+            vec![location(2), location(3)],
+        ]);
+        assert_eq!(merged, vec![location(1), location(2), location(3)]);
+    }
+
+    #[test]
+    fn test_merge_diagnostics_labels_source() {
+        let diagnostic = |message: &str, source: Option<&str>| Diagnostic {
+            range: Range::default(),
+            message: message.to_string(),
+            source: source.map(String::from),
+            ..Default::default()
+        };
+
+        let merged = merge_diagnostics(vec![
+            ("pyright".to_string(), vec![diagnostic("type error", None)]),
+            (
+                "ruff-lsp".to_string(),
+                vec![diagnostic("unused import", Some("Ruff"))],
+            ),
+        ]);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].source.as_deref(), Some("pyright"));
+        assert_eq!(merged[1].source.as_deref(), Some("Ruff"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_merge_mixed_symbol_responses() {
+        let uri = Url::parse("file:///src/app.py").unwrap();
+        let nested = DocumentSymbolResponse::Nested(vec![DocumentSymbol {
+            name: "App".to_string(),
+            detail: None,
+            kind: SymbolKind::CLASS,
+            tags: None,
+            deprecated: None,
+            range: location(1).range,
+            selection_range: location(1).range,
+            children: Some(vec![DocumentSymbol {
+                name: "run".to_string(),
+                detail: None,
+                kind: SymbolKind::METHOD,
+                tags: None,
+                deprecated: None,
+                range: location(2).range,
+                selection_range: location(2).range,
+                children: None,
+            }]),
+        }]);
+        let flat = DocumentSymbolResponse::Flat(vec![SymbolInformation {
+            name: "main".to_string(),
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            location: location(9),
+            container_name: None,
+        }]);
+
+        match merge_document_symbols(vec![nested, flat], &uri) {
+            Some(DocumentSymbolResponse::Flat(symbols)) => {
+                let names: Vec<_> = symbols.iter().map(|s| s.name.as_str()).collect();
+                assert_eq!(names, vec!["App", "run", "main"]);
+                assert_eq!(symbols[1].container_name.as_deref(), Some("App"));
+            }
+            other => panic!("expected flat symbols, got {:?}", other),
+        }
+    }
+}
@@ -1,9 +1,36 @@
 //! LSP client and manager implementation
 
+pub mod breaker;
+pub mod builder;
+pub mod cache;
+pub mod capabilities;
 pub mod client;
+pub mod documents;
+pub mod file_watcher;
+pub mod health;
 pub mod languages;
+pub mod launch;
 pub mod manager;
+pub mod merge;
+pub mod orphans;
+pub mod overlay;
+pub mod partial;
+pub mod prepare;
+pub mod problems;
 pub mod process;
+pub mod progress;
+pub mod project;
+pub mod registrations;
+pub mod remote;
+pub mod resources;
+pub mod runnables;
+pub mod session;
+pub mod shared_daemon;
+pub mod symbols;
+pub mod throttle;
+pub mod virtual_docs;
+pub mod warm_cache;
 
+pub use builder::{LspClientBuilder, LspManagerBuilder};
 pub use client::LspClient;
 pub use manager::LspManager;
@@ -0,0 +1,340 @@
+//! Servers left running by lsmcp processes that are gone
+//!
+//! Servers are killed when their client is dropped, which only happens when
+//! lsmcp exits normally. After a crash or a `kill -9` they keep running with
+//! nobody to talk to, and a rust-analyzer alone can hold gigabytes. Each
+//! lsmcp process records the servers it spawned in a file of its own under
+//! `<data dir>/processes`, along with the start times `ps` reports, so a
+//! reused PID isn't taken for one of them. Servers recorded by a process
+//! that no longer runs are orphans: they are stopped at startup and by
+//! `lsmcp kill-orphans`, and `lsmcp ps` lists every recorded server.
+
+use crate::installer::ServerInstaller;
+use crate::lsp::resources::ProcessTable;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+/// How long an orphan gets to exit after SIGTERM before it is killed
+const TERM_GRACE: Duration = Duration::from_secs(2);
+
+/// A server process spawned by lsmcp
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerProcess {
+    pub pid: u32,
+    /// Start time as `ps -o lstart=` shows it
+    pub started: String,
+    pub server: String,
+    pub language: String,
+    pub workspace_root: PathBuf,
+}
+
+/// The servers one lsmcp process spawned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ProcessFile {
+    owner_pid: u32,
+    owner_started: String,
+    servers: Vec<ServerProcess>,
+}
+
+/// A recorded server that is still running
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedServer {
+    pub process: ServerProcess,
+    /// The lsmcp process that spawned it
+    pub owner_pid: u32,
+    /// Whether that process is gone
+    pub orphaned: bool,
+}
+
+/// This process's record, once it has spawned a server
+static OWN: Mutex<Option<ProcessFile>> = Mutex::new(None);
+
+fn processes_dir() -> Option<PathBuf> {
+    ServerInstaller::get_data_dir()
+        .ok()
+        .map(|dir| dir.join("processes"))
+}
+
+fn own_path(dir: &Path) -> PathBuf {
+    dir.join(format!("{}.json", std::process::id()))
+}
+
+/// Record a server this process spawned
+pub async fn record(pid: u32, server: &str, language: &str, workspace_root: &Path) {
+    let Some(dir) = processes_dir() else {
+        return;
+    };
+    let owner_pid = std::process::id();
+    let times = start_times(&[pid, owner_pid]).await;
+    let (Some(started), Some(owner_started)) = (times.get(&pid), times.get(&owner_pid)) else {
+        debug!("No start time for {} ({}), not recording it", server, pid);
+        return;
+    };
+
+    let mut own = OWN.lock().unwrap_or_else(|e| e.into_inner());
+    let file = own.get_or_insert_with(|| ProcessFile {
+        owner_pid,
+        owner_started: owner_started.clone(),
+        servers: Vec::new(),
+    });
+    file.servers.push(ServerProcess {
+        pid,
+        started: started.clone(),
+        server: server.to_string(),
+        language: language.to_string(),
+        workspace_root: workspace_root.to_path_buf(),
+    });
+    if let Err(e) = save(&own_path(&dir), file) {
+        warn!("Failed to record server process {}: {}", pid, e);
+    }
+}
+
+/// Forget a server this process stopped
+pub fn forget(pid: u32) {
+    let Some(dir) = processes_dir() else {
+        return;
+    };
+    let mut own = OWN.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(file) = own.as_mut() else {
+        return;
+    };
+    file.servers.retain(|server| server.pid != pid);
+    let path = own_path(&dir);
+    let result = if file.servers.is_empty() {
+        std::fs::remove_file(&path)
+    } else {
+        save(&path, file)
+    };
+    if let Err(e) = result {
+        debug!("Failed to update {}: {}", path.display(), e);
+    }
+}
+
+fn save(path: &Path, file: &ProcessFile) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, serde_json::to_string(file)?)?;
+    std::fs::rename(&temp, path)
+}
+
+/// Every process file, with its path
+fn load_all(dir: &Path) -> Vec<(PathBuf, ProcessFile)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let content = std::fs::read_to_string(&path).ok()?;
+            match serde_json::from_str(&content) {
+                Ok(file) => Some((path, file)),
+                Err(e) => {
+                    debug!("Ignoring {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The recorded servers that are still running
+pub async fn list() -> Vec<RecordedServer> {
+    let Some(dir) = processes_dir() else {
+        return Vec::new();
+    };
+    let files: Vec<ProcessFile> = load_all(&dir).into_iter().map(|(_, f)| f).collect();
+    let times = start_times(&pids(&files)).await;
+    running(&files, &times)
+}
+
+/// Stop the servers of lsmcp processes that are gone, with their child
+/// processes, and return them
+pub async fn kill_orphans() -> Vec<ServerProcess> {
+    let Some(dir) = processes_dir() else {
+        return Vec::new();
+    };
+    let files = load_all(&dir);
+    let times = start_times(&pids(files.iter().map(|(_, f)| f))).await;
+    let orphans: Vec<ServerProcess> = running(files.iter().map(|(_, f)| f), &times)
+        .into_iter()
+        .filter(|server| server.orphaned)
+        .map(|server| server.process)
+        .collect();
+
+    if !orphans.is_empty() {
+        let mut targets = Vec::new();
+        if let Some(table) = ProcessTable::sample().await {
+            for orphan in &orphans {
+                targets.extend(table.tree(orphan.pid));
+            }
+        } else {
+            targets.extend(orphans.iter().map(|orphan| orphan.pid));
+        }
+        signal("TERM", &targets).await;
+        tokio::time::sleep(TERM_GRACE).await;
+        let remaining: Vec<u32> = start_times(&targets).await.into_keys().collect();
+        signal("KILL", &remaining).await;
+    }
+
+    // The files of processes that are gone have served their purpose
+    for (path, file) in &files {
+        if !is_running(file.owner_pid, &file.owner_started, &times) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    orphans
+}
+
+/// Stop orphaned servers in the background, logging what was stopped
+pub fn spawn_orphan_reaper() {
+    tokio::spawn(async {
+        for orphan in kill_orphans().await {
+            info!(
+                "Stopped orphaned {} server {} (pid {}) for {}",
+                orphan.language,
+                orphan.server,
+                orphan.pid,
+                orphan.workspace_root.display()
+            );
+        }
+    });
+}
+
+fn pids<'a>(files: impl IntoIterator<Item = &'a ProcessFile>) -> Vec<u32> {
+    files
+        .into_iter()
+        .flat_map(|file| {
+            std::iter::once(file.owner_pid).chain(file.servers.iter().map(|server| server.pid))
+        })
+        .collect()
+}
+
+fn is_running(pid: u32, started: &str, times: &HashMap<u32, String>) -> bool {
+    times.get(&pid).is_some_and(|time| time == started)
+}
+
+/// The servers in `files` still running according to `times` (start time
+/// by PID), and whether their owner is gone
+fn running<'a>(
+    files: impl IntoIterator<Item = &'a ProcessFile>,
+    times: &HashMap<u32, String>,
+) -> Vec<RecordedServer> {
+    files
+        .into_iter()
+        .flat_map(|file| {
+            let orphaned = !is_running(file.owner_pid, &file.owner_started, times);
+            file.servers
+                .iter()
+                .filter(|server| is_running(server.pid, &server.started, times))
+                .map(move |server| RecordedServer {
+                    process: server.clone(),
+                    owner_pid: file.owner_pid,
+                    orphaned,
+                })
+        })
+        .collect()
+}
+
+/// Start times of those of `pids` that are running
+async fn start_times(pids: &[u32]) -> HashMap<u32, String> {
+    if pids.is_empty() {
+        return HashMap::new();
+    }
+    let list = pids
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    // ps fails when some of the PIDs aren't running but still lists the rest
+    match Command::new("ps")
+        .args(["-o", "pid=", "-o", "lstart=", "-p", &list])
+        .env("LC_ALL", "C")
+        .output()
+        .await
+    {
+        Ok(output) => parse_start_times(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            debug!("Failed to run ps: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Parse `ps -o pid=,lstart=` output
+fn parse_start_times(output: &str) -> HashMap<u32, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (pid, started) = line.trim().split_once(char::is_whitespace)?;
+            Some((pid.parse().ok()?, started.trim().to_string()))
+        })
+        .collect()
+}
+
+async fn signal(signal: &str, pids: &[u32]) {
+    if pids.is_empty() {
+        return;
+    }
+    let result = Command::new("kill")
+        .arg(format!("-{}", signal))
+        .args(pids.iter().map(u32::to_string))
+        .output()
+        .await;
+    if let Err(e) = result {
+        warn!("Failed to run kill: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_servers() {
+        let times = parse_start_times(
+            "  100 Fri Oct 16 09:00:00 2026\n  201 Fri Oct 16 09:01:00 2026\n\
+             \x20 301 Fri Oct 16 10:00:00 2026\n  302 Sat Oct 17 08:00:00 2026\n",
+        );
+        assert_eq!(times[&100], "Fri Oct 16 09:00:00 2026");
+
+        let server = |pid, started: &str| ServerProcess {
+            pid,
+            started: started.to_string(),
+            server: "rust-analyzer".to_string(),
+            language: "rust".to_string(),
+            workspace_root: PathBuf::from("/app"),
+        };
+        let files = [
+            ProcessFile {
+                owner_pid: 100,
+                owner_started: "Fri Oct 16 09:00:00 2026".to_string(),
+                servers: vec![server(201, "Fri Oct 16 09:01:00 2026")],
+            },
+            // Owner gone; 302 is a different process that reused the PID
+            ProcessFile {
+                owner_pid: 300,
+                owner_started: "Fri Oct 16 10:00:00 2026".to_string(),
+                servers: vec![
+                    server(301, "Fri Oct 16 10:00:00 2026"),
+                    server(302, "Fri Oct 16 10:00:01 2026"),
+                    server(303, "Fri Oct 16 10:00:01 2026"),
+                ],
+            },
+        ];
+
+        let running = running(&files, &times);
+        assert_eq!(running.len(), 2);
+        assert_eq!((running[0].process.pid, running[0].orphaned), (201, false));
+        assert_eq!((running[1].process.pid, running[1].orphaned), (301, true));
+        assert_eq!(pids(&files), [100, 201, 300, 301, 302, 303]);
+    }
+}
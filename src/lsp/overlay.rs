@@ -0,0 +1,71 @@
+//! Unsaved content for a tool call's file
+//!
+//! Tools given `content` query that text instead of the file on disk, so
+//! agents can check code they are drafting before writing it (even to a
+//! file that doesn't exist yet). Servers get the content like any other
+//! change: a `didOpen`, or a `didChange` with the next version. The next
+//! query without `content` sends the file's own content again.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+tokio::task_local! {
+    /// The file the current tool call is about and its unsaved content
+    static OVERLAY: (PathBuf, Arc<str>);
+}
+
+/// Run `f` (a tool call) with `file`'s content taken to be `content`, when
+/// given
+pub async fn scope<F: Future>(overlay: Option<(PathBuf, String)>, f: F) -> F::Output {
+    match overlay {
+        Some((file, content)) => OVERLAY.scope((file, Arc::from(content)), f).await,
+        None => f.await,
+    }
+}
+
+/// The unsaved content of `path`, if the current tool call has some
+pub fn content(path: &Path) -> Option<String> {
+    OVERLAY
+        .try_with(|(file, content)| (file == path).then(|| content.to_string()))
+        .ok()
+        .flatten()
+}
+
+/// `path`'s content: the unsaved content when the current tool call has
+/// some, otherwise the file on disk
+pub async fn read_file(path: &Path) -> std::io::Result<String> {
+    match content(path) {
+        Some(content) => Ok(content),
+        None => tokio::fs::read_to_string(path).await,
+    }
+}
+
+/// `read_file`, for synchronous callers
+pub fn read_file_sync(path: &Path) -> std::io::Result<String> {
+    match content(path) {
+        Some(content) => Ok(content),
+        None => std::fs::read_to_string(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("saved.rs");
+        std::fs::write(&saved, "fn saved() {}").unwrap();
+        let draft = dir.path().join("draft.rs");
+
+        let overlay = Some((draft.clone(), "fn draft() {}".to_string()));
+        scope(overlay, async {
+            assert_eq!(read_file(&draft).await.unwrap(), "fn draft() {}");
+            assert_eq!(read_file_sync(&saved).unwrap(), "fn saved() {}");
+        })
+        .await;
+        assert!(read_file(&draft).await.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Partial results streamed to the current tool call
+//!
+//! Servers can send a large result (references, workspace symbols) in
+//! batches through `$/progress` instead of all at once at the end. When the
+//! MCP client asks for progress on a tool call, the requests made for it
+//! carry a partial result token and each batch is passed to the call's sink
+//! as it arrives, so a find-references over a big workspace shows results
+//! long before it finishes. The final results still include every batch.
+
+use lsp_types::{Location, WorkspaceSymbolResponse};
+use std::future::Future;
+use std::sync::Arc;
+
+/// A batch of results, with positions already in the tool call's encoding
+pub enum PartialBatch<'a> {
+    Locations(&'a [Location]),
+    Symbols(&'a WorkspaceSymbolResponse),
+}
+
+impl PartialBatch<'_> {
+    /// Results in the batch
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Locations(locations) => locations.len(),
+            Self::Symbols(WorkspaceSymbolResponse::Flat(symbols)) => symbols.len(),
+            Self::Symbols(WorkspaceSymbolResponse::Nested(symbols)) => symbols.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Where a tool call's partial results go
+pub type PartialSink = Arc<dyn Fn(PartialBatch<'_>) + Send + Sync>;
+
+tokio::task_local! {
+    /// The current tool call's sink, when its client wants progress
+    static SINK: PartialSink;
+}
+
+/// Run `f` (a tool call) passing partial results to `sink`, when given
+pub async fn scope<F: Future>(sink: Option<PartialSink>, f: F) -> F::Output {
+    match sink {
+        Some(sink) => SINK.scope(sink, f).await,
+        None => f.await,
+    }
+}
+
+/// Whether the current tool call takes partial results
+pub fn is_streaming() -> bool {
+    SINK.try_with(|_| ()).is_ok()
+}
+
+/// Pass a batch to the current tool call's sink, if it has one
+pub fn report(batch: PartialBatch<'_>) {
+    if batch.is_empty() {
+        return;
+    }
+    let _ = SINK.try_with(|sink| sink(batch));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Range, Url};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_partial_results() {
+        let location = Location {
+            uri: Url::parse("file:///src/main.rs").unwrap(),
+            range: Range::default(),
+        };
+        let reported = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reported);
+        let sink: PartialSink = Arc::new(move |batch| {
+            counter.fetch_add(batch.len(), Ordering::Relaxed);
+        });
+
+        scope(Some(sink), async {
+            assert!(is_streaming());
+            report(PartialBatch::Locations(&[
+                location.clone(),
+                location.clone(),
+            ]));
+            report(PartialBatch::Locations(&[]));
+        })
+        .await;
+        assert_eq!(reported.load(Ordering::Relaxed), 2);
+
+        // Outside a streaming tool call batches go nowhere
+        assert!(!is_streaming());
+        report(PartialBatch::Locations(&[location]));
+        assert_eq!(reported.load(Ordering::Relaxed), 2);
+    }
+}
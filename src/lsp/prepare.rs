@@ -0,0 +1,137 @@
+//! Pre-opening files for workspace diagnostics
+//!
+//! Some servers (typescript-language-server, pyright) only diagnose the
+//! files a client has opened, so the problems summary covers whatever the
+//! agent happened to look at. `lsp_prepare_workspace`, and the
+//! `prepare_workspace` setting at startup, open the files matching some
+//! globs a batch at a time. They go through each server's open-document
+//! LRU like any other, and no more than `max_open_documents` are opened, so
+//! preparing never evicts the files it just opened.
+
+use crate::tools::files::find_files;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Files opened unless the caller asks for a different number
+pub const DEFAULT_PREPARE_LIMIT: usize = 100;
+
+/// Files opened concurrently
+pub const PREPARE_BATCH: usize = 16;
+
+/// What preparing the workspace did
+#[derive(Debug, Default)]
+pub struct PreparedWorkspace {
+    /// Files matching the patterns
+    pub matched: usize,
+    /// Files opened, or already open, on their servers
+    pub files: usize,
+    /// Of those, how many each server has
+    pub opened: BTreeMap<String, usize>,
+    /// Matching files no configured server handles
+    pub unsupported: usize,
+    /// Files that couldn't be opened, with why
+    pub failed: Vec<(PathBuf, String)>,
+    /// How many files were opened at most
+    pub limit: usize,
+}
+
+impl PreparedWorkspace {
+    /// Summary for the tool's answer and the log
+    pub fn render(&self) -> String {
+        let mut text = if self.files == 0 {
+            format!("Opened no files ({} matched)", self.matched)
+        } else {
+            let servers: Vec<String> = self
+                .opened
+                .iter()
+                .map(|(server, count)| format!("{} on {}", count, server))
+                .collect();
+            format!(
+                "Opened {} file(s) for diagnostics ({} matched): {}",
+                self.files,
+                self.matched,
+                servers.join(", ")
+            )
+        };
+        if self.matched > self.limit {
+            text.push_str(&format!(
+                "\nStopped at {} files; raise `limit` or `max_open_documents` to open more",
+                self.limit
+            ));
+        }
+        if self.unsupported > 0 {
+            text.push_str(&format!(
+                "\nSkipped {} file(s) no language server handles",
+                self.unsupported
+            ));
+        }
+        for (path, error) in &self.failed {
+            text.push_str(&format!("\nFailed to open {}: {}", path.display(), error));
+        }
+        text
+    }
+}
+
+/// The files under `root` matching any of `patterns`, sorted and without
+/// duplicates, with how many matched in all
+///
+/// Patterns are `lsp_find_files` globs. At most `limit` files are returned,
+/// and no more than `max_open` (the open-document cap, 0 for none).
+pub fn files_to_prepare(
+    root: &Path,
+    patterns: &[String],
+    limit: usize,
+    max_open: usize,
+) -> (Vec<PathBuf>, usize) {
+    let limit = match max_open {
+        0 => limit,
+        max => limit.min(max),
+    };
+    let mut files: Vec<PathBuf> = patterns
+        .iter()
+        .flat_map(|pattern| find_files(root, pattern, usize::MAX).files)
+        .collect();
+    files.sort();
+    files.dedup();
+    let matched = files.len();
+    files.truncate(limit);
+    (files, matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_to_prepare() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join("src/lib")).unwrap();
+        for name in ["src/a.ts", "src/b.ts", "src/lib/c.ts", "src/d.py", "e.ts"] {
+            std::fs::write(root.path().join(name), "").unwrap();
+        }
+
+        let patterns = vec!["src/**/*.ts".to_string(), "*.ts".to_string()];
+        let (files, matched) = files_to_prepare(root.path(), &patterns, 10, 0);
+        assert_eq!(matched, 4);
+        assert_eq!(files.len(), 4);
+
+        // The open-document cap wins over a larger limit
+        let (files, matched) = files_to_prepare(root.path(), &patterns, 10, 2);
+        assert_eq!(matched, 4);
+        assert_eq!(
+            files,
+            [root.path().join("e.ts"), root.path().join("src/a.ts")]
+        );
+
+        let prepared = PreparedWorkspace {
+            matched: 4,
+            files: 2,
+            opened: BTreeMap::from([("tsserver".to_string(), 2)]),
+            limit: 2,
+            ..PreparedWorkspace::default()
+        };
+        let text = prepared.render();
+        assert!(text.starts_with("Opened 2 file(s) for diagnostics (4 matched): 2 on tsserver"));
+        assert!(text.contains("Stopped at 2 files"));
+    }
+}
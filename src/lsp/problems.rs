@@ -0,0 +1,238 @@
+//! Problems across the workspace
+//!
+//! Every `publishDiagnostics` from every server is counted here, by file,
+//! severity and source, so `lsp_problems_summary` and the
+//! `lsmcp://problems` resource show the state of the whole workspace
+//! without asking the servers again. A server's counts go when its
+//! connection closes. Each change bumps a revision that resource
+//! subscribers watch, so an agent fixing errors sees the counts fall.
+
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::watch;
+
+/// Diagnostics by severity; diagnostics without one aren't counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SeverityCounts {
+    pub error: usize,
+    pub warning: usize,
+    pub information: usize,
+    pub hint: usize,
+}
+
+impl SeverityCounts {
+    pub(crate) fn add(&mut self, severity: Option<DiagnosticSeverity>) {
+        match severity {
+            Some(DiagnosticSeverity::ERROR) => self.error += 1,
+            Some(DiagnosticSeverity::WARNING) => self.warning += 1,
+            Some(DiagnosticSeverity::INFORMATION) => self.information += 1,
+            Some(DiagnosticSeverity::HINT) => self.hint += 1,
+            None | Some(_) => {}
+        }
+    }
+
+    fn merge(&mut self, other: &SeverityCounts) {
+        self.error += other.error;
+        self.warning += other.warning;
+        self.information += other.information;
+        self.hint += other.hint;
+    }
+
+    pub fn total(&self) -> usize {
+        self.error + self.warning + self.information + self.hint
+    }
+}
+
+/// One file's problems
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileProblems {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub counts: SeverityCounts,
+}
+
+/// The workspace's problems
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProblemsSummary {
+    pub total: SeverityCounts,
+    /// By diagnostic source (the server's name for diagnostics without one)
+    pub by_source: BTreeMap<String, SeverityCounts>,
+    /// Files with problems, most errors first
+    pub files: Vec<FileProblems>,
+}
+
+/// What a server last published for a file
+struct Published {
+    server: String,
+    /// (severity, source) of each diagnostic
+    diagnostics: Vec<(Option<DiagnosticSeverity>, Option<String>)>,
+}
+
+/// Diagnostics published by every server, by client instance and file
+pub struct Problems {
+    published: Mutex<HashMap<(u64, PathBuf), Published>>,
+    revision: watch::Sender<u64>,
+}
+
+impl Default for Problems {
+    fn default() -> Self {
+        Self {
+            published: Mutex::default(),
+            revision: watch::Sender::new(0),
+        }
+    }
+}
+
+/// The process-wide problems
+pub fn global() -> &'static Problems {
+    static PROBLEMS: OnceLock<Problems> = OnceLock::new();
+    PROBLEMS.get_or_init(Problems::default)
+}
+
+impl Problems {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(u64, PathBuf), Published>> {
+        self.published.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record what `server` (the client `instance`) published for `path`
+    pub fn publish(&self, instance: u64, server: &str, path: &Path, diagnostics: &[Diagnostic]) {
+        let key = (instance, path.to_path_buf());
+        {
+            let mut published = self.lock();
+            if diagnostics.is_empty() {
+                if published.remove(&key).is_none() {
+                    return;
+                }
+            } else {
+                published.insert(
+                    key,
+                    Published {
+                        server: server.to_string(),
+                        diagnostics: diagnostics
+                            .iter()
+                            .map(|d| (d.severity, d.source.clone()))
+                            .collect(),
+                    },
+                );
+            }
+        }
+        self.revision.send_modify(|revision| *revision += 1);
+    }
+
+    /// Drop what the client `instance` published, once it is gone
+    pub fn forget(&self, instance: u64) {
+        let removed = {
+            let mut published = self.lock();
+            let before = published.len();
+            published.retain(|(client, _), _| *client != instance);
+            before != published.len()
+        };
+        if removed {
+            self.revision.send_modify(|revision| *revision += 1);
+        }
+    }
+
+    /// A receiver marked changed whenever the problems change
+    pub fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+
+    /// The problems of every file, or those under `root`
+    pub fn summary(&self, root: Option<&Path>) -> ProblemsSummary {
+        let mut summary = ProblemsSummary::default();
+        let mut files: BTreeMap<PathBuf, SeverityCounts> = BTreeMap::new();
+        for ((_, path), published) in self.lock().iter() {
+            if root.is_some_and(|root| !path.starts_with(root)) {
+                continue;
+            }
+            let file = files.entry(path.clone()).or_default();
+            for (severity, source) in &published.diagnostics {
+                file.add(*severity);
+                summary
+                    .by_source
+                    .entry(source.clone().unwrap_or_else(|| published.server.clone()))
+                    .or_default()
+                    .add(*severity);
+            }
+        }
+        summary.by_source.retain(|_, counts| counts.total() > 0);
+
+        for counts in files.values() {
+            summary.total.merge(counts);
+        }
+        summary.files = files
+            .into_iter()
+            .filter(|(_, counts)| counts.total() > 0)
+            .map(|(path, counts)| FileProblems { path, counts })
+            .collect();
+        // Stable for files with the same counts: they stay in path order
+        summary.files.sort_by(|a, b| {
+            (b.counts.error, b.counts.warning).cmp(&(a.counts.error, a.counts.warning))
+        });
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Range;
+
+    fn diagnostic(severity: DiagnosticSeverity, source: Option<&str>) -> Diagnostic {
+        Diagnostic {
+            severity: Some(severity),
+            source: source.map(str::to_string),
+            ..Diagnostic::new_simple(Range::default(), "problem".to_string())
+        }
+    }
+
+    #[test]
+    fn test_problems_summary() {
+        let problems = Problems::default();
+        let mut changes = problems.watch();
+        let main = Path::new("/app/src/main.rs");
+        let lib = Path::new("/app/src/lib.rs");
+
+        problems.publish(
+            1,
+            "rust-analyzer",
+            main,
+            &[
+                diagnostic(DiagnosticSeverity::WARNING, Some("clippy")),
+                diagnostic(DiagnosticSeverity::WARNING, None),
+            ],
+        );
+        problems.publish(
+            1,
+            "rust-analyzer",
+            lib,
+            &[diagnostic(DiagnosticSeverity::ERROR, Some("rustc"))],
+        );
+        problems.publish(
+            2,
+            "eslint",
+            Path::new("/other/app.js"),
+            &[diagnostic(DiagnosticSeverity::HINT, None)],
+        );
+        assert!(changes.has_changed().unwrap());
+
+        let summary = problems.summary(Some(Path::new("/app")));
+        assert_eq!((summary.total.error, summary.total.warning), (1, 2));
+        assert_eq!(summary.files.len(), 2);
+        assert_eq!(summary.files[0].path, lib);
+        assert_eq!(summary.by_source["clippy"].warning, 1);
+        assert_eq!(summary.by_source["rust-analyzer"].warning, 1);
+        assert_eq!(problems.summary(None).total.hint, 1);
+
+        // Fixes clear a file, and a stopped server's problems go with it
+        changes.mark_unchanged();
+        problems.publish(1, "rust-analyzer", lib, &[]);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(problems.summary(None).total.error, 0);
+        problems.forget(1);
+        assert_eq!(problems.summary(None).files.len(), 1);
+    }
+}
@@ -1 +1,205 @@
 //! Process spawning and management
+//!
+//! Starts a server and opens the byte streams its client speaks LSP over:
+//! the process's stdio by default, or a TCP socket for servers configured
+//! with `connection = { type = "tcp", ... }`. Servers with a `remote` are
+//! started through `ssh` or `docker exec` (see `lsp::remote`), and local
+//! ones with resource limits through `nice` or `systemd-run` (see
+//! `lsp::resources`).
+
+use crate::config::{Connection, LspPackage};
+use crate::installer::{MissingServer, ServerInstaller};
+use crate::lsp::{remote, resources};
+use crate::types::LspError;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::{Child, ChildStderr, Command};
+use tracing::{debug, info};
+
+/// Delay between attempts to connect to a server that is starting
+const TCP_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A started server: the process (if lsmcp runs one) and the streams to it
+pub struct Transport {
+    pub child: Option<Child>,
+    pub reader: Box<dyn AsyncRead + Unpin + Send>,
+    pub writer: Box<dyn AsyncWrite + Unpin + Send>,
+}
+
+impl Transport {
+    /// Streams to a server lsmcp didn't start, e.g. one run in-process
+    pub fn from_streams(
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        writer: impl AsyncWrite + Unpin + Send + 'static,
+    ) -> Self {
+        Self {
+            child: None,
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+        }
+    }
+}
+
+/// Replace a bare command name in `config` with the binary the installer
+/// finds for it
+///
+/// Paths are left alone, as are servers with nothing to run locally.
+pub fn resolve_command(config: &mut LspPackage) -> Result<(), LspError> {
+    let command = &config.bin.primary;
+    if !config.needs_local_binary() || Path::new(command).components().count() > 1 {
+        return Ok(());
+    }
+    let installer = match ServerInstaller::new() {
+        Ok(installer) => installer,
+        Err(e) => {
+            debug!("Running {} as given: {}", command, e);
+            return Ok(());
+        }
+    };
+    let binary = installer.resolve_binary(config)?;
+    config.bin.primary = binary.to_string_lossy().to_string();
+    Ok(())
+}
+
+/// Start `config`'s server in `cwd` and connect to it
+pub async fn open_transport(config: &LspPackage, cwd: &Path) -> Result<Transport, LspError> {
+    match &config.bin.connection {
+        Connection::Stdio => {
+            let mut child = spawn(config, cwd, Stdio::piped)?;
+            let stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| LspError::ProtocolError("Failed to get stdin".to_string()))?;
+            let stdout = child
+                .stdout
+                .take()
+                .ok_or_else(|| LspError::ProtocolError("Failed to get stdout".to_string()))?;
+
+            Ok(Transport {
+                child: Some(child),
+                reader: Box::new(stdout),
+                writer: Box::new(stdin),
+            })
+        }
+        Connection::Tcp { host, port } => {
+            // No command: the server is run by someone else
+            let mut child = if config.bin.primary.is_empty() {
+                None
+            } else {
+                Some(spawn(config, cwd, Stdio::null)?)
+            };
+            let stream = connect_tcp(config, host, *port, child.as_mut()).await?;
+            info!("Connected to {} at {}:{}", config.name, host, port);
+
+            let (reader, writer) = stream.into_split();
+            Ok(Transport {
+                child,
+                reader: Box::new(reader),
+                writer: Box::new(writer),
+            })
+        }
+    }
+}
+
+/// Spawn the server process, with `io` for its stdin and stdout
+fn spawn(config: &LspPackage, cwd: &Path, io: fn() -> Stdio) -> Result<Child, LspError> {
+    let mut command = match &config.remote {
+        Some(remote) => remote::command(remote, &config.bin, cwd),
+        None => {
+            let launcher = resources::launcher(&config.name, &config.bin.limits);
+            let mut command = match launcher.split_first() {
+                Some((program, args)) => {
+                    let mut command = Command::new(program);
+                    command.args(args).arg(&config.bin.primary);
+                    command
+                }
+                None => Command::new(&config.bin.primary),
+            };
+            command.args(&config.bin.lsp_args).envs(&config.bin.env);
+            command
+        }
+    };
+    let program = command.as_std().get_program().to_string_lossy().to_string();
+    command
+        .current_dir(cwd)
+        .stdin(io())
+        .stdout(io())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| {
+            MissingServer::new(config)
+                .spawn_failed(&program, e)
+                .into_error()
+        })
+        .map(|mut child| {
+            if let Some(stderr) = child.stderr.take() {
+                tokio::spawn(log_stderr(config.name.clone(), stderr));
+            }
+            child
+        })
+}
+
+/// Log what a server writes to stderr until it exits
+///
+/// The pipe is always drained, so a chatty server never blocks on it.
+async fn log_stderr(server: String, stderr: ChildStderr) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if !line.trim().is_empty() {
+            debug!("{} stderr: {}", server, line);
+        }
+    }
+}
+
+/// Connect to a server's port
+///
+/// A server lsmcp just started gets its spawn timeout to start listening,
+/// as long as it keeps running; one run elsewhere must already be listening.
+async fn connect_tcp(
+    config: &LspPackage,
+    host: &str,
+    port: u16,
+    mut child: Option<&mut Child>,
+) -> Result<TcpStream, LspError> {
+    let deadline = tokio::time::Instant::now() + config.bin.spawn_timeout();
+    loop {
+        let error = match TcpStream::connect((host, port)).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => e,
+        };
+
+        let Some(child) = child.as_deref_mut() else {
+            return Err(connection_failed(config, host, port, error));
+        };
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(connection_failed(
+                config,
+                host,
+                port,
+                format!("the server exited ({}) before listening", status),
+            ));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(connection_failed(config, host, port, error));
+        }
+
+        debug!("{} is not listening on {}:{} yet", config.name, host, port);
+        tokio::time::sleep(TCP_RETRY_INTERVAL).await;
+    }
+}
+
+fn connection_failed(
+    config: &LspPackage,
+    host: &str,
+    port: u16,
+    reason: impl std::fmt::Display,
+) -> LspError {
+    LspError::ConnectionFailed(
+        format!("{} at {}:{}", config.name, host, port),
+        reason.to_string(),
+    )
+}
@@ -0,0 +1,138 @@
+//! Work-done progress tracking
+//!
+//! Tracks `$/progress` begin/report/end notifications from a language server
+//! so callers can tell whether it is still indexing.
+
+use lsp_types::{NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// An in-flight progress operation reported by the server
+#[derive(Debug, Clone)]
+pub struct ActiveProgress {
+    pub title: String,
+    pub message: Option<String>,
+    pub percentage: Option<u32>,
+    pub started: Instant,
+}
+
+/// Progress state for a single server
+#[derive(Debug, Default)]
+pub struct ProgressTracker {
+    active: HashMap<String, ActiveProgress>,
+    seen_any: bool,
+    last_activity: Option<Instant>,
+}
+
+impl ProgressTracker {
+    /// Apply a `$/progress` notification
+    pub fn update(&mut self, params: ProgressParams) {
+        let token = token_key(&params.token);
+        let ProgressParamsValue::WorkDone(progress) = params.value;
+
+        self.seen_any = true;
+        self.last_activity = Some(Instant::now());
+
+        match progress {
+            WorkDoneProgress::Begin(begin) => {
+                self.active.insert(
+                    token,
+                    ActiveProgress {
+                        title: begin.title,
+                        message: begin.message,
+                        percentage: begin.percentage,
+                        started: Instant::now(),
+                    },
+                );
+            }
+            WorkDoneProgress::Report(report) => {
+                if let Some(active) = self.active.get_mut(&token) {
+                    if report.message.is_some() {
+                        active.message = report.message;
+                    }
+                    if report.percentage.is_some() {
+                        active.percentage = report.percentage;
+                    }
+                }
+            }
+            WorkDoneProgress::End(_) => {
+                self.active.remove(&token);
+            }
+        }
+    }
+
+    /// Whether any progress operation is currently running
+    pub fn is_busy(&self) -> bool {
+        !self.active.is_empty()
+    }
+
+    /// Whether the server has ever reported progress
+    pub fn seen_any(&self) -> bool {
+        self.seen_any
+    }
+
+    /// Time of the last progress notification, if any
+    pub fn last_activity(&self) -> Option<Instant> {
+        self.last_activity
+    }
+
+    /// Currently running progress operations
+    pub fn active(&self) -> Vec<ActiveProgress> {
+        self.active.values().cloned().collect()
+    }
+}
+
+fn token_key(token: &NumberOrString) -> String {
+    match token {
+        NumberOrString::Number(n) => n.to_string(),
+        NumberOrString::String(s) => s.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport};
+
+    fn params(token: &str, progress: WorkDoneProgress) -> ProgressParams {
+        ProgressParams {
+            token: NumberOrString::String(token.to_string()),
+            value: ProgressParamsValue::WorkDone(progress),
+        }
+    }
+
+    #[test]
+    fn test_begin_report_end() {
+        let mut tracker = ProgressTracker::default();
+        assert!(!tracker.is_busy());
+        assert!(!tracker.seen_any());
+
+        tracker.update(params(
+            "index",
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Indexing".to_string(),
+                ..Default::default()
+            }),
+        ));
+        assert!(tracker.is_busy());
+
+        tracker.update(params(
+            "index",
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                message: Some("42/100".to_string()),
+                percentage: Some(42),
+                ..Default::default()
+            }),
+        ));
+        let active = tracker.active();
+        assert_eq!(active[0].title, "Indexing");
+        assert_eq!(active[0].percentage, Some(42));
+
+        tracker.update(params(
+            "index",
+            WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+        ));
+        assert!(!tracker.is_busy());
+        assert!(tracker.seen_any());
+    }
+}
@@ -0,0 +1,194 @@
+//! Project root discovery
+//!
+//! Finds the nearest project marker above a file so monorepos with several
+//! Cargo workspaces, Go modules, npm packages or Python projects get one
+//! server per project.
+
+use std::path::{Path, PathBuf};
+
+/// Files that mark the root of a project
+pub const PROJECT_MARKERS: &[&str] = &[
+    "Cargo.toml",
+    "go.mod",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+];
+
+/// Find the project root for a file, bounded by the workspace root
+///
+/// Walks up from the file's directory to the nearest directory containing a
+/// project marker. Cargo workspace members resolve to the enclosing
+/// `[workspace]` manifest, Go modules to an enclosing `go.work` and npm
+/// packages to an enclosing pnpm, npm or Yarn workspace, so a single server
+/// covers the whole workspace. Python's src layout needs nothing special:
+/// `src/` has no marker, so its files resolve to the project above it.
+/// Falls back to `workspace_root`.
+pub fn find_project_root(file: &Path, workspace_root: &Path) -> PathBuf {
+    let start = if file.is_dir() {
+        file
+    } else {
+        match file.parent() {
+            Some(parent) => parent,
+            None => return workspace_root.to_path_buf(),
+        }
+    };
+
+    if !start.starts_with(workspace_root) {
+        return workspace_root.to_path_buf();
+    }
+
+    let mut dir = start;
+    loop {
+        if let Some(marker) = PROJECT_MARKERS.iter().find(|m| dir.join(m).is_file()) {
+            return match *marker {
+                "Cargo.toml" => find_enclosing(dir, workspace_root, |d| {
+                    std::fs::read_to_string(d.join("Cargo.toml"))
+                        .map(|c| c.lines().any(|l| l.trim() == "[workspace]"))
+                        .unwrap_or(false)
+                }),
+                "go.mod" => find_enclosing(dir, workspace_root, |d| d.join("go.work").is_file()),
+                "package.json" => find_enclosing(dir, workspace_root, is_js_workspace),
+                _ => dir.to_path_buf(),
+            };
+        }
+
+        if dir == workspace_root {
+            break;
+        }
+
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => break,
+        }
+    }
+
+    workspace_root.to_path_buf()
+}
+
+/// Whether `dir` is the root of a pnpm workspace, or of an npm or Yarn one
+/// (`workspaces` in its `package.json`)
+fn is_js_workspace(dir: &Path) -> bool {
+    dir.join("pnpm-workspace.yaml").is_file()
+        || std::fs::read_to_string(dir.join("package.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+            .is_some_and(|package| package.get("workspaces").is_some())
+}
+
+/// Find the outermost ancestor of `dir` (inclusive, within the workspace)
+/// satisfying `is_root`, or `dir` itself if none does
+fn find_enclosing(dir: &Path, workspace_root: &Path, is_root: impl Fn(&Path) -> bool) -> PathBuf {
+    let mut found = dir.to_path_buf();
+    let mut current = dir;
+
+    loop {
+        if is_root(current) {
+            found = current.to_path_buf();
+        }
+
+        if current == workspace_root {
+            break;
+        }
+
+        match current.parent() {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_nearest_marker() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("services/api/src")).unwrap();
+        fs::create_dir_all(root.join("services/web/src")).unwrap();
+        fs::write(root.join("services/api/go.mod"), "module api").unwrap();
+        fs::write(root.join("services/web/package.json"), "{}").unwrap();
+
+        let api = find_project_root(&root.join("services/api/src/main.go"), root);
+        assert_eq!(api, root.join("services/api"));
+
+        let web = find_project_root(&root.join("services/web/src/index.ts"), root);
+        assert_eq!(web, root.join("services/web"));
+    }
+
+    #[test]
+    fn test_cargo_workspace_member_resolves_to_workspace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("crates/core/src")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("crates/core/Cargo.toml"),
+            "[package]\nname = \"core\"\n",
+        )
+        .unwrap();
+
+        let project = find_project_root(&root.join("crates/core/src/lib.rs"), root);
+        assert_eq!(project, root);
+    }
+
+    #[test]
+    fn test_js_workspace_packages_resolve_to_workspace() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for (workspace, manifest) in [
+            ("pnpm", "{}"),
+            ("npm", r#"{ "workspaces": ["packages/*"] }"#),
+        ] {
+            let dir = root.join(workspace);
+            fs::create_dir_all(dir.join("packages/ui/src")).unwrap();
+            fs::write(dir.join("package.json"), manifest).unwrap();
+            fs::write(dir.join("packages/ui/package.json"), "{}").unwrap();
+        }
+        fs::write(
+            root.join("pnpm/pnpm-workspace.yaml"),
+            "packages: ['packages/*']",
+        )
+        .unwrap();
+
+        for workspace in ["pnpm", "npm"] {
+            let dir = root.join(workspace);
+            let project = find_project_root(&dir.join("packages/ui/src/index.ts"), root);
+            assert_eq!(project, dir);
+        }
+    }
+
+    #[test]
+    fn test_python_src_layout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("libs/core/src/core")).unwrap();
+        fs::write(root.join("libs/core/setup.cfg"), "[metadata]").unwrap();
+
+        let project = find_project_root(&root.join("libs/core/src/core/__init__.py"), root);
+        assert_eq!(project, root.join("libs/core"));
+    }
+
+    #[test]
+    fn test_falls_back_to_workspace_root() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("scripts")).unwrap();
+
+        let project = find_project_root(&root.join("scripts/build.py"), root);
+        assert_eq!(project, root);
+
+        let outside = find_project_root(Path::new("/somewhere/else.rs"), root);
+        assert_eq!(outside, root);
+    }
+}
@@ -0,0 +1,194 @@
+//! Capabilities a server registers at runtime (`client/registerCapability`)
+//!
+//! Servers such as gopls and rust-analyzer register file watchers and
+//! other features after `initialize` instead of in their capabilities.
+//! Registrations are kept per client until the server unregisters them;
+//! registered file watchers drive the polling in `lsp/file_watcher.rs`.
+
+use crate::utils::glob::{expand_braces, path_glob_match};
+use lsp_types::{
+    DidChangeWatchedFilesRegistrationOptions, GlobPattern, OneOf, Registration, RegistrationParams,
+    UnregistrationParams, WatchKind,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// The method file watchers are registered for
+pub const WATCHED_FILES_METHOD: &str = "workspace/didChangeWatchedFiles";
+
+/// A server's current dynamic registrations, by id
+#[derive(Debug, Default)]
+pub struct Registrations {
+    by_id: HashMap<String, Registration>,
+}
+
+impl Registrations {
+    pub fn register(&mut self, params: RegistrationParams) {
+        for registration in params.registrations {
+            debug!(
+                "Server registered {} ({})",
+                registration.method, registration.id
+            );
+            self.by_id.insert(registration.id.clone(), registration);
+        }
+    }
+
+    pub fn unregister(&mut self, params: UnregistrationParams) {
+        for unregistration in params.unregisterations {
+            debug!(
+                "Server unregistered {} ({})",
+                unregistration.method, unregistration.id
+            );
+            self.by_id.remove(&unregistration.id);
+        }
+    }
+
+    /// Whether some registration is for `method`
+    pub fn has(&self, method: &str) -> bool {
+        self.by_id.values().any(|r| r.method == method)
+    }
+
+    /// Registered methods, sorted and without duplicates
+    pub fn methods(&self) -> Vec<String> {
+        let mut methods: Vec<String> = self.by_id.values().map(|r| r.method.clone()).collect();
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+
+    /// The file watchers of every watched-files registration, with
+    /// relative patterns resolved against `root`
+    pub fn file_watchers(&self, root: &Path) -> Vec<FileWatcher> {
+        self.by_id
+            .values()
+            .filter(|r| r.method == WATCHED_FILES_METHOD)
+            .filter_map(|r| {
+                let options = r.register_options.clone()?;
+                match serde_json::from_value::<DidChangeWatchedFilesRegistrationOptions>(options) {
+                    Ok(options) => Some(options.watchers),
+                    Err(e) => {
+                        warn!("Ignoring malformed file watchers ({}): {}", r.id, e);
+                        None
+                    }
+                }
+            })
+            .flatten()
+            .filter_map(|watcher| FileWatcher::new(watcher.glob_pattern, watcher.kind, root))
+            .collect()
+    }
+}
+
+/// A glob a server wants file events for
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileWatcher {
+    /// Directory the patterns are relative to
+    base: PathBuf,
+    /// The pattern's `{a,b}` alternatives
+    patterns: Vec<String>,
+    kind: WatchKind,
+}
+
+impl FileWatcher {
+    fn new(glob: GlobPattern, kind: Option<WatchKind>, root: &Path) -> Option<Self> {
+        let (base, pattern) = match glob {
+            GlobPattern::String(pattern) => (root.to_path_buf(), pattern),
+            GlobPattern::Relative(relative) => {
+                let uri = match relative.base_uri {
+                    OneOf::Left(folder) => folder.uri,
+                    OneOf::Right(uri) => uri,
+                };
+                (uri.to_file_path().ok()?, relative.pattern)
+            }
+        };
+        Some(Self {
+            base,
+            patterns: expand_braces(&pattern),
+            kind: kind.unwrap_or(WatchKind::all()),
+        })
+    }
+
+    /// Whether events of `kind` for `path` are wanted
+    pub fn wants(&self, path: &Path, kind: WatchKind) -> bool {
+        self.kind.contains(kind) && self.matches(path)
+    }
+
+    /// Whether `path` matches the pattern, whatever the event
+    pub fn matches(&self, path: &Path) -> bool {
+        // Absolute patterns match the whole path, others only paths below
+        // the base
+        let relative = path
+            .strip_prefix(&self.base)
+            .ok()
+            .map(|relative| relative.to_string_lossy());
+        let absolute = path.to_string_lossy();
+        self.patterns.iter().any(|pattern| {
+            if pattern.starts_with('/') {
+                path_glob_match(pattern, &absolute)
+            } else {
+                relative
+                    .as_ref()
+                    .is_some_and(|relative| path_glob_match(pattern, relative))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn register(registrations: &mut Registrations, id: &str, method: &str, options: Value) {
+        registrations.register(RegistrationParams {
+            registrations: vec![Registration {
+                id: id.to_string(),
+                method: method.to_string(),
+                register_options: Some(options),
+            }],
+        });
+    }
+
+    #[test]
+    fn test_file_watcher_registrations() {
+        let root = Path::new("/ws");
+        let mut registrations = Registrations::default();
+        register(
+            &mut registrations,
+            "1",
+            WATCHED_FILES_METHOD,
+            json!({ "watchers": [
+                { "globPattern": "**/*.{go,mod}" },
+                { "globPattern": { "baseUri": "file:///ws/sub", "pattern": "*.txt" }, "kind": 4 },
+            ]}),
+        );
+        register(
+            &mut registrations,
+            "2",
+            "textDocument/formatting",
+            json!({}),
+        );
+
+        assert_eq!(
+            registrations.methods(),
+            ["textDocument/formatting", WATCHED_FILES_METHOD]
+        );
+        let watchers = registrations.file_watchers(root);
+        assert_eq!(watchers.len(), 2);
+        let wants = |path: &str, kind| watchers.iter().any(|w| w.wants(Path::new(path), kind));
+        assert!(wants("/ws/pkg/main.go", WatchKind::Change));
+        assert!(wants("/ws/go.mod", WatchKind::Create));
+        assert!(!wants("/ws/README.md", WatchKind::Change));
+        assert!(wants("/ws/sub/notes.txt", WatchKind::Delete));
+        assert!(!wants("/ws/sub/notes.txt", WatchKind::Change));
+
+        registrations.unregister(UnregistrationParams {
+            unregisterations: vec![lsp_types::Unregistration {
+                id: "1".to_string(),
+                method: WATCHED_FILES_METHOD.to_string(),
+            }],
+        });
+        assert!(!registrations.has(WATCHED_FILES_METHOD));
+        assert!(registrations.file_watchers(root).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Servers run over SSH or in a container
+//!
+//! A remote server sees the workspace where it is mounted on its side, so
+//! every message crossing the boundary has its paths translated: local paths
+//! and `file://` URIs to remote ones on the way out, and back on the way in.
+
+use crate::config::{BinaryConfig, Remote};
+use crate::utils::uri::PathMap;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Local to remote path translation, or `None` when paths are the same on
+/// both sides
+pub fn path_map(remote: &Remote) -> Option<PathMap> {
+    PathMap::new(
+        remote
+            .path_map()
+            .iter()
+            .map(|(local, remote)| (local.clone(), remote.clone())),
+    )
+}
+
+/// The command running `bin` on `remote`, in the remote counterpart of `cwd`
+pub fn command(remote: &Remote, bin: &BinaryConfig, cwd: &Path) -> Command {
+    let remote_cwd = match path_map(remote) {
+        Some(map) => map.map_path(cwd),
+        None => Some(cwd.to_path_buf()),
+    };
+
+    match remote {
+        Remote::Docker {
+            container, options, ..
+        } => {
+            let mut command = Command::new("docker");
+            command.args(["exec", "-i"]);
+            if let Some(cwd) = &remote_cwd {
+                command.arg("-w").arg(cwd);
+            }
+            for (key, value) in &bin.env {
+                command.arg("-e").arg(format!("{}={}", key, value));
+            }
+            command
+                .args(options)
+                .arg(container)
+                .arg(&bin.primary)
+                .args(&bin.lsp_args);
+            command
+        }
+        Remote::Ssh { host, options, .. } => {
+            // ssh hands the remote shell one command line
+            let mut script = String::new();
+            if let Some(cwd) = &remote_cwd {
+                script.push_str(&format!("cd {} && ", shell_quote(&cwd.to_string_lossy())));
+            }
+            script.push_str("exec");
+            if !bin.env.is_empty() {
+                script.push_str(" env");
+                for (key, value) in &bin.env {
+                    script.push(' ');
+                    script.push_str(&shell_quote(&format!("{}={}", key, value)));
+                }
+            }
+            for arg in std::iter::once(&bin.primary).chain(&bin.lsp_args) {
+                script.push(' ');
+                script.push_str(&shell_quote(arg));
+            }
+
+            let mut command = Command::new("ssh");
+            command.arg("-T").args(options).arg(host).arg(script);
+            command
+        }
+    }
+}
+
+/// `s` quoted for a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn docker(path_map: &[(&str, &str)]) -> Remote {
+        Remote::Docker {
+            container: "dev".to_string(),
+            options: Vec::new(),
+            path_map: path_map
+                .iter()
+                .map(|(local, remote)| (PathBuf::from(local), PathBuf::from(remote)))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_remote_commands() {
+        let bin = BinaryConfig {
+            primary: "rust-analyzer".to_string(),
+            additional: Vec::new(),
+            lsp_args: vec!["--log".to_string(), "it's".to_string()],
+            env: [("RUST_LOG".to_string(), "info".to_string())].into(),
+            cwd: None,
+            connection: Default::default(),
+            spawn_timeout_secs: None,
+            initialize_timeout_secs: None,
+            limits: Default::default(),
+            setup: Vec::new(),
+        };
+        let args = |command: Command| -> Vec<String> {
+            let command = command.as_std();
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect()
+        };
+
+        let remote = docker(&[("/home/me/app", "/workspaces/app")]);
+        assert_eq!(
+            args(command(&remote, &bin, Path::new("/home/me/app"))),
+            [
+                "docker",
+                "exec",
+                "-i",
+                "-w",
+                "/workspaces/app",
+                "-e",
+                "RUST_LOG=info",
+                "dev",
+                "rust-analyzer",
+                "--log",
+                "it's"
+            ]
+        );
+
+        let remote = Remote::Ssh {
+            host: "build".to_string(),
+            options: vec!["-p".to_string(), "2222".to_string()],
+            path_map: BTreeMap::new(),
+        };
+        assert_eq!(
+            args(command(&remote, &bin, Path::new("/src/app"))),
+            [
+                "ssh",
+                "-T",
+                "-p",
+                "2222",
+                "build",
+                r"cd '/src/app' && exec env 'RUST_LOG=info' 'rust-analyzer' '--log' 'it'\''s'"
+            ]
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Memory and CPU guardrails for server processes
+//!
+//! `nice` and a systemd scope are applied by launching the server through
+//! `nice -n` and `systemd-run --user --scope`, the way remote servers are
+//! launched through `ssh` or `docker exec`. Memory use is sampled with `ps`
+//! for the manager's watchdog, which restarts servers that outgrow
+//! `max_memory_mb`: language servers such as typescript-language-server
+//! spread over child processes, so a server's whole process tree counts.
+
+use crate::config::ResourceLimits;
+use std::collections::HashMap;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+/// The command to launch a server through to apply `limits`, empty when
+/// it runs directly
+pub fn launcher(server: &str, limits: &ResourceLimits) -> Vec<String> {
+    let mut launcher = Vec::new();
+
+    if limits.cgroup == Some(true) {
+        if cfg!(target_os = "linux") {
+            launcher.extend(["systemd-run", "--user", "--scope", "--quiet"].map(String::from));
+            if let Some(mb) = limits.max_memory_mb {
+                launcher.extend(["-p".to_string(), format!("MemoryMax={}M", mb)]);
+            }
+            if let Some(percent) = limits.max_cpu_percent {
+                launcher.extend(["-p".to_string(), format!("CPUQuota={}%", percent)]);
+            }
+            launcher.push("--".to_string());
+        } else {
+            warn!(
+                "Ignoring `cgroup` for {}: systemd scopes are only available on Linux",
+                server
+            );
+        }
+    } else if limits.max_cpu_percent.is_some() {
+        warn!(
+            "Ignoring `max_cpu_percent` for {}: it needs `cgroup = true`",
+            server
+        );
+    }
+
+    if let Some(nice) = limits.nice {
+        if cfg!(unix) {
+            launcher.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        } else {
+            warn!(
+                "Ignoring `nice` for {}: not supported on this platform",
+                server
+            );
+        }
+    }
+
+    launcher
+}
+
+/// Parent and resident memory of every process, from one `ps` run
+#[derive(Debug, Default)]
+pub struct ProcessTable {
+    /// pid to (parent pid, resident bytes)
+    processes: HashMap<u32, (u32, u64)>,
+}
+
+impl ProcessTable {
+    /// Sample the running processes (`None` where `ps` isn't available)
+    pub async fn sample() -> Option<Self> {
+        let output = Command::new("ps")
+            .args(["-A", "-o", "pid=", "-o", "ppid=", "-o", "rss="])
+            .output()
+            .await;
+        match output {
+            Ok(output) if output.status.success() => {
+                Some(Self::parse(&String::from_utf8_lossy(&output.stdout)))
+            }
+            Ok(output) => {
+                debug!("ps failed: {}", String::from_utf8_lossy(&output.stderr));
+                None
+            }
+            Err(e) => {
+                debug!("Failed to run ps: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Parse `ps -o pid=,ppid=,rss=` output (rss in KiB)
+    fn parse(output: &str) -> Self {
+        let processes = output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().map(str::parse::<u64>);
+                let (Some(Ok(pid)), Some(Ok(ppid)), Some(Ok(rss))) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return None;
+                };
+                Some((u32::try_from(pid).ok()?, (ppid as u32, rss * 1024)))
+            })
+            .collect();
+        Self { processes }
+    }
+
+    /// Resident memory of `pid` and all its descendants, in bytes
+    pub fn tree_rss(&self, pid: u32) -> u64 {
+        self.tree(pid)
+            .iter()
+            .filter_map(|pid| self.processes.get(pid))
+            .map(|(_, rss)| rss)
+            .sum()
+    }
+
+    /// `pid` and all its descendants, parents first
+    pub fn tree(&self, pid: u32) -> Vec<u32> {
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (&child, &(parent, _)) in &self.processes {
+            children.entry(parent).or_default().push(child);
+        }
+
+        let mut tree = Vec::new();
+        let mut queue = std::collections::VecDeque::from([pid]);
+        while let Some(pid) = queue.pop_front() {
+            tree.push(pid);
+            if let Some(descendants) = children.get(&pid) {
+                queue.extend(descendants.iter().filter(|&&child| child != pid));
+            }
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launcher() {
+        assert!(launcher("ts", &ResourceLimits::default()).is_empty());
+
+        let limits = ResourceLimits {
+            max_memory_mb: Some(4096),
+            nice: Some(10),
+            cgroup: Some(true),
+            max_cpu_percent: Some(200),
+            max_concurrent_requests: Some(4),
+        };
+        if cfg!(target_os = "linux") {
+            assert_eq!(
+                launcher("ts", &limits).join(" "),
+                "systemd-run --user --scope --quiet -p MemoryMax=4096M -p CPUQuota=200% -- nice -n 10"
+            );
+        }
+
+        // Memory alone is enforced by the watchdog
+        let limits = ResourceLimits {
+            max_memory_mb: Some(4096),
+            ..Default::default()
+        };
+        assert!(launcher("ts", &limits).is_empty());
+    }
+
+    #[test]
+    fn test_tree_rss() {
+        let table = ProcessTable::parse(
+            "    1     0   100\n  200     1  1000\n  201   200  2000\n  202   201   500\n  300     1  9999\nbogus line\n",
+        );
+        assert_eq!(table.tree_rss(200), 3500 * 1024);
+        assert_eq!(table.tree_rss(202), 500 * 1024);
+        assert_eq!(table.tree_rss(12345), 0);
+        assert_eq!(table.tree(201), [201, 202]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Runnable tests and binaries
+//!
+//! rust-analyzer lists them with its `experimental/runnables` request, and
+//! gopls attaches them to code lenses (`gopls.run_tests`, enabled by the
+//! `test` code lens setting). Both become a `Runnable` carrying the exact
+//! command that runs it.
+
+use lsp_types::{CodeLens, Location, Range, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A test, benchmark or binary a server knows how to run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Runnable {
+    /// e.g. `test tests::test_parse` or `run lsmcp`
+    pub label: String,
+    /// Where it is defined
+    pub location: Option<Location>,
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    /// Extra environment variables
+    pub env: BTreeMap<String, String>,
+}
+
+impl Runnable {
+    /// The command as a shell would take it
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| shell_word(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// `arg`, quoted if a shell would otherwise split or expand it
+fn shell_word(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    match plain {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', r"'\''")),
+    }
+}
+
+/// One entry of rust-analyzer's `experimental/runnables` response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RustAnalyzerRunnable {
+    label: String,
+    location: Option<RustAnalyzerLocation>,
+    #[serde(default)]
+    kind: String,
+    args: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RustAnalyzerLocation {
+    target_uri: Url,
+    target_range: Range,
+}
+
+/// `kind = "cargo"` arguments
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CargoArgs {
+    workspace_root: Option<PathBuf>,
+    cwd: Option<PathBuf>,
+    override_cargo: Option<String>,
+    cargo_args: Vec<String>,
+    /// Older rust-analyzers split off some of `cargo_args`
+    cargo_extra_args: Vec<String>,
+    executable_args: Vec<String>,
+    environment: BTreeMap<String, String>,
+}
+
+/// `kind = "shell"` arguments
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ShellArgs {
+    program: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    environment: BTreeMap<String, String>,
+}
+
+/// Runnables from an `experimental/runnables` response; entries of unknown
+/// kinds are skipped
+pub fn from_rust_analyzer(response: Value) -> Vec<Runnable> {
+    let entries: Vec<RustAnalyzerRunnable> = serde_json::from_value(response).unwrap_or_default();
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let location = entry
+                .location
+                .map(|l| Location::new(l.target_uri, l.target_range));
+            match entry.kind.as_str() {
+                "cargo" => {
+                    let args: CargoArgs = serde_json::from_value(entry.args).ok()?;
+                    let mut command: Vec<String> = args
+                        .cargo_args
+                        .into_iter()
+                        .chain(args.cargo_extra_args)
+                        .collect();
+                    if !args.executable_args.is_empty() {
+                        command.push("--".to_string());
+                        command.extend(args.executable_args);
+                    }
+                    Some(Runnable {
+                        label: entry.label,
+                        location,
+                        program: args.override_cargo.unwrap_or_else(|| "cargo".to_string()),
+                        args: command,
+                        cwd: args.cwd.or(args.workspace_root),
+                        env: args.environment,
+                    })
+                }
+                "shell" => {
+                    let args: ShellArgs = serde_json::from_value(entry.args).ok()?;
+                    Some(Runnable {
+                        label: entry.label,
+                        location,
+                        program: args.program,
+                        args: args.args,
+                        cwd: args.cwd,
+                        env: args.environment,
+                    })
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Arguments of gopls' `gopls.run_tests` command
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+struct GoTestArgs {
+    #[serde(rename = "URI")]
+    uri: Option<Url>,
+    tests: Vec<String>,
+    benchmarks: Vec<String>,
+}
+
+/// Runnables from gopls' test code lenses in `file_path`; other lenses are
+/// skipped
+pub fn from_code_lenses(lenses: Vec<CodeLens>, file_path: &Path) -> Vec<Runnable> {
+    lenses
+        .into_iter()
+        .filter_map(|lens| {
+            let command = lens.command?;
+            let args: GoTestArgs = match command.command.as_str() {
+                "gopls.run_tests" => {
+                    serde_json::from_value(command.arguments?.into_iter().next()?).ok()?
+                }
+                // Older gopls: [uri, tests, benchmarks]
+                "gopls.test" => {
+                    let mut arguments = command.arguments?.into_iter();
+                    GoTestArgs {
+                        uri: serde_json::from_value(arguments.next()?).ok(),
+                        tests: serde_json::from_value(arguments.next()?).unwrap_or_default(),
+                        benchmarks: arguments
+                            .next()
+                            .and_then(|b| serde_json::from_value(b).ok())
+                            .unwrap_or_default(),
+                    }
+                }
+                _ => return None,
+            };
+            go_test(args, lens.range, file_path)
+        })
+        .collect()
+}
+
+/// `go test` for the tests and benchmarks of one lens, run in the package's
+/// directory
+fn go_test(lens: GoTestArgs, range: Range, file_path: &Path) -> Option<Runnable> {
+    let pattern = |names: &[String]| match names {
+        [name] => format!("^{}$", name),
+        names => format!("^({})$", names.join("|")),
+    };
+    let label = |kind: &str, names: &[String], plural: &str| match names {
+        [name] => format!("{} {}", kind, name),
+        names => format!("{} {} {}", kind, names.len(), plural),
+    };
+
+    let (label, mut args) = if !lens.tests.is_empty() {
+        let args = vec!["test".to_string(), "-run".to_string(), pattern(&lens.tests)];
+        (label("test", &lens.tests, "tests"), args)
+    } else if !lens.benchmarks.is_empty() {
+        let args = ["test", "-run", "^$", "-bench"]
+            .map(str::to_string)
+            .into_iter()
+            .chain([pattern(&lens.benchmarks)])
+            .collect();
+        (label("bench", &lens.benchmarks, "benchmarks"), args)
+    } else {
+        return None;
+    };
+    args.push(".".to_string());
+
+    let uri = match lens.uri {
+        Some(uri) => uri,
+        None => Url::from_file_path(file_path).ok()?,
+    };
+    let cwd = uri.to_file_path().ok()?.parent()?.to_path_buf();
+
+    Some(Runnable {
+        label,
+        location: Some(Location::new(uri, range)),
+        program: "go".to_string(),
+        args,
+        cwd: Some(cwd),
+        env: BTreeMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Command, Position};
+
+    #[test]
+    fn test_from_rust_analyzer() {
+        let response = serde_json::json!([
+            {
+                "label": "test tests::test_parse",
+                "location": {
+                    "targetUri": "file:///src/app/src/lib.rs",
+                    "targetRange": { "start": { "line": 10, "character": 4 }, "end": { "line": 14, "character": 5 } },
+                    "targetSelectionRange": { "start": { "line": 11, "character": 7 }, "end": { "line": 11, "character": 17 } }
+                },
+                "kind": "cargo",
+                "args": {
+                    "workspaceRoot": "/src/app",
+                    "cargoArgs": ["test", "--package", "app", "--lib"],
+                    "executableArgs": ["tests::test_parse", "--exact", "--show-output"]
+                }
+            },
+            { "label": "debug", "kind": "lldb", "args": {} }
+        ]);
+
+        let runnables = from_rust_analyzer(response);
+        assert_eq!(runnables.len(), 1);
+        assert_eq!(
+            runnables[0].command_line(),
+            "cargo test --package app --lib -- tests::test_parse --exact --show-output"
+        );
+        assert_eq!(runnables[0].cwd, Some(PathBuf::from("/src/app")));
+        assert_eq!(
+            runnables[0].location.as_ref().unwrap().range.start,
+            Position::new(10, 4)
+        );
+    }
+
+    #[test]
+    fn test_from_gopls_code_lenses() {
+        let lens = |command: &str, arguments: Value| CodeLens {
+            range: Range::new(Position::new(4, 0), Position::new(4, 4)),
+            command: Some(Command {
+                title: "run test".to_string(),
+                command: command.to_string(),
+                arguments: Some(vec![arguments]),
+            }),
+            data: None,
+        };
+        let lenses = vec![
+            lens(
+                "gopls.run_tests",
+                serde_json::json!({ "URI": "file:///src/app/pkg/parse_test.go", "Tests": ["TestParse"] }),
+            ),
+            lens(
+                "gopls.run_tests",
+                serde_json::json!({ "URI": "file:///src/app/pkg/parse_test.go", "Benchmarks": ["BenchmarkA", "BenchmarkB"] }),
+            ),
+            lens("gopls.tidy", serde_json::json!({})),
+        ];
+
+        let runnables = from_code_lenses(lenses, Path::new("/src/app/pkg/parse_test.go"));
+        let commands: Vec<(String, String)> = runnables
+            .iter()
+            .map(|r| (r.label.clone(), r.command_line()))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                (
+                    "test TestParse".to_string(),
+                    "go test -run '^TestParse$' .".to_string()
+                ),
+                (
+                    "bench 2 benchmarks".to_string(),
+                    "go test -run '^$' -bench '^(BenchmarkA|BenchmarkB)$' .".to_string()
+                ),
+            ]
+        );
+        assert_eq!(runnables[0].cwd, Some(PathBuf::from("/src/app/pkg")));
+    }
+}
@@ -0,0 +1,155 @@
+//! Session persistence
+//!
+//! What the servers of a workspace had open is saved to the data directory
+//! while lsmcp runs and on shutdown, and restored on the next start: the
+//! active languages' servers are started again and the documents reopened,
+//! so restarting lsmcp mid-conversation keeps the servers warm. Diagnostics
+//! are saved too and shown for unchanged files until the servers publish
+//! fresh ones.
+
+use crate::types::LspError;
+use lsp_types::Diagnostic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Saved sessions older than this are ignored
+pub const MAX_SESSION_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Most documents reopened at startup
+pub const MAX_RESTORED_DOCUMENTS: usize = 50;
+
+/// What a workspace's servers had open
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub workspace_root: PathBuf,
+    /// Seconds since the Unix epoch
+    pub saved_at: u64,
+    /// Languages with a running server
+    pub languages: Vec<String>,
+    /// Open documents, sorted
+    pub documents: Vec<PathBuf>,
+    /// Last published diagnostics of the open documents by server, as the
+    /// servers sent them
+    #[serde(default)]
+    pub diagnostics: BTreeMap<String, BTreeMap<PathBuf, Vec<Diagnostic>>>,
+}
+
+impl SessionState {
+    /// Whether the state is worth restoring
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty() && self.documents.is_empty()
+    }
+
+    /// `server`'s diagnostics for `path`, if the file hasn't changed since
+    /// they were saved
+    pub fn diagnostics_for(&self, server: &str, path: &Path) -> Option<&Vec<Diagnostic>> {
+        let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        let modified = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        if modified > self.saved_at {
+            return None;
+        }
+        self.diagnostics.get(server)?.get(path)
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// File holding the saved session of `workspace_root` under `data_dir`
+pub fn session_path(data_dir: &Path, workspace_root: &Path) -> PathBuf {
+    data_dir
+        .join("sessions")
+        .join(format!("{}.json", workspace_key(workspace_root)))
+}
+
+/// A short name for `workspace_root`, for files kept per workspace
+pub fn workspace_key(workspace_root: &Path) -> String {
+    Sha256::digest(workspace_root.to_string_lossy().as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The saved session at `path`, unless missing, unreadable, stale or for
+/// another workspace
+pub fn load(path: &Path, workspace_root: &Path) -> Option<SessionState> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let state: SessionState = match serde_json::from_str(&content) {
+        Ok(state) => state,
+        Err(e) => {
+            debug!("Ignoring unreadable session {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let age = Duration::from_secs(now().saturating_sub(state.saved_at));
+    (state.workspace_root == workspace_root && age <= MAX_SESSION_AGE).then_some(state)
+}
+
+/// Write `state` to `path`, replacing it atomically
+pub fn save(path: &Path, state: &SessionState) -> Result<(), LspError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string(state)?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    #[test]
+    fn test_save_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("app");
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join("main.rs");
+        std::fs::write(&file, "fn main() {}\n").unwrap();
+
+        let diagnostic = Diagnostic::new_simple(
+            Range::new(Position::new(0, 3), Position::new(0, 7)),
+            "unused".to_string(),
+        );
+        let state = SessionState {
+            workspace_root: root.clone(),
+            saved_at: now() + 1,
+            languages: vec!["rust".to_string()],
+            documents: vec![file.clone()],
+            diagnostics: BTreeMap::from([(
+                "rust-analyzer".to_string(),
+                BTreeMap::from([(file.clone(), vec![diagnostic])]),
+            )]),
+        };
+        let path = session_path(tmp.path(), &root);
+        save(&path, &state).unwrap();
+
+        let loaded = load(&path, &root).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(
+            loaded.diagnostics_for("rust-analyzer", &file).map(Vec::len),
+            Some(1)
+        );
+        assert!(loaded.diagnostics_for("mock-lint", &file).is_none());
+        assert!(load(&path, &tmp.path().join("other")).is_none());
+
+        // Saved before the file last changed: the diagnostics are stale
+        let stale = SessionState {
+            saved_at: 0,
+            ..state
+        };
+        assert!(stale.diagnostics_for("rust-analyzer", &file).is_none());
+    }
+}
@@ -0,0 +1,79 @@
+//! One gopls for every session
+//!
+//! Each lsmcp session, and each Go project in it, would otherwise run a
+//! gopls of its own, each loading and type-checking the same modules.
+//! Unless `share_gopls_daemon = false`, gopls is started with
+//! `-remote=auto`: the process lsmcp runs only forwards the connection to a
+//! shared gopls daemon, which the first one starts and which exits a minute
+//! after its last client leaves. The daemon logs to
+//! `{cache_dir}/daemon.log`.
+//!
+//! The daemon isn't lsmcp's child, so `limits.max_memory_mb` and
+//! `lsmcp kill-orphans` only see the forwarder. Servers whose `args`
+//! already choose a `-remote`, remote servers and TCP servers are left
+//! alone.
+
+use crate::config::{Connection, LspPackage};
+
+/// Arguments that make gopls forward to the shared daemon
+const SHARED_ARGS: [&str; 2] = ["-remote=auto", "-remote.logfile={cache_dir}/daemon.log"];
+
+/// Start `config`'s server through the shared daemon, if it is gopls
+pub fn apply(config: &mut LspPackage) {
+    let eligible = config.name == "gopls"
+        && config.remote.is_none()
+        && matches!(config.bin.connection, Connection::Stdio)
+        && !config
+            .bin
+            .lsp_args
+            .iter()
+            .any(|arg| arg.starts_with("-remote") || arg.starts_with("--remote"));
+    if eligible {
+        // gopls takes its flags before any subcommand
+        let args = SHARED_ARGS.iter().map(|arg| arg.to_string());
+        config.bin.lsp_args.splice(0..0, args);
+    }
+}
+
+/// Whether `config` runs as a forwarder to a shared daemon
+pub fn is_shared(config: &LspPackage) -> bool {
+    config
+        .bin
+        .lsp_args
+        .iter()
+        .any(|arg| arg == "-remote=auto" || arg.starts_with("-remote=auto;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::get_default_configs;
+
+    #[test]
+    fn test_apply() {
+        let mut gopls = get_default_configs()["go"].clone();
+        gopls.bin.lsp_args = vec!["serve".to_string()];
+        apply(&mut gopls);
+        assert_eq!(
+            gopls.bin.lsp_args,
+            [
+                "-remote=auto",
+                "-remote.logfile={cache_dir}/daemon.log",
+                "serve"
+            ]
+        );
+        assert!(is_shared(&gopls));
+
+        // A remote the user chose is kept
+        let mut gopls = get_default_configs()["go"].clone();
+        gopls.bin.lsp_args = vec!["-remote=unix;/tmp/gopls.sock".to_string()];
+        apply(&mut gopls);
+        assert_eq!(gopls.bin.lsp_args, ["-remote=unix;/tmp/gopls.sock"]);
+        assert!(!is_shared(&gopls));
+
+        let mut pyright = get_default_configs()["python"].clone();
+        let args = pyright.bin.lsp_args.clone();
+        apply(&mut pyright);
+        assert_eq!(pyright.bin.lsp_args, args);
+    }
+}
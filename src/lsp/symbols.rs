@@ -0,0 +1,392 @@
+//! Resolving symbol names to positions
+//!
+//! The by-name tools look a symbol up through document or workspace symbols
+//! and then run a positional query at its name. Symbol ranges often cover a
+//! whole declaration (`pub fn foo() {...}`), so the name itself is located
+//! in the document text before querying.
+
+use crate::utils::position::{convert_character, line_text, PositionEncoding};
+use lsp_types::*;
+use std::path::{Path, PathBuf};
+
+/// A symbol whose name matched a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolMatch {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub container: Option<String>,
+    pub path: PathBuf,
+    /// Range reported by the server, in UTF-8 columns
+    pub range: Range,
+}
+
+impl SymbolMatch {
+    /// Position of the symbol's name, in `encoding` columns
+    ///
+    /// This is where positional queries about the symbol should be made.
+    pub fn position(&self, name: &str, encoding: PositionEncoding) -> Position {
+        let Ok(text) = std::fs::read_to_string(&self.path) else {
+            return self.range.start;
+        };
+        let position = name_position(&text, self.range, name);
+        let character = line_text(&text, position.line).map_or(position.character, |line| {
+            convert_character(line, position.character, PositionEncoding::Utf8, encoding)
+        });
+        Position::new(position.line, character)
+    }
+}
+
+/// Whether a server's symbol name refers to `name`
+///
+/// Servers decorate names differently (`foo(int)` from jdtls, `Foo<T>`,
+/// `impl Foo` blocks), so the name must match up to such a suffix.
+fn name_matches(symbol: &str, name: &str) -> bool {
+    match symbol.strip_prefix(name) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['(', '<', ' ', ':']),
+        None => false,
+    }
+}
+
+/// Whether a symbol's container is `container`
+///
+/// Matches the full container name or its last path segment, so
+/// `Server` matches a container of `crate::mcp::Server`.
+fn container_matches(symbol: Option<&str>, container: Option<&str>) -> bool {
+    let Some(container) = container else {
+        return true;
+    };
+    let Some(symbol) = symbol else {
+        return false;
+    };
+    symbol == container
+        || symbol
+            .rsplit(['.', ':', '/', '\\'])
+            .find(|segment| !segment.is_empty())
+            == Some(container)
+        || symbol
+            .strip_prefix("impl ")
+            .is_some_and(|s| name_matches(s, container))
+}
+
+/// Symbols in a document response named `name`, inside `container` if given
+pub fn document_matches(
+    response: DocumentSymbolResponse,
+    path: PathBuf,
+    name: &str,
+    container: Option<&str>,
+) -> Vec<SymbolMatch> {
+    document_symbol_list(response, path)
+        .into_iter()
+        .filter(|symbol| {
+            name_matches(&symbol.name, name)
+                && container_matches(symbol.container.as_deref(), container)
+        })
+        .collect()
+}
+
+/// Every symbol in a document response, in document order
+pub fn document_symbol_list(response: DocumentSymbolResponse, path: PathBuf) -> Vec<SymbolMatch> {
+    let mut symbols = Vec::new();
+    match response {
+        DocumentSymbolResponse::Flat(flat) => {
+            for symbol in flat {
+                symbols.push(SymbolMatch {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    container: symbol.container_name,
+                    path: path.clone(),
+                    range: symbol.location.range,
+                });
+            }
+        }
+        DocumentSymbolResponse::Nested(nested) => {
+            flatten_nested(&nested, None, &path, &mut symbols);
+        }
+    }
+    symbols
+}
+
+fn flatten_nested(
+    symbols: &[DocumentSymbol],
+    parent: Option<&str>,
+    path: &PathBuf,
+    out: &mut Vec<SymbolMatch>,
+) {
+    for symbol in symbols {
+        out.push(SymbolMatch {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            container: parent.map(String::from),
+            path: path.clone(),
+            // The selection range is the name itself
+            range: symbol.selection_range,
+        });
+        if let Some(children) = &symbol.children {
+            flatten_nested(children, Some(&symbol.name), path, out);
+        }
+    }
+}
+
+/// A symbol whose range contains a position
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnclosingSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// E.g. a function's signature, when the server gives one
+    pub detail: Option<String>,
+    /// The enclosing symbol's name, or the container the server reports
+    pub container: Option<String>,
+    /// The whole declaration
+    pub range: Range,
+    /// The name, or the whole declaration for flat responses
+    pub selection_range: Range,
+}
+
+/// Every symbol in a document response whose range contains `position`,
+/// outermost first: module, then impl, then function
+pub fn enclosing_symbols(
+    response: &DocumentSymbolResponse,
+    position: Position,
+) -> Vec<EnclosingSymbol> {
+    let contains = |range: &Range| range.start <= position && position <= range.end;
+    match response {
+        DocumentSymbolResponse::Flat(symbols) => {
+            let mut chain: Vec<&SymbolInformation> = symbols
+                .iter()
+                .filter(|symbol| contains(&symbol.location.range))
+                .collect();
+            // Outermost: starts first, then ends last
+            chain.sort_by(|a, b| {
+                let (a, b) = (a.location.range, b.location.range);
+                a.start.cmp(&b.start).then(b.end.cmp(&a.end))
+            });
+            chain
+                .into_iter()
+                .map(|symbol| EnclosingSymbol {
+                    name: symbol.name.clone(),
+                    kind: symbol.kind,
+                    detail: None,
+                    container: symbol.container_name.clone(),
+                    range: symbol.location.range,
+                    selection_range: symbol.location.range,
+                })
+                .collect()
+        }
+        DocumentSymbolResponse::Nested(symbols) => {
+            let mut chain: Vec<EnclosingSymbol> = Vec::new();
+            let mut level = symbols.as_slice();
+            while let Some(symbol) = level.iter().find(|s| contains(&s.range)) {
+                chain.push(EnclosingSymbol {
+                    name: symbol.name.clone(),
+                    kind: symbol.kind,
+                    detail: symbol.detail.clone(),
+                    container: chain.last().map(|parent| parent.name.clone()),
+                    range: symbol.range,
+                    selection_range: symbol.selection_range,
+                });
+                level = symbol.children.as_deref().unwrap_or_default();
+            }
+            chain
+        }
+    }
+}
+
+/// The innermost symbol in a document response whose range contains
+/// `position`, e.g. the function a line of text is in
+pub fn enclosing_symbol(
+    response: &DocumentSymbolResponse,
+    path: &Path,
+    position: Position,
+) -> Option<SymbolMatch> {
+    let innermost = enclosing_symbols(response, position).pop()?;
+    Some(SymbolMatch {
+        name: innermost.name,
+        kind: innermost.kind,
+        container: innermost.container,
+        path: path.to_path_buf(),
+        range: innermost.selection_range,
+    })
+}
+
+/// Symbols in a workspace response named `name`, inside `container` if given
+///
+/// Workspace symbol queries are fuzzy, so most hits are dropped here.
+pub fn workspace_matches(
+    response: WorkspaceSymbolResponse,
+    name: &str,
+    container: Option<&str>,
+) -> Vec<SymbolMatch> {
+    workspace_symbol_list(response)
+        .into_iter()
+        .filter(|symbol| {
+            name_matches(&symbol.name, name)
+                && container_matches(symbol.container.as_deref(), container)
+        })
+        .collect()
+}
+
+/// Every symbol in a workspace response
+///
+/// Hits without a range or outside the file system are skipped.
+pub fn workspace_symbol_list(response: WorkspaceSymbolResponse) -> Vec<SymbolMatch> {
+    let candidates: Vec<(String, SymbolKind, Option<String>, Location)> = match response {
+        WorkspaceSymbolResponse::Flat(symbols) => symbols
+            .into_iter()
+            .map(|s| (s.name, s.kind, s.container_name, s.location))
+            .collect(),
+        WorkspaceSymbolResponse::Nested(symbols) => symbols
+            .into_iter()
+            .filter_map(|s| match s.location {
+                OneOf::Left(location) => Some((s.name, s.kind, s.container_name, location)),
+                OneOf::Right(_) => None,
+            })
+            .collect(),
+    };
+
+    candidates
+        .into_iter()
+        .filter_map(|(name, kind, container, location)| {
+            Some(SymbolMatch {
+                name,
+                kind,
+                container,
+                path: location.uri.to_file_path().ok()?,
+                range: location.range,
+            })
+        })
+        .collect()
+}
+
+/// Position of `name` within `range` of `text`, in UTF-8 columns
+///
+/// Falls back to the start of the range when the name isn't found there,
+/// e.g. for symbols whose reported name differs from the source text.
+pub fn name_position(text: &str, range: Range, name: &str) -> Position {
+    for line in range.start.line..=range.end.line {
+        let Some(line_text) = line_text(text, line) else {
+            break;
+        };
+        let start = if line == range.start.line {
+            range.start.character as usize
+        } else {
+            0
+        };
+        let Some(haystack) = line_text.get(start..) else {
+            continue;
+        };
+
+        let found = haystack.match_indices(name).find(|(i, _)| {
+            let before = haystack[..*i].chars().next_back();
+            let after = haystack[i + name.len()..].chars().next();
+            !before.is_some_and(is_identifier_char) && !after.is_some_and(is_identifier_char)
+        });
+        if let Some((i, _)) = found {
+            return Position::new(line, (start + i) as u32);
+        }
+    }
+    range.start
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, line: u32, children: Option<Vec<DocumentSymbol>>) -> DocumentSymbol {
+        #[allow(deprecated)]
+        DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            range: Range::new(Position::new(line, 0), Position::new(line + 2, 1)),
+            selection_range: Range::new(Position::new(line, 3), Position::new(line, 6)),
+            children,
+        }
+    }
+
+    #[test]
+    fn test_document_matches_respect_container() {
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol("new", 0, None),
+            symbol("impl Server", 4, Some(vec![symbol("new", 5, None)])),
+        ]);
+        let path = PathBuf::from("/src/lib.rs");
+
+        let all = document_matches(response.clone(), path.clone(), "new", None);
+        assert_eq!(all.len(), 2);
+
+        let scoped = document_matches(response, path, "new", Some("Server"));
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].range.start, Position::new(5, 3));
+        assert_eq!(scoped[0].container.as_deref(), Some("impl Server"));
+    }
+
+    #[test]
+    fn test_enclosing_symbol() {
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol("new", 0, None),
+            symbol("impl Server", 4, Some(vec![symbol("start", 5, None)])),
+        ]);
+        let path = Path::new("/src/lib.rs");
+        let at = |line, character| {
+            enclosing_symbol(&response, path, Position::new(line, character))
+                .map(|s| (s.name, s.container))
+        };
+
+        assert_eq!(
+            at(5, 4),
+            Some(("start".to_string(), Some("impl Server".to_string())))
+        );
+        assert_eq!(at(4, 0), Some(("impl Server".to_string(), None)));
+        assert_eq!(at(3, 0), None);
+    }
+
+    #[test]
+    fn test_enclosing_symbols() {
+        let response = DocumentSymbolResponse::Nested(vec![symbol(
+            "mod server",
+            0,
+            Some(vec![symbol(
+                "impl Server",
+                0,
+                Some(vec![symbol("start", 1, None)]),
+            )]),
+        )]);
+        let names = |line| {
+            enclosing_symbols(&response, Position::new(line, 0))
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(2), vec!["mod server", "impl Server", "start"]);
+        assert_eq!(names(0), vec!["mod server", "impl Server"]);
+        assert!(names(5).is_empty());
+    }
+
+    #[test]
+    fn test_name_matching() {
+        assert!(name_matches("foo", "foo"));
+        assert!(name_matches("foo(int, String)", "foo"));
+        assert!(!name_matches("foobar", "foo"));
+        assert!(container_matches(
+            Some("crate::mcp::Server"),
+            Some("Server")
+        ));
+        assert!(!container_matches(None, Some("Server")));
+    }
+
+    #[test]
+    fn test_name_position() {
+        let text = "pub fn foo_bar() {}\npub async fn foo() {\n}\n";
+        let range = Range::new(Position::new(1, 0), Position::new(2, 1));
+        assert_eq!(name_position(text, range, "foo"), Position::new(1, 13));
+
+        // Not on the declaration's lines: start of the range
+        assert_eq!(name_position(text, range, "baz"), Position::new(1, 0));
+    }
+}
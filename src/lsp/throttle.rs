@@ -0,0 +1,177 @@
+//! Concurrency limit on requests to a server
+//!
+//! Agents fire queries in parallel, and a server sent fifty hovers at once
+//! (pyright, tsserver) answers all of them slowly. Each client lets at most
+//! `max_concurrent_requests` requests be in flight; the rest wait their
+//! turn, and how many wait and for how long shows in `lsp_server_status`.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Requests in flight per server unless configured otherwise
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Limits a client's requests in flight
+#[derive(Debug)]
+pub struct RequestLimiter {
+    /// `None` when unlimited. Replaced when the limit changes; requests
+    /// holding a permit of the old one finish as they are
+    slots: Mutex<Option<Arc<Semaphore>>>,
+    limit: AtomicUsize,
+    in_flight: Arc<AtomicUsize>,
+    queued: AtomicUsize,
+    peak_queued: AtomicUsize,
+    /// Requests that had to wait, and for how long in all
+    waited: AtomicU64,
+    waited_micros: AtomicU64,
+}
+
+/// A request's turn, held until it is answered
+pub struct RequestSlot {
+    /// How long the request waited for it, if it had to
+    pub waited: Option<Duration>,
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for RequestSlot {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts the current waiter while it waits, even if it gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// How busy a client's request queue is
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// 0 when unlimited
+    pub limit: usize,
+    pub in_flight: usize,
+    pub queued: usize,
+    pub peak_queued: usize,
+    /// Requests that had to wait since the server started
+    pub waited: u64,
+    pub average_wait: Duration,
+}
+
+impl RequestLimiter {
+    /// A limiter letting `limit` requests run at once (0 for no limit)
+    pub fn new(limit: usize) -> Self {
+        Self {
+            slots: Mutex::new(Self::semaphore(limit)),
+            limit: AtomicUsize::new(limit),
+            in_flight: Arc::default(),
+            queued: AtomicUsize::new(0),
+            peak_queued: AtomicUsize::new(0),
+            waited: AtomicU64::new(0),
+            waited_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn semaphore(limit: usize) -> Option<Arc<Semaphore>> {
+        (limit > 0).then(|| Arc::new(Semaphore::new(limit)))
+    }
+
+    /// Change the limit, for requests that start from now on
+    pub fn set_limit(&self, limit: usize) {
+        if self.limit.swap(limit, Ordering::Relaxed) != limit {
+            *self.slots.lock().unwrap_or_else(|e| e.into_inner()) = Self::semaphore(limit);
+        }
+    }
+
+    /// Wait for a turn to send a request
+    pub async fn acquire(&self) -> RequestSlot {
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let (permit, waited) = match slots {
+            None => (None, None),
+            Some(slots) => match Arc::clone(&slots).try_acquire_owned() {
+                Ok(permit) => (Some(permit), None),
+                Err(_) => {
+                    let queued = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+                    let _waiting = Waiting(&self.queued);
+                    self.peak_queued.fetch_max(queued, Ordering::Relaxed);
+                    let started = Instant::now();
+                    // The semaphore is never closed
+                    let permit = slots.acquire_owned().await.ok();
+                    let waited = started.elapsed();
+                    self.waited.fetch_add(1, Ordering::Relaxed);
+                    self.waited_micros
+                        .fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
+                    (permit, Some(waited))
+                }
+            },
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestSlot {
+            waited,
+            _permit: permit,
+            in_flight: Arc::clone(&self.in_flight),
+        }
+    }
+
+    /// How busy the queue is now
+    pub fn stats(&self) -> QueueStats {
+        let waited = self.waited.load(Ordering::Relaxed);
+        let average_wait = match waited {
+            0 => Duration::ZERO,
+            n => Duration::from_micros(self.waited_micros.load(Ordering::Relaxed) / n),
+        };
+        QueueStats {
+            limit: self.limit.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            peak_queued: self.peak_queued.load(Ordering::Relaxed),
+            waited,
+            average_wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_limiter() {
+        let limiter = Arc::new(RequestLimiter::new(2));
+        let first = limiter.acquire().await;
+        let second = limiter.acquire().await;
+
+        let waiter = {
+            let limiter = Arc::clone(&limiter);
+            tokio::spawn(async move {
+                let _slot = limiter.acquire().await;
+            })
+        };
+        while limiter.stats().queued == 0 {
+            tokio::task::yield_now().await;
+        }
+        let stats = limiter.stats();
+        assert_eq!((stats.limit, stats.in_flight, stats.queued), (2, 2, 1));
+
+        drop(first);
+        waiter.await.unwrap();
+        drop(second);
+        let stats = limiter.stats();
+        assert_eq!((stats.in_flight, stats.queued), (0, 0));
+        assert_eq!((stats.peak_queued, stats.waited), (1, 1));
+
+        // Without a limit nothing waits
+        limiter.set_limit(0);
+        let slots: Vec<RequestSlot> =
+            futures::future::join_all((0..10).map(|_| limiter.acquire())).await;
+        assert_eq!(limiter.stats().in_flight, 10);
+        assert_eq!(limiter.stats().waited, 1);
+        drop(slots);
+    }
+}
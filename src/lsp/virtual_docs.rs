@@ -0,0 +1,437 @@
+//! Embedded-language documents for mixed files
+//!
+//! Vue and Svelte components, HTML pages and Markdown files carry code in
+//! other languages: `<script>` and `<style>` blocks, fenced code blocks. A
+//! query at a position inside one of those regions is answered by the
+//! region language's servers, through a virtual document that sits next to
+//! the file (`App.vue` → `App.vue.embedded.ts`) and never touches the disk.
+//!
+//! A virtual document holds one language's regions with everything else
+//! blanked out: whole lines outside the regions become empty, and the rest
+//! of a line a region shares with markup becomes spaces. Lines and columns
+//! therefore stay the same as in the file, so positions need no mapping and
+//! only the virtual document's URI is swapped back for the file's in
+//! responses.
+
+use crate::lsp::overlay;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use url::Url;
+
+/// Marks a virtual document's file name: `<file>.embedded.<extension>`
+const MARKER: &str = ".embedded.";
+
+/// Embedded languages: the names a tag or fence may use, the language they
+/// are routed to and the virtual document's extension
+const LANGUAGES: &[(&[&str], &str, &str)] = &[
+    (&["ts", "typescript", "mts", "cts"], "typescript", "ts"),
+    (&["tsx"], "typescript", "tsx"),
+    (
+        &["js", "javascript", "mjs", "cjs", "module", "ecmascript"],
+        "javascript",
+        "js",
+    ),
+    (&["jsx"], "javascript", "jsx"),
+    (&["css", "postcss"], "css", "css"),
+    (&["scss"], "scss", "scss"),
+    (&["less"], "less", "less"),
+    (&["json", "jsonc"], "json", "json"),
+    (&["py", "python", "python3"], "python", "py"),
+    (&["rs", "rust"], "rust", "rs"),
+    (&["go", "golang"], "go", "go"),
+    (&["c"], "c", "c"),
+    (&["cpp", "c++", "cc", "cxx"], "cpp", "cpp"),
+    (&["java"], "java", "java"),
+    (&["rb", "ruby"], "ruby", "rb"),
+    (&["lua"], "lua", "lua"),
+    (&["sh", "bash", "shell", "zsh"], "bash", "sh"),
+    (&["yaml", "yml"], "yaml", "yaml"),
+    (&["toml"], "toml", "toml"),
+    (&["html"], "html", "html"),
+];
+
+/// File formats that embed other languages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `<script>` and `<style>` blocks (HTML, Vue, Svelte)
+    Markup,
+    /// Fenced code blocks
+    Markdown,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "vue" | "svelte" | "html" | "htm" => Some(Format::Markup),
+        "md" | "markdown" => Some(Format::Markdown),
+        _ => None,
+    }
+}
+
+/// Whether `path` is a format that embeds other languages
+pub fn is_mixed(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+/// The language and extension for a tag's `lang`/`type` or a fence's info
+fn embedded_language(name: &str) -> Option<(&'static str, &'static str)> {
+    let name = name.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(names, _, _)| names.contains(&name.as_str()))
+        .map(|(_, language, extension)| (*language, *extension))
+}
+
+/// A run of one embedded language in a file, as byte offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub language: &'static str,
+    pub extension: &'static str,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The embedded regions of `text`, the content of `path`
+pub fn regions(path: &Path, text: &str) -> Vec<Region> {
+    match format_of(path) {
+        Some(Format::Markup) => markup_regions(text),
+        Some(Format::Markdown) => markdown_regions(text),
+        None => Vec::new(),
+    }
+}
+
+/// `<script>` and `<style>` contents, by their `lang` or `type`
+fn markup_regions(text: &str) -> Vec<Region> {
+    static OPEN: OnceLock<Regex> = OnceLock::new();
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let open = OPEN.get_or_init(|| Regex::new(r"(?i)<(script|style)\b([^>]*)>").unwrap());
+    let attribute = ATTRIBUTE
+        .get_or_init(|| Regex::new(r#"(?i)\b(lang|type)\s*=\s*["']?([^"'\s>]+)"#).unwrap());
+    let lowercase = text.to_ascii_lowercase();
+
+    let mut regions = Vec::new();
+    let mut from = 0;
+    while let Some(captures) = open.captures_at(text, from) {
+        let tag = captures[1].to_ascii_lowercase();
+        let start = captures.get(0).map_or(from, |m| m.end());
+        let end = lowercase[start..]
+            .find(&format!("</{}", tag))
+            .map_or(text.len(), |offset| start + offset);
+        from = end;
+
+        let mut lang = None;
+        let mut kind = None;
+        for attribute in attribute.captures_iter(&captures[2]) {
+            let value = attribute[2].to_string();
+            match attribute[1].to_ascii_lowercase().as_str() {
+                "lang" => lang = Some(value),
+                _ => kind = Some(value),
+            }
+        }
+        // `type="text/typescript"`, `type="application/ld+json"`
+        let kind = kind.map(|kind| {
+            let subtype = kind.rsplit('/').next().unwrap_or(&kind);
+            subtype.rsplit('+').next().unwrap_or(subtype).to_string()
+        });
+        let default = if tag == "script" { "javascript" } else { "css" };
+        let name = lang.or(kind).unwrap_or_else(|| default.to_string());
+        // Templates and other non-code blocks are left to the file's server
+        if let Some((language, extension)) = embedded_language(&name) {
+            if start < end {
+                regions.push(Region {
+                    language,
+                    extension,
+                    start,
+                    end,
+                });
+            }
+        }
+    }
+    regions
+}
+
+/// A fenced code block being read
+#[derive(Clone, Copy)]
+struct OpenFence {
+    fence: char,
+    len: usize,
+    /// Language and extension, when it's one that can be routed
+    language: Option<(&'static str, &'static str)>,
+    /// Where the content starts
+    start: usize,
+}
+
+/// Fenced code block contents, by their info string
+fn markdown_regions(text: &str) -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut open: Option<OpenFence> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let rest = &line[indent..];
+        let fence_char = rest.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_len = fence_char.map_or(0, |c| rest.len() - rest.trim_start_matches(c).len());
+        let is_fence = indent <= 3 && fence_len >= 3;
+
+        match open {
+            None if is_fence => {
+                let info = rest[fence_len..].trim();
+                let name = info
+                    .trim_start_matches('{')
+                    .split(|c: char| c.is_whitespace() || c == ',' || c == '}')
+                    .next()
+                    .unwrap_or("");
+                open = Some(OpenFence {
+                    fence: fence_char.unwrap_or('`'),
+                    len: fence_len,
+                    language: embedded_language(name),
+                    start: offset,
+                });
+            }
+            Some(OpenFence {
+                fence,
+                len,
+                language,
+                start,
+            }) if is_fence
+                && fence_char == Some(fence)
+                && fence_len >= len
+                && rest[fence_len..].trim().is_empty() =>
+            {
+                if let Some((language, extension)) = language {
+                    regions.push(Region {
+                        language,
+                        extension,
+                        start,
+                        end: line_start,
+                    });
+                }
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    // An unclosed fence runs to the end of the file
+    if let Some(OpenFence {
+        language: Some((language, extension)),
+        start,
+        ..
+    }) = open
+    {
+        regions.push(Region {
+            language,
+            extension,
+            start,
+            end: text.len(),
+        });
+    }
+    regions
+}
+
+/// `text` with everything outside `regions` blanked out, keeping lines and
+/// columns (trailing blanks are dropped)
+fn blank_outside(text: &str, regions: &[&Region]) -> String {
+    let inside = |offset: usize| {
+        regions
+            .iter()
+            .any(|region| region.start <= offset && offset < region.end)
+    };
+    let mut blanked = String::with_capacity(text.len());
+    let mut line = String::new();
+    // Length of `line` up to its last kept character
+    let mut kept = 0;
+    for (offset, c) in text.char_indices() {
+        if c == '\n' {
+            blanked.push_str(&line[..kept]);
+            blanked.push('\n');
+            line.clear();
+            kept = 0;
+        } else if inside(offset) {
+            line.push(c);
+            kept = line.len();
+        } else {
+            line.push(if c == '\t' { '\t' } else { ' ' });
+        }
+    }
+    blanked.push_str(&line[..kept]);
+    blanked
+}
+
+/// The byte offset of `line`/`character` in `text`, counting characters as
+/// `char`s (regions start and end at ASCII markup, where that's exact)
+fn offset_of(text: &str, line: u32, character: u32) -> Option<usize> {
+    let line_start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(line as usize - 1)?.0 + 1
+    };
+    let line_text = text[line_start..].split('\n').next().unwrap_or("");
+    let column = line_text
+        .char_indices()
+        .nth(character as usize)
+        .map_or(line_text.len(), |(offset, _)| offset);
+    Some(line_start + column)
+}
+
+/// One language's regions of a mixed file, as a virtual document
+#[derive(Debug, Clone)]
+pub struct EmbeddedDocument {
+    source: PathBuf,
+    path: PathBuf,
+    language: &'static str,
+}
+
+impl EmbeddedDocument {
+    fn new(source: &Path, region: &Region) -> Self {
+        let mut name = source.file_name().unwrap_or_default().to_os_string();
+        name.push(MARKER);
+        name.push(region.extension);
+        Self {
+            source: source.to_path_buf(),
+            path: source.with_file_name(name),
+            language: region.language,
+        }
+    }
+
+    /// The document for the region at `line`/`character` of `source`, whose
+    /// content is `text`
+    pub fn at(source: &Path, text: &str, line: u32, character: u32) -> Option<Self> {
+        let offset = offset_of(text, line, character)?;
+        regions(source, text)
+            .iter()
+            .find(|region| region.start <= offset && offset <= region.end)
+            .map(|region| Self::new(source, region))
+    }
+
+    /// One document per embedded language of `source`
+    pub fn all(source: &Path, text: &str) -> Vec<Self> {
+        let mut documents: Vec<Self> = Vec::new();
+        for region in regions(source, text) {
+            let document = Self::new(source, &region);
+            if !documents.iter().any(|d| d.path == document.path) {
+                documents.push(document);
+            }
+        }
+        documents
+    }
+
+    /// The virtual document's path, next to the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The language the document is routed to
+    pub fn language(&self) -> &'static str {
+        self.language
+    }
+
+    /// `value` (a response about the virtual document) with its URI
+    /// replaced by the file's
+    pub fn restore<T: Serialize + DeserializeOwned>(&self, value: T) -> T {
+        let (Ok(virtual_uri), Ok(source_uri)) = (
+            Url::from_file_path(&self.path),
+            Url::from_file_path(&self.source),
+        ) else {
+            return value;
+        };
+        let Ok(json) = serde_json::to_string(&value) else {
+            return value;
+        };
+        let from = serde_json::to_string(virtual_uri.as_str()).unwrap_or_default();
+        if !json.contains(&from) {
+            return value;
+        }
+        let to = serde_json::to_string(source_uri.as_str()).unwrap_or_default();
+        serde_json::from_str(&json.replace(&from, &to)).unwrap_or(value)
+    }
+}
+
+/// The file and language behind a virtual document's path
+pub fn source_of(path: &Path) -> Option<(PathBuf, &'static str)> {
+    let name = path.file_name()?.to_str()?;
+    let (source_name, extension) = name.rsplit_once(MARKER)?;
+    let source = path.with_file_name(source_name);
+    if !is_mixed(&source) {
+        return None;
+    }
+    let (_, language, _) = LANGUAGES.iter().find(|(_, _, ext)| *ext == extension)?;
+    Some((source, *language))
+}
+
+/// The content of a document: read from disk (or the tool call's unsaved
+/// content), or extracted from its file for a virtual document
+pub async fn read_document(path: &Path) -> std::io::Result<String> {
+    let Some((source, _)) = source_of(path) else {
+        return overlay::read_file(path).await;
+    };
+    let text = overlay::read_file(&source).await?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let regions = regions(&source, &text);
+    let matching: Vec<&Region> = regions
+        .iter()
+        .filter(|region| region.extension == extension)
+        .collect();
+    Ok(blank_outside(&text, &matching))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_documents() {
+        let vue = "<template>\n  <p>{{ n }}</p>\n</template>\n\
+                   <script setup lang=\"ts\">\nconst n: number = 1\n</script>\n\
+                   <style scoped>\np { color: red }\n</style>\n";
+        let path = Path::new("/app/src/App.vue");
+        let found = regions(path, vue);
+        let languages: Vec<&str> = found.iter().map(|r| r.language).collect();
+        assert_eq!(languages, ["typescript", "css"]);
+
+        let document = EmbeddedDocument::at(path, vue, 4, 6).unwrap();
+        assert_eq!(document.language(), "typescript");
+        assert_eq!(document.path(), Path::new("/app/src/App.vue.embedded.ts"));
+        assert!(EmbeddedDocument::at(path, vue, 1, 4).is_none());
+        assert_eq!(
+            source_of(document.path()),
+            Some((path.to_path_buf(), "typescript"))
+        );
+        assert_eq!(source_of(Path::new("/app/src/main.embedded.ts")), None);
+
+        // Lines and columns survive blanking
+        let ts: Vec<&Region> = found.iter().filter(|r| r.extension == "ts").collect();
+        let blanked = blank_outside(vue, &ts);
+        assert_eq!(blanked.lines().count(), vue.lines().count());
+        assert_eq!(blanked.lines().nth(4), Some("const n: number = 1"));
+        assert_eq!(blanked.lines().nth(3), Some(""));
+
+        let html = "<p>hi</p><script>let x = 1</script>";
+        let blanked = blank_outside(
+            html,
+            &regions(Path::new("index.html"), html)
+                .iter()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(blanked, "                 let x = 1");
+
+        let markdown =
+            "# Usage\n\n```rust\nfn main() {}\n```\n\n~~~text\nnot code\n~~~\n\n```py\nprint(1)\n";
+        let found = regions(Path::new("README.md"), markdown);
+        let languages: Vec<&str> = found.iter().map(|r| r.language).collect();
+        assert_eq!(languages, ["rust", "python"]);
+        assert_eq!(&markdown[found[0].start..found[0].end], "fn main() {}\n");
+        assert_eq!(&markdown[found[1].start..found[1].end], "print(1)\n");
+
+        let uri = Url::from_file_path("/app/src/App.vue").unwrap();
+        let location = lsp_types::Location {
+            uri: Url::from_file_path(document.path()).unwrap(),
+            range: lsp_types::Range::default(),
+        };
+        assert_eq!(document.restore(location).uri, uri);
+    }
+}
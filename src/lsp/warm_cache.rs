@@ -0,0 +1,174 @@
+//! Server caches that outlive a session
+//!
+//! Every lsmcp session starts its servers afresh, and a server that keeps
+//! its index or build output in a temporary or shared place starts cold, or
+//! fights the user's own builds for it. Unless `warm_caches = false`, known
+//! servers get their caches under lsmcp's data directory through the launch
+//! placeholders (see `lsp::launch`):
+//!
+//! - rust-analyzer builds (build scripts, proc macros, `cargo check`) into
+//!   `{data_dir}/target`, so it neither waits on the lock of the project's
+//!   `target` nor rebuilds them each session
+//! - gopls keeps its file cache in `{cache_dir}`
+//! - clangd keeps the index of files outside a compilation database in
+//!   `{cache_dir}`; projects with one keep theirs in `.cache/clangd`
+//!
+//! A variable already set, in the server's `env` or lsmcp's environment, is
+//! left alone. `lsmcp cache` lists these directories and clears them.
+
+use crate::config::LspPackage;
+use std::path::{Path, PathBuf};
+
+/// Environment a server's caches are moved with: (server, variable, value)
+const CACHE_ENV: &[(&str, &str, &str)] = &[
+    ("rust-analyzer", "CARGO_TARGET_DIR", "{data_dir}/target"),
+    ("gopls", "GOPLSCACHE", "{cache_dir}"),
+    ("clangd", "XDG_CACHE_HOME", "{cache_dir}"),
+];
+
+/// Point `config`'s server at caches under lsmcp's data directory
+///
+/// Remote servers are left alone, as the paths are this machine's.
+pub fn apply(config: &mut LspPackage) {
+    if config.remote.is_some() {
+        return;
+    }
+    for (server, variable, value) in CACHE_ENV {
+        if config.name == *server
+            && !config.bin.env.contains_key(*variable)
+            && std::env::var_os(variable).is_none()
+        {
+            config
+                .bin
+                .env
+                .insert(variable.to_string(), value.to_string());
+        }
+    }
+}
+
+/// A server's cache or per-project data directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDir {
+    pub server: String,
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// The cache (`server-caches/<server>`) and project data (`workspaces/<server>/*`)
+/// directories under `data_dir`, of `server` or every server
+pub fn list(data_dir: &Path, server: Option<&str>) -> Vec<CacheDir> {
+    let mut dirs = Vec::new();
+    for (kind, per_project) in [("server-caches", false), ("workspaces", true)] {
+        let Ok(entries) = std::fs::read_dir(data_dir.join(kind)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if server.is_some_and(|server| server != name) || !entry.path().is_dir() {
+                continue;
+            }
+            let paths: Vec<PathBuf> = if per_project {
+                std::fs::read_dir(entry.path())
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|project| project.path())
+                    .filter(|path| path.is_dir())
+                    .collect()
+            } else {
+                vec![entry.path()]
+            };
+            dirs.extend(paths.into_iter().map(|path| CacheDir {
+                server: name.clone(),
+                bytes: dir_size(&path),
+                path,
+            }));
+        }
+    }
+    dirs.sort_by(|a, b| (&a.server, &a.path).cmp(&(&b.server, &b.path)));
+    dirs
+}
+
+/// Remove the directories `list` finds, returning those removed
+pub fn clear(data_dir: &Path, server: Option<&str>) -> std::io::Result<Vec<CacheDir>> {
+    let dirs = list(data_dir, server);
+    for dir in &dirs {
+        std::fs::remove_dir_all(&dir.path)?;
+    }
+    Ok(dirs)
+}
+
+/// Total size of the files under `path`, not following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::get_default_configs;
+
+    fn package(name: &str) -> LspPackage {
+        let mut package = get_default_configs()["python"].clone();
+        package.name = name.to_string();
+        package.bin.env.clear();
+        package
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut gopls = package("gopls");
+        apply(&mut gopls);
+        if std::env::var_os("GOPLSCACHE").is_none() {
+            assert_eq!(gopls.bin.env["GOPLSCACHE"], "{cache_dir}");
+        }
+
+        let mut clangd = package("clangd");
+        clangd
+            .bin
+            .env
+            .insert("XDG_CACHE_HOME".to_string(), "/mine".to_string());
+        apply(&mut clangd);
+        assert_eq!(clangd.bin.env["XDG_CACHE_HOME"], "/mine");
+
+        let mut pyright = package("pyright");
+        apply(&mut pyright);
+        assert!(pyright.bin.env.is_empty());
+    }
+
+    #[test]
+    fn test_list_and_clear() {
+        let data = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(data.path().join("server-caches/gopls")).unwrap();
+        std::fs::write(data.path().join("server-caches/gopls/index"), "12345").unwrap();
+        std::fs::create_dir_all(data.path().join("workspaces/rust-analyzer/abc/target")).unwrap();
+        std::fs::write(
+            data.path().join("workspaces/rust-analyzer/abc/target/lib"),
+            "123",
+        )
+        .unwrap();
+
+        let dirs = list(data.path(), None);
+        assert_eq!(dirs.len(), 2);
+        assert_eq!((dirs[0].server.as_str(), dirs[0].bytes), ("gopls", 5));
+        assert_eq!(
+            (dirs[1].server.as_str(), dirs[1].bytes),
+            ("rust-analyzer", 3)
+        );
+
+        let cleared = clear(data.path(), Some("gopls")).unwrap();
+        assert_eq!(cleared.len(), 1);
+        assert!(!data.path().join("server-caches/gopls").exists());
+        assert!(data.path().join("workspaces/rust-analyzer/abc").exists());
+    }
+}
@@ -1,6 +1,11 @@
+mod commands;
+
 use anyhow::Result;
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use lsmcp::mcp::daemon::{self, Endpoint, SessionLoggers};
+use lsmcp::mcp::protocol::LoggingLevel;
+use lsmcp::utils::signals;
+use std::path::{Path, PathBuf};
 use tracing::{error, info, Level};
 use tracing_subscriber::{fmt, EnvFilter};
 
@@ -11,29 +16,265 @@ use tracing_subscriber::{fmt, EnvFilter};
 #[command(name = "lsmcp")]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Workspace root directory
     ///
     /// If not specified, attempts to auto-detect from:
     /// 1. Current directory's git root
     /// 2. Current working directory
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     workspace: Option<PathBuf>,
 
-    /// Log level (trace, debug, info, warn, error)
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+    /// Log level (trace, debug, info, warn, error) [default: the config's
+    /// `log_level`, or info]
+    #[arg(short, long, global = true)]
+    log_level: Option<String>,
 
     /// Log to file instead of stderr
-    #[arg(long, default_value = "/tmp/lsmcp.log")]
+    #[arg(long, default_value = "/tmp/lsmcp.log", global = true)]
     log_file: PathBuf,
 
     /// Disable logging entirely (for MCP client compatibility)
-    #[arg(long)]
+    #[arg(long, global = true)]
     no_log: bool,
+
+    /// Write every MCP and LSP message to this JSONL file, for bug reports
+    #[arg(long, global = true, value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+
+    /// Never download language servers; only reuse cached artifacts
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Apply the config file's `[profile.<name>]` (overrides $LSMCP_PROFILE)
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Serve MCP on this Unix socket (or tcp://host:port) instead of stdio
+    #[arg(long, value_name = "SOCKET")]
+    listen: Option<String>,
+
+    /// Let a TCP listener bind to a non-loopback address
+    #[arg(long, global = true)]
+    allow_remote: bool,
 }
 
-fn setup_logging(log_level: &str, log_file: PathBuf) -> Result<()> {
-    let level = match log_level.to_lowercase().as_str() {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the MCP server on stdio (default)
+    Serve,
+
+    /// Keep language servers running for MCP sessions connecting over a socket
+    Daemon {
+        /// Unix socket to listen on (defaults to one per workspace)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// Listen on this TCP address (e.g. 127.0.0.1:7010) instead
+        #[arg(long, conflicts_with = "socket")]
+        tcp: Option<String>,
+    },
+
+    /// Bridge stdio to an lsmcp already listening on a socket
+    Proxy {
+        /// Unix socket or tcp://host:port (defaults to the workspace's daemon)
+        target: Option<String>,
+    },
+
+    /// Serve MCP on stdio through a daemon, starting one if none is running
+    Connect {
+        /// Unix socket of the daemon (defaults to the workspace's)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// Connect to a daemon on this TCP address instead
+        #[arg(long, conflicts_with = "socket")]
+        tcp: Option<String>,
+
+        /// Fail instead of starting a daemon when none is running
+        #[arg(long)]
+        no_start: bool,
+    },
+
+    /// Install a language server
+    Install {
+        /// Server name (e.g. "pyright") or language (e.g. "python")
+        #[arg(required_unless_present = "all_detected")]
+        server: Option<String>,
+
+        /// Install servers for every language found in the workspace
+        #[arg(long, conflicts_with = "server")]
+        all_detected: bool,
+    },
+
+    /// Uninstall a language server installed by lsmcp
+    Uninstall {
+        /// Server name (e.g. "pyright") or language (e.g. "python")
+        server: String,
+    },
+
+    /// List installed and available language servers
+    List {
+        /// Only show servers installed by lsmcp
+        #[arg(long)]
+        installed: bool,
+
+        /// Only show servers known to lsmcp
+        #[arg(long)]
+        available: bool,
+    },
+
+    /// Update installed language servers
+    Update {
+        /// Server to update (defaults to all servers installed by lsmcp)
+        server: Option<String>,
+
+        /// Only report available updates
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Remove stale manifest entries and orphaned server directories
+    Prune {
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List the caches kept for language servers, or clear them
+    Cache {
+        /// Only this server's caches
+        server: Option<String>,
+        /// Remove the caches instead of listing them
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Diagnose files once and exit non-zero on problems, for CI
+    Check {
+        /// Files, directories or globs (e.g. "src/**/*.ts") to check
+        /// (defaults to the whole workspace)
+        paths: Vec<String>,
+
+        /// Output: text, json, sarif (code scanning) or github (workflow
+        /// annotations)
+        #[arg(long, default_value = "text")]
+        format: lsmcp::check::CheckFormat,
+
+        /// Least severe diagnostic that fails the check: error, warning,
+        /// info, hint or never
+        #[arg(long, default_value = "error")]
+        fail_on: lsmcp::check::FailOn,
+
+        /// Seconds to wait for each file's diagnostics
+        #[arg(long, default_value_t = 10)]
+        wait: u64,
+
+        /// Only check files changed on the current git branch since it left
+        /// BASE (defaults to the remote's default branch, else main or master)
+        #[arg(long, value_name = "BASE", num_args = 0..=1, require_equals = true)]
+        changed: Option<Option<String>>,
+    },
+
+    /// Report which tools agents called and how many tokens the answers took
+    Stats {
+        /// Forget the usage recorded so far
+        #[arg(long)]
+        reset: bool,
+    },
+
+    /// List the language servers lsmcp processes have running
+    Ps,
+
+    /// Stop language servers left running by lsmcp processes that are gone
+    KillOrphans,
+
+    /// Inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+
+    /// Manage the local copy of the Mason registry
+    Registry {
+        #[command(subcommand)]
+        action: RegistryCommand,
+    },
+
+    /// List or revert edits lsmcp's tools applied to the workspace
+    Edits {
+        #[command(subcommand)]
+        action: EditsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Check the config file for errors, unknown keys and missing servers
+    Validate {
+        /// Config file to check (defaults to the one lsmcp would load)
+        path: Option<PathBuf>,
+    },
+
+    /// Print the JSON Schema for the config file
+    Schema,
+
+    /// Print config for the servers a Neovim setup uses, from Mason's
+    /// install receipts or a dump of the lspconfig configurations
+    ImportNeovim {
+        /// lspconfig configurations dumped as JSON or with `vim.inspect`
+        #[arg(long)]
+        lspconfig: Option<PathBuf>,
+
+        /// Mason install root (defaults to `$MASON`, else Neovim's)
+        #[arg(long)]
+        mason: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RegistryCommand {
+    /// Download the latest Mason registry and cache its language servers
+    Sync,
+}
+
+#[derive(Subcommand, Debug)]
+enum EditsCommand {
+    /// List journaled edits, newest first
+    List,
+
+    /// Restore the files an edit changed
+    Rollback {
+        /// Edit to revert (defaults to the latest)
+        id: Option<u64>,
+
+        /// Revert files changed since the edit, losing those changes
+        #[arg(long)]
+        force: bool,
+
+        /// Only print the diff
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Log to `log_file`, rotated and filtered as the user config asks
+///
+/// Writes happen on a background thread; the returned guard flushes them
+/// when dropped.
+fn setup_logging(
+    log_level: Option<&str>,
+    log_file: &Path,
+) -> Result<tracing_appender::non_blocking::WorkerGuard> {
+    let settings = lsmcp::ConfigLoader::user_settings();
+    let level = match log_level
+        .or(settings.log_level.as_deref())
+        .unwrap_or("info")
+        .to_lowercase()
+        .as_str()
+    {
         "trace" => Level::TRACE,
         "debug" => Level::DEBUG,
         "info" => Level::INFO,
@@ -42,7 +283,23 @@ fn setup_logging(log_level: &str, log_file: PathBuf) -> Result<()> {
         _ => Level::INFO,
     };
 
-    let filter = EnvFilter::from_default_env().add_directive(level.into());
+    // Module directives override the level; RUST_LOG overrides both
+    let mut filter = EnvFilter::default().add_directive(level.into());
+    let module_directives = settings
+        .log_filter
+        .as_deref()
+        .map(lsmcp::utils::logging::module_directives)
+        .unwrap_or_default();
+    let env_directives = std::env::var("RUST_LOG").unwrap_or_default();
+    for directive in module_directives
+        .iter()
+        .map(String::as_str)
+        .chain(env_directives.split(','))
+    {
+        if let Ok(directive) = directive.trim().parse() {
+            filter = filter.add_directive(directive);
+        }
+    }
 
     let subscriber = fmt()
         .with_env_filter(filter)
@@ -51,10 +308,11 @@ fn setup_logging(log_level: &str, log_file: PathBuf) -> Result<()> {
         .with_file(true)
         .with_line_number(true);
 
-    let file = std::fs::File::create(log_file)?;
-    subscriber.with_writer(file).init();
+    let file = lsmcp::utils::logging::log_file_writer(log_file, &settings)?;
+    let (writer, guard) = tracing_appender::non_blocking(file);
+    subscriber.with_ansi(false).with_writer(writer).init();
 
-    Ok(())
+    Ok(guard)
 }
 
 fn detect_workspace_root(provided: Option<PathBuf>) -> Result<PathBuf> {
@@ -87,29 +345,184 @@ fn detect_workspace_root(provided: Option<PathBuf>) -> Result<PathBuf> {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    if let Some(profile) = &args.profile {
+        lsmcp::ConfigLoader::select_profile(profile);
+    }
 
     // Setup logging (skip if disabled for MCP compatibility)
-    if !args.no_log {
-        setup_logging(&args.log_level, args.log_file)?;
-    }
+    let _log_guard = if args.no_log {
+        None
+    } else {
+        Some(setup_logging(args.log_level.as_deref(), &args.log_file)?)
+    };
 
     info!("Starting LSMCP v{}", env!("CARGO_PKG_VERSION"));
 
-    // Detect workspace root
-    let workspace_root = detect_workspace_root(args.workspace)?;
-    info!("Workspace root: {}", workspace_root.display());
+    // Connecting only bridges stdio; the daemon loads the config
+    if let Some(Command::Connect {
+        socket,
+        tcp,
+        no_start,
+    }) = args.command
+    {
+        let workspace_root = detect_workspace_root(args.workspace)?;
+        let endpoint = endpoint(&workspace_root, socket, tcp);
+        let start = (!no_start).then(|| {
+            let mut command = std::process::Command::new(
+                std::env::current_exe().unwrap_or_else(|_| PathBuf::from("lsmcp")),
+            );
+            command
+                .arg("daemon")
+                .arg("--workspace")
+                .arg(&workspace_root);
+            if let Endpoint::Unix(path) = &endpoint {
+                command.arg("--socket").arg(path);
+            }
+            if args.no_log {
+                command.arg("--no-log");
+            } else {
+                if let Some(log_level) = &args.log_level {
+                    command.arg("--log-level").arg(log_level);
+                }
+                command
+                    .arg("--log-file")
+                    .arg(daemon_log_file(&args.log_file));
+            }
+            if args.offline {
+                command.arg("--offline");
+            }
+            if let Some(profile) = &args.profile {
+                command.arg("--profile").arg(profile);
+            }
+            if let Some(trace_file) = &args.trace_file {
+                command.arg("--trace-file").arg(trace_file);
+            }
+            command
+        });
+        return daemon::connect(&endpoint, start).await;
+    }
+
+    if let Some(Command::Proxy { target }) = &args.command {
+        let endpoint = match target {
+            Some(target) => Endpoint::parse(target),
+            None => Endpoint::default_for(&detect_workspace_root(args.workspace)?),
+        };
+        return daemon::connect(&endpoint, None).await;
+    }
+
+    // Only the process talking to the servers traces; `connect` hands the
+    // path to the daemon it starts
+    if let Some(trace_file) = &args.trace_file {
+        lsmcp::trace::open(trace_file)?;
+    }
+
+    // Config commands must work even when the config file doesn't load
+    if let Some(Command::Config { action }) = args.command {
+        return match action {
+            ConfigCommand::Validate { path } => commands::config_validate(path),
+            ConfigCommand::Schema => commands::config_schema(),
+            ConfigCommand::ImportNeovim { lspconfig, mason } => {
+                commands::config_import_neovim(lspconfig, mason)
+            }
+        };
+    }
 
     // Initialize configuration loader
-    let config = match lsmcp::ConfigLoader::new() {
-        Ok(config) => std::sync::Arc::new(config),
+    let mut config = match lsmcp::ConfigLoader::new() {
+        Ok(config) => config,
         Err(e) => {
             error!("Failed to load configuration: {}", e);
             return Err(e.into());
         }
     };
 
+    if args.offline {
+        config.settings_mut().offline = Some(true);
+    }
+    let config = std::sync::Arc::new(config);
+
     info!("Configuration loaded successfully");
 
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => match args.listen {
+            Some(target) => {
+                let endpoint = Endpoint::parse(&target);
+                listen(args.workspace, endpoint, args.allow_remote, config).await
+            }
+            None => serve(args.workspace, config).await,
+        },
+        Command::Daemon { socket, tcp } => {
+            daemon(args.workspace, socket, tcp, args.allow_remote, config).await
+        }
+        Command::Connect { .. } | Command::Proxy { .. } => {
+            unreachable!("handled before loading the config")
+        }
+        Command::Install {
+            all_detected: true, ..
+        } => {
+            let workspace_root = detect_workspace_root(args.workspace)?;
+            commands::install_detected(&config, &workspace_root).await
+        }
+        Command::Install { server, .. } => {
+            commands::install(&config, server.as_deref().unwrap_or_default()).await
+        }
+        Command::Uninstall { server } => commands::uninstall(&config, &server),
+        Command::List {
+            installed,
+            available,
+        } => commands::list(&config, installed, available),
+        Command::Update { server, check } => {
+            commands::update(&config, server.as_deref(), check).await
+        }
+        Command::Prune { dry_run } => commands::prune(dry_run),
+        Command::Cache { server, clear } => commands::cache(server.as_deref(), clear),
+        Command::Check {
+            paths,
+            format,
+            fail_on,
+            wait,
+            changed,
+        } => {
+            let workspace_root = detect_workspace_root(args.workspace)?;
+            commands::check(
+                config,
+                workspace_root,
+                &paths,
+                changed,
+                format,
+                fail_on,
+                wait,
+            )
+            .await
+        }
+        Command::Stats { reset } => commands::stats(&config, reset),
+        Command::Ps => commands::ps().await,
+        Command::KillOrphans => commands::kill_orphans().await,
+        Command::Registry {
+            action: RegistryCommand::Sync,
+        } => commands::registry_sync(&config).await,
+        Command::Edits { action } => {
+            let workspace_root = detect_workspace_root(args.workspace)?;
+            match action {
+                EditsCommand::List => commands::edits_list(&workspace_root),
+                EditsCommand::Rollback { id, force, dry_run } => {
+                    commands::edits_rollback(&workspace_root, id, force, dry_run)
+                }
+            }
+        }
+        Command::Config { .. } => unreachable!("handled before loading the config"),
+    }
+}
+
+/// Create the LSP manager and start idle shutdown and preloading
+fn start_manager(
+    workspace: Option<PathBuf>,
+    config: std::sync::Arc<lsmcp::ConfigLoader>,
+) -> Result<std::sync::Arc<lsmcp::LspManager>> {
+    // Detect workspace root
+    let workspace_root = detect_workspace_root(workspace)?;
+    info!("Workspace root: {}", workspace_root.display());
+
     // Initialize LSP manager
     let lsp_manager = match lsmcp::LspManager::new(workspace_root, config) {
         Ok(manager) => std::sync::Arc::new(manager),
@@ -121,26 +534,220 @@ async fn main() -> Result<()> {
 
     info!("LSP manager initialized");
 
+    lsmcp::mcp::server::set_strict_stdio(lsp_manager.settings().strict_stdio.unwrap_or(true));
+
+    lsp_manager.spawn_idle_reaper();
+    lsmcp::lsp::orphans::spawn_orphan_reaper();
+    lsp_manager.spawn_session_persistence();
+    lsp_manager.spawn_file_watcher();
+    lsp_manager.spawn_workspace_scan();
+    if lsp_manager.settings().usage_stats != Some(false) {
+        lsmcp::usage::spawn_persistence();
+    }
+
+    if let Some(address) = lsp_manager.settings().metrics_address {
+        tokio::spawn(async move {
+            if let Err(e) = lsmcp::metrics::serve(&address).await {
+                error!("Failed to serve metrics at {}: {}", address, e);
+            }
+        });
+    }
+
+    // Warm up preloaded servers in the background so MCP initialize isn't delayed
+    let preload = lsp_manager.preload_languages();
+    if !preload.is_empty() {
+        info!("Preloading LSP servers: {}", preload.join(", "));
+        let manager = lsp_manager.clone();
+        tokio::spawn(async move {
+            for (language, result) in manager
+                .warmup(&preload, std::time::Duration::from_secs(300))
+                .await
+            {
+                match result {
+                    Ok(true) => info!("Preloaded {} server", language),
+                    Ok(false) => info!("{} server still indexing after preload", language),
+                    Err(e) => error!("Failed to preload {} server: {}", language, e),
+                }
+            }
+        });
+    }
+
+    // Open the files servers should diagnose, also in the background
+    let prepare = lsp_manager.settings().prepare_workspace.unwrap_or_default();
+    if !prepare.is_empty() {
+        let manager = lsp_manager.clone();
+        tokio::spawn(async move {
+            let prepared = manager
+                .prepare_workspace(&prepare, None, lsmcp::lsp::prepare::DEFAULT_PREPARE_LIMIT)
+                .await;
+            info!("{}", prepared.render());
+        });
+    }
+
+    Ok(lsp_manager)
+}
+
+/// Start watching the config file, refreshing the registry and enforcing
+/// memory limits, reporting to the MCP client through `log`
+fn spawn_reloaders(
+    lsp_manager: &std::sync::Arc<lsmcp::LspManager>,
+    log: impl Fn(LoggingLevel, &str, serde_json::Value) + Clone + Send + Sync + 'static,
+) {
+    // Pick up config file edits without a restart
+    let reload_log = log.clone();
+    lsp_manager.spawn_config_watcher(move |result| {
+        report_reload(
+            &|level, data| reload_log(level, "lsmcp.config", data),
+            result,
+        )
+    });
+
+    // Keep a synced Mason registry fresh
+    let refresh_log = log.clone();
+    lsp_manager.spawn_registry_refresh(move |result| {
+        report_reload(
+            &|level, data| refresh_log(level, "lsmcp.config", data),
+            result,
+        )
+    });
+
+    // Restart runaway servers
+    lsp_manager.spawn_memory_watchdog(move |exceeded| {
+        log(
+            LoggingLevel::Warning,
+            "lsmcp.resources",
+            serde_json::Value::String(format!(
+                "{} ({}) used {} MB, over its {} MB limit, and was stopped; it restarts on next use",
+                exceeded.server, exceeded.language, exceeded.memory_mb, exceeded.limit_mb
+            )),
+        )
+    });
+}
+
+/// Run the MCP server on stdio until the client disconnects
+async fn serve(
+    workspace: Option<PathBuf>,
+    config: std::sync::Arc<lsmcp::ConfigLoader>,
+) -> Result<()> {
+    let lsp_manager = start_manager(workspace, config)?;
+
     // Create MCP server
-    let mcp_server = lsmcp::McpServer::new(lsp_manager.clone());
+    let idle_exit = lsp_manager
+        .settings()
+        .exit_after_idle_minutes
+        .filter(|m| *m > 0)
+        .map(|m| std::time::Duration::from_secs(m * 60));
+    let mcp_server = lsmcp::McpServer::new(lsp_manager.clone()).with_idle_exit(idle_exit);
+    let logger = mcp_server.logger();
+    spawn_reloaders(&lsp_manager, move |level, name, data| {
+        logger.log(level, name, data)
+    });
 
     info!("LSMCP server starting - ready to accept MCP requests on stdio");
 
-    // Run MCP server (this blocks until client disconnects)
-    match mcp_server.run().await {
-        Ok(()) => {
-            info!("MCP server stopped normally");
-        }
-        Err(e) => {
-            error!("MCP server error: {}", e);
-            return Err(e);
+    // Run MCP server until the client disconnects or we're asked to stop
+    let result = tokio::select! {
+        result = mcp_server.run() => result,
+        signal = signals::stop_signal() => {
+            info!("{}; shutting down", signal);
+            Ok(())
         }
+    };
+    match &result {
+        Ok(()) => info!("MCP server stopped normally"),
+        Err(e) => error!("MCP server error: {}", e),
     }
 
-    // Shutdown LSP manager
+    // Shutdown LSP manager, however the server stopped
+    signals::exit_on_stop_signal();
     lsp_manager.shutdown().await;
+    lsmcp::usage::global().save();
 
     info!("LSMCP shut down successfully");
 
-    Ok(())
+    result
+}
+
+/// Run the daemon on the chosen endpoint, by default the workspace's socket
+async fn daemon(
+    workspace: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    tcp: Option<String>,
+    allow_remote: bool,
+    config: std::sync::Arc<lsmcp::ConfigLoader>,
+) -> Result<()> {
+    let workspace_root = detect_workspace_root(workspace)?;
+    let endpoint = endpoint(&workspace_root, socket, tcp);
+    listen(Some(workspace_root), endpoint, allow_remote, config).await
+}
+
+/// Serve MCP sessions on `endpoint` until interrupted
+async fn listen(
+    workspace: Option<PathBuf>,
+    endpoint: Endpoint,
+    allow_remote: bool,
+    config: std::sync::Arc<lsmcp::ConfigLoader>,
+) -> Result<()> {
+    let lsp_manager = start_manager(workspace, config)?;
+    let sessions = SessionLoggers::default();
+    let loggers = sessions.clone();
+    spawn_reloaders(&lsp_manager, move |level, name, data| {
+        loggers.log(level, name, data)
+    });
+
+    let result = daemon::run(lsp_manager.clone(), &endpoint, sessions, allow_remote).await;
+
+    signals::exit_on_stop_signal();
+    lsp_manager.shutdown().await;
+    lsmcp::usage::global().save();
+    info!("LSMCP daemon shut down");
+    result
+}
+
+/// The daemon endpoint chosen on the command line, else the workspace's
+fn endpoint(workspace_root: &Path, socket: Option<PathBuf>, tcp: Option<String>) -> Endpoint {
+    match (socket, tcp) {
+        (_, Some(addr)) => Endpoint::Tcp(addr),
+        (Some(path), None) => Endpoint::Unix(path),
+        (None, None) => Endpoint::default_for(workspace_root),
+    }
+}
+
+/// Log file of a daemon started by `connect`, next to our own
+fn daemon_log_file(log_file: &Path) -> PathBuf {
+    log_file.with_file_name("lsmcp-daemon.log")
+}
+
+/// Tell the MCP client(s) about a configuration reload
+fn report_reload(
+    log: &impl Fn(LoggingLevel, serde_json::Value),
+    result: Result<lsmcp::lsp::manager::ConfigReload, lsmcp::LspError>,
+) {
+    match result {
+        Ok(reload) => {
+            let source = reload
+                .path
+                .map_or("built-in defaults".to_string(), |p| p.display().to_string());
+            let mut message = format!("Reloaded configuration from {}", source);
+            if !reload.restarted.is_empty() {
+                let servers: Vec<String> = reload
+                    .restarted
+                    .iter()
+                    .map(|(language, server)| format!("{} ({})", server, language))
+                    .collect();
+                message.push_str(&format!("; restarting {}", servers.join(", ")));
+            }
+            log(LoggingLevel::Info, serde_json::Value::String(message));
+        }
+        Err(e) => {
+            error!("Failed to reload configuration: {}", e);
+            log(
+                LoggingLevel::Error,
+                serde_json::Value::String(format!(
+                    "Failed to reload configuration, keeping the previous one: {}",
+                    e
+                )),
+            );
+        }
+    }
 }
@@ -0,0 +1,357 @@
+//! Response size budget
+//!
+//! A tool's answer can run to megabytes (references to a common type, the
+//! diagnostics of a broken build), which would fill an agent's context
+//! window. Every result is cut down to `max_response_chars` (or
+//! `max_response_tokens`), from the settings or the call: text keeps its
+//! first entries whole, with the summary line that precedes them, and ends
+//! with a note counting what was left out and in how many files; in
+//! structured content the longest arrays are shortened until it fits.
+
+use crate::config::Settings;
+use crate::mcp::protocol::{CallToolResult, ToolContent};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// Characters a response may take unless configured otherwise
+pub const DEFAULT_MAX_RESPONSE_CHARS: usize = 50_000;
+
+/// Characters a token is counted as for `max_response_tokens`
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Room left for the note on what was cut
+const NOTE_RESERVE: usize = 200;
+
+/// Schema of the `max_response_chars` argument every tool takes
+pub fn argument_schema() -> Value {
+    serde_json::json!({
+        "type": "integer",
+        "minimum": 0,
+        "description": "Cut the answer down to about this many characters (0 for no limit). Defaults to `settings.max_response_chars`"
+    })
+}
+
+/// Offer the `max_response_chars` argument in a tool's input schema
+pub fn add_argument(schema: &mut Value) {
+    if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+        properties
+            .entry("max_response_chars")
+            .or_insert_with(argument_schema);
+    }
+}
+
+/// The call's budget in characters, `None` for no limit: its
+/// `max_response_chars` argument, else the settings' budgets (the smaller
+/// one when both are set), else the default
+pub fn budget(args: &Value, settings: &Settings) -> Result<Option<usize>, String> {
+    let chars = match args.get("max_response_chars") {
+        None | Some(Value::Null) => {
+            let tokens = settings
+                .max_response_tokens
+                .map(|tokens| tokens.saturating_mul(CHARS_PER_TOKEN));
+            match (settings.max_response_chars, tokens) {
+                (Some(chars), Some(tokens)) if chars > 0 && tokens > 0 => chars.min(tokens),
+                (Some(chars), Some(tokens)) => chars.max(tokens),
+                (chars, tokens) => chars.or(tokens).unwrap_or(DEFAULT_MAX_RESPONSE_CHARS),
+            }
+        }
+        Some(value) => value.as_u64().map(|chars| chars as usize).ok_or_else(|| {
+            format!(
+                "`max_response_chars` must be a non-negative integer, not {}",
+                value
+            )
+        })?,
+    };
+    Ok((chars > 0).then_some(chars))
+}
+
+/// `result` cut down to `budget` characters of text and as many of
+/// structured content
+pub fn fit(mut result: CallToolResult, budget: usize) -> CallToolResult {
+    let mut left = budget;
+    for content in &mut result.content {
+        if let ToolContent::Text { text } = content {
+            *text = fit_text(text, left);
+            left = left.saturating_sub(text.chars().count());
+        }
+    }
+    if let Some(structured) = &mut result.structured_content {
+        fit_structured(structured, budget);
+    }
+    result
+}
+
+/// Whether `result` is within `budget` already, so `fit` leaves it whole
+pub fn fits(result: &CallToolResult, budget: usize) -> bool {
+    let mut left = budget;
+    for content in &result.content {
+        if let ToolContent::Text { text } = content {
+            let length = text.chars().count();
+            if length > left {
+                return false;
+            }
+            left -= length;
+        }
+    }
+    result
+        .structured_content
+        .as_ref()
+        .is_none_or(|value| value.to_string().chars().count() <= budget)
+}
+
+/// Characters of `result`'s text and structured content
+pub fn result_chars(result: &CallToolResult) -> usize {
+    let text: usize = result
+        .content
+        .iter()
+        .map(|content| match content {
+            ToolContent::Text { text } => text.chars().count(),
+            _ => 0,
+        })
+        .sum();
+    let structured = result
+        .structured_content
+        .as_ref()
+        .map_or(0, |value| value.to_string().chars().count());
+    text + structured
+}
+
+/// `text` cut down to `budget` characters
+///
+/// Text is cut between entries: a line that isn't indented begins one, and
+/// the indented lines and code blocks after it belong to it, so a reference
+/// or a diagnostic with its related information is shown whole or not at
+/// all.
+pub fn fit_text(text: &str, budget: usize) -> String {
+    if text.chars().count() <= budget {
+        return text.to_string();
+    }
+    let room = budget.saturating_sub(NOTE_RESERVE);
+
+    let entries = entries(text);
+    let mut kept = String::new();
+    let mut used = 0;
+    let mut shown = 0;
+    for entry in &entries {
+        let length = entry.chars().count();
+        if used + length > room {
+            break;
+        }
+        kept.push_str(entry);
+        used += length;
+        shown += 1;
+    }
+
+    let note = if shown == 0 {
+        // The first entry alone is too long: keep what fits of it
+        kept = entries[0].chars().take(room).collect();
+        let cut = text.chars().count() - kept.chars().count();
+        format!("…and {} more characters", cut)
+    } else {
+        describe_rest(&entries[shown..])
+    };
+
+    if kept.lines().filter(|line| is_fence(line)).count() % 2 == 1 {
+        if !kept.ends_with('\n') {
+            kept.push('\n');
+        }
+        kept.push_str("```\n");
+    }
+    if !kept.ends_with('\n') {
+        kept.push('\n');
+    }
+    format!(
+        "{}{} (cut to fit the {}-character response budget; narrow the query or raise max_response_chars)",
+        kept, note, budget
+    )
+}
+
+/// `text` split into entries, each with its trailing newlines
+fn entries(text: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let begins_entry = !in_fence
+            && offset > start
+            && !line.trim().is_empty()
+            && !line.starts_with(char::is_whitespace)
+            && !is_fence(line);
+        if begins_entry {
+            entries.push(&text[start..offset]);
+            start = offset;
+        }
+        if is_fence(line) {
+            in_fence = !in_fence;
+        }
+        offset += line.len();
+    }
+    entries.push(&text[start..]);
+    entries
+}
+
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with("```")
+}
+
+/// `…and 230 more in 12 files`, for the entries left out
+fn describe_rest(rest: &[&str]) -> String {
+    let rest: Vec<&str> = rest
+        .iter()
+        .copied()
+        .filter(|entry| !entry.trim().is_empty())
+        .collect();
+    let files: BTreeSet<&str> = rest.iter().filter_map(|entry| entry_file(entry)).collect();
+    match files.len() {
+        0 => format!("…and {} more", rest.len()),
+        1 => format!("…and {} more in 1 file", rest.len()),
+        n => format!("…and {} more in {} files", rest.len(), n),
+    }
+}
+
+/// The file of the first location (`path:line:column`, or a URI with
+/// `#L<line>`) on an entry's first line
+fn entry_file(entry: &str) -> Option<&str> {
+    static LOCATION: OnceLock<Regex> = OnceLock::new();
+    let location = LOCATION.get_or_init(|| {
+        Regex::new(r"(file://[^\s#]+)#L\d+|([^\s:()]*[/.][^\s:()]*):\d+:\d+").unwrap()
+    });
+    let first_line = entry.lines().next()?;
+    let captures = location.captures(first_line)?;
+    captures.get(1).or(captures.get(2)).map(|m| m.as_str())
+}
+
+/// Shorten the longest arrays in `value` until it serializes to `budget`
+/// characters, noting under `omitted` how many items each lost
+fn fit_structured(value: &mut Value, budget: usize) {
+    let size = |value: &Value| value.to_string().chars().count();
+    let mut omitted = serde_json::Map::new();
+    while size(value) > budget {
+        let Some((_, path)) = longest_array(value, "") else {
+            break;
+        };
+        let Some(Value::Array(items)) = value.pointer_mut(&path) else {
+            break;
+        };
+        let keep = items.len() / 2;
+        let dropped = (items.len() - keep) as u64;
+        items.truncate(keep);
+
+        let key = path.trim_start_matches('/').replace('/', ".");
+        let total = omitted.get(&key).and_then(Value::as_u64).unwrap_or(0) + dropped;
+        omitted.insert(key, total.into());
+        if let Value::Object(object) = value {
+            object.insert("truncated".to_string(), Value::Bool(true));
+            object.insert("omitted".to_string(), Value::Object(omitted.clone()));
+        }
+    }
+}
+
+/// The length of, and JSON pointer to, the array with the most items (more
+/// than one) reached through object fields
+fn longest_array(value: &Value, path: &str) -> Option<(usize, String)> {
+    let Value::Object(object) = value else {
+        return None;
+    };
+    object
+        .iter()
+        .filter_map(|(key, field)| {
+            let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+            match field {
+                Value::Array(items) if items.len() > 1 => Some((items.len(), path)),
+                Value::Object(_) => longest_array(field, &path),
+                _ => None,
+            }
+        })
+        .max_by_key(|(len, _)| *len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_text() {
+        let mut text = "Found 300 reference(s):\n".to_string();
+        for i in 0..300 {
+            text.push_str(&format!("/src/file{}.rs:{}:5\n", i % 12, i + 1));
+        }
+        let fitted = fit_text(&text, 1000);
+        assert!(fitted.chars().count() <= 1000, "{}", fitted);
+        assert!(fitted.starts_with("Found 300 reference(s):\n/src/file0.rs:1:5\n"));
+        let shown = fitted.lines().filter(|line| line.starts_with('/')).count();
+        assert!(fitted.contains(&format!("…and {} more in 12 files", 300 - shown)));
+
+        // Indented lines stay with their entry, and open code blocks are closed
+        let text = format!(
+            "ERROR at line 1:1\n  Related: x\n```\n{}\n",
+            "y\n".repeat(400)
+        );
+        let fitted = fit_text(&text, 400);
+        assert!(fitted.starts_with("ERROR at line 1:1\n  Related: x\n```\ny\n"));
+        assert!(fitted.contains("```\n…and"));
+
+        assert_eq!(fit_text("short", 400), "short");
+    }
+
+    #[test]
+    fn test_fit_structured() {
+        let mut value = serde_json::json!({
+            "count": 1000,
+            "references": (0..1000).collect::<Vec<_>>(),
+            "nested": { "files": ["a", "b"] }
+        });
+        fit_structured(&mut value, 500);
+        assert!(value.to_string().len() <= 500);
+        assert_eq!(value["count"], 1000);
+        assert_eq!(value["truncated"], true);
+        let kept = value["references"].as_array().unwrap().len() as u64;
+        assert_eq!(value["omitted"]["references"].as_u64(), Some(1000 - kept));
+        assert_eq!(value["nested"]["files"].as_array().unwrap().len(), 2);
+
+        let result = CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "x".repeat(300),
+            }],
+            is_error: None,
+            structured_content: Some(value),
+        };
+        assert!(fits(&result, 500));
+        assert!(!fits(&result, 400));
+        assert_eq!(
+            result_chars(&result),
+            300 + result
+                .structured_content
+                .as_ref()
+                .unwrap()
+                .to_string()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let settings = Settings {
+            max_response_chars: Some(8000),
+            max_response_tokens: Some(1000),
+            ..Settings::default()
+        };
+        assert_eq!(budget(&Value::Null, &settings), Ok(Some(4000)));
+        assert_eq!(
+            budget(&serde_json::json!({ "max_response_chars": 0 }), &settings),
+            Ok(None)
+        );
+        assert_eq!(
+            budget(&Value::Null, &Settings::default()),
+            Ok(Some(DEFAULT_MAX_RESPONSE_CHARS))
+        );
+        assert!(budget(
+            &serde_json::json!({ "max_response_chars": "big" }),
+            &settings
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Tools defined in the config
+//!
+//! Each `[command_tools.<name>]` entry becomes a tool that runs its command
+//! in the workspace, with `${workspace}` and `${file}` filled in and the
+//! call's `args` appended, and returns what it printed, as
+//! `lsp_run_runnable` does. The commands are the user's own, so unlike
+//! runnables they don't need `allow_run`.
+
+use crate::config::CommandToolConfig;
+use crate::lsp::runnables::Runnable;
+use crate::mcp::protocol::{CallToolResult, Tool, ToolAnnotations};
+use crate::mcp::registry::{ToolContext, ToolHandler};
+use crate::mcp::tools::{invalid_parameter, lsp_error, run_result};
+use crate::tools::run::{self, DEFAULT_RUN_TIMEOUT_SECS};
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+/// A `[command_tools]` entry as a tool
+pub struct CommandTool {
+    name: String,
+    config: CommandToolConfig,
+}
+
+impl CommandTool {
+    pub fn new(name: String, config: CommandToolConfig) -> Self {
+        Self { name, config }
+    }
+
+    fn uses_file(&self) -> bool {
+        self.config
+            .command
+            .iter()
+            .any(|arg| arg.contains("${file}"))
+    }
+
+    /// The command for a call, with placeholders filled in
+    fn runnable(&self, file: Option<&str>, args: Vec<String>, workspace_root: &Path) -> Runnable {
+        let workspace = workspace_root.display().to_string();
+        let fill = |arg: &String| {
+            let arg = arg.replace("${workspace}", &workspace);
+            match file {
+                Some(file) => arg.replace("${file}", file),
+                None => arg,
+            }
+        };
+        let mut command = self.config.command.iter().map(fill);
+        Runnable {
+            label: self.name.clone(),
+            location: None,
+            program: command.next().unwrap_or_default(),
+            args: command.chain(args).collect(),
+            cwd: self.config.cwd.as_ref().map(|cwd| workspace_root.join(cwd)),
+            env: self.config.env.clone(),
+        }
+    }
+}
+
+impl ToolHandler for CommandTool {
+    fn definition(&self) -> Tool {
+        let mut required = Vec::new();
+        if self.uses_file() {
+            required.push("file");
+        }
+        Tool {
+            name: self.name.clone(),
+            description: self.config.description.clone(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "File the command is run for, absolute or relative to the workspace root"
+                    },
+                    "args": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Extra arguments appended to the command"
+                    }
+                },
+                "required": required
+            }),
+            annotations: Some(if self.config.read_only {
+                ToolAnnotations::read_only()
+            } else {
+                ToolAnnotations::writes(true, false, true)
+            }),
+        }
+    }
+
+    fn uses_servers(&self) -> bool {
+        false
+    }
+
+    fn call(&self, args: Value, ctx: ToolContext) -> BoxFuture<'static, CallToolResult> {
+        let file = args.get("file").and_then(Value::as_str);
+        let extra: Vec<String> = args
+            .get("args")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|arg| arg.as_str().map(str::to_string))
+            .collect();
+        let workspace_root = ctx.lsp_manager.workspace_root().to_path_buf();
+        let file = file.map(|file| workspace_root.join(file).display().to_string());
+        let runnable = self.runnable(file.as_deref(), extra, &workspace_root);
+        let timeout_secs = self
+            .config
+            .timeout_secs
+            .unwrap_or(DEFAULT_RUN_TIMEOUT_SECS)
+            .max(1);
+
+        Box::pin(async move {
+            if runnable.program.is_empty() {
+                return invalid_parameter("name", "the tool's `command` is empty");
+            }
+            let timeout = Duration::from_secs(timeout_secs);
+            match run::run(&runnable, &workspace_root, timeout).await {
+                Ok(output) => run_result(&runnable, &output, timeout_secs),
+                Err(e) => lsp_error(&e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_tool() {
+        let tool = CommandTool::new(
+            "lint".to_string(),
+            CommandToolConfig {
+                description: "Lint a file".to_string(),
+                command: vec![
+                    "eslint".to_string(),
+                    "--config=${workspace}/eslint.json".to_string(),
+                    "${file}".to_string(),
+                ],
+                read_only: true,
+                ..CommandToolConfig::default()
+            },
+        );
+        let definition = tool.definition();
+        assert_eq!(
+            definition.input_schema["required"],
+            serde_json::json!(["file"])
+        );
+        assert_eq!(definition.annotations.unwrap().read_only_hint, Some(true));
+
+        let runnable = tool.runnable(
+            Some("/app/src/a.js"),
+            vec!["--fix".to_string()],
+            Path::new("/app"),
+        );
+        assert_eq!(
+            runnable.command_line(),
+            "eslint --config=/app/eslint.json /app/src/a.js --fix"
+        );
+        assert_eq!(runnable.cwd, None);
+    }
+}
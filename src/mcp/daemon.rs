@@ -0,0 +1,523 @@
+//! Daemon mode
+//!
+//! `lsmcp daemon` keeps one `LspManager`, and with it the language servers,
+//! running across MCP sessions. Clients connect over a Unix socket or TCP,
+//! each connection being its own MCP session; `lsmcp connect` bridges an
+//! MCP client's stdio to the daemon, starting it first if needed, so a new
+//! client skips server startup and indexing. `lsmcp --listen <socket>` and
+//! `lsmcp proxy <socket>` are the same pieces without the automatic start.
+//!
+//! A TCP daemon only listens on loopback unless given `--allow-remote`,
+//! and either way each connection must first send the daemon's token, which
+//! it writes to a file only its user can read (see `token_file`).
+//!
+//! Sessions share servers but not documents: each session's queried files
+//! are closed on the servers when it disconnects, unless another session
+//! queried them too (see `LspManager::open_session`).
+
+use crate::lsp::LspManager;
+use crate::mcp::logging::McpLogger;
+use crate::mcp::protocol::LoggingLevel;
+use crate::mcp::server::{McpServer, MessageWriter};
+use crate::utils::signals;
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// How long `connect` waits for a daemon it started to start listening
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Token of a TCP daemon, for clients that cannot read its token file
+/// (and daemons that should use a fixed token)
+pub const TOKEN_ENV: &str = "LSMCP_DAEMON_TOKEN";
+
+/// How long a TCP connection has to send the token
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest token line read before giving up on a connection
+const MAX_TOKEN_LINE: u64 = 1024;
+
+/// Where the daemon listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Unix socket path
+    Unix(PathBuf),
+    /// TCP address, e.g. `127.0.0.1:7010`
+    Tcp(String),
+}
+
+impl Endpoint {
+    /// Parse a `--listen` or `proxy` target: `tcp://host:port`, else a
+    /// Unix socket path
+    pub fn parse(target: &str) -> Self {
+        match target.strip_prefix("tcp://") {
+            Some(addr) => Self::Tcp(addr.to_string()),
+            None => Self::Unix(PathBuf::from(target)),
+        }
+    }
+
+    /// The socket a workspace's daemon listens on unless told otherwise
+    ///
+    /// One per workspace, in the runtime directory (else the temp directory).
+    pub fn default_for(workspace_root: &Path) -> Self {
+        let hash: String = Sha256::digest(workspace_root.to_string_lossy().as_bytes())
+            .iter()
+            .take(6)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let dir = dirs::runtime_dir().unwrap_or_else(std::env::temp_dir);
+        Self::Unix(dir.join(format!("lsmcp-{}.sock", hash)))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            Self::Tcp(addr) => write!(f, "tcp://{}", addr),
+        }
+    }
+}
+
+/// Loggers of the connected sessions, for messages meant for all of them
+/// (such as configuration reloads)
+#[derive(Clone, Default)]
+pub struct SessionLoggers(Arc<Mutex<HashMap<u64, McpLogger>>>);
+
+impl SessionLoggers {
+    /// Send a log message to every session
+    pub fn log(&self, level: LoggingLevel, logger: &str, data: Value) {
+        for session in self.lock().values() {
+            session.log(level, logger, data.clone());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, McpLogger>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Serve MCP sessions on `endpoint` until interrupted
+pub async fn run(
+    lsp_manager: Arc<LspManager>,
+    endpoint: &Endpoint,
+    loggers: SessionLoggers,
+    allow_remote: bool,
+) -> Result<()> {
+    match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let listener = bind_unix(path)?;
+            info!("Daemon listening on {}", endpoint);
+            let accept = async {
+                loop {
+                    let (stream, _) = listener.accept().await?;
+                    tokio::spawn(serve_session(
+                        stream,
+                        Arc::clone(&lsp_manager),
+                        loggers.clone(),
+                    ));
+                }
+            };
+            let result = until_shutdown(accept).await;
+            let _ = std::fs::remove_file(path);
+            result
+        }
+        #[cfg(not(unix))]
+        Endpoint::Unix(_) => bail!("Unix sockets are not supported here; use --tcp"),
+        Endpoint::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen on {}", addr))?;
+            let local = listener.local_addr()?;
+            if !local.ip().is_loopback() {
+                if !allow_remote {
+                    bail!(
+                        "Refusing to listen on non-loopback address {}; pass --allow-remote to accept connections from other machines",
+                        addr
+                    );
+                }
+                warn!(
+                    "Daemon listening on non-loopback address {}; anyone who has its token can run queries",
+                    addr
+                );
+            }
+            let token_file = token_file(&local.port().to_string());
+            let token: Arc<str> = daemon_token()?.into();
+            write_token(&token_file, &token)?;
+            info!(
+                "Daemon listening on {} (token in {})",
+                endpoint,
+                token_file.display()
+            );
+            let accept = async {
+                loop {
+                    let (stream, peer) = listener.accept().await?;
+                    let token = Arc::clone(&token);
+                    let lsp_manager = Arc::clone(&lsp_manager);
+                    let loggers = loggers.clone();
+                    tokio::spawn(async move {
+                        match authenticate(stream, &token).await {
+                            Some(stream) => serve_session(stream, lsp_manager, loggers).await,
+                            None => {
+                                warn!("Rejected connection from {}: wrong or missing token", peer)
+                            }
+                        }
+                    });
+                }
+            };
+            let result = until_shutdown(accept).await;
+            let _ = std::fs::remove_file(&token_file);
+            result
+        }
+    }
+}
+
+/// Run `accept` until it fails or the process is asked to stop
+async fn until_shutdown<F>(accept: F) -> Result<()>
+where
+    F: std::future::Future<Output = std::io::Result<()>>,
+{
+    tokio::select! {
+        result = accept => result.context("Failed to accept connection"),
+        signal = signals::stop_signal() => {
+            info!("{}; stopping daemon", signal);
+            Ok(())
+        }
+    }
+}
+
+/// Listen on a Unix socket, replacing a stale one left by a dead daemon
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            bail!("A daemon is already listening on {}", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    // Sessions can read any file the daemon can, so only we may connect
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Where the TCP daemon on `port` keeps its token: a file in the runtime
+/// directory (else the temp directory) only its user can read
+pub fn token_file(port: &str) -> PathBuf {
+    let dir = dirs::runtime_dir().unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("lsmcp-tcp-{}.token", port))
+}
+
+/// The daemon's token: `$LSMCP_DAEMON_TOKEN` when set, else a fresh random one
+fn daemon_token() -> Result<String> {
+    if let Some(token) = std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty()) {
+        return Ok(token);
+    }
+    let mut bytes = [0u8; 32];
+    random_bytes(&mut bytes).context("Failed to generate the daemon token")?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(unix)]
+fn random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+    use std::io::Read;
+    std::fs::File::open("/dev/urandom")?.read_exact(buf)
+}
+
+/// Without /dev/urandom, hash the OS-seeded keys of the std hash maps
+#[cfg(not(unix))]
+fn random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = Sha256::new();
+    for _ in 0..4 {
+        let state = std::collections::hash_map::RandomState::new();
+        hasher.update(state.build_hasher().finish().to_le_bytes());
+    }
+    let digest = hasher.finalize();
+    for (byte, random) in buf.iter_mut().zip(digest.iter().cycle()) {
+        *byte = *random;
+    }
+    Ok(())
+}
+
+/// Write the token where only our user can read it, replacing one left by
+/// a dead daemon
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _ = std::fs::remove_file(path);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write token file {}", path.display()))?;
+    writeln!(file, "{}", token)?;
+    Ok(())
+}
+
+/// Check that a TCP connection starts with the token line, handing back the
+/// rest of the connection if so
+async fn authenticate(stream: TcpStream, token: &str) -> Option<BufReader<TcpStream>> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    let mut limited = (&mut stream).take(MAX_TOKEN_LINE);
+    match tokio::time::timeout(AUTH_TIMEOUT, limited.read_line(&mut line)).await {
+        Ok(Ok(_)) if same_token(line.trim_end(), token) => Some(stream),
+        _ => None,
+    }
+}
+
+/// Compare tokens in time independent of where they differ
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The token to send to the TCP daemon on `addr`: `$LSMCP_DAEMON_TOKEN`
+/// when set, else the one in its token file
+fn client_token(addr: &str) -> Result<String> {
+    if let Some(token) = std::env::var(TOKEN_ENV).ok().filter(|t| !t.is_empty()) {
+        return Ok(token);
+    }
+    let port = addr.rsplit(':').next().unwrap_or(addr);
+    let path = token_file(port);
+    let token = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "No token for tcp://{}; set ${} to the daemon's token (it is in {} on the daemon's machine)",
+            addr,
+            TOKEN_ENV,
+            path.display()
+        )
+    })?;
+    Ok(token.trim().to_string())
+}
+
+/// Serve one connection as an MCP session
+async fn serve_session<S>(stream: S, lsp_manager: Arc<LspManager>, loggers: SessionLoggers)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let write_task = tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let session = lsp_manager.open_session();
+    let server = McpServer::with_writer(Arc::clone(&lsp_manager), MessageWriter::Channel(tx))
+        .with_session(session);
+    loggers.lock().insert(session, server.logger());
+    info!("Session {} connected", session);
+
+    if let Err(e) = server.run_connection(BufReader::new(reader)).await {
+        warn!("Session {} failed: {}", session, e);
+    }
+
+    // Dropping the last sender ends the writer task
+    loggers.lock().remove(&session);
+    drop(server);
+    let _ = write_task.await;
+
+    lsp_manager.close_session(session).await;
+    info!("Session {} disconnected", session);
+}
+
+/// Bridge stdio to the daemon on `endpoint`
+///
+/// When nothing is listening and `start` is given, it is run to start the
+/// daemon, and the connection retried until it is up.
+pub async fn connect(endpoint: &Endpoint, start: Option<std::process::Command>) -> Result<()> {
+    match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => {
+            let stream = match tokio::net::UnixStream::connect(path).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let Some(start) = start else {
+                        return Err(e).with_context(|| no_daemon(endpoint));
+                    };
+                    start_daemon(start)?;
+                    let deadline = tokio::time::Instant::now() + DAEMON_START_TIMEOUT;
+                    loop {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        match tokio::net::UnixStream::connect(path).await {
+                            Ok(stream) => break stream,
+                            Err(e) if tokio::time::Instant::now() >= deadline => {
+                                return Err(e).with_context(|| no_daemon(endpoint));
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                }
+            };
+            bridge(stream).await
+        }
+        #[cfg(not(unix))]
+        Endpoint::Unix(_) => bail!("Unix sockets are not supported here; use --tcp"),
+        Endpoint::Tcp(addr) => {
+            // A TCP daemon may be remote, so it is never started from here
+            let token = client_token(addr)?;
+            let mut stream = TcpStream::connect(addr)
+                .await
+                .with_context(|| no_daemon(endpoint))?;
+            stream.write_all(format!("{}\n", token).as_bytes()).await?;
+            bridge(stream).await
+        }
+    }
+}
+
+fn no_daemon(endpoint: &Endpoint) -> String {
+    format!(
+        "No lsmcp daemon is listening on {}; start one with `lsmcp daemon`",
+        endpoint
+    )
+}
+
+/// Start a daemon in the background, detached from our process group so it
+/// outlives the client that started it
+fn start_daemon(mut command: std::process::Command) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("Failed to start the lsmcp daemon")?;
+    info!("Started lsmcp daemon");
+    Ok(())
+}
+
+/// Copy stdin to the connection and the connection to stdout until the
+/// daemon closes it
+async fn bridge<S: AsyncRead + AsyncWrite>(stream: S) -> Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+
+    let upload = async {
+        tokio::io::copy(&mut stdin, &mut writer).await?;
+        // Our client is done; let the daemon finish answering, then close
+        writer.shutdown().await
+    };
+    let download = tokio::io::copy(&mut reader, &mut stdout);
+    tokio::pin!(download);
+
+    tokio::select! {
+        result = &mut download => {
+            result?;
+            return Ok(());
+        }
+        result = upload => result?,
+    }
+    download.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            Endpoint::parse("/tmp/lsmcp.sock"),
+            Endpoint::Unix(PathBuf::from("/tmp/lsmcp.sock"))
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://127.0.0.1:7010"),
+            Endpoint::Tcp("127.0.0.1:7010".to_string())
+        );
+        let endpoint = Endpoint::Tcp("127.0.0.1:7010".to_string());
+        assert_eq!(Endpoint::parse(&endpoint.to_string()), endpoint);
+    }
+
+    #[test]
+    fn test_default_endpoint_per_workspace() {
+        let a = Endpoint::default_for(Path::new("/src/a"));
+        let b = Endpoint::default_for(Path::new("/src/b"));
+        assert_ne!(a, b);
+        assert_eq!(a, Endpoint::default_for(Path::new("/src/a")));
+        assert!(a.to_string().ends_with(".sock"));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"secret\n{\"id\":1}\n").await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = authenticate(stream, "secret").await.unwrap();
+        let mut message = String::new();
+        stream.read_line(&mut message).await.unwrap();
+        assert_eq!(message, "{\"id\":1}\n");
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"guess\n").await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(authenticate(stream, "secret").await.is_none());
+
+        // A client that never sends a newline is dropped at the size limit
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&[b'x'; 2048]).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert!(authenticate(stream, "secret").await.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_token() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lsmcp-tcp-7010.token");
+        std::fs::write(&path, "stale\n").unwrap();
+        write_token(&path, "fresh").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fresh\n");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let token = daemon_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, daemon_token().unwrap());
+    }
+}
@@ -0,0 +1,121 @@
+//! How tool output shows locations
+//!
+//! Every file and position in a tool's text goes through here, so the
+//! `location_format` setting (or a call's `location_format` argument)
+//! decides whether agents see absolute `path:line:column` as editors and
+//! compilers print it, paths relative to the workspace root, which are
+//! shorter in deep trees, or `file://` URIs for clients that open links.
+//! Structured content keeps absolute paths whatever the format.
+
+use crate::config::LocationFormat;
+use lsp_types::{Position, Url};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+tokio::task_local! {
+    /// The current tool call's format and workspace root
+    static FORMAT: (LocationFormat, PathBuf);
+}
+
+/// Run `f` (a tool call) showing locations in `format`
+pub async fn scope<F: Future>(format: LocationFormat, workspace_root: PathBuf, f: F) -> F::Output {
+    FORMAT.scope((format, workspace_root), f).await
+}
+
+fn with_format<T>(f: impl FnOnce(LocationFormat, &Path) -> T) -> T {
+    match FORMAT.try_with(|(format, root)| (*format, root.clone())) {
+        Ok((format, root)) => f(format, &root),
+        Err(_) => f(LocationFormat::default(), Path::new("")),
+    }
+}
+
+/// A file, as the current tool call shows it
+pub fn path(path: &Path) -> String {
+    with_format(|format, root| match format {
+        LocationFormat::Editor => path.display().to_string(),
+        LocationFormat::Relative => match path.strip_prefix(root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => path.display().to_string(),
+        },
+        LocationFormat::Uri => Url::from_file_path(path)
+            .map(String::from)
+            .unwrap_or_else(|_| path.display().to_string()),
+    })
+}
+
+/// A document, as the current tool call shows it; URIs that aren't
+/// `file://` (`jdt://`, `deno:`) are shown as they are
+pub fn uri(uri: &Url) -> String {
+    match uri.to_file_path() {
+        Ok(file) => path(&file),
+        Err(_) => uri.to_string(),
+    }
+}
+
+/// A 0-based position in a document, shown 1-based: `path:12:5`, or
+/// `file:///path#L12C5` for URIs
+pub fn position(uri: &Url, position: Position) -> String {
+    let (line, column) = (position.line + 1, position.character + 1);
+    match with_format(|format, _| format) {
+        LocationFormat::Uri => format!("{}#L{}C{}", uri, line, column),
+        _ => format!("{}:{}:{}", self::uri(uri), line, column),
+    }
+}
+
+/// A 0-based position in a file, shown like `position`
+pub fn file_position(file: &Path, position: Position) -> String {
+    match Url::from_file_path(file) {
+        Ok(uri) => self::position(&uri, position),
+        Err(_) => format!(
+            "{}:{}:{}",
+            file.display(),
+            position.line + 1,
+            position.character + 1
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_location_formats() {
+        let uri = Url::parse("file:///app/src/main.rs").unwrap();
+        let outside = Url::parse("file:///usr/lib/std.rs").unwrap();
+        let at = Position::new(11, 4);
+        let show = |format| {
+            let (uri, outside) = (uri.clone(), outside.clone());
+            scope(format, PathBuf::from("/app"), async move {
+                (
+                    position(&uri, at),
+                    position(&outside, at),
+                    path(Path::new("/app")),
+                )
+            })
+        };
+
+        // Outside a tool call locations are absolute
+        assert_eq!(position(&uri, at), "/app/src/main.rs:12:5");
+        assert_eq!(
+            show(LocationFormat::Relative).await,
+            (
+                "src/main.rs:12:5".to_string(),
+                "/usr/lib/std.rs:12:5".to_string(),
+                ".".to_string()
+            )
+        );
+        assert_eq!(
+            show(LocationFormat::Uri).await.0,
+            "file:///app/src/main.rs#L12C5"
+        );
+        assert_eq!(show(LocationFormat::Editor).await.1, "/usr/lib/std.rs:12:5");
+
+        let jdt = Url::parse("jdt://contents/rt.jar/java.lang/String.class").unwrap();
+        assert_eq!(
+            position(&jdt, at),
+            "jdt://contents/rt.jar/java.lang/String.class:12:5"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! MCP logging notifications
+//!
+//! Lets long-running tools report progress to the client as
+//! `notifications/message` while the tool call is still in flight.
+
+use crate::mcp::protocol::{JsonRpcNotification, LoggingLevel, LoggingMessageParams};
+use crate::mcp::server::MessageWriter;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Sends log messages at or above the client's chosen level
+#[derive(Clone)]
+pub struct McpLogger {
+    min_level: Arc<Mutex<LoggingLevel>>,
+    writer: MessageWriter,
+}
+
+impl McpLogger {
+    pub fn new() -> Self {
+        Self::with_writer(MessageWriter::Stdout)
+    }
+
+    /// A logger sending its messages to `writer`
+    pub fn with_writer(writer: MessageWriter) -> Self {
+        Self {
+            min_level: Arc::new(Mutex::new(LoggingLevel::Info)),
+            writer,
+        }
+    }
+
+    /// Set the minimum level sent (from `logging/setLevel`)
+    pub fn set_level(&self, level: LoggingLevel) {
+        if let Ok(mut min_level) = self.min_level.lock() {
+            *min_level = level;
+        }
+    }
+
+    /// Send a log message to the client
+    pub fn log(&self, level: LoggingLevel, logger: &str, data: Value) {
+        let enabled = self.min_level.lock().map_or(true, |min| level >= *min);
+        if !enabled {
+            return;
+        }
+
+        let params = LoggingMessageParams {
+            level,
+            logger: Some(logger.to_string()),
+            data,
+        };
+        let notification = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/message".to_string(),
+            params: serde_json::to_value(params).ok(),
+        };
+
+        if let Err(e) = self.writer.write(&notification) {
+            warn!("Failed to send log notification: {}", e);
+        }
+    }
+}
+
+impl Default for McpLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,7 +1,19 @@
 //! MCP server and tools module
 
+pub mod budget;
+pub mod command_tools;
+pub mod daemon;
+pub mod locations;
+pub mod logging;
+pub mod progress;
 pub mod protocol;
+pub mod registry;
+pub mod resources;
 pub mod server;
+pub mod service;
 pub mod tools;
+pub mod validation;
 
+pub use registry::{FnTool, ToolContext, ToolHandler, ToolRegistry};
 pub use server::McpServer;
+pub use service::McpService;
@@ -0,0 +1,140 @@
+//! MCP progress notifications
+//!
+//! A tool call whose request carries `_meta.progressToken` gets the partial
+//! results its servers stream (see `lsp::partial`) as
+//! `notifications/progress`, so a long find-references shows what it has
+//! found so far instead of looking hung until it finishes.
+
+use crate::lsp::partial::{PartialBatch, PartialSink};
+use crate::mcp::locations;
+use crate::mcp::protocol::{JsonRpcNotification, ProgressNotificationParams};
+use crate::mcp::server::MessageWriter;
+use lsp_types::{Location, OneOf, WorkspaceSymbolResponse};
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Results listed in one notification, the rest only counted
+const MAX_LISTED_RESULTS: usize = 50;
+
+/// Reports a tool call's progress to the client
+pub struct ProgressReporter {
+    token: Value,
+    writer: MessageWriter,
+    /// Whether the client's protocol revision has progress messages
+    messages: bool,
+    /// Results reported so far
+    found: AtomicU64,
+}
+
+impl ProgressReporter {
+    pub fn new(token: Value, writer: MessageWriter) -> Self {
+        Self {
+            token,
+            writer,
+            messages: true,
+            found: AtomicU64::new(0),
+        }
+    }
+
+    /// Leave out the listing of results, for clients whose protocol revision
+    /// predates progress messages
+    pub fn with_messages(mut self, messages: bool) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// A sink sending each batch of partial results as a notification
+    pub fn partial_results(self) -> PartialSink {
+        Arc::new(move |batch| self.report(batch))
+    }
+
+    fn report(&self, batch: PartialBatch<'_>) {
+        let lines = describe(&batch);
+        let found =
+            self.found.fetch_add(lines.len() as u64, Ordering::Relaxed) + lines.len() as u64;
+
+        let mut message = format!("{} result(s) so far, {} new:", found, lines.len());
+        for line in lines.iter().take(MAX_LISTED_RESULTS) {
+            message.push('\n');
+            message.push_str(line);
+        }
+        if lines.len() > MAX_LISTED_RESULTS {
+            message.push_str(&format!(
+                "\n... and {} more",
+                lines.len() - MAX_LISTED_RESULTS
+            ));
+        }
+
+        let params = ProgressNotificationParams {
+            progress_token: self.token.clone(),
+            progress: found,
+            message: self.messages.then_some(message),
+        };
+        let notification = JsonRpcNotification {
+            jsonrpc: "2.0".to_string(),
+            method: "notifications/progress".to_string(),
+            params: serde_json::to_value(params).ok(),
+        };
+        if let Err(e) = self.writer.write(&notification) {
+            warn!("Failed to send progress notification: {}", e);
+        }
+    }
+}
+
+/// One line per result in `batch`
+fn describe(batch: &PartialBatch<'_>) -> Vec<String> {
+    let position = |location: &Location| locations::position(&location.uri, location.range.start);
+    match batch {
+        PartialBatch::Locations(locations) => locations.iter().map(position).collect(),
+        PartialBatch::Symbols(WorkspaceSymbolResponse::Flat(symbols)) => symbols
+            .iter()
+            .map(|s| format!("{} ({:?}) {}", s.name, s.kind, position(&s.location)))
+            .collect(),
+        PartialBatch::Symbols(WorkspaceSymbolResponse::Nested(symbols)) => symbols
+            .iter()
+            .map(|s| {
+                let location = match &s.location {
+                    OneOf::Left(location) => position(location),
+                    OneOf::Right(location) => locations::uri(&location.uri),
+                };
+                format!("{} ({:?}) {}", s.name, s.kind, location)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range, Url};
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_progress_reporter() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let sink =
+            ProgressReporter::new(Value::from(7), MessageWriter::Channel(tx)).partial_results();
+        let location = |line| Location {
+            uri: Url::parse("file:///src/main.rs").unwrap(),
+            range: Range::new(Position::new(line, 4), Position::new(line, 7)),
+        };
+
+        sink(PartialBatch::Locations(&[location(0), location(9)]));
+        sink(PartialBatch::Locations(&[location(20)]));
+
+        let notifications: Vec<Value> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|line| serde_json::from_str(&line).unwrap())
+            .collect();
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0]["method"], "notifications/progress");
+        assert_eq!(notifications[0]["params"]["progressToken"], 7);
+        assert_eq!(
+            notifications[0]["params"]["message"],
+            "2 result(s) so far, 2 new:\n/src/main.rs:1:5\n/src/main.rs:10:5"
+        );
+        // Progress counts every result so far, so it always increases
+        assert_eq!(notifications[1]["params"]["progress"], 3);
+    }
+}
@@ -28,6 +28,15 @@ pub struct JsonRpcResponse {
     pub error: Option<JsonRpcError>,
 }
 
+/// JSON-RPC notification (server to client)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcNotification {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
 /// JSON-RPC error
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcError {
@@ -44,6 +53,48 @@ pub struct Tool {
     pub description: String,
     #[serde(rename = "inputSchema")]
     pub input_schema: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Hints about what a tool does, for clients deciding what to confirm
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// The tool doesn't change anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    /// The tool may overwrite or delete (only meaningful when not read-only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    /// Repeating a call with the same arguments has no further effect
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    /// The tool reaches outside the workspace (e.g. downloads)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /// A query that only reads the workspace
+    pub fn read_only() -> Self {
+        Self {
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        }
+    }
+
+    /// A tool that changes files or state, possibly destructively
+    pub fn writes(destructive: bool, idempotent: bool, open_world: bool) -> Self {
+        Self {
+            read_only_hint: Some(false),
+            destructive_hint: Some(destructive),
+            idempotent_hint: Some(idempotent),
+            open_world_hint: Some(open_world),
+        }
+    }
 }
 
 /// MCP Tool call parameters
@@ -52,14 +103,36 @@ pub struct CallToolParams {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arguments: Option<Value>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<RequestMeta>,
+}
+
+/// Request `_meta`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestMeta {
+    /// Token to send `notifications/progress` for the request with
+    #[serde(rename = "progressToken", skip_serializing_if = "Option::is_none")]
+    pub progress_token: Option<Value>,
 }
 
 /// MCP Tool call result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallToolResult {
     pub content: Vec<ToolContent>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        rename = "isError",
+        alias = "is_error",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
     pub is_error: Option<bool>,
+    /// Machine-readable result alongside the text content
+    #[serde(
+        rename = "structuredContent",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub structured_content: Option<serde_json::Value>,
 }
 
 /// Tool content (text or image)
@@ -72,6 +145,77 @@ pub enum ToolContent {
     Image { data: String, mime_type: String },
 }
 
+/// MCP protocol revisions lsmcp speaks, oldest first
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    V2024_11_05,
+    V2025_03_26,
+    #[default]
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    pub const ALL: [Self; 3] = [Self::V2024_11_05, Self::V2025_03_26, Self::V2025_06_18];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V2024_11_05 => "2024-11-05",
+            Self::V2025_03_26 => "2025-03-26",
+            Self::V2025_06_18 => "2025-06-18",
+        }
+    }
+
+    /// The revision to speak with a client asking for `requested`
+    ///
+    /// A supported revision is used as is. A client asking for one newer
+    /// than lsmcp knows is offered the latest, which it may accept or
+    /// disconnect over; anything else is rejected, listing the supported
+    /// revisions in the error's data.
+    pub fn negotiate(requested: &str) -> Result<Self, JsonRpcError> {
+        if let Some(version) = Self::ALL.into_iter().find(|v| v.as_str() == requested) {
+            return Ok(version);
+        }
+
+        // Revisions are dates, so they order as strings
+        let is_revision = requested.len() == 10
+            && requested.chars().enumerate().all(|(i, c)| {
+                if i == 4 || i == 7 {
+                    c == '-'
+                } else {
+                    c.is_ascii_digit()
+                }
+            });
+        let latest = Self::default();
+        if is_revision && requested > latest.as_str() {
+            return Ok(latest);
+        }
+
+        Err(JsonRpcError {
+            code: INVALID_PARAMS,
+            message: format!("Unsupported protocol version: {}", requested),
+            data: Some(serde_json::json!({
+                "supported": Self::ALL.map(Self::as_str),
+                "requested": requested,
+            })),
+        })
+    }
+
+    /// Whether tools carry `annotations` (from 2025-03-26)
+    pub fn has_tool_annotations(self) -> bool {
+        self >= Self::V2025_03_26
+    }
+
+    /// Whether progress notifications carry a `message` (from 2025-03-26)
+    pub fn has_progress_messages(self) -> bool {
+        self >= Self::V2025_03_26
+    }
+
+    /// Whether tool results carry `structuredContent` (from 2025-06-18)
+    pub fn has_structured_content(self) -> bool {
+        self >= Self::V2025_06_18
+    }
+}
+
 /// Initialize request params
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InitializeParams {
@@ -130,6 +274,74 @@ pub struct ServerInfo {
     pub version: String,
 }
 
+/// Log message severity (RFC 5424 levels, least severe first)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+/// `logging/setLevel` params
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetLevelParams {
+    pub level: LoggingLevel,
+}
+
+/// `notifications/message` params
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingMessageParams {
+    pub level: LoggingLevel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
+/// `notifications/progress` params
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressNotificationParams {
+    pub progress_token: Value,
+    /// Increases with every notification for the request
+    pub progress: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// A resource the server offers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub uri: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// `resources/read`, `resources/subscribe` and `resources/unsubscribe` params
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceParams {
+    pub uri: String,
+}
+
+/// A resource's contents, as text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceContents {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    pub text: String,
+}
+
 /// List tools result
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListToolsResult {
@@ -142,3 +354,33 @@ pub const INVALID_REQUEST: i32 = -32600;
 pub const METHOD_NOT_FOUND: i32 = -32601;
 pub const INVALID_PARAMS: i32 = -32602;
 pub const INTERNAL_ERROR: i32 = -32603;
+pub const RESOURCE_NOT_FOUND: i32 = -32002;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        for version in ProtocolVersion::ALL {
+            assert_eq!(
+                ProtocolVersion::negotiate(version.as_str()).unwrap(),
+                version
+            );
+        }
+        // Clients ahead of us are offered the latest revision we speak
+        assert_eq!(
+            ProtocolVersion::negotiate("2099-01-01").unwrap(),
+            ProtocolVersion::default()
+        );
+
+        let error = ProtocolVersion::negotiate("2024-01-01").unwrap_err();
+        assert_eq!(error.code, INVALID_PARAMS);
+        assert_eq!(error.data.unwrap()["supported"][0], "2024-11-05");
+        assert!(ProtocolVersion::negotiate("latest").is_err());
+
+        assert!(!ProtocolVersion::V2024_11_05.has_tool_annotations());
+        assert!(!ProtocolVersion::V2025_03_26.has_structured_content());
+        assert!(ProtocolVersion::V2025_06_18.has_structured_content());
+    }
+}
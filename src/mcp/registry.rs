@@ -0,0 +1,183 @@
+//! Tool registry
+//!
+//! Every tool is a `ToolHandler`: it declares its definition (name,
+//! description, input schema, annotations), whether the current settings
+//! offer it, and how to run a call. The registry holds the built-in tools
+//! and any an embedder registers through `McpService::with_tool`; on top of
+//! those it offers the `[command_tools]` of the config and the extension
+//! requests of the servers in use, which change as the config is reloaded.
+
+use crate::lsp::LspManager;
+use crate::mcp::command_tools::CommandTool;
+use crate::mcp::logging::McpLogger;
+use crate::mcp::protocol::{CallToolResult, Tool};
+use crate::mcp::tools;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// What a tool call runs with
+#[derive(Clone)]
+pub struct ToolContext {
+    pub lsp_manager: Arc<LspManager>,
+    /// For progress messages to the client
+    pub logger: McpLogger,
+}
+
+/// A tool: its definition and how to call it
+///
+/// Arguments are checked against the definition's input schema, and the
+/// file they name against the workspace, before `call` sees them (see
+/// `mcp::validation`).
+pub trait ToolHandler: Send + Sync {
+    fn definition(&self) -> Tool;
+
+    fn name(&self) -> String {
+        self.definition().name
+    }
+
+    /// Whether the tool is on offer with the current settings
+    fn is_available(&self, _lsp_manager: &LspManager) -> bool {
+        true
+    }
+
+    /// Whether calls go to language servers, so a `file` they name must
+    /// be of a language some server handles
+    fn uses_servers(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: Value, ctx: ToolContext) -> BoxFuture<'static, CallToolResult>;
+}
+
+/// Runs a call of a tool defined by a function
+pub type ToolFn = fn(Value, ToolContext) -> BoxFuture<'static, CallToolResult>;
+
+/// A tool made of a definition and a function, as the built-in tools are
+pub struct FnTool {
+    definition: Tool,
+    handler: ToolFn,
+    available: fn(&LspManager) -> bool,
+}
+
+impl FnTool {
+    pub fn new(definition: Tool, handler: ToolFn) -> Self {
+        Self {
+            definition,
+            handler,
+            available: |_| true,
+        }
+    }
+
+    /// Offer the tool only when `available` says so
+    pub fn with_availability(mut self, available: fn(&LspManager) -> bool) -> Self {
+        self.available = available;
+        self
+    }
+}
+
+impl ToolHandler for FnTool {
+    fn definition(&self) -> Tool {
+        self.definition.clone()
+    }
+
+    fn name(&self) -> String {
+        self.definition.name.clone()
+    }
+
+    fn is_available(&self, lsp_manager: &LspManager) -> bool {
+        (self.available)(lsp_manager)
+    }
+
+    fn call(&self, args: Value, ctx: ToolContext) -> BoxFuture<'static, CallToolResult> {
+        (self.handler)(args, ctx)
+    }
+}
+
+/// The tools a server offers
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    /// In the order they are listed
+    tools: Vec<Arc<dyn ToolHandler>>,
+}
+
+impl ToolRegistry {
+    /// A registry without any tools
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry of the built-in tools
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        for tool in tools::builtin_tools() {
+            registry.register(tool);
+        }
+        registry
+    }
+
+    /// Add a tool, replacing any of the same name
+    pub fn register(&mut self, tool: impl ToolHandler + 'static) {
+        let tool: Arc<dyn ToolHandler> = Arc::new(tool);
+        let name = tool.name();
+        match self
+            .tools
+            .iter_mut()
+            .find(|registered| registered.name() == name)
+        {
+            Some(registered) => *registered = tool,
+            None => self.tools.push(tool),
+        }
+    }
+
+    /// Every tool, on offer or not: the registered ones, then the config's
+    /// command tools and the servers' extension tools, unless a registered
+    /// tool has their name
+    fn all(&self, lsp_manager: &LspManager) -> Vec<Arc<dyn ToolHandler>> {
+        let mut tools = self.tools.clone();
+        let configured = lsp_manager
+            .command_tools()
+            .into_iter()
+            .map(|(name, config)| Arc::new(CommandTool::new(name, config)) as Arc<dyn ToolHandler>);
+        let extensions = tools::extension_tools(lsp_manager)
+            .into_iter()
+            .map(|tool| Arc::new(tool) as Arc<dyn ToolHandler>);
+        for tool in configured.chain(extensions) {
+            let name = tool.name();
+            if !tools.iter().any(|existing| existing.name() == name) {
+                tools.push(tool);
+            }
+        }
+        tools
+    }
+
+    /// The tools the settings and `[tools]` offer
+    pub fn tools(&self, lsp_manager: &LspManager) -> Vec<Arc<dyn ToolHandler>> {
+        let config = lsp_manager.tools_config();
+        self.all(lsp_manager)
+            .into_iter()
+            .filter(|tool| tool.is_available(lsp_manager) && config.is_enabled(&tool.name()))
+            .collect()
+    }
+
+    /// The definitions of the tools on offer, under their own names
+    /// (`McpService::list_tools` applies `[tools]` renames)
+    pub fn definitions(&self, lsp_manager: &LspManager) -> Vec<Tool> {
+        self.tools(lsp_manager)
+            .iter()
+            .map(|tool| tool.definition())
+            .collect()
+    }
+
+    /// The tool offered as `name`, after `[tools]` renames
+    ///
+    /// Tools the settings don't offer are found too, so that calling one
+    /// says how to turn it on.
+    pub fn get(&self, name: &str, lsp_manager: &LspManager) -> Option<Arc<dyn ToolHandler>> {
+        let config = lsp_manager.tools_config();
+        self.all(lsp_manager)
+            .into_iter()
+            .filter(|tool| config.exposed_name(&tool.name()) == name)
+            .max_by_key(|tool| config.is_enabled(&tool.name()))
+    }
+}
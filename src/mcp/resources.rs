@@ -0,0 +1,42 @@
+//! MCP resources
+//!
+//! `lsmcp://problems` is the workspace's diagnostics summary (see
+//! `lsp::problems`) as JSON. Clients can subscribe to it and are sent
+//! `notifications/resources/updated` whenever the counts change.
+
+use crate::lsp::problems;
+use crate::lsp::LspManager;
+use crate::mcp::protocol::{Resource, ResourceContents};
+
+/// The workspace problems summary
+pub const PROBLEMS_URI: &str = "lsmcp://problems";
+
+/// The resources on offer
+pub fn list() -> Vec<Resource> {
+    vec![Resource {
+        uri: PROBLEMS_URI.to_string(),
+        name: "problems".to_string(),
+        description: Some(
+            "Diagnostics published by every language server, counted by severity, source and file (most errors first). Subscribe to be told when they change.".to_string(),
+        ),
+        mime_type: Some("application/json".to_string()),
+    }]
+}
+
+/// Whether `uri` is one of ours
+pub fn exists(uri: &str) -> bool {
+    uri == PROBLEMS_URI
+}
+
+/// The contents of `uri`, if it is one of ours
+pub fn read(uri: &str, lsp_manager: &LspManager) -> serde_json::Result<Option<ResourceContents>> {
+    if uri != PROBLEMS_URI {
+        return Ok(None);
+    }
+    let summary = problems::global().summary(Some(lsp_manager.workspace_root()));
+    Ok(Some(ResourceContents {
+        uri: uri.to_string(),
+        mime_type: Some("application/json".to_string()),
+        text: serde_json::to_string_pretty(&summary)?,
+    }))
+}
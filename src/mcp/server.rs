@@ -1,82 +1,289 @@
 //! MCP server implementation
 //!
 //! Implements the Model Context Protocol server that exposes LSP
-//! functionality as MCP tools via stdio.
+//! functionality as MCP tools via stdio, or over a daemon connection.
 
+use crate::lsp::partial;
+use crate::lsp::problems;
 use crate::lsp::LspManager;
+use crate::mcp::logging::McpLogger;
+use crate::mcp::progress::ProgressReporter;
 use crate::mcp::protocol::*;
-use crate::mcp::tools;
+use crate::mcp::resources;
+use crate::mcp::service::McpService;
+use crate::trace::{self, Direction};
 use anyhow::Result;
+use serde::Serialize;
 use serde_json::Value;
 use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, error, info};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::AbortHandle;
+use tracing::{debug, error, info, warn, Instrument};
+
+/// How long servers must stop starting and stopping before the tool list
+/// is compared again, so a burst of startups sends one notification
+const TOOL_CHANGE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How long diagnostics must stop changing before subscribers are told,
+/// since servers publish them a file at a time
+const PROBLEMS_DEBOUNCE: Duration = Duration::from_secs(1);
 
 pub struct McpServer {
-    lsp_manager: Arc<LspManager>,
+    /// Tools and resources, which this server puts on the wire
+    service: McpService,
     initialized: Arc<Mutex<bool>>,
+    /// Protocol revision agreed on in `initialize`
+    protocol: std::sync::Mutex<ProtocolVersion>,
+    writer: MessageWriter,
+    /// The tool list the client last saw, once it asked for one
+    listed_tools: Arc<std::sync::Mutex<Option<Value>>>,
+    /// Task sending `notifications/tools/list_changed`, once initialized
+    tool_notifier: std::sync::Mutex<Option<AbortHandle>>,
+    /// Task sending `notifications/resources/updated` for the problems
+    /// resource, while the client is subscribed to it
+    problems_notifier: std::sync::Mutex<Option<AbortHandle>>,
+    /// Stop serving when the client sends nothing for this long (stdio only)
+    idle_exit: Option<Duration>,
+    /// Set once the client asked us to shut down
+    stopping: AtomicBool,
+}
+
+/// Whether outgoing messages are checked before they're written
+static STRICT_STDIO: AtomicBool = AtomicBool::new(true);
+
+/// Check every message sent to MCP clients is a JSON-RPC 2.0 frame, sending
+/// anything else to the log (and, on stdio, to stderr) instead
+///
+/// On unless the settings' `strict_stdio` is `false`.
+pub fn set_strict_stdio(strict: bool) {
+    STRICT_STDIO.store(strict, Ordering::Relaxed);
+}
+
+/// Why `line` isn't a single-line JSON-RPC 2.0 message, if it isn't one
+pub fn frame_error(line: &str) -> Option<String> {
+    if line.contains('\n') {
+        return Some("it spans several lines".to_string());
+    }
+    let message = match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(message)) => message,
+        Ok(_) => return Some("it isn't a JSON object".to_string()),
+        Err(e) => return Some(format!("it isn't JSON ({})", e)),
+    };
+    if message.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Some("it has no `\"jsonrpc\": \"2.0\"`".to_string());
+    }
+    if let Some(method) = message.get("method") {
+        return (!method.is_string()).then(|| "its method isn't a string".to_string());
+    }
+    if !message.contains_key("id") {
+        return Some("it has neither a method nor an id".to_string());
+    }
+    match (
+        message.contains_key("result"),
+        message.contains_key("error"),
+    ) {
+        (true, false) | (false, true) => None,
+        _ => Some("a response needs exactly one of result and error".to_string()),
+    }
+}
+
+/// Where a server's outgoing messages go
+#[derive(Clone)]
+pub enum MessageWriter {
+    /// This process's stdout
+    Stdout,
+    /// A connection's writer task, one line per message
+    Channel(mpsc::UnboundedSender<String>),
+    /// Nowhere, for an embedded `McpService` nobody listens to
+    Discard,
+}
+
+impl MessageWriter {
+    /// Write one newline-delimited JSON message
+    ///
+    /// Stdout is written under its lock so notifications sent while a tool
+    /// call is running never interleave with responses.
+    pub fn write<T: Serialize>(&self, message: &T) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        if STRICT_STDIO.load(Ordering::Relaxed) && !matches!(self, Self::Discard) {
+            if let Some(problem) = frame_error(&line) {
+                error!("Not sending a message to the client: {}: {}", problem, line);
+                if matches!(self, Self::Stdout) {
+                    eprintln!("{}", line);
+                }
+                return Ok(());
+            }
+        }
+        trace::mcp(Direction::Out, &line);
+        line.push('\n');
+
+        match self {
+            Self::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()
+            }
+            Self::Channel(tx) => tx.send(line).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "connection closed")
+            }),
+            Self::Discard => Ok(()),
+        }
+    }
 }
 
 impl McpServer {
     pub fn new(lsp_manager: Arc<LspManager>) -> Self {
+        Self::with_writer(lsp_manager, MessageWriter::Stdout)
+    }
+
+    /// A server sending its responses and notifications to `writer`
+    pub fn with_writer(lsp_manager: Arc<LspManager>, writer: MessageWriter) -> Self {
         Self {
-            lsp_manager,
+            service: McpService::with_writer(lsp_manager, writer.clone()),
             initialized: Arc::new(Mutex::new(false)),
+            protocol: std::sync::Mutex::new(ProtocolVersion::default()),
+            writer,
+            listed_tools: Arc::default(),
+            tool_notifier: std::sync::Mutex::new(None),
+            problems_notifier: std::sync::Mutex::new(None),
+            idle_exit: None,
+            stopping: AtomicBool::new(false),
         }
     }
 
-    /// Run the MCP server (blocking)
+    /// Stop serving stdio once the client has sent nothing for `limit`, so
+    /// a server its client forgot about doesn't run forever
+    pub fn with_idle_exit(mut self, limit: Option<Duration>) -> Self {
+        self.idle_exit = limit;
+        self
+    }
+
+    /// Scope this server's documents to a daemon session
+    pub fn with_session(mut self, session: u64) -> Self {
+        self.service = self.service.clone().with_session(session);
+        self
+    }
+
+    /// The protocol revision agreed on with the client
+    fn protocol(&self) -> ProtocolVersion {
+        *self.protocol.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Logger for sending notifications to the client from outside a request
+    pub fn logger(&self) -> McpLogger {
+        self.service.logger().clone()
+    }
+
+    /// Run the MCP server on stdio until the client disconnects, asks us to
+    /// shut down or (with `with_idle_exit`) goes quiet
     pub async fn run(&self) -> Result<()> {
         info!("MCP server starting on stdio");
 
-        let stdin = std::io::stdin();
-        let mut stdin = stdin.lock();
-        let mut stdout = std::io::stdout();
+        // Read newline-delimited JSON on a thread of its own: a blocking
+        // read can't be cancelled, and would hold up an exit the client
+        // didn't start by closing stdin
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
 
         loop {
-            // Read newline-delimited JSON
-            let mut line = String::new();
-            match stdin.read_line(&mut line) {
-                Ok(0) => {
+            let next = match self.idle_exit {
+                Some(limit) => match tokio::time::timeout(limit, rx.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        info!(
+                            "No requests for {} minute(s), shutting down",
+                            limit.as_secs() / 60
+                        );
+                        return Ok(());
+                    }
+                },
+                None => rx.recv().await,
+            };
+            match next {
+                Some(Ok(line)) => {
+                    self.handle_line(&line).await?;
+                    if self.stopping.load(Ordering::Relaxed) {
+                        info!("Client asked us to shut down");
+                        return Ok(());
+                    }
+                }
+                Some(Err(e)) => {
+                    error!("Failed to read line: {}", e);
+                    return Err(e.into());
+                }
+                None => {
                     info!("Client closed connection");
                     return Ok(());
                 }
-                Ok(_) => {
-                    let line = line.trim();
+            }
+        }
+    }
 
-                    // Skip empty lines
-                    if line.is_empty() {
-                        continue;
-                    }
+    /// Serve requests read from a daemon connection until it closes or the
+    /// client asks us to shut down
+    pub async fn run_connection<R: AsyncBufRead + Unpin>(&self, reader: R) -> Result<()> {
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next_line().await? {
+            self.handle_line(&line).await?;
+            if self.stopping.load(Ordering::Relaxed) {
+                info!("Client asked us to shut down");
+                return Ok(());
+            }
+        }
+        info!("Client closed connection");
+        Ok(())
+    }
 
-                    debug!("Received request: {}", line);
+    /// Handle one newline-delimited JSON request and write the response
+    async fn handle_line(&self, line: &str) -> Result<()> {
+        let line = line.trim();
 
-                    // Handle request
-                    let response = self.handle_request(line).await;
+        // Skip empty lines
+        if line.is_empty() {
+            return Ok(());
+        }
 
-                    // Write response as newline-delimited JSON
-                    let response_json = serde_json::to_string(&response)?;
-                    stdout.write_all(response_json.as_bytes())?;
-                    stdout.write_all(b"\n")?;
-                    stdout.flush()?;
+        // Everything this request leads to shares its correlation ID
+        let correlation_id = trace::next_correlation_id();
+        let span = tracing::info_span!("mcp_request", correlation_id = %correlation_id);
+        trace::with_correlation_id(
+            correlation_id,
+            async {
+                debug!("Received request: {}", line);
+                trace::mcp(Direction::In, line);
 
-                    debug!("Sent response");
-                }
-                Err(e) => {
-                    error!("Failed to read line: {}", e);
-                    return Err(e.into());
-                }
+                // Handle request; notifications get no response
+                let Some(response) = self.handle_request(line).await else {
+                    return Ok(());
+                };
+
+                // Write response as newline-delimited JSON
+                self.writer.write(&response)?;
+
+                debug!("Sent response");
+                Ok(())
             }
-        }
+            .instrument(span),
+        )
+        .await
     }
 
-    async fn handle_request(&self, content: &str) -> JsonRpcResponse {
+    async fn handle_request(&self, content: &str) -> Option<JsonRpcResponse> {
         // Parse request
         let request: JsonRpcRequest = match serde_json::from_str(content) {
             Ok(req) => req,
             Err(e) => {
-                return JsonRpcResponse {
+                return Some(JsonRpcResponse {
                     jsonrpc: "2.0".to_string(),
                     id: Value::Null,
                     result: None,
@@ -85,17 +292,34 @@ impl McpServer {
                         message: format!("Parse error: {}", e),
                         data: None,
                     }),
-                };
+                });
             }
         };
 
-        let id = request.id.clone().unwrap_or(Value::Null);
+        let Some(id) = request.id.clone() else {
+            self.handle_notification(&request.method);
+            return None;
+        };
 
         // Handle method
         let result = match request.method.as_str() {
             "initialize" => self.handle_initialize(request.params).await,
             "tools/list" => self.handle_list_tools().await,
             "tools/call" => self.handle_call_tool(request.params).await,
+            "logging/setLevel" => self.handle_set_level(request.params),
+            "resources/list" => {
+                Ok(serde_json::json!({ "resources": self.service.list_resources() }))
+            }
+            "resources/templates/list" => Ok(serde_json::json!({ "resourceTemplates": [] })),
+            "resources/read" => self.handle_read_resource(request.params),
+            "resources/subscribe" => self.handle_subscribe(request.params, true),
+            "resources/unsubscribe" => self.handle_subscribe(request.params, false),
+            "ping" => Ok(serde_json::json!({})),
+            "shutdown" => {
+                // Stops once the response is written
+                self.stopping.store(true, Ordering::Relaxed);
+                Ok(serde_json::json!({}))
+            }
             _ => Err(JsonRpcError {
                 code: METHOD_NOT_FOUND,
                 message: format!("Method not found: {}", request.method),
@@ -103,40 +327,64 @@ impl McpServer {
             }),
         };
 
-        match result {
+        Some(match result {
             Ok(result) => JsonRpcResponse {
                 jsonrpc: "2.0".to_string(),
                 id,
                 result: Some(result),
                 error: None,
             },
-            Err(error) => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(error),
-            },
+            Err(mut error) => {
+                if error.data.is_none() {
+                    error.data = trace::correlation_id()
+                        .map(|id| serde_json::json!({ "correlation_id": id }));
+                }
+                JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id,
+                    result: None,
+                    error: Some(error),
+                }
+            }
+        })
+    }
+
+    /// Handle a notification from the client
+    fn handle_notification(&self, method: &str) {
+        match method {
+            "exit" | "notifications/exit" => self.stopping.store(true, Ordering::Relaxed),
+            _ => debug!("Ignoring notification: {}", method),
         }
     }
 
     async fn handle_initialize(&self, params: Option<Value>) -> Result<Value, JsonRpcError> {
-        let _params: InitializeParams = serde_json::from_value(params.unwrap_or(Value::Null))
+        let params: InitializeParams = serde_json::from_value(params.unwrap_or(Value::Null))
             .map_err(|e| JsonRpcError {
                 code: INVALID_PARAMS,
                 message: format!("Invalid initialize params: {}", e),
                 data: None,
             })?;
+        let protocol = ProtocolVersion::negotiate(&params.protocol_version)?;
+        if protocol.as_str() != params.protocol_version {
+            info!(
+                "Client asked for protocol {}, offering {}",
+                params.protocol_version,
+                protocol.as_str()
+            );
+        }
+        *self.protocol.lock().unwrap_or_else(|e| e.into_inner()) = protocol;
 
         *self.initialized.lock().await = true;
+        self.spawn_tool_notifier();
 
         let result = InitializeResult {
-            protocol_version: "2024-11-05".to_string(),
+            protocol_version: protocol.as_str().to_string(),
             capabilities: ServerCapabilities {
                 experimental: None,
-                logging: None,
+                logging: Some(serde_json::json!({})),
                 prompts: None,
-                resources: None,
-                tools: Some(serde_json::json!({})),
+                resources: Some(serde_json::json!({ "subscribe": true, "listChanged": false })),
+                tools: Some(serde_json::json!({ "listChanged": true })),
             },
             server_info: ServerInfo {
                 name: "lsmcp".to_string(),
@@ -151,16 +399,152 @@ impl McpServer {
         })
     }
 
+    fn handle_set_level(&self, params: Option<Value>) -> Result<Value, JsonRpcError> {
+        let params: SetLevelParams = serde_json::from_value(params.unwrap_or(Value::Null))
+            .map_err(|e| JsonRpcError {
+                code: INVALID_PARAMS,
+                message: format!("Invalid setLevel params: {}", e),
+                data: None,
+            })?;
+
+        self.service.logger().set_level(params.level);
+        Ok(serde_json::json!({}))
+    }
+
     async fn handle_list_tools(&self) -> Result<Value, JsonRpcError> {
-        let tools = tools::get_tool_definitions();
+        let result = list_tools(&self.service, self.protocol())
+            .await
+            .map_err(|e| JsonRpcError {
+                code: INTERNAL_ERROR,
+                message: format!("Failed to serialize tools: {}", e),
+                data: None,
+            })?;
+        *self.listed_tools.lock().unwrap_or_else(|e| e.into_inner()) = Some(result.clone());
+        Ok(result)
+    }
 
-        let result = ListToolsResult { tools };
+    /// Send `notifications/tools/list_changed` whenever servers starting or
+    /// stopping (or a configuration reload) change the tool list the
+    /// client last fetched
+    fn spawn_tool_notifier(&self) {
+        let mut changes = self.service.lsp_manager().watch_servers();
+        changes.mark_unchanged();
+        let service = self.service.clone();
+        let protocol = self.protocol();
+        let listed_tools = Arc::clone(&self.listed_tools);
+        let writer = self.writer.clone();
 
-        serde_json::to_value(result).map_err(|e| JsonRpcError {
-            code: INTERNAL_ERROR,
-            message: format!("Failed to serialize tools: {}", e),
-            data: None,
-        })
+        let task = tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                tokio::time::sleep(TOOL_CHANGE_DEBOUNCE).await;
+                changes.mark_unchanged();
+
+                let Ok(current) = list_tools(&service, protocol).await else {
+                    continue;
+                };
+                {
+                    let mut listed = listed_tools.lock().unwrap_or_else(|e| e.into_inner());
+                    // Nothing to refresh before the client's first listing
+                    if listed.as_ref().is_none_or(|listed| *listed == current) {
+                        continue;
+                    }
+                    *listed = Some(current);
+                }
+
+                let notification = JsonRpcNotification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "notifications/tools/list_changed".to_string(),
+                    params: None,
+                };
+                debug!("Tool list changed, notifying the client");
+                if let Err(e) = writer.write(&notification) {
+                    warn!("Failed to send tool list change: {}", e);
+                    break;
+                }
+            }
+        });
+
+        let previous = self
+            .tool_notifier
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(task.abort_handle());
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+    }
+
+    fn handle_read_resource(&self, params: Option<Value>) -> Result<Value, JsonRpcError> {
+        let params = resource_params(params)?;
+        let contents = self
+            .service
+            .read_resource(&params.uri)
+            .map_err(|e| JsonRpcError {
+                code: INTERNAL_ERROR,
+                message: format!("Failed to serialize resource: {}", e),
+                data: None,
+            })?
+            .ok_or_else(|| resource_not_found(&params.uri))?;
+        Ok(serde_json::json!({ "contents": [contents] }))
+    }
+
+    /// Start or stop notifying the client of changes to a resource
+    fn handle_subscribe(
+        &self,
+        params: Option<Value>,
+        subscribe: bool,
+    ) -> Result<Value, JsonRpcError> {
+        let params = resource_params(params)?;
+        if !resources::exists(&params.uri) {
+            return Err(resource_not_found(&params.uri));
+        }
+
+        let mut notifier = self
+            .problems_notifier
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if !subscribe {
+            if let Some(task) = notifier.take() {
+                task.abort();
+            }
+        } else if notifier.is_none() {
+            *notifier = Some(self.spawn_problems_notifier());
+        }
+        Ok(serde_json::json!({}))
+    }
+
+    /// Send `notifications/resources/updated` for the problems resource
+    /// whenever its summary changes
+    fn spawn_problems_notifier(&self) -> AbortHandle {
+        let mut changes = problems::global().watch();
+        changes.mark_unchanged();
+        let root = self.service.lsp_manager().workspace_root().to_path_buf();
+        let writer = self.writer.clone();
+
+        let task = tokio::spawn(async move {
+            let mut last = problems::global().summary(Some(&root));
+            while changes.changed().await.is_ok() {
+                tokio::time::sleep(PROBLEMS_DEBOUNCE).await;
+                changes.mark_unchanged();
+
+                let current = problems::global().summary(Some(&root));
+                if current == last {
+                    continue;
+                }
+                last = current;
+
+                let notification = JsonRpcNotification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "notifications/resources/updated".to_string(),
+                    params: Some(serde_json::json!({ "uri": resources::PROBLEMS_URI })),
+                };
+                if let Err(e) = writer.write(&notification) {
+                    warn!("Failed to send resource update: {}", e);
+                    break;
+                }
+            }
+        });
+        task.abort_handle()
     }
 
     async fn handle_call_tool(&self, params: Option<Value>) -> Result<Value, JsonRpcError> {
@@ -179,12 +563,41 @@ impl McpServer {
                 data: None,
             })?;
 
-        let result = tools::call_tool(
-            &params.name,
-            params.arguments,
-            Arc::clone(&self.lsp_manager),
+        // Streamed partial results are reported when the client asked for
+        // progress
+        let partial_results = params
+            .meta
+            .and_then(|meta| meta.progress_token)
+            .map(|token| {
+                ProgressReporter::new(token, self.writer.clone())
+                    .with_messages(self.protocol().has_progress_messages())
+                    .partial_results()
+            });
+        let mut result = partial::scope(
+            partial_results,
+            self.service.call_tool(&params.name, params.arguments),
         )
         .await;
+        if result.is_error == Some(true) {
+            // Lets a failure be found in the log and trace file
+            if let Some(id) = trace::correlation_id() {
+                if let Some(ToolContent::Text { text }) = result.content.first_mut() {
+                    text.push_str(&format!("\n(correlation id: {})", id));
+                }
+                if let Some(error) = result
+                    .structured_content
+                    .as_mut()
+                    .and_then(|content| content.get_mut("error"))
+                    .and_then(Value::as_object_mut)
+                {
+                    error.insert("correlation_id".to_string(), id.into());
+                }
+            }
+        }
+
+        if !self.protocol().has_structured_content() {
+            result.structured_content = None;
+        }
 
         serde_json::to_value(result).map_err(|e| JsonRpcError {
             code: INTERNAL_ERROR,
@@ -193,3 +606,100 @@ impl McpServer {
         })
     }
 }
+
+impl Drop for McpServer {
+    fn drop(&mut self) {
+        for notifier in [&mut self.tool_notifier, &mut self.problems_notifier] {
+            if let Some(notifier) = notifier.get_mut().unwrap_or_else(|e| e.into_inner()).take() {
+                notifier.abort();
+            }
+        }
+    }
+}
+
+/// Params naming a resource
+fn resource_params(params: Option<Value>) -> Result<ResourceParams, JsonRpcError> {
+    serde_json::from_value(params.unwrap_or(Value::Null)).map_err(|e| JsonRpcError {
+        code: INVALID_PARAMS,
+        message: format!("Invalid resource params: {}", e),
+        data: None,
+    })
+}
+
+fn resource_not_found(uri: &str) -> JsonRpcError {
+    JsonRpcError {
+        code: RESOURCE_NOT_FOUND,
+        message: format!("Resource not found: {}", uri),
+        data: Some(serde_json::json!({ "uri": uri })),
+    }
+}
+
+/// The `tools/list` result, without annotations for clients too old for
+/// them
+async fn list_tools(service: &McpService, protocol: ProtocolVersion) -> serde_json::Result<Value> {
+    let mut tools = service.list_tools().await;
+    if !protocol.has_tool_annotations() {
+        for tool in &mut tools {
+            tool.annotations = None;
+        }
+    }
+
+    serde_json::to_value(ListToolsResult { tools })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigLoader;
+
+    #[test]
+    fn test_frame_error() {
+        assert_eq!(frame_error(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#), None);
+        assert_eq!(
+            frame_error(r#"{"jsonrpc":"2.0","method":"notifications/message"}"#),
+            None
+        );
+        assert!(frame_error("Starting lsmcp v0.1.0").is_some());
+        assert!(frame_error(r#"{"id":1,"result":{}}"#).is_some());
+        assert!(frame_error(r#"{"jsonrpc":"2.0","id":1}"#).is_some());
+        assert!(frame_error("{\"jsonrpc\":\"2.0\",\n\"id\":1,\"result\":1}").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_connection_writes_only_frames() {
+        let workspace = tempfile::tempdir().unwrap();
+        let config = ConfigLoader::with_user_config(None).unwrap();
+        let manager = LspManager::builder(workspace.path())
+            .with_config(config)
+            .build()
+            .unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = McpServer::with_writer(manager, MessageWriter::Channel(tx));
+
+        let requests = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-06-18","capabilities":{},"clientInfo":{"name":"test","version":"1"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            "not json at all",
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"no/such/method"}"#,
+        ]
+        .join("\n");
+        server.run_connection(requests.as_bytes()).await.unwrap();
+        drop(server);
+
+        let mut responses = 0;
+        while let Ok(line) = rx.try_recv() {
+            assert!(line.ends_with('\n'));
+            assert_eq!(frame_error(line.trim_end()), None, "{}", line);
+            responses += line.contains("\"id\"") as usize;
+        }
+        assert!(responses >= 4, "{}", responses);
+
+        // What isn't a frame never reaches the client
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        MessageWriter::Channel(tx)
+            .write(&serde_json::json!({ "banner": "lsmcp" }))
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
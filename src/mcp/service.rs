@@ -0,0 +1,170 @@
+//! Tool dispatch without a transport
+//!
+//! `McpService` is what `McpServer` answers `tools/*` and `resources/*`
+//! with, minus JSON-RPC and stdio, so Rust programs can embed lsmcp's
+//! tools and call them directly. Logging and progress notifications go
+//! nowhere unless the embedder asks for them.
+
+use crate::lsp::LspManager;
+use crate::mcp::budget;
+use crate::mcp::logging::McpLogger;
+use crate::mcp::protocol::{CallToolResult, Resource, ResourceContents, Tool};
+use crate::mcp::registry::{ToolContext, ToolHandler, ToolRegistry};
+use crate::mcp::resources;
+use crate::mcp::server::MessageWriter;
+use crate::mcp::tools;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// lsmcp's tools and resources for one workspace
+#[derive(Clone)]
+pub struct McpService {
+    lsp_manager: Arc<LspManager>,
+    registry: Arc<ToolRegistry>,
+    logger: McpLogger,
+    /// Daemon session the calls are made for (see `LspManager::open_session`)
+    session: Option<u64>,
+}
+
+impl McpService {
+    /// A service whose notifications are dropped
+    pub fn new(lsp_manager: Arc<LspManager>) -> Self {
+        Self::with_writer(lsp_manager, MessageWriter::Discard)
+    }
+
+    /// A service sending its notifications to `writer`
+    pub fn with_writer(lsp_manager: Arc<LspManager>, writer: MessageWriter) -> Self {
+        Self {
+            lsp_manager,
+            registry: Arc::new(ToolRegistry::builtin()),
+            logger: McpLogger::with_writer(writer),
+            session: None,
+        }
+    }
+
+    /// Send `notifications/message` sent during tool calls to `tx`, one
+    /// JSON-RPC message per string
+    pub fn with_notifications(self, tx: mpsc::UnboundedSender<String>) -> Self {
+        Self {
+            logger: McpLogger::with_writer(MessageWriter::Channel(tx)),
+            ..self
+        }
+    }
+
+    /// Offer `tool` too, in place of any tool of the same name
+    pub fn with_tool(mut self, tool: impl ToolHandler + 'static) -> Self {
+        Arc::make_mut(&mut self.registry).register(tool);
+        self
+    }
+
+    /// Offer the tools of `registry` instead of the built-in ones
+    pub fn with_registry(mut self, registry: ToolRegistry) -> Self {
+        self.registry = Arc::new(registry);
+        self
+    }
+
+    /// Scope the calls' documents to a daemon session
+    pub fn with_session(mut self, session: u64) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn lsp_manager(&self) -> &Arc<LspManager> {
+        &self.lsp_manager
+    }
+
+    /// Logger the tool calls report progress with
+    pub fn logger(&self) -> &McpLogger {
+        &self.logger
+    }
+
+    /// Every tool on offer, with extension tools for the configured servers
+    /// and notes on what running servers can't answer, named (and naming
+    /// the tools they batch) as `[tools]` says and taking a
+    /// `max_response_chars` argument
+    pub async fn list_tools(&self) -> Vec<Tool> {
+        let mut tools = self.registry.definitions(&self.lsp_manager);
+        tools::annotate_unsupported(&mut tools, &self.lsp_manager).await;
+        let config = self.lsp_manager.tools_config();
+        for tool in &mut tools {
+            tool.name = config.exposed_name(&tool.name);
+            tools::expose_batch_tools(&mut tool.input_schema, &config);
+            budget::add_argument(&mut tool.input_schema);
+        }
+        tools
+    }
+
+    /// Call a tool; failures come back as results with `is_error` set
+    pub async fn call_tool(&self, name: &str, arguments: Option<Value>) -> CallToolResult {
+        let started = Instant::now();
+        let ctx = ToolContext {
+            lsp_manager: Arc::clone(&self.lsp_manager),
+            logger: self.logger.clone(),
+        };
+        let call = tools::call_tool(&self.registry, name, arguments, ctx);
+        let result = match self.session {
+            Some(session) => self.lsp_manager.in_session(session, call).await,
+            None => call.await,
+        };
+        crate::metrics::global().record_tool(
+            name,
+            started.elapsed(),
+            result.is_error == Some(true),
+        );
+        result
+    }
+
+    /// The resources on offer
+    pub fn list_resources(&self) -> Vec<Resource> {
+        resources::list()
+    }
+
+    /// The contents of a resource, or `None` for an unknown URI
+    pub fn read_resource(&self, uri: &str) -> serde_json::Result<Option<ResourceContents>> {
+        resources::read(uri, &self.lsp_manager)
+    }
+
+    /// Shut every language server down
+    pub async fn shutdown(&self) {
+        self.lsp_manager.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigLoader;
+
+    #[tokio::test]
+    async fn test_call_tool_without_transport() {
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::write(workspace.path().join("notes.txt"), "hello\n").unwrap();
+        let config = ConfigLoader::with_user_config(None).unwrap();
+        let manager = LspManager::builder(workspace.path())
+            .with_config(config)
+            .build()
+            .unwrap();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let service = McpService::new(manager).with_notifications(tx);
+
+        let tools = service.list_tools().await;
+        assert!(tools.iter().any(|tool| tool.name == "lsp_find_files"));
+
+        let result = service
+            .call_tool(
+                "lsp_find_files",
+                Some(serde_json::json!({ "pattern": "*.txt" })),
+            )
+            .await;
+        assert_eq!(result.is_error, None);
+        assert!(service
+            .read_resource(resources::PROBLEMS_URI)
+            .unwrap()
+            .is_some());
+
+        let result = service.call_tool("lsp_find_files", None).await;
+        assert_eq!(result.is_error, Some(true));
+    }
+}
@@ -2,14 +2,46 @@
 //!
 //! Defines and implements all MCP tools that expose LSP functionality
 
+use crate::check::{self, CheckFormat};
+use crate::config::{EditPolicy, ExtensionParams, ExtensionRequest, LocationFormat, ToolsConfig};
+use crate::installer::{InstallEvent, InstallSummary};
+use crate::lsp::merge;
+use crate::lsp::overlay;
+use crate::lsp::prepare;
+use crate::lsp::problems::{self, ProblemsSummary, SeverityCounts};
+use crate::lsp::runnables::Runnable;
+use crate::lsp::symbols::{self, SymbolMatch};
 use crate::lsp::LspManager;
-use crate::mcp::protocol::{CallToolResult, Tool, ToolContent};
+use crate::mcp::budget;
+use crate::mcp::locations;
+use crate::mcp::logging::McpLogger;
+use crate::mcp::protocol::{CallToolResult, LoggingLevel, Tool, ToolAnnotations, ToolContent};
+use crate::mcp::registry::{FnTool, ToolContext, ToolFn, ToolHandler, ToolRegistry};
+use crate::mcp::validation;
+use crate::tools::hover::{HoverRenderer, DEFAULT_HOVER_MAX_LENGTH};
+use crate::tools::symbols::{NameMatch, SymbolFilter};
+use crate::tools::{edit_check, edits, files, impact, outline, run, search, unused};
+use crate::types::LspError;
+use crate::utils::dependency::dependency_source;
+use crate::utils::git;
+use crate::utils::glob::glob_match;
+use crate::utils::position::{
+    clamp_position, convert_character, line_count, line_text, PositionEncoding,
+};
+use crate::utils::sandbox::canonical;
+use crate::utils::sarif;
+use crate::utils::uri::display_path;
+use futures::future::BoxFuture;
+use futures::StreamExt;
 use lsp_types::*;
-use serde::Deserialize;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::error;
+use std::time::Duration;
+use tracing::{debug, error};
 
 /// Get all tool definitions
 pub fn get_tool_definitions() -> Vec<Tool> {
@@ -24,17 +56,24 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                         "type": "string",
                         "description": "Absolute path to the file"
                     },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
                     "line": {
                         "type": "integer",
-                        "description": "Line number (0-indexed)"
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
                     },
                     "character": {
                         "type": "integer",
-                        "description": "Character offset in line (0-indexed)"
-                    }
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                    },
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema(),
+                    "index_base": index_base_schema(),
+                    "include_source": include_source_schema()
                 },
                 "required": ["file", "line", "character"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
         Tool {
             name: "lsp_find_references".to_string(),
@@ -46,14 +85,19 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                         "type": "string",
                         "description": "Absolute path to the file"
                     },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
                     "line": {
                         "type": "integer",
-                        "description": "Line number (0-indexed)"
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
                     },
                     "character": {
                         "type": "integer",
-                        "description": "Character offset in line (0-indexed)"
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
                     },
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema(),
+                    "index_base": index_base_schema(),
                     "includeDeclaration": {
                         "type": "boolean",
                         "description": "Include the declaration in results",
@@ -62,6 +106,42 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                 },
                 "required": ["file", "line", "character"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_impact_analysis".to_string(),
+            description: "Answer \"what breaks if I change this?\" for the symbol at a position: finds its references and the functions, methods or types containing them, as a tree. With `depth` above 1, the references to those symbols are followed too.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
+                    "line": {
+                        "type": "integer",
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
+                    },
+                    "character": {
+                        "type": "integer",
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                    },
+                    "depth": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": impact::MAX_IMPACT_DEPTH,
+                        "description": "Levels to follow: 1 for the symbols referring to this one, 2 to add the symbols referring to those, and so on",
+                        "default": impact::DEFAULT_IMPACT_DEPTH
+                    },
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema(),
+                    "index_base": index_base_schema()
+                },
+                "required": ["file", "line", "character"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
         Tool {
             name: "lsp_hover".to_string(),
@@ -73,17 +153,38 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                         "type": "string",
                         "description": "Absolute path to the file"
                     },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
                     "line": {
                         "type": "integer",
-                        "description": "Line number (0-indexed)"
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
                     },
                     "character": {
                         "type": "integer",
-                        "description": "Character offset in line (0-indexed)"
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                    },
+                    "position_encoding": position_encoding_schema(),
+                    "index_base": index_base_schema(),
+                    "format": {
+                        "type": "string",
+                        "enum": ["markdown", "plaintext"],
+                        "description": "Render the hover as markdown (default) or as plain text without markup",
+                        "default": "markdown"
+                    },
+                    "max_length": {
+                        "type": "integer",
+                        "description": "Maximum characters to return; longer hovers keep the signature and first paragraph of documentation. 0 disables the limit",
+                        "default": DEFAULT_HOVER_MAX_LENGTH
+                    },
+                    "strip_html": {
+                        "type": "boolean",
+                        "description": "Remove HTML tags and decode HTML entities embedded in documentation",
+                        "default": true
                     }
                 },
                 "required": ["file", "line", "character"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
         Tool {
             name: "lsp_document_symbols".to_string(),
@@ -94,10 +195,43 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                     "file": {
                         "type": "string",
                         "description": "Absolute path to the file"
-                    }
+                    },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
+                    "position_encoding": position_encoding_schema()
                 },
                 "required": ["file"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_enclosing_symbol".to_string(),
+            description: "Find which symbol a position is in: the innermost one (e.g. the function) and the chain of symbols around it, outermost first (module, impl or class, function). Use it to tell what function a diagnostic or search hit is in.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
+                    "line": {
+                        "type": "integer",
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
+                    },
+                    "character": {
+                        "type": "integer",
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units",
+                        "default": 0
+                    },
+                    "position_encoding": position_encoding_schema(),
+                    "index_base": index_base_schema(),
+"location_format": location_format_schema()
+                },
+                "required": ["file", "line"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
         Tool {
             name: "lsp_diagnostics".to_string(),
@@ -108,14 +242,151 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                     "file": {
                         "type": "string",
                         "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "content": file_content_schema(),
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema(),
+                    "severity": {
+                        "type": "string",
+                        "enum": ["error", "warning", "information", "hint"],
+                        "description": "Minimum severity to return, e.g. \"error\" for errors only (default: all)"
+                    },
+                    "source": {
+                        "type": "string",
+                        "description": "Only diagnostics from this source, e.g. \"clippy\" or \"eslint\" (case-insensitive)"
+                    },
+                    "code": {
+                        "type": ["string", "integer"],
+                        "description": "Only diagnostics with this code, e.g. \"E0308\""
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": "Maximum number of diagnostics to list; counts still cover every match"
+                    },
+                    "include_related": {
+                        "type": "boolean",
+                        "description": "Show the source lines of each diagnostic's related locations",
+                        "default": false
+                    },
+                    "include_fixes": {
+                        "type": "boolean",
+                        "description": "Ask the server for quick fixes for each listed diagnostic and show their edits",
+                        "default": false
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["text", "sarif"],
+                        "description": "Readable list (default) or a SARIF 2.1.0 log of the listed diagnostics, for code scanning tools",
+                        "default": "text"
                     }
                 },
                 "required": ["file"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_diagnostics_after_edit".to_string(),
+            description: "Check a proposed edit before making it: applies `old_text` → `new_text` replacements to the file in memory, has the server diagnose the result, then restores the file's own content. Reports the diagnostics the edit introduces and resolves, and whether it introduces errors. The file on disk is never changed.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "edits": {
+                        "type": "array",
+                        "description": "Replacements, applied in order; each `old_text` must occur exactly once in the file as edited so far",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "old_text": { "type": "string" },
+                                "new_text": { "type": "string" }
+                            },
+                            "required": ["old_text", "new_text"]
+                        },
+                        "minItems": 1
+                    },
+                    "wait_secs": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": edit_check::MAX_WAIT_SECS,
+                        "description": "How long to wait for the server to publish diagnostics for each version of the file",
+                        "default": edit_check::DEFAULT_WAIT_SECS
+                    },
+                    "position_encoding": position_encoding_schema()
+                },
+                "required": ["file", "edits"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_problems_summary".to_string(),
+            description: "Summarize the diagnostics every language server has published for the workspace: error, warning, info and hint counts overall, by source and by file (most errors first). Covers the files servers have diagnosed so far, typically those opened. Also available as the `lsmcp://problems` resource, which clients can subscribe to for updates as fixes land.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "max_files": {
+                        "type": "integer",
+                        "description": "Most files to list; totals still cover every file",
+                        "default": DEFAULT_PROBLEM_FILES
+                    },
+                    "location_format": location_format_schema()
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_diagnostics_changed".to_string(),
+            description: "Get the diagnostics of every file changed on the current git branch: its commits since it left `base`, uncommitted edits and untracked files. Opens each changed file its language servers handle and waits for their diagnostics, so it reviews a branch before a pull request without diagnosing the whole workspace.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "base": {
+                        "type": "string",
+                        "description": "Branch, tag or commit the branch is compared with, e.g. \"origin/main\" (default: the remote's default branch, else main or master)"
+                    },
+                    "severity": {
+                        "type": "string",
+                        "enum": ["error", "warning", "information", "hint"],
+                        "description": "Minimum severity to return, e.g. \"error\" for errors only (default: all)"
+                    },
+                    "source": {
+                        "type": "string",
+                        "description": "Only diagnostics from this source, e.g. \"clippy\" or \"eslint\" (case-insensitive)"
+                    },
+                    "code": {
+                        "type": ["string", "integer"],
+                        "description": "Only diagnostics with this code, e.g. \"E0308\""
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Most changed files to diagnose",
+                        "default": DEFAULT_CHANGED_FILES
+                    },
+                    "wait_secs": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": edit_check::MAX_WAIT_SECS,
+                        "description": "How long to wait for each file's diagnostics",
+                        "default": edit_check::DEFAULT_WAIT_SECS
+                    },
+                    "format": {
+                        "type": "string",
+                        "enum": ["text", "sarif"],
+                        "description": "Readable list (default) or a SARIF 2.1.0 log of the diagnostics, for code scanning tools",
+                        "default": "text"
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
         Tool {
             name: "lsp_workspace_symbols".to_string(),
-            description: "Search for symbols across the entire workspace by name or pattern. Useful for finding functions, classes, variables, etc. across multiple files.".to_string(),
+            description: "Search for symbols across the entire workspace by name or pattern. Useful for finding functions, classes, variables, etc. across multiple files. Narrow the server's answer with `kinds`, `path_prefix`, `exclude_globs` and `match`, e.g. structs named `*Config` under `src/`.".to_string(),
             input_schema: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -126,383 +397,4850 @@ pub fn get_tool_definitions() -> Vec<Tool> {
                     "language": {
                         "type": "string",
                         "description": "Language to search in (e.g., 'rust', 'typescript', 'python', 'go')"
-                    }
+                    },
+                    "match": {
+                        "type": "string",
+                        "enum": ["fuzzy", "exact", "glob"],
+                        "description": "How names match `query`: \"fuzzy\" as the server matches them, \"exact\" for names equal to it, \"glob\" for names matching it with `*` and `?` (e.g. `*Config`)",
+                        "default": "fuzzy"
+                    },
+                    "kinds": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only symbols of these kinds (e.g. `Function`, `Struct`, `Class`, `Interface`)"
+                    },
+                    "path_prefix": {
+                        "type": "string",
+                        "description": "Only symbols in files under this directory, absolute or relative to the workspace root (e.g. `src/`)"
+                    },
+                    "exclude_globs": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Skip symbols in files matching these globs, as in lsp_find_files (e.g. `**/tests/**`, `*_test.go`)"
+                    },
+                    "changed_only": changed_only_schema(),
+                    "base": changed_base_schema(),
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema()
                 },
                 "required": ["query", "language"]
             }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
-    ]
-}
-
-/// Call a tool by name
-pub async fn call_tool(
-    name: &str,
-    arguments: Option<Value>,
-    lsp_manager: Arc<LspManager>,
-) -> CallToolResult {
-    let args = arguments.unwrap_or(Value::Null);
-
-    match name {
-        "lsp_goto_definition" => handle_goto_definition(args, lsp_manager).await,
-        "lsp_find_references" => handle_find_references(args, lsp_manager).await,
-        "lsp_hover" => handle_hover(args, lsp_manager).await,
-        "lsp_document_symbols" => handle_document_symbols(args, lsp_manager).await,
-        "lsp_diagnostics" => handle_diagnostics(args, lsp_manager).await,
-        "lsp_workspace_symbols" => handle_workspace_symbols(args, lsp_manager).await,
-        _ => CallToolResult {
-            content: vec![ToolContent::Text {
-                text: format!("Unknown tool: {}", name),
-            }],
-            is_error: Some(true),
+        Tool {
+            name: "lsp_find_definition_by_name".to_string(),
+            description: "Find where a symbol is defined by its name, without knowing its line and column. Looks the name up in `file` (if given) and then across the workspace, and returns the definition of every match.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": symbol_name_properties(serde_json::json!({
+                    "include_source": include_source_schema()
+                })),
+                "required": ["name"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct GotoDefinitionArgs {
-    file: String,
-    line: u32,
-    character: u32,
-}
-
-async fn handle_goto_definition(
-    args: Value,
-    lsp_manager: Arc<LspManager>,
-) -> CallToolResult {
-    let args: GotoDefinitionArgs = match serde_json::from_value(args) {
-        Ok(a) => a,
-        Err(e) => {
-            return CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
-                }],
-                is_error: Some(true),
-            };
-        }
-    };
-
-    let file_path = PathBuf::from(&args.file);
-
-    match lsp_manager
-        .goto_definition(&file_path, args.line, args.character)
-        .await
-    {
-        Ok(Some(response)) => {
-            let text = format_definition_response(response);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
-            }
-        }
-        Ok(None) => CallToolResult {
-            content: vec![ToolContent::Text {
-                text: "No definition found".to_string(),
-            }],
-            is_error: None,
+        Tool {
+            name: "lsp_references_by_name".to_string(),
+            description: "Find all references to a symbol by its name, without knowing its line and column. If several symbols share the name, lists them so the lookup can be narrowed with `file` or `container`.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": symbol_name_properties(serde_json::json!({
+                    "includeDeclaration": {
+                        "type": "boolean",
+                        "description": "Include the declaration in results",
+                        "default": true
+                    }
+                })),
+                "required": ["name"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
-        Err(e) => {
-            error!("goto_definition error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
-            }
-        }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct FindReferencesArgs {
-    file: String,
-    line: u32,
-    character: u32,
-    #[serde(rename = "includeDeclaration", default = "default_true")]
-    include_declaration: bool,
-}
-
-fn default_true() -> bool {
-    true
-}
-
-async fn handle_find_references(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
-    let args: FindReferencesArgs = match serde_json::from_value(args) {
-        Ok(a) => a,
-        Err(e) => {
-            return CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
-                }],
-                is_error: Some(true),
-            };
-        }
-    };
-
-    let file_path = PathBuf::from(&args.file);
-
-    match lsp_manager
-        .find_references(
-            &file_path,
-            args.line,
-            args.character,
-            args.include_declaration,
-        )
-        .await
-    {
-        Ok(Some(locations)) => {
-            let text = format_locations(locations);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
-            }
-        }
-        Ok(None) => CallToolResult {
-            content: vec![ToolContent::Text {
-                text: "No references found".to_string(),
-            }],
-            is_error: None,
+        Tool {
+            name: "lsp_workspace_info".to_string(),
+            description: "Summarize the workspace: the languages present with their file counts and most common extensions, the language servers for each and whether they are running, installed or missing, and the project roots (Cargo.toml, package.json, go.mod, pyproject.toml). Call it first to learn what can be queried.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "refresh": {
+                        "type": "boolean",
+                        "description": "Rescan the workspace instead of using the scan made at startup",
+                        "default": false
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
         },
-        Err(e) => {
-            error!("find_references error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
-            }
-        }
-    }
-}
-
-#[derive(Debug, Deserialize)]
-struct HoverArgs {
-    file: String,
-    line: u32,
-    character: u32,
-}
-
-async fn handle_hover(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
-    let args: HoverArgs = match serde_json::from_value(args) {
-        Ok(a) => a,
-        Err(e) => {
-            return CallToolResult {
+        Tool {
+            name: "lsp_project_tree".to_string(),
+            description: "List the workspace's files and directories as an indented tree, skipping anything .gitignore ignores. Use it to find the files to run other queries on.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to list, absolute or relative to the workspace root (default: the workspace root)"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Directory levels to expand",
+                        "default": files::DEFAULT_TREE_DEPTH
+                    },
+                    "max_entries": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of entries to list",
+                        "default": files::DEFAULT_TREE_ENTRIES
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_find_files".to_string(),
+            description: "Find files by glob, skipping anything .gitignore ignores. Patterns with a `/` match paths relative to the searched directory (`src/**/*.rs`); others match file names at any depth (`*_test.go`). Returns absolute paths.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob: `*` and `?` match within a name, `**` any number of directories"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to search, absolute or relative to the workspace root (default: the workspace root)"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of files to return",
+                        "default": files::DEFAULT_FIND_LIMIT
+                    }
+                },
+                "required": ["pattern"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_search_text".to_string(),
+            description: "Search file contents with a regular expression, skipping anything .gitignore ignores. Finds what symbol queries miss: string literals, comments, config files. With `with_symbols`, each hit names the function or class it is in.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Regular expression (Rust regex syntax), or literal text with `fixed_strings`"
+                    },
+                    "fixed_strings": {
+                        "type": "boolean",
+                        "description": "Treat `pattern` as literal text",
+                        "default": false
+                    },
+                    "ignore_case": {
+                        "type": "boolean",
+                        "description": "Match case-insensitively",
+                        "default": false
+                    },
+                    "include": {
+                        "type": "string",
+                        "description": "Only search files matching this glob, as in lsp_find_files (e.g. `*.rs`, `src/**/*.ts`)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to search, absolute or relative to the workspace root (default: the workspace root)"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of matching lines to return",
+                        "default": search::DEFAULT_MAX_RESULTS
+                    },
+                    "with_symbols": {
+                        "type": "boolean",
+                        "description": "Name the enclosing symbol of each hit, using the file's language server",
+                        "default": false
+                    },
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema()
+                },
+                "required": ["pattern"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_module_outline".to_string(),
+            description: "Outline every source file under a directory in one call: the document symbols of each file its language server handles, grouped by file. Use it to explore a package instead of calling lsp_document_symbols per file.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to outline, absolute or relative to the workspace root (default: the workspace root)"
+                    },
+                    "include": {
+                        "type": "string",
+                        "description": "Only outline files matching this glob, as in lsp_find_files (e.g. `*.rs`, `src/**/*.ts`)"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Symbol nesting levels to show: 1 for top-level symbols only, 2 to add their members",
+                        "default": outline::DEFAULT_OUTLINE_DEPTH
+                    },
+                    "kinds": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only show symbols of these kinds (e.g. `function`, `struct`, `class`, `interface`); their containers are kept for context"
+                    },
+                    "max_files": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of files to outline",
+                        "default": outline::DEFAULT_OUTLINE_FILES
+                    },
+                    "changed_only": changed_only_schema(),
+                    "base": changed_base_schema(),
+                    "position_encoding": position_encoding_schema()
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_find_unused".to_string(),
+            description: "Find possibly dead code: symbols with no references besides their declaration. Lists the symbols of a directory's files (or the workspace symbols matching `query`), then checks each one's references with the language server. Exported/public items are skipped by default, since their users may be outside the workspace. Slow on large directories; progress is sent as log messages.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to check, absolute or relative to the workspace root (default: the workspace root)"
+                    },
+                    "include": {
+                        "type": "string",
+                        "description": "Only check files matching this glob, as in lsp_find_files (e.g. `*.rs`, `src/**/*.ts`)"
+                    },
+                    "language": {
+                        "type": "string",
+                        "description": "Only check files of this language (required with `query`)"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "Check the workspace symbols matching this query instead of every symbol of the directory's files"
+                    },
+                    "kinds": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Symbol kinds to check (default: function, method, class, struct, enum, interface, constant)"
+                    },
+                    "include_exported": {
+                        "type": "boolean",
+                        "description": "Also check exported items: `pub` in Rust, capitalized in Go, `export` in TypeScript, no leading underscore in Python",
+                        "default": false
+                    },
+                    "exclude": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Skip symbols whose name matches one of these globs (e.g. `test_*`, `*Handler`)"
+                    },
+                    "max_symbols": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Maximum number of symbols to check",
+                        "default": unused::DEFAULT_MAX_SYMBOLS
+                    },
+                    "position_encoding": position_encoding_schema()
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_list_runnables".to_string(),
+            description: "List the tests, benchmarks and binaries in a file that the language server knows how to run (rust-analyzer runnables, gopls test code lenses), each with the exact command that runs it. Give a position to get just the ones there, e.g. the test the cursor is in.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "line": {
+                        "type": "integer",
+                        "description": "Line number (0-indexed unless `index_base` is 1); with `character`, only list runnables at this position"
+                    },
+                    "character": {
+                        "type": "integer",
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                    },
+                    "position_encoding": position_encoding_schema(),
+"location_format": location_format_schema(),
+                    "index_base": index_base_schema()
+                },
+                "required": ["file"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_batch".to_string(),
+            description: "Run several query tools in one call, concurrently, e.g. hover, definition and references for a list of positions. Returns each call's result in order; one failing call doesn't fail the others.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "calls": {
+                        "type": "array",
+                        "maxItems": MAX_BATCH_CALLS,
+                        "description": "Tool invocations to run",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "tool": {
+                                    "type": "string",
+                                    "enum": BATCH_TOOLS,
+                                    "description": "Name of the tool to call"
+                                },
+                                "arguments": {
+                                    "type": "object",
+                                    "description": "The tool's arguments, as for a direct call"
+                                }
+                            },
+                            "required": ["tool"]
+                        }
+                    }
+                },
+                "required": ["calls"]
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_warmup".to_string(),
+            description: "Start language servers ahead of time and wait for their initial indexing to finish, so later queries don't pay the cold-start cost. Defaults to the languages in the `preload` setting.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "languages": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Languages to warm up (e.g., ['rust', 'typescript']). Defaults to the configured preload list."
+                    },
+                    "timeout_secs": {
+                        "type": "integer",
+                        "description": "Maximum seconds to wait for indexing to finish",
+                        "default": 60
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::writes(false, true, false)),
+        },
+        Tool {
+            name: "lsp_prepare_workspace".to_string(),
+            description: "Open the files matching some globs on their language servers, so servers that only diagnose open files (typescript-language-server, pyright) report project-wide errors in `lsp_problems_summary`. Files are opened in batches and count against `max_open_documents`, which also caps how many are opened.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "patterns": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Globs as in `lsp_find_files` (e.g., ['src/**/*.ts'])"
+                    },
+                    "language": {
+                        "type": "string",
+                        "description": "Only open files of this language"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Most files to open (never more than `max_open_documents`)",
+                        "default": prepare::DEFAULT_PREPARE_LIMIT
+                    }
+                },
+                "required": ["patterns"]
+            }),
+            annotations: Some(ToolAnnotations::writes(false, true, false)),
+        },
+        Tool {
+            name: "lsp_rename".to_string(),
+            description: "Rename the symbol at a given position across the workspace. Under the default `preview` edit policy nothing is written: the result is a unified diff of every change and a token to pass to `lsp_confirm_edit` once the diff looks right.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file"
+                    },
+                    "language": file_language_schema(),
+                    "line": {
+                        "type": "integer",
+                        "description": "Line number (0-indexed unless `index_base` is 1)"
+                    },
+                    "character": {
+                        "type": "integer",
+                        "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                    },
+                    "new_name": {
+                        "type": "string",
+                        "description": "The symbol's new name"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "true to only preview the change, false to write it at once. Defaults to `settings.edit_policy`, which previews unless set to \"apply\""
+                    },
+                    "position_encoding": position_encoding_schema(),
+                    "index_base": index_base_schema()
+                },
+                "required": ["file", "line", "character", "new_name"]
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, false)),
+        },
+        Tool {
+            name: "lsp_confirm_edit".to_string(),
+            description: "Write an edit previewed by a tool like `lsp_rename`, given its token. Fails without writing anything if a file changed since the preview; tokens expire after 15 minutes and can be used once.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "token": {
+                        "type": "string",
+                        "description": "The token the preview returned"
+                    }
+                },
+                "required": ["token"]
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, false)),
+        },
+        Tool {
+            name: "lsp_undo_last_edit".to_string(),
+            description: "Revert the last edit lsmcp applied in this workspace (or the one with the given id), restoring the files' previous contents. Edits are journaled across restarts, so repeated calls go further back. Previewed first under the default edit policy, like other edits.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "integer",
+                        "description": "Journal id of the edit to revert (default: the latest)"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "true to only preview the revert, false to write it at once. Defaults to `settings.edit_policy`"
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Revert files even if they changed since the edit, losing those changes (default: false)"
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, false)),
+        },
+        Tool {
+            name: "lsp_server_status".to_string(),
+            description: "Report the health of every running language server: language, binary, PID, uptime, pending requests, last error and indexing progress. Use this to debug slow or failing queries.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {}
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_metrics".to_string(),
+            description: "Report tool call and language server request statistics since lsmcp started: counts, errors, timeouts and p50/p90/p99 latencies per tool and per server method. Use this to find which server or method is slow.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "format": {
+                        "type": "string",
+                        "enum": ["text", "prometheus"],
+                        "description": "Readable report (default) or Prometheus text exposition format",
+                        "default": "text"
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_usage_stats".to_string(),
+            description: "Report which tools have been called, how often, and how many tokens their answers took (estimated at four characters a token), with how often answers were cut to the response budget and which tools were never called. Use this to tune `max_response_chars` and the tools on offer.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "scope": {
+                        "type": "string",
+                        "enum": ["all", "session"],
+                        "description": "Usage recorded across lsmcp runs (default, when `usage_stats` is on) or since this one started",
+                        "default": "all"
+                    }
+                }
+            }),
+            annotations: Some(ToolAnnotations::read_only()),
+        },
+        Tool {
+            name: "lsp_manage_servers".to_string(),
+            description: "Manage language servers installed by lsmcp: list them, install, update or uninstall a server, install servers for every language in the workspace, check for updates, or prune stale installs.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "action": {
+                        "type": "string",
+                        "enum": ["list", "install", "install_detected", "uninstall", "update", "check_updates", "prune"],
+                        "description": "Operation to perform"
+                    },
+                    "server": {
+                        "type": "string",
+                        "description": "Server name (e.g. \"pyright\") or language (e.g. \"python\"); required for install, uninstall and update"
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "For prune: only report what would be removed (default: false)"
+                    }
+                },
+                "required": ["action"]
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, true)),
+        },
+    ]
+}
+
+/// Schema of the `position_encoding` argument shared by the query tools
+fn position_encoding_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "enum": ["utf-8", "utf-16", "utf-32"],
+        "description": "How columns are counted, in arguments and results: \"utf-32\" counts characters (default), \"utf-16\" UTF-16 code units as in LSP and most editors, \"utf-8\" bytes",
+        "default": "utf-32"
+    })
+}
+
+/// Schema of the `location_format` argument shared by the tools that
+/// list locations
+fn location_format_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "enum": ["editor", "relative", "uri"],
+        "description": "How locations are shown: \"editor\" as absolute path:line:column, \"relative\" with paths relative to the workspace root, \"uri\" as file:// URIs. Defaults to `settings.location_format`, else \"editor\""
+    })
+}
+
+fn changed_only_schema() -> Value {
+    serde_json::json!({
+        "type": "boolean",
+        "description": "Only files changed on the current git branch: its commits, uncommitted edits and untracked files",
+        "default": false
+    })
+}
+
+fn changed_base_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "description": "With `changed_only` (which it implies), the branch, tag or commit the branch is compared with, e.g. \"origin/main\" (default: the remote's default branch, else main or master)"
+    })
+}
+
+/// Columns count characters unless the caller says otherwise
+fn default_position_encoding() -> PositionEncoding {
+    PositionEncoding::Utf32
+}
+
+/// Schema of the `index_base` argument shared by the positional tools
+fn index_base_schema() -> Value {
+    serde_json::json!({
+        "type": "integer",
+        "enum": [0, 1],
+        "description": "Whether `line` and `character` count from 0 (as in LSP) or 1 (as in compiler output and editors). Defaults to `settings.index_base`, else 0"
+    })
+}
+
+/// Convert a caller's position to a 0-based one inside `file`
+///
+/// `file` is the path the request is sent for, so clamping reads the file
+/// the server answers about. Columns past the end of a line clamp to its
+/// end; lines past the end of the file are an error naming its line count.
+/// Files that can't be read are left to the server to report.
+async fn resolve_position(
+    lsp_manager: &LspManager,
+    file: &Path,
+    line: u32,
+    character: u32,
+    index_base: Option<u32>,
+    encoding: PositionEncoding,
+) -> Result<(u32, u32), String> {
+    let base = index_base
+        .or(lsp_manager.settings().index_base)
+        .unwrap_or(0);
+    if base > 1 {
+        return Err(format!("`index_base` must be 0 or 1, not {}", base));
+    }
+    if line < base || character < base {
+        return Err(format!(
+            "`line` and `character` are {}-indexed, got {}:{}",
+            base, line, character
+        ));
+    }
+
+    let position = Position::new(line - base, character - base);
+    let Ok(text) = overlay::read_file(file).await else {
+        return Ok((position.line, position.character));
+    };
+
+    match clamp_position(&text, position, encoding) {
+        Some(position) => Ok((position.line, position.character)),
+        None => Err(format!(
+            "`line` {} is past the end of {}, which has {} lines",
+            line,
+            file.display(),
+            line_count(&text)
+        )),
+    }
+}
+
+/// A failed tool call in machine-readable form, given as the structured
+/// content of the result (`{ "error": ... }`) so agents can decide whether
+/// to retry or fix their arguments
+#[derive(Debug, Serialize)]
+struct ToolError {
+    /// `invalid_arguments`, `unknown_tool`, `disabled`, `edit_failed`,
+    /// `internal` or an `LspError::kind`
+    kind: &'static str,
+    message: String,
+    retryable: bool,
+    /// The argument at fault, when one is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameter: Option<String>,
+}
+
+impl ToolError {
+    fn into_result(self) -> CallToolResult {
+        let text = match self.kind {
+            "invalid_arguments" => format!("Invalid arguments: {}", self.message),
+            _ => format!("Error: {}", self.message),
+        };
+        CallToolResult {
+            content: vec![ToolContent::Text { text }],
+            is_error: Some(true),
+            structured_content: Some(serde_json::json!({ "error": self })),
+        }
+    }
+}
+
+/// A tool call that failed with `error`
+pub(crate) fn lsp_error(error: &LspError) -> CallToolResult {
+    ToolError {
+        kind: error.kind(),
+        message: error.to_string(),
+        retryable: error.is_retryable(),
+        parameter: None,
+    }
+    .into_result()
+}
+
+/// Arguments that don't fit the tool's schema
+///
+/// The parameter at fault is taken from serde's "missing field `x`" and the
+/// like, or from a message starting with it in backticks.
+fn invalid_arguments(message: impl std::fmt::Display) -> CallToolResult {
+    let message = message.to_string();
+    let parameter = Regex::new(r"^(?:(?:missing|unknown|duplicate) field )?`(\w+)`")
+        .ok()
+        .and_then(|pattern| pattern.captures(&message))
+        .map(|captures| captures[1].to_string());
+    ToolError {
+        kind: "invalid_arguments",
+        message,
+        retryable: false,
+        parameter,
+    }
+    .into_result()
+}
+
+/// An invalid value for `parameter`
+pub(crate) fn invalid_parameter(
+    parameter: &str,
+    message: impl std::fmt::Display,
+) -> CallToolResult {
+    ToolError {
+        kind: "invalid_arguments",
+        message: message.to_string(),
+        retryable: false,
+        parameter: Some(parameter.to_string()),
+    }
+    .into_result()
+}
+
+/// A tool turned off in the settings
+fn disabled_error(message: &str) -> CallToolResult {
+    ToolError {
+        kind: "disabled",
+        message: message.to_string(),
+        retryable: false,
+        parameter: None,
+    }
+    .into_result()
+}
+
+/// A failure in lsmcp itself, such as a listing that couldn't finish
+fn internal_error(message: impl std::fmt::Display) -> CallToolResult {
+    ToolError {
+        kind: "internal",
+        message: message.to_string(),
+        retryable: false,
+        parameter: None,
+    }
+    .into_result()
+}
+
+/// The LSP method a tool depends on, for tools a server may not support
+fn tool_method(tool: &str) -> Option<&'static str> {
+    match tool {
+        "lsp_goto_definition" | "lsp_find_definition_by_name" => Some("textDocument/definition"),
+        "lsp_find_references" | "lsp_references_by_name" | "lsp_impact_analysis" => {
+            Some("textDocument/references")
+        }
+        "lsp_hover" => Some("textDocument/hover"),
+        "lsp_document_symbols" | "lsp_module_outline" | "lsp_enclosing_symbol" => {
+            Some("textDocument/documentSymbol")
+        }
+        "lsp_workspace_symbols" => Some("workspace/symbol"),
+        "lsp_rename" => Some("textDocument/rename"),
+        _ => None,
+    }
+}
+
+/// Note in the descriptions of `tools` which running servers can't answer
+/// them
+pub async fn annotate_unsupported(tools: &mut [Tool], lsp_manager: &LspManager) {
+    for tool in tools {
+        let Some(method) = tool_method(&tool.name) else {
+            continue;
+        };
+        let servers = lsp_manager.servers_without(method).await;
+        if !servers.is_empty() {
+            tool.description
+                .push_str(&format!(" Not supported by: {}.", servers.join(", ")));
+        }
+    }
+}
+
+/// `lsp_run_runnable`, offered when `allow_run` is on
+fn run_runnable_definition() -> Tool {
+    Tool {
+            name: "lsp_run_runnable".to_string(),
+            description: "Run a test, benchmark or binary listed by lsp_list_runnables and return its exit status and output (the end of it, if long).".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path to the file the runnable is in"
+                    },
+                    "language": file_language_schema(),
+                    "label": {
+                        "type": "string",
+                        "description": "The runnable's label, as listed by lsp_list_runnables (e.g. \"test tests::test_parse\")"
+                    },
+                    "timeout_secs": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Kill the command after this many seconds",
+                        "default": run::DEFAULT_RUN_TIMEOUT_SECS
+                    }
+                },
+                "required": ["file", "label"]
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, true)),
+    }
+}
+
+/// `lsp_raw_request`, offered when `allow_raw_requests` is on
+fn raw_request_definition() -> Tool {
+    Tool {
+            name: "lsp_raw_request".to_string(),
+            description: "Send any LSP request (including server-specific extensions) to the language server for a file or language and return its raw JSON result. Positions are sent and returned as the server counts them (usually UTF-16, 0-based).".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "method": {
+                        "type": "string",
+                        "description": "JSON-RPC method, e.g. \"textDocument/foldingRange\" or \"clangd/ast\""
+                    },
+                    "params": {
+                        "description": "The request's params, sent as given"
+                    },
+                    "file": {
+                        "type": "string",
+                        "description": "Absolute path of a file whose server gets the request; the file is synced with the server first"
+                    },
+                    "language": {
+                        "type": "string",
+                        "description": "Language whose server gets the request; with `file`, the language to treat it as"
+                    },
+                    "server": {
+                        "type": "string",
+                        "description": "Server name, when several handle the file or language (default: the first)"
+                    }
+                },
+                "required": ["method"]
+            }),
+            annotations: Some(ToolAnnotations::writes(true, false, false)),
+    }
+}
+
+/// A server's extension request, offered as a tool (see
+/// `LspManager::extension_tools`)
+pub struct ExtensionTool(ExtensionRequest);
+
+/// Tools for the extension requests of the servers in use
+pub fn extension_tools(lsp_manager: &LspManager) -> Vec<ExtensionTool> {
+    lsp_manager
+        .extension_tools()
+        .into_iter()
+        .map(ExtensionTool)
+        .collect()
+}
+
+impl ToolHandler for ExtensionTool {
+    fn definition(&self) -> Tool {
+        extension_tool_definition(&self.0)
+    }
+
+    fn call(&self, args: Value, ctx: ToolContext) -> BoxFuture<'static, CallToolResult> {
+        let tool = self.0.tool.clone();
+        Box::pin(async move { handle_extension(&tool, args, ctx.lsp_manager).await })
+    }
+}
+
+fn extension_tool_definition(extension: &ExtensionRequest) -> Tool {
+    let (properties, required) = match extension.params {
+        ExtensionParams::Position => (
+            serde_json::json!({
+                "file": {
+                    "type": "string",
+                    "description": "Absolute path to the file"
+                },
+                "language": file_language_schema(),
+                "line": {
+                    "type": "integer",
+                    "description": "Line number (0-indexed unless `index_base` is 1)"
+                },
+                "character": {
+                    "type": "integer",
+                    "description": "Character offset in line (0-indexed unless `index_base` is 1), counted in `position_encoding` units"
+                },
+                "position_encoding": position_encoding_schema(),
+                "index_base": index_base_schema()
+            }),
+            serde_json::json!(["file", "line", "character"]),
+        ),
+        ExtensionParams::Document | ExtensionParams::Uri => (
+            serde_json::json!({
+                "file": {
+                    "type": "string",
+                    "description": "Absolute path to the file"
+                },
+                "language": file_language_schema()
+            }),
+            serde_json::json!(["file"]),
+        ),
+    };
+    Tool {
+        name: extension.tool.clone(),
+        description: extension.description.clone(),
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required
+        }),
+        annotations: None,
+    }
+}
+
+/// Schema of the definition tools' `include_source` argument
+fn include_source_schema() -> Value {
+    serde_json::json!({
+        "type": "boolean",
+        "description": "Include the source of each definition, for targets the client can't open itself (dependency sources outside the workspace)",
+        "default": false
+    })
+}
+
+/// Schema of the `language` argument of tools taking a file
+fn file_language_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "description": "Language to treat the file as (e.g. \"vue\", \"python\"), instead of the one its name suggests: for extensionless or templated files, or to pick between languages claiming the same extension"
+    })
+}
+
+fn file_content_schema() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "description": "Unsaved content to query instead of the file on disk (the file need not exist yet), e.g. code being drafted"
+    })
+}
+
+/// Schema properties shared by the by-name tools, plus `extra`
+fn symbol_name_properties(extra: Value) -> Value {
+    let mut properties = serde_json::json!({
+        "name": {
+            "type": "string",
+            "description": "Symbol name, e.g. \"parse_config\" or \"Server\""
+        },
+        "container": {
+            "type": "string",
+            "description": "Class, module or type the symbol belongs to, to disambiguate (e.g. \"Server\" for Server::new)"
+        },
+        "file": {
+            "type": "string",
+            "description": "Absolute path of a file to look in first; also picks the language"
+        },
+        "language": {
+            "type": "string",
+            "description": "Language to search in when `file` is not given (e.g., 'rust', 'python'); with `file`, the language to treat it as"
+        },
+        "position_encoding": position_encoding_schema(),
+        "location_format": location_format_schema()
+    });
+    if let (Some(properties), Value::Object(extra)) = (properties.as_object_mut(), extra) {
+        properties.extend(extra);
+    }
+    properties
+}
+
+/// Call a tool of `registry` by name
+pub async fn call_tool(
+    registry: &ToolRegistry,
+    name: &str,
+    arguments: Option<Value>,
+    ctx: ToolContext,
+) -> CallToolResult {
+    let Some(tool) = registry.get(name, &ctx.lsp_manager) else {
+        return unknown_tool(name);
+    };
+    // The tool's own name from here on, whatever it is offered as
+    let name = tool.name();
+    let name = name.as_str();
+    if !ctx.lsp_manager.tools_config().is_enabled(name) {
+        return disabled_tool(name);
+    }
+    let mut args = arguments.unwrap_or(Value::Null);
+    let mut schema = tool.definition().input_schema;
+    expose_batch_tools(&mut schema, &ctx.lsp_manager.tools_config());
+    if let Err(result) = check_arguments(
+        name,
+        &mut args,
+        &schema,
+        tool.uses_servers(),
+        &ctx.lsp_manager,
+    )
+    .await
+    {
+        return result;
+    }
+
+    let format = match location_format(&args, &ctx.lsp_manager) {
+        Ok(format) => format,
+        Err(result) => return result,
+    };
+    let budget = match budget::budget(&args, &ctx.lsp_manager.settings()) {
+        Ok(budget) => budget,
+        Err(message) => return invalid_parameter("max_response_chars", message),
+    };
+
+    let language = file_language(&args);
+    let content = file_content(name, &args);
+    let manager = Arc::clone(&ctx.lsp_manager);
+    let root = manager.workspace_root().to_path_buf();
+    // Every tool's state machine is in this future; boxed, it doesn't
+    // overflow the stack of those awaiting it
+    let call = tool.call(args, ctx);
+    let result = locations::scope(
+        format,
+        root,
+        manager.with_file_language(language, manager.with_file_content(content, call)),
+    )
+    .await;
+    let full_chars = budget::result_chars(&result);
+    let (result, truncated) = match budget {
+        Some(budget) if !budget::fits(&result, budget) => (budget::fit(result, budget), true),
+        _ => (result, false),
+    };
+    crate::usage::global().record(
+        name,
+        full_chars,
+        budget::result_chars(&result),
+        truncated,
+        result.is_error == Some(true),
+    );
+    result
+}
+
+fn unknown_tool(name: &str) -> CallToolResult {
+    ToolError {
+        kind: "unknown_tool",
+        message: format!("Unknown tool: {}", name),
+        retryable: false,
+        parameter: Some("name".to_string()),
+    }
+    .into_result()
+}
+
+fn disabled_tool(name: &str) -> CallToolResult {
+    ToolError {
+        kind: "tool_disabled",
+        message: format!("{} is turned off by `[tools]` in the config", name),
+        retryable: false,
+        parameter: Some("name".to_string()),
+    }
+    .into_result()
+}
+
+/// The call's `location_format`, else the configured one
+fn location_format(
+    args: &Value,
+    lsp_manager: &LspManager,
+) -> Result<LocationFormat, CallToolResult> {
+    match args.get("location_format") {
+        None | Some(Value::Null) => Ok(lsp_manager.settings().location_format.unwrap_or_default()),
+        Some(value) => LocationFormat::deserialize(value).map_err(|_| {
+            invalid_parameter(
+                "location_format",
+                format!(
+                    "`location_format` must be \"editor\", \"relative\" or \"uri\", not {}",
+                    value
+                ),
+            )
+        }),
+    }
+}
+
+/// How a built-in tool is run
+const HANDLERS: &[(&str, ToolFn)] = &[
+    ("lsp_goto_definition", |args, ctx| {
+        Box::pin(handle_goto_definition(args, ctx.lsp_manager))
+    }),
+    ("lsp_find_references", |args, ctx| {
+        Box::pin(handle_find_references(args, ctx.lsp_manager))
+    }),
+    ("lsp_impact_analysis", |args, ctx| {
+        Box::pin(handle_impact_analysis(args, ctx.lsp_manager))
+    }),
+    ("lsp_hover", |args, ctx| {
+        Box::pin(handle_hover(args, ctx.lsp_manager))
+    }),
+    ("lsp_document_symbols", |args, ctx| {
+        Box::pin(handle_document_symbols(args, ctx.lsp_manager))
+    }),
+    ("lsp_enclosing_symbol", |args, ctx| {
+        Box::pin(handle_enclosing_symbol(args, ctx.lsp_manager))
+    }),
+    ("lsp_diagnostics", |args, ctx| {
+        Box::pin(handle_diagnostics(args, ctx.lsp_manager))
+    }),
+    ("lsp_diagnostics_after_edit", |args, ctx| {
+        Box::pin(handle_diagnostics_after_edit(args, ctx.lsp_manager))
+    }),
+    ("lsp_diagnostics_changed", |args, ctx| {
+        Box::pin(handle_diagnostics_changed(args, ctx.lsp_manager))
+    }),
+    ("lsp_problems_summary", |args, ctx| {
+        Box::pin(async move { handle_problems_summary(args, ctx.lsp_manager) })
+    }),
+    ("lsp_workspace_symbols", |args, ctx| {
+        Box::pin(handle_workspace_symbols(args, ctx.lsp_manager))
+    }),
+    ("lsp_find_definition_by_name", |args, ctx| {
+        Box::pin(handle_definition_by_name(args, ctx.lsp_manager))
+    }),
+    ("lsp_references_by_name", |args, ctx| {
+        Box::pin(handle_references_by_name(args, ctx.lsp_manager))
+    }),
+    ("lsp_workspace_info", |args, ctx| {
+        Box::pin(handle_workspace_info(args, ctx.lsp_manager))
+    }),
+    ("lsp_project_tree", |args, ctx| {
+        Box::pin(handle_project_tree(args, ctx.lsp_manager))
+    }),
+    ("lsp_find_files", |args, ctx| {
+        Box::pin(handle_find_files(args, ctx.lsp_manager))
+    }),
+    ("lsp_search_text", |args, ctx| {
+        Box::pin(handle_search_text(args, ctx.lsp_manager))
+    }),
+    ("lsp_module_outline", |args, ctx| {
+        Box::pin(handle_module_outline(args, ctx.lsp_manager))
+    }),
+    ("lsp_find_unused", |args, ctx| {
+        Box::pin(async move { handle_find_unused(args, ctx.lsp_manager, &ctx.logger).await })
+    }),
+    ("lsp_list_runnables", |args, ctx| {
+        Box::pin(handle_list_runnables(args, ctx.lsp_manager))
+    }),
+    ("lsp_run_runnable", |args, ctx| {
+        Box::pin(handle_run_runnable(args, ctx.lsp_manager))
+    }),
+    ("lsp_batch", |args, ctx| Box::pin(handle_batch(args, ctx))),
+    ("lsp_warmup", |args, ctx| {
+        Box::pin(handle_warmup(args, ctx.lsp_manager))
+    }),
+    ("lsp_prepare_workspace", |args, ctx| {
+        Box::pin(handle_prepare_workspace(args, ctx.lsp_manager))
+    }),
+    ("lsp_rename", |args, ctx| {
+        Box::pin(handle_rename(args, ctx.lsp_manager))
+    }),
+    ("lsp_confirm_edit", |args, ctx| {
+        Box::pin(async move { handle_confirm_edit(args, ctx.lsp_manager) })
+    }),
+    ("lsp_undo_last_edit", |args, ctx| {
+        Box::pin(async move { handle_undo_last_edit(args, ctx.lsp_manager) })
+    }),
+    ("lsp_server_status", |_, ctx| {
+        Box::pin(handle_server_status(ctx.lsp_manager))
+    }),
+    ("lsp_metrics", |args, _| {
+        Box::pin(async move { handle_metrics(args) })
+    }),
+    ("lsp_usage_stats", |args, ctx| {
+        Box::pin(async move { handle_usage_stats(args, ctx.lsp_manager) })
+    }),
+    ("lsp_manage_servers", |args, ctx| {
+        Box::pin(async move { handle_manage_servers(args, ctx.lsp_manager, &ctx.logger).await })
+    }),
+    ("lsp_raw_request", |args, ctx| {
+        Box::pin(handle_raw_request(args, ctx.lsp_manager))
+    }),
+];
+
+fn builtin_handler(name: &str) -> Option<ToolFn> {
+    HANDLERS
+        .iter()
+        .find(|(tool, _)| *tool == name)
+        .map(|(_, handler)| *handler)
+}
+
+/// The built-in tools, in the order they are listed
+///
+/// `lsp_run_runnable` and `lsp_raw_request` are only listed when
+/// `allow_run` and `allow_raw_requests` are on; called anyway, they say so.
+pub fn builtin_tools() -> Vec<FnTool> {
+    let builtin = |definition: Tool| {
+        let handler = builtin_handler(&definition.name)?;
+        Some(FnTool::new(definition, handler))
+    };
+    let mut tools: Vec<FnTool> = get_tool_definitions()
+        .into_iter()
+        .filter_map(builtin)
+        .collect();
+    tools.extend(builtin(run_runnable_definition()).map(|tool| {
+        tool.with_availability(|lsp_manager| lsp_manager.settings().allow_run == Some(true))
+    }));
+    tools.extend(builtin(raw_request_definition()).map(|tool| {
+        tool.with_availability(|lsp_manager| {
+            lsp_manager.settings().allow_raw_requests == Some(true)
+        })
+    }));
+    tools
+}
+
+/// The `language` a tool call's `file` is to be treated as, when given
+/// alongside it
+fn file_language(args: &Value) -> Option<String> {
+    args.get("file").and_then(Value::as_str)?;
+    args.get("language")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Tools that query unsaved `content` in place of their file
+const CONTENT_TOOLS: &[&str] = &[
+    "lsp_goto_definition",
+    "lsp_find_references",
+    "lsp_impact_analysis",
+    "lsp_hover",
+    "lsp_document_symbols",
+    "lsp_enclosing_symbol",
+    "lsp_diagnostics",
+];
+
+/// The file and unsaved content a call gives, for tools that take one
+fn file_content(name: &str, args: &Value) -> Option<(PathBuf, String)> {
+    if !CONTENT_TOOLS.contains(&name) {
+        return None;
+    }
+    let file = args.get("file").and_then(Value::as_str)?;
+    let content = args.get("content").and_then(Value::as_str)?;
+    Some((PathBuf::from(file), content.to_string()))
+}
+
+/// Reject a call's arguments before any server sees them: paths tools may
+/// not use, then anything `validation` finds wrong with them
+///
+/// Paths are resolved in place, so the tool uses the file that was checked.
+async fn check_arguments(
+    name: &str,
+    args: &mut Value,
+    schema: &Value,
+    uses_servers: bool,
+    lsp_manager: &LspManager,
+) -> Result<(), CallToolResult> {
+    resolve_paths(args, lsp_manager)?;
+    let invalid = |invalid: validation::Invalid| {
+        ToolError {
+            kind: invalid.kind,
+            message: invalid.message,
+            retryable: false,
+            parameter: Some(invalid.parameter),
+        }
+        .into_result()
+    };
+    validation::check_schema(args, schema).map_err(invalid)?;
+    let content = file_content(name, args);
+    let content = content.as_ref().map(|(_, content)| content.as_str());
+    validation::check_targets(args, content, uses_servers, lsp_manager)
+        .await
+        .map_err(invalid)
+}
+
+/// Replace the `file` and `path` arguments with their canonical absolute
+/// paths, rejecting any naming a file or directory tools may not use (see
+/// `restrict_paths`)
+///
+/// Relative paths are taken from the workspace root, as listings do, rather
+/// than from the server's working directory.
+fn resolve_paths(args: &mut Value, lsp_manager: &LspManager) -> Result<(), CallToolResult> {
+    for key in ["file", "path"] {
+        let Some(path) = args.get(key).and_then(Value::as_str) else {
+            continue;
+        };
+        let path = canonical(&lsp_manager.workspace_root().join(path));
+        if let Err(e) = lsp_manager.check_path(&path) {
+            return Err(lsp_error(&e));
+        }
+        args[key] = Value::String(path.display().to_string());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct GotoDefinitionArgs {
+    file: String,
+    line: u32,
+    character: u32,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+    #[serde(default)]
+    include_source: bool,
+}
+
+async fn handle_goto_definition(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: GotoDefinitionArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        args.character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    match lsp_manager
+        .goto_definition(&file_path, line, character, args.position_encoding)
+        .await
+    {
+        Ok(Some(response)) => {
+            let targets = definition_targets(response);
+            let blame = LocationBlame::of(&lsp_manager, &targets).await;
+            let text = format_definitions(&targets, args.include_source, &blame);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Ok(None) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "No definition found".to_string(),
+            }],
+            is_error: None,
+            structured_content: None,
+        },
+        Err(e) => {
+            error!("goto_definition error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FindReferencesArgs {
+    file: String,
+    line: u32,
+    character: u32,
+    #[serde(rename = "includeDeclaration", default = "default_true")]
+    include_declaration: bool,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+async fn handle_find_references(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: FindReferencesArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        args.character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    match lsp_manager
+        .find_references(
+            &file_path,
+            line,
+            character,
+            args.include_declaration,
+            args.position_encoding,
+        )
+        .await
+    {
+        Ok(Some(locations)) => {
+            let blame = LocationBlame::of(&lsp_manager, &locations).await;
+            let text = format_locations(locations, &blame);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Ok(None) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "No references found".to_string(),
+            }],
+            is_error: None,
+            structured_content: None,
+        },
+        Err(e) => {
+            error!("find_references error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HoverArgs {
+    file: String,
+    line: u32,
+    character: u32,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+    #[serde(flatten)]
+    render: HoverRenderer,
+}
+
+async fn handle_hover(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: HoverArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        args.character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    match lsp_manager
+        .hover(&file_path, line, character, args.position_encoding)
+        .await
+    {
+        Ok(Some(hover)) => {
+            let text = args.render.render(hover);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Ok(None) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "No hover information available".to_string(),
+            }],
+            is_error: None,
+            structured_content: None,
+        },
+        Err(e) => {
+            error!("hover error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DocumentSymbolsArgs {
+    file: String,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+async fn handle_document_symbols(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: DocumentSymbolsArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+
+    match lsp_manager
+        .document_symbols(&file_path, args.position_encoding)
+        .await
+    {
+        Ok(Some(response)) => {
+            let text = format_document_symbols(response);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Ok(None) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "No symbols found".to_string(),
+            }],
+            is_error: None,
+            structured_content: None,
+        },
+        Err(e) => {
+            error!("document_symbols error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EnclosingSymbolArgs {
+    file: String,
+    line: u32,
+    #[serde(default)]
+    character: u32,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+async fn handle_enclosing_symbol(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: EnclosingSymbolArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    // A line without a column means its start, whatever the index base
+    let character = match (args.character, args.index_base) {
+        (0, Some(1)) => 1,
+        (0, None) => lsp_manager.settings().index_base.unwrap_or(0),
+        (character, _) => character,
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
+    let position = Position::new(line, character);
+
+    let response = match lsp_manager
+        .document_symbols(&file_path, args.position_encoding)
+        .await
+    {
+        Ok(Some(response)) => response,
+        Ok(None) => {
+            return CallToolResult {
+                content: vec![ToolContent::Text {
+                    text: "No symbols found".to_string(),
+                }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Err(e) => {
+            error!("document_symbols error: {}", e);
+            return lsp_error(&e);
+        }
+    };
+
+    let chain = symbols::enclosing_symbols(&response, position);
+    let Some(innermost) = chain.last() else {
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!(
+                    "No symbol encloses {} (top-level code)",
+                    locations::file_position(&file_path, position)
+                ),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({ "innermost": null, "chain": [] })),
+        };
+    };
+
+    let describe = |symbol: &symbols::EnclosingSymbol| {
+        format!(
+            "{} ({:?}{}) at {}, lines {}-{}",
+            symbol.name,
+            symbol.kind,
+            symbol
+                .detail
+                .as_deref()
+                .filter(|detail| !detail.is_empty())
+                .map(|detail| format!(": {}", detail))
+                .unwrap_or_default(),
+            locations::file_position(&file_path, symbol.selection_range.start),
+            symbol.range.start.line + 1,
+            symbol.range.end.line + 1
+        )
+    };
+    let mut text = format!("In {}\n", describe(innermost));
+    if chain.len() > 1 {
+        text.push_str("\nEnclosing symbols, outermost first:\n");
+        for symbol in &chain {
+            text.push_str(&format!("- {}\n", describe(symbol)));
+        }
+    }
+
+    let structured: Vec<Value> = chain
+        .iter()
+        .map(|symbol| {
+            serde_json::json!({
+                "name": symbol.name,
+                "kind": format!("{:?}", symbol.kind),
+                "detail": symbol.detail,
+                "file": file_path.display().to_string(),
+                "range": symbol.range,
+                "selection_range": symbol.selection_range,
+            })
+        })
+        .collect();
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "innermost": structured.last(),
+            "chain": structured,
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnosticsArgs {
+    file: String,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    #[serde(flatten)]
+    filter: DiagnosticFilter,
+    max_results: Option<usize>,
+    #[serde(default)]
+    include_related: bool,
+    #[serde(default)]
+    include_fixes: bool,
+    #[serde(default)]
+    format: DiagnosticsFormat,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DiagnosticsFormat {
+    #[default]
+    Text,
+    Sarif,
+}
+
+/// Most diagnostics quick fixes are requested for in one call
+const MAX_FIX_QUERIES: usize = 20;
+
+/// Most lines of a related location's source shown
+const MAX_SNIPPET_LINES: u32 = 3;
+
+/// What is shown under a diagnostic besides its message
+#[derive(Debug, Default)]
+struct DiagnosticExtras {
+    /// Source of each related location, in order (`None` if unreadable)
+    snippets: Vec<Option<String>>,
+    fixes: Vec<CodeActionOrCommand>,
+}
+
+/// Which diagnostics `lsp_diagnostics` returns
+#[derive(Debug, Default, Deserialize)]
+struct DiagnosticFilter {
+    severity: Option<SeverityThreshold>,
+    source: Option<String>,
+    code: Option<NumberOrString>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SeverityThreshold {
+    Error,
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+}
+
+impl DiagnosticFilter {
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        // Lower values are more severe; a missing severity counts as an error
+        let severity_ok = self.severity.is_none_or(|threshold| {
+            let max = match threshold {
+                SeverityThreshold::Error => DiagnosticSeverity::ERROR,
+                SeverityThreshold::Warning => DiagnosticSeverity::WARNING,
+                SeverityThreshold::Information => DiagnosticSeverity::INFORMATION,
+                SeverityThreshold::Hint => DiagnosticSeverity::HINT,
+            };
+            diagnostic.severity.is_none_or(|severity| severity <= max)
+        });
+        let source_ok = self.source.as_ref().is_none_or(|source| {
+            diagnostic
+                .source
+                .as_ref()
+                .is_some_and(|s| s.eq_ignore_ascii_case(source))
+        });
+        let code_ok = self.code.as_ref().is_none_or(|code| {
+            diagnostic
+                .code
+                .as_ref()
+                .is_some_and(|c| code_string(c) == code_string(code))
+        });
+        severity_ok && source_ok && code_ok
+    }
+}
+
+pub(crate) fn code_string(code: &NumberOrString) -> String {
+    match code {
+        NumberOrString::Number(n) => n.to_string(),
+        NumberOrString::String(s) => s.clone(),
+    }
+}
+
+/// Diagnostics by severity; diagnostics without one aren't counted
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct DiagnosticCounts {
+    error: usize,
+    warning: usize,
+    information: usize,
+    hint: usize,
+}
+
+impl DiagnosticCounts {
+    fn of(diagnostics: &[Diagnostic]) -> Self {
+        let mut counts = Self::default();
+        for diagnostic in diagnostics {
+            match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => counts.error += 1,
+                Some(DiagnosticSeverity::WARNING) => counts.warning += 1,
+                Some(DiagnosticSeverity::INFORMATION) => counts.information += 1,
+                Some(DiagnosticSeverity::HINT) => counts.hint += 1,
+                None | Some(_) => {}
+            }
+        }
+        counts
+    }
+}
+
+async fn handle_diagnostics(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: DiagnosticsArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+
+    match lsp_manager
+        .get_diagnostics(&file_path, args.position_encoding)
+        .await
+    {
+        Ok(diagnostics) => {
+            let total = diagnostics.len();
+            let mut matched: Vec<Diagnostic> = diagnostics
+                .into_iter()
+                .filter(|d| args.filter.matches(d))
+                .collect();
+            let counts = DiagnosticCounts::of(&matched);
+            let matched_count = matched.len();
+            if let Some(max_results) = args.max_results {
+                matched.truncate(max_results);
+            }
+
+            let structured = serde_json::json!({
+                "file": args.file,
+                "total": total,
+                "matched": matched_count,
+                "returned": matched.len(),
+                "counts": counts,
+            });
+            if let DiagnosticsFormat::Sarif = args.format {
+                let log = sarif::log(
+                    lsp_manager.workspace_root(),
+                    [(file_path.as_path(), matched.as_slice())],
+                    args.position_encoding,
+                );
+                return CallToolResult {
+                    content: vec![ToolContent::Text {
+                        text: log.to_string(),
+                    }],
+                    is_error: None,
+                    structured_content: Some(structured),
+                };
+            }
+            let extras = diagnostic_extras(
+                &lsp_manager,
+                &file_path,
+                &matched,
+                args.include_related,
+                args.include_fixes,
+                args.position_encoding,
+            )
+            .await;
+            let text = format_diagnostics(&matched, &counts, matched_count, total, &extras);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: Some(structured),
+            }
+        }
+        Err(e) => {
+            error!("get_diagnostics error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DiagnosticsAfterEditArgs {
+    file: String,
+    edits: Vec<edit_check::ProposedEdit>,
+    wait_secs: Option<u64>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+async fn handle_diagnostics_after_edit(
+    args: Value,
+    lsp_manager: Arc<LspManager>,
+) -> CallToolResult {
+    let args: DiagnosticsAfterEditArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    if args.edits.is_empty() {
+        return invalid_parameter("edits", "`edits` is empty");
+    }
+    let wait = match args.wait_secs {
+        None => edit_check::DEFAULT_WAIT_SECS,
+        Some(secs @ 1..=edit_check::MAX_WAIT_SECS) => secs,
+        Some(secs) => {
+            return invalid_parameter(
+                "wait_secs",
+                format!(
+                    "`wait_secs` must be between 1 and {}, not {}",
+                    edit_check::MAX_WAIT_SECS,
+                    secs
+                ),
+            )
+        }
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let text = match overlay::read_file(&file_path).await {
+        Ok(text) => text,
+        Err(e) => return lsp_error(&LspError::Io(e)),
+    };
+    let edited = match edit_check::apply_edits(&text, &args.edits) {
+        Ok(edited) => edited,
+        Err(e) => return invalid_parameter("edits", e),
+    };
+
+    let (before, after) = match lsp_manager
+        .diagnostics_with_edit(
+            &file_path,
+            edited,
+            Duration::from_secs(wait),
+            args.position_encoding,
+        )
+        .await
+    {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            error!("diagnostics_with_edit error: {}", e);
+            return lsp_error(&e);
+        }
+    };
+    let delta = edit_check::compare(&before, &after);
+    let introduced = DiagnosticCounts::of(&delta.introduced);
+    let resolved = DiagnosticCounts::of(&delta.resolved);
+
+    let mut text = format!(
+        "{}: {} new diagnostic(s), {} resolved ({} before the edit, {} after)\n",
+        if delta.introduces_errors() {
+            "The edit introduces errors"
+        } else {
+            "The edit introduces no errors"
+        },
+        delta.introduced.len(),
+        delta.resolved.len(),
+        before.len(),
+        after.len()
+    );
+    for (title, diagnostics, counts) in [
+        (
+            "Introduced (positions in the edited file)",
+            &delta.introduced,
+            &introduced,
+        ),
+        ("Resolved", &delta.resolved, &resolved),
+    ] {
+        if !diagnostics.is_empty() {
+            text.push_str(&format!("\n{}:\n", title));
+            text.push_str(&format_diagnostics(
+                diagnostics,
+                counts,
+                diagnostics.len(),
+                diagnostics.len(),
+                &[],
+            ));
+        }
+    }
+
+    let structured = serde_json::json!({
+        "file": args.file,
+        "introduces_errors": delta.introduces_errors(),
+        "before": before.len(),
+        "after": after.len(),
+        "introduced": introduced,
+        "resolved": resolved,
+    });
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: Some(structured),
+    }
+}
+
+/// Changed files `lsp_diagnostics_changed` diagnoses unless asked for more
+const DEFAULT_CHANGED_FILES: usize = 50;
+
+#[derive(Debug, Default, Deserialize)]
+struct DiagnosticsChangedArgs {
+    base: Option<String>,
+    #[serde(flatten)]
+    filter: DiagnosticFilter,
+    max_files: Option<usize>,
+    wait_secs: Option<u64>,
+    #[serde(default)]
+    format: DiagnosticsFormat,
+}
+
+async fn handle_diagnostics_changed(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let args = match serde_json::from_value::<Option<DiagnosticsChangedArgs>>(args) {
+        Ok(args) => args.unwrap_or_default(),
+        Err(e) => return invalid_arguments(e),
+    };
+    let wait = match args.wait_secs {
+        None => edit_check::DEFAULT_WAIT_SECS,
+        Some(secs @ 1..=edit_check::MAX_WAIT_SECS) => secs,
+        Some(secs) => {
+            return invalid_parameter(
+                "wait_secs",
+                format!(
+                    "`wait_secs` must be between 1 and {}, not {}",
+                    edit_check::MAX_WAIT_SECS,
+                    secs
+                ),
+            )
+        }
+    };
+    let max_files = args.max_files.unwrap_or(DEFAULT_CHANGED_FILES).max(1);
+
+    let changed = match git::changed_files(lsp_manager.workspace_root(), args.base.as_deref()).await
+    {
+        Ok(changed) => changed,
+        Err(e) => return lsp_error(&e),
+    };
+    let mut heading = format!(
+        "{} file(s) changed since {} (merge base {})",
+        changed.files.len(),
+        changed.base,
+        changed.short_merge_base()
+    );
+
+    let (mut files, unsupported): (Vec<PathBuf>, Vec<PathBuf>) = changed
+        .files
+        .iter()
+        .cloned()
+        .partition(|file| lsp_manager.file_language(file).is_some());
+    let truncated = files.len() > max_files;
+    if truncated {
+        heading.push_str(&format!(
+            "; diagnosed the first {} of the {} a language server handles",
+            max_files,
+            files.len()
+        ));
+        files.truncate(max_files);
+    }
+
+    let mut report = check::check(&lsp_manager, files, Duration::from_secs(wait)).await;
+    report.unsupported = unsupported.len();
+    for file in &mut report.files {
+        file.diagnostics.retain(|d| args.filter.matches(d));
+    }
+
+    let structured = serde_json::json!({
+        "base": changed.base,
+        "merge_base": changed.merge_base,
+        "changed": changed.files.len(),
+        "checked": report.files.len(),
+        "truncated": truncated,
+        "counts": report.counts(),
+    });
+    let text = match args.format {
+        DiagnosticsFormat::Sarif => {
+            let files = report
+                .files
+                .iter()
+                .map(|file| (file.path.as_path(), file.diagnostics.as_slice()));
+            sarif::log(&report.root, files, check::CHECK_ENCODING).to_string()
+        }
+        DiagnosticsFormat::Text if changed.files.is_empty() => heading,
+        DiagnosticsFormat::Text => {
+            format!("{}\n\n{}", heading, report.render(CheckFormat::Text))
+        }
+    };
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: Some(structured),
+    }
+}
+
+/// The files changed on the branch, for tools narrowed to them with
+/// `changed_only` or `base`; `None` when they aren't
+async fn changed_file_set(
+    lsp_manager: &LspManager,
+    changed_only: bool,
+    base: Option<&str>,
+) -> Result<Option<HashSet<PathBuf>>, CallToolResult> {
+    if !changed_only && base.is_none() {
+        return Ok(None);
+    }
+    match git::changed_files(lsp_manager.workspace_root(), base).await {
+        Ok(changed) => Ok(Some(changed.files.into_iter().collect())),
+        Err(e) => Err(lsp_error(&e)),
+    }
+}
+
+/// Related-location snippets and quick fixes for `diagnostics`, as requested
+///
+/// Returns one entry per diagnostic, or none when neither was requested.
+async fn diagnostic_extras(
+    lsp_manager: &LspManager,
+    file_path: &Path,
+    diagnostics: &[Diagnostic],
+    include_related: bool,
+    include_fixes: bool,
+    encoding: PositionEncoding,
+) -> Vec<DiagnosticExtras> {
+    if !include_related && !include_fixes {
+        return Vec::new();
+    }
+
+    let mut extras: Vec<DiagnosticExtras> = diagnostics
+        .iter()
+        .map(|_| DiagnosticExtras::default())
+        .collect();
+
+    if include_related {
+        let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+        for (diagnostic, extra) in diagnostics.iter().zip(&mut extras) {
+            for info in diagnostic.related_information.iter().flatten() {
+                let snippet = info.location.uri.to_file_path().ok().and_then(|path| {
+                    let text = texts
+                        .entry(path)
+                        .or_insert_with_key(|path| overlay::read_file_sync(path).ok());
+                    source_snippet(text.as_deref()?, info.location.range)
+                });
+                extra.snippets.push(snippet);
+            }
+        }
+    }
+
+    if include_fixes {
+        let queries = diagnostics.iter().take(MAX_FIX_QUERIES).map(|diagnostic| {
+            lsp_manager.code_actions(
+                file_path,
+                diagnostic.range,
+                vec![diagnostic.clone()],
+                Some(vec![CodeActionKind::QUICKFIX]),
+                encoding,
+            )
+        });
+        let results = futures::future::join_all(queries).await;
+        for (result, extra) in results.into_iter().zip(&mut extras) {
+            match result {
+                Ok(fixes) => extra.fixes = fixes,
+                Err(LspError::Unsupported { .. }) => {}
+                Err(e) => error!("code_actions error: {}", e),
+            }
+        }
+    }
+
+    extras
+}
+
+/// Lines of `text` covered by `range`, at most `MAX_SNIPPET_LINES` of them
+fn source_snippet(text: &str, range: Range) -> Option<String> {
+    let last = range.end.line.min(range.start.line + MAX_SNIPPET_LINES - 1);
+    let lines: Vec<&str> = (range.start.line..=last)
+        .map_while(|line| line_text(text, line))
+        .collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceSymbolsArgs {
+    query: String,
+    language: String,
+    #[serde(default, rename = "match")]
+    name_match: NameMatch,
+    #[serde(default)]
+    kinds: Vec<String>,
+    path_prefix: Option<String>,
+    #[serde(default)]
+    exclude_globs: Vec<String>,
+    #[serde(default)]
+    changed_only: bool,
+    base: Option<String>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+async fn handle_workspace_symbols(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: WorkspaceSymbolsArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let kinds = match symbol_kinds(&args.kinds) {
+        Ok(kinds) => kinds,
+        Err(message) => return invalid_parameter("kinds", message),
+    };
+    let only_files =
+        match changed_file_set(&lsp_manager, args.changed_only, args.base.as_deref()).await {
+            Ok(files) => files,
+            Err(result) => return result,
+        };
+    let root = lsp_manager.workspace_root();
+    let filter = SymbolFilter {
+        query: args.query.clone(),
+        name_match: args.name_match,
+        kinds,
+        path_prefix: args.path_prefix.as_deref().map(|prefix| root.join(prefix)),
+        exclude_globs: args.exclude_globs,
+        only_files,
+        workspace_root: root.to_path_buf(),
+    };
+
+    match lsp_manager
+        .workspace_symbols(
+            filter.server_query(),
+            &args.language,
+            args.position_encoding,
+        )
+        .await
+    {
+        Ok(Some(symbols)) => {
+            let (symbols, filtered) = filter.apply(symbols);
+            let text = format_workspace_symbols(symbols, &args.query, filtered);
+            CallToolResult {
+                content: vec![ToolContent::Text { text }],
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Ok(None) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!("No symbols found for query: {}", args.query),
+            }],
+            is_error: None,
+            structured_content: None,
+        },
+        Err(e) => {
+            error!("workspace_symbols error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectTreeArgs {
+    path: Option<String>,
+    #[serde(default = "default_tree_depth")]
+    max_depth: usize,
+    #[serde(default = "default_tree_entries")]
+    max_entries: usize,
+}
+
+fn default_tree_depth() -> usize {
+    files::DEFAULT_TREE_DEPTH
+}
+
+fn default_tree_entries() -> usize {
+    files::DEFAULT_TREE_ENTRIES
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WorkspaceInfoArgs {
+    #[serde(default)]
+    refresh: bool,
+}
+
+async fn handle_workspace_info(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let args = match serde_json::from_value::<Option<WorkspaceInfoArgs>>(args) {
+        Ok(args) => args.unwrap_or_default(),
+        Err(e) => return invalid_arguments(e),
+    };
+    let scan = match lsp_manager.workspace_scan(args.refresh).await {
+        Ok(scan) => scan,
+        Err(e) => return lsp_error(&e),
+    };
+
+    let mut text = format!(
+        "Workspace: {} ({} files{})\n",
+        scan.root.display(),
+        scan.files,
+        if scan.truncated {
+            ", scan truncated"
+        } else {
+            ""
+        }
+    );
+    let mut languages = Vec::with_capacity(scan.languages.len());
+    if scan.languages.is_empty() {
+        text.push_str("\nNo files any configured language server handles\n");
+    } else {
+        text.push_str("\nLanguages:\n");
+    }
+    for language in &scan.languages {
+        let mut servers = Vec::with_capacity(language.servers.len());
+        for server in &language.servers {
+            let availability = lsp_manager.server_availability(server).await;
+            servers.push((server, availability));
+        }
+        text.push_str(&format!(
+            "- {}: {} file(s) ({}); {}\n",
+            language.language,
+            language.files,
+            language.extensions.join(", "),
+            servers
+                .iter()
+                .map(|(server, availability)| format!("{} ({})", server, availability.describe()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        languages.push(serde_json::json!({
+            "language": language.language,
+            "files": language.files,
+            "extensions": language.extensions,
+            "servers": servers
+                .iter()
+                .map(|(server, availability)| {
+                    serde_json::json!({ "name": server, "status": availability })
+                })
+                .collect::<Vec<_>>(),
+        }));
+    }
+    if scan.unrecognized > 0 {
+        text.push_str(&format!("- (no server): {} file(s)\n", scan.unrecognized));
+    }
+    if !scan.projects.is_empty() {
+        text.push_str("\nProjects:\n");
+        for project in &scan.projects {
+            text.push_str(&format!(
+                "- {} ({})\n",
+                project.path.display(),
+                project.markers.join(", ")
+            ));
+        }
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "root": scan.root,
+            "files": scan.files,
+            "unrecognized": scan.unrecognized,
+            "truncated": scan.truncated,
+            "languages": languages,
+            "projects": scan.projects,
+        })),
+    }
+}
+
+async fn handle_project_tree(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ProjectTreeArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let root = match listing_root(args.path.as_deref(), &lsp_manager) {
+        Ok(root) => root,
+        Err(message) => return invalid_parameter("path", message),
+    };
+
+    let (max_depth, max_entries) = (args.max_depth.max(1), args.max_entries.max(1));
+    let tree = match tokio::task::spawn_blocking(move || {
+        files::project_tree(&root, max_depth, max_entries)
+    })
+    .await
+    {
+        Ok(tree) => tree,
+        Err(e) => return internal_error(format!("listing failed: {}", e)),
+    };
+
+    let mut text = tree.text;
+    if tree.truncated {
+        text.push_str(&format!(
+            "\n[truncated: showing {} entries; raise max_entries or list a subdirectory]",
+            tree.entries
+        ));
+    }
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FindFilesArgs {
+    pattern: String,
+    path: Option<String>,
+    #[serde(default = "default_find_limit")]
+    limit: usize,
+}
+
+fn default_find_limit() -> usize {
+    files::DEFAULT_FIND_LIMIT
+}
+
+async fn handle_find_files(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: FindFilesArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let root = match listing_root(args.path.as_deref(), &lsp_manager) {
+        Ok(root) => root,
+        Err(message) => return invalid_parameter("path", message),
+    };
+
+    let (pattern, limit) = (args.pattern.clone(), args.limit.max(1));
+    let search_root = root.clone();
+    let found =
+        match tokio::task::spawn_blocking(move || files::find_files(&search_root, &pattern, limit))
+            .await
+        {
+            Ok(found) => found,
+            Err(e) => return internal_error(format!("search failed: {}", e)),
+        };
+
+    let paths: Vec<String> = found
+        .files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let text = if paths.is_empty() {
+        format!(
+            "No files matching `{}` under {}",
+            args.pattern,
+            root.display()
+        )
+    } else {
+        let mut text = format!(
+            "Found {} file(s) matching `{}`:\n{}",
+            paths.len(),
+            args.pattern,
+            paths.join("\n")
+        );
+        if found.truncated {
+            text.push_str(&format!(
+                "\n[truncated: showing the first {}; raise limit or narrow the pattern]",
+                paths.len()
+            ));
+        }
+        text
+    };
+
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "files": paths,
+            "truncated": found.truncated,
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchTextArgs {
+    pattern: String,
+    #[serde(default)]
+    fixed_strings: bool,
+    #[serde(default)]
+    ignore_case: bool,
+    include: Option<String>,
+    path: Option<String>,
+    #[serde(default = "default_search_results")]
+    max_results: usize,
+    #[serde(default)]
+    with_symbols: bool,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+fn default_search_results() -> usize {
+    search::DEFAULT_MAX_RESULTS
+}
+
+async fn handle_search_text(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: SearchTextArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let root = match listing_root(args.path.as_deref(), &lsp_manager) {
+        Ok(root) => root,
+        Err(message) => return invalid_parameter("path", message),
+    };
+    let pattern = match args.fixed_strings {
+        true => regex::escape(&args.pattern),
+        false => args.pattern.clone(),
+    };
+    let regex = match regex::RegexBuilder::new(&pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+    {
+        Ok(regex) => regex,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let (include, max_results) = (args.include.clone(), args.max_results.max(1));
+    let search_root = root.clone();
+    let results = match tokio::task::spawn_blocking(move || {
+        search::search_text(&search_root, &regex, include.as_deref(), max_results)
+    })
+    .await
+    {
+        Ok(results) => results,
+        Err(e) => return internal_error(format!("search failed: {}", e)),
+    };
+
+    if results.matches.is_empty() {
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!(
+                    "No matches for `{}` in {} file(s) under {}",
+                    args.pattern,
+                    results.files_searched,
+                    root.display()
+                ),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({ "matches": [], "truncated": false })),
+        };
+    }
+
+    let symbols = match args.with_symbols {
+        true => enclosing_symbols(&results.matches, &lsp_manager).await,
+        false => vec![None; results.matches.len()],
+    };
+
+    let files = results
+        .matches
+        .iter()
+        .map(|m| &m.path)
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    let mut text = format!(
+        "Found {} match(es) for `{}` in {} file(s):\n",
+        results.matches.len(),
+        args.pattern,
+        files
+    );
+    let mut structured = Vec::with_capacity(results.matches.len());
+    for (hit, symbol) in results.matches.iter().zip(&symbols) {
+        let character = convert_character(
+            &hit.line_text,
+            hit.column as u32,
+            PositionEncoding::Utf8,
+            args.position_encoding,
+        );
+        text.push_str(&format!(
+            "{}: {}\n",
+            locations::file_position(&hit.path, Position::new(hit.line, character)),
+            hit.excerpt()
+        ));
+        if let Some(symbol) = symbol {
+            let container = symbol
+                .container
+                .as_ref()
+                .map(|c| format!(" in {}", c))
+                .unwrap_or_default();
+            text.push_str(&format!(
+                "  symbol: {} ({:?}){}\n",
+                symbol.name, symbol.kind, container
+            ));
+        }
+        structured.push(serde_json::json!({
+            "file": hit.path.display().to_string(),
+            "line": hit.line,
+            "character": character,
+            "text": hit.line_text,
+            "symbol": symbol.as_ref().map(|s| serde_json::json!({
+                "name": s.name,
+                "kind": format!("{:?}", s.kind),
+                "container": s.container,
+            })),
+        }));
+    }
+    if results.truncated {
+        text.push_str(&format!(
+            "[truncated: showing the first {}; raise max_results or narrow the search]",
+            results.matches.len()
+        ));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "matches": structured,
+            "truncated": results.truncated,
+        })),
+    }
+}
+
+/// Most files whose symbols `lsp_module_outline` requests at once
+const MAX_OUTLINE_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct ModuleOutlineArgs {
+    path: Option<String>,
+    include: Option<String>,
+    #[serde(default = "default_outline_depth")]
+    max_depth: usize,
+    #[serde(default)]
+    kinds: Vec<String>,
+    #[serde(default = "default_outline_files")]
+    max_files: usize,
+    #[serde(default)]
+    changed_only: bool,
+    base: Option<String>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+fn default_outline_depth() -> usize {
+    outline::DEFAULT_OUTLINE_DEPTH
+}
+
+fn default_outline_files() -> usize {
+    outline::DEFAULT_OUTLINE_FILES
+}
+
+async fn handle_module_outline(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ModuleOutlineArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let root = match listing_root(args.path.as_deref(), &lsp_manager) {
+        Ok(root) => root,
+        Err(message) => return invalid_parameter("path", message),
+    };
+    let kinds = match symbol_kinds(&args.kinds) {
+        Ok(kinds) => kinds,
+        Err(message) => return invalid_parameter("kinds", message),
+    };
+
+    let (mut paths, complete) = match source_files(&root, args.include.clone(), &lsp_manager).await
+    {
+        Ok(found) => found,
+        Err(message) => return internal_error(message),
+    };
+    let changed =
+        match changed_file_set(&lsp_manager, args.changed_only, args.base.as_deref()).await {
+            Ok(changed) => changed,
+            Err(result) => return result,
+        };
+    if let Some(changed) = &changed {
+        paths.retain(|path| changed.contains(path));
+    }
+    let truncated = !complete || paths.len() > args.max_files.max(1);
+    paths.truncate(args.max_files.max(1));
+
+    if paths.is_empty() {
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!(
+                    "No {}files with a language server under {}",
+                    if changed.is_some() { "changed " } else { "" },
+                    root.display()
+                ),
+            }],
+            is_error: None,
+            structured_content: Some(
+                serde_json::json!({ "files": [], "errors": [], "truncated": false }),
+            ),
+        };
+    }
+
+    let encoding = args.position_encoding;
+    let outlines: Vec<Result<Option<DocumentSymbolResponse>, LspError>> =
+        futures::stream::iter(paths.clone())
+            .map(|path| {
+                let lsp_manager = Arc::clone(&lsp_manager);
+                async move { lsp_manager.document_symbols(&path, encoding).await }
+            })
+            .buffered(MAX_OUTLINE_CONCURRENCY)
+            .collect()
+            .await;
+
+    let max_depth = args.max_depth.max(1);
+    let (mut text, mut failed) = (String::new(), String::new());
+    let (mut outlined, mut structured, mut errors) = (0, Vec::new(), Vec::new());
+    for (path, outline) in paths.iter().zip(outlines) {
+        let relative = path.strip_prefix(&root).unwrap_or(path).display();
+        let entries = match outline {
+            Ok(Some(response)) => outline::outline_entries(&response, max_depth, &kinds),
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                failed.push_str(&format!("  {}: {}\n", relative, e));
+                errors.push(serde_json::json!({
+                    "file": path.display().to_string(),
+                    "error": e.to_string(),
+                }));
+                continue;
+            }
+        };
+        if entries.is_empty() {
+            continue;
+        }
+
+        outlined += 1;
+        text.push_str(&format!("{}\n", relative));
+        for entry in &entries {
+            text.push_str(&format!(
+                "{}- {} ({:?}) at {}:{}\n",
+                "  ".repeat(entry.depth + 1),
+                entry.name,
+                entry.kind,
+                entry.position.line + 1,
+                entry.position.character + 1
+            ));
+        }
+        text.push('\n');
+        structured.push(serde_json::json!({
+            "file": path.display().to_string(),
+            "symbols": entries.iter().map(|entry| serde_json::json!({
+                "name": entry.name,
+                "kind": format!("{:?}", entry.kind),
+                "depth": entry.depth,
+                "line": entry.position.line,
+                "character": entry.position.character,
+            })).collect::<Vec<_>>(),
+        }));
+    }
+
+    let mut text = format!(
+        "Outline of {} ({} of {} file(s) with symbols):\n\n{}",
+        root.display(),
+        outlined,
+        paths.len(),
+        text
+    );
+    if !failed.is_empty() {
+        text.push_str(&format!(
+            "Failed to outline {} file(s):\n{}\n",
+            errors.len(),
+            failed
+        ));
+    }
+    if truncated {
+        text.push_str(&format!(
+            "[truncated: outlined the first {} files; raise max_files or narrow path/include]",
+            paths.len()
+        ));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "files": structured,
+            "errors": errors,
+            "truncated": truncated,
+        })),
+    }
+}
+
+/// Symbols checked between `lsp_find_unused` progress messages
+const UNUSED_PROGRESS_INTERVAL: usize = 25;
+
+#[derive(Debug, Deserialize)]
+struct FindUnusedArgs {
+    path: Option<String>,
+    include: Option<String>,
+    language: Option<String>,
+    query: Option<String>,
+    kinds: Option<Vec<String>>,
+    #[serde(default)]
+    include_exported: bool,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default = "default_unused_symbols")]
+    max_symbols: usize,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+fn default_unused_symbols() -> usize {
+    unused::DEFAULT_MAX_SYMBOLS
+}
+
+async fn handle_find_unused(
+    args: Value,
+    lsp_manager: Arc<LspManager>,
+    logger: &McpLogger,
+) -> CallToolResult {
+    let args: FindUnusedArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let root = match listing_root(args.path.as_deref(), &lsp_manager) {
+        Ok(root) => root,
+        Err(message) => return invalid_parameter("path", message),
+    };
+    let kinds = match &args.kinds {
+        Some(names) => match symbol_kinds(names) {
+            Ok(kinds) => kinds,
+            Err(message) => return invalid_parameter("kinds", message),
+        },
+        None => unused::DEFAULT_UNUSED_KINDS.to_vec(),
+    };
+
+    // Candidates, with ranges in UTF-8 columns
+    let (candidates, complete) = match &args.query {
+        Some(query) => {
+            let Some(language) = &args.language else {
+                return invalid_parameter("language", "`language` is required with `query`");
+            };
+            let symbols = match lsp_manager
+                .workspace_symbols(query.clone(), language, PositionEncoding::Utf8)
+                .await
+            {
+                Ok(response) => response.map(symbols::workspace_symbol_list),
+                Err(e) => return lsp_error(&e),
+            };
+            let include = args.include.as_deref();
+            let symbols: Vec<SymbolMatch> = symbols
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|symbol| {
+                    symbol.path.starts_with(&root)
+                        && include
+                            .is_none_or(|pattern| files::glob_matches(&root, pattern, &symbol.path))
+                })
+                .collect();
+            (symbols, true)
+        }
+        None => {
+            let (mut paths, complete) =
+                match source_files(&root, args.include.clone(), &lsp_manager).await {
+                    Ok(found) => found,
+                    Err(message) => return internal_error(message),
+                };
+            if let Some(language) = &args.language {
+                paths.retain(|path| lsp_manager.file_language(path).as_ref() == Some(language));
+            }
+            let outlines: Vec<Vec<SymbolMatch>> = futures::stream::iter(paths)
+                .map(|path| {
+                    let lsp_manager = Arc::clone(&lsp_manager);
+                    async move {
+                        match lsp_manager
+                            .document_symbols(&path, PositionEncoding::Utf8)
+                            .await
+                        {
+                            Ok(Some(response)) => symbols::document_symbol_list(response, path),
+                            _ => Vec::new(),
+                        }
+                    }
+                })
+                .buffered(MAX_BATCH_CONCURRENCY)
+                .collect()
+                .await;
+            (outlines.into_iter().flatten().collect(), complete)
+        }
+    };
+
+    // Drop what can't or shouldn't be reported, reading each file once
+    let mut texts: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut checks = Vec::new();
+    for symbol in candidates {
+        let name = unused::bare_name(&symbol.name).to_string();
+        if !kinds.contains(&symbol.kind)
+            || unused::is_entry_point(&name)
+            || args
+                .exclude
+                .iter()
+                .any(|pattern| glob_match(pattern, &name))
+        {
+            continue;
+        }
+        let text = texts
+            .entry(symbol.path.clone())
+            .or_insert_with(|| std::fs::read_to_string(&symbol.path).ok());
+        let Some(text) = text else {
+            continue;
+        };
+        let position = symbols::name_position(text, symbol.range, &name);
+        let line = line_text(text, position.line).unwrap_or_default();
+        if !args.include_exported {
+            let language = lsp_manager.file_language(&symbol.path).unwrap_or_default();
+            if unused::is_exported(&language, &name, line) {
+                continue;
+            }
+        }
+        let character = convert_character(
+            line,
+            position.character,
+            PositionEncoding::Utf8,
+            args.position_encoding,
+        );
+        checks.push((symbol, name, Position::new(position.line, character)));
+    }
+    checks.sort_by(|(a, _, a_at), (b, _, b_at)| a.path.cmp(&b.path).then(a_at.cmp(b_at)));
+    checks.dedup_by(|(a, _, a_at), (b, _, b_at)| a.path == b.path && a_at == b_at);
+    let max_symbols = args.max_symbols.max(1);
+    let truncated = !complete || checks.len() > max_symbols;
+    checks.truncate(max_symbols);
+
+    let total = checks.len();
+    let encoding = args.position_encoding;
+    let queries: Vec<(PathBuf, Position)> = checks
+        .iter()
+        .map(|(symbol, _, position)| (symbol.path.clone(), *position))
+        .collect();
+    let mut references = futures::stream::iter(queries)
+        .map(|(path, position)| {
+            let lsp_manager = Arc::clone(&lsp_manager);
+            async move {
+                lsp_manager
+                    .find_references(&path, position.line, position.character, false, encoding)
+                    .await
+            }
+        })
+        .buffered(MAX_BATCH_CONCURRENCY);
+
+    let (mut unreferenced, mut failed, mut checked) = (Vec::new(), 0, 0);
+    while let Some(result) = references.next().await {
+        let (symbol, name, position) = &checks[checked];
+        checked += 1;
+        if checked % UNUSED_PROGRESS_INTERVAL == 0 || checked == total {
+            logger.log(
+                LoggingLevel::Info,
+                "lsmcp.unused",
+                serde_json::json!({ "checked": checked, "total": total }),
+            );
+        }
+
+        let locations = match result {
+            Ok(locations) => locations.unwrap_or_default(),
+            Err(e) => {
+                error!("find_references error for {}: {}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+        let Ok(uri) = Url::from_file_path(&symbol.path) else {
+            continue;
+        };
+        let declaration = Location::new(uri, Range::new(*position, *position));
+        if unused::other_references(&locations, &declaration) == 0 {
+            unreferenced.push((symbol, name, position));
+        }
+    }
+
+    let mut text = format!(
+        "Found {} symbol(s) with no references (checked {}",
+        unreferenced.len(),
+        total
+    );
+    if failed > 0 {
+        text.push_str(&format!(", {} failed", failed));
+    }
+    text.push_str(match unreferenced.is_empty() {
+        true => ")\n",
+        false => "):\n",
+    });
+    let mut current: Option<&Path> = None;
+    let mut structured = Vec::with_capacity(unreferenced.len());
+    for (symbol, name, position) in &unreferenced {
+        if current != Some(symbol.path.as_path()) {
+            let relative = symbol.path.strip_prefix(&root).unwrap_or(&symbol.path);
+            text.push_str(&format!("\n{}\n", relative.display()));
+            current = Some(&symbol.path);
+        }
+        text.push_str(&format!(
+            "  - {} ({:?}) at {}:{}\n",
+            name,
+            symbol.kind,
+            position.line + 1,
+            position.character + 1
+        ));
+        structured.push(serde_json::json!({
+            "file": symbol.path.display().to_string(),
+            "name": name,
+            "kind": format!("{:?}", symbol.kind),
+            "line": position.line,
+            "character": position.character,
+        }));
+    }
+    if !unreferenced.is_empty() {
+        text.push_str(
+            "\nReferences come from the language server, so code used only from outside the workspace, through macros or by reflection is listed too.\n",
+        );
+    }
+    if truncated {
+        text.push_str(&format!(
+            "[truncated: checked the first {} symbols; raise max_symbols or narrow path/include/kinds]",
+            total
+        ));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "unused": structured,
+            "checked": total,
+            "failed": failed,
+            "truncated": truncated,
+        })),
+    }
+}
+
+/// The symbol kinds named in `names`
+fn symbol_kinds(names: &[String]) -> Result<Vec<SymbolKind>, String> {
+    names
+        .iter()
+        .map(|name| {
+            outline::parse_symbol_kind(name).ok_or_else(|| format!("unknown symbol kind: {}", name))
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct ImpactAnalysisArgs {
+    file: String,
+    line: u32,
+    character: u32,
+    #[serde(default = "default_impact_depth")]
+    depth: usize,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+fn default_impact_depth() -> usize {
+    impact::DEFAULT_IMPACT_DEPTH
+}
+
+/// State shared while following references for `lsp_impact_analysis`
+struct ImpactSearch {
+    lsp_manager: Arc<LspManager>,
+    outlines: HashMap<PathBuf, Option<DocumentSymbolResponse>>,
+    texts: HashMap<PathBuf, Option<String>>,
+    /// Symbols whose references were already followed (path, name position)
+    followed: std::collections::HashSet<(PathBuf, Position)>,
+    /// References still allowed before the search stops
+    budget: usize,
+    truncated: bool,
+}
+
+impl ImpactSearch {
+    fn text(&mut self, path: &Path) -> Option<&str> {
+        self.texts
+            .entry(path.to_path_buf())
+            .or_insert_with(|| overlay::read_file_sync(path).ok())
+            .as_deref()
+    }
+
+    /// `position` (UTF-8 columns) in `encoding` columns
+    fn convert(&mut self, path: &Path, position: Position, encoding: PositionEncoding) -> Position {
+        let character = self
+            .text(path)
+            .and_then(|text| line_text(text, position.line))
+            .map_or(position.character, |line| {
+                convert_character(line, position.character, PositionEncoding::Utf8, encoding)
+            });
+        Position::new(position.line, character)
+    }
+
+    /// The symbols containing the references to the symbol at `position`
+    /// (UTF-8 columns), followed `depth - 1` more levels
+    fn references_to(
+        &mut self,
+        path: PathBuf,
+        position: Position,
+        depth: usize,
+    ) -> futures::future::BoxFuture<'_, Result<Vec<impact::ImpactNode>, LspError>> {
+        Box::pin(async move {
+            self.followed.insert((path.clone(), position));
+            let locations = self
+                .lsp_manager
+                .find_references(
+                    &path,
+                    position.line,
+                    position.character,
+                    false,
+                    PositionEncoding::Utf8,
+                )
+                .await?
+                .unwrap_or_default();
+
+            let mut hits = Vec::new();
+            for location in locations {
+                let Ok(reference_path) = location.uri.to_file_path() else {
+                    continue;
+                };
+                // Some servers list the declaration even when asked not to
+                let start = location.range.start;
+                if reference_path == path && start.line == position.line {
+                    continue;
+                }
+                if self.budget == 0 {
+                    self.truncated = true;
+                    break;
+                }
+                self.budget -= 1;
+
+                if !self.outlines.contains_key(&reference_path) {
+                    let outline = self
+                        .lsp_manager
+                        .document_symbols(&reference_path, PositionEncoding::Utf8)
+                        .await
+                        .ok()
+                        .flatten();
+                    self.outlines.insert(reference_path.clone(), outline);
+                }
+                let symbol = self.outlines[&reference_path]
+                    .as_ref()
+                    .and_then(|outline| symbols::enclosing_symbol(outline, &reference_path, start));
+                hits.push((reference_path, start, symbol));
+            }
+
+            let mut nodes = impact::group_references(hits);
+            if depth > 1 {
+                for node in &mut nodes {
+                    let Some(symbol) = &node.symbol else {
+                        continue;
+                    };
+                    let name = unused::bare_name(&symbol.name).to_string();
+                    let (symbol_path, range) = (symbol.path.clone(), symbol.range);
+                    let Some(text) = self.text(&symbol_path) else {
+                        continue;
+                    };
+                    let at = symbols::name_position(text, range, &name);
+                    if self.followed.contains(&(symbol_path.clone(), at)) {
+                        continue;
+                    }
+                    node.callers = self.references_to(symbol_path, at, depth - 1).await?;
+                }
+            }
+            Ok(nodes)
+        })
+    }
+}
+
+async fn handle_impact_analysis(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ImpactAnalysisArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    if args.depth == 0 || args.depth > impact::MAX_IMPACT_DEPTH {
+        return invalid_parameter(
+            "depth",
+            format!("depth must be between 1 and {}", impact::MAX_IMPACT_DEPTH),
+        );
+    }
+    let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        args.character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let mut search = ImpactSearch {
+        lsp_manager,
+        outlines: HashMap::new(),
+        texts: HashMap::new(),
+        followed: Default::default(),
+        budget: impact::MAX_IMPACT_REFERENCES,
+        truncated: false,
+    };
+    // Everything below counts UTF-8 columns; results are converted back
+    let (position, name) = match search.text(&file_path) {
+        Some(text) => {
+            let line_text = line_text(text, line).unwrap_or_default();
+            let utf8 = convert_character(
+                line_text,
+                character,
+                args.position_encoding,
+                PositionEncoding::Utf8,
+            );
+            let name = impact::identifier_at(line_text, utf8 as usize).map(str::to_string);
+            (Position::new(line, utf8), name)
+        }
+        None => (Position::new(line, character), None),
+    };
+    let name = name.unwrap_or_else(|| "the symbol".to_string());
+
+    let nodes = match search.references_to(file_path, position, args.depth).await {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            error!("impact_analysis error: {}", e);
+            return lsp_error(&e);
+        }
+    };
+    if nodes.is_empty() {
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!("No references to `{}` found", name),
+            }],
+            is_error: None,
+            structured_content: Some(
+                serde_json::json!({ "affected": [], "files": [], "truncated": false }),
+            ),
+        };
+    }
+
+    let mut text = String::new();
+    let mut files = std::collections::BTreeSet::new();
+    let structured = render_impact(
+        &nodes,
+        0,
+        args.position_encoding,
+        &mut search,
+        &mut text,
+        &mut files,
+    );
+    let references: usize = nodes.iter().map(|node| node.references.len()).sum();
+    let mut text = format!(
+        "Changing `{}` affects {} reference(s) in {} place(s); affected files: {}\n\n{}",
+        name,
+        references,
+        nodes.len(),
+        files.len(),
+        text
+    );
+    if search.truncated {
+        text.push_str(&format!(
+            "\n[truncated: followed the first {} references; lower depth or start from a narrower symbol]",
+            impact::MAX_IMPACT_REFERENCES
+        ));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "affected": structured,
+            "files": files.into_iter().collect::<Vec<_>>(),
+            "truncated": search.truncated,
+        })),
+    }
+}
+
+/// Write `nodes` as an indented tree, collecting their files; returns
+/// them as JSON
+fn render_impact(
+    nodes: &[impact::ImpactNode],
+    depth: usize,
+    encoding: PositionEncoding,
+    search: &mut ImpactSearch,
+    text: &mut String,
+    files: &mut std::collections::BTreeSet<String>,
+) -> Vec<Value> {
+    let mut structured = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let path = node.path.display().to_string();
+        files.insert(path.clone());
+        let references: Vec<Position> = node
+            .references
+            .iter()
+            .map(|position| search.convert(&node.path, *position, encoding))
+            .collect();
+        let lines: Vec<String> = references
+            .iter()
+            .map(|position| (position.line + 1).to_string())
+            .collect();
+        let lines = format!(
+            "{} {}",
+            if lines.len() == 1 { "line" } else { "lines" },
+            lines.join(", ")
+        );
+
+        let indent = "  ".repeat(depth);
+        let symbol = node.symbol.as_ref().map(|symbol| {
+            let at = search.convert(&node.path, symbol.range.start, encoding);
+            text.push_str(&format!(
+                "{}- {} ({:?}) at {}, {}\n",
+                indent,
+                unused::bare_name(&symbol.name),
+                symbol.kind,
+                locations::file_position(&node.path, at),
+                lines
+            ));
+            serde_json::json!({
+                "name": symbol.name,
+                "kind": format!("{:?}", symbol.kind),
+                "line": at.line,
+                "character": at.character,
+            })
+        });
+        if symbol.is_none() {
+            text.push_str(&format!(
+                "{}- (top level) in {}, {}\n",
+                indent,
+                locations::path(&node.path),
+                lines
+            ));
+        }
+
+        let callers = render_impact(&node.callers, depth + 1, encoding, search, text, files);
+        structured.push(serde_json::json!({
+            "file": path,
+            "symbol": symbol,
+            "references": references
+                .iter()
+                .map(|p| serde_json::json!({ "line": p.line, "character": p.character }))
+                .collect::<Vec<_>>(),
+            "callers": callers,
+        }));
+    }
+    structured
+}
+
+/// The files under `root` (matching `include`) that a language server
+/// handles, sorted, and whether the walk saw every file
+async fn source_files(
+    root: &Path,
+    include: Option<String>,
+    lsp_manager: &LspManager,
+) -> Result<(Vec<PathBuf>, bool), String> {
+    let walk_root = root.to_path_buf();
+    let (mut paths, complete) = tokio::task::spawn_blocking(move || {
+        let mut paths = Vec::new();
+        let complete = files::walk_files(&walk_root, |path| {
+            if include
+                .as_deref()
+                .is_none_or(|pattern| files::glob_matches(&walk_root, pattern, &path))
+            {
+                paths.push(path);
+            }
+            true
+        });
+        (paths, complete)
+    })
+    .await
+    .map_err(|e| format!("listing failed: {}", e))?;
+
+    paths.retain(|path| lsp_manager.file_language(path).is_some());
+    paths.sort();
+    Ok((paths, complete))
+}
+
+/// The symbol each hit is in, from its file's document symbols
+///
+/// Files without a language server (or whose server fails) get no symbols.
+async fn enclosing_symbols(
+    matches: &[search::TextMatch],
+    lsp_manager: &LspManager,
+) -> Vec<Option<SymbolMatch>> {
+    let mut outlines: HashMap<&Path, Option<DocumentSymbolResponse>> = HashMap::new();
+    let mut symbols = Vec::with_capacity(matches.len());
+    for hit in matches {
+        if !outlines.contains_key(hit.path.as_path()) {
+            let outline = lsp_manager
+                .document_symbols(&hit.path, PositionEncoding::Utf8)
+                .await
+                .ok()
+                .flatten();
+            outlines.insert(&hit.path, outline);
+        }
+        let position = Position::new(hit.line, hit.column as u32);
+        symbols.push(
+            outlines[hit.path.as_path()]
+                .as_ref()
+                .and_then(|outline| symbols::enclosing_symbol(outline, &hit.path, position)),
+        );
+    }
+    symbols
+}
+
+/// The directory a listing tool works in: `path` (relative to the
+/// workspace root unless absolute), else the workspace root
+fn listing_root(path: Option<&str>, lsp_manager: &LspManager) -> Result<PathBuf, String> {
+    let root = match path {
+        Some(path) => lsp_manager.workspace_root().join(path),
+        None => lsp_manager.workspace_root().to_path_buf(),
+    };
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()));
+    }
+    Ok(root)
+}
+
+/// Most symbols a by-name definition lookup follows
+const MAX_NAME_MATCHES: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct DefinitionByNameArgs {
+    name: String,
+    container: Option<String>,
+    file: Option<String>,
+    language: Option<String>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    #[serde(default)]
+    include_source: bool,
+}
+
+async fn handle_definition_by_name(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: DefinitionByNameArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let matches = match find_named_symbols(
+        &lsp_manager,
+        &args.name,
+        args.container.as_deref(),
+        args.file.as_deref(),
+        args.language.as_deref(),
+    )
+    .await
+    {
+        Ok(matches) => matches,
+        Err(result) => return result,
+    };
+
+    let mut definitions: Vec<String> = Vec::new();
+    for symbol in matches.iter().take(MAX_NAME_MATCHES) {
+        let position = symbol.position(&args.name, args.position_encoding);
+        let response = match lsp_manager
+            .goto_definition(
+                &symbol.path,
+                position.line,
+                position.character,
+                args.position_encoding,
+            )
+            .await
+        {
+            Ok(response) => response.filter(|r| !merge::definition_is_empty(r)),
+            Err(e) => {
+                error!("goto_definition error: {}", e);
+                None
+            }
+        };
+
+        // A symbol the server can't follow is its own definition
+        let found = match response {
+            Some(response) => {
+                let targets = definition_targets(response);
+                let blame = LocationBlame::of(&lsp_manager, &targets).await;
+                targets
+                    .iter()
+                    .map(|target| format_definition_target(target, args.include_source, &blame))
+                    .collect()
+            }
+            None => {
+                let mut found = format_symbol_match(symbol, position);
+                if args.include_source {
+                    found.push_str(&definition_source(&symbol.path, symbol.range));
+                }
+                vec![found]
+            }
+        };
+        for definition in found {
+            if !definitions.contains(&definition) {
+                definitions.push(definition);
+            }
+        }
+    }
+
+    let mut text = format!(
+        "Found {} definition(s) of '{}':\n{}",
+        definitions.len(),
+        args.name,
+        definitions.join("\n")
+    );
+    if matches.len() > MAX_NAME_MATCHES {
+        text.push_str(&format!(
+            "\n\n{} more symbols matched; pass `file` or `container` to narrow the search",
+            matches.len() - MAX_NAME_MATCHES
+        ));
+    }
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReferencesByNameArgs {
+    name: String,
+    container: Option<String>,
+    file: Option<String>,
+    language: Option<String>,
+    #[serde(rename = "includeDeclaration", default = "default_true")]
+    include_declaration: bool,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+}
+
+async fn handle_references_by_name(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ReferencesByNameArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let matches = match find_named_symbols(
+        &lsp_manager,
+        &args.name,
+        args.container.as_deref(),
+        args.file.as_deref(),
+        args.language.as_deref(),
+    )
+    .await
+    {
+        Ok(matches) => matches,
+        Err(result) => return result,
+    };
+
+    // References of several unrelated symbols would be misleading
+    if matches.len() > 1 {
+        let candidates = matches
+            .iter()
+            .map(|symbol| {
+                let position = symbol.position(&args.name, args.position_encoding);
+                format!("- {}", format_symbol_match(symbol, position))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!(
+                    "{} symbols are named '{}'; pass `file` or `container` to pick one, or use lsp_find_references at one of them:\n{}",
+                    matches.len(),
+                    args.name,
+                    candidates
+                ),
+            }],
+            is_error: None,
+            structured_content: None,
+        };
+    }
+
+    let symbol = &matches[0];
+    let position = symbol.position(&args.name, args.position_encoding);
+    match lsp_manager
+        .find_references(
+            &symbol.path,
+            position.line,
+            position.character,
+            args.include_declaration,
+            args.position_encoding,
+        )
+        .await
+    {
+        Ok(locations) => {
+            let locations = locations.unwrap_or_default();
+            let blame = LocationBlame::of(&lsp_manager, &locations).await;
+            CallToolResult {
                 content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
+                    text: format_locations(locations, &blame),
                 }],
-                is_error: Some(true),
-            };
+                is_error: None,
+                structured_content: None,
+            }
+        }
+        Err(e) => {
+            error!("find_references error: {}", e);
+            lsp_error(&e)
+        }
+    }
+}
+
+/// Symbols named `name`, or the result to return when there are none
+async fn find_named_symbols(
+    lsp_manager: &LspManager,
+    name: &str,
+    container: Option<&str>,
+    file: Option<&str>,
+    language: Option<&str>,
+) -> Result<Vec<SymbolMatch>, CallToolResult> {
+    if file.is_none() && language.is_none() {
+        return Err(invalid_arguments("either `file` or `language` is required"));
+    }
+
+    let file = file.map(PathBuf::from);
+    match lsp_manager
+        .find_symbols(name, container, file.as_deref(), language)
+        .await
+    {
+        Ok(matches) if matches.is_empty() => Err(CallToolResult {
+            content: vec![ToolContent::Text {
+                text: match container {
+                    Some(container) => format!("No symbol named '{}' in '{}'", name, container),
+                    None => format!("No symbol named '{}'", name),
+                },
+            }],
+            is_error: None,
+            structured_content: None,
+        }),
+        Ok(matches) => Ok(matches),
+        Err(e) => {
+            error!("find_symbols error: {}", e);
+            Err(lsp_error(&e))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListRunnablesArgs {
+    file: String,
+    line: Option<u32>,
+    character: Option<u32>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+async fn handle_list_runnables(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ListRunnablesArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file_path = PathBuf::from(&args.file);
+    let position = match (args.line, args.character) {
+        (Some(line), Some(character)) => match resolve_position(
+            &lsp_manager,
+            &file_path,
+            line,
+            character,
+            args.index_base,
+            args.position_encoding,
+        )
+        .await
+        {
+            Ok((line, character)) => Some(Position::new(line, character)),
+            Err(e) => return invalid_arguments(e),
+        },
+        (None, None) => None,
+        _ => return invalid_arguments("give both `line` and `character`, or neither"),
+    };
+
+    let runnables = match lsp_manager
+        .runnables(&file_path, position, args.position_encoding)
+        .await
+    {
+        Ok(runnables) => runnables,
+        Err(e) => {
+            error!("runnables error: {}", e);
+            return lsp_error(&e);
+        }
+    };
+
+    if runnables.is_empty() {
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: "No runnables found; the file's servers may not report any (rust-analyzer and gopls do)".to_string(),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({ "runnables": [] })),
+        };
+    }
+
+    let mut text = format!("Found {} runnable(s):\n", runnables.len());
+    for runnable in &runnables {
+        let location = runnable
+            .location
+            .as_ref()
+            .map(|l| format!(" ({})", format_location(l)))
+            .unwrap_or_default();
+        text.push_str(&format!("- {}{}\n", runnable.label, location));
+        text.push_str(&format!("  $ {}\n", runnable.command_line()));
+        if let Some(cwd) = &runnable.cwd {
+            text.push_str(&format!("  in {}\n", cwd.display()));
+        }
+    }
+    let structured: Vec<Value> = runnables
+        .iter()
+        .map(|runnable| {
+            serde_json::json!({
+                "label": runnable.label,
+                "file": runnable.location.as_ref().map(|l| display_path(&l.uri)),
+                "line": runnable.location.as_ref().map(|l| l.range.start.line),
+                "character": runnable.location.as_ref().map(|l| l.range.start.character),
+                "program": runnable.program,
+                "args": runnable.args,
+                "cwd": runnable.cwd,
+                "env": runnable.env,
+                "command": runnable.command_line(),
+            })
+        })
+        .collect();
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({ "runnables": structured })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RunRunnableArgs {
+    file: String,
+    label: String,
+    #[serde(default = "default_run_timeout")]
+    timeout_secs: u64,
+}
+
+fn default_run_timeout() -> u64 {
+    run::DEFAULT_RUN_TIMEOUT_SECS
+}
+
+async fn handle_run_runnable(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    if lsp_manager.settings().allow_run != Some(true) {
+        return disabled_error(
+            "running commands is disabled; set `allow_run = true` under [settings] to enable lsp_run_runnable",
+        );
+    }
+    let args: RunRunnableArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let file_path = PathBuf::from(&args.file);
+
+    // Look the runnable up again rather than taking a command from the
+    // caller: only what the server reports is run
+    let runnables = match lsp_manager
+        .runnables(&file_path, None, PositionEncoding::Utf16)
+        .await
+    {
+        Ok(runnables) => runnables,
+        Err(e) => return lsp_error(&e),
+    };
+    let Some(runnable) = runnables.iter().find(|r| r.label == args.label) else {
+        let labels: Vec<&str> = runnables.iter().map(|r| r.label.as_str()).collect();
+        return invalid_parameter(
+            "label",
+            match labels.is_empty() {
+                true => format!("no runnables in {}", args.file),
+                false => format!(
+                    "no runnable labeled '{}' in {}; available: {}",
+                    args.label,
+                    args.file,
+                    labels.join(", ")
+                ),
+            },
+        );
+    };
+
+    let timeout = Duration::from_secs(args.timeout_secs.max(1));
+    let output = match run::run(runnable, lsp_manager.workspace_root(), timeout).await {
+        Ok(output) => output,
+        Err(e) => return lsp_error(&e),
+    };
+
+    run_result(runnable, &output, args.timeout_secs)
+}
+
+/// What `runnable` printed and how it ended, for tools that run commands
+pub(crate) fn run_result(
+    runnable: &Runnable,
+    output: &run::RunOutput,
+    timeout_secs: u64,
+) -> CallToolResult {
+    let status = match (output.timed_out, output.exit_code) {
+        (true, _) => format!("timed out after {}s", timeout_secs),
+        (false, Some(code)) => format!("exit code {}", code),
+        (false, None) => "killed by a signal".to_string(),
+    };
+    let mut text = format!(
+        "$ {}\n(in {}) {} after {:.1}s\n",
+        runnable.command_line(),
+        output.cwd.display(),
+        status,
+        output.duration.as_secs_f64()
+    );
+    for (name, stream) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        if !stream.trim().is_empty() {
+            text.push_str(&format!("\n{}:\n{}\n", name, stream.trim_end()));
+        }
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        // The call worked even if the command failed; the status says how
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "command": runnable.command_line(),
+            "cwd": output.cwd,
+            "exit_code": output.exit_code,
+            "timed_out": output.timed_out,
+            "duration_secs": output.duration.as_secs_f64(),
+            "stdout": output.stdout,
+            "stderr": output.stderr,
+        })),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawRequestArgs {
+    method: String,
+    #[serde(default)]
+    params: Value,
+    file: Option<String>,
+    language: Option<String>,
+    server: Option<String>,
+}
+
+async fn handle_raw_request(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    if lsp_manager.settings().allow_raw_requests != Some(true) {
+        return disabled_error(
+            "raw requests are disabled; set `allow_raw_requests = true` under [settings] to enable lsp_raw_request",
+        );
+    }
+    let args: RawRequestArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let file = args.file.as_ref().map(PathBuf::from);
+    match lsp_manager
+        .raw_request(
+            &args.method,
+            args.params,
+            file.as_deref(),
+            args.language.as_deref(),
+            args.server.as_deref(),
+        )
+        .await
+    {
+        Ok(result) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: serde_json::to_string_pretty(&result).unwrap_or_default(),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({ "result": result })),
+        },
+        Err(e) => {
+            error!("{} error: {}", args.method, e);
+            lsp_error(&e)
         }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtensionArgs {
+    file: String,
+    line: Option<u32>,
+    character: Option<u32>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+/// Run a server's extension request; `tool` is one of `extension_tools`
+async fn handle_extension(tool: &str, args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ExtensionArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
     };
 
+    // Document requests ignore any position given
     let file_path = PathBuf::from(&args.file);
+    let position = match (args.line, args.character) {
+        (Some(line), Some(character)) => match resolve_position(
+            &lsp_manager,
+            &file_path,
+            line,
+            character,
+            args.index_base,
+            args.position_encoding,
+        )
+        .await
+        {
+            Ok((line, character)) => Some(Position::new(line, character)),
+            Err(e) => return invalid_arguments(e),
+        },
+        _ => None,
+    };
 
     match lsp_manager
-        .hover(&file_path, args.line, args.character)
+        .extension_request(tool, &file_path, position, args.position_encoding)
         .await
     {
-        Ok(Some(hover)) => {
-            let text = format_hover(hover);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
-            }
-        }
-        Ok(None) => CallToolResult {
+        Ok(result) => CallToolResult {
             content: vec![ToolContent::Text {
-                text: "No hover information available".to_string(),
+                text: render_extension_result(&result),
             }],
             is_error: None,
+            structured_content: Some(serde_json::json!({ "result": result })),
         },
         Err(e) => {
-            error!("hover error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
+            error!("{} error: {}", tool, e);
+            lsp_error(&e)
+        }
+    }
+}
+
+/// An extension request's result as text
+///
+/// Strings are shown as they are (`file://` URIs as paths) and objects of
+/// strings field by field (rust-analyzer's macro expansion is
+/// `{ name, expansion }`); anything else is shown as JSON.
+fn render_extension_result(result: &Value) -> String {
+    match result {
+        Value::Null => "No result".to_string(),
+        Value::String(text) => match Url::parse(text) {
+            Ok(uri) if uri.scheme() == "file" => display_path(&uri),
+            _ => text.clone(),
+        },
+        Value::Object(fields) if fields.values().all(Value::is_string) => fields
+            .iter()
+            .map(|(name, value)| {
+                let value = value.as_str().unwrap_or_default();
+                match value.contains('\n') {
+                    true => format!("{}:\n{}", name, value),
+                    false => format!("{}: {}", name, value),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => serde_json::to_string_pretty(other).unwrap_or_default(),
+    }
+}
+
+/// Tools `lsp_batch` can run
+const BATCH_TOOLS: &[&str] = &[
+    "lsp_goto_definition",
+    "lsp_find_references",
+    "lsp_hover",
+    "lsp_document_symbols",
+    "lsp_enclosing_symbol",
+    "lsp_diagnostics",
+    "lsp_workspace_symbols",
+    "lsp_find_definition_by_name",
+    "lsp_references_by_name",
+    "lsp_list_runnables",
+    "lsp_impact_analysis",
+];
+
+/// Name the tools `lsp_batch` can run as `[tools]` offers them, in a
+/// schema taking batch calls (other schemas are left alone)
+pub fn expose_batch_tools(schema: &mut Value, config: &ToolsConfig) {
+    if let Some(tool) = schema.pointer_mut("/properties/calls/items/properties/tool") {
+        tool["enum"] = BATCH_TOOLS
+            .iter()
+            .map(|tool| config.exposed_name(tool))
+            .collect();
+    }
+}
+
+/// Most calls in one batch
+const MAX_BATCH_CALLS: usize = 50;
+
+/// Most batch calls running at once
+const MAX_BATCH_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct BatchArgs {
+    calls: Vec<BatchCall>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BatchCall {
+    tool: String,
+    arguments: Option<Value>,
+}
+
+async fn handle_batch(args: Value, ctx: ToolContext) -> CallToolResult {
+    let args: BatchArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    if args.calls.is_empty() {
+        return invalid_parameter("calls", "calls must not be empty");
+    }
+    if args.calls.len() > MAX_BATCH_CALLS {
+        return invalid_parameter(
+            "calls",
+            format!(
+                "at most {} calls per batch, got {}",
+                MAX_BATCH_CALLS,
+                args.calls.len()
+            ),
+        );
+    }
+
+    // Calls name tools as they are offered, after `[tools]` renames
+    let tools_config = ctx.lsp_manager.tools_config();
+    let batchable: Vec<String> = BATCH_TOOLS
+        .iter()
+        .map(|tool| tools_config.exposed_name(tool))
+        .collect();
+    let mut calls = args.calls.clone();
+    for call in &mut calls {
+        match batchable.iter().position(|name| *name == call.tool) {
+            Some(i) => call.tool = BATCH_TOOLS[i].to_string(),
+            None => {
+                return invalid_parameter(
+                    "calls",
+                    format!(
+                        "{} can't be batched; batchable tools are {}",
+                        call.tool,
+                        batchable.join(", ")
+                    ),
+                )
             }
         }
     }
+    if let Some(call) = calls
+        .iter()
+        .find(|call| !tools_config.is_enabled(&call.tool))
+    {
+        return disabled_tool(&call.tool);
+    }
+
+    let definitions = get_tool_definitions();
+    let results: Vec<CallToolResult> = futures::stream::iter(calls)
+        .map(|call| {
+            let ctx = ctx.clone();
+            let definitions = &definitions;
+            async move {
+                let (Some(definition), Some(handler)) = (
+                    definitions.iter().find(|tool| tool.name == call.tool),
+                    builtin_handler(&call.tool),
+                ) else {
+                    return unknown_tool(&call.tool);
+                };
+                let mut args = call.arguments.unwrap_or(Value::Null);
+                if let Err(result) = check_arguments(
+                    &call.tool,
+                    &mut args,
+                    &definition.input_schema,
+                    true,
+                    &ctx.lsp_manager,
+                )
+                .await
+                {
+                    return result;
+                }
+                let language = file_language(&args);
+                let content = file_content(&call.tool, &args);
+                let manager = Arc::clone(&ctx.lsp_manager);
+                manager
+                    .with_file_language(
+                        language,
+                        manager.with_file_content(content, handler(args, ctx)),
+                    )
+                    .await
+            }
+        })
+        .buffered(MAX_BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut text = String::new();
+    let mut structured = Vec::new();
+    for (i, (call, result)) in args.calls.iter().zip(results).enumerate() {
+        let result_text: Vec<&str> = result
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                ToolContent::Text { text } => Some(text.as_str()),
+                ToolContent::Image { .. } => None,
+            })
+            .collect();
+        let result_text = result_text.join("\n");
+        let is_error = result.is_error.unwrap_or(false);
+
+        text.push_str(&format!(
+            "## {}. {}{}\n{}\n\n",
+            i + 1,
+            call.tool,
+            if is_error { " (failed)" } else { "" },
+            result_text.trim_end()
+        ));
+        structured.push(serde_json::json!({
+            "tool": call.tool,
+            "is_error": is_error,
+            "text": result_text,
+            "structured_content": result.structured_content,
+        }));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: text.trim_end().to_string(),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({ "results": structured })),
+    }
 }
 
 #[derive(Debug, Deserialize)]
-struct DocumentSymbolsArgs {
-    file: String,
+struct WarmupArgs {
+    languages: Option<Vec<String>>,
+    #[serde(default = "default_warmup_timeout")]
+    timeout_secs: u64,
 }
 
-async fn handle_document_symbols(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
-    let args: DocumentSymbolsArgs = match serde_json::from_value(args) {
+fn default_warmup_timeout() -> u64 {
+    60
+}
+
+async fn handle_warmup(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: WarmupArgs = match serde_json::from_value(args) {
         Ok(a) => a,
-        Err(e) => {
-            return CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
-                }],
-                is_error: Some(true),
-            };
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let languages = args
+        .languages
+        .unwrap_or_else(|| lsp_manager.preload_languages());
+
+    if languages.is_empty() {
+        return invalid_parameter(
+            "languages",
+            "No languages to warm up (pass `languages` or set `preload` in the config)",
+        );
+    }
+
+    let results = lsp_manager
+        .warmup(&languages, Duration::from_secs(args.timeout_secs))
+        .await;
+
+    let failed = results.iter().any(|(_, result)| result.is_err());
+    let mut text = String::from("Warm-up results:\n\n");
+    for (language, result) in results {
+        let status = match result {
+            Ok(true) => "ready".to_string(),
+            Ok(false) => format!("still indexing after {}s", args.timeout_secs),
+            Err(e) => {
+                error!("warmup error for {}: {}", language, e);
+                format!("failed: {}", e)
+            }
+        };
+        text.push_str(&format!("- {}: {}\n", language, status));
+    }
+
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: failed.then_some(true),
+        structured_content: None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PrepareWorkspaceArgs {
+    patterns: Vec<String>,
+    language: Option<String>,
+    #[serde(default = "default_prepare_limit")]
+    limit: usize,
+}
+
+fn default_prepare_limit() -> usize {
+    prepare::DEFAULT_PREPARE_LIMIT
+}
+
+async fn handle_prepare_workspace(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: PrepareWorkspaceArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    if args.patterns.is_empty() {
+        return invalid_parameter("patterns", "Give at least one glob of files to open");
+    }
+    if let Some(language) = &args.language {
+        if !lsp_manager.language_supported(language) {
+            return invalid_parameter(
+                "language",
+                format!("No language server is configured for {}", language),
+            );
+        }
+    }
+
+    let prepared = lsp_manager
+        .prepare_workspace(&args.patterns, args.language.as_deref(), args.limit)
+        .await;
+    let failed: Vec<Value> = prepared
+        .failed
+        .iter()
+        .map(|(path, error)| serde_json::json!({ "file": path.display().to_string(), "error": error }))
+        .collect();
+
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: prepared.render(),
+        }],
+        is_error: (prepared.files == 0 && !prepared.failed.is_empty()).then_some(true),
+        structured_content: Some(serde_json::json!({
+            "matched": prepared.matched,
+            "files": prepared.files,
+            "opened": prepared.opened,
+            "unsupported": prepared.unsupported,
+            "failed": failed,
+        })),
+    }
+}
+
+async fn handle_server_status(lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let status = lsp_manager.server_status().await;
+
+    let text = if status.is_empty() {
+        "No language servers running".to_string()
+    } else {
+        let mut text = format!("{} language server(s) running:\n\n", status.len());
+        for health in &status {
+            text.push_str(&health.render());
+        }
+        text
+    };
+
+    let text = match lsp_manager.cache_stats() {
+        Some((stats, ttl)) => format!(
+            "{}\nResponse cache: {} entries, {} hits, {} misses (ttl {}s)",
+            text.trim_end(),
+            stats.entries,
+            stats.hits,
+            stats.misses,
+            ttl.as_secs()
+        ),
+        None => format!("{}\nResponse cache: disabled", text.trim_end()),
+    };
+
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: None,
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MetricsFormat {
+    #[default]
+    Text,
+    Prometheus,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetricsArgs {
+    #[serde(default)]
+    format: MetricsFormat,
+}
+
+fn handle_metrics(args: Value) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let format = match serde_json::from_value::<Option<MetricsArgs>>(args) {
+        Ok(args) => args.map(|args| args.format).unwrap_or_default(),
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let metrics = crate::metrics::global();
+    let text = match format {
+        MetricsFormat::Text => metrics.render_text(),
+        MetricsFormat::Prometheus => metrics.render_prometheus(),
+    };
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: None,
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UsageScope {
+    #[default]
+    All,
+    Session,
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageStatsArgs {
+    #[serde(default)]
+    scope: UsageScope,
+}
+
+fn handle_usage_stats(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let scope = match serde_json::from_value::<Option<UsageStatsArgs>>(args) {
+        Ok(args) => args.map(|args| args.scope).unwrap_or_default(),
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let usage = crate::usage::global();
+    let tools_config = lsp_manager.tools_config();
+    let offered: Vec<String> = get_tool_definitions()
+        .into_iter()
+        .map(|tool| tool.name)
+        .filter(|name| tools_config.is_enabled(name))
+        .collect();
+    let (report, text) = match (scope, usage.recorded()) {
+        (UsageScope::All, Some(recorded)) => {
+            let text = recorded.render("Tool usage recorded", &offered);
+            (recorded, text)
         }
+        (UsageScope::All, None) => {
+            let session = usage.session();
+            let text = format!(
+                "{}\nOnly this session's usage is known, as `usage_stats` is off",
+                session.render("Tool usage this session", &offered)
+            );
+            (session, text)
+        }
+        (UsageScope::Session, _) => {
+            let session = usage.session();
+            let text = session.render("Tool usage this session", &offered);
+            (session, text)
+        }
+    };
+    CallToolResult {
+        content: vec![ToolContent::Text { text }],
+        is_error: None,
+        structured_content: serde_json::to_value(report).ok(),
+    }
+}
+
+/// Files `lsp_problems_summary` lists unless asked for more
+const DEFAULT_PROBLEM_FILES: usize = 20;
+
+#[derive(Debug, Deserialize)]
+struct ProblemsSummaryArgs {
+    #[serde(default = "default_problem_files")]
+    max_files: usize,
+}
+
+fn default_problem_files() -> usize {
+    DEFAULT_PROBLEM_FILES
+}
+
+fn handle_problems_summary(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let max_files = match serde_json::from_value::<Option<ProblemsSummaryArgs>>(args) {
+        Ok(args) => args.map_or(DEFAULT_PROBLEM_FILES, |args| args.max_files),
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let summary = problems::global().summary(Some(lsp_manager.workspace_root()));
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: format_problems_summary(&summary, max_files),
+        }],
+        is_error: None,
+        structured_content: serde_json::to_value(&summary).ok(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameArgs {
+    file: String,
+    line: u32,
+    character: u32,
+    new_name: String,
+    dry_run: Option<bool>,
+    #[serde(default = "default_position_encoding")]
+    position_encoding: PositionEncoding,
+    index_base: Option<u32>,
+}
+
+async fn handle_rename(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: RenameArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
     };
 
     let file_path = PathBuf::from(&args.file);
+    let (line, character) = match resolve_position(
+        &lsp_manager,
+        &file_path,
+        args.line,
+        args.character,
+        args.index_base,
+        args.position_encoding,
+    )
+    .await
+    {
+        Ok(position) => position,
+        Err(e) => return invalid_arguments(e),
+    };
 
-    match lsp_manager.document_symbols(&file_path).await {
-        Ok(Some(response)) => {
-            let text = format_document_symbols(response);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
-            }
+    // The edit's positions are turned into byte offsets, so ask for UTF-8
+    let character = match tokio::fs::read_to_string(&file_path).await {
+        Ok(text) => line_text(&text, line).map_or(character, |text| {
+            convert_character(
+                text,
+                character,
+                args.position_encoding,
+                PositionEncoding::Utf8,
+            )
+        }),
+        Err(_) => character,
+    };
+    let edit = match lsp_manager
+        .rename(
+            &file_path,
+            line,
+            character,
+            &args.new_name,
+            PositionEncoding::Utf8,
+        )
+        .await
+    {
+        Ok(Some(edit)) => edit,
+        Ok(None) => return edit_error("No server can rename the symbol here".to_string()),
+        Err(e) => {
+            error!("rename error: {}", e);
+            return edit_error(e.to_string());
         }
-        Ok(None) => CallToolResult {
+    };
+
+    let changes = match edits::file_changes(&edit) {
+        Ok(changes) => changes,
+        Err(e) => return edit_error(e.to_string()),
+    };
+    let edit = edits::PendingEdit::new(format!("Rename to `{}`", args.new_name), changes);
+    apply_or_preview(&lsp_manager, edit, args.dry_run)
+}
+
+/// Write `edit`, or keep it for `lsp_confirm_edit` and show its diff
+///
+/// `dry_run` overrides the `edit_policy` setting.
+fn apply_or_preview(
+    lsp_manager: &LspManager,
+    edit: edits::PendingEdit,
+    dry_run: Option<bool>,
+) -> CallToolResult {
+    let preview = dry_run
+        .unwrap_or(lsp_manager.settings().edit_policy.unwrap_or_default() == EditPolicy::Preview);
+    let changes = &edit.changes;
+    if changes.is_empty() {
+        return CallToolResult {
             content: vec![ToolContent::Text {
-                text: "No symbols found".to_string(),
+                text: format!("{}: nothing to change", edit.description),
             }],
             is_error: None,
+            structured_content: None,
+        };
+    }
+    // Tools may only write where they may read
+    if let Some(e) = changes
+        .iter()
+        .find_map(|change| lsp_manager.check_path(&change.path).err())
+    {
+        return edit_error(e.to_string());
+    }
+
+    let root = lsp_manager.workspace_root();
+    let diff = edits::unified_diff(changes, root);
+    let files: Vec<Value> = changes
+        .iter()
+        .map(|change| {
+            serde_json::json!({
+                "path": relative_path(&change.path, root),
+                "edits": change.edits,
+            })
+        })
+        .collect();
+    let summary = format!(
+        "{}: {} edit(s) in {} file(s)",
+        edit.description,
+        changes.iter().map(|change| change.edits).sum::<usize>(),
+        changes.len()
+    );
+
+    if preview {
+        let token = edits::pending().insert(edit);
+        return CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!(
+                    "{} (not applied)\n\n{}\nTo apply it, call lsp_confirm_edit with token \"{}\"",
+                    summary, diff, token
+                ),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({
+                "token": token,
+                "applied": false,
+                "files": files,
+            })),
+        };
+    }
+
+    match edit.apply(edit_journal(lsp_manager).as_ref()) {
+        Ok(()) => CallToolResult {
+            content: vec![ToolContent::Text {
+                text: format!("{} (applied)\n\n{}", summary, diff),
+            }],
+            is_error: None,
+            structured_content: Some(serde_json::json!({
+                "applied": true,
+                "files": files,
+            })),
         },
-        Err(e) => {
-            error!("document_symbols error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
-            }
-        }
+        Err(e) => edit_error(e.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfirmEditArgs {
+    token: String,
+}
+
+fn handle_confirm_edit(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    let args: ConfirmEditArgs = match serde_json::from_value(args) {
+        Ok(a) => a,
+        Err(e) => return invalid_arguments(e),
+    };
+    let Some(pending) = edits::pending().take(&args.token) else {
+        return edit_error(format!(
+            "No edit is waiting under token \"{}\"; it may have expired or been applied already",
+            args.token
+        ));
+    };
+    if let Err(e) = pending.apply(edit_journal(&lsp_manager).as_ref()) {
+        return edit_error(e.to_string());
+    }
+
+    let root = lsp_manager.workspace_root();
+    let files: Vec<String> = pending
+        .changes
+        .iter()
+        .map(|change| relative_path(&change.path, root))
+        .collect();
+    CallToolResult {
+        content: vec![ToolContent::Text {
+            text: format!(
+                "{}: applied to {} file(s)\n{}",
+                pending.description,
+                files.len(),
+                files.join("\n")
+            ),
+        }],
+        is_error: None,
+        structured_content: Some(serde_json::json!({
+            "applied": true,
+            "files": files,
+        })),
     }
 }
 
+/// The journal applied edits are recorded in, if the data directory is
+/// known
+fn edit_journal(lsp_manager: &LspManager) -> Option<edits::EditJournal> {
+    edits::EditJournal::for_workspace(lsp_manager.workspace_root()).ok()
+}
+
 #[derive(Debug, Deserialize)]
-struct DiagnosticsArgs {
-    file: String,
+struct UndoEditArgs {
+    id: Option<u64>,
+    dry_run: Option<bool>,
+    #[serde(default)]
+    force: bool,
 }
 
-async fn handle_diagnostics(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
-    let args: DiagnosticsArgs = match serde_json::from_value(args) {
-        Ok(a) => a,
-        Err(e) => {
-            return CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
-                }],
-                is_error: Some(true),
-            };
-        }
+fn handle_undo_last_edit(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
+    // The arguments are all optional, so clients may leave them out
+    let args = match serde_json::from_value::<Option<UndoEditArgs>>(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => UndoEditArgs {
+            id: None,
+            dry_run: None,
+            force: false,
+        },
+        Err(e) => return invalid_arguments(e),
     };
+    let Some(journal) = edit_journal(&lsp_manager) else {
+        return edit_error("No data directory to keep the edit journal in".to_string());
+    };
+    let undo = journal
+        .get(args.id)
+        .and_then(|entry| entry.undo(args.force));
+    match undo {
+        Ok(undo) => apply_or_preview(&lsp_manager, undo, args.dry_run),
+        Err(e) => edit_error(e.to_string()),
+    }
+}
 
-    let file_path = PathBuf::from(&args.file);
+/// `path` relative to `root` where it's inside it
+fn relative_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
 
-    match lsp_manager.get_diagnostics(&file_path).await {
-        Ok(diagnostics) => {
-            let text = format_diagnostics(diagnostics);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
-            }
-        }
-        Err(e) => {
-            error!("get_diagnostics error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
-            }
-        }
+fn edit_error(message: String) -> CallToolResult {
+    ToolError {
+        kind: "edit_failed",
+        message,
+        retryable: false,
+        parameter: None,
     }
+    .into_result()
 }
 
 #[derive(Debug, Deserialize)]
-struct WorkspaceSymbolsArgs {
-    query: String,
-    language: String,
+#[serde(rename_all = "snake_case")]
+enum ManageAction {
+    List,
+    Install,
+    InstallDetected,
+    Uninstall,
+    Update,
+    CheckUpdates,
+    Prune,
 }
 
-async fn handle_workspace_symbols(args: Value, lsp_manager: Arc<LspManager>) -> CallToolResult {
-    let args: WorkspaceSymbolsArgs = match serde_json::from_value(args) {
+#[derive(Debug, Deserialize)]
+struct ManageServersArgs {
+    action: ManageAction,
+    server: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn handle_manage_servers(
+    args: Value,
+    lsp_manager: Arc<LspManager>,
+    logger: &McpLogger,
+) -> CallToolResult {
+    let args: ManageServersArgs = match serde_json::from_value(args) {
         Ok(a) => a,
-        Err(e) => {
+        Err(e) => return invalid_arguments(e),
+    };
+
+    let server = match (&args.action, args.server.as_deref()) {
+        (ManageAction::Install | ManageAction::Uninstall | ManageAction::Update, None) => {
+            return invalid_parameter(
+                "server",
+                format!(
+                    "`server` is required for {}",
+                    format!("{:?}", args.action).to_lowercase()
+                ),
+            );
+        }
+        (_, server) => server.unwrap_or_default(),
+    };
+
+    let result = match args.action {
+        ManageAction::List => {
+            let servers = lsp_manager.installed_servers().await;
+            if servers.is_empty() {
+                Ok("No servers installed by lsmcp".to_string())
+            } else {
+                let mut text = format!("{} server(s) installed by lsmcp:\n\n", servers.len());
+                for server in servers {
+                    text.push_str(&format!(
+                        "- {} {} ({})\n",
+                        server.name,
+                        server.version.as_deref().unwrap_or("(unknown version)"),
+                        server.binary_path.display()
+                    ));
+                }
+                Ok(text)
+            }
+        }
+        ManageAction::Install => lsp_manager
+            .install_server(server)
+            .await
+            .map(|(name, path)| format!("Installed {} to {}", name, path.display())),
+        ManageAction::InstallDetected => {
+            // Report each server's progress while the installs run
+            let on_event = |event: InstallEvent| {
+                let (level, data) = match event {
+                    InstallEvent::Started { name } => (
+                        LoggingLevel::Info,
+                        serde_json::json!({ "server": name, "status": "installing" }),
+                    ),
+                    InstallEvent::Finished { name, binary_path } => (
+                        LoggingLevel::Info,
+                        serde_json::json!({ "server": name, "status": "installed", "path": binary_path }),
+                    ),
+                    InstallEvent::Failed { name, error } => (
+                        LoggingLevel::Warning,
+                        serde_json::json!({ "server": name, "status": "failed", "error": error }),
+                    ),
+                };
+                logger.log(level, "lsmcp.installer", data);
+            };
+
+            let summary = lsp_manager.install_detected(&on_event).await;
             return CallToolResult {
                 content: vec![ToolContent::Text {
-                    text: format!("Invalid arguments: {}", e),
+                    text: format_install_summary(&summary),
                 }],
-                is_error: Some(true),
+                is_error: (summary.failed() > 0).then_some(true),
+                structured_content: None,
             };
         }
-    };
-
-    match lsp_manager.workspace_symbols(args.query.clone(), &args.language).await {
-        Ok(Some(symbols)) => {
-            let text = format_workspace_symbols(symbols, &args.query);
-            CallToolResult {
-                content: vec![ToolContent::Text { text }],
-                is_error: None,
+        ManageAction::Update => lsp_manager
+            .update_server(server)
+            .await
+            .map(|(name, path)| format!("Updated {} ({})", name, path.display())),
+        ManageAction::Uninstall => {
+            lsp_manager
+                .uninstall_server(server)
+                .await
+                .and_then(|(name, removed)| {
+                    if removed {
+                        Ok(format!("Uninstalled {}", name))
+                    } else {
+                        Err(LspError::ConfigError(format!(
+                            "{} is not installed by lsmcp",
+                            name
+                        )))
+                    }
+                })
+        }
+        ManageAction::CheckUpdates => {
+            let checks = lsp_manager.check_updates().await;
+            if checks.is_empty() {
+                Ok("No servers installed by lsmcp".to_string())
+            } else {
+                let mut text = String::from("Update check:\n\n");
+                for check in checks {
+                    let status = match &check.error {
+                        Some(e) => format!("check failed: {}", e),
+                        None if check.has_update() => format!(
+                            "update available: {} -> {}",
+                            check.current.as_deref().unwrap_or("unknown"),
+                            check.latest.as_deref().unwrap_or("unknown")
+                        ),
+                        None => "up to date".to_string(),
+                    };
+                    let pinned = if check.pinned { " (pinned)" } else { "" };
+                    text.push_str(&format!("- {}: {}{}\n", check.name, status, pinned));
+                }
+                Ok(text)
             }
         }
-        Ok(None) => CallToolResult {
-            content: vec![ToolContent::Text {
-                text: format!("No symbols found for query: {}", args.query),
-            }],
+        ManageAction::Prune => lsp_manager.prune_servers(args.dry_run).await.map(|report| {
+            if report.is_empty() {
+                return "Nothing to prune".to_string();
+            }
+            let verb = if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            };
+            let mut text = String::new();
+            for name in &report.stale_entries {
+                text.push_str(&format!(
+                    "{} manifest entry {} (binary missing)\n",
+                    verb, name
+                ));
+            }
+            for path in &report.orphaned_paths {
+                text.push_str(&format!("{} orphaned {}\n", verb, path.display()));
+            }
+            text
+        }),
+    };
+
+    match result {
+        Ok(text) => CallToolResult {
+            content: vec![ToolContent::Text { text }],
             is_error: None,
+            structured_content: None,
         },
         Err(e) => {
-            error!("workspace_symbols error: {}", e);
-            CallToolResult {
-                content: vec![ToolContent::Text {
-                    text: format!("Error: {}", e),
-                }],
-                is_error: Some(true),
-            }
+            error!("lsp_manage_servers error: {}", e);
+            lsp_error(&e)
         }
     }
 }
 
 // Formatting helpers
 
-fn format_definition_response(response: GotoDefinitionResponse) -> String {
+fn format_install_summary(summary: &InstallSummary) -> String {
+    if summary.present.is_empty() && summary.manual.is_empty() && summary.results.is_empty() {
+        return "No languages with a known language server found in the workspace".to_string();
+    }
+
+    let mut text = String::new();
+    for (name, result) in &summary.results {
+        match result {
+            Ok(path) => text.push_str(&format!("- {}: installed ({})\n", name, path.display())),
+            Err(e) => text.push_str(&format!("- {}: failed: {}\n", name, e)),
+        }
+    }
+    for name in &summary.present {
+        text.push_str(&format!("- {}: already installed\n", name));
+    }
+    for name in &summary.manual {
+        text.push_str(&format!("- {}: must be installed manually\n", name));
+    }
+    text
+}
+
+fn format_definitions(targets: &[Location], include_source: bool, blame: &LocationBlame) -> String {
+    if targets.is_empty() {
+        return "No definitions found".to_string();
+    }
+    targets
+        .iter()
+        .map(|target| format_definition_target(target, include_source, blame))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The locations a definition response points to; links point to their
+/// whole target range
+fn definition_targets(response: GotoDefinitionResponse) -> Vec<Location> {
     match response {
-        GotoDefinitionResponse::Scalar(location) => format_location(&location),
-        GotoDefinitionResponse::Array(locations) => {
-            if locations.is_empty() {
-                "No definitions found".to_string()
-            } else {
-                locations
-                    .iter()
-                    .map(format_location)
-                    .collect::<Vec<_>>()
-                    .join("\n")
+        GotoDefinitionResponse::Scalar(location) => vec![location],
+        GotoDefinitionResponse::Array(locations) => locations,
+        GotoDefinitionResponse::Link(links) => links
+            .into_iter()
+            .map(|link| Location::new(link.target_uri, link.target_range))
+            .collect(),
+    }
+}
+
+/// A definition's location, followed by its source if asked for
+fn format_definition_target(
+    location: &Location,
+    include_source: bool,
+    blame: &LocationBlame,
+) -> String {
+    let mut text = format_location(location);
+    text.push_str(&blame.label(location));
+    if include_source {
+        if let Ok(path) = location.uri.to_file_path() {
+            text.push_str(&definition_source(&path, location.range));
+        }
+    }
+    text
+}
+
+/// Numbered source lines of a definition, starting at `range` and running
+/// `DEFINITION_SOURCE_LINES` lines or to the end of `range`, whichever is
+/// longer (up to `MAX_DEFINITION_SOURCE_LINES`)
+///
+/// Servers often return just the name's range, so a few lines past it are
+/// shown to include the signature and the start of the body.
+fn definition_source(path: &Path, range: Range) -> String {
+    let Ok(text) = overlay::read_file_sync(path) else {
+        return String::new();
+    };
+    let first = range.start.line;
+    let last = range
+        .end
+        .line
+        .max(first + DEFINITION_SOURCE_LINES - 1)
+        .min(first + MAX_DEFINITION_SOURCE_LINES - 1)
+        .min((line_count(&text) as u32).saturating_sub(1));
+    let width = (last + 1).to_string().len();
+    let mut source = String::new();
+    for line in first..=last {
+        let Some(content) = line_text(&text, line) else {
+            break;
+        };
+        source.push_str(&format!("\n  {:>width$} | {}", line + 1, content));
+    }
+    if range.end.line > last {
+        source.push_str(&format!("\n  [{} more line(s)]", range.end.line - last));
+    }
+    source
+}
+
+/// `name (Kind) in container at path:line:column`, with `position` in the
+/// caller's encoding
+fn format_symbol_match(symbol: &SymbolMatch, position: Position) -> String {
+    let container = symbol
+        .container
+        .as_ref()
+        .map(|c| format!(" in {}", c))
+        .unwrap_or_default();
+    let dependency = dependency_source(&symbol.path)
+        .map(|source| format!(" ({}, read-only)", source))
+        .unwrap_or_default();
+    format!(
+        "{} ({:?}){} at {}{}",
+        symbol.name,
+        symbol.kind,
+        container,
+        locations::file_position(&symbol.path, position),
+        dependency
+    )
+}
+
+/// Most files `git blame` runs on for one answer
+const MAX_BLAME_FILES: usize = 20;
+
+/// Most `git blame` runs at once
+const MAX_BLAME_CONCURRENCY: usize = 8;
+
+/// The last commit to change each location's line, shown after the
+/// location when `settings.git_blame` is on
+#[derive(Debug, Default)]
+struct LocationBlame(HashMap<(PathBuf, u32), git::LineBlame>);
+
+impl LocationBlame {
+    /// Blame for the lines of `locations` in workspace files; none when
+    /// `git_blame` is off, and none for files git can't blame
+    async fn of(lsp_manager: &LspManager, locations: &[Location]) -> Self {
+        if lsp_manager.settings().git_blame != Some(true) {
+            return Self::default();
+        }
+        let root = lsp_manager.workspace_root();
+        let mut lines: BTreeMap<PathBuf, Vec<u32>> = BTreeMap::new();
+        for location in locations {
+            if let Ok(path) = location.uri.to_file_path() {
+                if path.starts_with(root) {
+                    lines
+                        .entry(path)
+                        .or_default()
+                        .push(location.range.start.line);
+                }
             }
         }
-        GotoDefinitionResponse::Link(links) => {
-            if links.is_empty() {
-                "No definitions found".to_string()
-            } else {
-                links
-                    .iter()
-                    .map(|link| {
-                        format!(
-                            "{}:{}:{}",
-                            link.target_uri,
-                            link.target_range.start.line + 1,
-                            link.target_range.start.character + 1
-                        )
-                    })
-                    .collect::<Vec<_>>()
-                    .join("\n")
+
+        let blamed: Vec<_> = futures::stream::iter(lines.into_iter().take(MAX_BLAME_FILES))
+            .map(|(path, lines)| async move {
+                let blame = git::blame_lines(&path, &lines).await;
+                (path, blame)
+            })
+            .buffer_unordered(MAX_BLAME_CONCURRENCY)
+            .collect()
+            .await;
+        let mut blame = HashMap::new();
+        for (path, lines) in blamed {
+            match lines {
+                Ok(lines) => {
+                    blame.extend(lines.into_iter().map(|(line, b)| ((path.clone(), line), b)))
+                }
+                Err(e) => debug!("No blame for {}: {}", path.display(), e),
             }
         }
+        Self(blame)
+    }
+
+    /// ` — Jane Doe, 2026-09-30 (a1b2c3d4)` for a blamed location, else
+    /// nothing
+    fn label(&self, location: &Location) -> String {
+        location
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| self.0.get(&(path, location.range.start.line)))
+            .map(|blame| format!(" — {}", blame))
+            .unwrap_or_default()
     }
 }
 
 fn format_location(location: &Location) -> String {
     format!(
-        "{}:{}:{}",
-        location.uri.path(),
-        location.range.start.line + 1,
-        location.range.start.character + 1
+        "{}{}",
+        locations::position(&location.uri, location.range.start),
+        dependency_label(&location.uri)
     )
 }
 
-fn format_locations(locations: Vec<Location>) -> String {
+/// ` (cargo registry, read-only)` and the like for dependency sources, else
+/// nothing
+fn dependency_label(uri: &Url) -> String {
+    uri.to_file_path()
+        .ok()
+        .and_then(|path| dependency_source(&path))
+        .map(|source| format!(" ({}, read-only)", source))
+        .unwrap_or_default()
+}
+
+fn format_locations(locations: Vec<Location>, blame: &LocationBlame) -> String {
     if locations.is_empty() {
         return "No references found".to_string();
     }
@@ -510,33 +5248,18 @@ fn format_locations(locations: Vec<Location>) -> String {
     let count = locations.len();
     let formatted = locations
         .iter()
-        .map(format_location)
+        .map(|location| format!("{}{}", format_location(location), blame.label(location)))
         .collect::<Vec<_>>()
         .join("\n");
 
     format!("Found {} reference(s):\n{}", count, formatted)
 }
 
-fn format_hover(hover: Hover) -> String {
-    match hover.contents {
-        HoverContents::Scalar(content) => format_markup_content(content),
-        HoverContents::Array(contents) => contents
-            .into_iter()
-            .map(format_markup_content)
-            .collect::<Vec<_>>()
-            .join("\n\n"),
-        HoverContents::Markup(content) => content.value,
-    }
-}
+/// Definition source lines shown at least, when `include_source` is set
+const DEFINITION_SOURCE_LINES: u32 = 10;
 
-fn format_markup_content(content: MarkedString) -> String {
-    match content {
-        MarkedString::String(s) => s,
-        MarkedString::LanguageString(ls) => {
-            format!("```{}\n{}\n```", ls.language, ls.value)
-        }
-    }
-}
+/// Definition source lines shown at most
+const MAX_DEFINITION_SOURCE_LINES: u32 = 60;
 
 fn format_document_symbols(response: DocumentSymbolResponse) -> String {
     match response {
@@ -589,36 +5312,34 @@ fn format_document_symbol(symbol: &DocumentSymbol, indent: usize, output: &mut S
     }
 }
 
-fn format_diagnostics(diagnostics: Vec<Diagnostic>) -> String {
-    if diagnostics.is_empty() {
+/// List `diagnostics`, the first of `matched` diagnostics that passed the
+/// filters out of `total`, with `extras` (if any) under each
+fn format_diagnostics(
+    diagnostics: &[Diagnostic],
+    counts: &DiagnosticCounts,
+    matched: usize,
+    total: usize,
+    extras: &[DiagnosticExtras],
+) -> String {
+    if total == 0 {
         return "No diagnostics found (no errors or warnings)".to_string();
     }
-
-    let mut errors = 0;
-    let mut warnings = 0;
-    let mut infos = 0;
-    let mut hints = 0;
-
-    for diagnostic in &diagnostics {
-        match diagnostic.severity {
-            Some(DiagnosticSeverity::ERROR) => errors += 1,
-            Some(DiagnosticSeverity::WARNING) => warnings += 1,
-            Some(DiagnosticSeverity::INFORMATION) => infos += 1,
-            Some(DiagnosticSeverity::HINT) => hints += 1,
-            None | Some(_) => {}
-        }
+    if matched == 0 {
+        return format!("No diagnostics match the filters ({} filtered out)", total);
     }
 
+    let filtered = if matched < total {
+        format!(" ({} more filtered out)", total - matched)
+    } else {
+        String::new()
+    };
     let mut output = format!(
-        "Found {} diagnostic(s): {} error(s), {} warning(s), {} info(s), {} hint(s)\n\n",
-        diagnostics.len(),
-        errors,
-        warnings,
-        infos,
-        hints
+        "Found {} diagnostic(s): {} error(s), {} warning(s), {} info(s), {} hint(s){}\n\n",
+        matched, counts.error, counts.warning, counts.information, counts.hint, filtered
     );
 
-    for diagnostic in &diagnostics {
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        let extra = extras.get(i);
         let severity = match diagnostic.severity {
             Some(DiagnosticSeverity::ERROR) => "ERROR",
             Some(DiagnosticSeverity::WARNING) => "WARNING",
@@ -632,11 +5353,17 @@ fn format_diagnostics(diagnostics: Vec<Diagnostic>) -> String {
             .as_ref()
             .map(|s| format!("[{}] ", s))
             .unwrap_or_default();
+        let code = diagnostic
+            .code
+            .as_ref()
+            .map(|c| format!(" {}", code_string(c)))
+            .unwrap_or_default();
 
         output.push_str(&format!(
-            "{}{} at line {}:{}-{}:{}: {}\n",
+            "{}{}{} at line {}:{}-{}:{}: {}\n",
             source,
             severity,
+            code,
             diagnostic.range.start.line + 1,
             diagnostic.range.start.character + 1,
             diagnostic.range.end.line + 1,
@@ -646,60 +5373,490 @@ fn format_diagnostics(diagnostics: Vec<Diagnostic>) -> String {
 
         // Add related information if available
         if let Some(related) = &diagnostic.related_information {
-            for info in related {
+            for (j, info) in related.iter().enumerate() {
                 output.push_str(&format!(
-                    "  Related: {} at {}:{}:{}\n",
+                    "  Related: {} at {}\n",
                     info.message,
-                    info.location.uri.path(),
-                    info.location.range.start.line + 1,
-                    info.location.range.start.character + 1
+                    locations::position(&info.location.uri, info.location.range.start)
                 ));
+                if let Some(Some(snippet)) = extra.and_then(|e| e.snippets.get(j)) {
+                    for line in snippet.lines() {
+                        output.push_str(&format!("    | {}\n", line));
+                    }
+                }
             }
         }
 
+        for fix in extra.map(|e| e.fixes.as_slice()).unwrap_or_default() {
+            output.push_str(&format_code_action(fix, "  "));
+        }
+
         output.push('\n');
     }
 
+    if diagnostics.len() < matched {
+        output.push_str(&format!(
+            "... {} more not shown (raise max_results to see them)\n",
+            matched - diagnostics.len()
+        ));
+    }
+
     output
 }
 
-fn format_workspace_symbols(symbols: Vec<SymbolInformation>, query: &str) -> String {
-    if symbols.is_empty() {
-        return format!("No symbols found for query: {}", query);
+/// `error(s), warning(s)...` for the severities with any diagnostics
+fn format_severity_counts(counts: &SeverityCounts) -> String {
+    let parts: Vec<String> = [
+        (counts.error, "error"),
+        (counts.warning, "warning"),
+        (counts.information, "info"),
+        (counts.hint, "hint"),
+    ]
+    .iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, label)| format!("{} {}(s)", count, label))
+    .collect();
+    parts.join(", ")
+}
+
+/// The workspace's problems, listing the first `max_files` files
+fn format_problems_summary(summary: &ProblemsSummary, max_files: usize) -> String {
+    if summary.files.is_empty() {
+        return "No problems reported by the language servers".to_string();
     }
 
-    let mut output = format!("Found {} symbol(s) matching '{}':\n\n", symbols.len(), query);
+    let total = &summary.total;
+    let mut output = format!(
+        "Workspace problems: {} error(s), {} warning(s), {} info(s), {} hint(s) in {} file(s)\n",
+        total.error,
+        total.warning,
+        total.information,
+        total.hint,
+        summary.files.len()
+    );
 
-    for symbol in symbols {
-        let kind_str = format!("{:?}", symbol.kind);
-        let location_str = if let Ok(path) = symbol.location.uri.to_file_path() {
-            format!(
-                "{}:{}:{}",
-                path.display(),
-                symbol.location.range.start.line + 1,
-                symbol.location.range.start.character + 1
-            )
-        } else {
-            format!(
-                "{}:{}:{}",
-                symbol.location.uri.path(),
-                symbol.location.range.start.line + 1,
-                symbol.location.range.start.character + 1
-            )
-        };
+    output.push_str("\nBy source:\n");
+    for (source, counts) in &summary.by_source {
+        output.push_str(&format!(
+            "  {}: {}\n",
+            source,
+            format_severity_counts(counts)
+        ));
+    }
 
+    output.push_str("\nBy file (most errors first):\n");
+    for file in summary.files.iter().take(max_files) {
         output.push_str(&format!(
-            "- {} ({}) at {}\n",
-            symbol.name,
-            kind_str,
-            location_str
+            "  {}: {}\n",
+            locations::path(&file.path),
+            format_severity_counts(&file.counts)
+        ));
+    }
+    if summary.files.len() > max_files {
+        output.push_str(&format!(
+            "  ... {} more file(s) (raise max_files to see them)\n",
+            summary.files.len() - max_files
         ));
+    }
+    output
+}
+
+/// A code action's title and edits, each line prefixed with `indent`
+fn format_code_action(action: &CodeActionOrCommand, indent: &str) -> String {
+    match action {
+        CodeActionOrCommand::Command(command) => format!(
+            "{}Fix: {} (runs server command {})\n",
+            indent, command.title, command.command
+        ),
+        CodeActionOrCommand::CodeAction(action) => {
+            let preferred = if action.is_preferred == Some(true) {
+                " (preferred)"
+            } else {
+                ""
+            };
+            let mut output = format!("{}Fix: {}{}\n", indent, action.title, preferred);
+            match &action.edit {
+                Some(edit) => {
+                    for line in format_workspace_edit(edit) {
+                        output.push_str(&format!("{}  {}\n", indent, line));
+                    }
+                }
+                None => {
+                    if let Some(command) = &action.command {
+                        output.push_str(&format!(
+                            "{}  runs server command {}\n",
+                            indent, command.command
+                        ));
+                    }
+                }
+            }
+            output
+        }
+    }
+}
+
+/// One line per text edit or file operation in `edit`
+fn format_workspace_edit(edit: &WorkspaceEdit) -> Vec<String> {
+    let text_edit = |uri: &Url, edit: &TextEdit| {
+        format!(
+            "{}:{}:{}-{}:{}: replace with {:?}",
+            display_path(uri),
+            edit.range.start.line + 1,
+            edit.range.start.character + 1,
+            edit.range.end.line + 1,
+            edit.range.end.character + 1,
+            edit.new_text
+        )
+    };
+    let document_edit = |edit: &TextDocumentEdit| {
+        edit.edits
+            .iter()
+            .map(|e| match e {
+                OneOf::Left(e) => text_edit(&edit.text_document.uri, e),
+                OneOf::Right(e) => text_edit(&edit.text_document.uri, &e.text_edit),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut lines = Vec::new();
+    if let Some(changes) = &edit.changes {
+        let mut uris: Vec<&Url> = changes.keys().collect();
+        uris.sort();
+        for uri in uris {
+            lines.extend(changes[uri].iter().map(|e| text_edit(uri, e)));
+        }
+    }
+    match &edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => {
+            lines.extend(edits.iter().flat_map(document_edit));
+        }
+        Some(DocumentChanges::Operations(operations)) => {
+            for operation in operations {
+                match operation {
+                    DocumentChangeOperation::Edit(edit) => lines.extend(document_edit(edit)),
+                    DocumentChangeOperation::Op(ResourceOp::Create(op)) => {
+                        lines.push(format!("create {}", display_path(&op.uri)))
+                    }
+                    DocumentChangeOperation::Op(ResourceOp::Rename(op)) => lines.push(format!(
+                        "rename {} to {}",
+                        display_path(&op.old_uri),
+                        display_path(&op.new_uri)
+                    )),
+                    DocumentChangeOperation::Op(ResourceOp::Delete(op)) => {
+                        lines.push(format!("delete {}", display_path(&op.uri)))
+                    }
+                }
+            }
+        }
+        None => {}
+    }
+    lines
+}
+
+fn format_workspace_symbols(
+    response: WorkspaceSymbolResponse,
+    query: &str,
+    filtered: usize,
+) -> String {
+    let entries: Vec<(String, SymbolKind, String, Option<String>)> = match response {
+        WorkspaceSymbolResponse::Flat(symbols) => symbols
+            .into_iter()
+            .map(|symbol| {
+                let location =
+                    format_symbol_location(&symbol.location.uri, Some(&symbol.location.range));
+                (symbol.name, symbol.kind, location, symbol.container_name)
+            })
+            .collect(),
+        WorkspaceSymbolResponse::Nested(symbols) => symbols
+            .into_iter()
+            .map(|symbol| {
+                let location = match &symbol.location {
+                    OneOf::Left(location) => {
+                        format_symbol_location(&location.uri, Some(&location.range))
+                    }
+                    OneOf::Right(location) => format_symbol_location(&location.uri, None),
+                };
+                (symbol.name, symbol.kind, location, symbol.container_name)
+            })
+            .collect(),
+    };
+
+    if entries.is_empty() && filtered > 0 {
+        return format!(
+            "No symbols for query {} match the filters ({} filtered out)",
+            query, filtered
+        );
+    }
+    if entries.is_empty() {
+        return format!("No symbols found for query: {}", query);
+    }
+
+    let filtered = if filtered > 0 {
+        format!(" ({} more filtered out)", filtered)
+    } else {
+        String::new()
+    };
+    let mut output = format!(
+        "Found {} symbol(s) matching '{}'{}:\n\n",
+        entries.len(),
+        query,
+        filtered
+    );
+
+    for (name, kind, location, container) in entries {
+        output.push_str(&format!("- {} ({:?}) at {}\n", name, kind, location));
 
         // Add container name if available (e.g., class or module name)
-        if let Some(container) = symbol.container_name {
+        if let Some(container) = container {
             output.push_str(&format!("  in: {}\n", container));
         }
     }
 
     output
 }
+
+/// Format a symbol location; the range is absent for unresolved `WorkspaceSymbol`s
+fn format_symbol_location(uri: &Url, range: Option<&Range>) -> String {
+    let location = match range {
+        Some(range) => locations::position(uri, range.start),
+        None => locations::uri(uri),
+    };
+    format!("{}{}", location, dependency_label(uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_extension_result() {
+        let expansion = serde_json::json!({
+            "name": "vec",
+            "expansion": "<[_]>::into_vec(\n    Box::new([1, 2]),\n)",
+        });
+        assert_eq!(
+            render_extension_result(&expansion),
+            "expansion:\n<[_]>::into_vec(\n    Box::new([1, 2]),\n)\nname: vec"
+        );
+        assert_eq!(
+            render_extension_result(&Value::from("SOURCE_FILE@0..12")),
+            "SOURCE_FILE@0..12"
+        );
+        assert_eq!(render_extension_result(&Value::Null), "No result");
+        assert_eq!(
+            render_extension_result(&Value::from("file:///src/app/parser.h")),
+            "/src/app/parser.h"
+        );
+        assert_eq!(
+            render_extension_result(&serde_json::json!({ "count": 2 })),
+            "{\n  \"count\": 2\n}"
+        );
+    }
+
+    #[test]
+    fn test_format_locations_with_blame() {
+        let location = |line| {
+            Location::new(
+                Url::from_file_path("/tmp/project/src/lib.rs").unwrap(),
+                Range::new(Position::new(line, 4), Position::new(line, 9)),
+            )
+        };
+        let blame = LocationBlame(HashMap::from([(
+            (PathBuf::from("/tmp/project/src/lib.rs"), 2),
+            git::LineBlame {
+                commit: Some("3f4e1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b".to_string()),
+                author: "Jane Doe".to_string(),
+                time: 1_790_000_000,
+            },
+        )]));
+
+        let text = format_locations(vec![location(2), location(7)], &blame);
+        assert_eq!(
+            text,
+            "Found 2 reference(s):\n/tmp/project/src/lib.rs:3:5 — Jane Doe, 2026-09-21 (3f4e1a2b)\n/tmp/project/src/lib.rs:8:5"
+        );
+    }
+
+    #[test]
+    fn test_format_definition_in_dependency() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("node_modules/left-pad/index.js");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, "// pad\nfunction leftPad(s, n) {\n  return s;\n}\n").unwrap();
+        let location = Location::new(
+            Url::from_file_path(&file).unwrap(),
+            Range::new(Position::new(1, 9), Position::new(1, 16)),
+        );
+
+        let text = format_definitions(&[location], true, &LocationBlame::default());
+        assert_eq!(
+            text,
+            format!(
+                "{}:2:10 (node_modules, read-only)\n  2 | function leftPad(s, n) {{\n  3 |   return s;\n  4 | }}",
+                file.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_format_workspace_symbols_unresolved_location() {
+        let uri = Url::parse("file:///tmp/project/src/lib.rs").unwrap();
+        let response = WorkspaceSymbolResponse::Nested(vec![WorkspaceSymbol {
+            name: "Config".to_string(),
+            kind: SymbolKind::STRUCT,
+            tags: None,
+            container_name: Some("config".to_string()),
+            location: OneOf::Right(WorkspaceLocation { uri }),
+            data: None,
+        }]);
+
+        let text = format_workspace_symbols(response, "Config", 0);
+        assert!(text.contains("Found 1 symbol(s)"));
+        assert!(text.contains("- Config (Struct) at /tmp/project/src/lib.rs\n"));
+        assert!(text.contains("in: config"));
+    }
+
+    #[test]
+    fn test_format_workspace_symbols_flat() {
+        let uri = Url::parse("file:///tmp/project/src/main.rs").unwrap();
+        #[allow(deprecated)]
+        let response = WorkspaceSymbolResponse::Flat(vec![SymbolInformation {
+            name: "main".to_string(),
+            kind: SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            location: Location {
+                uri,
+                range: Range::new(Position::new(4, 3), Position::new(4, 7)),
+            },
+            container_name: None,
+        }]);
+
+        let text = format_workspace_symbols(response, "main", 0);
+        assert!(text.contains("- main (Function) at /tmp/project/src/main.rs:5:4"));
+    }
+
+    #[test]
+    fn test_diagnostic_filter() {
+        let diagnostic = |severity, source: &str, code: NumberOrString| Diagnostic {
+            severity: Some(severity),
+            source: Some(source.to_string()),
+            code: Some(code),
+            ..Default::default()
+        };
+        let diagnostics = vec![
+            diagnostic(
+                DiagnosticSeverity::ERROR,
+                "rustc",
+                NumberOrString::String("E0308".to_string()),
+            ),
+            diagnostic(
+                DiagnosticSeverity::WARNING,
+                "clippy",
+                NumberOrString::String("needless_return".to_string()),
+            ),
+            diagnostic(DiagnosticSeverity::HINT, "ts", NumberOrString::Number(6133)),
+        ];
+        let matching = |filter: Value| {
+            let filter: DiagnosticFilter = serde_json::from_value(filter).unwrap();
+            diagnostics.iter().filter(|d| filter.matches(d)).count()
+        };
+
+        assert_eq!(matching(serde_json::json!({})), 3);
+        assert_eq!(matching(serde_json::json!({ "severity": "error" })), 1);
+        assert_eq!(matching(serde_json::json!({ "severity": "warning" })), 2);
+        assert_eq!(matching(serde_json::json!({ "source": "Clippy" })), 1);
+        assert_eq!(matching(serde_json::json!({ "code": 6133 })), 1);
+        assert_eq!(matching(serde_json::json!({ "code": "6133" })), 1);
+
+        let counts = DiagnosticCounts::of(&diagnostics);
+        let text = format_diagnostics(&diagnostics[..1], &counts, 3, 4, &[]);
+        assert!(text.starts_with("Found 3 diagnostic(s): 1 error(s), 1 warning(s), 0 info(s), 1 hint(s) (1 more filtered out)"));
+        assert!(text.contains("[rustc] ERROR E0308 at line 1:1-1:1"));
+        assert!(text.contains("... 2 more not shown"));
+    }
+
+    #[test]
+    fn test_format_quick_fix() {
+        let uri = Url::parse("file:///tmp/project/src/main.rs").unwrap();
+        let action = CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Import HashMap".to_string(),
+            kind: Some(CodeActionKind::QUICKFIX),
+            is_preferred: Some(true),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(
+                    uri,
+                    vec![TextEdit::new(
+                        Range::new(Position::new(0, 0), Position::new(0, 0)),
+                        "use std::collections::HashMap;\n".to_string(),
+                    )],
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            format_code_action(&action, "  "),
+            "  Fix: Import HashMap (preferred)\n    /tmp/project/src/main.rs:1:1-1:1: replace with \"use std::collections::HashMap;\\n\"\n"
+        );
+
+        let text = "a\nb\nc\nd\ne\n";
+        let range = Range::new(Position::new(1, 0), Position::new(4, 1));
+        assert_eq!(source_snippet(text, range).as_deref(), Some("b\nc\nd"));
+        assert_eq!(
+            source_snippet(text, Range::new(Position::new(9, 0), Position::new(9, 1))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_structured_errors() {
+        let result = invalid_arguments("missing field `line`");
+        assert_eq!(result.is_error, Some(true));
+        let error = &result.structured_content.unwrap()["error"];
+        assert_eq!(error["kind"], "invalid_arguments");
+        assert_eq!(error["parameter"], "line");
+        assert_eq!(error["retryable"], false);
+
+        let result = lsp_error(&LspError::Timeout(30));
+        let error = &result.structured_content.unwrap()["error"];
+        assert_eq!(error["kind"], "timeout");
+        assert_eq!(error["retryable"], true);
+        assert!(error.get("parameter").is_none());
+
+        assert!(get_tool_definitions()
+            .iter()
+            .all(|tool| tool.annotations.is_some()));
+    }
+
+    #[test]
+    fn test_expose_batch_tools() {
+        let config = ToolsConfig {
+            prefix: Some("code_".to_string()),
+            rename: [("lsp_hover".to_string(), "hover".to_string())].into(),
+            ..Default::default()
+        };
+        let definitions = get_tool_definitions();
+        let mut schema = definitions
+            .iter()
+            .find(|tool| tool.name == "lsp_batch")
+            .unwrap()
+            .input_schema
+            .clone();
+        expose_batch_tools(&mut schema, &config);
+        let names = &schema["properties"]["calls"]["items"]["properties"]["tool"]["enum"];
+        assert_eq!(names[0], "code_lsp_goto_definition");
+        assert_eq!(names[2], "hover");
+        let args = serde_json::json!({ "calls": [{ "tool": "hover" }] });
+        assert!(validation::check_schema(&args, &schema).is_ok());
+        let args = serde_json::json!({ "calls": [{ "tool": "lsp_hover" }] });
+        assert!(validation::check_schema(&args, &schema).is_err());
+
+        // Schemas without batch calls are left alone
+        let mut schema = serde_json::json!({ "properties": { "file": { "type": "string" } } });
+        let before = schema.clone();
+        expose_batch_tools(&mut schema, &config);
+        assert_eq!(schema, before);
+    }
+}
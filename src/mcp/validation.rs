@@ -0,0 +1,338 @@
+//! Tool argument validation
+//!
+//! A tool call's arguments are checked against the tool's input schema
+//! (required properties, types, enums, bounds) and then for what a schema
+//! can't say: that `file` exists, that a server handles its language (or
+//! the `language` given) and that `line` is inside the file. Every failure
+//! names the parameter at fault and comes back before any server is
+//! started or queried, so agents get the same kind of error from every tool.
+
+use crate::lsp::{overlay, virtual_docs, LspManager};
+use crate::utils::position::line_count;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Why a tool call's arguments were rejected
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    /// `invalid_arguments`, or `unsupported_language` when no server
+    /// handles the file or language
+    pub kind: &'static str,
+    /// The argument at fault, as a path for nested ones (`edits[0].old_text`)
+    pub parameter: String,
+    pub message: String,
+}
+
+impl Invalid {
+    fn new(parameter: &str, message: String) -> Self {
+        Self {
+            kind: "invalid_arguments",
+            parameter: parameter.to_string(),
+            message,
+        }
+    }
+}
+
+/// Check `args` against `schema`, a tool's input schema
+pub fn check_schema(args: &Value, schema: &Value) -> Result<(), Invalid> {
+    // Tools whose arguments are all optional may be called without any
+    if args.is_null() && schema.get("required").is_none() {
+        return Ok(());
+    }
+    check_value(args, schema, "")
+}
+
+fn check_value(value: &Value, schema: &Value, parameter: &str) -> Result<(), Invalid> {
+    let name = if parameter.is_empty() {
+        "arguments"
+    } else {
+        parameter
+    };
+
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            return Err(Invalid::new(
+                parameter,
+                format!(
+                    "`{}` must be {}, not {}",
+                    name,
+                    types
+                        .iter()
+                        .map(|t| article(t))
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                    describe(value)
+                ),
+            ));
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            return Err(Invalid::new(
+                parameter,
+                format!(
+                    "`{}` must be one of {}, not {}",
+                    name,
+                    allowed.join(", "),
+                    value
+                ),
+            ));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        let minimum = schema.get("minimum").and_then(Value::as_f64);
+        let maximum = schema.get("maximum").and_then(Value::as_f64);
+        if minimum.is_some_and(|min| number < min) || maximum.is_some_and(|max| number > max) {
+            let range = match (minimum, maximum) {
+                (Some(min), Some(max)) => format!("between {} and {}", min, max),
+                (Some(min), None) => format!("at least {}", min),
+                (None, _) => format!("at most {}", maximum.unwrap_or_default()),
+            };
+            return Err(Invalid::new(
+                parameter,
+                format!("`{}` must be {}, not {}", name, range, value),
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(object) => check_object(object, schema, parameter),
+        Value::Array(items) => {
+            let min_items = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+            if (items.len() as u64) < min_items {
+                return Err(Invalid::new(
+                    parameter,
+                    format!("`{}` needs at least {} item(s)", name, min_items),
+                ));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_value(item, item_schema, &format!("{}[{}]", parameter, i))?;
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_object(
+    object: &Map<String, Value>,
+    schema: &Value,
+    parameter: &str,
+) -> Result<(), Invalid> {
+    let nested = |key: &str| match parameter {
+        "" => key.to_string(),
+        _ => format!("{}.{}", parameter, key),
+    };
+
+    for required in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if object.get(required).is_none_or(Value::is_null) {
+            let key = nested(required);
+            return Err(Invalid::new(&key, format!("`{}` is required", key)));
+        }
+    }
+
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return Ok(());
+    };
+    for (key, value) in object {
+        // Unknown and null arguments are left to the tool, which ignores them
+        if let (Some(property), false) = (properties.get(key), value.is_null()) {
+            check_value(value, property, &nested(key))?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, schema_type: &str) -> bool {
+    match schema_type {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn article(schema_type: &str) -> String {
+    match schema_type {
+        "integer" | "array" | "object" => format!("an {}", schema_type),
+        "null" => "null".to_string(),
+        _ => format!("a {}", schema_type),
+    }
+}
+
+/// A value's type for error messages, with the value when it is short
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Array(_) => "an array".to_string(),
+        Value::Object(_) => "an object".to_string(),
+        Value::String(s) if s.chars().count() > 40 => "a string".to_string(),
+        Value::Number(n) if n.is_f64() => format!("{} (a fraction)", n),
+        value => value.to_string(),
+    }
+}
+
+/// Check what a call's `file`, `language` and `line` refer to: the file
+/// exists (unless the call queries unsaved `content` instead), a server
+/// handles it (for tools that ask one) and the line is inside it
+pub async fn check_targets(
+    args: &Value,
+    content: Option<&str>,
+    uses_servers: bool,
+    lsp_manager: &LspManager,
+) -> Result<(), Invalid> {
+    if let Some(language) = args.get("language").and_then(Value::as_str) {
+        if !lsp_manager.language_supported(language) {
+            return Err(Invalid {
+                kind: "unsupported_language",
+                parameter: "language".to_string(),
+                message: format!(
+                    "No language server is configured for `language` {:?}",
+                    language
+                ),
+            });
+        }
+    }
+
+    let Some(file) = args.get("file").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    let path = lsp_manager.workspace_root().join(file);
+    if content.is_none() && !path.is_file() {
+        let problem = if path.exists() {
+            "is not a file"
+        } else {
+            "does not exist"
+        };
+        return Err(Invalid::new("file", format!("`file` {} {}", file, problem)));
+    }
+
+    // Mixed files can be served by their embedded languages' servers alone
+    if uses_servers
+        && args.get("language").is_none()
+        && lsp_manager.file_language(&path).is_none()
+        && !virtual_docs::is_mixed(&path)
+    {
+        return Err(Invalid {
+            kind: "unsupported_language",
+            parameter: "file".to_string(),
+            message: format!("No language server is configured for {}", file),
+        });
+    }
+
+    if let Some(line) = args.get("line").and_then(Value::as_u64) {
+        let base = args
+            .get("index_base")
+            .and_then(Value::as_u64)
+            .or(lsp_manager.settings().index_base.map(u64::from))
+            .unwrap_or(0);
+        let text = match content {
+            Some(content) => Some(content.to_string()),
+            None => overlay::read_file(Path::new(&path)).await.ok(),
+        };
+        if let Some(text) = text {
+            let lines = line_count(&text) as u64;
+            if line < base || line - base >= lines {
+                return Err(Invalid::new(
+                    "line",
+                    format!(
+                        "`line` {} is outside {}, which has {} lines ({}-indexed)",
+                        line, file, lines, base
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "file": { "type": "string" },
+                "line": { "type": "integer", "minimum": 0 },
+                "severity": { "type": "string", "enum": ["error", "warning"] },
+                "code": { "type": ["string", "integer"] },
+                "edits": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": { "old_text": { "type": "string" } },
+                        "required": ["old_text"]
+                    }
+                }
+            },
+            "required": ["file", "line"]
+        });
+        let check =
+            |args: Value| check_schema(&args, &schema).map_err(|e| (e.parameter, e.message));
+
+        assert!(check(json!({ "file": "/a.rs", "line": 3, "code": 7, "other": true })).is_ok());
+        assert_eq!(
+            check(json!({ "line": 3 })),
+            Err(("file".to_string(), "`file` is required".to_string()))
+        );
+        assert_eq!(
+            check(json!({ "file": "/a.rs", "line": "3" })),
+            Err((
+                "line".to_string(),
+                "`line` must be an integer, not \"3\"".to_string()
+            ))
+        );
+        assert_eq!(
+            check(json!({ "file": "/a.rs", "line": -1 })).unwrap_err().0,
+            "line"
+        );
+        assert_eq!(
+            check(json!({ "file": "/a.rs", "line": 0, "severity": "fatal" })),
+            Err((
+                "severity".to_string(),
+                "`severity` must be one of \"error\", \"warning\", not \"fatal\"".to_string()
+            ))
+        );
+        assert_eq!(
+            check(json!({ "file": "/a.rs", "line": 0, "edits": [{ "old_text": "a" }, {}] })),
+            Err((
+                "edits[1].old_text".to_string(),
+                "`edits[1].old_text` is required".to_string()
+            ))
+        );
+        assert_eq!(
+            check(json!({ "file": "/a.rs", "line": 0, "edits": [] }))
+                .unwrap_err()
+                .0,
+            "edits"
+        );
+
+        // Calls without arguments are fine when nothing is required
+        assert!(check_schema(&Value::Null, &json!({ "type": "object" })).is_ok());
+    }
+}
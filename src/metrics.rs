@@ -0,0 +1,409 @@
+//! Metrics
+//!
+//! Counts and times every tool call and every request sent to a language
+//! server, so complaints about a slow server can be traced to the server,
+//! method or tool responsible. `lsp_metrics` reports them as text or in the
+//! Prometheus exposition format, which the `metrics_address` setting also
+//! serves over HTTP for scraping.
+
+use crate::types::LspError;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+/// Latencies kept per tool or method for the percentiles
+const MAX_SAMPLES: usize = 1024;
+
+/// Percentiles reported
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Counts and recent latencies of one tool or server method
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Requests that got no answer in time (also counted as errors)
+    pub timeouts: u64,
+    pub total: Duration,
+    /// The last `MAX_SAMPLES` latencies
+    samples: VecDeque<Duration>,
+}
+
+impl Stats {
+    fn record(&mut self, elapsed: Duration, error: bool, timeout: bool) {
+        self.calls += 1;
+        self.errors += u64::from(error);
+        self.timeouts += u64::from(timeout);
+        self.total += elapsed;
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(elapsed);
+    }
+
+    /// The `quantile` (0 to 1) of the recent latencies
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        let mut samples: Vec<Duration> = self.samples.iter().copied().collect();
+        samples.sort();
+        let rank = (quantile * samples.len() as f64).ceil() as usize;
+        samples
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Tool call and server request statistics since startup
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    tools: Mutex<BTreeMap<String, Stats>>,
+    /// By (server, method)
+    requests: Mutex<BTreeMap<(String, String), Stats>>,
+    /// Waits for a request slot, by server (see `lsp::throttle`)
+    queue_waits: Mutex<BTreeMap<String, Stats>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            tools: Mutex::default(),
+            requests: Mutex::default(),
+            queue_waits: Mutex::default(),
+        }
+    }
+}
+
+/// The process-wide metrics
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    /// Record a tool call that took `elapsed`
+    pub fn record_tool(&self, tool: &str, elapsed: Duration, error: bool) {
+        if let Ok(mut tools) = self.tools.lock() {
+            tools
+                .entry(tool.to_string())
+                .or_default()
+                .record(elapsed, error, false);
+        }
+    }
+
+    /// Record a request to `server` that took `elapsed`
+    pub fn record_request(
+        &self,
+        server: &str,
+        method: &str,
+        elapsed: Duration,
+        error: Option<&LspError>,
+    ) {
+        let timeout = matches!(error, Some(LspError::Timeout(_)));
+        if let Ok(mut requests) = self.requests.lock() {
+            requests
+                .entry((server.to_string(), method.to_string()))
+                .or_default()
+                .record(elapsed, error.is_some(), timeout);
+        }
+    }
+
+    /// Record a request to `server` that waited `waited` for its turn
+    pub fn record_queue_wait(&self, server: &str, waited: Duration) {
+        if let Ok(mut queue_waits) = self.queue_waits.lock() {
+            queue_waits
+                .entry(server.to_string())
+                .or_default()
+                .record(waited, false, false);
+        }
+    }
+
+    /// Statistics per tool
+    pub fn tools(&self) -> BTreeMap<String, Stats> {
+        self.tools.lock().map(|t| t.clone()).unwrap_or_default()
+    }
+
+    /// Statistics per (server, method)
+    pub fn requests(&self) -> BTreeMap<(String, String), Stats> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    /// Queue wait statistics per server
+    pub fn queue_waits(&self) -> BTreeMap<String, Stats> {
+        self.queue_waits
+            .lock()
+            .map(|q| q.clone())
+            .unwrap_or_default()
+    }
+
+    /// A readable report, slowest first
+    pub fn render_text(&self) -> String {
+        let line = |stats: &Stats| {
+            let mut line = format!("{} call(s), {} error(s)", stats.calls, stats.errors);
+            if stats.timeouts > 0 {
+                let _ = write!(line, " ({} timed out)", stats.timeouts);
+            }
+            for quantile in QUANTILES {
+                let _ = write!(
+                    line,
+                    ", p{} {}ms",
+                    (quantile * 100.0) as u32,
+                    stats.percentile(quantile).as_millis()
+                );
+            }
+            line
+        };
+        let slowest_first = |a: &Stats, b: &Stats| b.percentile(0.9).cmp(&a.percentile(0.9));
+
+        let mut text = format!(
+            "Metrics since start ({}s ago):\n",
+            self.started.elapsed().as_secs()
+        );
+        let mut tools: Vec<(String, Stats)> = self.tools().into_iter().collect();
+        tools.sort_by(|(_, a), (_, b)| slowest_first(a, b));
+        text.push_str("\nTool calls:\n");
+        if tools.is_empty() {
+            text.push_str("  none\n");
+        }
+        for (tool, stats) in &tools {
+            let _ = writeln!(text, "  {}: {}", tool, line(stats));
+        }
+
+        let mut requests: Vec<((String, String), Stats)> = self.requests().into_iter().collect();
+        requests.sort_by(|(_, a), (_, b)| slowest_first(a, b));
+        text.push_str("\nLanguage server requests:\n");
+        if requests.is_empty() {
+            text.push_str("  none\n");
+        }
+        for ((server, method), stats) in &requests {
+            let _ = writeln!(text, "  {} {}: {}", server, method, line(stats));
+        }
+
+        let queue_waits = self.queue_waits();
+        if !queue_waits.is_empty() {
+            text.push_str("\nRequests queued behind max_concurrent_requests:\n");
+            for (server, stats) in &queue_waits {
+                let _ = writeln!(
+                    text,
+                    "  {}: {} waited, p50 {}ms, p99 {}ms",
+                    server,
+                    stats.calls,
+                    stats.percentile(0.5).as_millis(),
+                    stats.percentile(0.99).as_millis()
+                );
+            }
+        }
+        text
+    }
+
+    /// The statistics in the Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP lsmcp_uptime_seconds Seconds since lsmcp started\n\
+             # TYPE lsmcp_uptime_seconds gauge\n\
+             lsmcp_uptime_seconds {}",
+            self.started.elapsed().as_secs()
+        );
+
+        let tools: Vec<(String, Stats)> = self
+            .tools()
+            .into_iter()
+            .map(|(tool, stats)| (format!("tool=\"{}\"", escape_label(&tool)), stats))
+            .collect();
+        write_family(&mut out, "lsmcp_tool_calls", "Tool calls", &tools, false);
+
+        let requests: Vec<(String, Stats)> = self
+            .requests()
+            .into_iter()
+            .map(|((server, method), stats)| {
+                let labels = format!(
+                    "server=\"{}\",method=\"{}\"",
+                    escape_label(&server),
+                    escape_label(&method)
+                );
+                (labels, stats)
+            })
+            .collect();
+        write_family(
+            &mut out,
+            "lsmcp_lsp_requests",
+            "Requests to language servers",
+            &requests,
+            true,
+        );
+
+        let queue_waits: Vec<(String, Stats)> = self
+            .queue_waits()
+            .into_iter()
+            .map(|(server, stats)| (format!("server=\"{}\"", escape_label(&server)), stats))
+            .collect();
+        write_family(
+            &mut out,
+            "lsmcp_lsp_queue_waits",
+            "Requests that waited for a slot under max_concurrent_requests",
+            &queue_waits,
+            false,
+        );
+        out
+    }
+}
+
+/// Write the counters and latency summary of `prefix` for every label set
+fn write_family(
+    out: &mut String,
+    prefix: &str,
+    help: &str,
+    series: &[(String, Stats)],
+    timeouts: bool,
+) {
+    let mut counter = |name: &str, help: &str, value: fn(&Stats) -> u64| {
+        let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+        let _ = writeln!(out, "# TYPE {}_{} counter", prefix, name);
+        for (labels, stats) in series {
+            let _ = writeln!(out, "{}_{}{{{}}} {}", prefix, name, labels, value(stats));
+        }
+    };
+    counter("total", help, |s| s.calls);
+    counter("errors_total", &format!("{} that failed", help), |s| {
+        s.errors
+    });
+    if timeouts {
+        counter("timeouts_total", &format!("{} that timed out", help), |s| {
+            s.timeouts
+        });
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP {}_duration_seconds {} latency\n# TYPE {}_duration_seconds summary",
+        prefix, help, prefix
+    );
+    for (labels, stats) in series {
+        for quantile in QUANTILES {
+            let _ = writeln!(
+                out,
+                "{}_duration_seconds{{{},quantile=\"{}\"}} {}",
+                prefix,
+                labels,
+                quantile,
+                stats.percentile(quantile).as_secs_f64()
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_duration_seconds_sum{{{}}} {}",
+            prefix,
+            labels,
+            stats.total.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "{}_duration_seconds_count{{{}}} {}",
+            prefix, labels, stats.calls
+        );
+    }
+}
+
+/// `value` escaped for a Prometheus label
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve the global metrics at `http://<address>/metrics` until the process
+/// exits
+pub async fn serve(address: &str) -> Result<(), LspError> {
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Serving metrics at http://{}/metrics",
+        listener.local_addr()?
+    );
+    loop {
+        let (mut stream, peer) = listener.accept().await?;
+        tokio::spawn(async move {
+            // Only the request line matters; read until the headers end
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+            let (status, body) = if path == "/metrics" {
+                ("200 OK", global().render_prometheus())
+            } else {
+                ("404 Not Found", "Not found\n".to_string())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                debug!("Failed to send metrics to {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut stats = Stats::default();
+        for ms in 1..=100 {
+            stats.record(Duration::from_millis(ms), ms > 95, ms == 100);
+        }
+        assert_eq!(stats.calls, 100);
+        assert_eq!(stats.errors, 5);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.percentile(0.5), Duration::from_millis(50));
+        assert_eq!(stats.percentile(0.9), Duration::from_millis(90));
+        assert_eq!(stats.percentile(0.99), Duration::from_millis(99));
+        assert_eq!(Stats::default().percentile(0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metrics = Metrics::default();
+        metrics.record_tool("lsp_hover", Duration::from_millis(20), false);
+        metrics.record_request(
+            "rust-analyzer",
+            "textDocument/hover",
+            Duration::from_secs(30),
+            Some(&LspError::Timeout(30)),
+        );
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("lsmcp_tool_calls_total{tool=\"lsp_hover\"} 1\n"));
+        assert!(text.contains("lsmcp_tool_calls_errors_total{tool=\"lsp_hover\"} 0\n"));
+        assert!(text.contains(
+            "lsmcp_lsp_requests_timeouts_total{server=\"rust-analyzer\",method=\"textDocument/hover\"} 1\n"
+        ));
+        assert!(text.contains(
+            "lsmcp_tool_calls_duration_seconds{tool=\"lsp_hover\",quantile=\"0.5\"} 0.02\n"
+        ));
+        assert!(!text.contains("lsmcp_tool_calls_timeouts_total"));
+        assert_eq!(escape_label("a\"b\\"), "a\\\"b\\\\");
+
+        let report = metrics.render_text();
+        assert!(report.contains("lsp_hover: 1 call(s), 0 error(s), p50 20ms"));
+        assert!(report
+            .contains("rust-analyzer textDocument/hover: 1 call(s), 1 error(s) (1 timed out)"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Conversion of Mason registry entries into `LspPackage`s
+//!
+//! Mason describes packages by a package URL (`pkg:npm/pyright@1.1.390`) and
+//! a map of installed commands. It has no notion of file extensions or LSP
+//! arguments, so those come from the tables below and are best-effort.
+
+use crate::config::{BinaryConfig, Connection, InstallSource, LspPackage};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A package entry from Mason's `registry.json`
+#[derive(Debug, Deserialize)]
+pub struct MasonPackage {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub licenses: Vec<String>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    pub source: MasonSource,
+    #[serde(default)]
+    pub bin: BTreeMap<String, String>,
+    pub deprecation: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MasonSource {
+    pub id: String,
+}
+
+/// Mason language names that don't match ours once lowercased
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("c++", "cpp"),
+    ("c#", "csharp"),
+    ("f#", "fsharp"),
+    ("objective-c", "objc"),
+    ("shell", "bash"),
+];
+
+/// File extensions for languages Mason lists
+const LANGUAGE_EXTENSIONS: &[(&str, &[&str])] = &[
+    ("angular", &["component.html"]),
+    ("astro", &["astro"]),
+    ("bash", &["sh", "bash"]),
+    ("c", &["c", "h"]),
+    ("clojure", &["clj", "cljs", "cljc", "edn"]),
+    ("cmake", &["cmake"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "hxx"]),
+    ("csharp", &["cs"]),
+    ("css", &["css", "scss", "less"]),
+    ("d", &["d"]),
+    ("dart", &["dart"]),
+    ("dockerfile", &["dockerfile"]),
+    ("elixir", &["ex", "exs"]),
+    ("elm", &["elm"]),
+    ("erlang", &["erl", "hrl"]),
+    ("fortran", &["f90", "f95", "f03", "f08"]),
+    ("fsharp", &["fs", "fsi", "fsx"]),
+    ("gleam", &["gleam"]),
+    ("go", &["go"]),
+    ("graphql", &["graphql", "gql"]),
+    ("groovy", &["groovy", "gradle"]),
+    ("haskell", &["hs", "lhs"]),
+    ("html", &["html", "htm"]),
+    ("java", &["java"]),
+    ("javascript", &["js", "mjs", "cjs", "jsx"]),
+    ("json", &["json", "jsonc"]),
+    ("julia", &["jl"]),
+    ("kotlin", &["kt", "kts"]),
+    ("latex", &["tex", "bib"]),
+    ("lua", &["lua"]),
+    ("markdown", &["md", "markdown"]),
+    ("nim", &["nim"]),
+    ("nix", &["nix"]),
+    ("objc", &["m", "mm"]),
+    ("ocaml", &["ml", "mli"]),
+    ("perl", &["pl", "pm"]),
+    ("php", &["php"]),
+    ("powershell", &["ps1", "psm1"]),
+    ("prisma", &["prisma"]),
+    ("protobuf", &["proto"]),
+    ("python", &["py", "pyi"]),
+    ("r", &["r", "rmd"]),
+    ("ruby", &["rb", "rake", "gemspec"]),
+    ("rust", &["rs"]),
+    ("scala", &["scala", "sc", "sbt"]),
+    ("sql", &["sql"]),
+    ("svelte", &["svelte"]),
+    ("swift", &["swift"]),
+    ("terraform", &["tf", "tfvars"]),
+    ("toml", &["toml"]),
+    ("typescript", &["ts", "tsx", "mts", "cts"]),
+    ("vue", &["vue"]),
+    ("xml", &["xml", "xsd", "xsl"]),
+    ("yaml", &["yaml", "yml"]),
+    ("zig", &["zig"]),
+];
+
+/// Arguments for servers that don't follow the `--stdio` convention of
+/// Node-based servers
+const LSP_ARGS: &[(&str, &[&str])] = &[
+    ("bash-language-server", &["start"]),
+    ("elm-language-server", &[]),
+    ("solargraph", &["stdio"]),
+    ("taplo", &["lsp", "stdio"]),
+    ("vim-language-server", &["--stdio"]),
+];
+
+/// Convert a Mason entry, or `None` when it isn't a usable language server
+pub fn to_lsp_package(mason: &MasonPackage) -> Option<LspPackage> {
+    if !mason.categories.iter().any(|c| c == "LSP") || mason.deprecation.is_some() {
+        return None;
+    }
+
+    let primary = primary_bin(mason)?;
+    let purl = Purl::parse(&mason.source.id)?;
+    let source = install_source(&purl, &primary);
+
+    let languages: Vec<String> = mason.languages.iter().map(|l| language_id(l)).collect();
+    let file_extensions = languages
+        .iter()
+        .filter_map(|language| {
+            LANGUAGE_EXTENSIONS
+                .iter()
+                .find(|(name, _)| name == language)
+        })
+        .flat_map(|(_, extensions)| extensions.iter().map(|e| e.to_string()))
+        .collect();
+
+    let lsp_args = match LSP_ARGS.iter().find(|(name, _)| *name == mason.name) {
+        Some((_, args)) => args.iter().map(|a| a.to_string()).collect(),
+        None if matches!(source, InstallSource::Npm { .. }) => vec!["--stdio".to_string()],
+        None => Vec::new(),
+    };
+
+    Some(LspPackage {
+        name: mason.name.clone(),
+        description: mason.description.trim().to_string(),
+        homepage: mason.homepage.clone(),
+        licenses: mason.licenses.clone(),
+        // Servers without a language still resolve by name
+        languages: if languages.is_empty() {
+            vec![mason.name.clone()]
+        } else {
+            languages
+        },
+        file_extensions,
+        filenames: Vec::new(),
+        filename_patterns: Vec::new(),
+        shebangs: Vec::new(),
+        source,
+        bin: BinaryConfig {
+            primary: primary.clone(),
+            additional: mason
+                .bin
+                .keys()
+                .filter(|b| **b != primary)
+                .cloned()
+                .collect(),
+            lsp_args,
+            env: HashMap::new(),
+            cwd: None,
+            connection: Connection::Stdio,
+            spawn_timeout_secs: None,
+            initialize_timeout_secs: None,
+            limits: Default::default(),
+            setup: Vec::new(),
+        },
+        initialization_options: None,
+        settings: None,
+        remote: None,
+        extensions: Vec::new(),
+    })
+}
+
+/// Our language id for a Mason language name (`C++` -> `cpp`)
+fn language_id(name: &str) -> String {
+    let lower = name.to_lowercase();
+    LANGUAGE_ALIASES
+        .iter()
+        .find(|(mason, _)| *mason == lower)
+        .map_or_else(|| lower.replace(' ', "-"), |(_, ours)| ours.to_string())
+}
+
+/// The command that starts the server
+///
+/// Prefers a command that looks like a language server over helper CLIs
+/// (`pyright-langserver` over `pyright`).
+fn primary_bin(mason: &MasonPackage) -> Option<String> {
+    let looks_like_server = |bin: &&String| {
+        ["language-server", "langserver", "-lsp", "-ls", "lsp-"]
+            .iter()
+            .any(|marker| bin.contains(marker))
+    };
+
+    mason
+        .bin
+        .keys()
+        .find(looks_like_server)
+        .or_else(|| mason.bin.keys().find(|b| **b == mason.name))
+        .or_else(|| mason.bin.keys().next())
+        .cloned()
+}
+
+fn install_source(purl: &Purl, primary: &str) -> InstallSource {
+    match purl.kind.as_str() {
+        "npm" => InstallSource::Npm {
+            package: purl.path.clone(),
+            version: None,
+        },
+        // Crates from git repositories can't be installed by name
+        "cargo" if !purl.qualifiers.contains("repository_url") => InstallSource::Cargo {
+            crate_name: purl.path.clone(),
+            version: None,
+        },
+        "pypi" => InstallSource::Pip {
+            package: purl.path.clone(),
+            version: None,
+        },
+        "golang" => InstallSource::Go {
+            package: match &purl.subpath {
+                Some(subpath) => format!("{}/{}", purl.path, subpath),
+                None => purl.path.clone(),
+            },
+            version: None,
+        },
+        "github" => InstallSource::GithubRelease {
+            repo: purl.path.clone(),
+            tag: None,
+            asset: None,
+        },
+        // gem, luarocks, opam, nuget, generic, ...: bring your own binary
+        _ => InstallSource::External {
+            command: primary.to_string(),
+        },
+    }
+}
+
+/// The parts of a package URL we use
+#[derive(Debug, PartialEq)]
+struct Purl {
+    kind: String,
+    path: String,
+    qualifiers: String,
+    subpath: Option<String>,
+}
+
+impl Purl {
+    /// Parse `pkg:<type>/<namespace>/<name>@<version>?<qualifiers>#<subpath>`
+    fn parse(id: &str) -> Option<Self> {
+        let rest = id.strip_prefix("pkg:")?;
+        let (rest, subpath) = match rest.split_once('#') {
+            Some((rest, subpath)) => (rest, Some(subpath.to_string())),
+            None => (rest, None),
+        };
+        let (rest, qualifiers) = rest.split_once('?').unwrap_or((rest, ""));
+        let (rest, _version) = match rest.rsplit_once('@') {
+            // `@` at the start of a segment is an npm scope, not a version
+            Some((path, version)) if !path.ends_with('/') => (path, Some(version)),
+            _ => (rest, None),
+        };
+        let (kind, path) = rest.split_once('/')?;
+
+        Some(Self {
+            kind: kind.to_string(),
+            path: path.replace("%40", "@"),
+            qualifiers: qualifiers.to_string(),
+            subpath,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mason(json: serde_json::Value) -> MasonPackage {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_purl() {
+        let purl = Purl::parse("pkg:npm/%40vue/language-server@2.0.1").unwrap();
+        assert_eq!(purl.kind, "npm");
+        assert_eq!(purl.path, "@vue/language-server");
+
+        let purl = Purl::parse("pkg:golang/golang.org/x/tools/gopls@v0.15.2").unwrap();
+        assert_eq!(purl.path, "golang.org/x/tools/gopls");
+
+        let purl =
+            Purl::parse("pkg:cargo/nil@2023-08-09?repository_url=https://github.com/oxalica/nil")
+                .unwrap();
+        assert_eq!(purl.path, "nil");
+        assert_eq!(
+            purl.qualifiers,
+            "repository_url=https://github.com/oxalica/nil"
+        );
+
+        let purl = Purl::parse("pkg:golang/github.com/a/b@v1#cmd/b-lsp").unwrap();
+        assert_eq!(purl.subpath.as_deref(), Some("cmd/b-lsp"));
+    }
+
+    #[test]
+    fn test_convert_mason_packages() {
+        let pyright = to_lsp_package(&mason(serde_json::json!({
+            "name": "pyright",
+            "description": "Static type checker for Python\n",
+            "homepage": "https://github.com/microsoft/pyright",
+            "licenses": ["MIT"],
+            "languages": ["Python"],
+            "categories": ["LSP"],
+            "source": { "id": "pkg:npm/pyright@1.1.390" },
+            "bin": { "pyright": "npm:pyright", "pyright-langserver": "npm:pyright-langserver" }
+        })))
+        .unwrap();
+        assert_eq!(pyright.languages, vec!["python"]);
+        assert_eq!(pyright.file_extensions, vec!["py", "pyi"]);
+        assert_eq!(pyright.bin.primary, "pyright-langserver");
+        assert_eq!(pyright.bin.additional, vec!["pyright"]);
+        assert_eq!(pyright.bin.lsp_args, vec!["--stdio"]);
+        assert_eq!(pyright.description, "Static type checker for Python");
+
+        let clangd = to_lsp_package(&mason(serde_json::json!({
+            "name": "clangd",
+            "languages": ["C", "C++"],
+            "categories": ["LSP"],
+            "source": { "id": "pkg:github/clangd/clangd@18.1.3" },
+            "bin": { "clangd": "{{source.asset.bin}}" }
+        })))
+        .unwrap();
+        assert_eq!(clangd.languages, vec!["c", "cpp"]);
+        assert!(matches!(
+            clangd.source,
+            InstallSource::GithubRelease { ref repo, .. } if repo == "clangd/clangd"
+        ));
+
+        // Formatters and packages without commands are skipped
+        assert!(to_lsp_package(&mason(serde_json::json!({
+            "name": "black",
+            "categories": ["Formatter"],
+            "source": { "id": "pkg:pypi/black@24.1.0" },
+            "bin": { "black": "pypi:black" }
+        })))
+        .is_none());
+    }
+}
@@ -0,0 +1,192 @@
+//! Mason registry sync and local cache
+//!
+//! `lsmcp registry sync` downloads the upstream Mason registry, keeps its
+//! language servers and caches them under the data directory. The config
+//! loader merges the cache below the embedded registry, so curated entries
+//! always win.
+
+pub mod mason;
+
+use crate::config::LspPackage;
+use crate::installer::github;
+use crate::installer::{InstallOptions, ServerInstaller};
+use crate::types::LspError;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// GitHub repository publishing `registry.json.zip` with every release
+pub const MASON_REPO: &str = "mason-org/mason-registry";
+
+const REGISTRY_ASSET: &str = "registry.json.zip";
+
+/// Refresh a cached registry older than this by default
+pub const DEFAULT_REFRESH_HOURS: u64 = 24;
+
+/// Language servers converted from one Mason registry release
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegistryCache {
+    /// Mason registry release tag
+    pub version: String,
+    /// When the cache was written (RFC 3339)
+    pub synced_at: String,
+    pub packages: Vec<LspPackage>,
+}
+
+impl RegistryCache {
+    /// Time since the cache was written (`None` if unknown)
+    pub fn age(&self) -> Option<Duration> {
+        let synced_at = chrono::DateTime::parse_from_rfc3339(&self.synced_at).ok()?;
+        (chrono::Utc::now() - synced_at.with_timezone(&chrono::Utc))
+            .to_std()
+            .ok()
+    }
+
+    /// Whether the cache is older than `max_age`
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age().is_none_or(|age| age >= max_age)
+    }
+}
+
+/// Location of the registry cache
+pub fn cache_path() -> Result<PathBuf, LspError> {
+    Ok(ServerInstaller::get_data_dir()?
+        .join("registry")
+        .join("mason-registry.json"))
+}
+
+/// The cached registry, if `lsmcp registry sync` has been run
+pub fn load_cached() -> Option<RegistryCache> {
+    let path = cache_path().ok()?;
+    let content = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            warn!(
+                "Ignoring unreadable registry cache {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Result of a registry sync
+#[derive(Debug)]
+pub struct SyncReport {
+    pub version: String,
+    /// Packages in the Mason registry
+    pub total: usize,
+    /// Language servers kept
+    pub servers: usize,
+    pub path: PathBuf,
+}
+
+/// Download the latest Mason registry and replace the cache
+pub async fn sync(options: &InstallOptions) -> Result<SyncReport, LspError> {
+    if options.offline {
+        return Err(LspError::ConfigError(
+            "Cannot sync the Mason registry in offline mode".to_string(),
+        ));
+    }
+
+    let release = github::fetch_release(MASON_REPO, None, options).await?;
+    let asset = release
+        .assets
+        .iter()
+        .find(|a| a.name == REGISTRY_ASSET)
+        .ok_or_else(|| {
+            LspError::ConfigError(format!(
+                "Mason registry release {} has no {}",
+                release.tag_name, REGISTRY_ASSET
+            ))
+        })?;
+
+    let path = cache_path()?;
+    let dir = path.parent().expect("cache path has a parent");
+    std::fs::create_dir_all(dir)?;
+
+    let archive = dir.join(REGISTRY_ASSET);
+    github::download(&asset.browser_download_url, &archive, MASON_REPO, options).await?;
+    if let Some(digest) = github::asset_digest(asset) {
+        github::verify_sha256(&archive, &digest, MASON_REPO)?;
+    }
+
+    let output = options
+        .command("unzip")
+        .arg("-p")
+        .arg(&archive)
+        .arg("registry.json")
+        .output()
+        .await;
+    let _ = std::fs::remove_file(&archive);
+    let output =
+        output.map_err(|e| LspError::ConfigError(format!("unzip not found or failed: {}", e)))?;
+    if !output.status.success() {
+        return Err(LspError::ConfigError(format!(
+            "Failed to extract {}: {}",
+            REGISTRY_ASSET,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // Convert entry by entry so one unexpected entry doesn't fail the sync
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    let total = entries.len();
+    let mut packages: Vec<LspPackage> = entries
+        .into_iter()
+        .filter_map(|entry| match serde_json::from_value(entry) {
+            Ok(mason) => mason::to_lsp_package(&mason),
+            Err(e) => {
+                debug!("Skipping unreadable Mason entry: {}", e);
+                None
+            }
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let cache = RegistryCache {
+        version: release.tag_name,
+        synced_at: chrono::Utc::now().to_rfc3339(),
+        packages,
+    };
+
+    // Write then rename so a running lsmcp never reads half a file
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(&cache)?)?;
+    std::fs::rename(&tmp, &path)?;
+
+    info!(
+        "Synced {} language servers from Mason registry {}",
+        cache.packages.len(),
+        cache.version
+    );
+
+    Ok(SyncReport {
+        version: cache.version,
+        total,
+        servers: cache.packages.len(),
+        path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_staleness() {
+        let mut cache = RegistryCache {
+            version: "2024-01-01-abc".to_string(),
+            synced_at: (chrono::Utc::now() - chrono::Duration::hours(30)).to_rfc3339(),
+            packages: Vec::new(),
+        };
+        assert!(cache.is_stale(Duration::from_secs(DEFAULT_REFRESH_HOURS * 3600)));
+        assert!(!cache.is_stale(Duration::from_secs(48 * 3600)));
+
+        cache.synced_at = "not a date".to_string();
+        assert!(cache.is_stale(Duration::from_secs(48 * 3600)));
+    }
+}
@@ -0,0 +1,171 @@
+//! Speculative edit checks
+//!
+//! `lsp_diagnostics_after_edit` applies proposed edits to a file's content
+//! in memory, has its servers diagnose the result and reports which
+//! diagnostics the edits introduce or resolve, without touching the working
+//! tree. Diagnostics are compared by severity, source, code and message
+//! rather than position, as edits move the code around them.
+
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Seconds to wait for a server's diagnostics, unless asked otherwise
+pub const DEFAULT_WAIT_SECS: u64 = 10;
+
+/// Longest wait for a server's diagnostics a call may ask for
+pub const MAX_WAIT_SECS: u64 = 60;
+
+/// One proposed replacement
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProposedEdit {
+    /// Text to replace, which must occur exactly once
+    pub old_text: String,
+    pub new_text: String,
+}
+
+/// `text` with `edits` applied in order, each to the result of the last
+pub fn apply_edits(text: &str, edits: &[ProposedEdit]) -> Result<String, String> {
+    let mut edited = text.to_string();
+    for (i, edit) in edits.iter().enumerate() {
+        if edit.old_text.is_empty() {
+            return Err(format!("edit {} has an empty `old_text`", i + 1));
+        }
+        let mut matches = edited.match_indices(&edit.old_text);
+        let Some((offset, _)) = matches.next() else {
+            return Err(format!("`old_text` of edit {} was not found", i + 1));
+        };
+        if matches.next().is_some() {
+            return Err(format!(
+                "`old_text` of edit {} occurs more than once; include more context",
+                i + 1
+            ));
+        }
+        edited.replace_range(offset..offset + edit.old_text.len(), &edit.new_text);
+    }
+    Ok(edited)
+}
+
+/// What proposed edits change about a file's diagnostics
+#[derive(Debug, Default)]
+pub struct DiagnosticDelta {
+    /// Diagnostics of the edited file that it didn't have before
+    pub introduced: Vec<Diagnostic>,
+    /// Diagnostics the file had that the edits remove
+    pub resolved: Vec<Diagnostic>,
+}
+
+impl DiagnosticDelta {
+    /// Whether the edits introduce an error (diagnostics without a severity
+    /// count as errors)
+    pub fn introduces_errors(&self) -> bool {
+        self.introduced
+            .iter()
+            .any(|d| d.severity.is_none_or(|s| s == DiagnosticSeverity::ERROR))
+    }
+}
+
+/// What identifies a diagnostic across edits: its severity (by rank, as
+/// `DiagnosticSeverity` isn't hashable), source, code and message
+type DiagnosticKey = (u8, Option<String>, Option<String>, String);
+
+fn key(diagnostic: &Diagnostic) -> DiagnosticKey {
+    let severity = match diagnostic.severity {
+        None => 0,
+        Some(DiagnosticSeverity::ERROR) => 1,
+        Some(DiagnosticSeverity::WARNING) => 2,
+        Some(DiagnosticSeverity::INFORMATION) => 3,
+        Some(DiagnosticSeverity::HINT) => 4,
+        Some(_) => 5,
+    };
+    (
+        severity,
+        diagnostic.source.clone(),
+        diagnostic.code.as_ref().map(|code| match code {
+            NumberOrString::Number(n) => n.to_string(),
+            NumberOrString::String(s) => s.clone(),
+        }),
+        diagnostic.message.clone(),
+    )
+}
+
+/// The diagnostics in `after` beyond those in `before`, and the other way
+/// round, counting repeats
+pub fn compare(before: &[Diagnostic], after: &[Diagnostic]) -> DiagnosticDelta {
+    DiagnosticDelta {
+        introduced: beyond(after, before),
+        resolved: beyond(before, after),
+    }
+}
+
+/// The diagnostics of `these` left over once each one in `those` has
+/// cancelled out one with the same key
+fn beyond(these: &[Diagnostic], those: &[Diagnostic]) -> Vec<Diagnostic> {
+    let mut available: HashMap<DiagnosticKey, usize> = HashMap::new();
+    for diagnostic in those {
+        *available.entry(key(diagnostic)).or_default() += 1;
+    }
+    these
+        .iter()
+        .filter(|diagnostic| match available.get_mut(&key(diagnostic)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    fn diagnostic(line: u32, severity: DiagnosticSeverity, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            severity: Some(severity),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_edit_check() {
+        let edit = |old: &str, new: &str| ProposedEdit {
+            old_text: old.to_string(),
+            new_text: new.to_string(),
+        };
+        let text = "fn a() {}\nfn b() {}\n";
+        assert_eq!(
+            apply_edits(text, &[edit("fn b", "fn c"), edit("c() {}", "c() { a() }")]).unwrap(),
+            "fn a() {}\nfn c() { a() }\n"
+        );
+        assert!(apply_edits(text, &[edit("fn", "pub fn")])
+            .unwrap_err()
+            .contains("more than once"));
+        assert!(apply_edits(text, &[edit("fn z", "")])
+            .unwrap_err()
+            .contains("not found"));
+
+        let before = [
+            diagnostic(1, DiagnosticSeverity::WARNING, "unused"),
+            diagnostic(4, DiagnosticSeverity::ERROR, "mismatched types"),
+        ];
+        // The warning moved down a line and the error was fixed, but a new
+        // one appeared
+        let after = [
+            diagnostic(2, DiagnosticSeverity::WARNING, "unused"),
+            diagnostic(7, DiagnosticSeverity::ERROR, "cannot find value `x`"),
+        ];
+        let delta = compare(&before, &after);
+        assert_eq!(delta.introduced.len(), 1);
+        assert_eq!(delta.introduced[0].message, "cannot find value `x`");
+        assert_eq!(delta.resolved.len(), 1);
+        assert_eq!(delta.resolved[0].message, "mismatched types");
+        assert!(delta.introduces_errors());
+        assert!(!compare(&after, &after).introduces_errors());
+    }
+}
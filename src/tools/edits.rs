@@ -0,0 +1,551 @@
+//! Workspace edits
+//!
+//! Tools that change files (`lsp_rename`) work out the new contents first
+//! and, under the default `preview` edit policy, only show them as a unified
+//! diff. The change is kept under a token until `lsp_confirm_edit` applies
+//! it, so a person can review an agent's refactor before anything is
+//! written; MCP clients that ask before each tool call then ask before
+//! every applied edit.
+//!
+//! Applied edits are journaled in the data directory with the files'
+//! previous contents, so `lsp_undo_last_edit` or `lsmcp edits rollback` can
+//! revert a botched rename without git.
+
+use crate::installer::ServerInstaller;
+use crate::lsp::session;
+use crate::types::LspError;
+use lsp_types::{
+    DocumentChangeOperation, DocumentChanges, OneOf, Position, TextEdit, WorkspaceEdit,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, warn};
+
+/// How long a previewed edit can be confirmed
+pub const PENDING_EDIT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Previewed edits kept at once; the oldest are dropped first
+const MAX_PENDING_EDITS: usize = 32;
+
+/// Applied edits journaled per workspace; the oldest are dropped first
+pub const MAX_JOURNAL_ENTRIES: usize = 50;
+
+/// One file's contents before and after an edit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub original: String,
+    pub modified: String,
+    /// Text edits made to the file
+    #[serde(default)]
+    pub edits: usize,
+}
+
+impl FileChange {
+    /// The change putting the file back as it was
+    pub fn reversed(&self) -> FileChange {
+        FileChange {
+            path: self.path.clone(),
+            original: self.modified.clone(),
+            modified: self.original.clone(),
+            edits: self.edits,
+        }
+    }
+}
+
+/// The files an edit changes, with their new contents
+///
+/// `edit`'s positions must be in UTF-8. Creating, renaming and deleting
+/// files isn't supported.
+pub fn file_changes(edit: &WorkspaceEdit) -> Result<Vec<FileChange>, LspError> {
+    let mut by_file: BTreeMap<PathBuf, Vec<TextEdit>> = BTreeMap::new();
+    let mut add = |uri: &lsp_types::Url, edits: Vec<TextEdit>| -> Result<(), LspError> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| LspError::ProtocolError(format!("Edit to a non-file URI: {}", uri)))?;
+        by_file.entry(path).or_default().extend(edits);
+        Ok(())
+    };
+
+    for (uri, edits) in edit.changes.iter().flatten() {
+        add(uri, edits.clone())?;
+    }
+    let document_edits = |edit: &lsp_types::TextDocumentEdit| -> Vec<TextEdit> {
+        edit.edits
+            .iter()
+            .map(|edit| match edit {
+                OneOf::Left(edit) => edit.clone(),
+                OneOf::Right(annotated) => annotated.text_edit.clone(),
+            })
+            .collect()
+    };
+    match &edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => {
+            for edit in edits {
+                add(&edit.text_document.uri, document_edits(edit))?;
+            }
+        }
+        Some(DocumentChanges::Operations(operations)) => {
+            for operation in operations {
+                match operation {
+                    DocumentChangeOperation::Edit(edit) => {
+                        add(&edit.text_document.uri, document_edits(edit))?
+                    }
+                    DocumentChangeOperation::Op(_) => {
+                        return Err(LspError::EditFailed(
+                            "it creates, renames or deletes files, which isn't supported"
+                                .to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+        None => {}
+    }
+
+    by_file
+        .into_iter()
+        .map(|(path, edits)| {
+            let original = std::fs::read_to_string(&path)?;
+            let modified = apply_text_edits(&original, &edits)
+                .map_err(|e| LspError::ProtocolError(format!("{}: {}", path.display(), e)))?;
+            Ok(FileChange {
+                path,
+                original,
+                modified,
+                edits: edits.len(),
+            })
+        })
+        .filter(|change| !matches!(change, Ok(change) if change.original == change.modified))
+        .collect()
+}
+
+/// `text` with `edits` (in UTF-8 positions) made
+pub fn apply_text_edits(text: &str, edits: &[TextEdit]) -> Result<String, String> {
+    let mut spans: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            (
+                byte_offset(text, edit.range.start),
+                byte_offset(text, edit.range.end),
+                edit.new_text.as_str(),
+            )
+        })
+        .collect();
+    // Stable, so inserts at the same place keep the server's order
+    spans.sort_by_key(|(start, end, _)| (*start, *end));
+
+    let mut result = String::with_capacity(text.len());
+    let mut at = 0;
+    for (start, end, new_text) in spans {
+        if start < at || end < start {
+            return Err("the edits overlap".to_string());
+        }
+        result.push_str(&text[at..start]);
+        result.push_str(new_text);
+        at = end;
+    }
+    result.push_str(&text[at..]);
+    Ok(result)
+}
+
+/// Byte offset of `position` (in UTF-8 columns) in `text`, clamped to the
+/// line's end and the text's end
+fn byte_offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line_len = line.find('\n').unwrap_or(line.len());
+    let line_len = line[..line_len]
+        .strip_suffix('\r')
+        .map_or(line_len, str::len);
+    let mut offset = (position.character as usize).min(line_len);
+    while !line.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    line_start + offset
+}
+
+/// `changes` as a unified diff, with paths relative to `root` where
+/// possible
+pub fn unified_diff(changes: &[FileChange], root: &Path) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let name = change.path.strip_prefix(root).unwrap_or(&change.path);
+            diffy::DiffOptions::new()
+                .set_original_filename(format!("a/{}", name.display()))
+                .set_modified_filename(format!("b/{}", name.display()))
+                .create_patch(&change.original, &change.modified)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Write `changes`, unless any file changed since they were worked out
+///
+/// Every file is checked before any is written, and each is replaced
+/// atomically.
+pub fn write_changes(changes: &[FileChange]) -> Result<(), LspError> {
+    for change in changes {
+        if std::fs::read_to_string(&change.path)? != change.original {
+            return Err(LspError::EditFailed(format!(
+                "{} changed since the edit was prepared; prepare it again",
+                change.path.display()
+            )));
+        }
+    }
+    for change in changes {
+        let temp = change.path.with_extension("lsmcp-edit.tmp");
+        std::fs::write(&temp, &change.modified)?;
+        if let Ok(metadata) = std::fs::metadata(&change.path) {
+            let _ = std::fs::set_permissions(&temp, metadata.permissions());
+        }
+        std::fs::rename(&temp, &change.path)?;
+    }
+    Ok(())
+}
+
+/// An edit shown but not yet applied
+#[derive(Debug, Clone)]
+pub struct PendingEdit {
+    /// What the edit does, e.g. "Rename to `bar`"
+    pub description: String,
+    pub changes: Vec<FileChange>,
+    /// The journal entry the edit reverts, if it's an undo
+    pub undoes: Option<u64>,
+    created: Instant,
+}
+
+impl PendingEdit {
+    pub fn new(description: String, changes: Vec<FileChange>) -> Self {
+        Self {
+            description,
+            changes,
+            undoes: None,
+            created: Instant::now(),
+        }
+    }
+
+    /// Write the edit, then journal it, or drop the entry it undoes from
+    /// `journal`
+    ///
+    /// The files are written either way; a journal that can't be updated is
+    /// only logged.
+    pub fn apply(&self, journal: Option<&EditJournal>) -> Result<(), LspError> {
+        write_changes(&self.changes)?;
+        let Some(journal) = journal else {
+            return Ok(());
+        };
+        let journaled = match self.undoes {
+            Some(id) => journal.remove(id),
+            None => journal.record(&self.description, &self.changes).map(|_| ()),
+        };
+        if let Err(e) = journaled {
+            warn!("Failed to update the edit journal: {}", e);
+        }
+        Ok(())
+    }
+}
+
+/// Previewed edits by token
+#[derive(Debug, Default)]
+pub struct PendingEdits {
+    edits: Mutex<HashMap<String, PendingEdit>>,
+}
+
+/// The process-wide pending edits
+pub fn pending() -> &'static PendingEdits {
+    static PENDING: OnceLock<PendingEdits> = OnceLock::new();
+    PENDING.get_or_init(PendingEdits::default)
+}
+
+impl PendingEdits {
+    /// Keep an edit for confirmation, returning its token
+    pub fn insert(&self, mut edit: PendingEdit) -> String {
+        let token = new_token();
+        edit.created = Instant::now();
+        if let Ok(mut edits) = self.edits.lock() {
+            edits.retain(|_, edit| edit.created.elapsed() < PENDING_EDIT_TTL);
+            while edits.len() >= MAX_PENDING_EDITS {
+                let oldest = edits
+                    .iter()
+                    .min_by_key(|(_, edit)| edit.created)
+                    .map(|(token, _)| token.clone());
+                match oldest {
+                    Some(oldest) => edits.remove(&oldest),
+                    None => break,
+                };
+            }
+            edits.insert(token.clone(), edit);
+        }
+        token
+    }
+
+    /// Remove and return the edit kept under `token`, unless it expired
+    pub fn take(&self, token: &str) -> Option<PendingEdit> {
+        let edit = self.edits.lock().ok()?.remove(token)?;
+        (edit.created.elapsed() < PENDING_EDIT_TTL).then_some(edit)
+    }
+}
+
+/// An applied edit, as journaled
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Increasing within a workspace's journal
+    pub id: u64,
+    pub description: String,
+    /// Seconds since the Unix epoch
+    pub applied_at: u64,
+    pub changes: Vec<FileChange>,
+}
+
+impl JournalEntry {
+    /// The edit reverting this one
+    ///
+    /// With `force`, files changed since are reverted anyway, losing those
+    /// changes; otherwise applying it fails on them.
+    pub fn undo(&self, force: bool) -> Result<PendingEdit, LspError> {
+        let mut changes = Vec::new();
+        for change in &self.changes {
+            let mut reversed = change.reversed();
+            if force {
+                reversed.original = std::fs::read_to_string(&change.path)?;
+            }
+            changes.push(reversed);
+        }
+        let mut edit = PendingEdit::new(format!("Undo {}", self.description), changes);
+        edit.undoes = Some(self.id);
+        Ok(edit)
+    }
+}
+
+/// The applied edits of one workspace, one JSON file each
+#[derive(Debug, Clone)]
+pub struct EditJournal {
+    dir: PathBuf,
+}
+
+impl EditJournal {
+    /// The journal in `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The journal of `workspace_root` in the data directory
+    pub fn for_workspace(workspace_root: &Path) -> Result<Self, LspError> {
+        let dir = ServerInstaller::get_data_dir()?
+            .join("edits")
+            .join(session::workspace_key(workspace_root));
+        Ok(Self::new(dir))
+    }
+
+    /// Journal an applied edit, dropping the oldest entries past
+    /// `MAX_JOURNAL_ENTRIES`
+    pub fn record(
+        &self,
+        description: &str,
+        changes: &[FileChange],
+    ) -> Result<JournalEntry, LspError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let entries = self.list()?;
+        let entry = JournalEntry {
+            id: entries.first().map_or(1, |latest| latest.id + 1),
+            description: description.to_string(),
+            applied_at: session::now(),
+            changes: changes.to_vec(),
+        };
+        let path = self.entry_path(entry.id);
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_string(&entry)?)?;
+        std::fs::rename(&temp, &path)?;
+
+        for old in entries.iter().skip(MAX_JOURNAL_ENTRIES - 1) {
+            self.remove(old.id)?;
+        }
+        Ok(entry)
+    }
+
+    /// The journaled edits, newest first
+    ///
+    /// Unreadable entries are skipped.
+    pub fn list(&self) -> Result<Vec<JournalEntry>, LspError> {
+        let dir = match std::fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries: Vec<JournalEntry> = dir
+            .flatten()
+            .filter(|file| file.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|file| {
+                let content = std::fs::read_to_string(file.path()).ok()?;
+                match serde_json::from_str(&content) {
+                    Ok(entry) => Some(entry),
+                    Err(e) => {
+                        debug!("Skipping journal entry {}: {}", file.path().display(), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.id));
+        Ok(entries)
+    }
+
+    /// The entry `id`, or the latest
+    pub fn get(&self, id: Option<u64>) -> Result<JournalEntry, LspError> {
+        let entries = self.list()?;
+        let entry = match id {
+            Some(id) => entries.into_iter().find(|entry| entry.id == id),
+            None => entries.into_iter().next(),
+        };
+        entry.ok_or_else(|| {
+            LspError::EditFailed(match id {
+                Some(id) => format!("No journaled edit {}", id),
+                None => "No journaled edits to undo".to_string(),
+            })
+        })
+    }
+
+    /// Drop the entry `id`
+    pub fn remove(&self, id: u64) -> Result<(), LspError> {
+        match std::fs::remove_file(self.entry_path(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:06}.json", id))
+    }
+}
+
+/// A hard to guess token, so one daemon session can't confirm another's edit
+fn new_token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let seed = format!(
+        "{}:{}:{:?}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        SystemTime::now()
+    );
+    Sha256::digest(seed.as_bytes())
+        .iter()
+        .take(6)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Range, Url};
+
+    fn edit(line: u32, start: u32, end: u32, text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(line, start), Position::new(line, end)),
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_apply_text_edits() {
+        let text = "fn foo() {}\nlet café = foo();\r\n";
+        let edits = [edit(1, 12, 15, "bar"), edit(0, 3, 6, "bar")];
+        assert_eq!(
+            apply_text_edits(text, &edits).unwrap(),
+            "fn bar() {}\nlet café = bar();\r\n"
+        );
+        // Columns count bytes; past the end of a line or the file clamps
+        assert_eq!(
+            apply_text_edits("ab\n", &[edit(0, 9, 9, "!"), edit(5, 0, 0, "end")]).unwrap(),
+            "ab!\nend"
+        );
+        assert!(apply_text_edits(text, &[edit(0, 0, 5, "x"), edit(0, 3, 6, "y")]).is_err());
+    }
+
+    #[test]
+    fn test_preview_and_write() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("lib.rs");
+        std::fs::write(&file, "fn foo() {}\nfn main() { foo() }\n").unwrap();
+        let uri = Url::from_file_path(&file).unwrap();
+        let workspace_edit = WorkspaceEdit::new(HashMap::from([(
+            uri,
+            vec![edit(0, 3, 6, "bar"), edit(1, 12, 15, "bar")],
+        )]));
+
+        let changes = file_changes(&workspace_edit).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].modified, "fn bar() {}\nfn main() { bar() }\n");
+        let diff = unified_diff(&changes, tmp.path());
+        assert!(diff.starts_with("--- a/lib.rs\n+++ b/lib.rs\n"));
+        assert!(diff.contains("-fn foo() {}\n") && diff.contains("+fn bar() {}\n"));
+
+        let token = pending().insert(PendingEdit::new("Rename".to_string(), changes));
+        let confirmed = pending().take(&token).unwrap();
+        assert!(pending().take(&token).is_none());
+
+        std::fs::write(&file, "fn foo() {}\n").unwrap();
+        assert!(write_changes(&confirmed.changes).is_err());
+        std::fs::write(&file, "fn foo() {}\nfn main() { foo() }\n").unwrap();
+        let journal = EditJournal::new(tmp.path().join("journal"));
+        confirmed.apply(Some(&journal)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "fn bar() {}\nfn main() { bar() }\n"
+        );
+
+        // Undoing restores the file and drops the entry
+        let entry = journal.get(None).unwrap();
+        assert_eq!((entry.id, entry.description.as_str()), (1, "Rename"));
+        entry.undo(false).unwrap().apply(Some(&journal)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "fn foo() {}\nfn main() { foo() }\n"
+        );
+        assert!(journal.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_journal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.txt");
+        let journal = EditJournal::new(tmp.path().join("journal"));
+        let change = |original: &str, modified: &str| FileChange {
+            path: file.clone(),
+            original: original.to_string(),
+            modified: modified.to_string(),
+            edits: 1,
+        };
+        for n in 0..MAX_JOURNAL_ENTRIES + 2 {
+            journal
+                .record(&format!("edit {}", n), &[change("a", "b")])
+                .unwrap();
+        }
+        let entries = journal.list().unwrap();
+        assert_eq!(entries.len(), MAX_JOURNAL_ENTRIES);
+        assert_eq!(entries[0].id, MAX_JOURNAL_ENTRIES as u64 + 2);
+        assert_eq!(journal.get(Some(3)).unwrap().description, "edit 2");
+        assert!(journal.get(Some(1)).is_err());
+
+        // The file changed since the edit: undoing fails unless forced
+        std::fs::write(&file, "c").unwrap();
+        let latest = journal.get(None).unwrap();
+        assert!(latest.undo(false).unwrap().apply(Some(&journal)).is_err());
+        latest.undo(true).unwrap().apply(Some(&journal)).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "a");
+        assert_eq!(journal.list().unwrap().len(), MAX_JOURNAL_ENTRIES - 1);
+    }
+}
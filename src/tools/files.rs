@@ -0,0 +1,376 @@
+//! Workspace file listing tools
+//!
+//! `lsp_project_tree` and `lsp_find_files` let agents find the files to run
+//! queries on. Both skip `.git` and whatever `.gitignore` files (and
+//! `.git/info/exclude`) ignore, and never follow symlinked directories.
+
+use crate::utils::glob::path_glob_match;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory levels shown by `lsp_project_tree` unless asked otherwise
+pub const DEFAULT_TREE_DEPTH: usize = 3;
+
+/// Entries shown by `lsp_project_tree` unless asked otherwise
+pub const DEFAULT_TREE_ENTRIES: usize = 500;
+
+/// Files returned by `lsp_find_files` unless asked otherwise
+pub const DEFAULT_FIND_LIMIT: usize = 200;
+
+/// Stop walking after this many entries so huge trees stay fast
+const MAX_WALKED_ENTRIES: usize = 100_000;
+
+/// A `.gitignore` line
+#[derive(Debug)]
+struct IgnoreRule {
+    /// Directory of the `.gitignore` the rule is from
+    base: PathBuf,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // A slash anywhere but the end anchors the pattern to `base`;
+        // otherwise it matches at any depth
+        let pattern = match line.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if line.contains('/') => line.to_string(),
+            None => format!("**/{}", line),
+        };
+
+        Some(Self {
+            base: base.to_path_buf(),
+            pattern,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        path.strip_prefix(&self.base)
+            .is_ok_and(|relative| path_glob_match(&self.pattern, &relative.to_string_lossy()))
+    }
+}
+
+/// The ignore rules in effect while walking; each directory's `.gitignore`
+/// applies below it, with later (deeper) rules taking precedence
+#[derive(Debug, Default)]
+struct IgnoreStack {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreStack {
+    /// Rules for walking `dir`: those of the `.gitignore` files from the
+    /// repository root (the nearest ancestor with `.git`) down to it
+    fn for_dir(dir: &Path) -> Self {
+        let mut stack = Self::default();
+        let ancestors: Vec<&Path> = dir.ancestors().collect();
+        let repo_root = ancestors.iter().position(|d| d.join(".git").exists());
+        let Some(repo_root) = repo_root else {
+            stack.push_dir(dir);
+            return stack;
+        };
+
+        stack.push_file(
+            ancestors[repo_root],
+            &ancestors[repo_root].join(".git/info/exclude"),
+        );
+        for ancestor in ancestors[..=repo_root].iter().rev() {
+            stack.push_dir(ancestor);
+        }
+        stack
+    }
+
+    /// Add `dir`'s `.gitignore`, returning how many rules to keep when
+    /// leaving it
+    fn push_dir(&mut self, dir: &Path) -> usize {
+        let len = self.rules.len();
+        self.push_file(dir, &dir.join(".gitignore"));
+        len
+    }
+
+    fn push_file(&mut self, base: &Path, file: &Path) {
+        if let Ok(content) = fs::read_to_string(file) {
+            self.rules.extend(
+                content
+                    .lines()
+                    .filter_map(|line| IgnoreRule::parse(base, line)),
+            );
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+/// A directory's entries that aren't ignored: directories first, then by name
+fn children(dir: &Path, ignore: &IgnoreStack) -> Vec<(PathBuf, bool)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut children: Vec<(PathBuf, bool)> = entries
+        .flatten()
+        .filter(|entry| entry.file_name() != ".git")
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            (entry.path(), is_dir)
+        })
+        .filter(|(path, is_dir)| !ignore.is_ignored(path, *is_dir))
+        .collect();
+    children.sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+    children
+}
+
+/// An indented listing of `root`, `max_depth` levels deep
+#[derive(Debug)]
+pub struct ProjectTree {
+    pub text: String,
+    pub entries: usize,
+    /// Whether `max_entries` cut the listing short
+    pub truncated: bool,
+}
+
+/// List `root`, expanding directories up to `max_depth` levels deep
+pub fn project_tree(root: &Path, max_depth: usize, max_entries: usize) -> ProjectTree {
+    let mut tree = ProjectTree {
+        text: format!("{}/\n", root.display()),
+        entries: 0,
+        truncated: false,
+    };
+    let mut ignore = IgnoreStack::for_dir(root);
+    add_tree_level(root, 1, max_depth, max_entries, &mut ignore, &mut tree);
+    tree
+}
+
+fn add_tree_level(
+    dir: &Path,
+    depth: usize,
+    max_depth: usize,
+    max_entries: usize,
+    ignore: &mut IgnoreStack,
+    tree: &mut ProjectTree,
+) {
+    for (path, is_dir) in children(dir, ignore) {
+        if tree.entries >= max_entries {
+            tree.truncated = true;
+            return;
+        }
+        tree.entries += 1;
+
+        let indent = "  ".repeat(depth);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !is_dir {
+            tree.text.push_str(&format!("{}{}\n", indent, name));
+        } else if depth >= max_depth {
+            // Not expanded: say whether there is anything to expand
+            let more = fs::read_dir(&path).is_ok_and(|mut entries| entries.next().is_some());
+            let suffix = if more { " …" } else { "" };
+            tree.text
+                .push_str(&format!("{}{}/{}\n", indent, name, suffix));
+        } else {
+            tree.text.push_str(&format!("{}{}/\n", indent, name));
+            let keep = ignore.push_dir(&path);
+            add_tree_level(&path, depth + 1, max_depth, max_entries, ignore, tree);
+            ignore.rules.truncate(keep);
+        }
+    }
+}
+
+/// Files under a directory matching a glob
+#[derive(Debug)]
+pub struct FoundFiles {
+    pub files: Vec<PathBuf>,
+    /// Whether more files matched than `limit`
+    pub truncated: bool,
+}
+
+/// Files under `root` matching `pattern`, sorted
+///
+/// Patterns with a `/` match the path relative to `root` (`src/**/*.rs`);
+/// others match file names at any depth (`*.rs`).
+pub fn find_files(root: &Path, pattern: &str, limit: usize) -> FoundFiles {
+    let mut found = FoundFiles {
+        files: Vec::new(),
+        truncated: false,
+    };
+    found.truncated = !walk_files(root, |path| {
+        if glob_matches(root, pattern, &path) {
+            found.files.push(path);
+        }
+        true
+    });
+
+    found.files.sort();
+    if found.files.len() > limit {
+        found.files.truncate(limit);
+        found.truncated = true;
+    }
+    found
+}
+
+/// Whether `path` matches a `find_files` pattern, relative to `root`
+pub fn glob_matches(root: &Path, pattern: &str, path: &Path) -> bool {
+    if pattern.contains('/') {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        path_glob_match(pattern, &relative.to_string_lossy())
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        path_glob_match(pattern, &name)
+    }
+}
+
+/// Call `visit` with every file under `root` that isn't ignored, depth
+/// first, until it returns `false`
+///
+/// Returns whether every file was visited: `false` if `visit` stopped the
+/// walk or the tree was too big.
+pub fn walk_files(root: &Path, mut visit: impl FnMut(PathBuf) -> bool) -> bool {
+    let mut walked = 0;
+    let mut ignore = IgnoreStack::for_dir(root);
+    let mut stack = vec![(root.to_path_buf(), ignore.rules.len())];
+
+    // Each directory carries its parent's rule count, so a `.gitignore`
+    // stops applying once its subtree is done
+    while let Some((dir, keep)) = stack.pop() {
+        ignore.rules.truncate(keep);
+        if dir != root {
+            ignore.push_dir(&dir);
+        }
+        let rules = ignore.rules.len();
+
+        for (path, is_dir) in children(&dir, &ignore) {
+            walked += 1;
+            if walked > MAX_WALKED_ENTRIES {
+                return false;
+            }
+            if is_dir {
+                stack.push((path, rules));
+            } else if !visit(path) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for dir in [".git/info", "src/lsp", "target/debug", "docs", "logs"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "Cargo.toml",
+            "src/main.rs",
+            "src/lsp/client.rs",
+            "src/lsp/generated.rs",
+            "target/debug/lsmcp",
+            "docs/PLAN.md",
+            "logs/keep.log",
+            "logs/debug.log",
+            "notes.local",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "/target/\n*.log\n!keep.log\n").unwrap();
+        fs::write(root.join("src/.gitignore"), "generated.rs\n").unwrap();
+        fs::write(root.join(".git/info/exclude"), "*.local\n").unwrap();
+        tmp
+    }
+
+    fn relative(root: &Path, files: &[PathBuf]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.strip_prefix(root).unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_files_honors_gitignore() {
+        let tmp = workspace();
+        let root = tmp.path();
+
+        let found = find_files(root, "*.rs", 10);
+        assert_eq!(
+            relative(root, &found.files),
+            ["src/lsp/client.rs", "src/main.rs"]
+        );
+        assert!(!found.truncated);
+
+        let found = find_files(root, "**/*", 100);
+        assert_eq!(
+            relative(root, &found.files),
+            [
+                ".gitignore",
+                "Cargo.toml",
+                "docs/PLAN.md",
+                "logs/keep.log",
+                "src/.gitignore",
+                "src/lsp/client.rs",
+                "src/main.rs"
+            ]
+        );
+
+        let found = find_files(root, "src/*.rs", 10);
+        assert_eq!(relative(root, &found.files), ["src/main.rs"]);
+
+        let found = find_files(&root.join("src"), "*.rs", 1);
+        assert_eq!(found.files.len(), 1);
+        assert!(found.truncated);
+    }
+
+    #[test]
+    fn test_project_tree() {
+        let tmp = workspace();
+        let root = tmp.path();
+
+        let tree = project_tree(root, 2, 100);
+        let listing: Vec<&str> = tree.text.lines().skip(1).collect();
+        assert_eq!(
+            listing,
+            [
+                "  docs/",
+                "    PLAN.md",
+                "  logs/",
+                "    keep.log",
+                "  src/",
+                "    lsp/ …",
+                "    .gitignore",
+                "    main.rs",
+                "  .gitignore",
+                "  Cargo.toml",
+            ]
+        );
+        assert!(!tree.truncated);
+
+        let tree = project_tree(root, 3, 3);
+        assert_eq!(tree.entries, 3);
+        assert!(tree.truncated);
+    }
+}
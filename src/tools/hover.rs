@@ -1 +1,396 @@
 //! Hover information tool
+//!
+//! `lsp_hover` renders what servers return as markdown or plain text:
+//! documentation HTML is stripped, and long hovers are cut down to the
+//! signature and the first paragraph of documentation.
+
+use lsp_types::{Hover, HoverContents, MarkedString};
+use serde::Deserialize;
+
+/// Hovers longer than this many characters are truncated by default
+pub const DEFAULT_HOVER_MAX_LENGTH: usize = 4000;
+
+/// HTML tags removed from documentation; anything else in angle brackets
+/// (`Vec<T>`, `<init>`) is left alone
+const HTML_TAGS: &[&str] = &[
+    "a",
+    "b",
+    "blockquote",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "del",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+];
+
+const HTML_ENTITIES: &[(&str, &str)] = &[
+    ("&lt;", "<"),
+    ("&gt;", ">"),
+    ("&amp;", "&"),
+    ("&quot;", "\""),
+    ("&#39;", "'"),
+    ("&apos;", "'"),
+    ("&nbsp;", " "),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoverFormat {
+    #[default]
+    Markdown,
+    Plaintext,
+}
+
+/// Renders hover contents for display
+///
+/// Hover text is split into code blocks and paragraphs so that truncation
+/// can keep whole blocks: the signature (first code block) and the first
+/// paragraph of documentation survive, the rest is dropped.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HoverRenderer {
+    #[serde(default)]
+    format: HoverFormat,
+    #[serde(default = "default_hover_max_length")]
+    max_length: usize,
+    #[serde(default = "default_true")]
+    strip_html: bool,
+}
+
+fn default_hover_max_length() -> usize {
+    DEFAULT_HOVER_MAX_LENGTH
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A piece of hover markdown
+#[derive(Debug, Clone, PartialEq)]
+enum HoverBlock {
+    Code {
+        language: String,
+        text: String,
+    },
+    Paragraph(String),
+    /// `---` separator between hover sections
+    Rule,
+}
+
+impl HoverRenderer {
+    /// Hover contents as text in the chosen format, within `max_length`
+    pub fn render(&self, hover: Hover) -> String {
+        let mut blocks = hover_blocks(&format_hover(hover));
+        if self.strip_html {
+            for block in &mut blocks {
+                if let HoverBlock::Paragraph(text) = block {
+                    *text = strip_html(text);
+                }
+            }
+            blocks.retain(|b| !matches!(b, HoverBlock::Paragraph(text) if text.trim().is_empty()));
+        }
+
+        let full = self.render_blocks(&blocks);
+        let total = full.chars().count();
+        if self.max_length == 0 || total <= self.max_length {
+            return full;
+        }
+
+        // Keep the signature and the first paragraph of documentation
+        let signature = blocks.iter().find(|b| matches!(b, HoverBlock::Code { .. }));
+        let summary = blocks
+            .iter()
+            .find(|b| matches!(b, HoverBlock::Paragraph(_)));
+        let kept: Vec<HoverBlock> = blocks
+            .iter()
+            .filter(|b| Some(*b) == signature || Some(*b) == summary)
+            .cloned()
+            .collect();
+
+        let mut text = self.render_blocks(&kept);
+        if text.chars().count() > self.max_length {
+            text = text.chars().take(self.max_length).collect();
+            text.truncate(text.trim_end().len());
+            // Don't leave a code fence open
+            if self.format == HoverFormat::Markdown && text.matches("```").count() % 2 == 1 {
+                text.push_str("\n```");
+            }
+        }
+        let shown = text.chars().count();
+        format!(
+            "{}\n\n[truncated: showing {} of {} characters; raise max_length for the full text]",
+            text, shown, total
+        )
+    }
+
+    fn render_blocks(&self, blocks: &[HoverBlock]) -> String {
+        blocks
+            .iter()
+            .filter_map(|block| match (block, self.format) {
+                (HoverBlock::Code { language, text }, HoverFormat::Markdown) => {
+                    Some(format!("```{}\n{}\n```", language, text))
+                }
+                (HoverBlock::Code { text, .. }, HoverFormat::Plaintext) => Some(text.clone()),
+                (HoverBlock::Paragraph(text), HoverFormat::Markdown) => Some(text.clone()),
+                (HoverBlock::Paragraph(text), HoverFormat::Plaintext) => Some(plain_text(text)),
+                (HoverBlock::Rule, HoverFormat::Markdown) => Some("---".to_string()),
+                (HoverBlock::Rule, HoverFormat::Plaintext) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Split hover markdown into code blocks, paragraphs and rules
+fn hover_blocks(markdown: &str) -> Vec<HoverBlock> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<(String, Vec<&str>)> = None;
+
+    fn flush(paragraph: &mut Vec<&str>, blocks: &mut Vec<HoverBlock>) {
+        if !paragraph.is_empty() {
+            blocks.push(HoverBlock::Paragraph(paragraph.join("\n")));
+            paragraph.clear();
+        }
+    }
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if let Some((language, lines)) = &mut code {
+            if trimmed.starts_with("```") {
+                blocks.push(HoverBlock::Code {
+                    language: std::mem::take(language),
+                    text: lines.join("\n"),
+                });
+                code = None;
+            } else {
+                lines.push(line);
+            }
+        } else if let Some(language) = trimmed.strip_prefix("```") {
+            flush(&mut paragraph, &mut blocks);
+            code = Some((language.trim().to_string(), Vec::new()));
+        } else if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+        } else if trimmed.len() >= 3 && trimmed.chars().all(|c| c == '-' || c == '_' || c == '*') {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(HoverBlock::Rule);
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    flush(&mut paragraph, &mut blocks);
+    // An unterminated fence still holds code
+    if let Some((language, lines)) = code {
+        blocks.push(HoverBlock::Code {
+            language,
+            text: lines.join("\n"),
+        });
+    }
+    blocks
+}
+
+/// Remove HTML tags and comments and decode entities, outside inline code
+fn strip_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut in_code = false;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if !in_code && c == '<' {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            if let Some(end) = rest.find('>') {
+                let tag = rest[1..end].trim_start_matches('/');
+                let name: String = tag
+                    .chars()
+                    .take_while(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                if HTML_TAGS.contains(&name.as_str()) {
+                    if name == "br" || name == "p" {
+                        out.push('\n');
+                    }
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        } else if !in_code && c == '&' {
+            if let Some((entity, decoded)) = HTML_ENTITIES.iter().find(|(e, _)| rest.starts_with(e))
+            {
+                out.push_str(decoded);
+                rest = &rest[entity.len()..];
+                continue;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Markdown paragraph as plain text: no emphasis, inline code, headings or
+/// link targets
+fn plain_text(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.trim_start_matches('#').trim_start();
+            let mut out = String::with_capacity(line.len());
+            let mut rest = line;
+            while let Some(c) = rest.chars().next() {
+                if let Some(link) = rest.strip_prefix('[') {
+                    // [text](target) -> text
+                    if let Some((label, after)) = link.split_once("](") {
+                        if let Some(end) = after.find(')') {
+                            out.push_str(label);
+                            rest = &after[end + 1..];
+                            continue;
+                        }
+                    }
+                }
+                if rest.starts_with("**") || rest.starts_with("__") {
+                    rest = &rest[2..];
+                    continue;
+                }
+                if c == '`' {
+                    rest = &rest[1..];
+                    continue;
+                }
+                if c == '\\' {
+                    if let Some(escaped) = rest[1..]
+                        .chars()
+                        .next()
+                        .filter(|e| e.is_ascii_punctuation())
+                    {
+                        out.push(escaped);
+                        rest = &rest[1 + escaped.len_utf8()..];
+                        continue;
+                    }
+                }
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+            out
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_hover(hover: Hover) -> String {
+    match hover.contents {
+        HoverContents::Scalar(content) => format_markup_content(content),
+        HoverContents::Array(contents) => contents
+            .into_iter()
+            .map(format_markup_content)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        HoverContents::Markup(content) => content.value,
+    }
+}
+
+fn format_markup_content(content: MarkedString) -> String {
+    match content {
+        MarkedString::String(s) => s,
+        MarkedString::LanguageString(ls) => {
+            format!("```{}\n{}\n```", ls.language, ls.value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{MarkupContent, MarkupKind};
+    use serde_json::Value;
+
+    fn markdown_hover(value: &str) -> Hover {
+        Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: value.to_string(),
+            }),
+            range: None,
+        }
+    }
+
+    fn renderer(args: Value) -> HoverRenderer {
+        serde_json::from_value(args).unwrap()
+    }
+
+    #[test]
+    fn test_render_hover_plaintext_strips_html() {
+        let hover = markdown_hover(
+            "```rust\npub fn parse<T>(s: &str) -> Vec<T>\n```\n\n---\n\nParses **input** into a `Vec<T>`.<br>See [docs](https://example.com) &amp; <b>more</b>.",
+        );
+
+        let markdown = renderer(serde_json::json!({})).render(hover.clone());
+        assert!(markdown.contains("```rust\npub fn parse<T>(s: &str) -> Vec<T>\n```"));
+        assert!(markdown.contains("into a `Vec<T>`.\nSee [docs](https://example.com) & more."));
+
+        let plain = renderer(serde_json::json!({ "format": "plaintext" })).render(hover);
+        assert_eq!(
+            plain,
+            "pub fn parse<T>(s: &str) -> Vec<T>\n\nParses input into a Vec<T>.\nSee docs & more."
+        );
+    }
+
+    #[test]
+    fn test_render_hover_truncates_to_summary() {
+        let impls = "impl Clone for Config\n".repeat(100);
+        let hover = markdown_hover(&format!(
+            "```rust\npub struct Config\n```\n\nServer configuration.\n\n```rust\n{}```",
+            impls
+        ));
+
+        let text = renderer(serde_json::json!({ "max_length": 200 })).render(hover.clone());
+        assert!(text
+            .starts_with("```rust\npub struct Config\n```\n\nServer configuration.\n\n[truncated"));
+        assert!(!text.contains("impl Clone"));
+
+        // Even the summary is too long: cut, closing the open fence
+        let text = renderer(serde_json::json!({ "max_length": 18 })).render(hover.clone());
+        assert!(text.starts_with("```rust\npub struct\n```\n\n[truncated: showing"));
+
+        let text = renderer(serde_json::json!({ "max_length": 0 })).render(hover);
+        assert!(text.contains("impl Clone"));
+    }
+}
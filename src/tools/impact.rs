@@ -0,0 +1,128 @@
+//! Impact analysis tool
+//!
+//! `lsp_impact_analysis` answers "what breaks if I change this?": it finds a
+//! symbol's references, places each in the function or type containing it
+//! (from document symbols), and can repeat that for those symbols' own
+//! references, giving a tree of everything the change reaches.
+
+use crate::lsp::symbols::SymbolMatch;
+use lsp_types::Position;
+use std::path::PathBuf;
+
+/// Levels of callers followed unless asked otherwise
+pub const DEFAULT_IMPACT_DEPTH: usize = 1;
+
+/// Most levels of callers followed
+pub const MAX_IMPACT_DEPTH: usize = 3;
+
+/// Most references followed in one analysis, across all levels
+pub const MAX_IMPACT_REFERENCES: usize = 300;
+
+/// A symbol containing references to the changed symbol (or to a symbol
+/// affected by it)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpactNode {
+    pub path: PathBuf,
+    /// `None` for references outside any symbol, e.g. top-level code
+    pub symbol: Option<SymbolMatch>,
+    /// The references it contains, in UTF-8 columns
+    pub references: Vec<Position>,
+    /// The symbols referring to this one, when followed
+    pub callers: Vec<ImpactNode>,
+}
+
+/// Group references by the symbol containing them, in path and then
+/// position order
+pub fn group_references(
+    mut hits: Vec<(PathBuf, Position, Option<SymbolMatch>)>,
+) -> Vec<ImpactNode> {
+    hits.sort_by(|(a, a_at, _), (b, b_at, _)| a.cmp(b).then(a_at.cmp(b_at)));
+    let mut nodes: Vec<ImpactNode> = Vec::new();
+    for (path, position, symbol) in hits {
+        let same = |node: &ImpactNode| {
+            node.path == path
+                && node.symbol.as_ref().map(|s| s.range) == symbol.as_ref().map(|s| s.range)
+        };
+        match nodes.iter_mut().find(|node| same(node)) {
+            Some(node) => node.references.push(position),
+            None => nodes.push(ImpactNode {
+                path,
+                symbol,
+                references: vec![position],
+                callers: Vec::new(),
+            }),
+        }
+    }
+    nodes
+}
+
+/// The identifier at `character` (a UTF-8 offset) in `line`, if any
+pub fn identifier_at(line: &str, character: usize) -> Option<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let character = character.min(line.len());
+    let start = line[..character]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_ident(*c))
+        .last()
+        .map_or(character, |(i, _)| i);
+    let end = line[character..]
+        .char_indices()
+        .find(|(_, c)| !is_ident(*c))
+        .map_or(line.len(), |(i, _)| character + i);
+    (start < end).then(|| &line[start..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Range, SymbolKind};
+
+    #[test]
+    fn test_identifier_at() {
+        let line = "    let total = parse_args(&argv);";
+        assert_eq!(identifier_at(line, 18), Some("parse_args"));
+        assert_eq!(identifier_at(line, 16), Some("parse_args"));
+        assert_eq!(identifier_at(line, 26), Some("parse_args"));
+        assert_eq!(identifier_at(line, 2), None);
+    }
+
+    #[test]
+    fn test_group_references() {
+        let symbol = |name: &str, line: u32| SymbolMatch {
+            name: name.to_string(),
+            kind: SymbolKind::FUNCTION,
+            container: None,
+            path: PathBuf::from("/src/a.rs"),
+            range: Range::new(Position::new(line, 3), Position::new(line, 6)),
+        };
+        let hit = |path: &str, line: u32, symbol: Option<SymbolMatch>| {
+            (PathBuf::from(path), Position::new(line, 4), symbol)
+        };
+        let nodes = group_references(vec![
+            hit("/src/b.rs", 2, None),
+            hit("/src/a.rs", 7, Some(symbol("run", 5))),
+            hit("/src/a.rs", 1, Some(symbol("main", 0))),
+            hit("/src/a.rs", 6, Some(symbol("run", 5))),
+        ]);
+
+        let summary: Vec<(String, Option<String>, Vec<u32>)> = nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.path.display().to_string(),
+                    node.symbol.as_ref().map(|s| s.name.clone()),
+                    node.references.iter().map(|p| p.line).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("/src/a.rs".to_string(), Some("main".to_string()), vec![1]),
+                ("/src/a.rs".to_string(), Some("run".to_string()), vec![6, 7]),
+                ("/src/b.rs".to_string(), None, vec![2]),
+            ]
+        );
+    }
+}
@@ -1,6 +1,15 @@
 //! MCP tools that expose LSP capabilities
 
 pub mod definition;
+pub mod edit_check;
+pub mod edits;
+pub mod files;
 pub mod references;
 pub mod hover;
+pub mod impact;
+pub mod outline;
+pub mod run;
+pub mod search;
 pub mod symbols;
+pub mod unused;
+pub mod workspace;
@@ -0,0 +1,210 @@
+//! Module outline tool
+//!
+//! `lsp_module_outline` runs document symbols on every source file under a
+//! directory and lists them together, so exploring a package takes one call
+//! instead of one per file. Nesting depth and symbol kinds can be limited to
+//! keep large packages readable.
+
+use lsp_types::{DocumentSymbol, DocumentSymbolResponse, Position, SymbolKind};
+
+/// Symbol nesting levels shown unless asked otherwise: types and their members
+pub const DEFAULT_OUTLINE_DEPTH: usize = 2;
+
+/// Files outlined unless asked otherwise
+pub const DEFAULT_OUTLINE_FILES: usize = 100;
+
+/// One symbol of a file's outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    /// 0 for top-level symbols
+    pub depth: usize,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the symbol's name is
+    pub position: Position,
+}
+
+const SYMBOL_KINDS: [SymbolKind; 26] = [
+    SymbolKind::FILE,
+    SymbolKind::MODULE,
+    SymbolKind::NAMESPACE,
+    SymbolKind::PACKAGE,
+    SymbolKind::CLASS,
+    SymbolKind::METHOD,
+    SymbolKind::PROPERTY,
+    SymbolKind::FIELD,
+    SymbolKind::CONSTRUCTOR,
+    SymbolKind::ENUM,
+    SymbolKind::INTERFACE,
+    SymbolKind::FUNCTION,
+    SymbolKind::VARIABLE,
+    SymbolKind::CONSTANT,
+    SymbolKind::STRING,
+    SymbolKind::NUMBER,
+    SymbolKind::BOOLEAN,
+    SymbolKind::ARRAY,
+    SymbolKind::OBJECT,
+    SymbolKind::KEY,
+    SymbolKind::NULL,
+    SymbolKind::ENUM_MEMBER,
+    SymbolKind::STRUCT,
+    SymbolKind::EVENT,
+    SymbolKind::OPERATOR,
+    SymbolKind::TYPE_PARAMETER,
+];
+
+/// The symbol kind called `name` (`function`, `Struct`, `enum_member`...)
+pub fn parse_symbol_kind(name: &str) -> Option<SymbolKind> {
+    let wanted: String = name
+        .chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+    SYMBOL_KINDS
+        .into_iter()
+        .find(|kind| format!("{:?}", kind).to_lowercase() == wanted)
+}
+
+/// The symbols of `response` up to `max_depth` levels deep, in document
+/// order
+///
+/// With `kinds`, only symbols of those kinds are listed, along with the
+/// symbols containing them so they keep their context.
+pub fn outline_entries(
+    response: &DocumentSymbolResponse,
+    max_depth: usize,
+    kinds: &[SymbolKind],
+) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    match response {
+        DocumentSymbolResponse::Flat(symbols) => {
+            for symbol in symbols {
+                if kinds.is_empty() || kinds.contains(&symbol.kind) {
+                    entries.push(OutlineEntry {
+                        depth: 0,
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        position: symbol.location.range.start,
+                    });
+                }
+            }
+        }
+        DocumentSymbolResponse::Nested(symbols) => {
+            for symbol in symbols {
+                add_nested(symbol, 0, max_depth, kinds, &mut entries);
+            }
+        }
+    }
+    entries
+}
+
+/// Add `symbol` and its children, returning whether anything was added
+fn add_nested(
+    symbol: &DocumentSymbol,
+    depth: usize,
+    max_depth: usize,
+    kinds: &[SymbolKind],
+    entries: &mut Vec<OutlineEntry>,
+) -> bool {
+    if depth >= max_depth {
+        return false;
+    }
+    let index = entries.len();
+    let mut added = false;
+    for child in symbol.children.iter().flatten() {
+        added |= add_nested(child, depth + 1, max_depth, kinds, entries);
+    }
+    if added || kinds.is_empty() || kinds.contains(&symbol.kind) {
+        entries.insert(
+            index,
+            OutlineEntry {
+                depth,
+                name: symbol.name.clone(),
+                kind: symbol.kind,
+                position: symbol.selection_range.start,
+            },
+        );
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Range;
+
+    #[allow(deprecated)]
+    fn symbol(
+        name: &str,
+        kind: SymbolKind,
+        line: u32,
+        children: Vec<DocumentSymbol>,
+    ) -> DocumentSymbol {
+        let range = Range::new(Position::new(line, 0), Position::new(line, 10));
+        DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind,
+            tags: None,
+            deprecated: None,
+            range,
+            selection_range: range,
+            children: Some(children),
+        }
+    }
+
+    #[test]
+    fn test_parse_symbol_kind() {
+        assert_eq!(parse_symbol_kind("function"), Some(SymbolKind::FUNCTION));
+        assert_eq!(parse_symbol_kind("Struct"), Some(SymbolKind::STRUCT));
+        assert_eq!(
+            parse_symbol_kind("type_parameter"),
+            Some(SymbolKind::TYPE_PARAMETER)
+        );
+        assert_eq!(parse_symbol_kind("widget"), None);
+    }
+
+    #[test]
+    fn test_outline_entries() {
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol("MAX", SymbolKind::CONSTANT, 0, vec![]),
+            symbol(
+                "impl Parser",
+                SymbolKind::OBJECT,
+                2,
+                vec![
+                    symbol(
+                        "parse",
+                        SymbolKind::METHOD,
+                        3,
+                        vec![symbol("inner", SymbolKind::FUNCTION, 4, vec![])],
+                    ),
+                    symbol("LIMIT", SymbolKind::CONSTANT, 8, vec![]),
+                ],
+            ),
+        ]);
+        let names = |entries: Vec<OutlineEntry>| -> Vec<(usize, String)> {
+            entries.into_iter().map(|e| (e.depth, e.name)).collect()
+        };
+
+        assert_eq!(
+            names(outline_entries(&response, 2, &[])),
+            [
+                (0, "MAX".to_string()),
+                (0, "impl Parser".to_string()),
+                (1, "parse".to_string()),
+                (1, "LIMIT".to_string()),
+            ]
+        );
+        assert_eq!(
+            names(outline_entries(&response, 3, &[SymbolKind::FUNCTION])),
+            [
+                (0, "impl Parser".to_string()),
+                (1, "parse".to_string()),
+                (2, "inner".to_string()),
+            ]
+        );
+        assert!(outline_entries(&response, 2, &[SymbolKind::FUNCTION]).is_empty());
+    }
+}
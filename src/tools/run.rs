@@ -0,0 +1,219 @@
+//! Runnable execution
+//!
+//! `lsp_run_runnable` runs a command from `lsp_list_runnables` and returns
+//! what it printed. It is off unless the `allow_run` setting is on, since
+//! the commands come from language servers and run with the user's rights.
+
+use crate::lsp::runnables::Runnable;
+use crate::types::LspError;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+/// Seconds a runnable may take unless asked otherwise
+pub const DEFAULT_RUN_TIMEOUT_SECS: u64 = 300;
+
+/// Output kept per stream; test summaries come last, so the end is kept
+const MAX_OUTPUT_CHARS: usize = 20_000;
+
+/// Bytes kept per stream while reading: enough for `MAX_OUTPUT_CHARS` of
+/// any UTF-8
+const MAX_OUTPUT_BYTES: usize = MAX_OUTPUT_CHARS * 4;
+
+/// What a finished (or timed out) runnable printed
+#[derive(Debug)]
+pub struct RunOutput {
+    pub cwd: PathBuf,
+    /// `None` if it was killed (by a signal or the timeout)
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run `runnable` in its directory (`default_cwd` if it has none), killing
+/// it after `timeout`
+pub async fn run(
+    runnable: &Runnable,
+    default_cwd: &Path,
+    timeout: Duration,
+) -> Result<RunOutput, LspError> {
+    let cwd = runnable
+        .cwd
+        .clone()
+        .unwrap_or_else(|| default_cwd.to_path_buf());
+    let mut command = Command::new(&runnable.program);
+    command
+        .args(&runnable.args)
+        .envs(&runnable.env)
+        .current_dir(&cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Its own process group, so a timeout also stops what it started
+    // (cargo's test binary, say)
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let started = Instant::now();
+    let mut child = command.spawn().map_err(|e| {
+        LspError::ProtocolError(format!("Failed to run {}: {}", runnable.program, e))
+    })?;
+
+    // Read both streams while waiting, so a chatty command can't fill a
+    // pipe and stall
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let (mut out, mut err) = (OutputTail::default(), OutputTail::default());
+    let finished = tokio::time::timeout(timeout, async {
+        let (status, _, _) = tokio::join!(
+            child.wait(),
+            out.read_from(&mut stdout),
+            err.read_from(&mut stderr)
+        );
+        status
+    })
+    .await;
+
+    let (exit_code, timed_out) = match finished {
+        Ok(status) => (status?.code(), false),
+        Err(_) => {
+            #[cfg(unix)]
+            if let Some(pid) = child.id() {
+                let _ = Command::new("kill")
+                    .args(["-KILL", "--", &format!("-{}", pid)])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .await;
+            }
+            let _ = child.kill().await;
+            (None, true)
+        }
+    };
+    Ok(RunOutput {
+        cwd,
+        exit_code,
+        timed_out,
+        duration: started.elapsed(),
+        stdout: out.into_text(),
+        stderr: err.into_text(),
+    })
+}
+
+/// The end of a stream, kept while it is read so a chatty command's output
+/// is never held whole
+#[derive(Default)]
+struct OutputTail {
+    bytes: VecDeque<u8>,
+    /// Characters dropped from the front so far
+    cut: usize,
+}
+
+impl OutputTail {
+    /// Read `reader` to its end (or the first error)
+    async fn read_from(&mut self, reader: &mut (impl AsyncRead + Unpin)) {
+        let mut chunk = [0; 8192];
+        while let Ok(read @ 1..) = reader.read(&mut chunk).await {
+            self.push(&chunk[..read]);
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend(chunk);
+        let excess = self.bytes.len().saturating_sub(MAX_OUTPUT_BYTES);
+        // Each character has one byte that isn't a UTF-8 continuation byte
+        self.cut += self
+            .bytes
+            .drain(..excess)
+            .filter(|byte| byte & 0xC0 != 0x80)
+            .count();
+    }
+
+    /// The last `MAX_OUTPUT_CHARS` of the stream, noting what was cut
+    fn into_text(mut self) -> String {
+        // Don't start inside a character whose first byte was dropped
+        while self.bytes.front().is_some_and(|byte| byte & 0xC0 == 0x80) {
+            self.bytes.pop_front();
+        }
+        let text = String::from_utf8_lossy(self.bytes.make_contiguous()).into_owned();
+        let chars = text.chars().count();
+        let skipped = chars.saturating_sub(MAX_OUTPUT_CHARS);
+        let cut = self.cut + skipped;
+        if cut == 0 {
+            return text;
+        }
+        let kept: String = text.chars().skip(skipped).collect();
+        format!("[{} earlier characters cut]\n{}", cut, kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn shell(script: &str) -> Runnable {
+        Runnable {
+            label: "test".to_string(),
+            location: None,
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            cwd: None,
+            env: BTreeMap::from([("GREETING".to_string(), "hi".to_string())]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_captures_output() {
+        let tmp = tempfile::tempdir().unwrap();
+        let output = run(
+            &shell("echo $GREETING; pwd; echo oops >&2; exit 3"),
+            tmp.path(),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        let cwd = tmp.path().canonicalize().unwrap();
+        assert_eq!(output.stdout, format!("hi\n{}\n", cwd.display()));
+        assert_eq!(output.stderr, "oops\n");
+        assert_eq!(output.exit_code, Some(3));
+        assert!(!output.timed_out);
+    }
+
+    #[test]
+    fn test_output_tail() {
+        let mut tail = OutputTail::default();
+        for _ in 0..MAX_OUTPUT_BYTES {
+            tail.push("é".as_bytes());
+        }
+        tail.push(b"done");
+        let text = tail.into_text();
+        let cut = MAX_OUTPUT_BYTES - MAX_OUTPUT_CHARS + 4;
+        assert!(text.starts_with(&format!("[{} earlier characters cut]\né", cut)));
+        assert!(text.ends_with("éédone"));
+        assert_eq!(
+            text.lines().nth(1).unwrap().chars().count(),
+            MAX_OUTPUT_CHARS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_times_out() {
+        let output = run(
+            &shell("echo started; sleep 10"),
+            Path::new("/"),
+            Duration::from_millis(300),
+        )
+        .await
+        .unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.exit_code, None);
+    }
+}
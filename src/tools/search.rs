@@ -0,0 +1,177 @@
+//! Text search tool
+//!
+//! `lsp_search_text` greps the workspace, honoring `.gitignore` like the file
+//! listing tools. Symbol queries miss string literals, comments and
+//! configuration files; a text search finds them, and its hits can then be
+//! placed in their enclosing symbol with document symbols.
+
+use crate::tools::files::{glob_matches, walk_files};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Matches returned by `lsp_search_text` unless asked otherwise
+pub const DEFAULT_MAX_RESULTS: usize = 100;
+
+/// Larger files are skipped: they are almost always generated or data
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Matched lines longer than this are cut around the match
+const MAX_LINE_CHARS: usize = 200;
+
+/// One line matching the search
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    pub path: PathBuf,
+    /// 0-based
+    pub line: u32,
+    /// Byte offset of the match in the line
+    pub column: usize,
+    /// The whole line, without its terminator
+    pub line_text: String,
+}
+
+impl TextMatch {
+    /// The line for display, cut to `MAX_LINE_CHARS` around the match
+    pub fn excerpt(&self) -> String {
+        let text = self.line_text.trim_end();
+        if text.chars().count() <= MAX_LINE_CHARS {
+            return text.trim_start().to_string();
+        }
+        let before = text[..self.column.min(text.len())].chars().count();
+        let skip = before.saturating_sub(MAX_LINE_CHARS / 4);
+        let excerpt: String = text.chars().skip(skip).take(MAX_LINE_CHARS).collect();
+        let prefix = if skip > 0 { "…" } else { "" };
+        format!("{}{}…", prefix, excerpt.trim_start())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub matches: Vec<TextMatch>,
+    pub files_searched: usize,
+    /// Whether the search stopped at `max_results` (or the walk was cut short)
+    pub truncated: bool,
+}
+
+/// Lines matching `regex` in the files under `root`, in path order
+///
+/// `include` restricts the search to files matching a glob, as in
+/// `lsp_find_files`. Binary and very large files are skipped.
+pub fn search_text(
+    root: &Path,
+    regex: &Regex,
+    include: Option<&str>,
+    max_results: usize,
+) -> SearchResults {
+    let mut files = Vec::new();
+    let complete = walk_files(root, |path| {
+        if include.is_none_or(|pattern| glob_matches(root, pattern, &path)) {
+            files.push(path);
+        }
+        true
+    });
+    files.sort();
+
+    let mut results = SearchResults {
+        truncated: !complete,
+        ..Default::default()
+    };
+    for path in files {
+        let Some(text) = read_text(&path) else {
+            continue;
+        };
+        results.files_searched += 1;
+
+        for (line, line_text) in text.lines().enumerate() {
+            let Some(found) = regex.find(line_text) else {
+                continue;
+            };
+            if results.matches.len() >= max_results {
+                results.truncated = true;
+                return results;
+            }
+            results.matches.push(TextMatch {
+                path: path.clone(),
+                line: line as u32,
+                column: found.start(),
+                line_text: line_text.to_string(),
+            });
+        }
+    }
+    results
+}
+
+/// A file's text, unless it is too large or looks binary
+fn read_text(path: &Path) -> Option<String> {
+    let size = std::fs::metadata(path).ok()?.len();
+    if size > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_search_text() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    // TODO: parse args\n    run(\"TODO\");\n}\n",
+        )
+        .unwrap();
+        fs::write(root.join("README.md"), "TODO: write docs\n").unwrap();
+        fs::write(root.join("build/out.rs"), "// TODO generated\n").unwrap();
+        fs::write(root.join("src/blob.bin"), b"TODO\0\x01").unwrap();
+
+        let regex = Regex::new("TODO").unwrap();
+        let results = search_text(root, &regex, None, 10);
+        let hits: Vec<(String, u32, usize)> = results
+            .matches
+            .iter()
+            .map(|m| {
+                let path = m.path.strip_prefix(root).unwrap();
+                (path.to_string_lossy().to_string(), m.line, m.column)
+            })
+            .collect();
+        assert_eq!(
+            hits,
+            [
+                ("README.md".to_string(), 0, 0),
+                ("src/main.rs".to_string(), 1, 7),
+                ("src/main.rs".to_string(), 2, 9),
+            ]
+        );
+        assert_eq!(results.matches[1].excerpt(), "// TODO: parse args");
+        assert!(!results.truncated);
+
+        let results = search_text(root, &regex, Some("*.rs"), 1);
+        assert_eq!(results.matches.len(), 1);
+        assert!(results.truncated);
+    }
+
+    #[test]
+    fn test_excerpt_cuts_long_lines() {
+        let line = format!("{}needle{}", "a".repeat(500), "b".repeat(500));
+        let hit = TextMatch {
+            path: PathBuf::from("/x"),
+            line: 0,
+            column: 500,
+            line_text: line,
+        };
+        let excerpt = hit.excerpt();
+        assert!(excerpt.starts_with('…') && excerpt.ends_with('…'));
+        assert!(excerpt.contains("needle"));
+    }
+}
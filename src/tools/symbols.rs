@@ -1 +1,188 @@
 //! Symbol search tools
+//!
+//! Servers answer `workspace/symbol` with whatever fuzzy matching they use,
+//! for every kind of symbol anywhere in the workspace and its dependencies.
+//! `lsp_workspace_symbols` narrows their answer down afterwards: to some
+//! kinds, to files under a directory and not matching exclusion globs, to
+//! the files changed on the branch, and to names equal to the query or
+//! matching it as a glob (`*Config`).
+
+use crate::tools::files::glob_matches;
+use crate::tools::unused::bare_name;
+use lsp_types::{OneOf, SymbolKind, Url, WorkspaceSymbolResponse};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// How symbol names are matched against the query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameMatch {
+    /// Whatever the server returns for the query
+    #[default]
+    Fuzzy,
+    /// Names equal to the query
+    Exact,
+    /// Names matching the query as a glob, `*` for any run of characters
+    /// and `?` for one
+    Glob,
+}
+
+/// What to keep of a workspace symbol search
+#[derive(Debug, Clone, Default)]
+pub struct SymbolFilter {
+    pub query: String,
+    pub name_match: NameMatch,
+    /// Kinds to keep; empty keeps all
+    pub kinds: Vec<SymbolKind>,
+    /// Only symbols in files under this directory
+    pub path_prefix: Option<PathBuf>,
+    /// Skip symbols in files matching these globs, as in `lsp_find_files`
+    pub exclude_globs: Vec<String>,
+    /// Only symbols in these files, such as those changed on the branch
+    pub only_files: Option<HashSet<PathBuf>>,
+    pub workspace_root: PathBuf,
+}
+
+impl SymbolFilter {
+    /// The query to send the server: for globs, their longest literal part,
+    /// which every name they match contains
+    pub fn server_query(&self) -> String {
+        match self.name_match {
+            NameMatch::Glob => self
+                .query
+                .split(['*', '?'])
+                .max_by_key(|part| part.len())
+                .unwrap_or_default()
+                .to_string(),
+            _ => self.query.clone(),
+        }
+    }
+
+    /// Whether a symbol passes every filter
+    pub fn matches(&self, name: &str, kind: SymbolKind, uri: &Url) -> bool {
+        let name = bare_name(name);
+        let name_matches = match self.name_match {
+            NameMatch::Fuzzy => true,
+            NameMatch::Exact => name == self.query,
+            NameMatch::Glob => crate::utils::glob::glob_match(&self.query, name),
+        };
+        if !name_matches || !(self.kinds.is_empty() || self.kinds.contains(&kind)) {
+            return false;
+        }
+        if self.path_prefix.is_none() && self.exclude_globs.is_empty() && self.only_files.is_none()
+        {
+            return true;
+        }
+
+        // Symbols outside the file system (`jdt://`) aren't under any path
+        let Ok(path) = uri.to_file_path() else {
+            return false;
+        };
+        self.path_prefix
+            .as_ref()
+            .is_none_or(|prefix| path.starts_with(prefix))
+            && self
+                .only_files
+                .as_ref()
+                .is_none_or(|files| files.contains(&path))
+            && !self
+                .exclude_globs
+                .iter()
+                .any(|pattern| glob_matches(&self.workspace_root, pattern, &path))
+    }
+
+    /// The symbols of `response` that pass, and how many didn't
+    pub fn apply(&self, response: WorkspaceSymbolResponse) -> (WorkspaceSymbolResponse, usize) {
+        match response {
+            WorkspaceSymbolResponse::Flat(symbols) => {
+                let total = symbols.len();
+                let kept: Vec<_> = symbols
+                    .into_iter()
+                    .filter(|s| self.matches(&s.name, s.kind, &s.location.uri))
+                    .collect();
+                let filtered = total - kept.len();
+                (WorkspaceSymbolResponse::Flat(kept), filtered)
+            }
+            WorkspaceSymbolResponse::Nested(symbols) => {
+                let total = symbols.len();
+                let kept: Vec<_> = symbols
+                    .into_iter()
+                    .filter(|s| {
+                        let uri = match &s.location {
+                            OneOf::Left(location) => &location.uri,
+                            OneOf::Right(location) => &location.uri,
+                        };
+                        self.matches(&s.name, s.kind, uri)
+                    })
+                    .collect();
+                let filtered = total - kept.len();
+                (WorkspaceSymbolResponse::Nested(kept), filtered)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Location, Range, SymbolInformation};
+
+    #[allow(deprecated)]
+    fn symbol(name: &str, kind: SymbolKind, path: &str) -> SymbolInformation {
+        SymbolInformation {
+            name: name.to_string(),
+            kind,
+            tags: None,
+            deprecated: None,
+            location: Location::new(Url::from_file_path(path).unwrap(), Range::default()),
+            container_name: None,
+        }
+    }
+
+    #[test]
+    fn test_symbol_filter() {
+        let filter = SymbolFilter {
+            query: "*Config".to_string(),
+            name_match: NameMatch::Glob,
+            kinds: vec![SymbolKind::STRUCT],
+            path_prefix: Some(PathBuf::from("/app/src")),
+            exclude_globs: vec!["**/tests/**".to_string()],
+            only_files: None,
+            workspace_root: PathBuf::from("/app"),
+        };
+        assert_eq!(filter.server_query(), "Config");
+
+        let response = WorkspaceSymbolResponse::Flat(vec![
+            symbol("UserConfig", SymbolKind::STRUCT, "/app/src/config.rs"),
+            symbol("load_config", SymbolKind::FUNCTION, "/app/src/config.rs"),
+            symbol("ConfigLoader", SymbolKind::STRUCT, "/app/src/loader.rs"),
+            symbol("TestConfig", SymbolKind::STRUCT, "/app/src/tests/util.rs"),
+            symbol("DepConfig", SymbolKind::STRUCT, "/deps/lib.rs"),
+        ]);
+        let (response, filtered) = filter.apply(response);
+        let WorkspaceSymbolResponse::Flat(kept) = response else {
+            unreachable!()
+        };
+        let names: Vec<&str> = kept.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["UserConfig"]);
+        assert_eq!(filtered, 4);
+
+        let exact = SymbolFilter {
+            query: "Foo".to_string(),
+            name_match: NameMatch::Exact,
+            ..SymbolFilter::default()
+        };
+        let uri = Url::from_file_path("/a.ts").unwrap();
+        assert!(exact.matches("Foo<T>", SymbolKind::CLASS, &uri));
+        assert!(!exact.matches("FooBar", SymbolKind::CLASS, &uri));
+
+        let changed = SymbolFilter {
+            only_files: Some(HashSet::from([PathBuf::from("/a.ts")])),
+            ..SymbolFilter::default()
+        };
+        assert!(changed.matches("Foo", SymbolKind::CLASS, &uri));
+        let other = Url::from_file_path("/b.ts").unwrap();
+        assert!(!changed.matches("Foo", SymbolKind::CLASS, &other));
+    }
+}
@@ -0,0 +1,111 @@
+//! Unused symbol report
+//!
+//! `lsp_find_unused` looks for dead code: it lists the symbols of a
+//! directory's files (or the workspace symbols matching a query), asks the
+//! language server for each one's references, and reports those referenced
+//! nowhere but their own declaration. Servers only see the workspace, so
+//! exported items, trait implementations and entry points called from
+//! elsewhere are easy false positives; exported items are skipped unless
+//! asked for, and names can be excluded by pattern.
+
+use lsp_types::{Location, SymbolKind};
+
+/// Symbol kinds checked unless asked otherwise
+pub const DEFAULT_UNUSED_KINDS: [SymbolKind; 7] = [
+    SymbolKind::FUNCTION,
+    SymbolKind::METHOD,
+    SymbolKind::CLASS,
+    SymbolKind::STRUCT,
+    SymbolKind::ENUM,
+    SymbolKind::INTERFACE,
+    SymbolKind::CONSTANT,
+];
+
+/// Symbols whose references are checked unless asked otherwise
+pub const DEFAULT_MAX_SYMBOLS: usize = 300;
+
+/// Whether `name`, declared on `line`, is visible outside its module by
+/// `language`'s conventions
+///
+/// Languages without a known convention count everything as internal.
+pub fn is_exported(language: &str, name: &str, line: &str) -> bool {
+    let line = line.trim_start();
+    match language {
+        // `pub(crate)` and `pub(super)` stay inside the crate
+        "rust" => line.starts_with("pub "),
+        "go" => name.starts_with(|c: char| c.is_uppercase()),
+        "typescript" | "javascript" => line.starts_with("export "),
+        "python" => !name.starts_with('_'),
+        "java" | "csharp" | "scala" => line.contains("public "),
+        _ => false,
+    }
+}
+
+/// Whether the runtime calls `name` itself: `main`, Go's `init`, Python's
+/// `__dunder__` methods
+pub fn is_entry_point(name: &str) -> bool {
+    matches!(name, "main" | "init")
+        || (name.len() > 4 && name.starts_with("__") && name.ends_with("__"))
+}
+
+/// The name as written in source, without the signature or generics some
+/// servers append (`foo(int)`, `Foo<T>`)
+pub fn bare_name(name: &str) -> &str {
+    name.split(['(', '<']).next().unwrap_or(name).trim()
+}
+
+/// How many of `references` are somewhere other than `declaration`
+///
+/// Some servers include the declaration even when asked not to.
+pub fn other_references(references: &[Location], declaration: &Location) -> usize {
+    let position = declaration.range.start;
+    references
+        .iter()
+        .filter(|reference| {
+            reference.uri != declaration.uri
+                || !(reference.range.start <= position && position <= reference.range.end)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range, Url};
+
+    #[test]
+    fn test_is_exported() {
+        assert!(is_exported("rust", "parse", "    pub fn parse() {"));
+        assert!(!is_exported("rust", "parse", "pub(crate) fn parse() {"));
+        assert!(!is_exported("rust", "parse", "fn parse() {"));
+        assert!(is_exported("go", "Parse", "func Parse() {"));
+        assert!(!is_exported("go", "parse", "func parse() {"));
+        assert!(is_exported(
+            "typescript",
+            "parse",
+            "export function parse() {"
+        ));
+        assert!(!is_exported("python", "_parse", "def _parse():"));
+        assert!(!is_exported("zig", "parse", "pub fn parse() void {"));
+        assert!(is_entry_point("__init__") && is_entry_point("main"));
+        assert!(!is_entry_point("__"));
+        assert_eq!(bare_name("parse(String, int)"), "parse");
+    }
+
+    #[test]
+    fn test_other_references() {
+        let uri = Url::parse("file:///src/lib.rs").unwrap();
+        let at = |line: u32, start: u32, end: u32| {
+            Location::new(
+                uri.clone(),
+                Range::new(Position::new(line, start), Position::new(line, end)),
+            )
+        };
+        let declaration = at(3, 7, 7);
+        assert_eq!(other_references(&[at(3, 7, 12)], &declaration), 0);
+        assert_eq!(
+            other_references(&[at(3, 7, 12), at(9, 4, 9)], &declaration),
+            1
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Workspace language detection for `lsp_workspace_info`
+//!
+//! Walks the workspace once (skipping ignored files, like the listing
+//! tools) and counts files per language, with the servers configured for
+//! each and the project roots found, so agents know what can be queried
+//! before picking files.
+
+use crate::config::ConfigLoader;
+use crate::lsp::project::PROJECT_MARKERS;
+use crate::tools::files::walk_files;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Languages and projects found in a workspace
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceScan {
+    pub root: PathBuf,
+    /// Files looked at
+    pub files: usize,
+    /// Files no configured server handles
+    pub unrecognized: usize,
+    /// Whether the tree was too big to walk completely
+    pub truncated: bool,
+    /// Most files first
+    pub languages: Vec<LanguageFiles>,
+    /// Sorted by path
+    pub projects: Vec<Project>,
+}
+
+/// Files of one language
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageFiles {
+    pub language: String,
+    pub files: usize,
+    /// Servers for the language, highest priority first
+    pub servers: Vec<String>,
+    /// The most common extensions, most files first
+    pub extensions: Vec<String>,
+}
+
+/// A directory with a project marker (`Cargo.toml`, `package.json`...)
+#[derive(Debug, Clone, Serialize)]
+pub struct Project {
+    /// Relative to the workspace root (`.` for the root itself)
+    pub path: PathBuf,
+    pub markers: Vec<String>,
+}
+
+/// Whether a server can answer queries yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerAvailability {
+    Running,
+    /// Found, and started on the first query
+    Installed,
+    /// Run elsewhere (over TCP, SSH or in a container)
+    Remote,
+    NotInstalled,
+}
+
+impl ServerAvailability {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Installed => "installed",
+            Self::Remote => "remote",
+            Self::NotInstalled => "not installed",
+        }
+    }
+}
+
+/// Extensions listed per language
+const MAX_EXTENSIONS: usize = 5;
+
+/// Detect the languages and projects under `root`
+pub fn scan(root: &Path, config: &ConfigLoader) -> WorkspaceScan {
+    struct Counts {
+        files: usize,
+        servers: Vec<String>,
+        extensions: HashMap<String, usize>,
+    }
+
+    let mut languages: HashMap<String, Counts> = HashMap::new();
+    let mut projects: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    let (mut files, mut unrecognized) = (0, 0);
+
+    let complete = walk_files(root, |path| {
+        files += 1;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if PROJECT_MARKERS.contains(&name.as_ref()) {
+            let dir = path.parent().unwrap_or(root);
+            let relative = match dir.strip_prefix(root) {
+                Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+                Ok(relative) => relative.to_path_buf(),
+                Err(_) => dir.to_path_buf(),
+            };
+            projects.entry(relative).or_default().push(name.to_string());
+        }
+
+        match config.get_lsps_for_file(&path) {
+            Ok((language, packages)) => {
+                let counts = languages.entry(language).or_insert_with(|| Counts {
+                    files: 0,
+                    servers: packages.iter().map(|p| p.name.clone()).collect(),
+                    extensions: HashMap::new(),
+                });
+                counts.files += 1;
+                let extension = match path.extension() {
+                    Some(extension) => format!(".{}", extension.to_string_lossy()),
+                    None => name.to_string(),
+                };
+                *counts.extensions.entry(extension).or_default() += 1;
+            }
+            Err(_) => unrecognized += 1,
+        }
+        true
+    });
+
+    let mut languages: Vec<LanguageFiles> = languages
+        .into_iter()
+        .map(|(language, counts)| {
+            let mut extensions: Vec<(String, usize)> = counts.extensions.into_iter().collect();
+            extensions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            LanguageFiles {
+                language,
+                files: counts.files,
+                servers: counts.servers,
+                extensions: extensions
+                    .into_iter()
+                    .take(MAX_EXTENSIONS)
+                    .map(|(extension, _)| extension)
+                    .collect(),
+            }
+        })
+        .collect();
+    languages.sort_by(|a, b| {
+        b.files
+            .cmp(&a.files)
+            .then_with(|| a.language.cmp(&b.language))
+    });
+
+    WorkspaceScan {
+        root: root.to_path_buf(),
+        files,
+        unrecognized,
+        truncated: !complete,
+        languages,
+        projects: projects
+            .into_iter()
+            .map(|(path, mut markers)| {
+                markers.sort();
+                Project { path, markers }
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_scan() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        for dir in ["src", "web/src", "target/debug"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "Cargo.toml",
+            "src/main.rs",
+            "src/lib.rs",
+            "web/package.json",
+            "web/src/app.ts",
+            "target/debug/build.rs",
+            "notes.unknownext",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+
+        let config = ConfigLoader::with_user_config(None).unwrap();
+        let scan = scan(root, &config);
+
+        let rust = &scan.languages[0];
+        assert_eq!(rust.language, "rust");
+        assert_eq!(rust.files, 2);
+        assert_eq!(rust.servers, ["rust-analyzer"]);
+        assert_eq!(rust.extensions, [".rs"]);
+        assert!(scan
+            .languages
+            .iter()
+            .any(|l| l.language == "typescript" && l.files == 1));
+        assert!(scan.unrecognized >= 1);
+        assert!(!scan.truncated);
+
+        let projects: Vec<(&str, &[String])> = scan
+            .projects
+            .iter()
+            .map(|p| (p.path.to_str().unwrap(), p.markers.as_slice()))
+            .collect();
+        assert_eq!(
+            projects,
+            [
+                (".", ["Cargo.toml".to_string()].as_slice()),
+                ("web", ["package.json".to_string()].as_slice()),
+            ]
+        );
+    }
+}
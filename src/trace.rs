@@ -0,0 +1,183 @@
+//! Request tracing
+//!
+//! Every MCP request gets a correlation ID that follows it into the
+//! language server requests it triggers: it is set on the request's tracing
+//! span, kept in server errors and added to failed tool results, so a
+//! complaint can be matched to its log lines. With `--trace-file`, every MCP
+//! and LSP message is also written to a JSONL file, tagged with the ID where
+//! one applies, for reproducible bug reports.
+
+use crate::types::LspError;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+tokio::task_local! {
+    /// Correlation ID of the MCP request being handled
+    static CORRELATION_ID: String;
+}
+
+/// A fresh correlation ID, unique within this process
+pub fn next_correlation_id() -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    format!("r{}", NEXT_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Run `f` as part of the request `id`
+pub async fn with_correlation_id<F: Future>(id: String, f: F) -> F::Output {
+    CORRELATION_ID.scope(id, f).await
+}
+
+/// `f`, kept part of the current request (if any) when spawned as a task
+pub fn inherit<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    let id = correlation_id();
+    async move {
+        match id {
+            Some(id) => CORRELATION_ID.scope(id, f).await,
+            None => f.await,
+        }
+    }
+}
+
+/// Correlation ID of the request being handled, if any
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
+
+/// Which way a traced message went, from lsmcp's side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One line of the trace file
+#[derive(Debug, Serialize)]
+struct TraceEntry<'a> {
+    /// Seconds since the Unix epoch
+    ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+    /// `mcp`, or the language server's name
+    channel: &'a str,
+    direction: Direction,
+    message: Value,
+}
+
+struct TraceFile {
+    file: Mutex<std::fs::File>,
+    /// Correlation IDs of requests sent to servers, by (server, request id),
+    /// until their responses arrive
+    requests: Mutex<HashMap<(String, u64), String>>,
+}
+
+static TRACE: OnceLock<TraceFile> = OnceLock::new();
+
+/// Write every message from now on to `path`, replacing its contents
+pub fn open(path: &Path) -> Result<(), LspError> {
+    let file = std::fs::File::create(path)?;
+    let trace = TraceFile {
+        file: Mutex::new(file),
+        requests: Mutex::default(),
+    };
+    TRACE
+        .set(trace)
+        .map_err(|_| LspError::ConfigError("Trace file already open".to_string()))
+}
+
+/// Trace a message exchanged with the MCP client
+pub fn mcp(direction: Direction, message: &str) {
+    if let Some(trace) = TRACE.get() {
+        trace.write("mcp", direction, correlation_id(), message);
+    }
+}
+
+/// Trace a message exchanged with the language server `server`
+///
+/// Responses are matched to the correlation ID their request was sent
+/// under, since they're read outside the request.
+pub fn lsp(server: &str, direction: Direction, message: &str) {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+    let parsed: Value = serde_json::from_str(message).unwrap_or(Value::Null);
+    let id = parsed.get("id").and_then(Value::as_u64);
+    let is_request = parsed.get("method").is_some();
+    let correlation_id = match (direction, id) {
+        (Direction::Out, Some(id)) if is_request => {
+            let correlation_id = correlation_id();
+            if let (Some(correlation_id), Ok(mut requests)) =
+                (&correlation_id, trace.requests.lock())
+            {
+                requests.insert((server.to_string(), id), correlation_id.clone());
+            }
+            correlation_id
+        }
+        (Direction::In, Some(id)) if !is_request => trace
+            .requests
+            .lock()
+            .ok()
+            .and_then(|mut requests| requests.remove(&(server.to_string(), id))),
+        _ => correlation_id(),
+    };
+    trace.write(server, direction, correlation_id, message);
+}
+
+impl TraceFile {
+    fn write(
+        &self,
+        channel: &str,
+        direction: Direction,
+        correlation_id: Option<String>,
+        message: &str,
+    ) {
+        let entry = TraceEntry {
+            ts: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_secs_f64()),
+            correlation_id,
+            channel,
+            direction,
+            message: serde_json::from_str(message)
+                .unwrap_or_else(|_| Value::String(message.to_string())),
+        };
+        let Ok(mut line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        line.push('\n');
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_correlation_id_scope() {
+        assert_eq!(correlation_id(), None);
+        let id = next_correlation_id();
+        assert_ne!(id, next_correlation_id());
+
+        let seen = with_correlation_id(id.clone(), async { correlation_id() }).await;
+        assert_eq!(seen, Some(id.clone()));
+        assert_eq!(correlation_id(), None);
+
+        let spawned = with_correlation_id(id.clone(), async {
+            tokio::spawn(inherit(async { correlation_id() }))
+                .await
+                .unwrap()
+        })
+        .await;
+        assert_eq!(spawned, Some(id));
+    }
+}
@@ -6,27 +6,187 @@ pub enum LspError {
     #[error("LSP server not found: {0}. Install with: {1}")]
     ServerNotFound(String, String),
 
+    #[error("{server} is not available: `{binary}` {problem}. {hint}")]
+    BinaryNotFound {
+        server: String,
+        binary: String,
+        /// What went wrong, such as the locations searched
+        problem: String,
+        /// What to do about it (see `installer::MissingServer`)
+        hint: String,
+    },
+
+    #[error("Offline mode: {0} is not cached. Install it once with network access or disable offline mode")]
+    Offline(String),
+
     #[error("LSP server crashed: {0}")]
     ServerCrashed(String),
 
+    #[error("{0} failed to start: {1}")]
+    StartupFailed(String, String),
+
+    #[error("Too many requests waiting for {0} to start; try again shortly")]
+    StartupQueueFull(String),
+
+    #[error("Failed to connect to {0}: {1}")]
+    ConnectionFailed(String, String),
+
+    #[error("{0} is not provided by the server(s) for this file: {1}")]
+    ExtensionUnavailable(String, String),
+
+    #[error("{server} does not support {feature}")]
+    Unsupported {
+        /// The servers asked, joined with "or"
+        server: String,
+        feature: String,
+    },
+
     #[error("Request timeout after {0}s")]
     Timeout(u64),
 
+    #[error(
+        "{server} is not answering ({failures} request(s) in a row went unanswered), so its requests fail at once; it is tried again in {retry_in_secs}s"
+    )]
+    CircuitOpen {
+        server: String,
+        failures: u32,
+        retry_in_secs: u64,
+    },
+
+    #[error(
+        "no answer to `initialize` after {secs}s; set `initialize_timeout_secs` in [lsp.{server}] to wait longer"
+    )]
+    InitializeTimeout { server: String, secs: u64 },
+
     #[error("Language not supported: {0}")]
     UnsupportedLanguage(String),
 
     #[error("Invalid file path: {0}")]
     InvalidPath(PathBuf),
 
+    #[error(
+        "Path is outside the workspace: {0}. Add it to `allowed_paths` in the config to allow it"
+    )]
+    PathNotAllowed(PathBuf),
+
+    #[error("Edit not applied: {0}")]
+    EditFailed(String),
+
     #[error("LSP protocol error: {0}")]
     ProtocolError(String),
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
+    #[error("git failed: {0}")]
+    Git(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 }
+
+/// Errors are cloned for every request sharing one in-flight LSP request.
+/// `io::Error` and `serde_json::Error` aren't `Clone`; their copies keep the
+/// kind and message.
+impl Clone for LspError {
+    fn clone(&self) -> Self {
+        match self {
+            Self::ServerNotFound(name, hint) => Self::ServerNotFound(name.clone(), hint.clone()),
+            Self::BinaryNotFound {
+                server,
+                binary,
+                problem,
+                hint,
+            } => Self::BinaryNotFound {
+                server: server.clone(),
+                binary: binary.clone(),
+                problem: problem.clone(),
+                hint: hint.clone(),
+            },
+            Self::Offline(what) => Self::Offline(what.clone()),
+            Self::ServerCrashed(reason) => Self::ServerCrashed(reason.clone()),
+            Self::StartupFailed(server, reason) => {
+                Self::StartupFailed(server.clone(), reason.clone())
+            }
+            Self::StartupQueueFull(server) => Self::StartupQueueFull(server.clone()),
+            Self::ConnectionFailed(address, reason) => {
+                Self::ConnectionFailed(address.clone(), reason.clone())
+            }
+            Self::ExtensionUnavailable(tool, servers) => {
+                Self::ExtensionUnavailable(tool.clone(), servers.clone())
+            }
+            Self::Unsupported { server, feature } => Self::Unsupported {
+                server: server.clone(),
+                feature: feature.clone(),
+            },
+            Self::Timeout(secs) => Self::Timeout(*secs),
+            Self::CircuitOpen {
+                server,
+                failures,
+                retry_in_secs,
+            } => Self::CircuitOpen {
+                server: server.clone(),
+                failures: *failures,
+                retry_in_secs: *retry_in_secs,
+            },
+            Self::InitializeTimeout { server, secs } => Self::InitializeTimeout {
+                server: server.clone(),
+                secs: *secs,
+            },
+            Self::UnsupportedLanguage(language) => Self::UnsupportedLanguage(language.clone()),
+            Self::InvalidPath(path) => Self::InvalidPath(path.clone()),
+            Self::PathNotAllowed(path) => Self::PathNotAllowed(path.clone()),
+            Self::EditFailed(reason) => Self::EditFailed(reason.clone()),
+            Self::ProtocolError(message) => Self::ProtocolError(message.clone()),
+            Self::ConfigError(message) => Self::ConfigError(message.clone()),
+            Self::Git(message) => Self::Git(message.clone()),
+            Self::Io(e) => Self::Io(std::io::Error::new(e.kind(), e.to_string())),
+            Self::Json(e) => Self::Json(serde::de::Error::custom(e.to_string())),
+        }
+    }
+}
+
+impl LspError {
+    /// Short machine-readable name for the kind of error, for clients that
+    /// act on failures (e.g. `timeout`, `not_installed`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ServerNotFound(..) | Self::BinaryNotFound { .. } => "not_installed",
+            Self::Offline(_) => "offline",
+            Self::ServerCrashed(_) => "server_crashed",
+            Self::StartupFailed(..) => "startup_failed",
+            Self::StartupQueueFull(_) => "busy",
+            Self::ConnectionFailed(..) => "connection_failed",
+            Self::ExtensionUnavailable(..) | Self::Unsupported { .. } => "unsupported",
+            Self::Timeout(_) | Self::InitializeTimeout { .. } => "timeout",
+            Self::CircuitOpen { .. } => "server_unresponsive",
+            Self::UnsupportedLanguage(_) => "unsupported_language",
+            Self::InvalidPath(_) => "invalid_path",
+            Self::PathNotAllowed(_) => "path_not_allowed",
+            Self::EditFailed(_) => "edit_failed",
+            Self::ProtocolError(_) => "protocol_error",
+            Self::ConfigError(_) => "config_error",
+            Self::Git(_) => "git_error",
+            Self::Io(_) => "io_error",
+            Self::Json(_) => "json_error",
+        }
+    }
+
+    /// Whether the same call may succeed if tried again shortly, without
+    /// changing anything
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::ServerCrashed(_)
+                | Self::StartupFailed(..)
+                | Self::StartupQueueFull(_)
+                | Self::ConnectionFailed(..)
+                | Self::Timeout(_)
+                | Self::InitializeTimeout { .. }
+                | Self::CircuitOpen { .. }
+        )
+    }
+}
@@ -0,0 +1,399 @@
+//! Tool usage
+//!
+//! Which tools an agent calls, and how much of its context window their
+//! answers take. Every answer is measured in characters before and after
+//! the response budget (see `mcp::budget`) cuts it, and estimated in tokens
+//! at four characters each. `lsp_usage_stats` reports this session's usage
+//! and all usage recorded; unless `usage_stats = false`, the counts are
+//! added to `usage.json` in the data directory every minute and on exit,
+//! which `lsmcp stats` reports from as well. Tools cut to the budget often
+//! or never called at all show which settings are worth tuning.
+
+use crate::installer::ServerInstaller;
+use crate::mcp::budget::CHARS_PER_TOKEN;
+use crate::types::LspError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// How often the counts are added to the usage file
+const USAGE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Share of a tool's calls cut to the budget above which it is pointed out
+const OFTEN_TRUNCATED: f64 = 0.25;
+
+/// Calls and answer sizes of one tool
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolUsage {
+    pub calls: u64,
+    pub errors: u64,
+    /// Characters answered, after cutting to the response budget
+    pub chars: u64,
+    /// Characters the answers had before cutting
+    pub full_chars: u64,
+    /// Answers cut to the response budget
+    pub truncated: u64,
+    /// The longest answer, in characters
+    pub max_chars: u64,
+}
+
+impl ToolUsage {
+    fn add(&mut self, other: &ToolUsage) {
+        self.calls += other.calls;
+        self.errors += other.errors;
+        self.chars += other.chars;
+        self.full_chars += other.full_chars;
+        self.truncated += other.truncated;
+        self.max_chars = self.max_chars.max(other.max_chars);
+    }
+
+    /// Estimated tokens of every answer
+    pub fn tokens(&self) -> u64 {
+        tokens(self.chars)
+    }
+
+    /// Estimated tokens of an answer, on average
+    pub fn average_tokens(&self) -> u64 {
+        match self.calls {
+            0 => 0,
+            calls => tokens(self.chars / calls),
+        }
+    }
+}
+
+/// Estimated tokens of `chars` characters
+pub fn tokens(chars: u64) -> u64 {
+    chars.div_ceil(CHARS_PER_TOKEN as u64)
+}
+
+/// Tool usage over some time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageReport {
+    /// When recording began, in seconds since the Unix epoch
+    pub since: u64,
+    pub tools: BTreeMap<String, ToolUsage>,
+}
+
+impl UsageReport {
+    fn started_now() -> Self {
+        Self {
+            since: now(),
+            tools: BTreeMap::new(),
+        }
+    }
+
+    /// Add `other`'s counts to these
+    pub fn merge(&mut self, other: &UsageReport) {
+        if self.since == 0 || (other.since != 0 && other.since < self.since) {
+            self.since = other.since;
+        }
+        for (tool, usage) in &other.tools {
+            self.tools.entry(tool.clone()).or_default().add(usage);
+        }
+    }
+
+    /// Estimated tokens of every answer
+    pub fn tokens(&self) -> u64 {
+        self.tools.values().map(ToolUsage::tokens).sum()
+    }
+
+    /// A readable report, most tokens first, ending with the tools cut to
+    /// the response budget often and the tools of `offered` never called
+    pub fn render(&self, title: &str, offered: &[String]) -> String {
+        let calls: u64 = self.tools.values().map(|usage| usage.calls).sum();
+        let mut text = format!(
+            "{} ({} ago): {} call(s), ~{} tokens\n",
+            title,
+            format_age(now().saturating_sub(self.since)),
+            calls,
+            format_tokens(self.tokens())
+        );
+        let mut tools: Vec<(&String, &ToolUsage)> = self.tools.iter().collect();
+        tools.sort_by(|(a, x), (b, y)| y.chars.cmp(&x.chars).then(a.cmp(b)));
+        for (tool, usage) in &tools {
+            let _ = write!(text, "  {}: {} call(s)", tool, usage.calls);
+            if usage.errors > 0 {
+                let _ = write!(text, " ({} failed)", usage.errors);
+            }
+            let _ = write!(
+                text,
+                ", ~{} tokens (~{} per call, largest ~{})",
+                format_tokens(usage.tokens()),
+                format_tokens(usage.average_tokens()),
+                format_tokens(tokens(usage.max_chars))
+            );
+            if usage.truncated > 0 {
+                let _ = write!(
+                    text,
+                    "; {} cut to the budget, ~{} tokens before cutting",
+                    usage.truncated,
+                    format_tokens(tokens(usage.full_chars))
+                );
+            }
+            text.push('\n');
+        }
+
+        let often: Vec<String> = tools
+            .iter()
+            .filter(|(_, usage)| usage.truncated as f64 > usage.calls as f64 * OFTEN_TRUNCATED)
+            .map(|(tool, usage)| format!("{} ({} of {})", tool, usage.truncated, usage.calls))
+            .collect();
+        if !often.is_empty() {
+            let _ = writeln!(
+                text,
+                "\nOften cut to the response budget: {}. Narrower queries or a larger `max_response_chars` would show more",
+                often.join(", ")
+            );
+        }
+        let unused: Vec<&str> = offered
+            .iter()
+            .filter(|tool| !self.tools.contains_key(*tool))
+            .map(String::as_str)
+            .collect();
+        if !unused.is_empty() {
+            let _ = writeln!(text, "\nNever called: {}", unused.join(", "));
+        }
+        text
+    }
+}
+
+/// Usage recorded by this process
+#[derive(Debug, Default)]
+pub struct Usage {
+    session: Mutex<Option<UsageReport>>,
+    /// Counts not yet added to the usage file
+    unsaved: Mutex<Option<UsageReport>>,
+    /// The usage file, once persistence has started
+    file: OnceLock<PathBuf>,
+}
+
+/// The process-wide usage
+pub fn global() -> &'static Usage {
+    static USAGE: OnceLock<Usage> = OnceLock::new();
+    USAGE.get_or_init(Usage::default)
+}
+
+impl Usage {
+    /// Record a call to `tool` whose answer had `full_chars` characters,
+    /// and `chars` after the response budget, which `truncated` it or not
+    pub fn record(
+        &self,
+        tool: &str,
+        full_chars: usize,
+        chars: usize,
+        truncated: bool,
+        error: bool,
+    ) {
+        let call = ToolUsage {
+            calls: 1,
+            errors: u64::from(error),
+            chars: chars as u64,
+            full_chars: full_chars as u64,
+            truncated: u64::from(truncated),
+            max_chars: chars as u64,
+        };
+        for report in [&self.session, &self.unsaved] {
+            if let Ok(mut report) = report.lock() {
+                report
+                    .get_or_insert_with(UsageReport::started_now)
+                    .tools
+                    .entry(tool.to_string())
+                    .or_default()
+                    .add(&call);
+            }
+        }
+    }
+
+    /// Usage by this process
+    pub fn session(&self) -> UsageReport {
+        self.session
+            .lock()
+            .ok()
+            .and_then(|report| report.clone())
+            .unwrap_or_else(UsageReport::started_now)
+    }
+
+    /// Every use recorded, saved or not, when usage is being saved
+    pub fn recorded(&self) -> Option<UsageReport> {
+        let mut report = load(self.file.get()?);
+        if let Ok(unsaved) = self.unsaved.lock() {
+            if let Some(unsaved) = unsaved.as_ref() {
+                report.merge(unsaved);
+            }
+        }
+        Some(report)
+    }
+
+    /// Add the unsaved counts to the usage file
+    ///
+    /// The file is read again first, so lsmcp processes running side by
+    /// side all add to it.
+    pub fn save(&self) {
+        let Some(path) = self.file.get() else {
+            return;
+        };
+        let Some(unsaved) = self.unsaved.lock().ok().and_then(|mut u| u.take()) else {
+            return;
+        };
+        let mut report = load(path);
+        report.merge(&unsaved);
+        match write(path, &report) {
+            Ok(()) => debug!("Saved tool usage to {}", path.display()),
+            Err(e) => {
+                warn!("Failed to save tool usage to {}: {}", path.display(), e);
+                // Keep the counts for the next try
+                if let Ok(mut pending) = self.unsaved.lock() {
+                    pending
+                        .get_or_insert_with(UsageReport::started_now)
+                        .merge(&unsaved);
+                }
+            }
+        }
+    }
+}
+
+/// Save usage to the data directory every minute from now on
+///
+/// The task holds no state of its own; call `Usage::save` on exit for the
+/// last minute's counts.
+pub fn spawn_persistence() {
+    let path = match ServerInstaller::get_data_dir() {
+        Ok(dir) => usage_path(&dir),
+        Err(e) => {
+            warn!("Not saving tool usage: {}", e);
+            return;
+        }
+    };
+    if global().file.set(path).is_err() {
+        return;
+    }
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(USAGE_SAVE_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            global().save();
+        }
+    });
+}
+
+/// The usage file under `data_dir`
+pub fn usage_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("usage.json")
+}
+
+/// The usage saved at `path`, empty if missing or unreadable
+pub fn load(path: &Path) -> UsageReport {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return UsageReport::default();
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        debug!("Ignoring unreadable usage {}: {}", path.display(), e);
+        UsageReport::default()
+    })
+}
+
+/// Write `report` to `path`, replacing it atomically
+fn write(path: &Path, report: &UsageReport) -> Result<(), LspError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string_pretty(report)?;
+    // Per process, as several may save at once
+    let temp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// `340`, `12.5k`, `1.2M`
+fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..=999 => tokens.to_string(),
+        1_000..=999_999 => format!("{:.1}k", tokens as f64 / 1e3),
+        _ => format!("{:.1}M", tokens as f64 / 1e6),
+    }
+}
+
+/// `45s`, `12m`, `3h`, `5d`
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_report() {
+        let usage = Usage::default();
+        usage.record("lsp_find_references", 80_000, 50_000, true, false);
+        usage.record("lsp_find_references", 400, 400, false, false);
+        usage.record("lsp_hover", 120, 120, false, true);
+
+        let session = usage.session();
+        let references = &session.tools["lsp_find_references"];
+        assert_eq!((references.calls, references.truncated), (2, 1));
+        assert_eq!(references.tokens(), 12_600);
+        assert_eq!(references.max_chars, 50_000);
+        assert_eq!(session.tools["lsp_hover"].errors, 1);
+
+        let offered = vec!["lsp_hover".to_string(), "lsp_rename".to_string()];
+        let text = session.render("Tool usage this session", &offered);
+        assert!(text.contains("3 call(s), ~12.6k tokens"));
+        assert!(text.contains("lsp_find_references: 2 call(s), ~12.6k tokens"));
+        assert!(text.contains("1 cut to the budget, ~20.1k tokens before cutting"));
+        assert!(text.contains("Often cut to the response budget: lsp_find_references (1 of 2)"));
+        assert!(text.ends_with("Never called: lsp_rename\n"));
+    }
+
+    #[test]
+    fn test_save_merges() {
+        let data = tempfile::tempdir().unwrap();
+        let path = usage_path(data.path());
+        let saved = UsageReport {
+            since: 1000,
+            tools: BTreeMap::from([(
+                "lsp_hover".to_string(),
+                ToolUsage {
+                    calls: 4,
+                    chars: 400,
+                    full_chars: 400,
+                    max_chars: 200,
+                    ..ToolUsage::default()
+                },
+            )]),
+        };
+        write(&path, &saved).unwrap();
+
+        let usage = Usage::default();
+        usage.file.set(path.clone()).unwrap();
+        usage.record("lsp_hover", 300, 300, false, false);
+        usage.save();
+        // Nothing left to add twice
+        usage.save();
+
+        let report = load(&path);
+        assert_eq!(report.since, 1000);
+        let hover = &report.tools["lsp_hover"];
+        assert_eq!((hover.calls, hover.chars, hover.max_chars), (5, 700, 300));
+        assert_eq!(usage.recorded(), Some(report));
+    }
+}
@@ -0,0 +1,164 @@
+//! Dependency source detection
+//!
+//! Definitions often resolve into sources the user doesn't own: the cargo
+//! registry, the Go module cache, `node_modules`, Python `site-packages`.
+//! They are read-only and usually outside the workspace, where MCP clients
+//! may refuse to open them, so tools label them and can inline their source.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Where a dependency's sources live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencySource {
+    /// `$CARGO_HOME/registry/src` or `$CARGO_HOME/git/checkouts`
+    CargoRegistry,
+    /// `rustlib/src` in a rustup toolchain
+    RustStd,
+    /// `$GOPATH/pkg/mod`
+    GoModules,
+    NodeModules,
+    /// `site-packages` or `dist-packages`
+    PythonPackages,
+}
+
+impl fmt::Display for DependencySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::CargoRegistry => "cargo registry",
+            Self::RustStd => "Rust standard library",
+            Self::GoModules => "Go module cache",
+            Self::NodeModules => "node_modules",
+            Self::PythonPackages => "Python site-packages",
+        })
+    }
+}
+
+/// The dependency store `path` is in, if any
+pub fn dependency_source(path: &Path) -> Option<DependencySource> {
+    let names: Vec<&str> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    let has_pair = |first: &str, second: &str| {
+        names
+            .windows(2)
+            .any(|pair| pair[0] == first && pair[1] == second)
+    };
+
+    if has_pair("registry", "src") || has_pair("git", "checkouts") {
+        Some(DependencySource::CargoRegistry)
+    } else if has_pair("rustlib", "src") {
+        Some(DependencySource::RustStd)
+    } else if has_pair("pkg", "mod") {
+        Some(DependencySource::GoModules)
+    } else if names.contains(&"node_modules") {
+        Some(DependencySource::NodeModules)
+    } else if names
+        .iter()
+        .any(|name| *name == "site-packages" || *name == "dist-packages")
+    {
+        Some(DependencySource::PythonPackages)
+    } else {
+        None
+    }
+}
+
+/// Directories each kind of dependency store may be found under: the
+/// workspace's own `node_modules` and virtualenv (wherever they link to),
+/// and this machine's toolchain and package manager homes
+pub fn dependency_roots(workspace_root: &Path) -> Vec<(DependencySource, PathBuf)> {
+    let env = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let home = dirs::home_dir();
+    let in_home = |path: &str| home.as_ref().map(|home| home.join(path));
+    let mut roots = Vec::new();
+
+    for root in [env("CARGO_HOME"), in_home(".cargo")].into_iter().flatten() {
+        roots.push((DependencySource::CargoRegistry, root));
+    }
+    for root in [env("RUSTUP_HOME"), in_home(".rustup")]
+        .into_iter()
+        .flatten()
+    {
+        roots.push((DependencySource::RustStd, root));
+    }
+
+    let gopaths = env("GOPATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_else(|| in_home("go").into_iter().collect::<Vec<_>>());
+    for root in env("GOMODCACHE")
+        .into_iter()
+        .chain(gopaths.into_iter().map(|path| path.join("pkg/mod")))
+    {
+        roots.push((DependencySource::GoModules, root));
+    }
+
+    let node = [
+        Some(workspace_root.join("node_modules")),
+        env("NPM_CONFIG_PREFIX"),
+        env("NVM_DIR"),
+        env("VOLTA_HOME"),
+        in_home(".npm-global"),
+        in_home(".nvm"),
+        in_home(".volta"),
+        in_home(".local/share/pnpm"),
+        Some(PathBuf::from("/usr/lib/node_modules")),
+        Some(PathBuf::from("/usr/local/lib/node_modules")),
+        Some(PathBuf::from("/opt/homebrew/lib/node_modules")),
+    ];
+    for root in node.into_iter().flatten() {
+        roots.push((DependencySource::NodeModules, root));
+    }
+
+    let python = [
+        Some(workspace_root.join(".venv")),
+        Some(workspace_root.join("venv")),
+        env("VIRTUAL_ENV"),
+        env("CONDA_PREFIX"),
+        in_home(".local/lib"),
+        in_home(".pyenv"),
+        dirs::cache_dir().map(|cache| cache.join("pypoetry/virtualenvs")),
+        Some(PathBuf::from("/usr/lib")),
+        Some(PathBuf::from("/usr/local/lib")),
+        Some(PathBuf::from("/opt/homebrew/lib")),
+    ];
+    for root in python.into_iter().flatten() {
+        roots.push((DependencySource::PythonPackages, root));
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependency_source() {
+        let source = |path: &str| dependency_source(Path::new(path));
+        assert_eq!(
+            source("/home/me/.cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.200/src/de/mod.rs"),
+            Some(DependencySource::CargoRegistry)
+        );
+        assert_eq!(
+            source("/home/me/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/src/rust/library/core/src/option.rs"),
+            Some(DependencySource::RustStd)
+        );
+        assert_eq!(
+            source("/home/me/go/pkg/mod/github.com/spf13/cobra@v1.8.0/command.go"),
+            Some(DependencySource::GoModules)
+        );
+        assert_eq!(
+            source("/src/app/node_modules/react/index.d.ts"),
+            Some(DependencySource::NodeModules)
+        );
+        assert_eq!(
+            source("/src/app/.venv/lib/python3.12/site-packages/requests/api.py"),
+            Some(DependencySource::PythonPackages)
+        );
+        assert_eq!(source("/src/app/src/registry/mod.rs"), None);
+    }
+}
@@ -0,0 +1,364 @@
+//! Files changed on the current branch, from `git`
+//!
+//! A branch's changes are what differs from the commit it left its base at
+//! (`git merge-base HEAD <base>`): its own commits, uncommitted edits and
+//! untracked files, which is what its pull request will show once they're
+//! committed. Deleted files are left out, as there is nothing to diagnose.
+//! `git` is run as a process, like the language servers, so lsmcp needs no
+//! git library.
+//!
+//! `git blame` says which commit last changed a line, for locations shown
+//! with their author and date when `settings.git_blame` is on.
+
+use crate::types::LspError;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Bases tried in order when none is given: the remote's default branch,
+/// then the usual names for it
+const DEFAULT_BASES: &[&str] = &["origin/main", "origin/master", "main", "master"];
+
+/// The files a branch changed
+#[derive(Debug, Clone)]
+pub struct ChangedFiles {
+    /// The ref compared against, e.g. `origin/main`
+    pub base: String,
+    /// The commit the branch left `base` at
+    pub merge_base: String,
+    /// Absolute paths under the root, sorted
+    pub files: Vec<PathBuf>,
+}
+
+impl ChangedFiles {
+    /// `merge_base` shortened for display
+    pub fn short_merge_base(&self) -> &str {
+        &self.merge_base[..self.merge_base.len().min(12)]
+    }
+}
+
+/// The files under `root` changed since the branch left `base`, or the
+/// repository's default branch when `base` is `None`
+pub async fn changed_files(root: &Path, base: Option<&str>) -> Result<ChangedFiles, LspError> {
+    let base = match base {
+        // git would take it for an option, e.g. `--output=<file>`
+        Some(base) if base.starts_with('-') => {
+            return Err(LspError::Git(format!("'{}' is not a git ref", base)));
+        }
+        Some(base) => base.to_string(),
+        None => default_base(root).await?,
+    };
+    let merge_base = git(root, &["merge-base", "HEAD", &base])
+        .await?
+        .trim()
+        .to_string();
+
+    // Both list paths relative to `root`, and only those under it
+    let changed = git(
+        root,
+        &[
+            "diff",
+            "--name-only",
+            "--relative",
+            "--diff-filter=d",
+            "-z",
+            &merge_base,
+        ],
+    )
+    .await?;
+    let untracked = git(root, &["ls-files", "--others", "--exclude-standard", "-z"]).await?;
+
+    let mut files: Vec<PathBuf> = changed
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(path))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    files.dedup();
+
+    Ok(ChangedFiles {
+        base,
+        merge_base,
+        files,
+    })
+}
+
+/// The branch `origin/HEAD` points at, else the first of `DEFAULT_BASES`
+/// that exists
+async fn default_base(root: &Path) -> Result<String, LspError> {
+    if let Ok(remote_head) = git(
+        root,
+        &[
+            "symbolic-ref",
+            "--quiet",
+            "--short",
+            "refs/remotes/origin/HEAD",
+        ],
+    )
+    .await
+    {
+        return Ok(remote_head.trim().to_string());
+    }
+    for base in DEFAULT_BASES {
+        let commit = format!("{}^{{commit}}", base);
+        if git(root, &["rev-parse", "--verify", "--quiet", &commit])
+            .await
+            .is_ok()
+        {
+            return Ok(base.to_string());
+        }
+    }
+    Err(LspError::Git(format!(
+        "no base branch to compare with (tried {}); pass one as `base`",
+        DEFAULT_BASES.join(", ")
+    )))
+}
+
+/// The last commit to change a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineBlame {
+    /// `None` for changes not committed yet
+    pub commit: Option<String>,
+    pub author: String,
+    /// Author date, in seconds since the epoch
+    pub time: i64,
+}
+
+impl fmt::Display for LineBlame {
+    /// `Jane Doe, 2026-09-30 (a1b2c3d4)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(commit) = &self.commit else {
+            return write!(f, "not committed yet");
+        };
+        let date = chrono::DateTime::from_timestamp(self.time, 0)
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        write!(
+            f,
+            "{}, {} ({})",
+            self.author,
+            date,
+            &commit[..commit.len().min(8)]
+        )
+    }
+}
+
+/// The last commit to change each of `file`'s `lines` (0-based), from one
+/// `git blame` run
+///
+/// Lines past the end of the file on disk, such as those of unsaved edits,
+/// are left out: git refuses the whole run for one of them.
+pub async fn blame_lines(file: &Path, lines: &[u32]) -> Result<HashMap<u32, LineBlame>, LspError> {
+    let text = tokio::fs::read(file).await?;
+    let line_count = text.iter().filter(|&&b| b == b'\n').count()
+        + usize::from(!text.is_empty() && !text.ends_with(b"\n"));
+    let mut lines: Vec<u32> = lines
+        .iter()
+        .copied()
+        .filter(|&line| (line as usize) < line_count)
+        .collect();
+    lines.sort_unstable();
+    lines.dedup();
+    if lines.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let ranges: Vec<String> = lines
+        .iter()
+        .map(|line| format!("-L{},{}", line + 1, line + 1))
+        .collect();
+    let file = file.to_string_lossy();
+    let mut args = vec!["blame", "--porcelain"];
+    args.extend(ranges.iter().map(String::as_str));
+    args.extend(["--", &file]);
+    Ok(parse_blame(&git(dir, &args).await?))
+}
+
+/// `git blame --porcelain` output by 0-based line
+///
+/// Each line starts with a `<commit> <original line> <line>` header; the
+/// commit's author and date follow only the first time it appears.
+fn parse_blame(porcelain: &str) -> HashMap<u32, LineBlame> {
+    let mut authors: HashMap<&str, (&str, i64)> = HashMap::new();
+    let mut headers: Vec<(&str, u32)> = Vec::new();
+    let mut commit = "";
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            continue;
+        }
+        let mut fields = line.split(' ');
+        let first = fields.next().unwrap_or_default();
+        if first.len() >= 40 && first.bytes().all(|b| b.is_ascii_hexdigit()) {
+            commit = first;
+            if let Some(number) = fields.nth(1).and_then(|n| n.parse::<u32>().ok()) {
+                headers.push((commit, number.saturating_sub(1)));
+            }
+        } else if let Some(author) = line.strip_prefix("author ") {
+            authors.entry(commit).or_default().0 = author;
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            authors.entry(commit).or_default().1 = time.parse().unwrap_or_default();
+        }
+    }
+
+    headers
+        .into_iter()
+        .map(|(commit, line)| {
+            let (author, time) = authors.get(commit).copied().unwrap_or_default();
+            let committed = commit.bytes().any(|b| b != b'0');
+            let blame = LineBlame {
+                commit: committed.then(|| commit.to_string()),
+                author: author.to_string(),
+                time,
+            };
+            (line, blame)
+        })
+        .collect()
+}
+
+/// What `git args` prints in `root`, or what it complained about
+async fn git(root: &Path, args: &[&str]) -> Result<String, LspError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| LspError::Git(format!("couldn't run git: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(LspError::Git(format!(
+            "`git {}`: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run(root: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-c",
+                "user.name=lsmcp",
+                "-c",
+                "user.email=lsmcp@example.com",
+            ])
+            .arg("-C")
+            .arg(root)
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_parse_blame() {
+        let porcelain = "\
+3f4e1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b 10 12 1
+author Jane Doe
+author-mail <jane@example.com>
+author-time 1790000000
+author-tz +0000
+summary Fix the parser
+filename src/lib.rs
+\tfn parse() {}
+0000000000000000000000000000000000000000 20 20 1
+author Not Committed Yet
+author-time 1800000000
+filename src/lib.rs
+\tlet x = 1;
+3f4e1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b 30 31
+filename src/lib.rs
+\t}
+";
+        let blame = parse_blame(porcelain);
+        assert_eq!(blame.len(), 3);
+        assert_eq!(blame[&11].author, "Jane Doe");
+        assert_eq!(blame[&11].to_string(), "Jane Doe, 2026-09-21 (3f4e1a2b)");
+        // A commit seen before has no author lines of its own
+        assert_eq!(blame[&30], blame[&11]);
+        assert_eq!(blame[&19].commit, None);
+        assert_eq!(blame[&19].to_string(), "not committed yet");
+    }
+
+    #[tokio::test]
+    async fn test_changed_files() {
+        if std::process::Command::new("git")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        for name in ["src/main.rs", "src/lib.rs", "src/old.rs", "README.md"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        run(root, &["init", "--quiet"]);
+        run(root, &["checkout", "--quiet", "-b", "main"]);
+        run(root, &["add", "."]);
+        run(root, &["commit", "--quiet", "-m", "base"]);
+
+        run(root, &["checkout", "--quiet", "-b", "feature"]);
+        fs::write(root.join("src/lib.rs"), "pub fn f() {}\n").unwrap();
+        fs::remove_file(root.join("src/old.rs")).unwrap();
+        run(root, &["commit", "--quiet", "-am", "change"]);
+        // Uncommitted, untracked and ignored
+        fs::write(root.join("README.md"), "# app\n").unwrap();
+        fs::write(root.join("src/new.rs"), "").unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+
+        let changed = changed_files(root, None).await.unwrap();
+        assert_eq!(changed.base, "main");
+        assert_eq!(changed.merge_base.len(), 40);
+        let names: Vec<_> = changed
+            .files
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            names,
+            ["README.md", "src/lib.rs", "src/new.rs"].map(PathBuf::from)
+        );
+
+        // From a subdirectory, only the files under it
+        let changed = changed_files(&root.join("src"), Some("main"))
+            .await
+            .unwrap();
+        assert_eq!(
+            changed.files,
+            [root.join("src/lib.rs"), root.join("src/new.rs")]
+        );
+
+        let error = changed_files(root, Some("no-such-branch"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "git_error");
+        let error = changed_files(root, Some("--output=changes.diff"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), "git_error");
+
+        let blame = blame_lines(&root.join("src/lib.rs"), &[0]).await.unwrap();
+        assert_eq!(blame[&0].author, "lsmcp");
+        assert!(blame[&0].commit.is_some());
+        // Past the end of the file
+        let blame = blame_lines(&root.join("README.md"), &[0, 5]).await.unwrap();
+        assert_eq!(blame[&0].commit, None);
+        assert_eq!(blame.len(), 1);
+    }
+}
@@ -0,0 +1,95 @@
+//! Minimal glob matching
+
+/// Match `text` against a pattern where `*` matches any run of characters
+/// and `?` matches exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    match pattern_chars.next() {
+        None => text.is_empty(),
+        Some('*') => {
+            let rest = pattern_chars.as_str();
+            (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(p) => {
+            let mut text_chars = text.chars();
+            match text_chars.next() {
+                Some(t) if p == '?' || p == t => {
+                    glob_match(pattern_chars.as_str(), text_chars.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Match a `/`-separated path against a pattern whose `**` segments match
+/// any number of directories; `*` and `?` stay within one segment
+pub fn path_glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match_segments(&pattern, &path)
+}
+
+/// The alternatives of a pattern with `{a,b}` groups (`**/*.{ts,js}` is
+/// `**/*.ts` and `**/*.js`), as LSP file watchers use them
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|i| open + i) else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    pattern[open + 1..close]
+        .split(',')
+        .flat_map(|choice| expand_braces(&format!("{}{}{}", prefix, choice, suffix)))
+        .collect()
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        Some((segment, rest)) => path
+            .split_first()
+            .is_some_and(|(name, path)| glob_match(segment, name) && match_segments(rest, path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("clangd-*.zip", "clangd-linux-1.zip"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("clangd-*.zip", "clangd-linux-1.tar.gz"));
+        assert!(glob_match("Dockerfile.*", "Dockerfile.dev"));
+        assert!(!glob_match("Dockerfile.*", "Dockerfile"));
+        assert!(glob_match("?akefile", "Makefile"));
+        assert!(!glob_match("?akefile", "akefile"));
+    }
+
+    #[test]
+    fn test_path_glob_match() {
+        assert!(path_glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(path_glob_match("src/**/*.rs", "src/lsp/languages/rust.rs"));
+        assert!(!path_glob_match("src/*.rs", "src/lsp/client.rs"));
+        assert!(path_glob_match("**/target", "crates/core/target"));
+        assert!(path_glob_match("**", "anything/at/all"));
+        assert!(!path_glob_match("*.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("**/*.go"), ["**/*.go"]);
+        assert_eq!(expand_braces("**/go.{mod,sum}"), ["**/go.mod", "**/go.sum"]);
+        assert_eq!(
+            expand_braces("{src,lib}/*.{ts,js}"),
+            ["src/*.ts", "src/*.js", "lib/*.ts", "lib/*.js"]
+        );
+    }
+}
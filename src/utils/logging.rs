@@ -1 +1,210 @@
 //! Logging utilities
+//!
+//! The log file is rotated so long daemon sessions don't grow it without
+//! bound: by size (the default), by the hour or day, or not at all. Levels
+//! can be set per module with directives like `lsp::client=debug`, which
+//! name lsmcp's modules without the crate prefix.
+
+use crate::config::{LogRotation, Settings};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Size a log file may reach before it is rotated, unless configured
+pub const DEFAULT_LOG_MAX_SIZE_MB: u64 = 10;
+
+/// Rotated log files kept, unless configured
+pub const DEFAULT_LOG_MAX_FILES: usize = 5;
+
+/// lsmcp's top-level modules, which directives may name without `lsmcp::`
+const MODULES: [&str; 11] = [
+    "commands",
+    "config",
+    "installer",
+    "lsp",
+    "mcp",
+    "metrics",
+    "registry",
+    "tools",
+    "trace",
+    "types",
+    "utils",
+];
+
+/// The directives of a comma-separated filter like `lsp::client=debug,
+/// mcp=info`, with lsmcp's module paths made absolute
+///
+/// Other targets (`hyper=warn`) and bare levels are kept as written.
+pub fn module_directives(filter: &str) -> Vec<String> {
+    filter
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| {
+            let target = directive.split(['=', '[']).next().unwrap_or_default();
+            let module = target.split("::").next().unwrap_or_default();
+            if (target == directive && is_level(directive)) || !MODULES.contains(&module) {
+                directive.to_string()
+            } else {
+                format!("lsmcp::{}", directive)
+            }
+        })
+        .collect()
+}
+
+fn is_level(word: &str) -> bool {
+    matches!(
+        word.to_lowercase().as_str(),
+        "trace" | "debug" | "info" | "warn" | "error" | "off"
+    )
+}
+
+/// The writer for the log file at `path`, rotated as `settings` ask
+pub fn log_file_writer(path: &Path, settings: &Settings) -> io::Result<Box<dyn Write + Send>> {
+    let max_files = settings.log_max_files.unwrap_or(DEFAULT_LOG_MAX_FILES);
+    let rotation = match settings.log_rotation.unwrap_or_default() {
+        LogRotation::Never => return Ok(Box::new(File::create(path)?)),
+        LogRotation::Size => {
+            let max_bytes = settings
+                .log_max_size_mb
+                .unwrap_or(DEFAULT_LOG_MAX_SIZE_MB)
+                .max(1)
+                * 1024
+                * 1024;
+            return Ok(Box::new(SizeRollingFile::open(path, max_bytes, max_files)?));
+        }
+        LogRotation::Hourly => tracing_appender::rolling::Rotation::HOURLY,
+        LogRotation::Daily => tracing_appender::rolling::Rotation::DAILY,
+    };
+
+    // Files are named `<name>.<date>` next to where `path` would be
+    let directory = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .map_or("lsmcp.log".into(), |name| name.to_string_lossy());
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(rotation)
+        .filename_prefix(name)
+        .max_log_files(max_files.max(1))
+        .build(directory)
+        .map_err(io::Error::other)?;
+    Ok(Box::new(appender))
+}
+
+/// A log file moved to `<path>.1` (and older ones to `.2`, `.3`...) when it
+/// would grow past a size
+///
+/// The previous run's log is rotated on open, so it's kept too.
+pub struct SizeRollingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    /// Rotated files kept besides the current one
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRollingFile {
+    pub fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if path.exists() {
+            shift(path, max_files);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            max_files,
+            file: File::create(path)?,
+            written: 0,
+        })
+    }
+
+    /// Move the full file aside and start an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        shift(&self.path, self.max_files);
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// Move `path` to `<path>.1`, shifting older files up and dropping those
+/// past `max_files`
+fn shift(path: &Path, max_files: usize) {
+    if max_files == 0 {
+        return;
+    }
+    let _ = std::fs::remove_file(rotated(path, max_files));
+    for n in (1..max_files).rev() {
+        let _ = std::fs::rename(rotated(path, n), rotated(path, n + 1));
+    }
+    let _ = std::fs::rename(path, rotated(path, 1));
+}
+
+/// `path` with `.n` appended (`path` itself for 0)
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_directives() {
+        assert_eq!(
+            module_directives("lsp::client=debug, mcp=info,,hyper=warn,debug"),
+            [
+                "lsmcp::lsp::client=debug",
+                "lsmcp::mcp=info",
+                "hyper=warn",
+                "debug"
+            ]
+        );
+        assert_eq!(
+            module_directives("lsmcp::tools=trace,lsp[span]=debug"),
+            ["lsmcp::tools=trace", "lsmcp::lsp[span]=debug"]
+        );
+    }
+
+    #[test]
+    fn test_size_rolling_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("lsmcp.log");
+        std::fs::write(&path, "previous run\n").unwrap();
+
+        let mut log = SizeRollingFile::open(&path, 10, 2).unwrap();
+        let read = |n: usize| std::fs::read_to_string(rotated(&path, n)).unwrap_or_default();
+        assert_eq!(read(1), "previous run\n");
+
+        log.write_all(b"first\n").unwrap();
+        log.write_all(b"second\n").unwrap();
+        log.write_all(b"third\n").unwrap();
+        assert_eq!(read(0), "third\n");
+        assert_eq!(read(1), "second\n");
+        assert_eq!(read(2), "first\n");
+        assert!(!rotated(&path, 3).exists());
+    }
+}
@@ -1,5 +1,12 @@
 //! Utility functions
 
+pub mod dependency;
+pub mod git;
+pub mod glob;
 pub mod logging;
 pub mod position;
+pub mod sandbox;
+pub mod sarif;
+pub mod signals;
 pub mod uri;
+pub mod workspace;
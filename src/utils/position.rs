@@ -1 +1,462 @@
 //! LSP position utilities
+//!
+//! LSP columns count UTF-16 code units unless client and server negotiate
+//! another `positionEncoding`, while callers usually count characters (or
+//! bytes). `PositionMapper` translates positions between the two using the
+//! text of the documents involved.
+
+use lsp_types::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Unit a position's `character` column is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub enum PositionEncoding {
+    /// Bytes of the UTF-8 encoded line
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    /// UTF-16 code units (the LSP default)
+    #[default]
+    #[serde(rename = "utf-16", alias = "utf16")]
+    Utf16,
+    /// Unicode code points, i.e. characters
+    #[serde(rename = "utf-32", alias = "utf32")]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Encoding named by a server's `positionEncoding` capability
+    pub fn from_lsp(kind: &PositionEncodingKind) -> Option<Self> {
+        match kind.as_str() {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn to_lsp(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Width of `c` in this encoding's units
+    fn width(self, c: char) -> u32 {
+        match self {
+            Self::Utf8 => c.len_utf8() as u32,
+            Self::Utf16 => c.len_utf16() as u32,
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Line `line` of `text` without its line terminator
+pub fn line_text(text: &str, line: u32) -> Option<&str> {
+    text.split('\n')
+        .nth(line as usize)
+        .map(|l| l.strip_suffix('\r').unwrap_or(l))
+}
+
+/// Number of lines in `text`, not counting an empty line after a final newline
+pub fn line_count(text: &str) -> usize {
+    text.lines().count()
+}
+
+/// Length of `line` in `encoding` units
+pub fn line_length(line: &str, encoding: PositionEncoding) -> u32 {
+    line.chars().map(|c| encoding.width(c)).sum()
+}
+
+/// `position` with its column clamped to the end of its line
+///
+/// Returns `None` when the line is past the end of `text`. The empty line
+/// after a final newline is allowed, since that is where appended text goes.
+pub fn clamp_position(
+    text: &str,
+    position: Position,
+    encoding: PositionEncoding,
+) -> Option<Position> {
+    let line = line_text(text, position.line)?;
+    Some(Position {
+        line: position.line,
+        character: position.character.min(line_length(line, encoding)),
+    })
+}
+
+/// Convert a column on `line` from one encoding to another
+///
+/// A column inside a multi-unit character snaps to the start of that
+/// character, and a column past the end of the line clamps to its end.
+pub fn convert_character(
+    line: &str,
+    character: u32,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) -> u32 {
+    if from == to {
+        return character;
+    }
+
+    let mut seen = 0;
+    let mut converted = 0;
+    for c in line.chars() {
+        let width = from.width(c);
+        if seen + width > character {
+            break;
+        }
+        seen += width;
+        converted += to.width(c);
+    }
+    converted
+}
+
+/// Translates positions between a caller's encoding and a server's
+///
+/// Document text is taken from what was registered with `add_text` (the
+/// client's tracked documents), falling back to reading the file from disk.
+/// Positions in files that can't be read are left unchanged.
+pub struct PositionMapper {
+    from: PositionEncoding,
+    to: PositionEncoding,
+    texts: HashMap<PathBuf, Option<String>>,
+}
+
+impl PositionMapper {
+    pub fn new(from: PositionEncoding, to: PositionEncoding) -> Self {
+        Self {
+            from,
+            to,
+            texts: HashMap::new(),
+        }
+    }
+
+    /// Whether positions pass through unchanged
+    pub fn is_identity(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Use `text` as the contents of `path`
+    pub fn add_text(&mut self, path: &Path, text: String) {
+        self.texts.insert(path.to_path_buf(), Some(text));
+    }
+
+    pub fn position(&mut self, path: &Path, position: Position) -> Position {
+        if self.is_identity() {
+            return position;
+        }
+
+        let (from, to) = (self.from, self.to);
+        let text = self
+            .texts
+            .entry(path.to_path_buf())
+            .or_insert_with(|| std::fs::read_to_string(path).ok());
+
+        match text.as_deref().and_then(|t| line_text(t, position.line)) {
+            Some(line) => Position {
+                line: position.line,
+                character: convert_character(line, position.character, from, to),
+            },
+            None => position,
+        }
+    }
+
+    pub fn range(&mut self, path: &Path, range: Range) -> Range {
+        Range {
+            start: self.position(path, range.start),
+            end: self.position(path, range.end),
+        }
+    }
+
+    fn uri_range(&mut self, uri: &Url, range: Range) -> Range {
+        match uri.to_file_path() {
+            Ok(path) => self.range(&path, range),
+            Err(_) => range,
+        }
+    }
+
+    pub fn location(&mut self, mut location: Location) -> Location {
+        location.range = self.uri_range(&location.uri, location.range);
+        location
+    }
+
+    pub fn definition(&mut self, response: GotoDefinitionResponse) -> GotoDefinitionResponse {
+        if self.is_identity() {
+            return response;
+        }
+
+        match response {
+            GotoDefinitionResponse::Scalar(location) => {
+                GotoDefinitionResponse::Scalar(self.location(location))
+            }
+            GotoDefinitionResponse::Array(locations) => GotoDefinitionResponse::Array(
+                locations.into_iter().map(|l| self.location(l)).collect(),
+            ),
+            GotoDefinitionResponse::Link(links) => GotoDefinitionResponse::Link(
+                links
+                    .into_iter()
+                    .map(|mut link| {
+                        link.target_range = self.uri_range(&link.target_uri, link.target_range);
+                        link.target_selection_range =
+                            self.uri_range(&link.target_uri, link.target_selection_range);
+                        link
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// A hover's range, which lies in the hovered file
+    pub fn hover(&mut self, path: &Path, mut hover: Hover) -> Hover {
+        hover.range = hover.range.map(|range| self.range(path, range));
+        hover
+    }
+
+    pub fn document_symbols(
+        &mut self,
+        path: &Path,
+        response: DocumentSymbolResponse,
+    ) -> DocumentSymbolResponse {
+        if self.is_identity() {
+            return response;
+        }
+
+        match response {
+            DocumentSymbolResponse::Flat(symbols) => DocumentSymbolResponse::Flat(
+                symbols
+                    .into_iter()
+                    .map(|mut symbol| {
+                        symbol.location = self.location(symbol.location);
+                        symbol
+                    })
+                    .collect(),
+            ),
+            DocumentSymbolResponse::Nested(symbols) => DocumentSymbolResponse::Nested(
+                symbols
+                    .into_iter()
+                    .map(|symbol| self.document_symbol(path, symbol))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn document_symbol(&mut self, path: &Path, mut symbol: DocumentSymbol) -> DocumentSymbol {
+        symbol.range = self.range(path, symbol.range);
+        symbol.selection_range = self.range(path, symbol.selection_range);
+        symbol.children = symbol.children.map(|children| {
+            children
+                .into_iter()
+                .map(|child| self.document_symbol(path, child))
+                .collect()
+        });
+        symbol
+    }
+
+    pub fn workspace_symbols(
+        &mut self,
+        response: WorkspaceSymbolResponse,
+    ) -> WorkspaceSymbolResponse {
+        if self.is_identity() {
+            return response;
+        }
+
+        match response {
+            WorkspaceSymbolResponse::Flat(symbols) => WorkspaceSymbolResponse::Flat(
+                symbols
+                    .into_iter()
+                    .map(|mut symbol| {
+                        symbol.location = self.location(symbol.location);
+                        symbol
+                    })
+                    .collect(),
+            ),
+            WorkspaceSymbolResponse::Nested(symbols) => WorkspaceSymbolResponse::Nested(
+                symbols
+                    .into_iter()
+                    .map(|symbol| self.workspace_symbol(symbol))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn workspace_symbol(&mut self, mut symbol: WorkspaceSymbol) -> WorkspaceSymbol {
+        if let OneOf::Left(location) = symbol.location {
+            symbol.location = OneOf::Left(self.location(location));
+        }
+        symbol
+    }
+
+    /// Diagnostics for `path`, including their related locations
+    pub fn diagnostics(&mut self, path: &Path, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        if self.is_identity() {
+            return diagnostics;
+        }
+
+        diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                diagnostic.range = self.range(path, diagnostic.range);
+                diagnostic.related_information = diagnostic.related_information.map(|related| {
+                    related
+                        .into_iter()
+                        .map(|mut info| {
+                            info.location = self.location(info.location);
+                            info
+                        })
+                        .collect()
+                });
+                diagnostic
+            })
+            .collect()
+    }
+
+    pub fn workspace_edit(&mut self, mut edit: WorkspaceEdit) -> WorkspaceEdit {
+        if self.is_identity() {
+            return edit;
+        }
+
+        edit.changes = edit.changes.map(|changes| {
+            changes
+                .into_iter()
+                .map(|(uri, edits)| {
+                    let edits = edits
+                        .into_iter()
+                        .map(|mut e| {
+                            e.range = self.uri_range(&uri, e.range);
+                            e
+                        })
+                        .collect();
+                    (uri, edits)
+                })
+                .collect()
+        });
+
+        let document_edits = |mapper: &mut Self, mut edit: TextDocumentEdit| {
+            let uri = edit.text_document.uri.clone();
+            for e in &mut edit.edits {
+                match e {
+                    OneOf::Left(e) => e.range = mapper.uri_range(&uri, e.range),
+                    OneOf::Right(e) => {
+                        e.text_edit.range = mapper.uri_range(&uri, e.text_edit.range)
+                    }
+                }
+            }
+            edit
+        };
+        edit.document_changes = edit.document_changes.map(|changes| match changes {
+            DocumentChanges::Edits(edits) => {
+                DocumentChanges::Edits(edits.into_iter().map(|e| document_edits(self, e)).collect())
+            }
+            DocumentChanges::Operations(operations) => DocumentChanges::Operations(
+                operations
+                    .into_iter()
+                    .map(|operation| match operation {
+                        DocumentChangeOperation::Edit(e) => {
+                            DocumentChangeOperation::Edit(document_edits(self, e))
+                        }
+                        other => other,
+                    })
+                    .collect(),
+            ),
+        });
+        edit
+    }
+
+    /// A code action's edit and the diagnostics it fixes
+    pub fn code_action(&mut self, path: &Path, action: CodeActionOrCommand) -> CodeActionOrCommand {
+        match action {
+            CodeActionOrCommand::CodeAction(mut action) => {
+                action.edit = action.edit.map(|edit| self.workspace_edit(edit));
+                action.diagnostics = action
+                    .diagnostics
+                    .map(|diagnostics| self.diagnostics(path, diagnostics));
+                CodeActionOrCommand::CodeAction(action)
+            }
+            command => command,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PositionEncoding::*;
+
+    #[test]
+    fn test_convert_character() {
+        // 'é' is 2 UTF-8 bytes, '😀' is 4 bytes and 2 UTF-16 units
+        let line = "let é = \"😀\"; x";
+        let x_chars = line.chars().position(|c| c == 'x').unwrap() as u32;
+
+        assert_eq!(convert_character(line, x_chars, Utf32, Utf16), x_chars + 1);
+        assert_eq!(convert_character(line, x_chars, Utf32, Utf8), x_chars + 4);
+        assert_eq!(convert_character(line, x_chars + 4, Utf8, Utf32), x_chars);
+        assert_eq!(
+            convert_character(line, x_chars + 1, Utf16, Utf8),
+            x_chars + 4
+        );
+
+        // Inside the emoji's surrogate pair: snap to its start
+        let emoji = line.chars().position(|c| c == '😀').unwrap() as u32;
+        assert_eq!(convert_character(line, emoji + 1, Utf16, Utf32), emoji);
+
+        // Past the end of the line: clamp
+        assert_eq!(convert_character("ab", 10, Utf32, Utf16), 2);
+        assert_eq!(convert_character("ab", 10, Utf16, Utf16), 10);
+    }
+
+    #[test]
+    fn test_clamp_position() {
+        let text = "fn é() {}\nx\n";
+        assert_eq!(line_count(text), 2);
+        assert_eq!(
+            clamp_position(text, Position::new(0, 40), Utf8),
+            Some(Position::new(0, 10))
+        );
+        assert_eq!(
+            clamp_position(text, Position::new(0, 40), Utf32),
+            Some(Position::new(0, 9))
+        );
+        assert_eq!(
+            clamp_position(text, Position::new(2, 3), Utf32),
+            Some(Position::new(2, 0))
+        );
+        assert_eq!(clamp_position(text, Position::new(3, 0), Utf32), None);
+    }
+
+    #[test]
+    fn test_mapper_uses_registered_text() {
+        let path = Path::new("/nonexistent/lib.rs");
+        let mut mapper = PositionMapper::new(Utf16, Utf32);
+        mapper.add_text(path, "fn main() {}\r\n// 😀 hi\n".to_string());
+
+        assert_eq!(
+            mapper.position(path, Position::new(1, 6)),
+            Position::new(1, 5)
+        );
+        // Unknown lines and unreadable files pass through
+        assert_eq!(
+            mapper.position(path, Position::new(7, 6)),
+            Position::new(7, 6)
+        );
+        assert_eq!(
+            mapper.position(Path::new("/nonexistent/other.rs"), Position::new(0, 3)),
+            Position::new(0, 3)
+        );
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        let parse = |s: &str| serde_json::from_value::<PositionEncoding>(serde_json::json!(s));
+        assert_eq!(parse("utf-8").unwrap(), Utf8);
+        assert_eq!(parse("utf16").unwrap(), Utf16);
+        assert!(parse("latin-1").is_err());
+        assert_eq!(
+            PositionEncoding::from_lsp(&PositionEncodingKind::UTF32),
+            Some(Utf32)
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Workspace path allowlisting
+//!
+//! Tools only read and query files under the workspace root and the
+//! directories in `allowed_paths`, so a client driven by an untrusted prompt
+//! can't point them at `~/.ssh` or `/etc`. Paths are canonicalized first, so
+//! `..` components and symlinks can't escape. Dependency sources (the cargo
+//! registry, `node_modules`...) are allowed too unless
+//! `allow_dependency_paths` is off, since definitions lead there, but only
+//! under the workspace's dependency directories and the toolchain and
+//! package manager homes: a `node_modules` anywhere else is no exception.
+
+use crate::config::Settings;
+use crate::types::LspError;
+use crate::utils::dependency::{dependency_roots, dependency_source, DependencySource};
+use std::path::{Component, Path, PathBuf};
+
+/// Where tools may look
+#[derive(Debug, Clone)]
+pub struct PathPolicy {
+    /// Canonical directories everything under is allowed
+    roots: Vec<PathBuf>,
+    /// Canonical directories dependency sources of each kind are allowed
+    /// under (none when `allow_dependency_paths` is off)
+    dependency_roots: Vec<(DependencySource, PathBuf)>,
+}
+
+impl PathPolicy {
+    /// The policy `settings` ask for, or `None` when `restrict_paths` is off
+    pub fn new(workspace_root: &Path, settings: &Settings) -> Option<Self> {
+        if settings.restrict_paths == Some(false) {
+            return None;
+        }
+        let roots = std::iter::once(workspace_root.to_path_buf())
+            .chain(
+                settings
+                    .allowed_paths
+                    .iter()
+                    .flatten()
+                    .map(|path| expand_home(path)),
+            )
+            .map(|root| canonical(&root))
+            .collect();
+        let dependency_roots = match settings.allow_dependency_paths {
+            Some(false) => Vec::new(),
+            _ => dependency_roots(workspace_root)
+                .into_iter()
+                .map(|(kind, root)| (kind, canonical(&root)))
+                .collect(),
+        };
+        Some(Self {
+            roots,
+            dependency_roots,
+        })
+    }
+
+    /// Whether tools may use `path`
+    pub fn check(&self, path: &Path) -> Result<(), LspError> {
+        let path = canonical(path);
+        let allowed = self.roots.iter().any(|root| path.starts_with(root))
+            || dependency_source(&path).is_some_and(|kind| {
+                self.dependency_roots
+                    .iter()
+                    .any(|(root_kind, root)| *root_kind == kind && path.starts_with(root))
+            });
+        if allowed {
+            Ok(())
+        } else {
+            Err(LspError::PathNotAllowed(path))
+        }
+    }
+}
+
+/// `path` with `~/` expanded to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// `path` with symlinks resolved and `.` and `..` removed
+///
+/// The longest existing prefix is resolved by the OS, so `link/..` means
+/// what it would when opened; the missing rest has no symlinks and is
+/// resolved as written.
+pub fn canonical(path: &Path) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    for split in (1..=components.len()).rev() {
+        let prefix: PathBuf = components[..split].iter().collect();
+        if let Ok(mut resolved) = prefix.canonicalize() {
+            for component in &components[split..] {
+                match component {
+                    Component::ParentDir => {
+                        resolved.pop();
+                    }
+                    Component::Normal(name) => resolved.push(name),
+                    _ => {}
+                }
+            }
+            return resolved;
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_policy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let workspace = tmp.path().join("app");
+        let shared = tmp.path().join("shared");
+        let secrets = tmp.path().join("secrets");
+        for dir in [&workspace, &shared, &secrets] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&secrets, workspace.join("link")).unwrap();
+
+        let settings = Settings {
+            allowed_paths: Some(vec![shared.display().to_string()]),
+            ..Settings::default()
+        };
+        let policy = PathPolicy::new(&workspace, &settings).unwrap();
+        assert!(policy.check(&workspace.join("src/new.rs")).is_ok());
+        assert!(policy.check(&shared.join("lib.rs")).is_ok());
+        assert!(policy.check(&workspace.join("../secrets/key")).is_err());
+        assert!(policy.check(&workspace.join("src/../../secrets")).is_err());
+        assert!(policy
+            .check(&workspace.join("src/missing/../../../secrets"))
+            .is_err());
+        #[cfg(unix)]
+        {
+            assert!(policy.check(&workspace.join("link/key")).is_err());
+            // `link/..` is the secrets directory's parent, not the workspace
+            assert!(policy.check(&workspace.join("link/../secrets")).is_err());
+        }
+
+        let cargo_home = tmp.path().join(".cargo");
+        let mut policy = policy;
+        policy
+            .dependency_roots
+            .push((DependencySource::CargoRegistry, canonical(&cargo_home)));
+        let dependency = cargo_home.join("registry/src/idx/serde-1.0.0/src/lib.rs");
+        assert!(policy.check(&dependency).is_ok());
+        // Only where that kind of store lives, not any directory so named
+        assert!(policy
+            .check(&secrets.join("node_modules/react/index.js"))
+            .is_err());
+        assert!(policy
+            .check(&secrets.join("registry/src/idx/serde-1.0.0/src/lib.rs"))
+            .is_err());
+        let strict = Settings {
+            allow_dependency_paths: Some(false),
+            ..Settings::default()
+        };
+        let strict = PathPolicy::new(&workspace, &strict).unwrap();
+        assert!(strict.dependency_roots.is_empty());
+        assert!(strict.check(&dependency).is_err());
+
+        let off = Settings {
+            restrict_paths: Some(false),
+            ..Settings::default()
+        };
+        assert!(PathPolicy::new(&workspace, &off).is_none());
+    }
+}
@@ -0,0 +1,234 @@
+//! SARIF 2.1.0 logs of diagnostics
+//!
+//! Code scanning services, GitHub's among them, take results as SARIF. Each
+//! diagnostic becomes a result whose rule is its source and code
+//! (`rustc/E0308`), at a location relative to the workspace root
+//! (`%SRCROOT%`), with its related information as related locations.
+
+use crate::mcp::tools::code_string;
+use crate::utils::position::PositionEncoding;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Range, Url};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Where the workspace root is, for the URIs relative to it
+const ROOT_ID: &str = "SRCROOT";
+
+/// A SARIF log of `files`' diagnostics, found under `root`, with columns
+/// counted in `encoding`
+pub fn log<'a>(
+    root: &Path,
+    files: impl IntoIterator<Item = (&'a Path, &'a [Diagnostic])>,
+    encoding: PositionEncoding,
+) -> Value {
+    let base = Url::from_directory_path(root).ok();
+    let mut rules: BTreeMap<String, Value> = BTreeMap::new();
+    let mut results = Vec::new();
+
+    for (path, diagnostics) in files {
+        for diagnostic in diagnostics {
+            let rule_id = rule_id(diagnostic);
+            let rule = rules
+                .entry(rule_id.clone())
+                .or_insert_with(|| json!({ "id": rule_id }));
+            if let Some(description) = &diagnostic.code_description {
+                rule["helpUri"] = json!(description.href);
+            }
+
+            let mut result = json!({
+                "ruleId": rule_id,
+                "level": level(diagnostic.severity),
+                "message": { "text": diagnostic.message },
+                "locations": [location(base.as_ref(), path, &diagnostic.range)],
+            });
+            let related: Vec<Value> = diagnostic
+                .related_information
+                .iter()
+                .flatten()
+                .filter_map(|info| {
+                    let path = info.location.uri.to_file_path().ok()?;
+                    let mut location = location(base.as_ref(), &path, &info.location.range);
+                    location["message"] = json!({ "text": info.message });
+                    Some(location)
+                })
+                .enumerate()
+                .map(|(id, mut location)| {
+                    location["id"] = json!(id);
+                    location
+                })
+                .collect();
+            if !related.is_empty() {
+                result["relatedLocations"] = json!(related);
+            }
+            results.push(result);
+        }
+    }
+
+    // Results point into the rules by index, which follows the sorted ids
+    let index: BTreeMap<String, usize> = rules
+        .keys()
+        .enumerate()
+        .map(|(i, id)| (id.clone(), i))
+        .collect();
+    for result in &mut results {
+        let id = result["ruleId"].as_str().unwrap_or_default();
+        result["ruleIndex"] = json!(index[id]);
+    }
+
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": "lsmcp",
+                "version": env!("CARGO_PKG_VERSION"),
+                "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                "rules": rules.into_values().collect::<Vec<_>>(),
+            }
+        },
+        "results": results,
+    });
+    if let Some(base) = base {
+        run["originalUriBaseIds"] = json!({ ROOT_ID: { "uri": base } });
+    }
+    // SARIF has no kind for UTF-8 columns; readers then assume UTF-16
+    let column_kind = match encoding {
+        PositionEncoding::Utf32 => Some("unicodeCodePoints"),
+        PositionEncoding::Utf16 => Some("utf16CodeUnits"),
+        PositionEncoding::Utf8 => None,
+    };
+    if let Some(kind) = column_kind {
+        run["columnKind"] = json!(kind);
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [run],
+    })
+}
+
+/// `source/code`, whichever of them the diagnostic has, else `lsp`
+fn rule_id(diagnostic: &Diagnostic) -> String {
+    let code = diagnostic.code.as_ref().map(code_string);
+    match (&diagnostic.source, code) {
+        (Some(source), Some(code)) => format!("{}/{}", source, code),
+        (Some(source), None) => source.clone(),
+        (None, Some(code)) => code,
+        (None, None) => "lsp".to_string(),
+    }
+}
+
+fn level(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        _ => "note",
+    }
+}
+
+/// A physical location: relative to the root when under it, else by its
+/// `file://` URI
+fn location(base: Option<&Url>, path: &Path, range: &Range) -> Value {
+    let uri = Url::from_file_path(path).ok();
+    let artifact = match (base, &uri) {
+        (Some(base), Some(uri)) if uri.as_str().starts_with(base.as_str()) => json!({
+            "uri": &uri.as_str()[base.as_str().len()..],
+            "uriBaseId": ROOT_ID,
+        }),
+        (_, Some(uri)) => json!({ "uri": uri }),
+        (_, None) => json!({ "uri": path.to_string_lossy() }),
+    };
+
+    json!({
+        "physicalLocation": {
+            "artifactLocation": artifact,
+            "region": {
+                "startLine": range.start.line + 1,
+                "startColumn": range.start.character + 1,
+                "endLine": range.end.line + 1,
+                "endColumn": range.end.character + 1,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        CodeDescription, DiagnosticRelatedInformation, Location, NumberOrString, Position,
+    };
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_log() {
+        let root = PathBuf::from("/work/my app");
+        let file = root.join("src/main.rs");
+        let range = Range::new(Position::new(2, 4), Position::new(2, 9));
+        let diagnostics = vec![
+            Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("E0308".to_string())),
+                code_description: Some(CodeDescription {
+                    href: Url::parse("https://doc.rust-lang.org/error_codes/E0308.html").unwrap(),
+                }),
+                source: Some("rustc".to_string()),
+                message: "mismatched types".to_string(),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(Url::from_file_path("/usr/lib/std.rs").unwrap(), range),
+                    message: "expected due to this".to_string(),
+                }]),
+                ..Diagnostic::default()
+            },
+            Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::HINT),
+                message: "unused".to_string(),
+                ..Diagnostic::default()
+            },
+        ];
+
+        let log = log(
+            &root,
+            [(file.as_path(), diagnostics.as_slice())],
+            PositionEncoding::Utf32,
+        );
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["columnKind"], "unicodeCodePoints");
+        assert_eq!(
+            run["originalUriBaseIds"]["SRCROOT"]["uri"],
+            "file:///work/my%20app/"
+        );
+
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["id"], "lsp");
+        assert_eq!(
+            rules[1]["helpUri"],
+            "https://doc.rust-lang.org/error_codes/E0308.html"
+        );
+
+        let error = &run["results"][0];
+        assert_eq!(error["ruleId"], "rustc/E0308");
+        assert_eq!(error["ruleIndex"], 1);
+        assert_eq!(error["level"], "error");
+        let location = &error["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/main.rs");
+        assert_eq!(location["artifactLocation"]["uriBaseId"], "SRCROOT");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(location["region"]["startColumn"], 5);
+        // Outside the root, by absolute URI
+        let related = &error["relatedLocations"][0];
+        assert_eq!(
+            related["physicalLocation"]["artifactLocation"]["uri"],
+            "file:///usr/lib/std.rs"
+        );
+        assert_eq!(related["message"]["text"], "expected due to this");
+
+        assert_eq!(run["results"][1]["level"], "note");
+    }
+}
@@ -0,0 +1,70 @@
+//! Signals asking the process to stop
+//!
+//! Both the stdio server and the daemon stop on Ctrl-C, a supervisor's
+//! SIGTERM or a closed terminal's SIGHUP, and shut their language servers
+//! down before exiting rather than leaving them orphaned.
+
+use std::fmt;
+use tracing::warn;
+
+/// Why the process was asked to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopSignal {
+    /// SIGINT, usually Ctrl-C
+    Interrupt,
+    /// SIGTERM, usually from a supervisor
+    Terminate,
+    /// SIGHUP, the terminal went away
+    Hangup,
+}
+
+impl fmt::Display for StopSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Interrupt => "Interrupted",
+            Self::Terminate => "Terminated",
+            Self::Hangup => "Hung up",
+        })
+    }
+}
+
+/// Wait until the process is asked to stop
+pub async fn stop_signal() -> StopSignal {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        // A signal that can't be listened for never arrives
+        let wait = |kind: SignalKind| async move {
+            match signal(kind) {
+                Ok(mut signal) => {
+                    signal.recv().await;
+                }
+                Err(_) => std::future::pending::<()>().await,
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => StopSignal::Interrupt,
+            _ = wait(SignalKind::terminate()) => StopSignal::Terminate,
+            _ = wait(SignalKind::hangup()) => StopSignal::Hangup,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        StopSignal::Interrupt
+    }
+}
+
+/// Exit at once if asked to stop during shutdown, for a shutdown that
+/// hangs on a server that won't stop
+pub fn exit_on_stop_signal() {
+    tokio::spawn(async {
+        let signal = stop_signal().await;
+        warn!(
+            "{} while shutting down; exiting without waiting for servers",
+            signal
+        );
+        std::process::exit(1);
+    });
+}
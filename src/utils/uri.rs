@@ -1 +1,218 @@
 //! File path <-> URI conversion utilities
+//!
+//! `PathMap` rewrites paths under one directory to another directory, in
+//! plain paths and `file://` URIs alike. It translates the messages exchanged
+//! with remote servers, and the user's `[path_map]` rewrites the paths
+//! servers report (say, a symlink-resolved or container-internal workspace)
+//! to ones the MCP client can open.
+
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Directory prefix rewrites, applied to paths and `file://` URIs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMap {
+    /// (from, to) directories, most specific first
+    paths: Vec<(String, String)>,
+    /// The same pairs as `file://` URIs
+    uris: Vec<(String, String)>,
+}
+
+impl PathMap {
+    /// A map rewriting each `from` directory to its `to`, or `None` if there
+    /// is nothing to rewrite
+    ///
+    /// Relative paths are ignored: they can't be matched reliably.
+    pub fn new(pairs: impl IntoIterator<Item = (PathBuf, PathBuf)>) -> Option<Self> {
+        let mut pairs: Vec<(PathBuf, PathBuf)> = pairs
+            .into_iter()
+            .filter(|(from, to)| from.is_absolute() && to.is_absolute() && from != to)
+            .collect();
+        if pairs.is_empty() {
+            return None;
+        }
+        // Nested directories: the deeper one wins
+        pairs.sort_by_key(|(from, _)| std::cmp::Reverse(from.components().count()));
+
+        let uri = |path: &Path| {
+            Url::from_file_path(path)
+                .map(|uri| uri.as_str().trim_end_matches('/').to_string())
+                .unwrap_or_default()
+        };
+        let text = |path: &Path| path.to_string_lossy().trim_end_matches('/').to_string();
+
+        Some(Self {
+            uris: pairs
+                .iter()
+                .map(|(from, to)| (uri(from), uri(to)))
+                .collect(),
+            paths: pairs
+                .iter()
+                .map(|(from, to)| (text(from), text(to)))
+                .collect(),
+        })
+    }
+
+    /// The map in the other direction
+    pub fn reversed(&self) -> Self {
+        let swap = |pairs: &[(String, String)]| -> Vec<(String, String)> {
+            let mut pairs: Vec<_> = pairs
+                .iter()
+                .map(|(from, to)| (to.clone(), from.clone()))
+                .collect();
+            pairs.sort_by_key(|(from, _)| std::cmp::Reverse(from.matches('/').count()));
+            pairs
+        };
+        Self {
+            paths: swap(&self.paths),
+            uris: swap(&self.uris),
+        }
+    }
+
+    /// `path` rewritten, if it is under one of the mapped directories
+    pub fn map_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = path.to_string_lossy();
+        self.paths
+            .iter()
+            .find_map(|(from, to)| replace_prefix(&path, from, to))
+            .map(PathBuf::from)
+    }
+
+    /// Rewrite every path and URI in a JSON-RPC message
+    ///
+    /// Messages that aren't valid JSON are returned unchanged, for the
+    /// reader to report.
+    pub fn rewrite_message(&self, message: &str) -> String {
+        match serde_json::from_str::<Value>(message) {
+            Ok(mut value) => {
+                self.rewrite_json(&mut value);
+                value.to_string()
+            }
+            Err(_) => message.to_string(),
+        }
+    }
+
+    /// Rewrite every path and URI in a JSON value, object keys included
+    /// (`WorkspaceEdit.changes` is keyed by URI)
+    pub fn rewrite_json(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(mapped) = self.rewrite_str(s) {
+                    *s = mapped;
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.rewrite_json(item);
+                }
+            }
+            Value::Object(object) => {
+                let entries = std::mem::take(object);
+                *object = entries
+                    .into_iter()
+                    .map(|(key, mut value)| {
+                        self.rewrite_json(&mut value);
+                        (self.rewrite_str(&key).unwrap_or(key), value)
+                    })
+                    .collect::<Map<String, Value>>();
+            }
+            _ => {}
+        }
+    }
+
+    fn rewrite_str(&self, s: &str) -> Option<String> {
+        let pairs = if s.starts_with("file:") {
+            &self.uris
+        } else {
+            &self.paths
+        };
+        pairs
+            .iter()
+            .find_map(|(from, to)| replace_prefix(s, from, to))
+    }
+}
+
+/// `s` with the directory `from` at its start replaced by `to`
+fn replace_prefix(s: &str, from: &str, to: &str) -> Option<String> {
+    let rest = s.strip_prefix(from)?;
+    (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", to, rest))
+}
+
+/// A URI as shown to the MCP client: the decoded file path for `file://`
+/// URIs, else the URI itself
+pub fn display_path(uri: &Url) -> String {
+    uri.to_file_path()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(pairs: &[(&str, &str)]) -> PathMap {
+        PathMap::new(
+            pairs
+                .iter()
+                .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to))),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rewrites_uris_and_paths() {
+        let map = map(&[
+            ("/home/me/app", "/workspaces/app"),
+            ("/home/me/app/vendor", "/opt/vendor"),
+        ]);
+
+        let message = r#"{"rootUri":"file:///home/me/app","rootPath":"/home/me/app",
+            "textDocument":{"uri":"file:///home/me/app/src/main.rs"},
+            "other":"file:///home/me/application/x.rs",
+            "dep":"file:///home/me/app/vendor/lib.rs",
+            "changes":{"file:///home/me/app/src/lib.rs":[]}}"#;
+        let rewritten: Value = serde_json::from_str(&map.rewrite_message(message)).unwrap();
+        assert_eq!(rewritten["rootUri"], "file:///workspaces/app");
+        assert_eq!(rewritten["rootPath"], "/workspaces/app");
+        assert_eq!(
+            rewritten["textDocument"]["uri"],
+            "file:///workspaces/app/src/main.rs"
+        );
+        // Only whole directories are mapped
+        assert_eq!(rewritten["other"], "file:///home/me/application/x.rs");
+        assert_eq!(rewritten["dep"], "file:///opt/vendor/lib.rs");
+        assert!(rewritten["changes"]
+            .get("file:///workspaces/app/src/lib.rs")
+            .is_some());
+
+        let back = map.reversed();
+        assert_eq!(
+            back.map_path(Path::new("/workspaces/app/src")),
+            Some(PathBuf::from("/home/me/app/src"))
+        );
+        assert_eq!(
+            back.map_path(Path::new("/opt/vendor/lib.rs")),
+            Some(PathBuf::from("/home/me/app/vendor/lib.rs"))
+        );
+        assert_eq!(back.map_path(Path::new("/tmp")), None);
+    }
+
+    #[test]
+    fn test_ignores_relative_paths() {
+        let pairs = [(PathBuf::from("app"), PathBuf::from("/app"))];
+        assert!(PathMap::new(pairs).is_none());
+        assert!(PathMap::new([]).is_none());
+    }
+
+    #[test]
+    fn test_display_path() {
+        let uri = Url::parse("file:///home/me/my%20app/main.rs").unwrap();
+        assert_eq!(display_path(&uri), "/home/me/my app/main.rs");
+        let uri = Url::parse("jdt://contents/rt.jar/java.lang/String.class").unwrap();
+        assert_eq!(
+            display_path(&uri),
+            "jdt://contents/rt.jar/java.lang/String.class"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Workspace scanning
+
+use std::path::{Path, PathBuf};
+
+/// Directories that hold dependencies or build output rather than sources
+const SKIPPED_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    "vendor",
+    "dist",
+    "build",
+    "out",
+    "__pycache__",
+    "venv",
+];
+
+/// Stop scanning after this many files so huge trees stay fast
+const MAX_SCANNED_FILES: usize = 20_000;
+
+/// Files in a workspace, in no particular order
+///
+/// Hidden directories and dependency/build directories are skipped, and
+/// symlinks are not followed.
+pub fn source_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    stack.push(path);
+                }
+            } else if file_type.is_file() {
+                if files.len() >= MAX_SCANNED_FILES {
+                    return files;
+                }
+                files.push(path);
+            }
+        }
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_source_files_skips_dependencies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("src/main.RS"), "").unwrap();
+        fs::write(root.join("script.py"), "").unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+        fs::write(root.join(".git/config.toml"), "").unwrap();
+
+        let mut files: Vec<_> = source_files(root)
+            .into_iter()
+            .map(|f| f.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![PathBuf::from("script.py"), PathBuf::from("src/main.RS")]
+        );
+    }
+}